@@ -0,0 +1,10 @@
+create table addresses(
+    id serial primary key,
+    user_id integer not null references users(id) on delete cascade,
+    street varchar(255) not null,
+    city varchar(255) not null,
+    postal_code varchar(32) not null,
+    country varchar(255) not null
+);
+
+create index addresses_user_id_idx on addresses(user_id);
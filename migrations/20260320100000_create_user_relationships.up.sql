@@ -0,0 +1,10 @@
+create table user_relationships(
+    user_id integer not null references users(id) on delete cascade,
+    related_user_id integer not null references users(id) on delete cascade,
+    kind varchar(32) not null,
+    created_at timestamptz not null default now(),
+    primary key (user_id, kind, related_user_id),
+    check (user_id <> related_user_id)
+);
+
+create index user_relationships_related_idx on user_relationships(related_user_id);
@@ -0,0 +1 @@
+drop table user_audit_log;
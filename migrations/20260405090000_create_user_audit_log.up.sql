@@ -0,0 +1,11 @@
+-- no foreign keys: entries must outlive the users they mention
+create table user_audit_log(
+    id bigserial primary key,
+    action varchar(32) not null,
+    target_user_id integer not null,
+    actor_user_id integer,
+    effective_user_id integer,
+    created_at timestamptz not null default now()
+);
+
+create index user_audit_log_target_idx on user_audit_log(target_user_id);
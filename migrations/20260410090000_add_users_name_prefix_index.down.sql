@@ -0,0 +1 @@
+drop index users_name_prefix_idx;
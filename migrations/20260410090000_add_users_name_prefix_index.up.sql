@@ -0,0 +1,2 @@
+-- text_pattern_ops lets `name LIKE 'prefix%'` use the index under any collation
+create index users_name_prefix_idx on users (name text_pattern_ops) where deleted_at is null;
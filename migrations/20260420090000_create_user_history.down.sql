@@ -0,0 +1,3 @@
+drop trigger users_history on users;
+drop function record_user_history();
+drop table user_history;
@@ -0,0 +1,36 @@
+-- one row per version of a user, written by trigger so every write path is covered
+create table user_history(
+    id bigserial primary key,
+    user_id integer not null,
+    name varchar(255) not null,
+    surname varchar(255) not null,
+    created_at timestamptz not null,
+    -- hard or soft deleted as of this version
+    deleted boolean not null default false,
+    changed_at timestamptz not null default now()
+);
+
+create index user_history_user_changed_idx on user_history(user_id, changed_at);
+
+create function record_user_history() returns trigger as $$
+begin
+    if tg_op = 'DELETE' then
+        insert into user_history(user_id, name, surname, created_at, deleted)
+        values (old.id, old.name, old.surname, old.created_at, true);
+        return old;
+    end if;
+
+    insert into user_history(user_id, name, surname, created_at, deleted)
+    values (new.id, new.name, new.surname, new.created_at, new.deleted_at is not null);
+    return new;
+end;
+$$ language plpgsql;
+
+create trigger users_history
+    after insert or update or delete on users
+    for each row execute function record_user_history();
+
+-- earlier edits are lost, so existing users start their history at creation
+insert into user_history(user_id, name, surname, created_at, deleted, changed_at)
+select id, name, surname, created_at, deleted_at is not null, created_at
+from users;
@@ -0,0 +1,2 @@
+drop table user_versions;
+drop sequence user_versions_user_id_seq;
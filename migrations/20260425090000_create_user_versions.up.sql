@@ -0,0 +1,18 @@
+-- storage for DATABASE_BACKEND=temporal: every version of a user is a row,
+-- valid over [valid_from, valid_to), and the current one has no valid_to
+create sequence user_versions_user_id_seq;
+
+create table user_versions(
+    version_id bigserial primary key,
+    id integer not null,
+    name varchar(255) not null,
+    surname varchar(255) not null,
+    created_at timestamptz not null default now(),
+    valid_from timestamptz not null default now(),
+    valid_to timestamptz,
+    check (valid_to is null or valid_to >= valid_from)
+);
+
+create unique index user_versions_current_idx on user_versions(id) where valid_to is null;
+create index user_versions_id_valid_from_idx on user_versions(id, valid_from);
+create index user_versions_name_prefix_idx on user_versions (name text_pattern_ops) where valid_to is null;
@@ -0,0 +1,11 @@
+-- no foreign key: a consent stays on record after the user is gone
+create table consents(
+    id bigserial primary key,
+    user_id integer not null,
+    consent_type varchar(64) not null,
+    version varchar(32) not null,
+    source varchar(64) not null,
+    granted_at timestamptz not null default now()
+);
+
+create index consents_user_id_idx on consents(user_id);
@@ -0,0 +1,3 @@
+drop index user_audit_log_created_at_idx;
+drop index user_audit_log_action_idx;
+drop index user_audit_log_actor_idx;
@@ -0,0 +1,4 @@
+-- for ListAuditEntries, which pages through the matching entries by id
+create index user_audit_log_actor_idx on user_audit_log(actor_user_id, id);
+create index user_audit_log_action_idx on user_audit_log(action, id);
+create index user_audit_log_created_at_idx on user_audit_log(created_at);
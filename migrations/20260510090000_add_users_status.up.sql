@@ -0,0 +1 @@
+alter table users add column status varchar(16) not null default 'active' check (status in ('active', 'suspended'));
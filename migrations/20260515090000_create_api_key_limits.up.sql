@@ -0,0 +1,17 @@
+-- a null limit leaves the key unlimited on that axis; keys without a row are
+-- not limited at all
+create table api_key_limits(
+    api_key varchar(128) primary key,
+    requests_per_minute integer check (requests_per_minute > 0),
+    monthly_quota bigint check (monthly_quota > 0)
+);
+
+-- the current window of each key and period only, a new window starts the
+-- count again
+create table api_key_usage(
+    api_key varchar(128) not null references api_key_limits(api_key) on delete cascade,
+    period varchar(8) not null check (period in ('minute', 'month')),
+    window_start timestamptz not null,
+    requests bigint not null,
+    primary key (api_key, period)
+);
@@ -0,0 +1,23 @@
+create or replace function record_user_history() returns trigger as $$
+begin
+    if tg_op = 'DELETE' then
+        insert into user_history(user_id, name, surname, created_at, deleted)
+        values (old.id, old.name, old.surname, old.created_at, true);
+        return old;
+    end if;
+
+    insert into user_history(user_id, name, surname, created_at, deleted)
+    values (new.id, new.name, new.surname, new.created_at, new.deleted_at is not null);
+    return new;
+end;
+$$ language plpgsql;
+
+-- fails once a longer encrypted value is stored
+alter table user_history
+    alter column name type varchar(255),
+    alter column surname type varchar(255);
+alter table users
+    alter column name type varchar(255),
+    alter column surname type varchar(255);
+
+drop table pii_data_keys;
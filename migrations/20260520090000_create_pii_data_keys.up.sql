@@ -0,0 +1,36 @@
+-- the data keys encrypting names and surnames, each wrapped by the master key;
+-- the newest encrypts, the older ones are kept to decrypt what they encrypted
+create table pii_data_keys(
+    id serial primary key,
+    wrapped_key bytea not null,
+    created_at timestamptz not null default now()
+);
+
+-- room for the ciphertext of a 255 character name; widening a varchar
+-- rewrites neither the table nor its indexes
+alter table users
+    alter column name type varchar(2048),
+    alter column surname type varchar(2048);
+alter table user_history
+    alter column name type varchar(2048),
+    alter column surname type varchar(2048);
+
+-- a transaction setting gin_tonik.skip_user_history re-encrypts users without
+-- recording a version for what is only a change of key
+create or replace function record_user_history() returns trigger as $$
+begin
+    if current_setting('gin_tonik.skip_user_history', true) = 'on' then
+        return null;
+    end if;
+
+    if tg_op = 'DELETE' then
+        insert into user_history(user_id, name, surname, created_at, deleted)
+        values (old.id, old.name, old.surname, old.created_at, true);
+        return old;
+    end if;
+
+    insert into user_history(user_id, name, surname, created_at, deleted)
+    values (new.id, new.name, new.surname, new.created_at, new.deleted_at is not null);
+    return new;
+end;
+$$ language plpgsql;
@@ -0,0 +1,4 @@
+drop table pii_key_rotations;
+
+-- the newest data key encrypts again, activated or not
+alter table pii_data_keys drop column activated_at;
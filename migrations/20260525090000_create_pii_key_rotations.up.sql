@@ -0,0 +1,29 @@
+-- a data key added by a rotation only encrypts once every replica has loaded
+-- it; until then it is only used to decrypt and match
+alter table pii_data_keys add column activated_at timestamptz default now();
+update pii_data_keys set activated_at = created_at;
+
+-- re-encryption of every user under a new data key, batch by batch in id
+-- order: `after_user_id` is the last user rewritten, so a rotation resumes
+-- where it stopped. The replica running it holds a lease, renewed with
+-- `heartbeat_at` after each batch
+create table pii_key_rotations(
+    id serial primary key,
+    data_key_id integer not null references pii_data_keys(id),
+    state varchar(16) not null default 'pending'
+        check (state in ('pending', 'rewriting', 'done')),
+    batch_size integer not null check (batch_size > 0),
+    -- unthrottled when null
+    max_users_per_second integer check (max_users_per_second > 0),
+    after_user_id integer not null default 0,
+    values_rewritten bigint not null default 0,
+    owner varchar(64),
+    heartbeat_at timestamptz,
+    started_at timestamptz not null default now(),
+    updated_at timestamptz not null default now(),
+    finished_at timestamptz
+);
+
+-- one rotation at a time
+create unique index pii_key_rotations_unfinished on pii_key_rotations((true))
+    where finished_at is null;
@@ -0,0 +1,11 @@
+-- the change feed as committed, behind WatchUsers resume tokens. Only the user
+-- ids are kept: a replay reads the users as they are now, so no name ever
+-- outlives an erasure here. Purged after EVENT_STORE_RETENTION_HOURS
+create table user_events(
+    token bigserial primary key,
+    kind varchar(16) not null check (kind in ('created', 'updated', 'deleted')),
+    user_id integer not null,
+    created_at timestamptz not null default now()
+);
+
+create index user_events_created_at on user_events(created_at);
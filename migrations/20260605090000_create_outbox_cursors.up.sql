@@ -0,0 +1,8 @@
+-- how far each OUTBOX_DESTINATIONS endpoint confirmed the user_events relayed
+-- to it, in token order; events are only purged once every destination
+-- confirmed them
+create table outbox_cursors(
+    destination varchar(64) primary key,
+    delivered_token bigint not null default 0,
+    updated_at timestamptz not null default now()
+);
@@ -0,0 +1,14 @@
+-- user_events an OUTBOX_DESTINATIONS endpoint failed to take on every
+-- attempt. The relay moves the destination's cursor past them, so they are
+-- copied here, until RedeliverDeadLetter gets one through
+create table dead_letters(
+    id bigserial primary key,
+    destination varchar(64) not null,
+    token bigint not null,
+    kind varchar(16) not null,
+    user_id integer not null,
+    attempts integer not null,
+    reason text not null,
+    failed_at timestamptz not null default now(),
+    unique (destination, token)
+);
@@ -0,0 +1,24 @@
+-- snapshots taken by CreateBackup: the objects under `object` in
+-- BACKUP_STORAGE_URL, a manifest and the parts it lists. The last
+-- RestoreBackup of each backup is recorded with it
+create table backups(
+    id bigserial primary key,
+    object varchar(255) not null unique,
+    state varchar(16) not null default 'running'
+        check (state in ('running', 'completed', 'failed')),
+    row_count bigint not null default 0,
+    byte_count bigint not null default 0,
+    error text,
+    created_at timestamptz not null default now(),
+    completed_at timestamptz,
+    restore_state varchar(16)
+        check (restore_state in ('restoring', 'restored', 'failed')),
+    restored_rows bigint not null default 0,
+    restore_error text,
+    restore_started_at timestamptz,
+    restored_at timestamptz
+);
+
+-- one restore at a time
+create unique index backups_restoring on backups((true))
+    where restore_state = 'restoring';
@@ -0,0 +1,323 @@
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{
+    Error,
+    config::AlertSettings,
+    metrics::{
+        Registry, Value, registry,
+        requests::{DB_ERRORS_TOTAL, REQUESTS_TOTAL},
+    },
+    shutdown::Shutdown,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    ErrorRate,
+    DbFailures,
+    // sent by repositories::failover, not the monitor
+    DbFailover,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Alert {
+    pub rule: Rule,
+    // false once the rule is back under its threshold
+    pub firing: bool,
+    pub value: f64,
+    pub threshold: f64,
+    pub window_secs: u64,
+    // the database a failover alert is about
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Alert {
+    pub fn text(&self) -> String {
+        let state = if self.firing { "FIRING" } else { "resolved" };
+        match self.rule {
+            Rule::ErrorRate => format!(
+                "[{}] gin_tonik error rate {:.1}% over the last {}s (threshold {:.1}%)",
+                state,
+                self.value * 100.0,
+                self.window_secs,
+                self.threshold * 100.0
+            ),
+            Rule::DbFailures => format!(
+                "[{}] gin_tonik saw {} database failures in the last {}s (threshold {})",
+                state, self.value, self.window_secs, self.threshold
+            ),
+            Rule::DbFailover if self.firing => format!(
+                "[{}] gin_tonik cannot reach database {} after {} checks, failing over",
+                state,
+                self.detail.as_deref().unwrap_or("?"),
+                self.value
+            ),
+            Rule::DbFailover => format!(
+                "[{}] gin_tonik is connected to database {}",
+                state,
+                self.detail.as_deref().unwrap_or("?")
+            ),
+        }
+    }
+}
+
+#[async_trait]
+pub trait AlertHook: Send + Sync {
+    async fn send(&self, alert: &Alert) -> Result<(), Error>;
+}
+
+// Slack incoming webhooks show `text`, other receivers can read the rest
+pub struct WebhookHook {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookHook {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    text: String,
+    #[serde(flatten)]
+    alert: &'a Alert,
+}
+
+#[async_trait]
+impl AlertHook for WebhookHook {
+    async fn send(&self, alert: &Alert) -> Result<(), Error> {
+        self.client
+            .post(&self.url)
+            .timeout(Duration::from_secs(10))
+            .json(&Payload {
+                text: alert.text(),
+                alert,
+            })
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(())
+    }
+}
+
+// checks the registry every `check_interval` and sends an alert whenever a
+// rule starts or stops firing
+pub fn spawn_monitor(settings: AlertSettings, hook: Arc<dyn AlertHook>, shutdown: Shutdown) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(settings.check_interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut monitor = Monitor::new(settings);
+
+        loop {
+            tokio::select! {
+                _ = shutdown.triggered() => break,
+                _ = ticker.tick() => {}
+            }
+            for alert in monitor.check(Instant::now(), Sample::take(registry())) {
+                info!("{}", alert.text());
+                if let Err(e) = hook.send(&alert).await {
+                    warn!("failed to send alert: {}", e);
+                }
+            }
+        }
+    });
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Sample {
+    requests: u64,
+    errors: u64,
+    db_failures: u64,
+}
+
+impl Sample {
+    fn take(registry: &Registry) -> Self {
+        let mut sample = Self::default();
+        for (key, value) in registry.snapshot() {
+            let Value::Counter(c) = value else {
+                continue;
+            };
+            if key.name == REQUESTS_TOTAL {
+                sample.requests += c;
+                if key
+                    .labels
+                    .iter()
+                    .any(|(k, v)| *k == "result" && v == "error")
+                {
+                    sample.errors += c;
+                }
+            } else if key.name == DB_ERRORS_TOTAL {
+                sample.db_failures += c;
+            }
+        }
+
+        sample
+    }
+}
+
+struct Monitor {
+    settings: AlertSettings,
+    samples: VecDeque<(Instant, Sample)>,
+    firing: Vec<Rule>,
+}
+
+impl Monitor {
+    fn new(settings: AlertSettings) -> Self {
+        Self {
+            settings,
+            samples: VecDeque::new(),
+            firing: Vec::new(),
+        }
+    }
+
+    fn check(&mut self, now: Instant, sample: Sample) -> Vec<Alert> {
+        // the oldest sample still inside the window is the baseline
+        while let Some((at, _)) = self.samples.front()
+            && now.duration_since(*at) > self.settings.window
+        {
+            self.samples.pop_front();
+        }
+        let base = self.samples.front().map_or(sample, |(_, s)| *s);
+        self.samples.push_back((now, sample));
+
+        let requests = sample.requests.saturating_sub(base.requests);
+        let errors = sample.errors.saturating_sub(base.errors);
+        let db_failures = sample.db_failures.saturating_sub(base.db_failures);
+        let error_rate = if requests > 0 {
+            errors as f64 / requests as f64
+        } else {
+            0.0
+        };
+
+        let rules = [
+            (
+                Rule::ErrorRate,
+                error_rate,
+                self.settings.error_rate,
+                // a handful of requests says little about the rate
+                requests >= self.settings.min_requests && error_rate > self.settings.error_rate,
+            ),
+            (
+                Rule::DbFailures,
+                db_failures as f64,
+                self.settings.db_failures as f64,
+                self.settings.db_failures > 0 && db_failures >= self.settings.db_failures,
+            ),
+        ];
+
+        let mut alerts = Vec::new();
+        for (rule, value, threshold, firing) in rules {
+            if firing == self.firing.contains(&rule) {
+                continue;
+            }
+            if firing {
+                self.firing.push(rule);
+            } else {
+                self.firing.retain(|r| *r != rule);
+            }
+            alerts.push(Alert {
+                rule,
+                firing,
+                value,
+                threshold,
+                window_secs: self.settings.window.as_secs(),
+                detail: None,
+            });
+        }
+
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> AlertSettings {
+        AlertSettings {
+            webhook_url: "http://localhost/hook".to_string(),
+            window: Duration::from_secs(60),
+            check_interval: Duration::from_secs(10),
+            error_rate: 0.1,
+            min_requests: 10,
+            db_failures: 5,
+        }
+    }
+
+    fn sample(requests: u64, errors: u64, db_failures: u64) -> Sample {
+        Sample {
+            requests,
+            errors,
+            db_failures,
+        }
+    }
+
+    #[test]
+    fn test_error_rate_fires_and_resolves() {
+        let mut monitor = Monitor::new(settings());
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(monitor.check(at(0), sample(100, 50, 0)).is_empty());
+        // 3 of 20 new requests failed
+        let alerts = monitor.check(at(10), sample(120, 53, 0));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule, Rule::ErrorRate);
+        assert!(alerts[0].firing);
+        assert_eq!(alerts[0].value, 0.15);
+        // still firing, nothing new to say
+        assert!(monitor.check(at(20), sample(140, 55, 0)).is_empty());
+
+        // the failing samples have left the window
+        let alerts = monitor.check(at(85), sample(1140, 60, 0));
+        assert_eq!(alerts.len(), 1);
+        assert!(!alerts[0].firing);
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let mut monitor = Monitor::new(settings());
+        let start = Instant::now();
+
+        monitor.check(start, sample(0, 0, 0));
+        // too few requests for the rate, but enough database failures
+        let alerts = monitor.check(start + Duration::from_secs(10), sample(5, 5, 5));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule, Rule::DbFailures);
+        assert!(
+            alerts[0]
+                .text()
+                .starts_with("[FIRING] gin_tonik saw 5 database")
+        );
+    }
+
+    #[test]
+    fn test_sample() {
+        let registry = Registry::default();
+        registry.increment_counter(REQUESTS_TOTAL, &[("protocol", "grpc"), ("result", "ok")], 7);
+        registry.increment_counter(
+            REQUESTS_TOTAL,
+            &[("protocol", "http"), ("result", "error")],
+            2,
+        );
+        registry.increment_counter(DB_ERRORS_TOTAL, &[], 1);
+
+        assert_eq!(Sample::take(&registry), sample(9, 2, 1));
+    }
+}
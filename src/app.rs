@@ -0,0 +1,777 @@
+use std::{sync::Arc, time::Duration};
+
+use tonic::{
+    codec::CompressionEncoding,
+    service::{Routes, interceptor::InterceptedService},
+    transport::Server,
+};
+use tonic_health::ServingStatus;
+use tracing::Level;
+
+use crate::{
+    Error,
+    alerting::{self, AlertHook, WebhookHook},
+    auth::{
+        AuthInterceptor,
+        nonces::{self, InMemoryNonceStore, NonceStore},
+        signing::{SignedBodyLayer, Signing},
+    },
+    backup::{self, Backups},
+    build_info,
+    cache::{self, UserCache, responses::ResponseCache},
+    config::{Config, ReadOnlyMode, TlsMode},
+    db,
+    faults::FaultInjectionLayer,
+    flags::EnvFeatureFlags,
+    gateway,
+    grpc::{
+        address_service_server::AddressServiceServer,
+        admin_service_server::AdminServiceServer,
+        relationship_service_server::RelationshipServiceServer,
+        user_service_server::UserServiceServer,
+        v2::{
+            address_service_server::AddressServiceServer as AddressServiceServerV2,
+            user_service_server::UserServiceServer as UserServiceServerV2,
+        },
+    },
+    health::{self, DependencyHealth},
+    http,
+    jobs::{self, outbox_relay::OutboxRelay},
+    metrics::{self, requests::RequestMetricsLayer},
+    notifications::NotificationQueue,
+    pii::{self, PiiCipher, PiiKeyring, rotation::KeyRotator},
+    ratelimit::{self, PostgresUsageCounter, RateLimitLayer, RateLimiter},
+    redact,
+    reload::{self, Reloader},
+    repositories::{
+        ApiKeyRepository as ApiKeyRepositoryTrait, AuditRepository as AuditRepositoryTrait,
+        ConsentRepository as ConsentRepositoryTrait, ImportRepository as ImportRepositoryTrait,
+        PiiRepository as PiiRepositoryTrait, RevisionRepository as RevisionRepositoryTrait,
+        StatsRepository as StatsRepositoryTrait, UserEventRepository as UserEventRepositoryTrait,
+        address_repository::AddressRepository,
+        any_user_repository::AnyUserRepository,
+        api_key_repository::ApiKeyRepository,
+        audit_repository::{AuditRepository, LogAuditRepository},
+        backup_repository::BackupRepository,
+        cached_user_repository::CachedUserRepository,
+        consent_repository::{ConsentRepository, InMemoryConsentRepository},
+        encrypted_user_repository::EncryptedUserRepository,
+        failover::{self, Failover},
+        import_repository::ImportRepository,
+        pii_repository::PiiRepository,
+        relationship_repository::RelationshipRepository,
+        revision_repository::RevisionRepository,
+        shadow_user_repository::ShadowUserRepository,
+        slow_user_repository::{SlowOperations, SlowUserRepository},
+        stats_repository::StatsRepository,
+        user_event_repository::UserEventRepository,
+    },
+    servers::{
+        address_server::AddressServer, admin_server::AdminServer,
+        relationship_server::RelationshipServer, request_log, user_server::UserServer, v2,
+    },
+    session::SessionLayer,
+    shutdown::Shutdown,
+    telemetry::{self, TraceContextLayer},
+    tls,
+    usecases::{
+        address_usecase::AddressUsecase,
+        count_estimate::CountEstimate,
+        feed_bus,
+        relationship_usecase::RelationshipUsecase,
+        user_feed::UserFeed,
+        user_usecase::UserUsecase,
+        write_protection::{self, WriteProtection},
+    },
+};
+
+// how often DATABASE_READ_ONLY=auto asks postgres whether it is a standby
+const WRITE_PROTECTION_INTERVAL: Duration = Duration::from_secs(10);
+
+pub type UserRepo = CachedUserRepository<
+    SlowUserRepository<EncryptedUserRepository<ShadowUserRepository<AnyUserRepository>>>,
+>;
+pub type UserService = UserServer<UserUsecase<UserRepo>>;
+pub type AddressService = AddressServer<AddressUsecase<AddressRepository, UserRepo>>;
+pub type RelationshipService =
+    RelationshipServer<RelationshipUsecase<RelationshipRepository, UserRepo>>;
+pub type UserServiceV2 = v2::UserServer<UserUsecase<UserRepo>>;
+pub type AddressServiceV2 = v2::AddressServer<AddressUsecase<AddressRepository, UserRepo>>;
+
+pub async fn run(config: Config) -> Result<(), Error> {
+    run_with_shutdown(config, Shutdown::new()).await
+}
+
+// serves until SIGINT/SIGTERM or until `shutdown` is triggered by the caller
+pub async fn run_with_shutdown(mut config: Config, shutdown: Shutdown) -> Result<(), Error> {
+    // reloads compare against the environment, not what Vault filled in
+    let started = config.clone();
+    let vault = config.resolve_secrets().await?;
+    let addr = config.addr;
+    let span = tracing::span!(Level::INFO, "UserService");
+    telemetry::set_trace_sampling(config.trace_sampling.clone());
+    request_log::set_rules(config.request_log.clone());
+    redact::set_fields(config.redact_fields);
+
+    let user_repo = AnyUserRepository::connect(&config)
+        .await
+        .map_err(|e| Error::Internal(format!("failed to connect to database: {}", e).into()))?;
+    let pg_pool = user_repo.pg_pool();
+    let health_pools = user_repo.health_pools();
+    let mut vault_certificate = None;
+    if let Some(vault) = vault {
+        vault_certificate = vault.certificate();
+        vault.spawn(user_repo.credential_pools(), shutdown.clone());
+    }
+    let mut user_repo = ShadowUserRepository::new(user_repo);
+    if let Some(shadow) = &config.shadow {
+        let shadow_config = Config {
+            database_backend: shadow.backend,
+            database_url: shadow.url.clone(),
+            database_replica_url: None,
+            database_failover: None,
+            ..config.clone()
+        };
+        let shadow_repo = AnyUserRepository::connect(&shadow_config)
+            .await
+            .map_err(|e| {
+                Error::Internal(format!("failed to connect to shadow database: {}", e).into())
+            })?;
+        user_repo = user_repo.with_shadow(shadow_repo, shadow.writes, shadow.max_in_flight);
+        tracing::info!(
+            "shadowing {} traffic to the {:?} backend",
+            if shadow.writes { "all" } else { "read" },
+            shadow.backend
+        );
+    }
+    // above the shadow, so both backends store the same ciphertext
+    let mut user_repo = EncryptedUserRepository::new(user_repo);
+    let mut pii_keyring = None;
+    let mut key_rotator = None;
+    if let (Some(master_key), Some(pool)) = (&config.pii_master_key, &pg_pool) {
+        let master = pii::master_key(master_key)?;
+        let pii_repo: Arc<dyn PiiRepositoryTrait> = Arc::new(PiiRepository::new(pool.clone()));
+        let cipher = PiiCipher::load(master.as_ref(), pii_repo.as_ref())
+            .await
+            .map_err(|e| {
+                Error::Internal(format!("failed to load the PII data keys: {}", e).into())
+            })?;
+        tracing::info!(
+            "encrypting names and surnames with PII data key {}",
+            cipher.active_key()
+        );
+        let keyring = Arc::new(PiiKeyring::new(cipher));
+        user_repo = user_repo.with_keyring(keyring.clone());
+        // also resumes a rotation an earlier process left unfinished
+        let rotator = Arc::new(KeyRotator::new(
+            keyring.clone(),
+            master,
+            pii_repo,
+            config.pii_key_reload,
+        ));
+        rotator.clone().spawn(shutdown.clone());
+        pii_keyring = Some(keyring);
+        key_rotator = Some(rotator);
+    }
+    // below the cache, so cached reads stay fast like they would with a slow database
+    let slow_operations = SlowOperations::default();
+    let user_repo = SlowUserRepository::new(user_repo, slow_operations.clone());
+    if config.slow_db_simulation {
+        tracing::warn!("SLOW_DB_SIMULATION is set, the admin service can delay repository calls");
+    }
+    let user_cache = UserCache::new(config.cache_ttl).with_negative_ttl(config.cache_negative_ttl);
+    let mut user_repo = CachedUserRepository::new(user_repo, Arc::new(user_cache));
+    if let Some(url) = &config.cache_invalidation_url {
+        user_repo = user_repo.with_invalidation_bus(cache::invalidation::connect(url).await?);
+        user_repo.listen_for_invalidations(&shutdown);
+    }
+    let write_protection = WriteProtection::new(config.database_read_only == ReadOnlyMode::On);
+    if let (ReadOnlyMode::Detect, Some(pool)) = (config.database_read_only, &pg_pool) {
+        if write_protection::in_recovery(pool).await? {
+            write_protection.set(true);
+            tracing::warn!("the database is a read-only standby, rejecting writes");
+        }
+        write_protection.follow(pool.clone(), WRITE_PROTECTION_INTERVAL, &shutdown);
+    }
+    let alert_hook = config
+        .alerts
+        .as_ref()
+        .map(|alerts| Arc::new(WebhookHook::new(alerts.webhook_url.clone())) as Arc<dyn AlertHook>);
+    if let (Some(settings), Some(pool)) = (&config.database_failover, &pg_pool) {
+        let candidates = failover::candidates(&config.database_url, settings)?;
+        let mut failover = Failover::new(pool.clone(), candidates, settings.clone());
+        if config.database_read_only == ReadOnlyMode::Detect {
+            failover = failover.with_write_protection(write_protection.clone());
+        }
+        if let Some(hook) = &alert_hook {
+            failover = failover.with_alert_hook(hook.clone());
+        }
+        failover.spawn(shutdown.clone());
+    }
+    // addresses and relationships only exist in the single postgres backend
+    let address_server: Option<AddressService> = pg_pool.clone().map(|pool| {
+        AddressServer::new(
+            tracing::span!(Level::INFO, "AddressService"),
+            AddressUsecase::new(AddressRepository::new(pool), user_repo.clone())
+                .with_write_protection(write_protection.clone()),
+        )
+    });
+    let address_server_v2: Option<AddressServiceV2> = pg_pool.clone().map(|pool| {
+        v2::AddressServer::new(
+            tracing::span!(Level::INFO, "AddressServiceV2"),
+            AddressUsecase::new(AddressRepository::new(pool), user_repo.clone())
+                .with_write_protection(write_protection.clone()),
+        )
+    });
+    let audit: Arc<dyn AuditRepositoryTrait> = match &pg_pool {
+        Some(pool) => Arc::new(AuditRepository::new(pool.clone())),
+        None => Arc::new(LogAuditRepository),
+    };
+    // the other backends keep consents for as long as the process runs
+    let consents: Arc<dyn ConsentRepositoryTrait> = match &pg_pool {
+        Some(pool) => Arc::new(ConsentRepository::new(pool.clone())),
+        None => Arc::new(InMemoryConsentRepository::default()),
+    };
+    let addresses = pg_pool.clone().map(AddressRepository::new);
+    let revisions = pg_pool.clone().map(|pool| {
+        let repo = RevisionRepository::new(pool);
+        let repo = match &pii_keyring {
+            Some(keyring) => repo.with_keyring(keyring.clone()),
+            None => repo,
+        };
+        Arc::new(repo) as Arc<dyn RevisionRepositoryTrait>
+    });
+    let stats = pg_pool
+        .clone()
+        .map(|pool| Arc::new(StatsRepository::new(pool)) as Arc<dyn StatsRepositoryTrait>);
+    let imports = pg_pool
+        .clone()
+        .filter(|_| pii_keyring.is_none())
+        .map(|pool| Arc::new(ImportRepository::new(pool)) as Arc<dyn ImportRepositoryTrait>);
+    // RATE_LIMITS is only accepted with a postgres backend
+    let rate_limiter = match (&config.rate_limits, &pg_pool) {
+        (Some(settings), Some(pool)) => {
+            let keys: Arc<dyn ApiKeyRepositoryTrait> =
+                Arc::new(ApiKeyRepository::new(pool.clone()));
+            let usage = match &settings.redis_url {
+                Some(url) => ratelimit::connect_usage_counter(url).await?,
+                None => Arc::new(PostgresUsageCounter::new(keys.clone())),
+            };
+            Some(Arc::new(RateLimiter::new(keys, usage, settings.cache_ttl)))
+        }
+        _ => None,
+    };
+    let relationship_server: Option<RelationshipService> = pg_pool.clone().map(|pool| {
+        RelationshipServer::new(
+            tracing::span!(Level::INFO, "RelationshipService"),
+            RelationshipUsecase::new(RelationshipRepository::new(pool), user_repo.clone())
+                .with_write_protection(write_protection.clone()),
+        )
+    });
+    let flags = Arc::new(EnvFeatureFlags::from_config(&config)?);
+    // every API surface shares one change feed so watchers see all mutations,
+    // those handled by other replicas too with a bus
+    let mut feed = UserFeed::new();
+    if let Some(url) = &config.change_feed_url {
+        feed = feed.with_bus(feed_bus::connect(url).await?, &shutdown);
+    }
+    let mut outbox_relay = None;
+    // EVENT_STORE_RETENTION_HOURS is only accepted with a postgres backend
+    if let (Some(retention), Some(pool)) = (config.event_store_retention, &pg_pool) {
+        let store: Arc<dyn UserEventRepositoryTrait> =
+            Arc::new(UserEventRepository::new(pool.clone()));
+        feed = feed.with_event_store(store.clone(), &shutdown);
+        let destinations = config
+            .outbox_destinations
+            .iter()
+            .map(|d| d.name.clone())
+            .collect();
+        jobs::event_purge::spawn(
+            pool.clone(),
+            store.clone(),
+            retention,
+            destinations,
+            shutdown.clone(),
+        );
+        if !config.outbox_destinations.is_empty() {
+            let relay = Arc::new(OutboxRelay::new(store, config.outbox_destinations.clone()));
+            jobs::outbox_relay::spawn(pool.clone(), relay.clone(), shutdown.clone());
+            outbox_relay = Some(relay);
+        }
+    }
+    let count_estimate = CountEstimate::new(config.count_estimate_max_age);
+    let notifications = config
+        .notifications
+        .as_ref()
+        .map(|settings| NotificationQueue::spawn(settings, &shutdown))
+        .transpose()?;
+    let user_usecase = || {
+        let usecase = UserUsecase::new(user_repo.clone())
+            .with_shutdown(shutdown.clone())
+            .with_feature_flags(flags.clone())
+            .with_audit_log(audit.clone())
+            .with_consents(consents.clone())
+            .with_required_consents(config.required_consents.clone())
+            .with_change_feed(feed.clone())
+            .with_count_estimate(count_estimate.clone())
+            .with_write_protection(write_protection.clone())
+            .with_get_users_max_rows(config.get_users_max_rows);
+        let usecase = match &addresses {
+            Some(addresses) => usecase.with_addresses(addresses.clone()),
+            None => usecase,
+        };
+        let usecase = match &revisions {
+            Some(revisions) => usecase.with_revisions(revisions.clone()),
+            None => usecase,
+        };
+        let usecase = match &stats {
+            Some(stats) => usecase.with_stats(stats.clone()),
+            None => usecase,
+        };
+        let usecase = match &imports {
+            Some(imports) => usecase.with_imports(imports.clone()),
+            None => usecase,
+        };
+        match &notifications {
+            Some(notifications) => usecase.with_notifications(notifications.clone()),
+            None => usecase,
+        }
+    };
+    let user_server_v2: UserServiceV2 =
+        v2::UserServer::new(tracing::span!(Level::INFO, "UserServiceV2"), user_usecase());
+    let responses = ResponseCache::new(config.response_cache_ttl, feed.clone());
+    let user_server: UserService =
+        UserServer::new(span, user_usecase()).with_response_cache(Arc::new(responses));
+    #[cfg(feature = "graphql")]
+    let graphql_schema = crate::graphql::schema(Arc::new(user_usecase()));
+
+    // a schema this build cannot use keeps every service NOT_SERVING and
+    // unregistered, instead of failing on the first mismatched query
+    let schema = db::schema::check(&config).await;
+    if let Err(e) = &schema {
+        tracing::error!("refusing to serve: {}", e);
+    }
+    let serving = schema.is_ok();
+
+    let (health_reporter, health_service) = tonic_health::server::health_reporter();
+    if !serving {
+        health_reporter
+            .set_service_status("", ServingStatus::NotServing)
+            .await;
+        health_reporter
+            .set_not_serving::<UserServiceServer<UserService>>()
+            .await;
+        health_reporter
+            .set_not_serving::<UserServiceServerV2<UserServiceV2>>()
+            .await;
+    } else {
+        health_reporter
+            .set_serving::<UserServiceServer<UserService>>()
+            .await;
+        health_reporter
+            .set_serving::<UserServiceServerV2<UserServiceV2>>()
+            .await;
+    }
+    if address_server.is_some() && serving {
+        health_reporter
+            .set_serving::<AddressServiceServer<AddressService>>()
+            .await;
+    }
+    if address_server_v2.is_some() && serving {
+        health_reporter
+            .set_serving::<AddressServiceServerV2<AddressServiceV2>>()
+            .await;
+    }
+    if relationship_server.is_some() && serving {
+        health_reporter
+            .set_serving::<RelationshipServiceServer<RelationshipService>>()
+            .await;
+    }
+    // touches no database, so it serves even when the schema check failed
+    health_reporter
+        .set_serving::<AdminServiceServer<AdminServer>>()
+        .await;
+    // lame duck takes the business services out of load balancing, the admin
+    // service stays SERVING for the operators
+    let optional_services = (
+        address_server.is_some(),
+        address_server_v2.is_some(),
+        relationship_server.is_some(),
+    );
+    let reporter = health_reporter.clone();
+    let lame_duck = shutdown.clone();
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = lame_duck.lame_duck_entered() => {}
+            _ = lame_duck.triggered() => return,
+        }
+        reporter
+            .set_service_status("", ServingStatus::NotServing)
+            .await;
+        reporter
+            .set_not_serving::<UserServiceServer<UserService>>()
+            .await;
+        reporter
+            .set_not_serving::<UserServiceServerV2<UserServiceV2>>()
+            .await;
+        let (address, address_v2, relationship) = optional_services;
+        if address {
+            reporter
+                .set_not_serving::<AddressServiceServer<AddressService>>()
+                .await;
+        }
+        if address_v2 {
+            reporter
+                .set_not_serving::<AddressServiceServerV2<AddressServiceV2>>()
+                .await;
+        }
+        if relationship {
+            reporter
+                .set_not_serving::<RelationshipServiceServer<RelationshipService>>()
+                .await;
+        }
+        tracing::warn!("in lame duck: health is NOT_SERVING and new streams are refused");
+    });
+    let backup_store = config.backups.as_ref().map(backup::connect).transpose()?;
+    let dependency_health = DependencyHealth::new(health::dependencies(
+        &config,
+        health_pools,
+        backup_store.clone(),
+    )?);
+    // a failed schema check keeps health NOT_SERVING whatever the dependencies say
+    if serving {
+        dependency_health.clone().spawn(
+            config.health_check_interval,
+            health_reporter.clone(),
+            shutdown.clone(),
+        );
+    }
+
+    // health stays unauthenticated so probes work without a token
+    let mut auth = AuthInterceptor::new(config.auth_jwt_secret.as_deref());
+    let mut signed_body = None;
+    if !config.auth_hmac_keys.is_empty() {
+        let signing = Arc::new(Signing::new(
+            config.auth_hmac_keys.clone(),
+            config.auth_hmac_max_skew,
+        ));
+        let nonces: Arc<dyn NonceStore> = match &config.auth_nonce_redis_url {
+            Some(url) => nonces::connect(url).await?,
+            None => Arc::new(InMemoryNonceStore::new()),
+        };
+        auth = auth.with_signing(signing.clone());
+        signed_body = Some(SignedBodyLayer::new(signing, nonces));
+    }
+    if !auth.is_enabled() {
+        tracing::warn!(
+            "neither AUTH_JWT_SECRET nor AUTH_HMAC_KEYS is set, serving requests unauthenticated"
+        );
+    }
+
+    let mut builder = Server::builder()
+        .layer(RequestMetricsLayer)
+        .layer(TraceContextLayer)
+        .layer(SessionLayer);
+    let mut certificate = None;
+    match &config.tls {
+        // served through tls::incoming, which picks up reloaded certificates
+        TlsMode::Files { cert, key } => {
+            certificate = Some(Arc::new(tls::ReloadableCertificate::load(cert, key)?));
+        }
+        // replaced by the Vault refresh rather than on reloads
+        TlsMode::Vault { .. } => {
+            certificate = vault_certificate;
+        }
+        TlsMode::SelfSigned => {
+            let self_signed = tls::self_signed_tls_config()?;
+            tracing::warn!(
+                "serving a self-signed development certificate, do not use in production"
+            );
+            println!("{}", self_signed.cert_pem);
+            println!("SHA256 Fingerprint={}", self_signed.fingerprint);
+            builder = builder
+                .tls_config(self_signed.config)
+                .map_err(|e| Error::Internal(Box::new(e)))?;
+        }
+        TlsMode::Disabled | TlsMode::Acme(_) => {}
+    }
+
+    let reflection_v1 = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(crate::grpc::FILE_DESCRIPTOR_SET)
+        .build_v1()
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+    // grpcurl and older tooling still speak v1alpha
+    let reflection_v1alpha = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(crate::grpc::FILE_DESCRIPTOR_SET)
+        .build_v1alpha()
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+    // the business services are also served over REST by the gateway, which
+    // calls into the same (intercepted) routes in-process
+    let mut services = Routes::builder();
+    // compressed only for clients that send grpc-accept-encoding, which
+    // matters most for StreamUsers exports
+    let user_service = UserServiceServer::new(user_server)
+        .send_compressed(CompressionEncoding::Zstd)
+        .send_compressed(CompressionEncoding::Gzip)
+        .accept_compressed(CompressionEncoding::Zstd)
+        .accept_compressed(CompressionEncoding::Gzip);
+    if serving {
+        services
+            .add_service(InterceptedService::new(user_service, auth.clone()))
+            .add_service(UserServiceServerV2::with_interceptor(
+                user_server_v2,
+                auth.clone(),
+            ));
+        if let Some(s) = address_server {
+            services.add_service(AddressServiceServer::with_interceptor(s, auth.clone()));
+        }
+        if let Some(s) = relationship_server {
+            services.add_service(RelationshipServiceServer::with_interceptor(s, auth.clone()));
+        }
+        if let Some(s) = address_server_v2 {
+            services.add_service(AddressServiceServerV2::with_interceptor(s, auth.clone()));
+        }
+    }
+    let reloader = Arc::new(Reloader::new(
+        started,
+        flags.clone(),
+        certificate
+            .clone()
+            .filter(|_| matches!(config.tls, TlsMode::Files { .. })),
+    ));
+    reload::reload_on_sighup(reloader.clone(), &shutdown)?;
+    let mut admin_server =
+        AdminServer::new(tracing::span!(Level::INFO, "AdminService"), slow_operations)
+            .with_slow_db_simulation(config.slow_db_simulation)
+            .with_audit_log(audit.clone())
+            .with_users(Arc::new(user_usecase()))
+            .with_reloader(reloader)
+            .with_dependency_health(dependency_health)
+            .with_shutdown(shutdown.clone());
+    if let Some(limiter) = &rate_limiter {
+        admin_server = admin_server.with_rate_limiter(limiter.clone());
+    }
+    if let Some(rotator) = key_rotator {
+        admin_server = admin_server.with_key_rotator(rotator);
+    }
+    if let Some(relay) = outbox_relay {
+        admin_server = admin_server.with_outbox_relay(relay);
+    }
+    if let Some(pool) = &pg_pool {
+        admin_server = admin_server.with_db_pool(pool.clone());
+    }
+    if let (Some(store), Some(pool)) = (&backup_store, &pg_pool) {
+        let backups = Backups::new(
+            Arc::new(BackupRepository::new(pool.clone())),
+            store.clone(),
+            shutdown.clone(),
+        );
+        admin_server = admin_server.with_backups(Arc::new(backups));
+    }
+    services.add_service(AdminServiceServer::with_interceptor(
+        admin_server,
+        auth.clone(),
+    ));
+    let mut services = services.routes();
+    // on the routes rather than the server, so REST calls through the gateway
+    // see the same faults
+    if !config.faults.is_empty() {
+        tracing::warn!(
+            "FAULT_INJECTION is set, injecting faults: {:?}",
+            config.faults
+        );
+        let router = services
+            .into_axum_router()
+            .layer(FaultInjectionLayer::new(config.faults.clone()));
+        services = Routes::from(router);
+    }
+    if let Some(layer) = signed_body {
+        let router = services.into_axum_router().layer(layer);
+        services = Routes::from(router);
+    }
+    if let Some(limiter) = rate_limiter {
+        let router = services
+            .into_axum_router()
+            .layer(RateLimitLayer::new(limiter));
+        services = Routes::from(router);
+    }
+    let http_router = http::router().merge(gateway::router(services.clone())?);
+    #[cfg(feature = "graphql")]
+    let http_router = http_router.merge(crate::graphql::router(graphql_schema, auth));
+    let http_router = http_router
+        .layer(SessionLayer)
+        .layer(TraceContextLayer)
+        .layer(RequestMetricsLayer);
+
+    let router = builder
+        .add_routes(services)
+        .add_service(health_service)
+        .add_service(reflection_v1)
+        .add_service(reflection_v1alpha);
+
+    build_info::register();
+    metrics::runtime::spawn_sampler(config.runtime_metrics_interval, shutdown.clone());
+    if let Some(statsd) = config.statsd.clone() {
+        metrics::statsd::spawn_exporter(statsd, shutdown.clone()).await?;
+    }
+    if let (Some(alerts), Some(hook)) = (config.alerts.clone(), alert_hook) {
+        alerting::spawn_monitor(alerts, hook, shutdown.clone());
+    }
+    let http_server = tokio::spawn(http::serve(config.http_addr, http_router, shutdown.clone()));
+
+    tracing::info!(
+        "server started at {} (version {}, commit {}, {})",
+        addr,
+        build_info::VERSION,
+        build_info::GIT_SHA,
+        build_info::RUSTC_VERSION
+    );
+
+    let served = match &config.tls {
+        #[cfg(feature = "acme")]
+        TlsMode::Acme(settings) => {
+            tracing::info!(
+                "provisioning certificates via ACME for {:?}",
+                settings.domains
+            );
+            router
+                .serve_with_incoming_shutdown(
+                    tls::acme::incoming(addr, settings).await?,
+                    shutdown.clone().wait_for_signal(config.lame_duck),
+                )
+                .await
+        }
+        #[cfg(not(feature = "acme"))]
+        TlsMode::Acme(_) => {
+            shutdown.trigger();
+            return Err(Error::Internal(
+                "TLS_MODE=acme requires building with the `acme` feature".into(),
+            ));
+        }
+        TlsMode::Files { .. } | TlsMode::Vault { .. } => {
+            let certificate = certificate.expect("loaded for TLS_MODE=files and vault");
+            router
+                .serve_with_incoming_shutdown(
+                    tls::incoming(addr, certificate).await?,
+                    shutdown.clone().wait_for_signal(config.lame_duck),
+                )
+                .await
+        }
+        TlsMode::Disabled | TlsMode::SelfSigned => {
+            router
+                .serve_with_shutdown(addr, shutdown.clone().wait_for_signal(config.lame_duck))
+                .await
+        }
+    };
+
+    shutdown.trigger();
+    if let Ok(Err(e)) = http_server.await {
+        tracing::error!("http server failed: {:?}", e);
+    }
+
+    if !shutdown.drain(config.drain_timeout).await {
+        tracing::warn!(
+            "streams still open after {:?}, abandoning them",
+            config.drain_timeout
+        );
+    }
+
+    served.map_err(|e| Error::Internal(Box::new(e)))?;
+    tracing::info!("server shut down gracefully");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use super::*;
+    use crate::{
+        client,
+        grpc::{
+            CountUsersRequest, CreateUserRequest, StreamUsersRequest,
+            user_service_client::UserServiceClient,
+            v2::{GetUserRequest, user_service_client::UserServiceClient as UserServiceClientV2},
+        },
+    };
+
+    fn free_addr() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_run_embedded() {
+        let grpc_addr = free_addr();
+        let vars = HashMap::from([
+            ("DATABASE_BACKEND", "memory".to_string()),
+            ("GRPC_ADDR", grpc_addr.clone()),
+            ("HTTP_ADDR", free_addr()),
+        ]);
+        let config = Config::from_lookup(|key| vars.get(key).cloned()).unwrap();
+        let shutdown = Shutdown::new();
+        let server = tokio::spawn(run_with_shutdown(config, shutdown.clone()));
+
+        let mut channel = None;
+        for _ in 0..50 {
+            match client::connect(
+                format!("http://{}", grpc_addr),
+                None,
+                Duration::from_secs(1),
+            )
+            .await
+            {
+                Ok(c) => {
+                    channel = Some(c);
+                    break;
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+            }
+        }
+        let channel = channel.expect("server did not start");
+        let mut users = UserServiceClient::new(channel.clone());
+        let mut users_v2 = UserServiceClientV2::new(channel);
+
+        let created = users
+            .create_user(CreateUserRequest {
+                name: "Embedded".to_string(),
+                surname: "Server".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .user
+            .unwrap();
+        let count = users
+            .count_users(CountUsersRequest { exact: None })
+            .await
+            .unwrap();
+        let fetched = users_v2
+            .get_user(GetUserRequest {
+                name: format!("users/{}", created.id),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+
+        let mut exports = users.accept_compressed(CompressionEncoding::Zstd);
+        let mut stream = exports
+            .stream_users(StreamUsersRequest {
+                chunk_size: 10,
+                heartbeat_secs: 0,
+            })
+            .await
+            .unwrap();
+        assert_eq!(stream.metadata().get("grpc-encoding").unwrap(), "zstd");
+        let chunk = stream.get_mut().message().await.unwrap().unwrap();
+
+        assert_eq!(count.into_inner().count, 1);
+        assert_eq!(fetched.given_name, "Embedded");
+        assert_eq!(chunk.users, [created]);
+        shutdown.trigger();
+        assert!(server.await.unwrap().is_ok());
+    }
+}
@@ -0,0 +1,322 @@
+use std::{sync::Arc, time::SystemTime};
+
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use tonic::{Request, Status, metadata::MetadataMap, service::Interceptor};
+
+pub mod nonces;
+pub mod signing;
+
+use signing::{SIGNATURE_HEADER, SignedBody, Signing};
+
+pub const ADMIN_ROLE: &str = "admin";
+pub const IMPERSONATE_HEADER: &str = "x-impersonate-user";
+
+// the effective caller, stored in the request extensions by `AuthInterceptor`;
+// `impersonator` is the admin acting on behalf of `user_id`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Principal {
+    pub user_id: i32,
+    pub roles: Vec<String>,
+    pub tenant: Option<String>,
+    pub impersonator: Option<i32>,
+}
+
+impl Principal {
+    pub fn from_extensions(extensions: &tonic::Extensions) -> Option<&Self> {
+        extensions.get::<Self>()
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.roles.iter().any(|r| r == ADMIN_ROLE)
+    }
+
+    pub fn real_user_id(&self) -> i32 {
+        self.impersonator.unwrap_or(self.user_id)
+    }
+
+    // the impersonated user gets none of the admin's roles
+    fn impersonate(self, user_id: i32) -> Result<Self, Status> {
+        if !self.is_admin() {
+            return Err(Status::permission_denied(
+                "only admins may impersonate users",
+            ));
+        }
+
+        Ok(Self {
+            user_id,
+            roles: Vec::new(),
+            tenant: self.tenant,
+            impersonator: Some(self.user_id),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Claims {
+    pub sub: String,
+    pub exp: u64,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+// verifies HS256 bearer tokens, or with signing keys the x-signature of
+// machine callers instead; without either every request passes
+// unauthenticated
+#[derive(Clone, Default)]
+pub struct AuthInterceptor {
+    key: Option<Arc<DecodingKey>>,
+    signing: Option<Arc<Signing>>,
+}
+
+impl AuthInterceptor {
+    pub fn new(secret: Option<&str>) -> Self {
+        Self {
+            key: secret.map(|s| Arc::new(DecodingKey::from_secret(s.as_bytes()))),
+            signing: None,
+        }
+    }
+
+    // AUTH_HMAC_KEYS; requests with an x-signature are checked against these
+    // rather than carrying a token
+    pub fn with_signing(mut self, signing: Arc<Signing>) -> Self {
+        self.signing = Some(signing);
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.key.is_some() || self.signing.is_some()
+    }
+
+    fn authenticate(&self, request: &Request<()>) -> Result<Principal, Status> {
+        let metadata = request.metadata();
+        let principal = match &self.signing {
+            Some(signing) if metadata.contains_key(SIGNATURE_HEADER) => {
+                signing
+                    .verify(
+                        metadata,
+                        request.extensions().get::<SignedBody>(),
+                        SystemTime::now(),
+                    )?
+                    .principal
+            }
+            _ => match &self.key {
+                Some(key) => self.verify_token(key, metadata)?,
+                None => return Err(Status::unauthenticated("missing x-signature")),
+            },
+        };
+
+        match metadata.get(IMPERSONATE_HEADER) {
+            Some(value) => {
+                let target = value
+                    .to_str()
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .ok_or_else(|| {
+                        Status::invalid_argument(format!(
+                            "{} must be a user id",
+                            IMPERSONATE_HEADER
+                        ))
+                    })?;
+                principal.impersonate(target)
+            }
+            None => Ok(principal),
+        }
+    }
+
+    fn verify_token(&self, key: &DecodingKey, metadata: &MetadataMap) -> Result<Principal, Status> {
+        let token = metadata
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("missing bearer token"))?;
+
+        let claims = jsonwebtoken::decode::<Claims>(token, key, &Validation::new(Algorithm::HS256))
+            .map_err(|e| Status::unauthenticated(format!("invalid token: {}", e)))?
+            .claims;
+        let user_id = claims
+            .sub
+            .parse()
+            .map_err(|_| Status::unauthenticated("token subject is not a user id"))?;
+
+        Ok(Principal {
+            user_id,
+            roles: claims.roles,
+            tenant: claims.tenant,
+            impersonator: None,
+        })
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        match self.is_enabled() {
+            true => {
+                let principal = self.authenticate(&request)?;
+                request.extensions_mut().insert(principal);
+            }
+            false if request.metadata().contains_key(IMPERSONATE_HEADER) => {
+                return Err(Status::unauthenticated(
+                    "impersonation requires authentication",
+                ));
+            }
+            false => {}
+        }
+
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use jsonwebtoken::{EncodingKey, Header};
+
+    use super::*;
+
+    const SECRET: &str = "test-secret";
+
+    fn token(sub: &str, roles: &[&str]) -> String {
+        let exp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 60;
+        let claims = Claims {
+            sub: sub.to_string(),
+            exp,
+            roles: roles.iter().map(|r| r.to_string()).collect(),
+            tenant: Some("acme".to_string()),
+        };
+        jsonwebtoken::encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(SECRET.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    fn request(authorization: Option<String>) -> Request<()> {
+        let mut request = Request::new(());
+        if let Some(value) = authorization {
+            request
+                .metadata_mut()
+                .insert("authorization", value.parse().unwrap());
+        }
+        request
+    }
+
+    fn impersonating(roles: &[&str], target: &str) -> Request<()> {
+        let mut request = request(Some(format!("Bearer {}", token("7", roles))));
+        request
+            .metadata_mut()
+            .insert(IMPERSONATE_HEADER, target.parse().unwrap());
+        request
+    }
+
+    #[test]
+    fn test_valid_token_sets_principal() {
+        let mut auth = AuthInterceptor::new(Some(SECRET));
+
+        let request = auth
+            .call(request(Some(format!("Bearer {}", token("7", &["admin"])))))
+            .unwrap();
+        let principal = Principal::from_extensions(request.extensions()).unwrap();
+
+        assert_eq!(principal.user_id, 7);
+        assert_eq!(principal.tenant.as_deref(), Some("acme"));
+        assert!(principal.is_admin());
+    }
+
+    #[test]
+    fn test_rejects_missing_or_invalid_token() {
+        let mut auth = AuthInterceptor::new(Some(SECRET));
+
+        for authorization in [
+            None,
+            Some("Bearer garbage".to_string()),
+            Some(format!("Bearer {}", token("not-a-number", &[]))),
+        ] {
+            let status = auth.call(request(authorization)).unwrap_err();
+            assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        }
+    }
+
+    #[test]
+    fn test_admin_impersonation() {
+        let mut auth = AuthInterceptor::new(Some(SECRET));
+
+        let request = auth.call(impersonating(&["admin"], "42")).unwrap();
+        let principal = Principal::from_extensions(request.extensions()).unwrap();
+
+        assert_eq!(principal.user_id, 42);
+        assert_eq!(principal.real_user_id(), 7);
+        assert!(!principal.is_admin());
+    }
+
+    #[test]
+    fn test_impersonation_requires_admin() {
+        let mut auth = AuthInterceptor::new(Some(SECRET));
+
+        let denied = auth.call(impersonating(&[], "42")).unwrap_err();
+        let invalid = auth.call(impersonating(&["admin"], "bob")).unwrap_err();
+
+        assert_eq!(denied.code(), tonic::Code::PermissionDenied);
+        assert_eq!(invalid.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn test_signed_requests() {
+        let key = signing::HmacKey {
+            id: "billing".to_string(),
+            secret: "s3cret".to_string(),
+            roles: vec!["admin".to_string()],
+        };
+        let mut auth = AuthInterceptor::new(Some(SECRET)).with_signing(Arc::new(Signing::new(
+            vec![key.clone()],
+            std::time::Duration::from_secs(300),
+        )));
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let path = "/user.v1.UserService/DeleteUser";
+        let signed = |body: &[u8]| {
+            let mut request = request(None);
+            request.metadata_mut().insert(
+                SIGNATURE_HEADER,
+                signing::sign(&key, path, now, "n1", b"payload")
+                    .parse()
+                    .unwrap(),
+            );
+            request.extensions_mut().insert(SignedBody {
+                path: path.to_string(),
+                sha256: signing::body_sha256(body),
+            });
+            request
+        };
+
+        let verified = auth.call(signed(b"payload")).unwrap();
+        let principal = Principal::from_extensions(verified.extensions()).unwrap();
+        assert!(principal.is_admin());
+        let status = auth.call(signed(b"tampered")).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        // tokens still work next to signatures
+        assert!(
+            auth.call(request(Some(format!("Bearer {}", token("7", &[])))))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_disabled_passes_through() {
+        let mut auth = AuthInterceptor::new(None);
+
+        let request = auth.call(request(None)).unwrap();
+
+        assert!(Principal::from_extensions(request.extensions()).is_none());
+    }
+}
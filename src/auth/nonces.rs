@@ -0,0 +1,144 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+
+use crate::Error;
+
+// expired nonces are swept once this many are held
+const SWEEP_AFTER: usize = 10_000;
+
+// the nonces of signed requests seen while their signature is still valid,
+// so a captured request cannot be sent again
+#[async_trait]
+pub trait NonceStore: Send + Sync {
+    // false when `nonce` was already claimed for the key and `ttl` has not
+    // passed since
+    async fn claim(&self, key_id: &str, nonce: &str, ttl: Duration) -> Result<bool, Error>;
+}
+
+pub async fn connect(url: &str) -> Result<Arc<dyn NonceStore>, Error> {
+    #[cfg(feature = "redis")]
+    return Ok(Arc::new(redis::RedisNonceStore::connect(url).await?));
+
+    #[cfg(not(feature = "redis"))]
+    Err(Error::Internal(
+        format!(
+            "AUTH_NONCE_REDIS_URL={:?} requires building with the `redis` feature",
+            url
+        )
+        .into(),
+    ))
+}
+
+// per replica; a request replayed to another replica is not caught
+#[derive(Default)]
+pub struct InMemoryNonceStore {
+    seen: Mutex<HashMap<(String, String), Instant>>,
+}
+
+impl InMemoryNonceStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl NonceStore for InMemoryNonceStore {
+    async fn claim(&self, key_id: &str, nonce: &str, ttl: Duration) -> Result<bool, Error> {
+        let now = Instant::now();
+        let mut seen = self.seen.lock().unwrap();
+        if seen.len() >= SWEEP_AFTER {
+            seen.retain(|_, expires| *expires > now);
+        }
+
+        let key = (key_id.to_string(), nonce.to_string());
+        if seen.get(&key).is_some_and(|expires| *expires > now) {
+            return Ok(false);
+        }
+        seen.insert(key, now + ttl);
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "redis")]
+pub mod redis {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use redis::{Client, aio::MultiplexedConnection};
+
+    use super::NonceStore;
+    use crate::Error;
+
+    // `SET NX` with the ttl, shared by every replica
+    pub struct RedisNonceStore {
+        connection: MultiplexedConnection,
+    }
+
+    impl RedisNonceStore {
+        pub async fn connect(url: &str) -> Result<Self, Error> {
+            let client = Client::open(url).map_err(|e| Error::Internal(Box::new(e)))?;
+            let connection = client
+                .get_multiplexed_async_connection()
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))?;
+
+            Ok(Self { connection })
+        }
+    }
+
+    #[async_trait]
+    impl NonceStore for RedisNonceStore {
+        async fn claim(&self, key_id: &str, nonce: &str, ttl: Duration) -> Result<bool, Error> {
+            let mut connection = self.connection.clone();
+            let set: Option<String> = redis::cmd("SET")
+                .arg(format!("request_nonce:{}:{}", key_id, nonce))
+                .arg(1)
+                .arg("NX")
+                .arg("PX")
+                .arg(ttl.as_millis().max(1) as u64)
+                .query_async(&mut connection)
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))?;
+
+            Ok(set.is_some())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_in_memory_claims_once_per_ttl() {
+        let store = InMemoryNonceStore::new();
+
+        assert!(
+            store
+                .claim("billing", "n1", Duration::from_secs(60))
+                .await
+                .unwrap()
+        );
+        assert!(
+            !store
+                .claim("billing", "n1", Duration::from_secs(60))
+                .await
+                .unwrap()
+        );
+        // another key may use the same nonce
+        assert!(
+            store
+                .claim("reports", "n1", Duration::from_secs(60))
+                .await
+                .unwrap()
+        );
+
+        assert!(store.claim("billing", "n2", Duration::ZERO).await.unwrap());
+        assert!(store.claim("billing", "n2", Duration::ZERO).await.unwrap());
+    }
+}
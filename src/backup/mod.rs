@@ -0,0 +1,437 @@
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+pub mod store;
+
+use crate::{
+    Error,
+    entities::backups::{Backup, BackupState, SnapshotRow},
+    repositories::BackupRepository,
+    shutdown::Shutdown,
+};
+pub use store::{BlobStore, connect};
+
+// an object is stored once it reaches this, so memory stays bounded however
+// many users there are
+const PART_BYTES: usize = 8 * 1024 * 1024;
+const MANIFEST_FORMAT: u32 = 1;
+// rows in flight between the database and the storage
+const ROWS_BUFFERED: usize = 1024;
+
+// what a backup is made of, `{object}/manifest.json`, written last: a backup
+// without one never completed
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    format: u32,
+    // the latest migration of the snapshot, a restore needs the same schema
+    schema_version: i64,
+    row_count: u64,
+    parts: Vec<Part>,
+}
+
+// `{object}/{name}`, a row per line as `{"table": .., "row": ..}`
+#[derive(Debug, Serialize, Deserialize)]
+struct Part {
+    name: String,
+    row_count: u64,
+    byte_count: u64,
+    sha256: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Line {
+    table: String,
+    row: serde_json::Value,
+}
+
+// CreateBackup and RestoreBackup. A backup is a consistent snapshot of the
+// users and what belongs to them, streamed part by part to the backup storage
+// while it is read; a restore replaces all of them with it in one transaction,
+// in the background, one at a time across replicas
+#[derive(Clone)]
+pub struct Backups {
+    repo: Arc<dyn BackupRepository>,
+    store: Arc<dyn BlobStore>,
+    shutdown: Shutdown,
+    part_bytes: usize,
+}
+
+impl Backups {
+    pub fn new(
+        repo: Arc<dyn BackupRepository>,
+        store: Arc<dyn BlobStore>,
+        shutdown: Shutdown,
+    ) -> Self {
+        Self {
+            repo,
+            store,
+            shutdown,
+            part_bytes: PART_BYTES,
+        }
+    }
+
+    pub async fn backup(&self, id: i64) -> Result<Option<Backup>, Error> {
+        self.repo.backup(id).await
+    }
+
+    pub async fn latest_backup(&self) -> Result<Option<Backup>, Error> {
+        self.repo.latest_backup().await
+    }
+
+    // returns once the backup is stored, completed or failed. It is taken in
+    // a task of its own, so a caller giving up does not leave it running
+    pub async fn create(&self) -> Result<Backup, Error> {
+        let backups = self.clone();
+        self.shutdown
+            .spawn(async move { backups.take().await })
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?
+    }
+
+    async fn take(&self) -> Result<Backup, Error> {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let object = format!("backup-{}-{:08x}", secs, rand::random::<u32>());
+        let backup = self.repo.create_backup(&object).await?;
+        info!("backing up to {}", object);
+
+        match self.write(&object).await {
+            Ok(manifest) => {
+                let bytes = manifest.parts.iter().map(|p| p.byte_count).sum();
+                let backup = self
+                    .repo
+                    .finish_backup(backup.id, manifest.row_count, bytes, None)
+                    .await?;
+                info!(
+                    "backed up {} rows in {} parts to {}",
+                    manifest.row_count,
+                    manifest.parts.len(),
+                    object
+                );
+                Ok(backup)
+            }
+            Err(e) => {
+                warn!("failed to back up to {}: {}", object, e);
+                self.repo
+                    .finish_backup(backup.id, 0, 0, Some(e.to_string()))
+                    .await?;
+                Err(e)
+            }
+        }
+    }
+
+    async fn write(&self, object: &str) -> Result<Manifest, Error> {
+        let (tx, mut rx) = mpsc::channel(ROWS_BUFFERED);
+        let repo = self.repo.clone();
+        // stops at the next row once the parts are no longer read
+        let snapshot = tokio::spawn(async move { repo.snapshot(tx).await });
+
+        let mut manifest = Manifest {
+            format: MANIFEST_FORMAT,
+            ..Manifest::default()
+        };
+        let mut part = Vec::new();
+        let mut part_rows = 0;
+        while let Some(row) = rx.recv().await {
+            let line = format!(
+                "{{\"table\":{},\"row\":{}}}\n",
+                serde_json::Value::from(row.table),
+                row.row
+            );
+            part.extend_from_slice(line.as_bytes());
+            part_rows += 1;
+            if part.len() >= self.part_bytes {
+                self.put_part(object, &mut manifest, std::mem::take(&mut part), part_rows)
+                    .await?;
+                part_rows = 0;
+            }
+        }
+        manifest.schema_version = snapshot.await.map_err(|e| Error::Internal(Box::new(e)))??;
+        if !part.is_empty() {
+            self.put_part(object, &mut manifest, part, part_rows)
+                .await?;
+        }
+
+        let body =
+            serde_json::to_vec_pretty(&manifest).map_err(|e| Error::Internal(Box::new(e)))?;
+        self.store
+            .put(&format!("{}/manifest.json", object), body)
+            .await?;
+        Ok(manifest)
+    }
+
+    async fn put_part(
+        &self,
+        object: &str,
+        manifest: &mut Manifest,
+        body: Vec<u8>,
+        row_count: u64,
+    ) -> Result<(), Error> {
+        let part = Part {
+            name: format!("part-{:05}.jsonl", manifest.parts.len() + 1),
+            row_count,
+            byte_count: body.len() as u64,
+            sha256: hex(&Sha256::digest(&body)),
+        };
+        self.store
+            .put(&format!("{}/{}", object, part.name), body)
+            .await?;
+        manifest.row_count += row_count;
+        manifest.parts.push(part);
+
+        Ok(())
+    }
+
+    // returns once the restore started; it goes on in the background and
+    // GetBackup tells how it went. Shutdown rolls it back, and the next
+    // restore records it as interrupted
+    pub async fn restore(&self, id: i64) -> Result<Backup, Error> {
+        let backup = self.repo.backup(id).await?.ok_or(Error::NotFound)?;
+        if backup.state != BackupState::Completed {
+            return Err(Error::FailedPrecondition(format!(
+                "backup {} is {}, not completed",
+                id,
+                backup.state.as_str()
+            )));
+        }
+        let manifest = self.manifest(&backup.object).await?;
+        let schema_version = self.repo.schema_version().await?;
+        if manifest.schema_version != schema_version {
+            return Err(Error::FailedPrecondition(format!(
+                "backup {} is of schema version {}, the database is at {}",
+                id, manifest.schema_version, schema_version
+            )));
+        }
+
+        let (backup, session) = self.repo.start_restore(id).await?;
+        info!("restoring backup {} from {}", id, backup.object);
+        let (tx, rx) = mpsc::channel(ROWS_BUFFERED);
+        let (store, object) = (self.store.clone(), backup.object.clone());
+        let reader =
+            tokio::spawn(async move { read_parts(store.as_ref(), &object, &manifest, &tx).await });
+        let (shutdown, row_count) = (self.shutdown.clone(), backup.row_count);
+        self.shutdown.spawn(async move {
+            let restored = tokio::select! {
+                restored = session.restore(row_count, rx) => restored,
+                // the transaction is rolled back with the session
+                _ = shutdown.triggered() => Err(Error::Unavailable("shutting down".to_string())),
+            };
+            reader.abort();
+            match restored {
+                Ok(rows) => info!("restored {} rows of backup {}", rows, id),
+                Err(e) => warn!("failed to restore backup {}: {}", id, e),
+            }
+        });
+
+        Ok(backup)
+    }
+
+    async fn manifest(&self, object: &str) -> Result<Manifest, Error> {
+        let body = self
+            .store
+            .get(&format!("{}/manifest.json", object))
+            .await
+            .map_err(|e| match e {
+                Error::NotFound => Error::FailedPrecondition(format!("{} has no manifest", object)),
+                e => e,
+            })?;
+        let manifest: Manifest = serde_json::from_slice(&body)
+            .map_err(|e| Error::Internal(format!("{}/manifest.json: {}", object, e).into()))?;
+        if manifest.format != MANIFEST_FORMAT {
+            return Err(Error::FailedPrecondition(format!(
+                "{} is of backup format {}, not {}",
+                object, manifest.format, MANIFEST_FORMAT
+            )));
+        }
+
+        Ok(manifest)
+    }
+}
+
+// sends the rows of every part in order, or the first reason one is unusable,
+// after which the restore rolls back
+async fn read_parts(
+    store: &dyn BlobStore,
+    object: &str,
+    manifest: &Manifest,
+    rows: &mpsc::Sender<Result<SnapshotRow, Error>>,
+) {
+    for part in &manifest.parts {
+        let lines = match read_part(store, object, part).await {
+            Ok(lines) => lines,
+            Err(e) => {
+                let _ = rows.send(Err(e)).await;
+                return;
+            }
+        };
+        for line in lines {
+            if rows.send(line).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+async fn read_part(
+    store: &dyn BlobStore,
+    object: &str,
+    part: &Part,
+) -> Result<Vec<Result<SnapshotRow, Error>>, Error> {
+    let key = format!("{}/{}", object, part.name);
+    let body = store.get(&key).await?;
+    if hex(&Sha256::digest(&body)) != part.sha256 {
+        return Err(Error::Internal(
+            format!("{} does not match its checksum", key).into(),
+        ));
+    }
+    let body =
+        String::from_utf8(body).map_err(|e| Error::Internal(format!("{}: {}", key, e).into()))?;
+
+    Ok(body
+        .lines()
+        .map(|line| {
+            let line: Line = serde_json::from_str(line)
+                .map_err(|e| Error::Internal(format!("{}: {}", key, e).into()))?;
+            Ok(SnapshotRow {
+                table: line.table,
+                row: line.row.to_string(),
+            })
+        })
+        .collect())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::{
+        entities::backups::RestoreState,
+        repositories::{
+            UserRepository as _, backup_repository::BackupRepository as PgBackupRepository,
+            user_repository::UserRepository,
+        },
+    };
+
+    #[sqlx::test]
+    async fn test_backup_and_restore(pool: PgPool) {
+        let dir = std::env::temp_dir().join(format!("gin_tonik_backup_{}", rand::random::<u32>()));
+        let store: Arc<dyn BlobStore> = Arc::new(store::FileBlobStore::new(dir.clone()));
+        let mut backups = Backups::new(
+            Arc::new(PgBackupRepository::new(pool.clone())),
+            store.clone(),
+            Shutdown::new(),
+        );
+        // a part per couple of rows
+        backups.part_bytes = 100;
+        let users = UserRepository::new(pool.clone());
+        for name in ["Ann", "Bob", "Cy"] {
+            users
+                .create_user(name.to_string(), "Lee".to_string())
+                .await
+                .unwrap();
+        }
+
+        let backup = backups.create().await.unwrap();
+        assert_eq!(backup.state, BackupState::Completed);
+        // the users and their one version each
+        assert_eq!(backup.row_count, 6);
+        let manifest = backups.manifest(&backup.object).await.unwrap();
+        assert!(manifest.parts.len() > 1);
+        assert_eq!(
+            manifest.parts.iter().map(|p| p.byte_count).sum::<u64>(),
+            backup.byte_count
+        );
+
+        let bob = users
+            .get_user_by_name("Bob".to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        users.delete_user(bob.id).await.unwrap();
+        let dan = users
+            .create_user("Dan".to_string(), "Lee".to_string())
+            .await
+            .unwrap();
+
+        backups.restore(backup.id).await.unwrap();
+        let mut restore = None;
+        for _ in 0..100 {
+            restore = backups.backup(backup.id).await.unwrap().unwrap().restore;
+            if restore
+                .as_ref()
+                .is_some_and(|r| r.state != RestoreState::Restoring)
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let restore = restore.unwrap();
+        assert_eq!(restore.state, RestoreState::Restored, "{:?}", restore.error);
+        assert_eq!(restore.row_count, 6);
+
+        let names: Vec<(String,)> = sqlx::query_as("SELECT name FROM users ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            names,
+            [
+                ("Ann".to_string(),),
+                ("Bob".to_string(),),
+                ("Cy".to_string(),)
+            ]
+        );
+        let history: i64 = sqlx::query_scalar("SELECT count(*) FROM user_history")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(history, 3);
+        // Dan's id is not handed out again
+        let eve = users
+            .create_user("Eve".to_string(), "Lee".to_string())
+            .await
+            .unwrap();
+        assert!(eve.id > dan.id);
+
+        // a part gone bad fails the restore and changes nothing
+        store
+            .put(
+                &format!("{}/part-00001.jsonl", backup.object),
+                b"{}\n".to_vec(),
+            )
+            .await
+            .unwrap();
+        backups.restore(backup.id).await.unwrap();
+        let mut restore = None;
+        for _ in 0..100 {
+            restore = backups.backup(backup.id).await.unwrap().unwrap().restore;
+            if restore
+                .as_ref()
+                .is_some_and(|r| r.state != RestoreState::Restoring)
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(restore.unwrap().state, RestoreState::Failed);
+        assert!(users.get_user_by_id(eve.id).await.unwrap().is_some());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,190 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+
+use crate::{Error, config::BackupSettings};
+
+// how long one object may take to store or fetch
+const TIMEOUT: Duration = Duration::from_secs(300);
+
+// where backups are kept, objects by key like `backup-1/manifest.json`
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    // replaces whatever is stored under `key`
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), Error>;
+    // NotFound when nothing is
+    async fn get(&self, key: &str) -> Result<Vec<u8>, Error>;
+}
+
+// the store BACKUP_STORAGE_URL names, a directory for file:// and anything
+// answering PUT and GET below the URL for http(s)://, like a WebDAV server or
+// an object store's HTTP gateway, with BACKUP_STORAGE_TOKEN as bearer token
+pub fn connect(settings: &BackupSettings) -> Result<Arc<dyn BlobStore>, Error> {
+    let mut url = reqwest::Url::parse(&settings.storage_url)
+        .map_err(|e| Error::InvalidArgument(format!("BACKUP_STORAGE_URL: {}", e)))?;
+    match url.scheme() {
+        "file" => {
+            let root = url.to_file_path().map_err(|_| {
+                Error::InvalidArgument(format!("{} is not a local path", settings.storage_url))
+            })?;
+            Ok(Arc::new(FileBlobStore::new(root)))
+        }
+        "http" | "https" => {
+            // keys are joined below the last segment, not in place of it
+            if !url.path().ends_with('/') {
+                url.set_path(&format!("{}/", url.path()));
+            }
+            Ok(Arc::new(HttpBlobStore {
+                client: reqwest::Client::new(),
+                base: url,
+                token: settings.token.clone(),
+            }))
+        }
+        scheme => Err(Error::InvalidArgument(format!(
+            "backups cannot be stored at {}://",
+            scheme
+        ))),
+    }
+}
+
+pub struct FileBlobStore {
+    root: PathBuf,
+}
+
+impl FileBlobStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, Error> {
+        if key.split('/').any(|part| part.is_empty() || part == "..") {
+            return Err(Error::InvalidArgument(format!("bad object key {}", key)));
+        }
+
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl BlobStore for FileBlobStore {
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), Error> {
+        let path = self.path(key)?;
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))?;
+        }
+        // renamed into place, so a reader never sees half an object
+        let partial = path.with_extension("partial");
+        tokio::fs::write(&partial, body)
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        tokio::fs::rename(&partial, &path)
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, Error> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(body) => Ok(body),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(Error::NotFound),
+            Err(e) => Err(Error::Internal(Box::new(e))),
+        }
+    }
+}
+
+pub struct HttpBlobStore {
+    client: reqwest::Client,
+    base: reqwest::Url,
+    token: Option<String>,
+}
+
+impl HttpBlobStore {
+    fn request(
+        &self,
+        method: reqwest::Method,
+        key: &str,
+    ) -> Result<reqwest::RequestBuilder, Error> {
+        let url = self
+            .base
+            .join(key)
+            .map_err(|e| Error::InvalidArgument(format!("bad object key {}: {}", key, e)))?;
+        let mut request = self.client.request(method, url).timeout(TIMEOUT);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        Ok(request)
+    }
+}
+
+#[async_trait]
+impl BlobStore for HttpBlobStore {
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), Error> {
+        self.request(reqwest::Method::PUT, key)?
+            .body(body)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| Error::Unavailable(format!("failed to store {}: {}", key, e)))?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, Error> {
+        let res = self
+            .request(reqwest::Method::GET, key)?
+            .send()
+            .await
+            .map_err(|e| Error::Unavailable(format!("failed to fetch {}: {}", key, e)))?;
+        if res.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(Error::NotFound);
+        }
+        let body = res
+            .error_for_status()
+            .map_err(|e| Error::Unavailable(format!("failed to fetch {}: {}", key, e)))?
+            .bytes()
+            .await
+            .map_err(|e| Error::Unavailable(format!("failed to fetch {}: {}", key, e)))?;
+
+        Ok(body.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_store() {
+        let dir = std::env::temp_dir().join(format!("gin_tonik_backups_{}", std::process::id()));
+        let store = connect(&BackupSettings {
+            storage_url: reqwest::Url::from_directory_path(&dir).unwrap().to_string(),
+            token: None,
+        })
+        .unwrap();
+
+        store
+            .put("backup-1/part-00001.jsonl", b"{}\n".to_vec())
+            .await
+            .unwrap();
+        store
+            .put("backup-1/part-00001.jsonl", b"[]\n".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get("backup-1/part-00001.jsonl").await.unwrap(),
+            b"[]\n"
+        );
+        assert!(matches!(
+            store.get("backup-1/manifest.json").await,
+            Err(Error::NotFound)
+        ));
+        assert!(matches!(
+            store.get("../etc/passwd").await,
+            Err(Error::InvalidArgument(_))
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::metrics::registry;
+
+// set by build.rs
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_SHA: &str = env!("BUILD_GIT_SHA");
+pub const RUSTC_VERSION: &str = env!("BUILD_RUSTC_VERSION");
+const TIMESTAMP: &str = env!("BUILD_TIMESTAMP");
+
+pub fn built_at() -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(TIMESTAMP.parse().unwrap_or_default())
+}
+
+// `build_info{version, git_sha, rustc_version} 1`, so dashboards can tell
+// which commit every instance runs
+pub fn register() {
+    registry().set_gauge(
+        "build_info",
+        &[
+            ("version", VERSION),
+            ("git_sha", GIT_SHA),
+            ("rustc_version", RUSTC_VERSION),
+        ],
+        1.0,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Value;
+
+    #[test]
+    fn test_build_info() {
+        assert!(!GIT_SHA.is_empty());
+        assert!(RUSTC_VERSION.starts_with("rustc ") || RUSTC_VERSION == "unknown");
+        assert!(built_at() > UNIX_EPOCH && built_at() <= SystemTime::now());
+
+        register();
+        assert_eq!(
+            registry().get(
+                "build_info",
+                &[
+                    ("version", VERSION),
+                    ("git_sha", GIT_SHA),
+                    ("rustc_version", RUSTC_VERSION)
+                ]
+            ),
+            Some(Value::Gauge(1.0))
+        );
+    }
+}
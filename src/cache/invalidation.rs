@@ -0,0 +1,164 @@
+use std::{pin::Pin, sync::Arc};
+
+use async_trait::async_trait;
+use tokio::sync::broadcast;
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+
+use crate::Error;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    User(i32),
+    Name(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Invalidation {
+    // origin identifies the publishing replica so it can skip its own messages
+    Evict { origin: u64, target: Target },
+    // the subscriber may have missed messages, so everything is suspect
+    All,
+}
+
+pub type Invalidations = Pin<Box<dyn Stream<Item = Invalidation> + Send>>;
+
+#[async_trait]
+pub trait InvalidationBus: Send + Sync {
+    async fn publish(&self, origin: u64, target: Target) -> Result<(), Error>;
+    async fn subscribe(&self) -> Result<Invalidations, Error>;
+}
+
+pub async fn connect(url: &str) -> Result<Arc<dyn InvalidationBus>, Error> {
+    #[cfg(feature = "redis")]
+    return Ok(Arc::new(redis::RedisInvalidationBus::connect(url).await?));
+
+    #[cfg(not(feature = "redis"))]
+    Err(Error::Internal(
+        format!(
+            "CACHE_INVALIDATION_URL={:?} requires building with the `redis` feature",
+            url
+        )
+        .into(),
+    ))
+}
+
+#[derive(Clone)]
+pub struct LocalInvalidationBus {
+    sender: broadcast::Sender<(u64, Target)>,
+}
+
+impl LocalInvalidationBus {
+    pub fn new() -> Self {
+        Self {
+            sender: broadcast::channel(1024).0,
+        }
+    }
+}
+
+impl Default for LocalInvalidationBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl InvalidationBus for LocalInvalidationBus {
+    async fn publish(&self, origin: u64, target: Target) -> Result<(), Error> {
+        // no subscribers is not an error
+        let _ = self.sender.send((origin, target));
+        Ok(())
+    }
+
+    async fn subscribe(&self) -> Result<Invalidations, Error> {
+        let stream = BroadcastStream::new(self.sender.subscribe()).map(|msg| match msg {
+            Ok((origin, target)) => Invalidation::Evict { origin, target },
+            Err(_) => Invalidation::All,
+        });
+
+        Ok(Box::pin(stream))
+    }
+}
+
+#[cfg(feature = "redis")]
+pub mod redis {
+    use async_trait::async_trait;
+    use redis::{AsyncCommands, Client, aio::MultiplexedConnection};
+    use tokio_stream::StreamExt;
+    use tracing::warn;
+
+    use super::{Invalidation, InvalidationBus, Invalidations, Target};
+    use crate::Error;
+
+    const CHANNEL: &str = "user_cache_invalidation";
+
+    pub struct RedisInvalidationBus {
+        client: Client,
+        connection: MultiplexedConnection,
+    }
+
+    impl RedisInvalidationBus {
+        pub async fn connect(url: &str) -> Result<Self, Error> {
+            let client = Client::open(url).map_err(|e| Error::Internal(Box::new(e)))?;
+            let connection = client
+                .get_multiplexed_async_connection()
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))?;
+
+            Ok(Self { client, connection })
+        }
+    }
+
+    #[async_trait]
+    impl InvalidationBus for RedisInvalidationBus {
+        async fn publish(&self, origin: u64, target: Target) -> Result<(), Error> {
+            let payload = match target {
+                Target::User(id) => format!("{}:id:{}", origin, id),
+                Target::Name(name) => format!("{}:name:{}", origin, name),
+            };
+
+            let mut connection = self.connection.clone();
+            connection
+                .publish::<_, _, ()>(CHANNEL, payload)
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))
+        }
+
+        async fn subscribe(&self) -> Result<Invalidations, Error> {
+            let mut pubsub = self
+                .client
+                .get_async_pubsub()
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))?;
+            pubsub
+                .subscribe(CHANNEL)
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))?;
+
+            let stream =
+                pubsub
+                    .into_on_message()
+                    .map(|msg| match parse(msg.get_payload::<String>().ok()) {
+                        Some((origin, target)) => Invalidation::Evict { origin, target },
+                        None => {
+                            warn!("malformed cache invalidation message on {}", CHANNEL);
+                            Invalidation::All
+                        }
+                    });
+
+            Ok(Box::pin(stream))
+        }
+    }
+
+    // `<origin>:id:<id>` or `<origin>:name:<name>`, names may contain ':'
+    fn parse(payload: Option<String>) -> Option<(u64, Target)> {
+        let payload = payload?;
+        let (origin, rest) = payload.split_once(':')?;
+        let target = match rest.split_once(':')? {
+            ("id", id) => Target::User(id.parse().ok()?),
+            ("name", name) => Target::Name(name.to_owned()),
+            _ => return None,
+        };
+
+        Some((origin.parse().ok()?, target))
+    }
+}
@@ -0,0 +1,187 @@
+pub mod invalidation;
+pub mod responses;
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{entities::users::User, metrics};
+
+#[derive(Default)]
+struct Entries {
+    by_id: HashMap<i32, Entry>,
+    by_name: HashMap<String, Entry>,
+}
+
+// a `None` user remembers that the lookup found nothing
+struct Entry {
+    user: Option<User>,
+    expires_at: Instant,
+}
+
+pub struct UserCache {
+    ttl: Duration,
+    negative_ttl: Duration,
+    entries: Mutex<Entries>,
+}
+
+impl UserCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            negative_ttl: Duration::ZERO,
+            entries: Mutex::default(),
+        }
+    }
+
+    pub fn with_negative_ttl(mut self, ttl: Duration) -> Self {
+        self.negative_ttl = ttl;
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    pub fn get_by_id(&self, id: i32) -> Option<Option<User>> {
+        let mut entries = self.entries.lock().unwrap();
+        record(fresh(&mut entries.by_id, &id))
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<Option<User>> {
+        let mut entries = self.entries.lock().unwrap();
+        record(fresh(&mut entries.by_name, name))
+    }
+
+    pub fn insert_by_id(&self, id: i32, user: Option<&User>) {
+        if let Some(entry) = self.entry(user) {
+            self.entries.lock().unwrap().by_id.insert(id, entry);
+        }
+    }
+
+    pub fn insert_by_name(&self, name: &str, user: Option<&User>) {
+        if let Some(entry) = self.entry(user) {
+            self.entries
+                .lock()
+                .unwrap()
+                .by_name
+                .insert(name.to_owned(), entry);
+        }
+    }
+
+    pub fn invalidate(&self, id: i32) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.by_id.remove(&id);
+        entries
+            .by_name
+            .retain(|_, entry| entry.user.as_ref().is_none_or(|user| user.id != id));
+    }
+
+    pub fn invalidate_name(&self, name: &str) {
+        self.entries.lock().unwrap().by_name.remove(name);
+    }
+
+    pub fn clear(&self) {
+        *self.entries.lock().unwrap() = Entries::default();
+    }
+
+    fn entry(&self, user: Option<&User>) -> Option<Entry> {
+        let ttl = match user {
+            Some(_) => self.ttl,
+            None => self.negative_ttl,
+        };
+
+        (self.is_enabled() && !ttl.is_zero()).then(|| Entry {
+            user: user.cloned(),
+            expires_at: Instant::now() + ttl,
+        })
+    }
+}
+
+fn fresh<K, Q>(map: &mut HashMap<K, Entry>, key: &Q) -> Option<Option<User>>
+where
+    K: std::borrow::Borrow<Q> + std::hash::Hash + Eq,
+    Q: std::hash::Hash + Eq + ?Sized,
+{
+    match map.get(key) {
+        Some(entry) if entry.expires_at > Instant::now() => Some(entry.user.clone()),
+        Some(_) => {
+            map.remove(key);
+            None
+        }
+        None => None,
+    }
+}
+
+fn record(cached: Option<Option<User>>) -> Option<Option<User>> {
+    let result = match &cached {
+        Some(Some(_)) => "hit",
+        Some(None) => "negative_hit",
+        None => "miss",
+    };
+    metrics::registry().increment_counter("user_cache_requests_total", &[("result", result)], 1);
+    cached
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: i32, name: &str) -> User {
+        User {
+            id,
+            name: name.to_string(),
+            surname: "Cached".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_invalidate_evicts_id_and_name_entries() {
+        let cache = UserCache::new(Duration::from_secs(60));
+        cache.insert_by_id(1, Some(&user(1, "Ann")));
+        cache.insert_by_name("Ann", Some(&user(1, "Ann")));
+        cache.insert_by_name("Bob", Some(&user(2, "Bob")));
+
+        cache.invalidate(1);
+
+        assert_eq!(cache.get_by_id(1), None);
+        assert_eq!(cache.get_by_name("Ann"), None);
+        assert_eq!(cache.get_by_name("Bob"), Some(Some(user(2, "Bob"))));
+    }
+
+    #[test]
+    fn test_expired_and_disabled() {
+        let expired = UserCache::new(Duration::from_nanos(1));
+        expired.insert_by_id(1, Some(&user(1, "Ann")));
+        std::thread::sleep(Duration::from_millis(1));
+
+        let disabled = UserCache::new(Duration::ZERO).with_negative_ttl(Duration::from_secs(5));
+        disabled.insert_by_id(1, Some(&user(1, "Ann")));
+        disabled.insert_by_id(2, None);
+
+        assert_eq!(expired.get_by_id(1), None);
+        assert_eq!(disabled.get_by_id(1), None);
+        assert_eq!(disabled.get_by_id(2), None);
+    }
+
+    #[test]
+    fn test_negative_entries() {
+        let cache =
+            UserCache::new(Duration::from_secs(60)).with_negative_ttl(Duration::from_secs(5));
+        let without = UserCache::new(Duration::from_secs(60));
+        cache.insert_by_id(7, None);
+        cache.insert_by_name("Nobody", None);
+        without.insert_by_id(7, None);
+
+        assert_eq!(cache.get_by_id(7), Some(None));
+        assert_eq!(without.get_by_id(7), None);
+
+        cache.invalidate(7);
+        cache.invalidate_name("Nobody");
+
+        assert_eq!(cache.get_by_id(7), None);
+        assert_eq!(cache.get_by_name("Nobody"), None);
+    }
+}
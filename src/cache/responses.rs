@@ -0,0 +1,180 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use prost::Message;
+use tonic::Status;
+
+use crate::{metrics, session, usecases::user_feed::UserFeed};
+
+const MAX_ENTRIES: usize = 1024;
+
+// encoded responses of idempotent listing RPCs keyed by method and encoded
+// request; an entry is dropped as soon as the change feed moves past the
+// sequence it was read at, so mutations through any API surface invalidate
+// it, and writes on other replicas are bounded by the ttl
+pub struct ResponseCache {
+    ttl: Duration,
+    feed: UserFeed,
+    entries: Mutex<HashMap<(&'static str, Vec<u8>), Entry>>,
+}
+
+struct Entry {
+    response: Vec<u8>,
+    sequence: u64,
+    expires_at: Instant,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: Duration, feed: UserFeed) -> Self {
+        Self {
+            ttl,
+            feed,
+            entries: Mutex::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    pub async fn get_or_insert_with<Req, Res, F, Fut>(
+        &self,
+        method: &'static str,
+        request: &Req,
+        f: F,
+    ) -> Result<Res, Status>
+    where
+        Req: Message,
+        Res: Message + Default,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Res, Status>>,
+    {
+        // a session token may name writes from another replica the cached
+        // response predates
+        if !self.is_enabled() || session::read_after().is_some() {
+            return f().await;
+        }
+        let key = (method, request.encode_to_vec());
+        // taken before the read, so a mutation racing it makes the entry stale
+        let sequence = self.feed.sequence();
+
+        if let Some(response) = self.get(&key, sequence) {
+            record(method, "hit");
+            return Ok(response);
+        }
+        record(method, "miss");
+        let response = f().await?;
+        self.insert(key, sequence, &response);
+
+        Ok(response)
+    }
+
+    fn get<Res: Message + Default>(
+        &self,
+        key: &(&'static str, Vec<u8>),
+        sequence: u64,
+    ) -> Option<Res> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some(entry) if entry.sequence == sequence && entry.expires_at > Instant::now() => {
+                Res::decode(entry.response.as_slice()).ok()
+            }
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, key: (&'static str, Vec<u8>), sequence: u64, response: &impl Message) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_ENTRIES {
+            let now = Instant::now();
+            entries.retain(|_, e| e.sequence == sequence && e.expires_at > now);
+        }
+        // still full of live entries, the next miss will try again
+        if entries.len() < MAX_ENTRIES {
+            entries.insert(
+                key,
+                Entry {
+                    response: response.encode_to_vec(),
+                    sequence,
+                    expires_at: Instant::now() + self.ttl,
+                },
+            );
+        }
+    }
+}
+
+fn record(method: &'static str, result: &'static str) {
+    metrics::registry().increment_counter(
+        "response_cache_requests_total",
+        &[("method", method), ("result", result)],
+        1,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{
+        entities::users::{User, UserEventKind},
+        grpc::{CountUsersRequest, CountUsersResponse, GetUsersRequest},
+    };
+
+    async fn count(cache: &ResponseCache, calls: &AtomicUsize, filter: &str) -> i64 {
+        let request = GetUsersRequest {
+            filter: filter.to_string(),
+            ..Default::default()
+        };
+        cache
+            .get_or_insert_with("GetUsers", &request, || async {
+                Ok(CountUsersResponse {
+                    count: calls.fetch_add(1, Ordering::SeqCst) as i64,
+                })
+            })
+            .await
+            .unwrap()
+            .count
+    }
+
+    #[tokio::test]
+    async fn test_entries_are_keyed_by_request_and_invalidated_by_mutations() {
+        let feed = UserFeed::new();
+        let cache = ResponseCache::new(Duration::from_secs(60), feed.clone());
+        let calls = AtomicUsize::new(0);
+
+        assert_eq!(count(&cache, &calls, "name = \"Ann\"").await, 0);
+        assert_eq!(count(&cache, &calls, "name = \"Ann\"").await, 0);
+        assert_eq!(count(&cache, &calls, "name = \"Bob\"").await, 1);
+
+        feed.publish(UserEventKind::Created, User::default());
+        assert_eq!(count(&cache, &calls, "name = \"Ann\"").await, 2);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_and_errors() {
+        let disabled = ResponseCache::new(Duration::ZERO, UserFeed::new());
+        let calls = AtomicUsize::new(0);
+        count(&disabled, &calls, "").await;
+        count(&disabled, &calls, "").await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let cache = ResponseCache::new(Duration::from_secs(60), UserFeed::new());
+        let failed: Result<CountUsersResponse, _> = cache
+            .get_or_insert_with("CountUsers", &CountUsersRequest { exact: None }, || async {
+                Err(Status::internal("down"))
+            })
+            .await;
+        assert!(failed.is_err());
+        assert!(cache.entries.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+#[derive(Debug, Parser)]
+#[command(name = "gin_tonik", about = "gRPC user service")]
+pub struct Cli {
+    /// Serve TLS with a freshly generated self-signed certificate (local development only)
+    #[arg(long)]
+    pub dev_tls: bool,
+
+    /// Override a setting over the environment and CONFIG_FILE, repeatable
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_override)]
+    pub overrides: Vec<(String, String)>,
+
+    /// Print the effective settings and where each came from, secrets redacted, then exit
+    #[arg(long)]
+    pub print_config: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+fn parse_override(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("expected KEY=VALUE, got {:?}", value)),
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Probe a running server and exit 0 when it is healthy, 1 otherwise
+    Healthcheck(HealthcheckArgs),
+    /// Browse, search and edit users in a terminal UI with live updates
+    #[cfg(feature = "tui")]
+    Tui(ConnectArgs),
+    /// Run commands like `get 42` against one connection, printing JSON lines
+    #[cfg(feature = "repl")]
+    Repl(ReplArgs),
+    /// Fire a weighted mix of RPCs at a fixed rate and report latency percentiles
+    Loadtest(LoadtestArgs),
+    /// Insert fake users into the configured database, reproducible per seed
+    #[cfg(feature = "generate")]
+    Generate(GenerateArgs),
+    /// Set up the configured database for local development
+    Db(DbArgs),
+    /// Apply, revert or list the embedded migrations
+    Migrate(MigrateArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ConnectArgs {
+    /// Server to connect to, defaults to the locally configured GRPC_ADDR
+    #[arg(long)]
+    pub endpoint: Option<String>,
+
+    /// CA certificate used to verify the server when it serves TLS
+    #[arg(long)]
+    pub ca_cert: Option<PathBuf>,
+
+    /// Bearer token sent with every call when auth is enabled
+    #[arg(long)]
+    pub token: Option<String>,
+
+    #[arg(long, default_value_t = 3)]
+    pub connect_timeout_secs: u64,
+}
+
+#[derive(Debug, Args)]
+pub struct ReplArgs {
+    #[command(flatten)]
+    pub connect: ConnectArgs,
+
+    /// Command history file, defaults to ~/.gin_tonik_history
+    #[arg(long)]
+    pub history: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct LoadtestArgs {
+    #[command(flatten)]
+    pub connect: ConnectArgs,
+
+    /// Target calls per second across all RPCs
+    #[arg(long, default_value_t = 100)]
+    pub rps: u32,
+
+    #[arg(long, default_value_t = 30)]
+    pub duration_secs: u64,
+
+    /// Weighted mix of create, get, list, search and stream
+    #[arg(long, default_value = "create=1,get=6,list=2,stream=1")]
+    pub mix: String,
+
+    /// Calls in flight at once, ticks beyond it are skipped and reported
+    #[arg(long, default_value_t = 64)]
+    pub concurrency: usize,
+}
+
+#[cfg(feature = "generate")]
+#[derive(Debug, Args)]
+pub struct GenerateArgs {
+    #[arg(long, default_value_t = 100)]
+    pub count: usize,
+
+    /// Same seed and locale, same names
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    #[arg(long, value_enum, default_value_t = crate::generate::Locale::En)]
+    pub locale: crate::generate::Locale,
+}
+
+#[derive(Debug, Args)]
+pub struct DbArgs {
+    #[command(subcommand)]
+    pub command: DbCommand,
+
+    /// Required unless every database is on this machine
+    #[arg(long, global = true)]
+    pub yes_i_know: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DbCommand {
+    /// Apply the migrations, then add the fixture users to an empty database
+    Seed,
+    /// Drop every table, apply the migrations and add the fixture users
+    Reset,
+    /// Encrypt the names and surnames still stored in plaintext or under an
+    /// older data key; safe to interrupt and run again
+    EncryptPii {
+        /// Users rewritten per transaction
+        #[arg(long, default_value_t = 500)]
+        batch_size: i32,
+    },
+    /// Encrypt with a new data key and re-encrypt every user with it, or
+    /// resume the unfinished rotation; the services keep running meanwhile
+    RotatePiiKey {
+        /// Users re-encrypted per transaction
+        #[arg(long, default_value_t = 500)]
+        batch_size: u32,
+        /// Throttles the re-encryption, unthrottled by default
+        #[arg(long)]
+        max_users_per_second: Option<u32>,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct MigrateArgs {
+    #[command(subcommand)]
+    pub command: MigrateCommand,
+
+    /// Required by `down` unless every database is on this machine
+    #[arg(long, global = true)]
+    pub yes_i_know: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MigrateCommand {
+    /// Apply every pending migration
+    Up,
+    /// Revert the last `n` applied migrations
+    Down { n: usize },
+    /// List applied and pending versions
+    Status,
+}
+
+#[derive(Debug, Args)]
+pub struct HealthcheckArgs {
+    /// Server to probe, defaults to the locally configured GRPC_ADDR
+    #[arg(long)]
+    pub endpoint: Option<String>,
+
+    /// Service name passed to grpc.health.v1.Health/Check, empty means the whole server
+    #[arg(long, default_value = "user.v1.UserService")]
+    pub service: String,
+
+    /// Also call CountUsers to make sure the database is reachable
+    #[arg(long)]
+    pub count_users: bool,
+
+    /// CA certificate used to verify the server when it serves TLS
+    #[arg(long)]
+    pub ca_cert: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 3)]
+    pub timeout_secs: u64,
+}
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use tonic_health::pb::{
+    HealthCheckRequest, health_check_response::ServingStatus, health_client::HealthClient,
+};
+
+use crate::{
+    Error,
+    cli::HealthcheckArgs,
+    client::{connect, local_endpoint},
+    config::{Config, TlsMode},
+    grpc::{CountUsersRequest, user_service_client::UserServiceClient},
+};
+
+pub async fn run(config: &Config, args: &HealthcheckArgs) -> Result<(), Error> {
+    let endpoint = args
+        .endpoint
+        .clone()
+        .unwrap_or_else(|| local_endpoint(config.addr, !matches!(config.tls, TlsMode::Disabled)));
+    let channel = connect(
+        endpoint,
+        args.ca_cert.as_deref(),
+        Duration::from_secs(args.timeout_secs),
+    )
+    .await?;
+
+    let res = HealthClient::new(channel.clone())
+        .check(HealthCheckRequest {
+            service: args.service.clone(),
+        })
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?
+        .into_inner();
+
+    if res.status() != ServingStatus::Serving {
+        return Err(Error::Internal(
+            format!("service {:?} is {:?}", args.service, res.status()).into(),
+        ));
+    }
+
+    if args.count_users {
+        UserServiceClient::new(channel)
+            .count_users(CountUsersRequest { exact: None })
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,333 @@
+use std::{
+    collections::BTreeMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::{sync::Semaphore, task::JoinSet};
+use tokio_stream::StreamExt;
+use tonic::Status;
+
+use crate::{
+    Error,
+    cli::LoadtestArgs,
+    client::{UserClient, user_client},
+    config::Config,
+    grpc::{
+        CreateUserRequest, GetUserByIdRequest, GetUsersRequest, ListUsersByNamePrefixRequest,
+        StreamUsersRequest,
+    },
+};
+
+const NAMES: &[&str] = &[
+    "Ann", "Bohdan", "Chiara", "Dmitri", "Elif", "Farah", "Gustavo", "Hana", "Ivan", "Jun",
+];
+const SURNAMES: &[&str] = &[
+    "Lee", "Kowalski", "Rossi", "Petrova", "Yilmaz", "Haddad", "Silva", "Sato", "Novak", "Chen",
+];
+const SEARCH_LIMIT: i32 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Rpc {
+    Create,
+    Get,
+    List,
+    Search,
+    Stream,
+}
+
+impl Rpc {
+    fn name(self) -> &'static str {
+        match self {
+            Rpc::Create => "create",
+            Rpc::Get => "get",
+            Rpc::List => "list",
+            Rpc::Search => "search",
+            Rpc::Stream => "stream",
+        }
+    }
+}
+
+impl FromStr for Rpc {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "create" => Ok(Rpc::Create),
+            "get" => Ok(Rpc::Get),
+            "list" => Ok(Rpc::List),
+            "search" => Ok(Rpc::Search),
+            "stream" => Ok(Rpc::Stream),
+            _ => Err(Error::InvalidArgument(format!("unknown rpc {:?}", s))),
+        }
+    }
+}
+
+// weighted RPC mix, e.g. `create=1,get=6,list=2,stream=1`
+#[derive(Debug, PartialEq)]
+struct Mix(Vec<(Rpc, u32)>);
+
+impl Mix {
+    fn parse(spec: &str) -> Result<Self, Error> {
+        let weights = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                let (rpc, weight) = part.split_once('=').unwrap_or((part, "1"));
+                let weight = weight
+                    .trim()
+                    .parse()
+                    .map_err(|_| Error::InvalidArgument(format!("bad weight in {:?}", part)))?;
+                Ok((rpc.trim().parse()?, weight))
+            })
+            .filter(|weight| !matches!(weight, Ok((_, 0))))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        if weights.is_empty() {
+            return Err(Error::InvalidArgument("the rpc mix is empty".to_string()));
+        }
+        Ok(Self(weights))
+    }
+
+    fn pick(&self, n: u64) -> Rpc {
+        let total: u64 = self.0.iter().map(|(_, w)| *w as u64).sum();
+        let mut slot = n % total;
+        for (rpc, weight) in &self.0 {
+            if slot < *weight as u64 {
+                return *rpc;
+            }
+            slot -= *weight as u64;
+        }
+        unreachable!("slot is below the total weight")
+    }
+}
+
+#[derive(Default)]
+struct Sample {
+    latencies: Vec<Duration>,
+    errors: u64,
+}
+
+// nearest-rank percentile over sorted latencies
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn payload(n: u64) -> (String, String) {
+    let n = n as usize;
+    (
+        NAMES[n % NAMES.len()].to_string(),
+        SURNAMES[(n / NAMES.len()) % SURNAMES.len()].to_string(),
+    )
+}
+
+pub async fn run(config: &Config, args: &LoadtestArgs) -> Result<(), Error> {
+    if args.rps == 0 || args.concurrency == 0 {
+        return Err(Error::InvalidArgument(
+            "rps and concurrency must be positive".to_string(),
+        ));
+    }
+    let mix = Mix::parse(&args.mix)?;
+    let mut client = user_client(config, &args.connect).await?;
+
+    // gets need at least one id to read back before the first create lands
+    let (name, surname) = payload(0);
+    let seed = client
+        .create_user(CreateUserRequest {
+            name,
+            surname,
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?
+        .into_inner()
+        .user
+        .unwrap_or_default();
+
+    let ids = Arc::new(Mutex::new(vec![seed.id]));
+    let samples = Arc::new(Mutex::new(BTreeMap::<Rpc, Sample>::new()));
+    let limit = Arc::new(Semaphore::new(args.concurrency));
+    let mut tasks = JoinSet::new();
+    let mut skipped = 0u64;
+
+    let duration = Duration::from_secs(args.duration_secs);
+    let mut ticks = tokio::time::interval(Duration::from_secs_f64(1.0 / args.rps as f64));
+    let started = Instant::now();
+
+    for n in 0.. {
+        ticks.tick().await;
+        if started.elapsed() >= duration {
+            break;
+        }
+        while tasks.try_join_next().is_some() {}
+
+        // open loop: a full concurrency budget drops the call instead of
+        // slowing the schedule down
+        let Ok(permit) = limit.clone().try_acquire_owned() else {
+            skipped += 1;
+            continue;
+        };
+        let rpc = mix.pick(n);
+        let (client, ids, samples) = (client.clone(), ids.clone(), samples.clone());
+        tasks.spawn(async move {
+            let start = Instant::now();
+            let result = call(client, rpc, n, &ids).await;
+            let elapsed = start.elapsed();
+            drop(permit);
+
+            let mut samples = samples.lock().unwrap();
+            let sample = samples.entry(rpc).or_default();
+            match result {
+                Ok(()) => sample.latencies.push(elapsed),
+                Err(_) => sample.errors += 1,
+            }
+        });
+    }
+    tasks.join_all().await;
+
+    let elapsed = started.elapsed();
+    let samples = std::mem::take(&mut *samples.lock().unwrap());
+    print!("{}", report(samples, elapsed, skipped));
+
+    Ok(())
+}
+
+async fn call(
+    mut client: UserClient,
+    rpc: Rpc,
+    n: u64,
+    ids: &Mutex<Vec<i32>>,
+) -> Result<(), Status> {
+    let (name, surname) = payload(n);
+
+    match rpc {
+        Rpc::Create => {
+            let res = client
+                .create_user(CreateUserRequest {
+                    name,
+                    surname,
+                    ..Default::default()
+                })
+                .await?;
+            if let Some(user) = res.into_inner().user {
+                ids.lock().unwrap().push(user.id);
+            }
+        }
+        Rpc::Get => {
+            let id = {
+                let ids = ids.lock().unwrap();
+                ids[n as usize % ids.len()]
+            };
+            client
+                .get_user_by_id(GetUserByIdRequest { id, as_of: None })
+                .await?;
+        }
+        Rpc::List => {
+            client
+                .get_users(GetUsersRequest {
+                    filter: format!("surname = {:?}", surname),
+                    ..Default::default()
+                })
+                .await?;
+        }
+        Rpc::Search => {
+            client
+                .list_users_by_name_prefix(ListUsersByNamePrefixRequest {
+                    prefix: name.chars().take(2).collect(),
+                    limit: SEARCH_LIMIT,
+                })
+                .await?;
+        }
+        // the latency covers draining the whole stream
+        Rpc::Stream => {
+            let mut users = client
+                .stream_users(StreamUsersRequest {
+                    chunk_size: 0,
+                    heartbeat_secs: 0,
+                })
+                .await?
+                .into_inner();
+            while let Some(user) = users.next().await {
+                user?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn report(samples: BTreeMap<Rpc, Sample>, elapsed: Duration, skipped: u64) -> String {
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let mut out = format!(
+        "{:<8} {:>8} {:>7} {:>9} {:>9} {:>9} {:>9}\n",
+        "rpc", "calls", "errors", "p50 ms", "p90 ms", "p99 ms", "max ms"
+    );
+    let mut total = 0;
+
+    for (rpc, mut sample) in samples {
+        sample.latencies.sort();
+        let calls = sample.latencies.len() as u64 + sample.errors;
+        total += calls;
+        out += &format!(
+            "{:<8} {:>8} {:>7} {:>9.2} {:>9.2} {:>9.2} {:>9.2}\n",
+            rpc.name(),
+            calls,
+            sample.errors,
+            ms(percentile(&sample.latencies, 50.0)),
+            ms(percentile(&sample.latencies, 90.0)),
+            ms(percentile(&sample.latencies, 99.0)),
+            ms(sample.latencies.last().copied().unwrap_or_default()),
+        );
+    }
+    out += &format!(
+        "{} calls in {:.1}s ({:.1} rps), {} skipped at the concurrency limit\n",
+        total,
+        elapsed.as_secs_f64(),
+        total as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        skipped
+    );
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mix() {
+        let mix = Mix::parse("create=1, get=3, stream=0, list").unwrap();
+        assert_eq!(
+            mix,
+            Mix(vec![(Rpc::Create, 1), (Rpc::Get, 3), (Rpc::List, 1)])
+        );
+
+        let picks: Vec<_> = (0..5).map(|n| mix.pick(n)).collect();
+        assert_eq!(
+            picks,
+            [Rpc::Create, Rpc::Get, Rpc::Get, Rpc::Get, Rpc::List]
+        );
+        assert_eq!(mix.pick(5), Rpc::Create);
+
+        assert!(Mix::parse("get=0").is_err());
+        assert!(Mix::parse("delete=1").is_err());
+        assert!(Mix::parse("get=lots").is_err());
+    }
+
+    #[test]
+    fn test_percentile() {
+        let latencies: Vec<_> = (1..=100).map(Duration::from_millis).collect();
+
+        assert_eq!(percentile(&latencies, 50.0), Duration::from_millis(50));
+        assert_eq!(percentile(&latencies, 99.0), Duration::from_millis(99));
+        assert_eq!(percentile(&latencies, 100.0), Duration::from_millis(100));
+        assert_eq!(percentile(&latencies[..1], 90.0), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 50.0), Duration::ZERO);
+    }
+}
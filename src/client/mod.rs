@@ -0,0 +1,121 @@
+pub mod healthcheck;
+pub mod loadtest;
+pub mod pool;
+#[cfg(feature = "repl")]
+pub mod repl;
+pub mod service_config;
+#[cfg(feature = "tui")]
+pub mod tui;
+
+use std::{net::SocketAddr, path::Path, time::Duration};
+
+use tonic::{
+    Status,
+    metadata::{Ascii, MetadataValue},
+    service::{Interceptor, interceptor::InterceptedService},
+    transport::{Certificate, Channel, ClientTlsConfig, Endpoint},
+};
+
+use crate::{
+    Error,
+    cli::ConnectArgs,
+    config::{Config, TlsMode},
+    grpc::user_service_client::UserServiceClient,
+};
+
+pub type UserClient = UserServiceClient<InterceptedService<Channel, BearerToken>>;
+
+// attaches `authorization: Bearer <token>` to every call when a token is set,
+// along with the trace context of the request being served, if any
+#[derive(Clone, Default)]
+pub struct BearerToken(Option<MetadataValue<Ascii>>);
+
+impl BearerToken {
+    pub fn new(token: Option<&str>) -> Result<Self, Error> {
+        token
+            .map(|t| {
+                format!("Bearer {}", t)
+                    .parse()
+                    .map_err(|_| Error::InvalidArgument("malformed token".to_string()))
+            })
+            .transpose()
+            .map(Self)
+    }
+}
+
+impl Interceptor for BearerToken {
+    fn call(&mut self, mut request: tonic::Request<()>) -> Result<tonic::Request<()>, Status> {
+        if let Some(token) = &self.0 {
+            request
+                .metadata_mut()
+                .insert("authorization", token.clone());
+        }
+        crate::telemetry::inject(request.metadata_mut());
+        Ok(request)
+    }
+}
+
+pub fn local_endpoint(addr: SocketAddr, tls: bool) -> String {
+    let ip = match addr.ip() {
+        ip if ip.is_unspecified() && ip.is_ipv4() => std::net::Ipv4Addr::LOCALHOST.into(),
+        ip if ip.is_unspecified() => std::net::Ipv6Addr::LOCALHOST.into(),
+        ip => ip,
+    };
+    let scheme = if tls { "https" } else { "http" };
+
+    format!("{}://{}", scheme, SocketAddr::new(ip, addr.port()))
+}
+
+pub async fn connect(
+    endpoint: String,
+    ca_cert: Option<&Path>,
+    timeout: Duration,
+) -> Result<Channel, Error> {
+    connect_with(endpoint, ca_cert, timeout, Some(timeout)).await
+}
+
+// interactive sessions keep streams open, so there is no per-call timeout
+pub async fn user_client(config: &Config, args: &ConnectArgs) -> Result<UserClient, Error> {
+    let endpoint = args
+        .endpoint
+        .clone()
+        .unwrap_or_else(|| local_endpoint(config.addr, !matches!(config.tls, TlsMode::Disabled)));
+    let channel = connect_with(
+        endpoint,
+        args.ca_cert.as_deref(),
+        Duration::from_secs(args.connect_timeout_secs),
+        None,
+    )
+    .await?;
+
+    Ok(UserServiceClient::with_interceptor(
+        channel,
+        BearerToken::new(args.token.as_deref())?,
+    ))
+}
+
+async fn connect_with(
+    endpoint: String,
+    ca_cert: Option<&Path>,
+    connect_timeout: Duration,
+    timeout: Option<Duration>,
+) -> Result<Channel, Error> {
+    let mut endpoint = Endpoint::from_shared(endpoint)
+        .map_err(|e| Error::Internal(Box::new(e)))?
+        .connect_timeout(connect_timeout);
+    if let Some(timeout) = timeout {
+        endpoint = endpoint.timeout(timeout);
+    }
+
+    if let Some(ca_cert) = ca_cert {
+        let pem = std::fs::read(ca_cert).map_err(|e| Error::Internal(Box::new(e)))?;
+        endpoint = endpoint
+            .tls_config(ClientTlsConfig::new().ca_certificate(Certificate::from_pem(pem)))
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+    }
+
+    endpoint
+        .connect()
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))
+}
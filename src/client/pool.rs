@@ -0,0 +1,372 @@
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{
+        Arc, RwLock, Weak,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use tokio::task::JoinSet;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint};
+use tonic_health::pb::{
+    HealthCheckRequest, health_check_response::ServingStatus, health_client::HealthClient,
+};
+use tracing::warn;
+
+use super::{BearerToken, UserClient};
+use crate::{Error, grpc::user_service_client::UserServiceClient};
+
+// the name the server reports UserService health under
+const HEALTH_SERVICE: &str = "user.v1.UserService";
+
+#[derive(Clone, Debug)]
+pub struct PoolSettings {
+    // spread over every address the endpoints resolve to, at least one each
+    pub channels: usize,
+    // CA certificate verifying https endpoints, by their host name
+    pub ca_cert: Option<PathBuf>,
+    pub token: Option<String>,
+    pub connect_timeout: Duration,
+    // per call; None for streams that stay open
+    pub timeout: Option<Duration>,
+    // how often the endpoints are resolved again and every channel checked
+    pub health_interval: Duration,
+}
+
+impl Default for PoolSettings {
+    fn default() -> Self {
+        Self {
+            channels: 4,
+            ca_cert: None,
+            token: None,
+            connect_timeout: Duration::from_secs(5),
+            timeout: Some(Duration::from_secs(10)),
+            health_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+// channels to every replica behind one or more endpoints, like
+// `https://users.internal:42069`, for consumers making many calls: each
+// `client()` takes the next channel serving UserService, round robin. The
+// endpoints are resolved through DNS again every health interval, so
+// replicas coming and going are picked up; a channel only reconnects when
+// used, and one found not serving is skipped until a later check finds it
+// serving again. When none is, calls go round all of them regardless
+#[derive(Clone)]
+pub struct UserClientPool {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    endpoints: Vec<reqwest::Url>,
+    settings: PoolSettings,
+    ca_cert: Option<Certificate>,
+    token: BearerToken,
+    members: RwLock<Vec<Member>>,
+    next: AtomicUsize,
+}
+
+#[derive(Clone)]
+struct Member {
+    // into `endpoints`
+    endpoint: usize,
+    addr: SocketAddr,
+    channel: Channel,
+    healthy: Arc<AtomicBool>,
+}
+
+impl UserClientPool {
+    // fails when no endpoint resolves; the replicas need not be up yet
+    pub async fn connect(endpoints: &[String], settings: PoolSettings) -> Result<Self, Error> {
+        let endpoints = endpoints
+            .iter()
+            .map(|e| {
+                let url = reqwest::Url::parse(e)
+                    .map_err(|err| Error::InvalidArgument(format!("endpoint {}: {}", e, err)))?;
+                match url.scheme() {
+                    "http" | "https" if url.host_str().is_some() => Ok(url),
+                    _ => Err(Error::InvalidArgument(format!(
+                        "endpoint {} is not http(s)://host:port",
+                        e
+                    ))),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if endpoints.is_empty() || settings.channels == 0 {
+            return Err(Error::InvalidArgument(
+                "a pool needs an endpoint and a channel".to_string(),
+            ));
+        }
+        let ca_cert = settings
+            .ca_cert
+            .as_ref()
+            .map(|path| {
+                std::fs::read(path)
+                    .map(Certificate::from_pem)
+                    .map_err(|e| Error::Internal(Box::new(e)))
+            })
+            .transpose()?;
+
+        let inner = Arc::new(Inner {
+            token: BearerToken::new(settings.token.as_deref())?,
+            endpoints,
+            settings,
+            ca_cert,
+            members: RwLock::default(),
+            next: AtomicUsize::new(0),
+        });
+        inner.refresh().await?;
+        if inner.members.read().unwrap().is_empty() {
+            return Err(Error::Unavailable(
+                "no endpoint resolved to an address".to_string(),
+            ));
+        }
+        tokio::spawn(check_health(Arc::downgrade(&inner)));
+
+        Ok(Self { inner })
+    }
+
+    pub fn client(&self) -> UserClient {
+        UserServiceClient::with_interceptor(self.pick().channel, self.inner.token.clone())
+    }
+
+    // every channel's address and whether it served on the last check
+    pub fn channels(&self) -> Vec<(SocketAddr, bool)> {
+        self.inner
+            .members
+            .read()
+            .unwrap()
+            .iter()
+            .map(|m| (m.addr, m.healthy.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    fn pick(&self) -> Member {
+        let members = self.inner.members.read().unwrap();
+        let start = self.inner.next.fetch_add(1, Ordering::Relaxed);
+        (0..members.len())
+            .map(|i| &members[(start + i) % members.len()])
+            .find(|m| m.healthy.load(Ordering::Relaxed))
+            .unwrap_or(&members[start % members.len()])
+            .clone()
+    }
+}
+
+// until the pool is dropped
+async fn check_health(inner: Weak<Inner>) {
+    loop {
+        let Some(interval) = inner.upgrade().map(|i| i.settings.health_interval) else {
+            return;
+        };
+        tokio::time::sleep(interval).await;
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+        if let Err(e) = inner.refresh().await {
+            warn!("failed to refresh the user client pool: {}", e);
+        }
+    }
+}
+
+impl Inner {
+    async fn refresh(&self) -> Result<(), Error> {
+        let mut addrs = Vec::new();
+        let previous = self.members.read().unwrap().clone();
+        for (n, endpoint) in self.endpoints.iter().enumerate() {
+            match resolve(endpoint).await {
+                Ok(resolved) => addrs.extend(resolved.into_iter().map(|a| (n, a))),
+                // the endpoint's channels stay as they were
+                Err(e) => {
+                    warn!("failed to resolve {}: {}", endpoint, e);
+                    addrs.extend(
+                        previous
+                            .iter()
+                            .filter(|m| m.endpoint == n)
+                            .map(|m| (n, m.addr)),
+                    );
+                }
+            }
+        }
+        addrs.sort();
+        addrs.dedup();
+        if addrs.is_empty() {
+            return Ok(());
+        }
+
+        // the channels of addresses still resolved are kept, so only new
+        // replicas need connecting
+        let mut reusable = previous;
+        let mut members = Vec::new();
+        for slot in 0..self.settings.channels.max(addrs.len()) {
+            let (endpoint, addr) = addrs[slot % addrs.len()];
+            let member = match reusable
+                .iter()
+                .position(|m| m.endpoint == endpoint && m.addr == addr)
+            {
+                Some(at) => reusable.swap_remove(at),
+                None => Member {
+                    endpoint,
+                    addr,
+                    channel: self.channel(&self.endpoints[endpoint], addr)?,
+                    healthy: Arc::default(),
+                },
+            };
+            members.push(member);
+        }
+
+        let mut checks = JoinSet::new();
+        for member in &members {
+            let (channel, healthy) = (member.channel.clone(), member.healthy.clone());
+            let timeout = self.settings.connect_timeout;
+            checks.spawn(async move {
+                let mut client = HealthClient::new(channel);
+                let check = client.check(HealthCheckRequest {
+                    service: HEALTH_SERVICE.to_string(),
+                });
+                let serving = matches!(
+                    tokio::time::timeout(timeout, check).await,
+                    Ok(Ok(res)) if res.get_ref().status() == ServingStatus::Serving
+                );
+                healthy.store(serving, Ordering::Relaxed);
+            });
+        }
+        checks.join_all().await;
+        *self.members.write().unwrap() = members;
+
+        Ok(())
+    }
+
+    // to `addr`, verifying TLS against the endpoint's host name
+    fn channel(&self, endpoint: &reqwest::Url, addr: SocketAddr) -> Result<Channel, Error> {
+        let mut channel = Endpoint::from_shared(format!("{}://{}", endpoint.scheme(), addr))
+            .map_err(|e| Error::Internal(Box::new(e)))?
+            .connect_timeout(self.settings.connect_timeout);
+        if let Some(timeout) = self.settings.timeout {
+            channel = channel.timeout(timeout);
+        }
+        if endpoint.scheme() == "https" {
+            let mut tls = ClientTlsConfig::new().domain_name(host(endpoint));
+            if let Some(ca_cert) = &self.ca_cert {
+                tls = tls.ca_certificate(ca_cert.clone());
+            }
+            channel = channel
+                .tls_config(tls)
+                .map_err(|e| Error::Internal(Box::new(e)))?;
+        }
+
+        Ok(channel.connect_lazy())
+    }
+}
+
+// without the brackets of an IPv6 address
+fn host(endpoint: &reqwest::Url) -> &str {
+    let host = endpoint.host_str().unwrap_or_default();
+    host.strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host)
+}
+
+async fn resolve(endpoint: &reqwest::Url) -> Result<Vec<SocketAddr>, Error> {
+    let port = endpoint
+        .port_or_known_default()
+        .ok_or_else(|| Error::InvalidArgument(format!("endpoint {} has no port", endpoint)))?;
+    let addrs = tokio::net::lookup_host((host(endpoint), port))
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+    Ok(addrs.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic_health::server::HealthReporter;
+
+    use super::*;
+
+    async fn health_server(status: tonic_health::ServingStatus) -> (String, HealthReporter) {
+        let (reporter, service) = tonic_health::server::health_reporter();
+        reporter.set_service_status(HEALTH_SERVICE, status).await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        (format!("http://{}", addr), reporter)
+    }
+
+    fn picked(pool: &UserClientPool) -> HashSet<SocketAddr> {
+        (0..8).map(|_| pool.pick().addr).collect()
+    }
+
+    #[tokio::test]
+    async fn test_round_robin_over_serving_channels() {
+        let (serving, serving_reporter) = health_server(tonic_health::ServingStatus::Serving).await;
+        let (draining, draining_reporter) =
+            health_server(tonic_health::ServingStatus::NotServing).await;
+        let settings = PoolSettings {
+            health_interval: Duration::from_secs(3600),
+            ..PoolSettings::default()
+        };
+        let pool = UserClientPool::connect(&[serving.clone(), draining.clone()], settings)
+            .await
+            .unwrap();
+        let addr = |endpoint: &str| -> SocketAddr {
+            endpoint.trim_start_matches("http://").parse().unwrap()
+        };
+
+        let channels = pool.channels();
+        assert_eq!(channels.len(), 4);
+        assert_eq!(
+            channels
+                .iter()
+                .filter(|(a, _)| *a == addr(&serving))
+                .count(),
+            2
+        );
+        assert!(
+            channels
+                .iter()
+                .all(|(a, healthy)| *healthy == (*a == addr(&serving)))
+        );
+        assert_eq!(picked(&pool), HashSet::from([addr(&serving)]));
+
+        draining_reporter
+            .set_service_status(HEALTH_SERVICE, tonic_health::ServingStatus::Serving)
+            .await;
+        pool.inner.refresh().await.unwrap();
+        assert_eq!(
+            picked(&pool),
+            HashSet::from([addr(&serving), addr(&draining)])
+        );
+
+        serving_reporter
+            .set_service_status(HEALTH_SERVICE, tonic_health::ServingStatus::NotServing)
+            .await;
+        pool.inner.refresh().await.unwrap();
+        assert_eq!(picked(&pool), HashSet::from([addr(&draining)]));
+    }
+
+    #[tokio::test]
+    async fn test_endpoints_must_resolve() {
+        let settings = PoolSettings::default();
+        for endpoints in [vec![], vec!["users:42069".to_string()]] {
+            assert!(matches!(
+                UserClientPool::connect(&endpoints, settings.clone()).await,
+                Err(Error::InvalidArgument(_))
+            ));
+        }
+        assert!(
+            UserClientPool::connect(&["http://users.invalid:42069".to_string()], settings)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,413 @@
+use std::{
+    io::IsTerminal,
+    path::{Path, PathBuf},
+};
+
+use prost_reflect::{DescriptorPool, DynamicMessage};
+use rustyline::{Behavior, DefaultEditor, error::ReadlineError};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio_stream::StreamExt;
+use tonic::Status;
+
+use crate::{
+    Error,
+    cli::ReplArgs,
+    client::{UserClient, user_client},
+    config::Config,
+    grpc::{
+        self, CountUsersRequest, CreateUserRequest, DeleteUserRequest, GetUserByIdRequest,
+        GetUsersRequest, ListUsersByNamePrefixRequest, UpdateUserRequest, UserExistsRequest,
+        WatchUsersRequest,
+    },
+};
+
+const PROMPT: &str = "gin_tonik> ";
+const HISTORY_FILE: &str = ".gin_tonik_history";
+const HELP: &str = "\
+get <id>                                 GetUserById
+exists <id>                              UserExists
+create <name> <surname>                  CreateUser
+update <id> [name=<v>] [surname=<v>]     UpdateUser, an empty value clears the field
+delete <id>                              DeleteUser
+list [filter]                            GetUsers, e.g. list name = \"Ann\"
+count                                    CountUsers
+search <prefix> [limit]                  ListUsersByNamePrefix
+watch [after_sequence]                   WatchUsers until ctrl-c
+help | quit";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Get(i32),
+    Exists(i32),
+    Create {
+        name: String,
+        surname: String,
+    },
+    Update {
+        id: i32,
+        name: Option<String>,
+        surname: Option<String>,
+    },
+    Delete(i32),
+    List(String),
+    Count,
+    Search {
+        prefix: String,
+        limit: i32,
+    },
+    Watch(u64),
+    Help,
+    Quit,
+}
+
+// blank lines and `#` comments parse to None so scripts can be annotated
+fn parse(line: &str) -> Result<Option<Command>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let args = words(rest)?;
+
+    let command = match (command, args.as_slice()) {
+        ("get", [id]) => Command::Get(number(id)?),
+        ("exists", [id]) => Command::Exists(number(id)?),
+        ("create", [name, surname]) => Command::Create {
+            name: name.clone(),
+            surname: surname.clone(),
+        },
+        ("update", [id, fields @ ..]) if !fields.is_empty() => {
+            let (mut name, mut surname) = (None, None);
+            for field in fields {
+                match field.split_once('=') {
+                    Some(("name", value)) => name = Some(value.to_string()),
+                    Some(("surname", value)) => surname = Some(value.to_string()),
+                    _ => return Err(format!("expected name=<v> or surname=<v>, got {:?}", field)),
+                }
+            }
+            Command::Update {
+                id: number(id)?,
+                name,
+                surname,
+            }
+        }
+        ("delete", [id]) => Command::Delete(number(id)?),
+        // the filter keeps its own quoting
+        ("list", _) => Command::List(rest.trim().to_string()),
+        ("count", []) => Command::Count,
+        ("search", [prefix]) => Command::Search {
+            prefix: prefix.clone(),
+            limit: 0,
+        },
+        ("search", [prefix, limit]) => Command::Search {
+            prefix: prefix.clone(),
+            limit: number(limit)?,
+        },
+        ("watch", []) => Command::Watch(0),
+        ("watch", [after]) => Command::Watch(number(after)?),
+        ("help", _) => Command::Help,
+        ("quit" | "exit", _) => Command::Quit,
+        (command, _) => {
+            return Err(format!(
+                "unknown command or wrong arguments: {} (try help)",
+                command
+            ));
+        }
+    };
+
+    Ok(Some(command))
+}
+
+// whitespace separated, with '…' or "…" for values containing spaces
+fn words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_default().push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_default();
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_default().push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err("unterminated quote".to_string());
+    }
+    words.extend(word);
+
+    Ok(words)
+}
+
+fn number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("expected a number, got {:?}", value))
+}
+
+struct Session {
+    client: UserClient,
+    pool: DescriptorPool,
+}
+
+impl Session {
+    async fn execute(&mut self, command: Command) -> Result<(), Status> {
+        match command {
+            Command::Get(id) => {
+                let res = self
+                    .client
+                    .get_user_by_id(GetUserByIdRequest { id, as_of: None })
+                    .await?;
+                self.print("GetUserByIdResponse", res.get_ref())
+            }
+            Command::Exists(id) => {
+                let res = self.client.user_exists(UserExistsRequest { id }).await?;
+                self.print("UserExistsResponse", res.get_ref())
+            }
+            Command::Create { name, surname } => {
+                let res = self
+                    .client
+                    .create_user(CreateUserRequest {
+                        name,
+                        surname,
+                        ..Default::default()
+                    })
+                    .await?;
+                self.print("CreateUserResponse", res.get_ref())
+            }
+            Command::Update { id, name, surname } => {
+                let res = self
+                    .client
+                    .update_user(UpdateUserRequest { id, name, surname })
+                    .await?;
+                self.print("UpdateUserResponse", res.get_ref())
+            }
+            Command::Delete(id) => {
+                let res = self.client.delete_user(DeleteUserRequest { id }).await?;
+                self.print("DeleteUserResponse", res.get_ref())
+            }
+            Command::List(filter) => {
+                let res = self
+                    .client
+                    .get_users(GetUsersRequest {
+                        filter,
+                        ..Default::default()
+                    })
+                    .await?;
+                self.print("GetUsersResponse", res.get_ref())
+            }
+            Command::Count => {
+                let res = self
+                    .client
+                    .count_users(CountUsersRequest { exact: None })
+                    .await?;
+                self.print("CountUsersResponse", res.get_ref())
+            }
+            Command::Search { prefix, limit } => {
+                let res = self
+                    .client
+                    .list_users_by_name_prefix(ListUsersByNamePrefixRequest { prefix, limit })
+                    .await?;
+                self.print("ListUsersByNamePrefixResponse", res.get_ref())
+            }
+            Command::Watch(after_sequence) => {
+                let mut events = self
+                    .client
+                    .watch_users(WatchUsersRequest {
+                        after_sequence,
+                        resume_token: 0,
+                        heartbeat_secs: 0,
+                    })
+                    .await?
+                    .into_inner();
+                loop {
+                    tokio::select! {
+                        _ = tokio::signal::ctrl_c() => return Ok(()),
+                        event = events.next() => match event {
+                            Some(event) => self.print("UserEvent", &event?)?,
+                            None => return Ok(()),
+                        },
+                    }
+                }
+            }
+            Command::Help => {
+                println!("{}", HELP);
+                Ok(())
+            }
+            Command::Quit => Ok(()),
+        }
+    }
+
+    // one protojson document per line, ready for jq
+    fn print<M: prost::Message>(&self, name: &str, message: &M) -> Result<(), Status> {
+        println!("{}", to_json(&self.pool, name, message)?);
+        Ok(())
+    }
+}
+
+fn to_json<M: prost::Message>(
+    pool: &DescriptorPool,
+    name: &str,
+    message: &M,
+) -> Result<String, Status> {
+    let desc = pool
+        .get_message_by_name(&format!("user.v1.{}", name))
+        .ok_or_else(|| Status::internal(format!("unknown message {}", name)))?;
+    let mut dynamic = DynamicMessage::new(desc);
+    dynamic
+        .transcode_from(message)
+        .map_err(|e| Status::internal(e.to_string()))?;
+
+    serde_json::to_string(&dynamic).map_err(|e| Status::internal(e.to_string()))
+}
+
+pub async fn run(config: &Config, args: &ReplArgs) -> Result<(), Error> {
+    let mut session = Session {
+        client: user_client(config, &args.connect).await?,
+        pool: DescriptorPool::decode(grpc::FILE_DESCRIPTOR_SET)
+            .map_err(|e| Error::Internal(Box::new(e)))?,
+    };
+
+    if std::io::stdin().is_terminal() {
+        let history = args.history.clone().or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE))
+        });
+        interactive(&mut session, history.as_deref()).await
+    } else {
+        script(&mut session).await
+    }
+}
+
+async fn interactive(session: &mut Session, history: Option<&Path>) -> Result<(), Error> {
+    // prompts go to the terminal so stdout stays pipeable
+    let config = rustyline::Config::builder()
+        .behavior(Behavior::PreferTerm)
+        .auto_add_history(true)
+        .build();
+    let mut editor =
+        DefaultEditor::with_config(config).map_err(|e| Error::Internal(Box::new(e)))?;
+    if let Some(history) = history {
+        let _ = editor.load_history(history);
+    }
+
+    loop {
+        let line = match tokio::task::block_in_place(|| editor.readline(PROMPT)) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(Error::Internal(Box::new(e))),
+        };
+        match parse(&line) {
+            Ok(Some(Command::Quit)) => break,
+            Ok(Some(command)) => {
+                if let Err(status) = session.execute(command).await {
+                    eprintln!("error: {:?}: {}", status.code(), status.message());
+                }
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("error: {}", e),
+        }
+    }
+
+    if let Some(history) = history {
+        let _ = editor.save_history(history);
+    }
+    Ok(())
+}
+
+// commands from stdin, the first failure aborts like `set -e`
+async fn script(session: &mut Session) -> Result<(), Error> {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut number = 0;
+
+    while let Some(line) = lines
+        .next_line()
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?
+    {
+        number += 1;
+        let command = match parse(&line) {
+            Ok(Some(Command::Quit)) => break,
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(e) => return Err(Error::InvalidArgument(format!("line {}: {}", number, e))),
+        };
+        session.execute(command).await.map_err(|status| {
+            Error::Internal(
+                format!("line {}: {:?}: {}", number, status.code(), status.message()).into(),
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("  # setup").unwrap(), None);
+        assert_eq!(parse("get 42").unwrap(), Some(Command::Get(42)));
+        assert_eq!(
+            parse(r#"create "Mary Ann" Lee"#).unwrap(),
+            Some(Command::Create {
+                name: "Mary Ann".into(),
+                surname: "Lee".into(),
+            })
+        );
+        assert_eq!(
+            parse("update 7 surname=").unwrap(),
+            Some(Command::Update {
+                id: 7,
+                name: None,
+                surname: Some(String::new()),
+            })
+        );
+        assert_eq!(
+            parse(r#"list name = "Ann" AND surname = "Lee""#).unwrap(),
+            Some(Command::List(r#"name = "Ann" AND surname = "Lee""#.into()))
+        );
+        assert_eq!(parse("watch 12").unwrap(), Some(Command::Watch(12)));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("get").is_err());
+        assert!(parse("get abc").is_err());
+        assert!(parse("update 7").is_err());
+        assert!(parse("update 7 email=a@b").is_err());
+        assert!(parse(r#"create "Ann Lee"#).is_err());
+        assert!(parse("frobnicate").is_err());
+    }
+
+    #[test]
+    fn test_to_json() {
+        let pool = DescriptorPool::decode(grpc::FILE_DESCRIPTOR_SET).unwrap();
+        let json = to_json(
+            &pool,
+            "GetUsersResponse",
+            &grpc::GetUsersResponse {
+                users: vec![grpc::User {
+                    id: 1,
+                    name: "Ann".into(),
+                    surname: "Lee".into(),
+                }],
+                count: 1,
+                next_page_token: String::new(),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            json,
+            r#"{"users":[{"id":1,"name":"Ann","surname":"Lee"}],"count":1}"#
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use prost_reflect::DescriptorPool;
+use prost_types::method_options::IdempotencyLevel;
+use serde_json::{Value, json};
+
+use crate::grpc;
+
+// where the HTTP server serves it
+pub const PATH: &str = "/service-config.json";
+
+// the gRPC service config (gRFC A6) for every user.* service, from the
+// idempotency_level of each method: reads are hedged and time out after 5s,
+// idempotent writes are retried on UNAVAILABLE, which is all a draining or
+// unreachable replica answers, and streams have no deadline. Everything else
+// only times out after 10s, a write may have been applied before the failure
+pub fn service_config() -> Value {
+    let pool = DescriptorPool::decode(grpc::FILE_DESCRIPTOR_SET)
+        .expect("the embedded descriptor set decodes");
+    let mut services = Vec::new();
+    let (mut hedged, mut retried, mut retried_streams, mut streams) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for service in pool.services() {
+        if !service.package_name().starts_with("user.") {
+            continue;
+        }
+        services.push(service.full_name().to_owned());
+        for method in service.methods() {
+            let name = (service.full_name().to_owned(), method.name().to_owned());
+            let streaming = method.is_client_streaming() || method.is_server_streaming();
+            let level = method
+                .method_descriptor_proto()
+                .options
+                .as_ref()
+                .and_then(|o| o.idempotency_level)
+                .and_then(|l| IdempotencyLevel::try_from(l).ok())
+                .unwrap_or(IdempotencyLevel::IdempotencyUnknown);
+            // a client streaming its requests can only be retried as far as
+            // it buffers them, which for AutocompleteUsers and ImportUsers is
+            // never
+            let group = match (level, streaming) {
+                (_, true) if method.is_client_streaming() => &mut streams,
+                (IdempotencyLevel::IdempotencyUnknown, true) => &mut streams,
+                (IdempotencyLevel::IdempotencyUnknown, false) => continue,
+                (_, true) => &mut retried_streams,
+                (IdempotencyLevel::NoSideEffects, false) => &mut hedged,
+                (IdempotencyLevel::Idempotent, false) => &mut retried,
+            };
+            group.push(name);
+        }
+    }
+    services.sort();
+    let names = |mut methods: Vec<(String, String)>| {
+        methods.sort();
+        methods
+            .into_iter()
+            .map(|(service, method)| json!({ "service": service, "method": method }))
+            .collect::<Vec<_>>()
+    };
+    let retry_policy = json!({
+        "maxAttempts": 4,
+        "initialBackoff": "0.1s",
+        "maxBackoff": "2s",
+        "backoffMultiplier": 2,
+        "retryableStatusCodes": ["UNAVAILABLE"],
+    });
+
+    json!({
+        "methodConfig": [
+            {
+                "name": services.iter().map(|s| json!({ "service": s })).collect::<Vec<_>>(),
+                "timeout": "10s",
+            },
+            {
+                "name": names(hedged),
+                "timeout": "5s",
+                "hedgingPolicy": {
+                    "maxAttempts": 3,
+                    "hedgingDelay": "0.1s",
+                    "nonFatalStatusCodes": ["UNAVAILABLE"],
+                },
+            },
+            {
+                "name": names(retried),
+                "timeout": "10s",
+                "retryPolicy": retry_policy,
+            },
+            { "name": names(retried_streams), "retryPolicy": retry_policy },
+            { "name": names(streams) },
+        ],
+        // retries and hedges pause while failures far outnumber successes
+        "retryThrottling": { "maxTokens": 10, "tokenRatio": 0.1 },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn methods(config: &Value, group: usize) -> Vec<String> {
+        config["methodConfig"][group]["name"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|n| {
+                format!(
+                    "{}/{}",
+                    n["service"].as_str().unwrap(),
+                    n["method"].as_str().unwrap()
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_only_idempotent_methods_are_retried() {
+        let config = service_config();
+        let hedged = methods(&config, 1);
+        assert!(hedged.contains(&"user.v1.UserService/GetUserById".to_string()));
+        assert!(hedged.contains(&"user.v2.UserService/ListUsers".to_string()));
+        assert!(!hedged.iter().any(|m| m.ends_with("/CreateUser")));
+        assert_eq!(
+            methods(&config, 2),
+            [
+                "user.v1.AdminService/ClearRepositoryDelays",
+                "user.v1.AdminService/SetQuota",
+                "user.v1.AdminService/SetRepositoryDelay",
+                "user.v1.AdminService/SetTraceSampling"
+            ]
+        );
+        let retried_streams = methods(&config, 3);
+        assert!(retried_streams.contains(&"user.v1.UserService/WatchUsers".to_string()));
+        assert_eq!(
+            methods(&config, 4),
+            [
+                "user.v1.UserService/AutocompleteUsers",
+                "user.v1.UserService/ImportUsers"
+            ]
+        );
+        // nothing is named twice, gRPC rejects such a config
+        let mut all: Vec<String> = (1..5).flat_map(|group| methods(&config, group)).collect();
+        let count = all.len();
+        all.sort();
+        all.dedup();
+        assert_eq!(all.len(), count);
+
+        // the copy shipped along the protos is up to date
+        let shipped: Value =
+            serde_json::from_str(include_str!("../../proto/service_config.json")).unwrap();
+        assert_eq!(
+            shipped,
+            config,
+            "proto/service_config.json is stale, replace it with\n{}",
+            serde_json::to_string_pretty(&config).unwrap()
+        );
+    }
+}
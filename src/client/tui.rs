@@ -0,0 +1,550 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::{
+    DefaultTerminal, Frame,
+    layout::{Constraint, Flex, Layout, Rect},
+    style::{Style, Stylize},
+    text::Line,
+    widgets::{Block, Clear, Paragraph, Row, Table, TableState},
+};
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+use tonic::{Code, Status};
+
+use crate::{
+    Error,
+    cli::ConnectArgs,
+    client::{UserClient, user_client},
+    config::Config,
+    grpc::{
+        DeleteUserRequest, GetUsersRequest, ListUsersByNamePrefixRequest, UpdateUserRequest, User,
+        UserEvent, UserEventKind, WatchUsersRequest,
+    },
+};
+
+const SEARCH_LIMIT: i32 = 100;
+const WATCH_RETRY: Duration = Duration::from_secs(2);
+const HELP: &str = "/ search  ↑↓ move  e edit  d delete  r reload  q quit";
+
+#[derive(Debug)]
+enum Msg {
+    Loaded(Result<Vec<User>, Status>),
+    Searched {
+        prefix: String,
+        result: Result<Vec<User>, Status>,
+    },
+    Watched(UserEvent),
+    // the watch resumes by itself, missed events need a reload
+    WatchFailed {
+        status: Status,
+        resync: bool,
+    },
+    Saved(Result<User, Status>),
+    Deleted(i32, Result<(), Status>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    None,
+    Reload,
+    Search(String),
+    Update(UpdateUserRequest),
+    Delete(i32),
+}
+
+#[derive(Debug, Default, PartialEq)]
+enum Mode {
+    #[default]
+    Browse,
+    Search,
+    Edit(Form),
+    ConfirmDelete(i32),
+}
+
+#[derive(Debug, PartialEq)]
+struct Form {
+    id: i32,
+    name: String,
+    surname: String,
+    editing_surname: bool,
+}
+
+impl Form {
+    fn field(&mut self) -> &mut String {
+        if self.editing_surname {
+            &mut self.surname
+        } else {
+            &mut self.name
+        }
+    }
+}
+
+#[derive(Default)]
+struct App {
+    // the full list, or the prefix search results while `search` is set
+    users: BTreeMap<i32, User>,
+    search: String,
+    mode: Mode,
+    selected: usize,
+    status: String,
+    quit: bool,
+}
+
+impl App {
+    fn key(&mut self, key: KeyEvent) -> Action {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return Action::None;
+        }
+
+        match &mut self.mode {
+            Mode::Browse => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+                KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+                KeyCode::Down | KeyCode::Char('j') => self.select(self.selected + 1),
+                KeyCode::Char('/') => self.mode = Mode::Search,
+                KeyCode::Char('r') => return self.refresh(),
+                KeyCode::Char('e') | KeyCode::Enter => {
+                    if let Some(user) = self.current() {
+                        self.mode = Mode::Edit(Form {
+                            id: user.id,
+                            name: user.name.clone(),
+                            surname: user.surname.clone(),
+                            editing_surname: false,
+                        });
+                    }
+                }
+                KeyCode::Char('d') | KeyCode::Delete => {
+                    if let Some(user) = self.current() {
+                        self.mode = Mode::ConfirmDelete(user.id);
+                    }
+                }
+                _ => {}
+            },
+            Mode::Search => match key.code {
+                KeyCode::Enter => self.mode = Mode::Browse,
+                KeyCode::Esc => {
+                    self.mode = Mode::Browse;
+                    self.search.clear();
+                    return Action::Reload;
+                }
+                KeyCode::Backspace => {
+                    self.search.pop();
+                    return self.refresh();
+                }
+                KeyCode::Char(c) => {
+                    self.search.push(c);
+                    return self.refresh();
+                }
+                _ => {}
+            },
+            Mode::Edit(form) => match key.code {
+                KeyCode::Esc => self.mode = Mode::Browse,
+                KeyCode::Tab | KeyCode::BackTab => form.editing_surname = !form.editing_surname,
+                KeyCode::Backspace => {
+                    form.field().pop();
+                }
+                KeyCode::Char(c) => form.field().push(c),
+                KeyCode::Enter => {
+                    let Mode::Edit(form) = std::mem::take(&mut self.mode) else {
+                        unreachable!()
+                    };
+                    return Action::Update(UpdateUserRequest {
+                        id: form.id,
+                        name: Some(form.name),
+                        surname: Some(form.surname),
+                    });
+                }
+                _ => {}
+            },
+            Mode::ConfirmDelete(id) => {
+                let id = *id;
+                self.mode = Mode::Browse;
+                if key.code == KeyCode::Char('y') {
+                    return Action::Delete(id);
+                }
+            }
+        }
+
+        Action::None
+    }
+
+    fn apply(&mut self, msg: Msg) -> Action {
+        match msg {
+            Msg::Loaded(result) if self.search.is_empty() => self.replace(result),
+            Msg::Loaded(_) => {}
+            Msg::Searched { prefix, result } if prefix == self.search => self.replace(result),
+            Msg::Searched { .. } => {}
+            Msg::Watched(event) => {
+                let user = event.user.clone().unwrap_or_default();
+                match event.kind() {
+                    UserEventKind::Deleted => {
+                        self.users.remove(&user.id);
+                    }
+                    _ => self.upsert(user),
+                }
+            }
+            Msg::WatchFailed { status, resync } => {
+                self.status = format!("watch interrupted: {}", status.message());
+                if resync {
+                    return self.refresh();
+                }
+            }
+            Msg::Saved(Ok(user)) => {
+                self.status = format!("saved user {}", user.id);
+                self.upsert(user);
+            }
+            Msg::Deleted(id, Ok(())) => {
+                self.status = format!("deleted user {}", id);
+                self.users.remove(&id);
+            }
+            Msg::Saved(Err(status)) | Msg::Deleted(_, Err(status)) => {
+                self.status = status.message().to_string();
+            }
+        }
+
+        self.select(self.selected);
+        Action::None
+    }
+
+    fn refresh(&self) -> Action {
+        if self.search.is_empty() {
+            Action::Reload
+        } else {
+            Action::Search(self.search.clone())
+        }
+    }
+
+    fn replace(&mut self, result: Result<Vec<User>, Status>) {
+        match result {
+            Ok(users) => {
+                self.users = users.into_iter().map(|u| (u.id, u)).collect();
+                self.status.clear();
+            }
+            Err(status) => self.status = status.message().to_string(),
+        }
+    }
+
+    // same case-sensitive prefix rule as ListUsersByNamePrefix
+    fn upsert(&mut self, user: User) {
+        if user.name.starts_with(&self.search) {
+            self.users.insert(user.id, user);
+        } else {
+            self.users.remove(&user.id);
+        }
+    }
+
+    fn select(&mut self, index: usize) {
+        self.selected = index.min(self.users.len().saturating_sub(1));
+    }
+
+    fn current(&self) -> Option<&User> {
+        self.users.values().nth(self.selected)
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [search, list, status] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let search_block = Block::bordered().title("Search by name prefix");
+        let search_block = if self.mode == Mode::Search {
+            search_block.yellow()
+        } else {
+            search_block
+        };
+        frame.render_widget(
+            Paragraph::new(self.search.as_str()).block(search_block),
+            search,
+        );
+
+        let rows = self
+            .users
+            .values()
+            .map(|u| Row::new([u.id.to_string(), u.name.clone(), u.surname.clone()]));
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(10),
+                Constraint::Percentage(45),
+                Constraint::Percentage(45),
+            ],
+        )
+        .header(Row::new(["ID", "Name", "Surname"]).bold())
+        .block(Block::bordered().title(format!("Users ({})", self.users.len())))
+        .row_highlight_style(Style::new().reversed());
+        let mut state = TableState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(table, list, &mut state);
+
+        let line = match &self.mode {
+            Mode::ConfirmDelete(id) => format!("delete user {}? y/n", id),
+            _ if !self.status.is_empty() => self.status.clone(),
+            _ => HELP.to_string(),
+        };
+        frame.render_widget(Line::from(line), status);
+
+        if let Mode::Edit(form) = &self.mode {
+            let area = centered(frame.area(), 50, 4);
+            let marker = |editing| if editing { "> " } else { "  " };
+            let text = vec![
+                Line::from(format!(
+                    "{}Name:    {}",
+                    marker(!form.editing_surname),
+                    form.name
+                )),
+                Line::from(format!(
+                    "{}Surname: {}",
+                    marker(form.editing_surname),
+                    form.surname
+                )),
+            ];
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                Paragraph::new(text).block(
+                    Block::bordered().title(format!("Edit user {} (tab, enter, esc)", form.id)),
+                ),
+                area,
+            );
+        }
+    }
+}
+
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let [area] = Layout::horizontal([Constraint::Length(width)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
+
+pub async fn run(config: &Config, args: &ConnectArgs) -> Result<(), Error> {
+    let client = user_client(config, args).await?;
+    let (tx, rx) = mpsc::channel(64);
+    perform(Action::Reload, &client, &tx);
+    tokio::spawn(watch(client.clone(), tx.clone()));
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, client, tx, rx).await;
+    ratatui::restore();
+
+    result
+}
+
+async fn event_loop(
+    terminal: &mut DefaultTerminal,
+    client: UserClient,
+    tx: mpsc::Sender<Msg>,
+    mut rx: mpsc::Receiver<Msg>,
+) -> Result<(), Error> {
+    let mut app = App::default();
+    let mut keys = EventStream::new();
+
+    while !app.quit {
+        terminal
+            .draw(|frame| app.draw(frame))
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        let action = tokio::select! {
+            event = keys.next() => match event {
+                Some(Ok(Event::Key(key))) if key.kind == KeyEventKind::Press => app.key(key),
+                Some(Ok(_)) => Action::None,
+                Some(Err(e)) => return Err(Error::Internal(Box::new(e))),
+                None => break,
+            },
+            Some(msg) = rx.recv() => app.apply(msg),
+        };
+        perform(action, &client, &tx);
+    }
+
+    Ok(())
+}
+
+// every call runs in the background and reports back through `tx`
+fn perform(action: Action, client: &UserClient, tx: &mpsc::Sender<Msg>) {
+    let mut client = client.clone();
+    let tx = tx.clone();
+
+    tokio::spawn(async move {
+        let msg = match action {
+            Action::None => return,
+            Action::Reload => Msg::Loaded(
+                client
+                    .get_users(GetUsersRequest::default())
+                    .await
+                    .map(|res| res.into_inner().users),
+            ),
+            Action::Search(prefix) => Msg::Searched {
+                result: client
+                    .list_users_by_name_prefix(ListUsersByNamePrefixRequest {
+                        prefix: prefix.clone(),
+                        limit: SEARCH_LIMIT,
+                    })
+                    .await
+                    .map(|res| res.into_inner().users),
+                prefix,
+            },
+            Action::Update(request) => Msg::Saved(
+                client
+                    .update_user(request)
+                    .await
+                    .map(|res| res.into_inner().user.unwrap_or_default()),
+            ),
+            Action::Delete(id) => Msg::Deleted(
+                id,
+                client
+                    .delete_user(DeleteUserRequest { id })
+                    .await
+                    .map(|_| ()),
+            ),
+        };
+        let _ = tx.send(msg).await;
+    });
+}
+
+// reconnects after failures, resuming after the last event it saw
+async fn watch(mut client: UserClient, tx: mpsc::Sender<Msg>) {
+    let mut after_sequence = 0;
+
+    loop {
+        let status = match client
+            .watch_users(WatchUsersRequest {
+                after_sequence,
+                resume_token: 0,
+                heartbeat_secs: 0,
+            })
+            .await
+        {
+            Ok(res) => {
+                let mut events = res.into_inner();
+                loop {
+                    match events.next().await {
+                        Some(Ok(event)) => {
+                            after_sequence = event.sequence;
+                            if tx.send(Msg::Watched(event)).await.is_err() {
+                                return;
+                            }
+                        }
+                        Some(Err(status)) => break status,
+                        None => break Status::unavailable("watch stream closed"),
+                    }
+                }
+            }
+            Err(status) => status,
+        };
+
+        // the resume point was evicted or the server restarted with a new feed
+        let resync = status.code() == Code::InvalidArgument;
+        if resync {
+            after_sequence = 0;
+        }
+        if tx.send(Msg::WatchFailed { status, resync }).await.is_err() {
+            return;
+        }
+        tokio::time::sleep(WATCH_RETRY).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: i32, name: &str) -> User {
+        User {
+            id,
+            name: name.to_string(),
+            surname: "Lee".to_string(),
+        }
+    }
+
+    fn press(app: &mut App, code: KeyCode) -> Action {
+        app.key(KeyEvent::from(code))
+    }
+
+    #[test]
+    fn test_search_as_you_type() {
+        let mut app = App::default();
+        app.apply(Msg::Loaded(Ok(vec![user(1, "Ann"), user(2, "Bob")])));
+
+        press(&mut app, KeyCode::Char('/'));
+        assert_eq!(
+            press(&mut app, KeyCode::Char('A')),
+            Action::Search("A".into())
+        );
+        assert_eq!(
+            press(&mut app, KeyCode::Char('n')),
+            Action::Search("An".into())
+        );
+
+        // a slower response for an older prefix must not win
+        app.apply(Msg::Searched {
+            prefix: "An".into(),
+            result: Ok(vec![user(1, "Ann")]),
+        });
+        app.apply(Msg::Searched {
+            prefix: "A".into(),
+            result: Ok(vec![user(1, "Ann"), user(3, "Al")]),
+        });
+        assert_eq!(app.users.keys().collect::<Vec<_>>(), [&1]);
+
+        assert_eq!(press(&mut app, KeyCode::Esc), Action::Reload);
+        assert!(app.search.is_empty());
+    }
+
+    #[test]
+    fn test_watch_events_respect_search() {
+        let mut app = App {
+            search: "An".into(),
+            ..Default::default()
+        };
+        app.apply(Msg::Searched {
+            prefix: "An".into(),
+            result: Ok(vec![user(1, "Ann")]),
+        });
+
+        let event = |kind: UserEventKind, user| UserEvent {
+            sequence: 1,
+            kind: kind as i32,
+            user: Some(user),
+            resume_token: 0,
+            heartbeat: false,
+        };
+        app.apply(Msg::Watched(event(UserEventKind::Created, user(2, "Bob"))));
+        app.apply(Msg::Watched(event(UserEventKind::Created, user(3, "Andy"))));
+        app.apply(Msg::Watched(event(UserEventKind::Updated, user(1, "Zed"))));
+        assert_eq!(app.users.keys().collect::<Vec<_>>(), [&3]);
+
+        app.apply(Msg::Watched(event(UserEventKind::Deleted, user(3, ""))));
+        assert!(app.users.is_empty());
+    }
+
+    #[test]
+    fn test_edit_and_delete() {
+        let mut app = App::default();
+        app.apply(Msg::Loaded(Ok(vec![user(1, "Ann"), user(2, "Bob")])));
+
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Char('e'));
+        press(&mut app, KeyCode::Tab);
+        press(&mut app, KeyCode::Backspace);
+        press(&mut app, KeyCode::Char('o'));
+        assert_eq!(
+            press(&mut app, KeyCode::Enter),
+            Action::Update(UpdateUserRequest {
+                id: 2,
+                name: Some("Bob".into()),
+                surname: Some("Leo".into()),
+            })
+        );
+
+        press(&mut app, KeyCode::Char('d'));
+        assert_eq!(press(&mut app, KeyCode::Char('n')), Action::None);
+        press(&mut app, KeyCode::Char('d'));
+        assert_eq!(press(&mut app, KeyCode::Char('y')), Action::Delete(2));
+    }
+}
@@ -0,0 +1,11 @@
+use sqlx::FromRow;
+
+#[derive(Clone, Default, Debug, PartialEq, Eq, FromRow)]
+pub struct Address {
+    pub id: i32,
+    pub user_id: i32,
+    pub street: String,
+    pub city: String,
+    pub postal_code: String,
+    pub country: String,
+}
@@ -0,0 +1,121 @@
+use std::time::{Duration, SystemTime};
+
+// an `api_key_limits` row; either limit left out does not apply
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ApiKeyLimits {
+    pub requests_per_minute: Option<u32>,
+    pub monthly_quota: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UsagePeriod {
+    Minute,
+    Month,
+}
+
+impl UsagePeriod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UsagePeriod::Minute => "minute",
+            UsagePeriod::Month => "month",
+        }
+    }
+}
+
+// the minute or calendar month (in UTC) a request is counted against
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UsageWindow {
+    pub period: UsagePeriod,
+    pub start: SystemTime,
+    // when the next window starts and the count begins again
+    pub reset: SystemTime,
+}
+
+impl UsageWindow {
+    pub fn containing(period: UsagePeriod, at: SystemTime) -> Self {
+        let secs = at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let (start, reset) = match period {
+            UsagePeriod::Minute => {
+                let start = secs - secs % 60;
+                (start, start + 60)
+            }
+            UsagePeriod::Month => {
+                let (year, month) = year_month((secs / 86_400) as i64);
+                let (next_year, next_month) = match month {
+                    12 => (year + 1, 1),
+                    _ => (year, month + 1),
+                };
+                (
+                    first_day(year, month) as u64 * 86_400,
+                    first_day(next_year, next_month) as u64 * 86_400,
+                )
+            }
+        };
+
+        Self {
+            period,
+            start: SystemTime::UNIX_EPOCH + Duration::from_secs(start),
+            reset: SystemTime::UNIX_EPOCH + Duration::from_secs(reset),
+        }
+    }
+}
+
+// the civil year and month of a day counted from 1970-01-01, after Howard
+// Hinnant's `civil_from_days`
+fn year_month(days: i64) -> (i64, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month)
+}
+
+// the day, counted from 1970-01-01, a month starts on
+fn first_day(year: i64, month: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_windows() {
+        // 2026-10-14T12:34:56Z
+        let now = at(1_791_981_296);
+
+        let minute = UsageWindow::containing(UsagePeriod::Minute, now);
+        assert_eq!(minute.start, at(1_791_981_240));
+        assert_eq!(minute.reset, at(1_791_981_300));
+
+        // 2026-10-01 to 2026-11-01
+        let month = UsageWindow::containing(UsagePeriod::Month, now);
+        assert_eq!(month.start, at(1_790_812_800));
+        assert_eq!(month.reset, at(1_793_491_200));
+
+        // 2024-12-31T23:59:59Z rolls over into 2025
+        let december = UsageWindow::containing(UsagePeriod::Month, at(1_735_689_599));
+        assert_eq!(december.start, at(1_733_011_200));
+        assert_eq!(december.reset, at(1_735_689_600));
+        // 2024-02 of a leap year
+        let february = UsageWindow::containing(UsagePeriod::Month, at(1_707_264_000));
+        assert_eq!(february.reset, at(1_709_251_200));
+    }
+}
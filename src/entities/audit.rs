@@ -0,0 +1,91 @@
+use std::time::SystemTime;
+
+use crate::auth::Principal;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditAction {
+    CreateUser,
+    UpdateUser,
+    DeleteUser,
+    EraseUser,
+    RestoreUser,
+    MergeUsers,
+    SuspendUser,
+    ActivateUser,
+    RecordConsent,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::CreateUser => "create_user",
+            AuditAction::UpdateUser => "update_user",
+            AuditAction::DeleteUser => "delete_user",
+            AuditAction::EraseUser => "erase_user",
+            AuditAction::RestoreUser => "restore_user",
+            AuditAction::MergeUsers => "merge_users",
+            AuditAction::SuspendUser => "suspend_user",
+            AuditAction::ActivateUser => "activate_user",
+            AuditAction::RecordConsent => "record_consent",
+        }
+    }
+}
+
+impl std::str::FromStr for AuditAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "create_user" => Ok(AuditAction::CreateUser),
+            "update_user" => Ok(AuditAction::UpdateUser),
+            "delete_user" => Ok(AuditAction::DeleteUser),
+            "erase_user" => Ok(AuditAction::EraseUser),
+            "restore_user" => Ok(AuditAction::RestoreUser),
+            "merge_users" => Ok(AuditAction::MergeUsers),
+            "suspend_user" => Ok(AuditAction::SuspendUser),
+            "activate_user" => Ok(AuditAction::ActivateUser),
+            "record_consent" => Ok(AuditAction::RecordConsent),
+            other => Err(format!("unknown audit action {:?}", other)),
+        }
+    }
+}
+
+// `actor_user_id` is who authenticated, `effective_user_id` who they acted as;
+// they differ only while an admin impersonates someone
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub action: AuditAction,
+    pub target_user_id: i32,
+    pub actor_user_id: Option<i32>,
+    pub effective_user_id: Option<i32>,
+}
+
+impl AuditEntry {
+    pub fn new(action: AuditAction, target_user_id: i32, caller: Option<&Principal>) -> Self {
+        Self {
+            action,
+            target_user_id,
+            actor_user_id: caller.map(Principal::real_user_id),
+            effective_user_id: caller.map(|p| p.user_id),
+        }
+    }
+}
+
+// an entry as stored, when reading the log back
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub id: i64,
+    pub entry: AuditEntry,
+    pub created_at: SystemTime,
+}
+
+// what ListAuditEntries narrows the log to, every field left None matches all
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AuditFilter {
+    pub actor_user_id: Option<i32>,
+    pub target_user_id: Option<i32>,
+    pub action: Option<AuditAction>,
+    // from `start` included to `end` excluded
+    pub start: Option<SystemTime>,
+    pub end: Option<SystemTime>,
+}
@@ -0,0 +1,94 @@
+use std::time::SystemTime;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackupState {
+    Running,
+    Completed,
+    Failed,
+}
+
+impl BackupState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackupState::Running => "running",
+            BackupState::Completed => "completed",
+            BackupState::Failed => "failed",
+        }
+    }
+}
+
+impl std::str::FromStr for BackupState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "running" => Ok(BackupState::Running),
+            "completed" => Ok(BackupState::Completed),
+            "failed" => Ok(BackupState::Failed),
+            other => Err(format!("unknown backup state {:?}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestoreState {
+    Restoring,
+    Restored,
+    // nothing was changed, the restore is one transaction
+    Failed,
+}
+
+impl RestoreState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RestoreState::Restoring => "restoring",
+            RestoreState::Restored => "restored",
+            RestoreState::Failed => "failed",
+        }
+    }
+}
+
+impl std::str::FromStr for RestoreState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "restoring" => Ok(RestoreState::Restoring),
+            "restored" => Ok(RestoreState::Restored),
+            "failed" => Ok(RestoreState::Failed),
+            other => Err(format!("unknown restore state {:?}", other)),
+        }
+    }
+}
+
+// a `backups` row
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Backup {
+    pub id: i64,
+    // the prefix of its objects in the backup storage
+    pub object: String,
+    pub state: BackupState,
+    pub row_count: u64,
+    pub byte_count: u64,
+    pub error: Option<String>,
+    pub created_at: SystemTime,
+    pub completed_at: Option<SystemTime>,
+    // the last restore from it
+    pub restore: Option<Restore>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Restore {
+    pub state: RestoreState,
+    pub row_count: u64,
+    pub error: Option<String>,
+    pub started_at: SystemTime,
+    pub finished_at: Option<SystemTime>,
+}
+
+// a row of a backed up table, as `row_to_json` writes it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotRow {
+    pub table: String,
+    pub row: String,
+}
@@ -0,0 +1,75 @@
+use std::time::SystemTime;
+
+// what a user agreed to and through which channel, e.g. `terms` version
+// `2026-01` accepted on the `signup_form`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NewConsent {
+    pub consent_type: String,
+    pub version: String,
+    pub source: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Consent {
+    pub id: i64,
+    pub user_id: i32,
+    pub consent: NewConsent,
+    pub granted_at: SystemTime,
+}
+
+// a consent CreateUser refuses to go without, any version unless one is given
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequiredConsent {
+    pub consent_type: String,
+    pub version: Option<String>,
+}
+
+impl RequiredConsent {
+    // `REQUIRED_CONSENTS`: comma separated `type` or `type:version`
+    pub fn parse(spec: &str) -> Result<Vec<Self>, String> {
+        spec.split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(|c| {
+                let (consent_type, version) = match c.split_once(':') {
+                    Some((t, v)) => (t.trim(), Some(v.trim())),
+                    None => (c, None),
+                };
+                if consent_type.is_empty() || version.is_some_and(str::is_empty) {
+                    return Err(format!("{:?} is not `type` or `type:version`", c));
+                }
+                Ok(Self {
+                    consent_type: consent_type.to_string(),
+                    version: version.map(str::to_string),
+                })
+            })
+            .collect()
+    }
+
+    pub fn is_met_by(&self, consent: &NewConsent) -> bool {
+        consent.consent_type == self.consent_type
+            && self.version.as_ref().is_none_or(|v| *v == consent.version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let required = RequiredConsent::parse("terms:2026-01, privacy").unwrap();
+
+        let terms = |version: &str| NewConsent {
+            consent_type: "terms".to_string(),
+            version: version.to_string(),
+            source: "signup_form".to_string(),
+        };
+        assert!(required[0].is_met_by(&terms("2026-01")));
+        assert!(!required[0].is_met_by(&terms("2025-06")));
+        assert!(!required[1].is_met_by(&terms("2026-01")));
+        assert_eq!(required[1].version, None);
+        assert!(RequiredConsent::parse("").unwrap().is_empty());
+        assert!(RequiredConsent::parse("terms:").is_err());
+    }
+}
@@ -1 +1,10 @@
+pub mod addresses;
+pub mod api_keys;
+pub mod audit;
+pub mod backups;
+pub mod consents;
+pub mod pii;
+pub mod relationships;
+pub mod revisions;
+pub mod stats;
 pub mod users;
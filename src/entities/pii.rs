@@ -0,0 +1,58 @@
+use std::time::SystemTime;
+
+// a `pii_data_keys` row; a key added by a rotation is not active until every
+// replica has loaded it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WrappedDataKey {
+    pub id: i32,
+    pub wrapped_key: Vec<u8>,
+    pub active: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RotationState {
+    // the new data key decrypts but does not encrypt yet
+    Pending,
+    // the new data key encrypts, the users are being re-encrypted with it
+    Rewriting,
+    Done,
+}
+
+impl RotationState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RotationState::Pending => "pending",
+            RotationState::Rewriting => "rewriting",
+            RotationState::Done => "done",
+        }
+    }
+}
+
+impl std::str::FromStr for RotationState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(RotationState::Pending),
+            "rewriting" => Ok(RotationState::Rewriting),
+            "done" => Ok(RotationState::Done),
+            other => Err(format!("unknown key rotation state {:?}", other)),
+        }
+    }
+}
+
+// a `pii_key_rotations` row
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyRotation {
+    pub id: i32,
+    pub data_key_id: i32,
+    pub state: RotationState,
+    pub batch_size: u32,
+    pub max_users_per_second: Option<u32>,
+    // the last user re-encrypted, where the rotation resumes
+    pub after_user_id: i32,
+    pub values_rewritten: u64,
+    pub started_at: SystemTime,
+    pub updated_at: SystemTime,
+    pub finished_at: Option<SystemTime>,
+}
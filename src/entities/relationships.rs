@@ -0,0 +1,48 @@
+use std::str::FromStr;
+
+use crate::{Error, entities::users::User};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RelationshipKind {
+    Friend,
+    Follows,
+    Blocks,
+}
+
+impl RelationshipKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RelationshipKind::Friend => "friend",
+            RelationshipKind::Follows => "follows",
+            RelationshipKind::Blocks => "blocks",
+        }
+    }
+}
+
+impl FromStr for RelationshipKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "friend" => Ok(RelationshipKind::Friend),
+            "follows" => Ok(RelationshipKind::Follows),
+            "blocks" => Ok(RelationshipKind::Blocks),
+            _ => Err(Error::Internal(
+                format!("unknown relationship kind {:?}", s).into(),
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relationship {
+    pub user_id: i32,
+    pub related_user_id: i32,
+    pub kind: RelationshipKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RelatedUser {
+    pub user: User,
+    pub depth: i32,
+}
@@ -0,0 +1,22 @@
+use std::time::SystemTime;
+
+use crate::entities::{audit::AuditAction, users::User};
+
+// one stored version of a user, `revision` counting up from 1 for the first
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserRevision {
+    pub revision: i64,
+    pub user: User,
+    // hard or soft deleted as of this version
+    pub deleted: bool,
+    pub changed_at: SystemTime,
+    pub changed_by: Option<RevisionAuthor>,
+}
+
+// who made a change, from the audit entry written right after it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RevisionAuthor {
+    pub action: AuditAction,
+    pub actor_user_id: Option<i32>,
+    pub effective_user_id: Option<i32>,
+}
@@ -0,0 +1,39 @@
+use std::time::{Duration, SystemTime};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsPeriod {
+    Day,
+    Week,
+    Month,
+}
+
+impl StatsPeriod {
+    // the date_trunc field, weeks start on Monday
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatsPeriod::Day => "day",
+            StatsPeriod::Week => "week",
+            StatsPeriod::Month => "month",
+        }
+    }
+
+    // the longest a period can be, for sizing ranges
+    pub fn max_len(&self) -> Duration {
+        let day = Duration::from_secs(24 * 60 * 60);
+        match self {
+            StatsPeriod::Day => day,
+            StatsPeriod::Week => day * 7,
+            StatsPeriod::Month => day * 31,
+        }
+    }
+}
+
+// `created` and `deleted` happened within the period, `active` is the number
+// of users that existed at its end
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserStats {
+    pub period_start: SystemTime,
+    pub created: i64,
+    pub deleted: i64,
+    pub active: i64,
+}
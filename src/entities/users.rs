@@ -1,8 +1,118 @@
-use sqlx::{Decode, Encode};
+use std::time::SystemTime;
 
-#[derive(Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Encode, Decode)]
+use sqlx::{Decode, Encode, FromRow};
+
+#[derive(Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, FromRow)]
 pub struct User {
     pub id: i32,
     pub name: String,
     pub surname: String,
 }
+
+impl User {
+    // the names are moved, not cloned
+    pub fn into_grpc(self) -> crate::grpc::User {
+        crate::grpc::User {
+            id: self.id,
+            name: self.name,
+            surname: self.surname,
+        }
+    }
+}
+
+// a soft deleted user, as it was when deleted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeletedUser {
+    pub user: User,
+    pub deleted_at: SystemTime,
+}
+
+// suspended users stay readable by id but drop out of listings and cannot
+// change their own record
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UserStatus {
+    #[default]
+    Active,
+    Suspended,
+}
+
+impl UserStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserStatus::Active => "active",
+            UserStatus::Suspended => "suspended",
+        }
+    }
+}
+
+impl std::str::FromStr for UserStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "active" => Ok(UserStatus::Active),
+            "suspended" => Ok(UserStatus::Suspended),
+            other => Err(format!("unknown user status {:?}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UserEventKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl UserEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserEventKind::Created => "created",
+            UserEventKind::Updated => "updated",
+            UserEventKind::Deleted => "deleted",
+        }
+    }
+}
+
+impl std::str::FromStr for UserEventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created" => Ok(UserEventKind::Created),
+            "updated" => Ok(UserEventKind::Updated),
+            "deleted" => Ok(UserEventKind::Deleted),
+            other => Err(format!("unknown user event kind {:?}", other)),
+        }
+    }
+}
+
+// one committed mutation; deletions only carry the user id
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserEvent {
+    pub sequence: u64,
+    // the event store's, 0 without one
+    pub token: u64,
+    pub kind: UserEventKind,
+    pub user: User,
+}
+
+// an event as the event store keeps it, without the names
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StoredUserEvent {
+    pub token: u64,
+    pub kind: UserEventKind,
+    pub user_id: i32,
+}
+
+// a stored event `destination` failed to take on every attempt, set aside so
+// the relay could go on; `reason` is the last failure
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeadLetter {
+    pub id: i64,
+    pub destination: String,
+    pub event: StoredUserEvent,
+    pub attempts: i32,
+    pub reason: String,
+    pub failed_at: SystemTime,
+}
@@ -0,0 +1,98 @@
+use std::{
+    borrow::Cow,
+    sync::{Arc, OnceLock},
+};
+
+use sentry::{ClientInitGuard, ClientOptions, protocol::Event};
+
+use crate::{Error, config::SentrySettings, telemetry};
+
+static SAMPLE_RATE: OnceLock<f32> = OnceLock::new();
+
+// installs the panic hook too; the guard flushes queued events when dropped,
+// so main keeps it until the process exits
+pub fn init(settings: &SentrySettings) -> Result<ClientInitGuard, Error> {
+    let dsn = settings
+        .dsn
+        .parse()
+        .map_err(|e| Error::Internal(format!("invalid SENTRY_DSN: {}", e).into()))?;
+    let _ = SAMPLE_RATE.set(settings.sample_rate);
+
+    Ok(sentry::init(ClientOptions {
+        dsn: Some(dsn),
+        environment: settings.environment.clone().map(Cow::Owned),
+        release: sentry::release_name!(),
+        send_default_pii: false,
+        before_send: Some(Arc::new(scrub)),
+        ..Default::default()
+    }))
+}
+
+// only Error::Internal is reported, the other variants are the caller's
+pub fn report(e: &Error) {
+    if !matches!(e, Error::Internal(_)) {
+        return;
+    }
+    if rand::random::<f32>() >= SAMPLE_RATE.get().copied().unwrap_or(1.0) {
+        return;
+    }
+    sentry::capture_error(e);
+}
+
+// runs on the capturing thread, so the request's task-locals are still in
+// scope for reported errors and panics alike
+fn scrub(mut event: Event<'static>) -> Option<Event<'static>> {
+    // nothing that could identify a caller or the host
+    event.user = None;
+    event.request = None;
+    event.server_name = None;
+
+    if let Some(context) = telemetry::current() {
+        event.tags.insert("request_id".into(), context.to_string());
+    }
+    if let Some(path) = telemetry::current_path() {
+        event.tags.insert("method".into(), path);
+    }
+
+    Some(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Request, Response};
+    use sentry::protocol::User;
+    use tower::{Layer, ServiceExt};
+
+    use super::*;
+    use crate::telemetry::{TRACEPARENT_HEADER, TraceContextLayer};
+
+    #[tokio::test]
+    async fn test_scrub_tags_the_request() {
+        let service = TraceContextLayer.layer(tower::service_fn(|_: Request<()>| async {
+            let event = Event {
+                user: Some(User {
+                    email: Some("ann@example.com".into()),
+                    ..Default::default()
+                }),
+                server_name: Some("users-1".into()),
+                ..Default::default()
+            };
+            Ok::<_, std::convert::Infallible>(Response::new(scrub(event)))
+        }));
+
+        let req = Request::builder()
+            .uri("/user.v1.UserService/GetUserById")
+            .header(
+                TRACEPARENT_HEADER,
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            )
+            .body(())
+            .unwrap();
+        let event = service.oneshot(req).await.unwrap().into_body().unwrap();
+
+        assert_eq!(event.user, None);
+        assert_eq!(event.server_name, None);
+        assert_eq!(event.tags["request_id"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(event.tags["method"], "/user.v1.UserService/GetUserById");
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use arrow_array::{ArrayRef, Int32Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+
+use crate::{Error, entities::users::User, export::Encoder};
+
+// what the writer has produced since the last take
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Buffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// one row group per page; the footer only goes out with `finish`, so the
+// pieces are a valid file once concatenated
+pub struct ParquetEncoder {
+    schema: SchemaRef,
+    writer: ArrowWriter<Buffer>,
+    buffer: Buffer,
+}
+
+impl ParquetEncoder {
+    pub fn new() -> Result<Self, Error> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, false),
+            Field::new("surname", DataType::Utf8, false),
+        ]));
+        let props = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let buffer = Buffer::default();
+        let writer = ArrowWriter::try_new(buffer.clone(), schema.clone(), Some(props))
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(Self {
+            schema,
+            writer,
+            buffer,
+        })
+    }
+}
+
+impl Encoder for ParquetEncoder {
+    fn page(&mut self, users: &[User]) -> Result<Vec<u8>, Error> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(Int32Array::from_iter_values(users.iter().map(|u| u.id))),
+            Arc::new(StringArray::from_iter_values(users.iter().map(|u| &u.name))),
+            Arc::new(StringArray::from_iter_values(
+                users.iter().map(|u| &u.surname),
+            )),
+        ];
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        self.writer
+            .write(&batch)
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        self.writer
+            .flush()
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(self.buffer.take())
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>, Error> {
+        self.writer
+            .finish()
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(self.buffer.take())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use super::*;
+
+    #[test]
+    fn test_pieces_concatenate_to_one_file() {
+        let mut encoder = ParquetEncoder::new().unwrap();
+        let users: Vec<_> = (1..=5)
+            .map(|id| User {
+                id,
+                name: format!("User{}", id),
+                surname: "Parquet".to_string(),
+            })
+            .collect();
+
+        let mut file = encoder.page(&users[..3]).unwrap();
+        file.extend(encoder.page(&users[3..]).unwrap());
+        file.extend(encoder.finish().unwrap());
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(file)).unwrap();
+        assert_eq!(reader.metadata().num_row_groups(), 2);
+        let batches: Vec<_> = reader.build().unwrap().map(Result::unwrap).collect();
+        let ids: Vec<i32> = batches
+            .iter()
+            .flat_map(|b| {
+                b.column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap()
+                    .values()
+                    .to_vec()
+            })
+            .collect();
+        assert_eq!(ids, [1, 2, 3, 4, 5]);
+    }
+}
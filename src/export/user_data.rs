@@ -0,0 +1,148 @@
+use std::time::SystemTime;
+
+use serde_json::{Value, json};
+
+use crate::{
+    Error,
+    entities::{
+        addresses::Address,
+        audit::AuditRecord,
+        consents::Consent,
+        users::{User, UserEvent},
+    },
+};
+
+// everything stored about one user, gathered for a data portability request
+pub struct UserData {
+    pub user: User,
+    pub addresses: Vec<Address>,
+    pub consents: Vec<Consent>,
+    pub audit_entries: Vec<AuditRecord>,
+    pub events: Vec<UserEvent>,
+}
+
+impl UserData {
+    // one JSON document; timestamps are RFC 3339 in UTC
+    pub fn to_json(&self, exported_at: SystemTime) -> Result<Vec<u8>, Error> {
+        let document = json!({
+            "exported_at": timestamp(exported_at),
+            "user": user(&self.user),
+            "addresses": self.addresses.iter().map(address).collect::<Vec<_>>(),
+            "consents": self.consents.iter().map(consent).collect::<Vec<_>>(),
+            "audit_entries": self.audit_entries.iter().map(audit_entry).collect::<Vec<_>>(),
+            "events": self.events.iter().map(event).collect::<Vec<_>>(),
+        });
+
+        serde_json::to_vec_pretty(&document).map_err(|e| Error::Internal(Box::new(e)))
+    }
+}
+
+fn timestamp(at: SystemTime) -> String {
+    prost_types::Timestamp::from(at).to_string()
+}
+
+fn user(user: &User) -> Value {
+    json!({
+        "id": user.id,
+        "name": user.name,
+        "surname": user.surname,
+    })
+}
+
+fn address(address: &Address) -> Value {
+    json!({
+        "id": address.id,
+        "street": address.street,
+        "city": address.city,
+        "postal_code": address.postal_code,
+        "country": address.country,
+    })
+}
+
+fn consent(consent: &Consent) -> Value {
+    json!({
+        "id": consent.id,
+        "consent_type": consent.consent.consent_type,
+        "version": consent.consent.version,
+        "source": consent.consent.source,
+        "granted_at": timestamp(consent.granted_at),
+    })
+}
+
+fn audit_entry(record: &AuditRecord) -> Value {
+    json!({
+        "id": record.id,
+        "action": record.entry.action.as_str(),
+        "target_user_id": record.entry.target_user_id,
+        "actor_user_id": record.entry.actor_user_id,
+        "effective_user_id": record.entry.effective_user_id,
+        "created_at": timestamp(record.created_at),
+    })
+}
+
+fn event(event: &UserEvent) -> Value {
+    json!({
+        "sequence": event.sequence,
+        "kind": event.kind.as_str(),
+        "user": user(&event.user),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::entities::{
+        audit::{AuditAction, AuditEntry},
+        users::UserEventKind,
+    };
+
+    #[test]
+    fn test_to_json() {
+        let ann = User {
+            id: 7,
+            name: "Ann".to_string(),
+            surname: "Lee".to_string(),
+        };
+        let data = UserData {
+            user: ann.clone(),
+            addresses: vec![Address {
+                id: 1,
+                user_id: 7,
+                city: "Oslo".to_string(),
+                ..Address::default()
+            }],
+            consents: Vec::new(),
+            audit_entries: vec![AuditRecord {
+                id: 3,
+                entry: AuditEntry {
+                    action: AuditAction::CreateUser,
+                    target_user_id: 7,
+                    actor_user_id: None,
+                    effective_user_id: None,
+                },
+                created_at: SystemTime::UNIX_EPOCH + Duration::from_secs(60),
+            }],
+            events: vec![UserEvent {
+                sequence: 12,
+                token: 0,
+                kind: UserEventKind::Created,
+                user: ann,
+            }],
+        };
+
+        let document: Value =
+            serde_json::from_slice(&data.to_json(SystemTime::UNIX_EPOCH).unwrap()).unwrap();
+
+        assert_eq!(document["exported_at"], "1970-01-01T00:00:00Z");
+        assert_eq!(document["user"]["name"], "Ann");
+        assert_eq!(document["addresses"][0]["city"], "Oslo");
+        assert_eq!(document["audit_entries"][0]["action"], "create_user");
+        assert_eq!(
+            document["audit_entries"][0]["created_at"],
+            "1970-01-01T00:01:00Z"
+        );
+        assert_eq!(document["events"][0]["kind"], "created");
+    }
+}
@@ -0,0 +1,421 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    http::{Request, Response},
+};
+use bytes::BytesMut;
+use http_body::Frame;
+use tonic::{Code, Status};
+use tower::{Layer, Service};
+
+use crate::metrics::registry;
+
+pub const FAULTS_INJECTED_TOTAL: &str = "faults_injected_total";
+
+// gRPC message prefix: compressed flag and big-endian length
+const MESSAGE_HEADER_LEN: usize = 5;
+
+// one `FAULT_INJECTION` rule, e.g. `GetUserById:error=0.2,code=unavailable`
+// or `*:latency_ms=100,jitter_ms=50`
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaultRule {
+    // `Method`, `Service/Method` (with or without the package) or `*`
+    pub method: String,
+    pub error_rate: f64,
+    pub code: Code,
+    pub latency: Duration,
+    // up to this much extra latency, uniformly
+    pub jitter: Duration,
+    // share of response messages left out of the body
+    pub drop_rate: f64,
+}
+
+impl FaultRule {
+    fn matches(&self, path: &str) -> bool {
+        matches_method(&self.method, path)
+    }
+}
+
+// whether `pattern`, a `Method`, `Service/Method` (with or without the package)
+// or `*`, names the gRPC method at `path`
+pub(crate) fn matches_method(pattern: &str, path: &str) -> bool {
+    match path.trim_start_matches('/').split_once('/') {
+        Some((service, method)) => matches_service_method(pattern, service, method),
+        None => false,
+    }
+}
+
+// the same for `service` (`user.v1.UserService`) and `method` given apart
+pub(crate) fn matches_service_method(pattern: &str, service: &str, method: &str) -> bool {
+    let short = service.rsplit('.').next().unwrap_or(service);
+
+    match pattern.split_once('/') {
+        None => pattern == "*" || pattern == method,
+        Some((s, m)) => m == method && (s == service || s == short),
+    }
+}
+
+// rules separated by `;`, the first one matching a method applies
+pub fn parse(spec: &str) -> Result<Vec<FaultRule>, String> {
+    spec.split(';')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(parse_rule)
+        .collect()
+}
+
+fn parse_rule(rule: &str) -> Result<FaultRule, String> {
+    let (method, settings) = rule
+        .split_once(':')
+        .ok_or_else(|| format!("{:?} has no `method:` prefix", rule))?;
+    let mut parsed = FaultRule {
+        method: method.trim().to_string(),
+        error_rate: 0.0,
+        code: Code::Unavailable,
+        latency: Duration::ZERO,
+        jitter: Duration::ZERO,
+        drop_rate: 0.0,
+    };
+
+    for setting in settings.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (key, value) = setting
+            .split_once('=')
+            .ok_or_else(|| format!("{:?} is not `key=value`", setting))?;
+        let (key, value) = (key.trim(), value.trim());
+        let bad = || format!("bad {} {:?}", key, value);
+        let rate = || {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|r| (0.0..=1.0).contains(r))
+                .ok_or_else(bad)
+        };
+        let ms = || value.parse().map(Duration::from_millis).map_err(|_| bad());
+
+        match key {
+            "error" => parsed.error_rate = rate()?,
+            "code" => parsed.code = code(value).ok_or_else(bad)?,
+            "latency_ms" => parsed.latency = ms()?,
+            "jitter_ms" => parsed.jitter = ms()?,
+            "drop" => parsed.drop_rate = rate()?,
+            _ => return Err(format!("unknown setting {:?}", key)),
+        }
+    }
+
+    Ok(parsed)
+}
+
+// a gRPC code by number or by its name, `unavailable` or `UNAVAILABLE`
+fn code(value: &str) -> Option<Code> {
+    if let Ok(n) = value.parse() {
+        return (0..=16).contains(&n).then(|| Code::from_i32(n));
+    }
+    let code = match value.to_ascii_lowercase().as_str() {
+        "cancelled" => Code::Cancelled,
+        "unknown" => Code::Unknown,
+        "invalid_argument" => Code::InvalidArgument,
+        "deadline_exceeded" => Code::DeadlineExceeded,
+        "not_found" => Code::NotFound,
+        "already_exists" => Code::AlreadyExists,
+        "permission_denied" => Code::PermissionDenied,
+        "resource_exhausted" => Code::ResourceExhausted,
+        "failed_precondition" => Code::FailedPrecondition,
+        "aborted" => Code::Aborted,
+        "out_of_range" => Code::OutOfRange,
+        "unimplemented" => Code::Unimplemented,
+        "internal" => Code::Internal,
+        "unavailable" => Code::Unavailable,
+        "data_loss" => Code::DataLoss,
+        "unauthenticated" => Code::Unauthenticated,
+        _ => return None,
+    };
+    Some(code)
+}
+
+fn record(kind: &'static str) {
+    registry().increment_counter(FAULTS_INJECTED_TOTAL, &[("kind", kind)], 1);
+}
+
+// injects the errors, latency and dropped messages of `FAULT_INJECTION` into
+// the business routes, for client teams to exercise their retries against
+#[derive(Clone)]
+pub struct FaultInjectionLayer {
+    rules: Arc<[FaultRule]>,
+}
+
+impl FaultInjectionLayer {
+    pub fn new(rules: Vec<FaultRule>) -> Self {
+        Self {
+            rules: rules.into(),
+        }
+    }
+}
+
+impl<S> Layer<S> for FaultInjectionLayer {
+    type Service = FaultInjectionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        FaultInjectionService {
+            inner,
+            rules: self.rules.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct FaultInjectionService<S> {
+    inner: S,
+    rules: Arc<[FaultRule]>,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for FaultInjectionService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let Some(rule) = self
+            .rules
+            .iter()
+            .find(|rule| rule.matches(req.uri().path()))
+            .cloned()
+        else {
+            return Box::pin(self.inner.call(req));
+        };
+        // the ready service goes into the future, a clone stays for the next call
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            let jitter = rule.jitter.mul_f64(rand::random::<f64>());
+            let delay = rule.latency + jitter;
+            if !delay.is_zero() {
+                record("latency");
+                tokio::time::sleep(delay).await;
+            }
+            if rand::random::<f64>() < rule.error_rate {
+                record("error");
+                return Ok(Status::new(rule.code, "injected fault").into_http());
+            }
+
+            let res = inner.call(req).await?;
+            if rule.drop_rate > 0.0 {
+                return Ok(res.map(|body| Body::new(DroppingBody::new(body, rule.drop_rate))));
+            }
+            Ok(res)
+        })
+    }
+}
+
+// re-frames the body into whole gRPC messages and leaves some out; on a unary
+// call the client sees the reply go missing
+struct DroppingBody {
+    inner: Body,
+    buf: BytesMut,
+    // trailers held back until the buffered bytes before them are sent
+    trailers: Option<Frame<Bytes>>,
+    rate: f64,
+}
+
+impl DroppingBody {
+    fn new(inner: Body, rate: f64) -> Self {
+        Self {
+            inner,
+            buf: BytesMut::new(),
+            trailers: None,
+            rate,
+        }
+    }
+
+    fn next_message(&mut self) -> Option<Bytes> {
+        let header = self.buf.get(..MESSAGE_HEADER_LEN)?;
+        let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        (self.buf.len() >= MESSAGE_HEADER_LEN + len)
+            .then(|| self.buf.split_to(MESSAGE_HEADER_LEN + len).freeze())
+    }
+}
+
+impl HttpBody for DroppingBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(message) = this.next_message() {
+                if rand::random::<f64>() < this.rate {
+                    record("drop");
+                    continue;
+                }
+                return Poll::Ready(Some(Ok(Frame::data(message))));
+            }
+            if this.trailers.is_some() || this.inner.is_end_stream() {
+                // a partial message is passed on as it is, it cannot be dropped whole
+                if !this.buf.is_empty() {
+                    return Poll::Ready(Some(Ok(Frame::data(this.buf.split().freeze()))));
+                }
+                return Poll::Ready(this.trailers.take().map(Ok));
+            }
+
+            match ready!(Pin::new(&mut this.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => this.buf.extend_from_slice(&data),
+                    Err(frame) => this.trailers = Some(frame),
+                },
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None if this.buf.is_empty() => return Poll::Ready(None),
+                None => return Poll::Ready(Some(Ok(Frame::data(this.buf.split().freeze())))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Instant};
+
+    use http_body_util::{BodyExt, StreamBody};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn message(payload: &[u8]) -> Vec<u8> {
+        let mut framed = vec![0];
+        framed.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        framed.extend_from_slice(payload);
+        framed
+    }
+
+    // two messages split across chunks, then trailers
+    fn streamed() -> Body {
+        let bytes = [message(b"first"), message(b"second")].concat();
+        let (a, b) = bytes.split_at(7);
+        let mut trailers = axum::http::HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+        let frames = vec![
+            Ok::<_, Infallible>(Frame::data(Bytes::copy_from_slice(a))),
+            Ok(Frame::data(Bytes::copy_from_slice(b))),
+            Ok(Frame::trailers(trailers)),
+        ];
+        Body::new(StreamBody::new(tokio_stream::iter(frames)))
+    }
+
+    fn service(
+        rules: &str,
+    ) -> impl Service<Request<Body>, Response = Response<Body>, Error = Infallible> + Clone {
+        FaultInjectionLayer::new(parse(rules).unwrap()).layer(tower::service_fn(
+            |_: Request<Body>| async { Ok::<_, Infallible>(Response::new(streamed())) },
+        ))
+    }
+
+    fn call(path: &str) -> Request<Body> {
+        Request::builder().uri(path).body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let rules = parse(
+            "GetUserById: error=0.25, code=deadline_exceeded; UserService/StreamUsers:drop=1; *:latency_ms=5,jitter_ms=10",
+        )
+        .unwrap();
+
+        assert_eq!(rules.len(), 3);
+        assert_eq!(rules[0].error_rate, 0.25);
+        assert_eq!(rules[0].code, Code::DeadlineExceeded);
+        assert_eq!(rules[1].drop_rate, 1.0);
+        assert_eq!(rules[2].latency, Duration::from_millis(5));
+        assert_eq!(rules[2].jitter, Duration::from_millis(10));
+        assert_eq!(parse("*:code=14").unwrap()[0].code, Code::Unavailable);
+        assert!(parse("").unwrap().is_empty());
+
+        assert!(parse("error=0.5").is_err());
+        assert!(parse("*:error=2").is_err());
+        assert!(parse("*:code=teapot").is_err());
+        assert!(parse("*:explode=1").is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        let rule = |method: &str| FaultRule {
+            method: method.to_string(),
+            ..parse("*:").unwrap().remove(0)
+        };
+        let path = "/user.v1.UserService/GetUserById";
+
+        assert!(rule("*").matches(path));
+        assert!(rule("GetUserById").matches(path));
+        assert!(rule("UserService/GetUserById").matches(path));
+        assert!(rule("user.v1.UserService/GetUserById").matches(path));
+        assert!(!rule("GetUsers").matches(path));
+        assert!(!rule("AddressService/GetUserById").matches(path));
+    }
+
+    #[tokio::test]
+    async fn test_injects_errors_into_matching_methods() {
+        let service = service("GetUserById:error=1,code=resource_exhausted");
+
+        let failed = service
+            .clone()
+            .oneshot(call("/user.v1.UserService/GetUserById"))
+            .await
+            .unwrap();
+        assert_eq!(failed.headers()["grpc-status"], "8");
+
+        let passed = service
+            .oneshot(call("/user.v1.UserService/GetUsers"))
+            .await
+            .unwrap();
+        assert!(passed.headers().get("grpc-status").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_adds_latency() {
+        let started = Instant::now();
+        service("*:latency_ms=30")
+            .oneshot(call("/user.v1.UserService/GetUsers"))
+            .await
+            .unwrap();
+
+        assert!(started.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[tokio::test]
+    async fn test_drops_whole_messages_and_keeps_trailers() {
+        let kept = DroppingBody::new(streamed(), 0.0).collect().await.unwrap();
+        assert!(kept.trailers().is_some());
+        assert_eq!(
+            kept.to_bytes(),
+            [message(b"first"), message(b"second")].concat()
+        );
+
+        let dropped = service("*:drop=1")
+            .oneshot(call("/user.v1.UserService/StreamUsers"))
+            .await
+            .unwrap()
+            .into_body()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(dropped.trailers().unwrap()["grpc-status"], "0");
+        assert!(dropped.to_bytes().is_empty());
+    }
+}
@@ -0,0 +1,286 @@
+mod parser;
+
+use crate::{Error, entities::users::User};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldKind {
+    Integer,
+    Text,
+    Timestamp,
+}
+
+impl FieldKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            FieldKind::Integer => "integer",
+            FieldKind::Text => "string",
+            FieldKind::Timestamp => "timestamp",
+        }
+    }
+}
+
+// a filterable field and the column it maps to; only whitelisted columns ever reach SQL
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    pub column: &'static str,
+    pub kind: FieldKind,
+}
+
+pub const USER_FIELDS: &[Field] = &[
+    Field {
+        name: "id",
+        column: "id",
+        kind: FieldKind::Integer,
+    },
+    Field {
+        name: "name",
+        column: "name",
+        kind: FieldKind::Text,
+    },
+    Field {
+        name: "surname",
+        column: "surname",
+        kind: FieldKind::Text,
+    },
+    Field {
+        name: "created_at",
+        column: "created_at",
+        kind: FieldKind::Timestamp,
+    },
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparator {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            Comparator::Eq => "=",
+            Comparator::Ne => "<>",
+            Comparator::Lt => "<",
+            Comparator::Le => "<=",
+            Comparator::Gt => ">",
+            Comparator::Ge => ">=",
+        }
+    }
+
+    fn holds<T: Ord>(&self, left: T, right: T) -> bool {
+        match self {
+            Comparator::Eq => left == right,
+            Comparator::Ne => left != right,
+            Comparator::Lt => left < right,
+            Comparator::Le => left <= right,
+            Comparator::Gt => left > right,
+            Comparator::Ge => left >= right,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Integer(i64),
+    Text(String),
+    // validated by the database, which parses it with a cast
+    Timestamp(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Compare {
+        field: Field,
+        op: Comparator,
+        value: Value,
+    },
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dialect {
+    Postgres,
+    Sqlite,
+}
+
+// a parsed AIP-160 filter, e.g. `name = "Ann" AND created_at > "2024-01-01"`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    expr: Expr,
+}
+
+impl Filter {
+    // an empty filter matches everything and parses to None
+    pub fn parse(input: &str, fields: &[Field]) -> Result<Option<Self>, Error> {
+        Ok(parser::parse(input, fields)?.map(|expr| Self { expr }))
+    }
+
+    // returns the WHERE clause and the values to bind, in placeholder order;
+    // postgres placeholders are numbered from `first_param`
+    pub fn to_sql(&self, dialect: Dialect, first_param: usize) -> (String, Vec<Value>) {
+        let mut sql = String::new();
+        let mut values = Vec::new();
+        write_sql(&self.expr, dialect, first_param, &mut sql, &mut values);
+        (sql, values)
+    }
+
+    // evaluates the filter in memory; None when it references a field the user lacks
+    pub fn matches(&self, user: &User) -> Option<bool> {
+        evaluate(&self.expr, user)
+    }
+
+    pub fn references(&self, field: &str) -> bool {
+        references(&self.expr, field)
+    }
+}
+
+fn references(expr: &Expr, name: &str) -> bool {
+    match expr {
+        Expr::Compare { field, .. } => field.name == name,
+        Expr::And(left, right) | Expr::Or(left, right) => {
+            references(left, name) || references(right, name)
+        }
+        Expr::Not(inner) => references(inner, name),
+    }
+}
+
+fn write_sql(
+    expr: &Expr,
+    dialect: Dialect,
+    first_param: usize,
+    sql: &mut String,
+    values: &mut Vec<Value>,
+) {
+    match expr {
+        Expr::Compare { field, op, value } => {
+            let n = first_param + values.len();
+            let placeholder = match (dialect, value) {
+                (Dialect::Postgres, Value::Timestamp(_)) => format!("${}::timestamptz", n),
+                (Dialect::Postgres, _) => format!("${}", n),
+                (Dialect::Sqlite, Value::Timestamp(_)) => "datetime(?)".to_string(),
+                (Dialect::Sqlite, _) => "?".to_string(),
+            };
+            sql.push_str(&format!("{} {} {}", field.column, op.as_sql(), placeholder));
+            values.push(value.clone());
+        }
+        Expr::And(left, right) | Expr::Or(left, right) => {
+            let joiner = if matches!(expr, Expr::And(..)) {
+                " AND "
+            } else {
+                " OR "
+            };
+            sql.push('(');
+            write_sql(left, dialect, first_param, sql, values);
+            sql.push_str(joiner);
+            write_sql(right, dialect, first_param, sql, values);
+            sql.push(')');
+        }
+        Expr::Not(inner) => {
+            sql.push_str("NOT (");
+            write_sql(inner, dialect, first_param, sql, values);
+            sql.push(')');
+        }
+    }
+}
+
+fn evaluate(expr: &Expr, user: &User) -> Option<bool> {
+    match expr {
+        Expr::Compare { field, op, value } => match (field.name, value) {
+            ("id", Value::Integer(v)) => Some(op.holds(user.id as i64, *v)),
+            ("name", Value::Text(v)) => Some(op.holds(user.name.as_str(), v.as_str())),
+            ("surname", Value::Text(v)) => Some(op.holds(user.surname.as_str(), v.as_str())),
+            _ => None,
+        },
+        Expr::And(left, right) => Some(evaluate(left, user)? && evaluate(right, user)?),
+        Expr::Or(left, right) => Some(evaluate(left, user)? || evaluate(right, user)?),
+        Expr::Not(inner) => Some(!evaluate(inner, user)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<Option<Filter>, Error> {
+        Filter::parse(input, USER_FIELDS)
+    }
+
+    #[test]
+    fn test_to_sql() {
+        let filter = parse(r#"name="Ann" AND created_at>"2024-01-01""#)
+            .unwrap()
+            .unwrap();
+
+        let (sql, values) = filter.to_sql(Dialect::Postgres, 1);
+
+        assert_eq!(sql, "(name = $1 AND created_at > $2::timestamptz)");
+        assert_eq!(
+            values,
+            vec![
+                Value::Text("Ann".to_string()),
+                Value::Timestamp("2024-01-01".to_string())
+            ]
+        );
+        assert_eq!(
+            filter.to_sql(Dialect::Sqlite, 1).0,
+            "(name = ? AND created_at > datetime(?))"
+        );
+    }
+
+    #[test]
+    fn test_or_binds_tighter_than_and() {
+        let filter = parse(r#"id > 1 AND name = "a" OR NOT surname != "b""#)
+            .unwrap()
+            .unwrap();
+
+        let (sql, _) = filter.to_sql(Dialect::Postgres, 3);
+
+        assert_eq!(sql, "(id > $3 AND (name = $4 OR NOT (surname <> $5)))");
+    }
+
+    #[test]
+    fn test_matches() {
+        let user = User {
+            id: 2,
+            name: "Ann".to_string(),
+            surname: "Lee".to_string(),
+        };
+        let matching = parse(r#"(id >= 2 OR id = 9) AND -name = "Bob""#)
+            .unwrap()
+            .unwrap();
+        let by_date = parse(r#"created_at < "2024-01-01""#).unwrap().unwrap();
+
+        assert_eq!(matching.matches(&user), Some(true));
+        assert_eq!(by_date.matches(&user), None);
+        assert!(matching.references("name"));
+        assert!(!matching.references("surname"));
+    }
+
+    #[test]
+    fn test_rejects_invalid_filters() {
+        for input in [
+            r#"deleted_at = "x""#,
+            r#"id = "1""#,
+            "name = Ann",
+            r#"created_at > "yesterday""#,
+            r#"name = "Ann" AND"#,
+            r#"(name = "Ann""#,
+            r#"name = "Ann" id = 1"#,
+            r#"name = "unterminated"#,
+        ] {
+            assert!(
+                matches!(parse(input), Err(Error::InvalidArgument(_))),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(parse("  ").unwrap(), None);
+    }
+}
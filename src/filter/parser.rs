@@ -0,0 +1,245 @@
+use crate::{
+    Error,
+    filter::{Comparator, Expr, Field, FieldKind, Value},
+};
+
+const MAX_FILTER_LEN: usize = 1024;
+const MAX_DEPTH: usize = 16;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Number(String),
+    Op(Comparator),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::InvalidArgument(format!("invalid filter: {}", msg.into()))
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => value.push(escaped),
+                            None => return Err(invalid("unterminated string")),
+                        },
+                        Some(c) => value.push(c),
+                        None => return Err(invalid("unterminated string")),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            '=' | ':' => {
+                chars.next();
+                tokens.push(Token::Op(Comparator::Eq));
+            }
+            '!' | '<' | '>' => {
+                chars.next();
+                let eq = chars.next_if_eq(&'=').is_some();
+                let op = match (c, eq) {
+                    ('!', true) => Comparator::Ne,
+                    ('<', false) => Comparator::Lt,
+                    ('<', true) => Comparator::Le,
+                    ('>', false) => Comparator::Gt,
+                    ('>', true) => Comparator::Ge,
+                    _ => return Err(invalid("expected `!=`")),
+                };
+                tokens.push(Token::Op(op));
+            }
+            c if c == '-' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                number.push(c);
+                chars.next();
+                while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+                    number.push(d);
+                }
+                if number == "-" {
+                    tokens.push(Token::Not);
+                } else {
+                    tokens.push(Token::Number(number));
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut word = String::new();
+                while let Some(w) = chars.next_if(|w| w.is_alphanumeric() || *w == '_') {
+                    word.push(w);
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Ident(word),
+                });
+            }
+            other => return Err(invalid(format!("unexpected character {:?}", other))),
+        }
+    }
+
+    Ok(tokens)
+}
+
+// AIP-160 precedence: OR binds tighter than AND, NOT tighter than both
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    fields: &'a [Field],
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.pos) == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, Error> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(invalid("expression is nested too deeply"));
+        }
+
+        let mut expr = self.factor()?;
+        while self.eat(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.factor()?));
+        }
+
+        self.depth -= 1;
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.term()?;
+        while self.eat(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.term()?));
+        }
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> Result<Expr, Error> {
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.simple()?)));
+        }
+        self.simple()
+    }
+
+    fn simple(&mut self) -> Result<Expr, Error> {
+        match self.next() {
+            Some(Token::Open) => {
+                let expr = self.expression()?;
+                if !self.eat(&Token::Close) {
+                    return Err(invalid("expected `)`"));
+                }
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => self.restriction(&name),
+            other => Err(invalid(format!("expected a field, got {:?}", other))),
+        }
+    }
+
+    fn restriction(&mut self, name: &str) -> Result<Expr, Error> {
+        let field = self
+            .fields
+            .iter()
+            .find(|f| f.name == name)
+            .ok_or_else(|| invalid(format!("unknown field {:?}", name)))?;
+        let Some(Token::Op(op)) = self.next() else {
+            return Err(invalid(format!("expected a comparator after {:?}", name)));
+        };
+
+        let value = match (field.kind, self.next()) {
+            (FieldKind::Integer, Some(Token::Number(n))) => Value::Integer(
+                n.parse()
+                    .map_err(|_| invalid(format!("{:?} is out of range", n)))?,
+            ),
+            (FieldKind::Text, Some(Token::Str(s))) => Value::Text(s),
+            (FieldKind::Timestamp, Some(Token::Str(s))) if looks_like_date(&s) => {
+                Value::Timestamp(s)
+            }
+            (kind, other) => {
+                return Err(invalid(format!(
+                    "{:?} expects a {} value, got {:?}",
+                    name,
+                    kind.as_str(),
+                    other
+                )));
+            }
+        };
+
+        Ok(Expr::Compare {
+            field: *field,
+            op,
+            value,
+        })
+    }
+}
+
+// the database does the real parsing, this only rejects obvious garbage early
+fn looks_like_date(s: &str) -> bool {
+    let b = s.as_bytes();
+    b.len() >= 10
+        && b[..4].iter().all(u8::is_ascii_digit)
+        && b[4] == b'-'
+        && b[5..7].iter().all(u8::is_ascii_digit)
+        && b[7] == b'-'
+        && b[8..10].iter().all(u8::is_ascii_digit)
+}
+
+pub(super) fn parse(input: &str, fields: &[Field]) -> Result<Option<Expr>, Error> {
+    if input.len() > MAX_FILTER_LEN {
+        return Err(invalid(format!(
+            "longer than {} characters",
+            MAX_FILTER_LEN
+        )));
+    }
+
+    let tokens = tokenize(input)?;
+    if tokens.is_empty() {
+        return Ok(None);
+    }
+
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        fields,
+        depth: 0,
+    };
+    let expr = parser.expression()?;
+    if let Some(token) = parser.next() {
+        return Err(invalid(format!("unexpected {:?}", token)));
+    }
+
+    Ok(Some(expr))
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::RwLock,
+};
+
+use tracing::warn;
+
+use crate::{Error, config::Config};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Flag {
+    SoftDelete,
+    StrictValidation,
+}
+
+impl Flag {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Flag::SoftDelete => "soft_delete",
+            Flag::StrictValidation => "strict_validation",
+        }
+    }
+}
+
+impl FromStr for Flag {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "soft_delete" => Ok(Flag::SoftDelete),
+            "strict_validation" => Ok(Flag::StrictValidation),
+            _ => Err(Error::Internal(
+                format!("unknown feature flag {:?}", s).into(),
+            )),
+        }
+    }
+}
+
+pub trait FeatureFlags: Send + Sync {
+    fn is_enabled(&self, flag: Flag, tenant: Option<&str>) -> bool;
+}
+
+#[derive(Clone, Debug, Default)]
+struct FlagSet {
+    global: HashMap<Flag, bool>,
+    tenants: HashMap<(String, Flag), bool>,
+}
+
+impl FlagSet {
+    fn lookup(&self, flag: Flag, tenant: Option<&str>) -> Option<bool> {
+        tenant
+            .and_then(|t| self.tenants.get(&(t.to_owned(), flag)).copied())
+            .or_else(|| self.global.get(&flag).copied())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct EnvFeatureFlags {
+    from_env: RwLock<FlagSet>,
+    file: Option<PathBuf>,
+    from_file: RwLock<FlagSet>,
+}
+
+impl EnvFeatureFlags {
+    pub fn from_config(config: &Config) -> Result<Self, Error> {
+        Self::new(&config.feature_flags, config.feature_flags_file.clone())
+    }
+
+    pub fn new(enabled: &str, file: Option<PathBuf>) -> Result<Self, Error> {
+        let flags = Self {
+            from_env: RwLock::new(parse_enabled(enabled)?),
+            file,
+            from_file: RwLock::default(),
+        };
+        flags.reload()?;
+
+        Ok(flags)
+    }
+
+    // a new FEATURE_FLAGS list; the file keeps overriding it
+    pub fn set_enabled(&self, enabled: &str) -> Result<(), Error> {
+        *self.from_env.write().unwrap() = parse_enabled(enabled)?;
+        Ok(())
+    }
+
+    pub fn reload(&self) -> Result<(), Error> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+
+        let parsed = parse_file(path)?;
+        *self.from_file.write().unwrap() = parsed;
+
+        Ok(())
+    }
+}
+
+impl FeatureFlags for EnvFeatureFlags {
+    fn is_enabled(&self, flag: Flag, tenant: Option<&str>) -> bool {
+        self.from_file
+            .read()
+            .unwrap()
+            .lookup(flag, tenant)
+            .or_else(|| self.from_env.read().unwrap().lookup(flag, tenant))
+            .unwrap_or(false)
+    }
+}
+
+fn parse_enabled(enabled: &str) -> Result<FlagSet, Error> {
+    let mut set = FlagSet::default();
+    for name in enabled.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        set.global.insert(name.parse()?, true);
+    }
+    Ok(set)
+}
+
+fn parse_file(path: &Path) -> Result<FlagSet, Error> {
+    let content = std::fs::read_to_string(path).map_err(|e| Error::Internal(Box::new(e)))?;
+    parse(&content)
+}
+
+// one `flag = bool` or `tenant.<tenant>.<flag> = bool` entry per line
+fn parse(content: &str) -> Result<FlagSet, Error> {
+    let mut set = FlagSet::default();
+
+    for (n, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(Error::Internal(
+                format!("feature flags line {}: expected key = value", n + 1).into(),
+            ));
+        };
+        let value = match value.trim() {
+            "true" | "on" | "1" => true,
+            "false" | "off" | "0" => false,
+            other => {
+                return Err(Error::Internal(
+                    format!("feature flags line {}: invalid value {:?}", n + 1, other).into(),
+                ));
+            }
+        };
+
+        let key = key.trim();
+        let (tenant, name) = match key.strip_prefix("tenant.") {
+            Some(rest) => match rest.rsplit_once('.') {
+                Some((tenant, name)) => (Some(tenant), name),
+                None => (None, rest),
+            },
+            None => (None, key),
+        };
+        let Ok(flag) = name.parse::<Flag>() else {
+            warn!("ignoring unknown feature flag {:?}", key);
+            continue;
+        };
+
+        match tenant {
+            Some(tenant) => set.tenants.insert((tenant.to_owned(), flag), value),
+            None => set.global.insert(flag, value),
+        };
+    }
+
+    Ok(set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_flags() {
+        let flags = EnvFeatureFlags::new("soft_delete", None).unwrap();
+
+        assert!(flags.is_enabled(Flag::SoftDelete, None));
+        assert!(!flags.is_enabled(Flag::StrictValidation, None));
+    }
+
+    #[test]
+    fn test_unknown_env_flag() {
+        assert!(EnvFeatureFlags::new("teleport", None).is_err());
+    }
+
+    #[test]
+    fn test_set_enabled() {
+        let flags = EnvFeatureFlags::new("soft_delete", None).unwrap();
+
+        flags.set_enabled("strict_validation").unwrap();
+        assert!(!flags.is_enabled(Flag::SoftDelete, None));
+        assert!(flags.is_enabled(Flag::StrictValidation, None));
+
+        // an invalid list leaves the current one in place
+        assert!(flags.set_enabled("teleport").is_err());
+        assert!(flags.is_enabled(Flag::StrictValidation, None));
+    }
+
+    #[test]
+    fn test_file_overrides_per_tenant() {
+        let set = parse(
+            "# defaults\nstrict_validation = on\ntenant.acme.strict_validation = off\nteleport = on\n",
+        )
+        .unwrap();
+
+        assert_eq!(set.lookup(Flag::StrictValidation, None), Some(true));
+        assert_eq!(
+            set.lookup(Flag::StrictValidation, Some("acme")),
+            Some(false)
+        );
+        assert_eq!(
+            set.lookup(Flag::StrictValidation, Some("other")),
+            Some(true)
+        );
+        assert_eq!(set.lookup(Flag::SoftDelete, None), None);
+    }
+
+    #[test]
+    fn test_malformed_file() {
+        assert!(parse("strict_validation").is_err());
+        assert!(parse("strict_validation = maybe").is_err());
+    }
+}
@@ -0,0 +1,550 @@
+mod codec;
+mod template;
+mod watch;
+
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::{Query, RawPathParams},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{MethodFilter, MethodRouter, get, on},
+};
+use prost_reflect::{
+    DescriptorPool, DynamicMessage, Kind, MessageDescriptor, MethodDescriptor, ReflectMessage,
+    Value,
+};
+use serde_json::json;
+use tonic::{Code, Status, client::Grpc, codegen::http::uri::PathAndQuery, metadata::MetadataMap};
+
+use crate::{
+    Error, grpc,
+    ratelimit::{QUOTA_RESET_HEADER, RETRY_AFTER_HEADER},
+};
+
+use codec::DynamicCodec;
+use template::Template;
+
+const HTTP_RULE_EXTENSION: &str = "google.api.http";
+
+// one REST binding of a unary RPC, taken from its google.api.http annotation
+struct Binding {
+    method: MethodDescriptor,
+    path: PathAndQuery,
+    template: Template,
+    body: Option<String>,
+}
+
+// JSON/REST routes for every annotated unary RPC, served by calling `routes`
+// in-process so auth, validation and metrics match the gRPC listener
+pub fn router(routes: tonic::service::Routes) -> Result<Router, Error> {
+    let pool = DescriptorPool::decode(grpc::FILE_DESCRIPTOR_SET)
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+    let Some(extension) = pool.get_extension_by_name(HTTP_RULE_EXTENSION) else {
+        return Err(Error::Internal(
+            format!("{} is missing from the descriptor set", HTTP_RULE_EXTENSION).into(),
+        ));
+    };
+
+    let Some(event) = pool.get_message_by_name(watch::EVENT_MESSAGE) else {
+        return Err(Error::Internal(
+            format!(
+                "{} is missing from the descriptor set",
+                watch::EVENT_MESSAGE
+            )
+            .into(),
+        ));
+    };
+    let watch_router = Router::new()
+        .route(watch::ROUTE, get(watch::watch))
+        .with_state(watch::WatchState::new(routes.clone(), event));
+
+    let mut by_route: HashMap<String, MethodRouter> = HashMap::new();
+    for service in pool.services() {
+        for method in service.methods() {
+            if method.is_client_streaming() || method.is_server_streaming() {
+                continue;
+            }
+            let options = method.options();
+            if !options.has_extension(&extension) {
+                continue;
+            }
+            let Value::Message(rule) = options.get_extension(&extension).into_owned() else {
+                continue;
+            };
+
+            for (http_method, binding) in bindings(&method, &rule)? {
+                let route = binding.template.route().to_owned();
+                let handler = {
+                    let binding = Arc::new(binding);
+                    let routes = routes.clone();
+                    move |params: RawPathParams,
+                          Query(query): Query<Vec<(String, String)>>,
+                          headers: HeaderMap,
+                          body: Bytes| {
+                        transcode(binding, routes, params, query, headers, body)
+                    }
+                };
+                let filter = MethodFilter::try_from(http_method)
+                    .map_err(|e| Error::Internal(Box::new(e)))?;
+                let method_router = match by_route.remove(&route) {
+                    Some(existing) => existing.on(filter, handler),
+                    None => on(filter, handler),
+                };
+                by_route.insert(route, method_router);
+            }
+        }
+    }
+
+    Ok(by_route
+        .into_iter()
+        .fold(watch_router, |router, (route, method_router)| {
+            router.route(&route, method_router)
+        }))
+}
+
+fn bindings(
+    method: &MethodDescriptor,
+    rule: &DynamicMessage,
+) -> Result<Vec<(Method, Binding)>, Error> {
+    let text = |field: &str| match rule.get_field_by_name(field).as_deref() {
+        Some(Value::String(s)) if !s.is_empty() => Some(s.clone()),
+        _ => None,
+    };
+    let unsupported = |what: &str| {
+        Error::Internal(format!("{}: {} is not supported", method.full_name(), what).into())
+    };
+
+    let (http_method, path) = if let Some(path) = text("get") {
+        (Method::GET, path)
+    } else if let Some(path) = text("post") {
+        (Method::POST, path)
+    } else if let Some(path) = text("put") {
+        (Method::PUT, path)
+    } else if let Some(path) = text("patch") {
+        (Method::PATCH, path)
+    } else if let Some(path) = text("delete") {
+        (Method::DELETE, path)
+    } else {
+        return Err(unsupported(
+            "an http rule without get/post/put/patch/delete",
+        ));
+    };
+    if text("response_body").is_some() {
+        return Err(unsupported("response_body"));
+    }
+
+    let body = text("body");
+    if let Some(field) = body.as_deref().filter(|f| *f != "*")
+        && method.input().get_field_by_name(field).is_none()
+    {
+        return Err(unsupported(&format!("body field {:?}", field)));
+    }
+
+    let mut out = vec![(
+        http_method,
+        Binding {
+            method: method.clone(),
+            path: format!("/{}/{}", method.parent_service().full_name(), method.name())
+                .parse()
+                .map_err(|e| Error::Internal(Box::new(e)))?,
+            template: Template::parse(&path)?,
+            body,
+        },
+    )];
+    if let Some(Value::List(additional)) = rule.get_field_by_name("additional_bindings").as_deref()
+    {
+        for rule in additional {
+            if let Value::Message(rule) = rule {
+                out.extend(bindings(method, rule)?);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+async fn transcode(
+    binding: Arc<Binding>,
+    routes: tonic::service::Routes,
+    params: RawPathParams,
+    query: Vec<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let message = match build_message(&binding, &params, &query, &body) {
+        Ok(message) => message,
+        Err(status) => return error_response(&status),
+    };
+
+    let mut request = tonic::Request::new(message);
+    *request.metadata_mut() = MetadataMap::from_headers(forwarded(&headers));
+
+    let mut client = Grpc::new(routes);
+    if let Err(e) = client.ready().await {
+        return error_response(&Status::unavailable(format!("{}", e)));
+    }
+    let codec = DynamicCodec::new(binding.method.output());
+    match client.unary(request, binding.path.clone(), codec).await {
+        Ok(response) => match serde_json::to_vec(&response.into_inner()) {
+            Ok(json) => ([(header::CONTENT_TYPE, "application/json")], json).into_response(),
+            Err(e) => error_response(&Status::internal(format!(
+                "failed to encode response: {}",
+                e
+            ))),
+        },
+        Err(status) => error_response(&status),
+    }
+}
+
+fn build_message(
+    binding: &Binding,
+    params: &RawPathParams,
+    query: &[(String, String)],
+    body: &[u8],
+) -> Result<DynamicMessage, Status> {
+    let input = binding.method.input();
+    let mut message = match binding.body.as_deref() {
+        Some("*") => from_json(input, body)?,
+        Some(field) => {
+            let mut message = DynamicMessage::new(input.clone());
+            let field = input
+                .get_field_by_name(field)
+                .ok_or_else(|| Status::internal(format!("unknown body field {:?}", field)))?;
+            let Kind::Message(desc) = field.kind() else {
+                return Err(Status::internal(format!(
+                    "body field {:?} is not a message",
+                    field.name()
+                )));
+            };
+            message.set_field(&field, Value::Message(from_json(desc, body)?));
+            message
+        }
+        None => DynamicMessage::new(input),
+    };
+
+    // with body "*" every field comes from the body, otherwise the query
+    // string fills whatever the path and body leave unset
+    if binding.body.as_deref() != Some("*") {
+        for (key, value) in query {
+            set_path(&mut message, key, value)?;
+        }
+    }
+    let params: Vec<_> = params.iter().collect();
+    for (field, value) in binding.template.bind(&params) {
+        set_path(&mut message, &field, &value)?;
+    }
+
+    Ok(message)
+}
+
+fn from_json(desc: MessageDescriptor, body: &[u8]) -> Result<DynamicMessage, Status> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(DynamicMessage::new(desc));
+    }
+
+    let mut deserializer = serde_json::Deserializer::from_slice(body);
+    let message = DynamicMessage::deserialize(desc, &mut deserializer)
+        .and_then(|message| deserializer.end().map(|_| message))
+        .map_err(|e| Status::invalid_argument(format!("invalid JSON body: {}", e)))?;
+
+    Ok(message)
+}
+
+// `a.b.c=value`, accepting proto and JSON field names
+fn set_path(message: &mut DynamicMessage, path: &str, raw: &str) -> Result<(), Status> {
+    let (head, rest) = match path.split_once('.') {
+        Some((head, rest)) => (head, Some(rest)),
+        None => (path, None),
+    };
+    let desc = message.descriptor();
+    let field = desc
+        .get_field_by_name(head)
+        .or_else(|| desc.get_field_by_json_name(head))
+        .ok_or_else(|| Status::invalid_argument(format!("unknown field {:?}", path)))?;
+
+    if let Some(rest) = rest {
+        return match message.get_field_mut(&field) {
+            Value::Message(inner) => set_path(inner, rest, raw),
+            _ => Err(Status::invalid_argument(format!(
+                "{:?} is not a message field",
+                head
+            ))),
+        };
+    }
+    if field.is_map() {
+        return Err(Status::invalid_argument(format!(
+            "map field {:?} cannot be set from the url",
+            path
+        )));
+    }
+
+    let value = parse_value(&field.kind(), raw).ok_or_else(|| {
+        Status::invalid_argument(format!("invalid value {:?} for field {:?}", raw, path))
+    })?;
+    match message.get_field_mut(&field) {
+        Value::List(values) if field.is_list() => values.push(value),
+        slot => *slot = value,
+    }
+
+    Ok(())
+}
+
+fn parse_value(kind: &Kind, raw: &str) -> Option<Value> {
+    match kind {
+        Kind::Bool => raw.parse().ok().map(Value::Bool),
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => raw.parse().ok().map(Value::I32),
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => raw.parse().ok().map(Value::I64),
+        Kind::Uint32 | Kind::Fixed32 => raw.parse().ok().map(Value::U32),
+        Kind::Uint64 | Kind::Fixed64 => raw.parse().ok().map(Value::U64),
+        Kind::Float => raw.parse().ok().map(Value::F32),
+        Kind::Double => raw.parse().ok().map(Value::F64),
+        Kind::String => Some(Value::String(raw.to_owned())),
+        Kind::Enum(desc) => desc
+            .get_value_by_name(raw)
+            .map(|v| v.number())
+            .or_else(|| raw.parse().ok())
+            .map(Value::EnumNumber),
+        // well-known types such as FieldMask and Timestamp have a string JSON form
+        Kind::Message(desc) => {
+            DynamicMessage::deserialize(desc.clone(), serde_json::Value::from(raw))
+                .ok()
+                .map(Value::Message)
+        }
+        Kind::Bytes => None,
+    }
+}
+
+// credentials and x- headers (impersonation, request ids) reach the interceptors
+fn forwarded(headers: &HeaderMap) -> HeaderMap {
+    headers
+        .iter()
+        .filter(|(name, _)| *name == header::AUTHORIZATION || name.as_str().starts_with("x-"))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+pub(crate) fn error_response(status: &Status) -> Response {
+    let http_status = match status.code() {
+        Code::Ok => StatusCode::OK,
+        Code::Cancelled => StatusCode::from_u16(499).unwrap_or(StatusCode::BAD_REQUEST),
+        Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+            StatusCode::BAD_REQUEST
+        }
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::Unknown | Code::Internal | Code::DataLoss => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    let mut response = (http_status, Json(error_body(status))).into_response();
+    // when a rate limited caller may try again
+    for name in [RETRY_AFTER_HEADER, QUOTA_RESET_HEADER] {
+        if let Some(value) = status.metadata().get(name)
+            && let Ok(value) = HeaderValue::from_bytes(value.as_bytes())
+        {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    response
+}
+
+fn error_body(status: &Status) -> serde_json::Value {
+    json!({
+        "code": status.code() as i32,
+        "message": status.message(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use tokio_stream::StreamExt;
+    use tower::ServiceExt;
+    use tracing::Level;
+
+    use super::*;
+    use crate::{
+        grpc::{
+            user_service_server::UserServiceServer,
+            v2::user_service_server::UserServiceServer as UserServiceServerV2,
+        },
+        repositories::memory_user_repository::InMemoryUserRepository,
+        servers::{user_server::UserServer, v2},
+        usecases::user_usecase::UserUsecase,
+    };
+
+    fn gateway() -> Router {
+        let repo = InMemoryUserRepository::new();
+        let server = UserServer::new(
+            tracing::span!(Level::INFO, "UserService"),
+            UserUsecase::new(repo.clone()),
+        );
+        let server_v2 = v2::UserServer::new(
+            tracing::span!(Level::INFO, "UserServiceV2"),
+            UserUsecase::new(repo),
+        );
+        let routes = tonic::service::Routes::new(UserServiceServer::new(server))
+            .add_service(UserServiceServerV2::new(server_v2));
+        router(routes).unwrap()
+    }
+
+    async fn call(
+        router: &Router,
+        method: &str,
+        uri: &str,
+        body: &str,
+    ) -> (StatusCode, serde_json::Value) {
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body.to_owned()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_rest_round_trip() {
+        let router = gateway();
+
+        let (status, created) = call(
+            &router,
+            "POST",
+            "/v1/users",
+            r#"{"name":"Ann","surname":"Lee"}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let id = created["user"]["id"].as_i64().unwrap();
+
+        let (status, updated) = call(
+            &router,
+            "PATCH",
+            &format!("/v1/users/{}", id),
+            r#"{"surname":"Park"}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(updated["user"]["surname"], "Park");
+
+        let (status, fetched) = call(&router, "GET", &format!("/v1/users/{}", id), "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(fetched["user"]["name"], "Ann");
+
+        let (status, listed) = call(
+            &router,
+            "GET",
+            "/v1/users?filter=name%20%3D%20%22Ann%22",
+            "",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(listed["users"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_resource_name_templates() {
+        let router = gateway();
+
+        let (status, created) = call(
+            &router,
+            "POST",
+            "/v2/users",
+            r#"{"givenName":"Ann","familyName":"Lee"}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let name = created["name"].as_str().unwrap().to_owned();
+
+        let (status, updated) = call(
+            &router,
+            "PATCH",
+            &format!("/v2/{}?updateMask=familyName", name),
+            r#"{"familyName":""}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(updated["givenName"], "Ann");
+        assert!(updated.get("familyName").is_none());
+
+        let (status, _) = call(&router, "DELETE", &format!("/v2/{}", name), "").await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(&router, "GET", &format!("/v2/{}", name), "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_errors_map_to_http_status() {
+        let router = gateway();
+
+        let (status, body) = call(&router, "GET", "/v1/users/404", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], Code::NotFound as i32);
+
+        let (status, _) = call(&router, "GET", "/v1/users/abc", "").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = call(&router, "POST", "/v1/users", "{\"email\":\"a@b\"}").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    async fn first_sse_frame(router: &Router, last_event_id: &str) -> String {
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/v1/users:watch")
+                    .header("last-event-id", last_event_id)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut frames = response.into_body().into_data_stream();
+        let frame = tokio::time::timeout(std::time::Duration::from_secs(1), frames.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        String::from_utf8(frame.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sse_resumes_from_last_event_id() {
+        let router = gateway();
+        for name in ["Ann", "Bob"] {
+            let body = format!(r#"{{"name":"{}","surname":"Lee"}}"#, name);
+            call(&router, "POST", "/v1/users", &body).await;
+        }
+
+        let frame = first_sse_frame(&router, "1").await;
+        assert!(frame.contains("event: created"), "{}", frame);
+        assert!(frame.contains("id: 2"), "{}", frame);
+        assert!(frame.contains(r#""name":"Bob""#), "{}", frame);
+
+        // ahead of the feed, so nothing can be replayed
+        let frame = first_sse_frame(&router, "99").await;
+        assert!(frame.contains("event: reset"), "{}", frame);
+    }
+}
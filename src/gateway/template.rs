@@ -0,0 +1,157 @@
+use crate::Error;
+
+// a google.api.http path template such as `/v2/{name=users/*}/addresses`,
+// compiled into an axum route plus the request fields its captures fill in
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Template {
+    route: String,
+    variables: Vec<Variable>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Variable {
+    field: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Capture(String),
+}
+
+impl Template {
+    pub(crate) fn parse(path: &str) -> Result<Self, Error> {
+        if !path.starts_with('/') {
+            return Err(template_error(path, "must start with '/'"));
+        }
+
+        let mut route = String::with_capacity(path.len());
+        let mut variables = Vec::new();
+        let mut captures = 0;
+        let mut rest = path;
+        while let Some(start) = rest.find('{') {
+            route.push_str(&rest[..start]);
+            let Some(len) = rest[start..].find('}') else {
+                return Err(template_error(path, "unterminated variable"));
+            };
+            let inner = &rest[start + 1..start + len];
+            rest = &rest[start + len + 1..];
+
+            let (field, pattern) = inner.split_once('=').unwrap_or((inner, "*"));
+            if field.is_empty() || inner.contains('{') {
+                return Err(template_error(path, "malformed variable"));
+            }
+
+            let mut segments = Vec::new();
+            let mut parts = Vec::new();
+            let count = pattern.split('/').count();
+            for (i, part) in pattern.split('/').enumerate() {
+                let wildcard = match part {
+                    "*" => false,
+                    "**" if i == count - 1 => true,
+                    "**" => return Err(template_error(path, "'**' must be the last segment")),
+                    "" => return Err(template_error(path, "empty segment")),
+                    literal => {
+                        parts.push(literal.to_owned());
+                        segments.push(Segment::Literal(literal.to_owned()));
+                        continue;
+                    }
+                };
+                let name = format!("v{}", captures);
+                captures += 1;
+                parts.push(match wildcard {
+                    false => format!("{{{}}}", name),
+                    true => format!("{{*{}}}", name),
+                });
+                segments.push(Segment::Capture(name));
+            }
+            route.push_str(&parts.join("/"));
+            variables.push(Variable {
+                field: field.to_owned(),
+                segments,
+            });
+        }
+        route.push_str(rest);
+
+        Ok(Self { route, variables })
+    }
+
+    pub(crate) fn route(&self) -> &str {
+        &self.route
+    }
+
+    // (field path, value) pairs for the captured segments of a matched request
+    pub(crate) fn bind(&self, params: &[(&str, &str)]) -> Vec<(String, String)> {
+        self.variables
+            .iter()
+            .map(|variable| {
+                let value = variable
+                    .segments
+                    .iter()
+                    .map(|segment| match segment {
+                        Segment::Literal(literal) => literal.as_str(),
+                        Segment::Capture(name) => params
+                            .iter()
+                            .find(|(key, _)| key == name)
+                            .map(|(_, value)| *value)
+                            .unwrap_or_default(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/");
+                (variable.field.clone(), value)
+            })
+            .collect()
+    }
+}
+
+fn template_error(path: &str, reason: &str) -> Error {
+    Error::Internal(format!("invalid http template {:?}: {}", path, reason).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simple_variables() {
+        let template =
+            Template::parse("/v1/users/{user_id}/relationships/{related_user_id}").unwrap();
+
+        assert_eq!(template.route(), "/v1/users/{v0}/relationships/{v1}");
+        assert_eq!(
+            template.bind(&[("v0", "7"), ("v1", "9")]),
+            vec![
+                ("user_id".to_string(), "7".to_string()),
+                ("related_user_id".to_string(), "9".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resource_name_variables() {
+        let template = Template::parse("/v2/{user.name=users/*}").unwrap();
+
+        assert_eq!(template.route(), "/v2/users/{v0}");
+        assert_eq!(
+            template.bind(&[("v0", "42")]),
+            vec![("user.name".to_string(), "users/42".to_string())]
+        );
+        assert_eq!(
+            Template::parse("/v1/{path=files/**}").unwrap().route(),
+            "/v1/files/{*v0}"
+        );
+        assert_eq!(
+            Template::parse("/v1/users:count").unwrap().route(),
+            "/v1/users:count"
+        );
+    }
+
+    #[test]
+    fn test_malformed_templates() {
+        assert!(Template::parse("v1/users").is_err());
+        assert!(Template::parse("/v1/users/{id").is_err());
+        assert!(Template::parse("/v1/{name=**/users}").is_err());
+        assert!(Template::parse("/v1/{=users/*}").is_err());
+    }
+}
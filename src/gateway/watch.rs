@@ -0,0 +1,353 @@
+use std::{convert::Infallible, time::Duration};
+
+use axum::{
+    extract::{
+        Query, State,
+        ws::{Message, WebSocket, WebSocketUpgrade, rejection::WebSocketUpgradeRejection},
+    },
+    http::{HeaderMap, HeaderValue, header},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use prost_reflect::{DynamicMessage, MessageDescriptor};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio_stream::{StreamExt, wrappers::ReceiverStream};
+use tonic::{Code, Status, codec::Streaming, metadata::MetadataMap, service::Routes};
+
+use crate::{
+    entities::users::User,
+    filter::{Filter, USER_FIELDS},
+    grpc::{UserEvent, UserEventKind, WatchUsersRequest, user_service_client::UserServiceClient},
+};
+
+use super::{error_body, error_response, forwarded};
+
+pub(crate) const ROUTE: &str = "/v1/users:watch";
+pub(crate) const EVENT_MESSAGE: &str = "user.v1.UserEvent";
+const LAST_EVENT_ID: &str = "last-event-id";
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+#[derive(Clone)]
+pub(crate) struct WatchState {
+    routes: Routes,
+    event: MessageDescriptor,
+}
+
+impl WatchState {
+    pub(crate) fn new(routes: Routes, event: MessageDescriptor) -> Self {
+        Self { routes, event }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct WatchParams {
+    // AIP-160 over the event's user, e.g. `name = "Ann"`
+    #[serde(default)]
+    filter: String,
+    // comma separated subset of created,updated,deleted
+    #[serde(default)]
+    kinds: String,
+    // browsers cannot set headers on a websocket upgrade or an EventSource
+    access_token: Option<String>,
+    // resume point for clients that cannot send Last-Event-ID
+    last_event_id: Option<u64>,
+}
+
+impl WatchParams {
+    // the header an EventSource sends on reconnect wins over the query
+    fn after_sequence(&self, headers: &HeaderMap) -> u64 {
+        headers
+            .get(LAST_EVENT_ID)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok())
+            .or(self.last_event_id)
+            .unwrap_or_default()
+    }
+}
+
+// filters run per connection so every watcher shares the one WatchUsers feed;
+// deletions only carry the id, so field filters other than id skip them
+pub(crate) struct EventFilter {
+    filter: Option<Filter>,
+    kinds: Vec<UserEventKind>,
+}
+
+impl EventFilter {
+    pub(crate) fn parse(filter: &str, kinds: &str) -> Result<Self, Status> {
+        let filter = Filter::parse(filter, USER_FIELDS)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        if filter
+            .as_ref()
+            .is_some_and(|f| f.matches(&User::default()).is_none())
+        {
+            return Err(Status::invalid_argument(
+                "created_at cannot be filtered on while watching",
+            ));
+        }
+
+        let kinds = kinds
+            .split(',')
+            .map(str::trim)
+            .filter(|k| !k.is_empty())
+            .map(|k| match k.to_ascii_lowercase().as_str() {
+                "created" => Ok(UserEventKind::Created),
+                "updated" => Ok(UserEventKind::Updated),
+                "deleted" => Ok(UserEventKind::Deleted),
+                _ => Err(Status::invalid_argument(format!(
+                    "unknown event kind {:?}",
+                    k
+                ))),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { filter, kinds })
+    }
+
+    pub(crate) fn matches(&self, event: &UserEvent) -> bool {
+        if !self.kinds.is_empty() && !self.kinds.contains(&event.kind()) {
+            return false;
+        }
+        let Some(filter) = &self.filter else {
+            return true;
+        };
+        let user = event.user.clone().unwrap_or_default();
+
+        filter.matches(&User {
+            id: user.id,
+            name: user.name,
+            surname: user.surname,
+        }) == Some(true)
+    }
+}
+
+// same protojson encoding as the REST gateway responses
+pub(crate) fn event_json(desc: &MessageDescriptor, event: &UserEvent) -> String {
+    let mut message = DynamicMessage::new(desc.clone());
+    match message.transcode_from(event) {
+        Ok(()) => serde_json::to_string(&message).unwrap_or_default(),
+        Err(e) => {
+            error_body(&Status::internal(format!("failed to encode event: {}", e))).to_string()
+        }
+    }
+}
+
+// WatchUsers through the in-process routes, so the interceptors apply
+pub(crate) async fn subscribe(
+    routes: Routes,
+    headers: &HeaderMap,
+    params: &WatchParams,
+    after_sequence: u64,
+) -> Result<Streaming<UserEvent>, Status> {
+    let mut headers = forwarded(headers);
+    if let Some(token) = &params.access_token {
+        let value = HeaderValue::try_from(format!("Bearer {}", token))
+            .map_err(|_| Status::unauthenticated("malformed access_token"))?;
+        headers.insert(header::AUTHORIZATION, value);
+    }
+
+    let mut request = tonic::Request::new(WatchUsersRequest {
+        after_sequence,
+        resume_token: 0,
+        heartbeat_secs: 0,
+    });
+    *request.metadata_mut() = MetadataMap::from_headers(headers);
+    let events = UserServiceClient::new(routes)
+        .watch_users(request)
+        .await?
+        .into_inner();
+
+    Ok(events)
+}
+
+pub(crate) async fn watch(
+    State(state): State<WatchState>,
+    Query(params): Query<WatchParams>,
+    headers: HeaderMap,
+    // a plain GET without the upgrade headers gets server-sent events
+    upgrade: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+) -> Response {
+    let filter = match EventFilter::parse(&params.filter, &params.kinds) {
+        Ok(filter) => filter,
+        Err(status) => return error_response(&status),
+    };
+
+    let after = params.after_sequence(&headers);
+    // subscribing before the upgrade turns auth failures into plain HTTP errors
+    let (events, reset) = match subscribe(state.routes.clone(), &headers, &params, after).await {
+        Ok(events) => (events, false),
+        // the resume point was evicted, start over from the live tail
+        Err(status) if after > 0 && status.code() == Code::InvalidArgument => {
+            match subscribe(state.routes, &headers, &params, 0).await {
+                Ok(events) => (events, true),
+                Err(status) => return error_response(&status),
+            }
+        }
+        Err(status) => return error_response(&status),
+    };
+
+    match upgrade {
+        Ok(upgrade) => upgrade.on_upgrade(move |socket| relay(socket, events, filter, state.event)),
+        Err(_) => {
+            let (tx, rx) = mpsc::channel(16);
+            tokio::spawn(stream_events(tx, events, filter, state.event, reset));
+            Sse::new(ReceiverStream::new(rx))
+                .keep_alive(KeepAlive::new().interval(KEEP_ALIVE))
+                .into_response()
+        }
+    }
+}
+
+// an `event: reset` tells the client it missed events and should refetch
+async fn stream_events(
+    tx: mpsc::Sender<Result<Event, Infallible>>,
+    mut events: Streaming<UserEvent>,
+    filter: EventFilter,
+    desc: MessageDescriptor,
+    reset: bool,
+) {
+    if reset && tx.send(Ok(Event::default().event("reset"))).await.is_err() {
+        return;
+    }
+
+    loop {
+        let event = tokio::select! {
+            _ = tx.closed() => return,
+            event = events.next() => event,
+        };
+        let (frame, last) = match event {
+            Some(Ok(event)) if filter.matches(&event) => (sse_event(&desc, &event), false),
+            Some(Ok(_)) => continue,
+            Some(Err(status)) => (
+                Event::default()
+                    .event("error")
+                    .data(error_body(&status).to_string()),
+                true,
+            ),
+            None => return,
+        };
+        if tx.send(Ok(frame)).await.is_err() || last {
+            return;
+        }
+    }
+}
+
+// the id is what an EventSource replays as Last-Event-ID
+fn sse_event(desc: &MessageDescriptor, event: &UserEvent) -> Event {
+    let kind = match event.kind() {
+        UserEventKind::Created => "created",
+        UserEventKind::Updated => "updated",
+        UserEventKind::Deleted => "deleted",
+        UserEventKind::Unspecified => "unspecified",
+    };
+
+    Event::default()
+        .id(event.sequence.to_string())
+        .event(kind)
+        .data(event_json(desc, event))
+}
+
+async fn relay(
+    mut socket: WebSocket,
+    mut events: Streaming<UserEvent>,
+    filter: EventFilter,
+    desc: MessageDescriptor,
+) {
+    loop {
+        tokio::select! {
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                // nothing to read from the client besides pings
+                Some(Ok(_)) => continue,
+            },
+            event = events.next() => {
+                let frame = match event {
+                    Some(Ok(event)) if filter.matches(&event) => event_json(&desc, &event),
+                    Some(Ok(_)) => continue,
+                    Some(Err(status)) => {
+                        let _ = socket
+                            .send(Message::Text(error_body(&status).to_string().into()))
+                            .await;
+                        break;
+                    }
+                    None => break,
+                };
+                if socket.send(Message::Text(frame.into())).await.is_err() {
+                    return;
+                }
+            },
+        }
+    }
+
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grpc;
+
+    fn event(kind: UserEventKind, name: &str) -> UserEvent {
+        UserEvent {
+            sequence: 1,
+            kind: kind as i32,
+            user: Some(grpc::User {
+                id: 7,
+                name: name.to_string(),
+                surname: String::new(),
+            }),
+            resume_token: 0,
+            heartbeat: false,
+        }
+    }
+
+    #[test]
+    fn test_event_filter() {
+        let filter = EventFilter::parse(r#"name = "Ann""#, "created, UPDATED").unwrap();
+
+        assert!(filter.matches(&event(UserEventKind::Created, "Ann")));
+        assert!(!filter.matches(&event(UserEventKind::Created, "Bob")));
+        assert!(!filter.matches(&event(UserEventKind::Deleted, "Ann")));
+        assert!(
+            EventFilter::parse("", "")
+                .unwrap()
+                .matches(&event(UserEventKind::Deleted, ""))
+        );
+    }
+
+    #[test]
+    fn test_event_filter_rejects_unusable_input() {
+        assert!(EventFilter::parse("", "renamed").is_err());
+        assert!(EventFilter::parse(r#"created_at > "2024-01-01""#, "").is_err());
+        assert!(EventFilter::parse("name =", "").is_err());
+    }
+
+    #[test]
+    fn test_after_sequence() {
+        let params = WatchParams {
+            last_event_id: Some(3),
+            ..Default::default()
+        };
+        let mut headers = HeaderMap::new();
+        assert_eq!(params.after_sequence(&headers), 3);
+        assert_eq!(WatchParams::default().after_sequence(&headers), 0);
+
+        headers.insert(LAST_EVENT_ID, HeaderValue::from_static("9"));
+        assert_eq!(params.after_sequence(&headers), 9);
+    }
+
+    #[test]
+    fn test_event_json() {
+        let pool = prost_reflect::DescriptorPool::decode(grpc::FILE_DESCRIPTOR_SET).unwrap();
+        let desc = pool.get_message_by_name(EVENT_MESSAGE).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&event_json(&desc, &event(UserEventKind::Created, "Ann")))
+                .unwrap();
+
+        assert_eq!(json["kind"], "USER_EVENT_KIND_CREATED");
+        assert_eq!(json["sequence"], "1");
+        assert_eq!(json["user"]["name"], "Ann");
+    }
+}
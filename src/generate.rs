@@ -0,0 +1,89 @@
+use fake::{
+    Fake,
+    faker::name::raw::{FirstName, LastName},
+    locales::{DE_DE, EN, FR_FR, IT_IT, JA_JP, NL_NL, PT_BR, TR_TR, ZH_CN},
+    rand::{SeedableRng, rngs::ChaCha8Rng},
+};
+
+use crate::{Error, entities::users::User, repositories::UserRepository};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Locale {
+    En,
+    DeDe,
+    FrFr,
+    ItIt,
+    JaJp,
+    NlNl,
+    PtBr,
+    TrTr,
+    ZhCn,
+}
+
+// ChaCha8 rather than StdRng, whose algorithm may change between rand releases
+pub fn fake_names(count: usize, seed: u64, locale: Locale) -> Vec<(String, String)> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+    macro_rules! names {
+        ($locale:expr) => {
+            (0..count)
+                .map(|_| {
+                    (
+                        FirstName($locale).fake_with_rng(&mut rng),
+                        LastName($locale).fake_with_rng(&mut rng),
+                    )
+                })
+                .collect()
+        };
+    }
+
+    match locale {
+        Locale::En => names!(EN),
+        Locale::DeDe => names!(DE_DE),
+        Locale::FrFr => names!(FR_FR),
+        Locale::ItIt => names!(IT_IT),
+        Locale::JaJp => names!(JA_JP),
+        Locale::NlNl => names!(NL_NL),
+        Locale::PtBr => names!(PT_BR),
+        Locale::TrTr => names!(TR_TR),
+        Locale::ZhCn => names!(ZH_CN),
+    }
+}
+
+// writes straight to the repository, skipping the usecase's auth and audit
+pub async fn generate_users<R: UserRepository>(
+    repo: &R,
+    count: usize,
+    seed: u64,
+    locale: Locale,
+) -> Result<Vec<User>, Error> {
+    repo.create_users_bulk(fake_names(count, seed, locale))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::memory_user_repository::InMemoryUserRepository;
+
+    #[test]
+    fn test_fake_names_are_seeded() {
+        let names = fake_names(20, 7, Locale::DeDe);
+
+        assert_eq!(names.len(), 20);
+        assert_eq!(names, fake_names(20, 7, Locale::DeDe));
+        assert_ne!(names, fake_names(20, 8, Locale::DeDe));
+        assert!(names.iter().all(|(n, s)| !n.is_empty() && !s.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_generate_users() {
+        let repo = InMemoryUserRepository::new();
+
+        let users = generate_users(&repo, 5, 1, Locale::En).await.unwrap();
+
+        assert_eq!(users.len(), 5);
+        assert_eq!(repo.count_users().await.unwrap(), 5);
+        assert_eq!(users[0].name, fake_names(1, 1, Locale::En)[0].0);
+    }
+}
@@ -0,0 +1,183 @@
+mod schema;
+
+use std::sync::Arc;
+
+use async_graphql::{Data, Schema, http::ALL_WEBSOCKET_PROTOCOLS};
+use async_graphql_axum::{GraphQLProtocol, GraphQLRequest, GraphQLResponse, GraphQLWebSocket};
+use axum::{
+    Router,
+    extract::{State, WebSocketUpgrade},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use tonic::{Extensions, Status, metadata::MetadataMap, service::Interceptor};
+
+use crate::{
+    auth::{AuthInterceptor, IMPERSONATE_HEADER, Principal},
+    gateway,
+    usecases::UserUsecaseTrait,
+};
+
+pub use schema::{MutationRoot, QueryRoot, SubscriptionRoot, UserSchema};
+
+#[derive(Clone)]
+struct GraphqlState {
+    schema: UserSchema,
+    auth: AuthInterceptor,
+}
+
+pub fn schema(usecase: Arc<dyn UserUsecaseTrait>) -> UserSchema {
+    Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+        .data(usecase)
+        .finish()
+}
+
+// POST /graphql for queries and mutations, /graphql/ws for subscriptions
+pub fn router(schema: UserSchema, auth: AuthInterceptor) -> Router {
+    Router::new()
+        .route("/graphql", post(execute))
+        .route("/graphql/ws", get(subscribe))
+        .with_state(GraphqlState { schema, auth })
+}
+
+async fn execute(
+    State(state): State<GraphqlState>,
+    headers: HeaderMap,
+    request: GraphQLRequest,
+) -> Response {
+    let caller = match authenticate(&state.auth, MetadataMap::from_headers(headers)) {
+        Ok(caller) => caller,
+        Err(status) => return gateway::error_response(&status),
+    };
+
+    let mut request = request.into_inner();
+    if let Some(caller) = caller {
+        request = request.data(caller);
+    }
+
+    GraphQLResponse::from(state.schema.execute(request).await).into_response()
+}
+
+async fn subscribe(
+    State(state): State<GraphqlState>,
+    protocol: GraphQLProtocol,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    upgrade
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |socket| {
+            GraphQLWebSocket::new(socket, state.schema.clone(), protocol)
+                .on_connection_init(move |payload| async move {
+                    // browsers cannot set headers on a websocket, so the
+                    // connection_init payload may carry them instead
+                    let mut metadata = MetadataMap::from_headers(headers);
+                    for key in ["authorization", IMPERSONATE_HEADER] {
+                        if let Some(value) = payload.get(key).and_then(|v| v.as_str()) {
+                            metadata.insert(key, value.parse()?);
+                        }
+                    }
+
+                    let mut data = Data::default();
+                    if let Some(caller) = authenticate(&state.auth, metadata)
+                        .map_err(|status| async_graphql::Error::new(status.message()))?
+                    {
+                        data.insert(caller);
+                    }
+                    Ok(data)
+                })
+                .serve()
+        })
+}
+
+// runs the gRPC interceptor so tokens and impersonation behave the same
+fn authenticate(
+    auth: &AuthInterceptor,
+    metadata: MetadataMap,
+) -> Result<Option<Principal>, Status> {
+    let request = auth
+        .clone()
+        .call(tonic::Request::from_parts(metadata, Extensions::new(), ()))?;
+
+    Ok(Principal::from_extensions(request.extensions()).cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::{
+        repositories::memory_user_repository::InMemoryUserRepository,
+        usecases::user_usecase::UserUsecase,
+    };
+
+    fn test_schema() -> UserSchema {
+        schema(Arc::new(UserUsecase::new(InMemoryUserRepository::new())))
+    }
+
+    #[tokio::test]
+    async fn test_queries_and_mutations() {
+        let schema = test_schema();
+
+        let created = schema
+            .execute(r#"mutation { createUser(name: "Ann", surname: "Lee") { id } }"#)
+            .await;
+        assert!(created.errors.is_empty(), "{:?}", created.errors);
+        let updated = schema
+            .execute(r#"mutation { updateUser(id: 1, surname: "Park") { surname } }"#)
+            .await;
+        assert!(updated.errors.is_empty(), "{:?}", updated.errors);
+
+        let res = schema
+            .execute(
+                r#"{
+                    user(id: 1) { name surname }
+                    missing: user(id: 2) { name }
+                    users(filter: "name = \"Ann\"", page: { limit: 10 }) { count users { id } }
+                }"#,
+            )
+            .await
+            .data
+            .into_json()
+            .unwrap();
+
+        assert_eq!(res["user"]["surname"], "Park");
+        assert!(res["missing"].is_null());
+        assert_eq!(res["users"]["count"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_errors_carry_codes() {
+        let res = test_schema()
+            .execute(r#"{ users(filter: "email = \"a@b\"") { count } }"#)
+            .await;
+
+        assert_eq!(res.errors.len(), 1);
+        let code = res.errors[0]
+            .extensions
+            .as_ref()
+            .and_then(|ext| ext.get("code"))
+            .cloned();
+        assert_eq!(code, Some(async_graphql::Value::from("INVALID_ARGUMENT")));
+    }
+
+    #[tokio::test]
+    async fn test_user_events_subscription() {
+        let schema = test_schema();
+        let mut events =
+            schema.execute_stream("subscription { userEvents { sequence kind user { id } } }");
+        // the subscription registers with the feed on first poll
+        let next = tokio::spawn(async move { events.next().await });
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+        schema
+            .execute(r#"mutation { createUser(name: "Ann", surname: "Lee") { id } }"#)
+            .await;
+
+        let event = next.await.unwrap().unwrap().data.into_json().unwrap();
+        assert_eq!(event["userEvents"]["kind"], "CREATED");
+        assert_eq!(event["userEvents"]["user"]["id"], 1);
+    }
+}
@@ -0,0 +1,216 @@
+use std::sync::Arc;
+
+use async_graphql::{
+    Context, Enum, ErrorExtensions, InputObject, Object, Result, Schema, SimpleObject, Subscription,
+};
+use tokio_stream::{Stream, StreamExt, wrappers::ReceiverStream};
+use tracing::error;
+
+use crate::{Error, auth::Principal, grpc, usecases::UserUsecaseTrait};
+
+const DEFAULT_PAGE_SIZE: i32 = 50;
+const MAX_PAGE_SIZE: i32 = 1000;
+
+pub type UserSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
+
+#[derive(SimpleObject, Default)]
+pub struct User {
+    id: i32,
+    name: String,
+    surname: String,
+}
+
+impl From<grpc::User> for User {
+    fn from(user: grpc::User) -> Self {
+        Self {
+            id: user.id,
+            name: user.name,
+            surname: user.surname,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct UserPage {
+    users: Vec<User>,
+    count: i32,
+}
+
+#[derive(InputObject)]
+pub struct Page {
+    #[graphql(default)]
+    offset: i32,
+    #[graphql(default = 50)]
+    limit: i32,
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+pub enum UserEventKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+#[derive(SimpleObject)]
+pub struct UserEvent {
+    sequence: u64,
+    kind: UserEventKind,
+    // deletions only carry the id
+    user: User,
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn user(&self, ctx: &Context<'_>, id: i32) -> Result<Option<User>> {
+        match usecase(ctx).get_user_by_id(caller(ctx), id).await {
+            Ok(res) => Ok(res.user.map(User::from)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(into_error(e)),
+        }
+    }
+
+    // `filter` uses the AIP-160 syntax of GetUsers
+    async fn users(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<String>,
+        page: Option<Page>,
+    ) -> Result<UserPage> {
+        let (offset, limit) = page.map_or((0, DEFAULT_PAGE_SIZE), |p| (p.offset, p.limit));
+        if offset < 0 || !(1..=MAX_PAGE_SIZE).contains(&limit) {
+            return Err(into_error(Error::InvalidArgument(format!(
+                "page offset must be >= 0 and limit between 1 and {}",
+                MAX_PAGE_SIZE
+            ))));
+        }
+
+        let res = usecase(ctx)
+            .get_users_page(caller(ctx), filter.unwrap_or_default(), offset, limit)
+            .await
+            .map_err(into_error)?;
+
+        Ok(UserPage {
+            users: res.users.into_iter().map(User::from).collect(),
+            count: res.count,
+        })
+    }
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    async fn create_user(&self, ctx: &Context<'_>, name: String, surname: String) -> Result<User> {
+        let res = usecase(ctx)
+            .create_user(caller(ctx), name, surname)
+            .await
+            .map_err(into_error)?;
+
+        res.user
+            .map(User::from)
+            .ok_or_else(|| into_error(Error::Internal("no user returned".into())))
+    }
+
+    // omitted arguments are left unchanged, an empty string clears the field
+    async fn update_user(
+        &self,
+        ctx: &Context<'_>,
+        id: i32,
+        name: Option<String>,
+        surname: Option<String>,
+    ) -> Result<User> {
+        let res = usecase(ctx)
+            .update_user(caller(ctx), id, name, surname)
+            .await
+            .map_err(into_error)?;
+
+        res.user
+            .map(User::from)
+            .ok_or_else(|| into_error(Error::NotFound))
+    }
+
+    async fn delete_user(&self, ctx: &Context<'_>, id: i32) -> Result<bool> {
+        usecase(ctx)
+            .delete_user(caller(ctx), id)
+            .await
+            .map_err(into_error)?;
+
+        Ok(true)
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    // the WatchUsers change feed
+    async fn user_events(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<impl Stream<Item = Result<UserEvent>> + use<>> {
+        let (tx, rx) = tokio::sync::mpsc::channel(128);
+        usecase(ctx)
+            .send_user_events(caller(ctx), 0, 0, None, tx)
+            .await
+            .map_err(into_error)?;
+
+        Ok(ReceiverStream::new(rx).map(|event| match event {
+            Ok(event) => user_event(event),
+            Err(status) => Err(async_graphql::Error::new(status.message())
+                .extend_with(|_, ext| ext.set("code", format!("{:?}", status.code())))),
+        }))
+    }
+}
+
+fn user_event(event: grpc::UserEvent) -> Result<UserEvent> {
+    let kind = match event.kind() {
+        grpc::UserEventKind::Created => UserEventKind::Created,
+        grpc::UserEventKind::Updated => UserEventKind::Updated,
+        grpc::UserEventKind::Deleted => UserEventKind::Deleted,
+        grpc::UserEventKind::Unspecified => {
+            return Err(into_error(Error::Internal(
+                "user event without a kind".into(),
+            )));
+        }
+    };
+
+    Ok(UserEvent {
+        sequence: event.sequence,
+        kind,
+        user: event.user.map(User::from).unwrap_or_default(),
+    })
+}
+
+fn usecase<'a>(ctx: &Context<'a>) -> &'a Arc<dyn UserUsecaseTrait> {
+    ctx.data_unchecked::<Arc<dyn UserUsecaseTrait>>()
+}
+
+fn caller<'a>(ctx: &Context<'a>) -> Option<&'a Principal> {
+    ctx.data_opt::<Principal>()
+}
+
+// same codes as the gRPC mapping in servers::into_status
+fn into_error(e: Error) -> async_graphql::Error {
+    let code = match &e {
+        Error::NotFound => "NOT_FOUND",
+        Error::InvalidArgument(_) => "INVALID_ARGUMENT",
+        Error::PermissionDenied => "PERMISSION_DENIED",
+        Error::FailedPrecondition(_) => "FAILED_PRECONDITION",
+        Error::Unavailable(_) => "UNAVAILABLE",
+        Error::Internal(_) => "INTERNAL",
+    };
+    let message = match &e {
+        Error::Internal(source) => {
+            error!("graphql request failed: {:?}", e);
+            crate::metrics::requests::record_internal_error(source.as_ref());
+            #[cfg(feature = "sentry")]
+            crate::error_reporting::report(&e);
+            "internal error".to_owned()
+        }
+        _ => e.to_string(),
+    };
+
+    async_graphql::Error::new(message).extend_with(|_, ext| ext.set("code", code))
+}
@@ -0,0 +1,449 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+use sqlx::PgPool;
+use tokio::task::JoinSet;
+use tonic_health::{ServingStatus, server::HealthReporter};
+use tracing::{info, warn};
+
+use crate::{
+    Error, backup::store::BlobStore, config::Config, metrics::registry, shutdown::Shutdown,
+};
+
+// how long one check may take before it counts as failed
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    Healthy,
+    // a dependency part of the service needs is down, the rest serves
+    Degraded,
+    // a database is down, readiness is NOT_SERVING
+    Unhealthy,
+}
+
+impl State {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Healthy => "healthy",
+            Self::Degraded => "degraded",
+            Self::Unhealthy => "unhealthy",
+        }
+    }
+}
+
+#[async_trait]
+pub trait Probe: Send + Sync {
+    async fn check(&self) -> Result<(), Error>;
+}
+
+pub struct Dependency {
+    pub name: String,
+    // "postgres", "redis", "outbox" or "blob_storage"
+    pub kind: &'static str,
+    // nothing serves without it; the others only degrade the service
+    pub critical: bool,
+    pub probe: Arc<dyn Probe>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DependencyStatus {
+    pub name: String,
+    pub kind: &'static str,
+    pub critical: bool,
+    pub healthy: bool,
+    // why the last check failed
+    pub error: Option<String>,
+    pub latency: Duration,
+    pub checked_at: SystemTime,
+    // when it last became healthy or stopped being so
+    pub changed_at: SystemTime,
+}
+
+// the dependencies the configuration uses, checked together every interval;
+// readiness follows the critical ones
+#[derive(Clone, Default)]
+pub struct DependencyHealth {
+    dependencies: Arc<Vec<Dependency>>,
+    statuses: Arc<RwLock<Vec<DependencyStatus>>>,
+}
+
+impl DependencyHealth {
+    pub fn new(dependencies: Vec<Dependency>) -> Self {
+        Self {
+            dependencies: Arc::new(dependencies),
+            statuses: Arc::default(),
+        }
+    }
+
+    // as of the last check, healthy before the first
+    pub fn statuses(&self) -> Vec<DependencyStatus> {
+        self.statuses.read().unwrap().clone()
+    }
+
+    pub fn state(&self) -> State {
+        state(&self.statuses())
+    }
+
+    pub async fn check(&self) -> State {
+        let mut checks = JoinSet::new();
+        for (i, dependency) in self.dependencies.iter().enumerate() {
+            let probe = dependency.probe.clone();
+            checks.spawn(async move {
+                let started = Instant::now();
+                let res = match tokio::time::timeout(CHECK_TIMEOUT, probe.check()).await {
+                    Ok(res) => res,
+                    Err(_) => Err(Error::Unavailable(format!(
+                        "no answer within {:?}",
+                        CHECK_TIMEOUT
+                    ))),
+                };
+                (i, res, started.elapsed())
+            });
+        }
+        let mut results = Vec::with_capacity(self.dependencies.len());
+        while let Some(result) = checks.join_next().await {
+            match result {
+                Ok(result) => results.push(result),
+                Err(e) => warn!("a dependency check panicked: {}", e),
+            }
+        }
+        results.sort_by_key(|(i, _, _)| *i);
+
+        let now = SystemTime::now();
+        let mut statuses = self.statuses.write().unwrap();
+        let previous = std::mem::take(&mut *statuses);
+        for (i, res, latency) in results {
+            let dependency = &self.dependencies[i];
+            let healthy = res.is_ok();
+            let changed_at = match previous.iter().find(|s| s.name == dependency.name) {
+                Some(last) if last.healthy == healthy => last.changed_at,
+                Some(_) => {
+                    match &res {
+                        Ok(()) => info!("{} is healthy again", dependency.name),
+                        Err(e) => warn!("{} is unhealthy: {}", dependency.name, e),
+                    }
+                    now
+                }
+                None => {
+                    if let Err(e) = &res {
+                        warn!("{} is unhealthy: {}", dependency.name, e);
+                    }
+                    now
+                }
+            };
+            registry().set_gauge(
+                "dependency_up",
+                &[("dependency", dependency.name.as_str())],
+                if healthy { 1.0 } else { 0.0 },
+            );
+            statuses.push(DependencyStatus {
+                name: dependency.name.clone(),
+                kind: dependency.kind,
+                critical: dependency.critical,
+                healthy,
+                error: res.err().map(|e| e.to_string()),
+                latency,
+                checked_at: now,
+                changed_at,
+            });
+        }
+
+        state(&statuses)
+    }
+
+    // checks every `interval` and keeps the overall health status at
+    // NOT_SERVING while a critical dependency is down. Stops at lame duck,
+    // which takes readiness down for good
+    pub fn spawn(self, interval: Duration, reporter: HealthReporter, shutdown: Shutdown) {
+        let stop = shutdown.clone();
+        shutdown.spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            let mut serving = true;
+            loop {
+                tokio::select! {
+                    _ = stop.triggered() => return,
+                    _ = stop.lame_duck_entered() => return,
+                    _ = ticker.tick() => {}
+                }
+                let ready = self.check().await != State::Unhealthy;
+                // lame duck may have started while checking
+                if ready == serving || stop.is_lame_duck() {
+                    continue;
+                }
+                serving = ready;
+                let status = if ready {
+                    ServingStatus::Serving
+                } else {
+                    ServingStatus::NotServing
+                };
+                reporter.set_service_status("", status).await;
+            }
+        });
+    }
+}
+
+fn state(statuses: &[DependencyStatus]) -> State {
+    let down = statuses.iter().filter(|s| !s.healthy);
+    let mut state = State::Healthy;
+    for status in down {
+        if status.critical {
+            return State::Unhealthy;
+        }
+        state = State::Degraded;
+    }
+
+    state
+}
+
+// what `config` connects to over the network: the postgres pools, Redis, the
+// outbox destinations and the backup storage
+pub fn dependencies(
+    config: &Config,
+    pools: Vec<(String, PgPool)>,
+    backup_store: Option<Arc<dyn BlobStore>>,
+) -> Result<Vec<Dependency>, Error> {
+    let mut dependencies: Vec<Dependency> = pools
+        .into_iter()
+        .map(|(name, pool)| Dependency {
+            name,
+            kind: "postgres",
+            critical: true,
+            probe: Arc::new(PoolProbe(pool)),
+        })
+        .collect();
+
+    let redis_urls = [
+        ("redis_cache_invalidation", &config.cache_invalidation_url),
+        ("redis_change_feed", &config.change_feed_url),
+        ("redis_nonces", &config.auth_nonce_redis_url),
+        (
+            "redis_rate_limits",
+            &config
+                .rate_limits
+                .as_ref()
+                .and_then(|r| r.redis_url.clone()),
+        ),
+    ];
+    for (name, url) in redis_urls {
+        let Some(url) = url else {
+            continue;
+        };
+        dependencies.push(Dependency {
+            name: name.to_string(),
+            kind: "redis",
+            critical: false,
+            probe: redis_probe(url)?,
+        });
+    }
+
+    let client = reqwest::Client::new();
+    for destination in &config.outbox_destinations {
+        dependencies.push(Dependency {
+            name: format!("outbox_{}", destination.name),
+            kind: "outbox",
+            critical: false,
+            probe: Arc::new(HttpProbe {
+                client: client.clone(),
+                url: destination.url.clone(),
+            }),
+        });
+    }
+
+    if let Some(store) = backup_store {
+        dependencies.push(Dependency {
+            name: "backup_storage".to_string(),
+            kind: "blob_storage",
+            critical: false,
+            probe: Arc::new(StoreProbe(store)),
+        });
+    }
+
+    Ok(dependencies)
+}
+
+struct PoolProbe(PgPool);
+
+#[async_trait]
+impl Probe for PoolProbe {
+    async fn check(&self) -> Result<(), Error> {
+        sqlx::query("SELECT 1")
+            .execute(&self.0)
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(())
+    }
+}
+
+// any answer but a 5xx means the destination is there; most only take
+// POSTs, a HEAD answering 405 is fine
+struct HttpProbe {
+    client: reqwest::Client,
+    url: String,
+}
+
+#[async_trait]
+impl Probe for HttpProbe {
+    async fn check(&self) -> Result<(), Error> {
+        let res = self
+            .client
+            .head(&self.url)
+            .timeout(CHECK_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| Error::Unavailable(e.without_url().to_string()))?;
+        if res.status().is_server_error() {
+            return Err(Error::Unavailable(format!("answered {}", res.status())));
+        }
+
+        Ok(())
+    }
+}
+
+// a store that says an object is missing is reachable
+struct StoreProbe(Arc<dyn BlobStore>);
+
+#[async_trait]
+impl Probe for StoreProbe {
+    async fn check(&self) -> Result<(), Error> {
+        match self.0.get("health-check").await {
+            Ok(_) | Err(Error::NotFound) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+fn redis_probe(url: &str) -> Result<Arc<dyn Probe>, Error> {
+    let client = redis::Client::open(url).map_err(|e| Error::Internal(Box::new(e)))?;
+    Ok(Arc::new(RedisProbe(client)))
+}
+
+// the Redis URLs only connect with the feature, startup fails before this
+#[cfg(not(feature = "redis"))]
+fn redis_probe(url: &str) -> Result<Arc<dyn Probe>, Error> {
+    Err(Error::Internal(
+        format!("{:?} requires building with the `redis` feature", url).into(),
+    ))
+}
+
+#[cfg(feature = "redis")]
+struct RedisProbe(redis::Client);
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl Probe for RedisProbe {
+    async fn check(&self) -> Result<(), Error> {
+        let mut connection = self
+            .0
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(|e| Error::Unavailable(e.to_string()))?;
+        let _pong: String = redis::cmd("PING")
+            .query_async(&mut connection)
+            .await
+            .map_err(|e| Error::Unavailable(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+
+    struct Switch(AtomicBool);
+
+    #[async_trait]
+    impl Probe for Switch {
+        async fn check(&self) -> Result<(), Error> {
+            if self.0.load(Ordering::Relaxed) {
+                Ok(())
+            } else {
+                Err(Error::Unavailable("down".to_string()))
+            }
+        }
+    }
+
+    fn dependency(name: &str, critical: bool, probe: Arc<Switch>) -> Dependency {
+        Dependency {
+            name: name.to_string(),
+            kind: "postgres",
+            critical,
+            probe,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_aggregates_dependencies() {
+        let database = Arc::new(Switch(AtomicBool::new(true)));
+        let cache = Arc::new(Switch(AtomicBool::new(true)));
+        let health = DependencyHealth::new(vec![
+            dependency("postgres", true, database.clone()),
+            dependency("redis_cache_invalidation", false, cache.clone()),
+        ]);
+        assert_eq!(health.state(), State::Healthy);
+        assert_eq!(health.check().await, State::Healthy);
+        let first = health.statuses();
+        assert_eq!(first.len(), 2);
+        assert!(first.iter().all(|s| s.healthy && s.error.is_none()));
+
+        cache.0.store(false, Ordering::Relaxed);
+        assert_eq!(health.check().await, State::Degraded);
+        let statuses = health.statuses();
+        assert_eq!(statuses[1].error.as_deref(), Some("unavailable: down"));
+        assert_eq!(statuses[1].changed_at, statuses[1].checked_at);
+        // unchanged since the first check
+        assert_eq!(statuses[0].changed_at, first[0].changed_at);
+
+        database.0.store(false, Ordering::Relaxed);
+        assert_eq!(health.check().await, State::Unhealthy);
+        database.0.store(true, Ordering::Relaxed);
+        cache.0.store(true, Ordering::Relaxed);
+        assert_eq!(health.check().await, State::Healthy);
+    }
+
+    #[sqlx::test]
+    async fn test_checks_in_the_background(pool: PgPool) {
+        let database = Arc::new(Switch(AtomicBool::new(false)));
+        let health = DependencyHealth::new(vec![
+            dependency("postgres", true, database.clone()),
+            Dependency {
+                name: "postgres_replica".to_string(),
+                kind: "postgres",
+                critical: true,
+                probe: Arc::new(PoolProbe(pool)),
+            },
+        ]);
+        let (reporter, _service) = tonic_health::server::health_reporter();
+        let shutdown = Shutdown::new();
+        health
+            .clone()
+            .spawn(Duration::from_millis(10), reporter, shutdown.clone());
+
+        for _ in 0..100 {
+            if health.state() == State::Unhealthy {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(health.state(), State::Unhealthy);
+        assert!(health.statuses()[1].healthy);
+        database.0.store(true, Ordering::Relaxed);
+        for _ in 0..100 {
+            if health.state() == State::Healthy {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(health.state(), State::Healthy);
+        shutdown.trigger();
+    }
+}
@@ -0,0 +1,64 @@
+use std::net::SocketAddr;
+
+use axum::{Json, Router, http::header, response::IntoResponse, routing::get};
+use tracing::info;
+
+use crate::{Error, client::service_config, grpc, metrics, shutdown::Shutdown};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/metrics", get(render_metrics))
+        .route("/descriptor.binpb", get(descriptor_set))
+        .route(
+            service_config::PATH,
+            get(|| async { Json(service_config::service_config()) }),
+        )
+}
+
+pub async fn serve(addr: SocketAddr, router: Router, shutdown: Shutdown) -> Result<(), Error> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+    info!("http server started at {}", addr);
+
+    axum::serve(listener, router)
+        .with_graceful_shutdown(async move { shutdown.triggered().await })
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))
+}
+
+async fn render_metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::registry().render_prometheus(),
+    )
+}
+
+// serialized google.protobuf.FileDescriptorSet, for codegen and schema registries
+async fn descriptor_set() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "application/x-protobuf")],
+        grpc::FILE_DESCRIPTOR_SET,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+
+    use super::*;
+
+    #[test]
+    fn test_descriptor_set_covers_both_versions() {
+        let set = prost_types::FileDescriptorSet::decode(grpc::FILE_DESCRIPTOR_SET).unwrap();
+        let packages: Vec<_> = set
+            .file
+            .iter()
+            .filter_map(|f| f.package.as_deref())
+            .collect();
+
+        assert!(packages.contains(&"user.v1"));
+        assert!(packages.contains(&"user.v2"));
+        assert!(packages.contains(&"google.protobuf"));
+    }
+}
@@ -0,0 +1,35 @@
+use std::{sync::Arc, time::Duration};
+
+use sqlx::PgPool;
+use tracing::{info, warn};
+
+use super::{LeaderElection, spawn_singleton};
+use crate::{repositories::UserEventRepository, shutdown::Shutdown};
+
+const LEADER_INTERVAL: Duration = Duration::from_secs(15);
+const PURGE_INTERVAL: Duration = Duration::from_secs(600);
+
+// drops the events the event store kept past `retention`, from one replica;
+// those some of `destinations` has not confirmed yet stay
+pub fn spawn(
+    pool: PgPool,
+    store: Arc<dyn UserEventRepository>,
+    retention: Duration,
+    destinations: Vec<String>,
+    shutdown: Shutdown,
+) {
+    let election = LeaderElection::new(pool, "user_events_purge");
+    spawn_singleton(election, LEADER_INTERVAL, shutdown, move || {
+        let (store, destinations) = (store.clone(), destinations.clone());
+        async move {
+            loop {
+                match store.purge(retention, &destinations).await {
+                    Ok(0) => {}
+                    Ok(purged) => info!("purged {} user events past retention", purged),
+                    Err(e) => warn!("failed to purge user events: {}", e),
+                }
+                tokio::time::sleep(PURGE_INTERVAL).await;
+            }
+        }
+    });
+}
@@ -0,0 +1,113 @@
+use sqlx::{Connection, PgConnection, PgPool};
+
+use crate::Error;
+
+// leader election over a Postgres session advisory lock named after the job.
+// The lock is held on a connection taken out of the pool, so it lives exactly
+// as long as that session: when the leader dies or loses the database, the
+// session ends, Postgres drops the lock and the next replica to try takes over
+#[derive(Clone)]
+pub struct LeaderElection {
+    pool: PgPool,
+    name: String,
+}
+
+// the session holding the lock; dropping it closes the session, which
+// releases the lock as well
+pub struct Leadership {
+    conn: PgConnection,
+}
+
+impl LeaderElection {
+    pub fn new(pool: PgPool, name: impl Into<String>) -> Self {
+        Self {
+            pool,
+            name: name.into(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // None while another session leads
+    pub async fn try_acquire(&self) -> Result<Option<Leadership>, Error> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?
+            .detach();
+        let acquired: bool =
+            sqlx::query_scalar("SELECT pg_try_advisory_lock(hashtextextended($1, 0))")
+                .bind(&self.name)
+                .fetch_one(&mut conn)
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))?;
+        if !acquired {
+            let _ = conn.close().await;
+            return Ok(None);
+        }
+
+        Ok(Some(Leadership { conn }))
+    }
+}
+
+impl Leadership {
+    // false once the session, and with it the lock, is gone; another replica
+    // may be leading by then
+    pub async fn check(&mut self) -> bool {
+        self.conn.ping().await.is_ok()
+    }
+
+    pub async fn release(self) -> Result<(), Error> {
+        self.conn
+            .close()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_one_leader_and_failover(pool: PgPool) {
+        let replica = LeaderElection::new(pool.clone(), "outbox_relay");
+        let other = LeaderElection::new(pool.clone(), "outbox_relay");
+
+        let mut leader = replica.try_acquire().await.unwrap().unwrap();
+        assert!(other.try_acquire().await.unwrap().is_none());
+        // a different job elects its own leader
+        let purge = LeaderElection::new(pool.clone(), "purge");
+        purge
+            .try_acquire()
+            .await
+            .unwrap()
+            .unwrap()
+            .release()
+            .await
+            .unwrap();
+
+        leader.release().await.unwrap();
+        leader = other.try_acquire().await.unwrap().unwrap();
+        assert!(leader.check().await);
+
+        // the leader's session dies, as it would with the replica
+        let pids: Vec<i32> =
+            sqlx::query_scalar("SELECT pid FROM pg_locks WHERE locktype = 'advisory' AND granted")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(pids.len(), 1);
+        sqlx::query("SELECT pg_terminate_backend($1)")
+            .bind(pids[0])
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert!(!leader.check().await);
+        assert!(replica.try_acquire().await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,104 @@
+use std::{future::Future, time::Duration};
+
+use tracing::{info, warn};
+
+pub mod event_purge;
+pub mod leader;
+pub mod outbox_relay;
+
+use crate::shutdown::Shutdown;
+pub use leader::{LeaderElection, Leadership};
+
+// runs `job` on exactly one replica: every replica tries to lead every
+// `interval`, the leader runs the job and checks its lock as often, and stops
+// it as soon as the lock is lost. A job that returns is started again on the
+// next try, by whichever replica leads then. The old leader only notices a lost
+// lock on its next check, so a job may overlap its successor for up to an
+// interval and must tolerate it
+pub fn spawn_singleton<F, Fut>(
+    election: LeaderElection,
+    interval: Duration,
+    shutdown: Shutdown,
+    mut job: F,
+) where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    shutdown.clone().spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = shutdown.triggered() => break,
+                _ = ticker.tick() => {}
+            }
+            let mut leadership = match election.try_acquire().await {
+                Ok(Some(leadership)) => leadership,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("failed to run for leader of {}: {}", election.name(), e);
+                    continue;
+                }
+            };
+            info!("leading {}", election.name());
+
+            let run = job();
+            tokio::pin!(run);
+            let lost = loop {
+                tokio::select! {
+                    _ = shutdown.triggered() => break false,
+                    _ = &mut run => break false,
+                    _ = ticker.tick() => {
+                        if !leadership.check().await {
+                            break true;
+                        }
+                    }
+                }
+            };
+            if lost {
+                warn!("lost the lead of {}, stopped it", election.name());
+            } else if let Err(e) = leadership.release().await {
+                // closing failed, the lock goes with the session anyway
+                warn!("failed to step down from {}: {}", election.name(), e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use sqlx::PgPool;
+
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_singleton_runs_on_one_replica(pool: PgPool) {
+        let shutdown = Shutdown::new();
+        let started = Arc::new(AtomicUsize::new(0));
+        for _ in 0..3 {
+            let started = started.clone();
+            spawn_singleton(
+                LeaderElection::new(pool.clone(), "purge"),
+                Duration::from_millis(20),
+                shutdown.clone(),
+                move || {
+                    let started = started.clone();
+                    async move {
+                        started.fetch_add(1, Ordering::SeqCst);
+                        std::future::pending::<()>().await
+                    }
+                },
+            );
+        }
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+        shutdown.trigger();
+    }
+}
@@ -0,0 +1,378 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::Serialize;
+use sqlx::PgPool;
+use tokio::task::JoinSet;
+use tracing::warn;
+
+use super::{LeaderElection, spawn_singleton};
+use crate::{
+    Error,
+    config::OutboxDestination,
+    entities::users::{DeadLetter, StoredUserEvent},
+    metrics::registry,
+    repositories::UserEventRepository,
+    shutdown::Shutdown,
+};
+
+const LEADER_INTERVAL: Duration = Duration::from_secs(15);
+const BATCH: i64 = 100;
+// how long a destination rests after an empty batch, or after a failed
+// delivery, doubled with every failure after it up to MAX_BACKOFF
+const PAUSE: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// two minutes or so of retries with the backoff
+const MAX_ATTEMPTS: u32 = 8;
+// well past the commit of any event whose token was taken before a later one
+const SETTLE: Duration = Duration::from_secs(5);
+
+// POSTs each stored user event, in token order, to every destination, at least
+// once: a destination's cursor only moves past an event once it answered 2xx,
+// and the event store keeps events until every cursor has. A redelivery
+// carries the same `Idempotency-Key`, and tokens only grow per destination,
+// so a consumer dedupes on either. An event that fails MAX_ATTEMPTS times in
+// a row goes to the dead letters, for RedeliverDeadLetter, and the relay goes
+// on with the next
+pub struct OutboxRelay {
+    client: reqwest::Client,
+    store: Arc<dyn UserEventRepository>,
+    destinations: Vec<OutboxDestination>,
+    settle: Duration,
+    max_attempts: u32,
+    // the event each destination is failing on and how many times it did;
+    // a new leader starts counting over
+    failures: Mutex<HashMap<String, (u64, u32)>>,
+}
+
+// names are not sent, a consumer that needs them reads the user, which is gone
+// once erased
+#[derive(Serialize)]
+struct Delivery<'a> {
+    token: u64,
+    kind: &'a str,
+    user_id: i32,
+}
+
+impl OutboxRelay {
+    pub fn new(store: Arc<dyn UserEventRepository>, destinations: Vec<OutboxDestination>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            store,
+            destinations,
+            settle: SETTLE,
+            max_attempts: MAX_ATTEMPTS,
+            failures: Mutex::default(),
+        }
+    }
+
+    // how many events `destination` confirmed or had dead lettered; stops at
+    // the first failure short of that, which is retried on the next call
+    pub async fn relay(&self, destination: &OutboxDestination) -> Result<usize, Error> {
+        let cursor = self.store.delivered_through(&destination.name).await?;
+        let events = self
+            .store
+            .settled_events_after(cursor, BATCH, self.settle)
+            .await?;
+
+        let mut relayed = 0;
+        for event in events {
+            let result = self.deliver(destination, &event).await;
+            let attempts = self.attempt(&destination.name, event.token, result.is_ok());
+            let outcome = match &result {
+                Ok(()) => "ok",
+                Err(_) if attempts < self.max_attempts => "error",
+                Err(_) => "dead_letter",
+            };
+            registry().increment_counter(
+                "outbox_deliveries_total",
+                &[
+                    ("destination", destination.name.as_str()),
+                    ("result", outcome),
+                ],
+                1,
+            );
+
+            match result {
+                Ok(()) => {
+                    self.store
+                        .confirm_delivery(&destination.name, event.token)
+                        .await?
+                }
+                Err(reason) if attempts < self.max_attempts => {
+                    return Err(Error::Unavailable(format!(
+                        "{} failed to take event {}: {}",
+                        destination.name, event.token, reason
+                    )));
+                }
+                Err(reason) => {
+                    warn!(
+                        "{} failed to take event {} {} times, dead lettered: {}",
+                        destination.name, event.token, attempts, reason
+                    );
+                    self.store
+                        .dead_letter(&destination.name, &event, attempts as i32, &reason)
+                        .await?;
+                }
+            }
+            relayed += 1;
+        }
+
+        Ok(relayed)
+    }
+
+    pub async fn dead_letters(
+        &self,
+        destination: Option<&str>,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<DeadLetter>, Error> {
+        self.store.dead_letters(destination, after_id, limit).await
+    }
+
+    // sends a dead letter again, to its destination as configured now, and
+    // drops it once delivered; the letter as it was on success
+    pub async fn redeliver(&self, id: i64) -> Result<DeadLetter, Error> {
+        let letter = self
+            .store
+            .dead_letter_by_id(id)
+            .await?
+            .ok_or(Error::NotFound)?;
+        let destination = self
+            .destinations
+            .iter()
+            .find(|d| d.name == letter.destination)
+            .ok_or_else(|| {
+                Error::FailedPrecondition(format!(
+                    "{} is not in OUTBOX_DESTINATIONS",
+                    letter.destination
+                ))
+            })?;
+
+        if let Err(reason) = self.deliver(destination, &letter.event).await {
+            self.store.redelivery_failed(id, &reason).await?;
+            return Err(Error::Unavailable(format!(
+                "{} failed to take event {}: {}",
+                destination.name, letter.event.token, reason
+            )));
+        }
+        self.store.remove_dead_letter(id).await?;
+
+        Ok(letter)
+    }
+
+    // the attempts on `token` so far, this one included, 0 once it went through
+    fn attempt(&self, destination: &str, token: u64, delivered: bool) -> u32 {
+        let mut failures = self.failures.lock().unwrap();
+        if delivered {
+            failures.remove(destination);
+            return 0;
+        }
+        let entry = failures.entry(destination.to_owned()).or_insert((token, 0));
+        if entry.0 != token {
+            *entry = (token, 0);
+        }
+        entry.1 += 1;
+        let attempts = entry.1;
+        if attempts >= self.max_attempts {
+            failures.remove(destination);
+        }
+        attempts
+    }
+
+    // the failure, as a dead letter keeps it
+    async fn deliver(
+        &self,
+        destination: &OutboxDestination,
+        event: &StoredUserEvent,
+    ) -> Result<(), String> {
+        self.client
+            .post(&destination.url)
+            .timeout(Duration::from_secs(10))
+            .header(
+                "Idempotency-Key",
+                format!("{}:{}", destination.name, event.token),
+            )
+            .json(&Delivery {
+                token: event.token,
+                kind: event.kind.as_str(),
+                user_id: event.user_id,
+            })
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| e.without_url().to_string())?;
+
+        Ok(())
+    }
+}
+
+// relays to every destination from one replica, each at its own pace so a
+// destination that is down holds up only itself
+pub fn spawn(pool: PgPool, relay: Arc<OutboxRelay>, shutdown: Shutdown) {
+    let election = LeaderElection::new(pool, "outbox_relay");
+    spawn_singleton(election, LEADER_INTERVAL, shutdown, move || {
+        let mut loops = JoinSet::new();
+        for destination in relay.destinations.clone() {
+            let relay = relay.clone();
+            loops.spawn(async move {
+                let mut pause = PAUSE;
+                loop {
+                    match relay.relay(&destination).await {
+                        Ok(n) if n as i64 == BATCH => continue,
+                        Ok(_) => pause = PAUSE,
+                        Err(e) => {
+                            warn!("failed to relay user events to {}: {}", destination.name, e);
+                            tokio::time::sleep(pause).await;
+                            pause = (pause * 2).min(MAX_BACKOFF);
+                            continue;
+                        }
+                    }
+                    tokio::time::sleep(PAUSE).await;
+                }
+            });
+        }
+        // dropping the set, once the lead is lost, aborts the loops
+        async move { while loops.join_next().await.is_some() {} }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{Json, Router, extract::State, http::HeaderMap, http::StatusCode, routing::post};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{entities::users::UserEventKind, repositories::user_event_repository};
+
+    #[derive(Default)]
+    struct FakeConsumer {
+        // how many requests fail before the others go through
+        failing: AtomicUsize,
+        // the Idempotency-Key of every request, the failed ones included
+        keys: Mutex<Vec<String>>,
+        received: Mutex<Vec<Value>>,
+    }
+
+    async fn fake_consumer(failing: usize) -> (OutboxDestination, Arc<FakeConsumer>) {
+        let state = Arc::new(FakeConsumer {
+            failing: AtomicUsize::new(failing),
+            ..FakeConsumer::default()
+        });
+        let router = Router::new()
+            .route(
+                "/events",
+                post(
+                    |State(consumer): State<Arc<FakeConsumer>>,
+                     headers: HeaderMap,
+                     Json(body): Json<Value>| async move {
+                        let key = headers["idempotency-key"].to_str().unwrap().to_owned();
+                        consumer.keys.lock().unwrap().push(key);
+                        let failing = consumer.failing.load(Ordering::SeqCst);
+                        if failing > 0 {
+                            consumer.failing.store(failing - 1, Ordering::SeqCst);
+                            return StatusCode::SERVICE_UNAVAILABLE;
+                        }
+                        consumer.received.lock().unwrap().push(body);
+                        StatusCode::OK
+                    },
+                ),
+            )
+            .with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        let destination = OutboxDestination {
+            name: "search".to_string(),
+            url: format!("http://{}/events", addr),
+        };
+        (destination, state)
+    }
+
+    #[sqlx::test]
+    async fn test_redelivers_until_confirmed(pool: PgPool) {
+        let store: Arc<dyn UserEventRepository> =
+            Arc::new(user_event_repository::UserEventRepository::new(pool));
+        let first = store.append(UserEventKind::Created, 1).await.unwrap();
+        let second = store.append(UserEventKind::Deleted, 1).await.unwrap();
+        let (destination, consumer) = fake_consumer(1).await;
+        let relay = OutboxRelay {
+            settle: Duration::ZERO,
+            ..OutboxRelay::new(store.clone(), vec![destination.clone()])
+        };
+
+        assert!(relay.relay(&destination).await.is_err());
+        assert_eq!(store.delivered_through("search").await.unwrap(), 0);
+
+        assert_eq!(relay.relay(&destination).await.unwrap(), 2);
+        assert_eq!(store.delivered_through("search").await.unwrap(), second);
+        assert_eq!(
+            *consumer.keys.lock().unwrap(),
+            [
+                format!("search:{}", first),
+                format!("search:{}", first),
+                format!("search:{}", second)
+            ]
+        );
+        let received = consumer.received.lock().unwrap().clone();
+        assert_eq!(received[0]["kind"], "created");
+        assert_eq!(received[1]["token"], second);
+        assert!(received[0].get("name").is_none());
+
+        // nothing new, nothing sent
+        assert_eq!(relay.relay(&destination).await.unwrap(), 0);
+        assert_eq!(consumer.keys.lock().unwrap().len(), 3);
+    }
+
+    #[sqlx::test]
+    async fn test_dead_letters_and_redelivery(pool: PgPool) {
+        let store: Arc<dyn UserEventRepository> =
+            Arc::new(user_event_repository::UserEventRepository::new(pool));
+        let first = store.append(UserEventKind::Created, 1).await.unwrap();
+        let second = store.append(UserEventKind::Created, 2).await.unwrap();
+        let (destination, consumer) = fake_consumer(2).await;
+        let relay = OutboxRelay {
+            settle: Duration::ZERO,
+            max_attempts: 2,
+            ..OutboxRelay::new(store.clone(), vec![destination.clone()])
+        };
+
+        let status = relay.relay(&destination).await.unwrap_err();
+        assert!(matches!(status, Error::Unavailable(_)));
+        // the second failure sets the first event aside and the next goes on
+        assert_eq!(relay.relay(&destination).await.unwrap(), 2);
+        assert_eq!(store.delivered_through("search").await.unwrap(), second);
+        let letters = store.dead_letters(None, 0, 10).await.unwrap();
+        assert_eq!(letters.len(), 1);
+        assert_eq!((letters[0].event.token, letters[0].attempts), (first, 2));
+        assert!(letters[0].reason.contains("503"));
+
+        // failing again, kept with one more attempt
+        consumer.failing.store(1, Ordering::SeqCst);
+        assert!(matches!(
+            relay.redeliver(letters[0].id).await,
+            Err(Error::Unavailable(_))
+        ));
+        let letter = store
+            .dead_letter_by_id(letters[0].id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(letter.attempts, 3);
+
+        assert_eq!(relay.redeliver(letter.id).await.unwrap().event.token, first);
+        assert_eq!(store.dead_letter_by_id(letter.id).await.unwrap(), None);
+        assert!(matches!(
+            relay.redeliver(letter.id).await,
+            Err(Error::NotFound)
+        ));
+        let keys = consumer.keys.lock().unwrap().clone();
+        assert_eq!(keys.last().unwrap(), &format!("search:{}", first));
+        assert_eq!(consumer.received.lock().unwrap().len(), 2);
+    }
+}
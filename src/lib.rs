@@ -1,15 +1,64 @@
 pub mod grpc {
     tonic::include_proto!("user.v1");
+
+    // user.v1 and user.v2 together with their google.protobuf imports
+    pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("user_descriptor");
+
+    pub mod v2 {
+        tonic::include_proto!("user.v2");
+    }
 }
 
+pub mod alerting;
+pub mod app;
+pub mod auth;
+pub mod backup;
+pub mod build_info;
+pub mod cache;
+pub mod cli;
+pub mod client;
+pub mod config;
+pub mod db;
 pub mod entities;
+#[cfg(feature = "sentry")]
+pub mod error_reporting;
+pub mod export;
+pub mod faults;
+pub mod filter;
+pub mod flags;
+pub mod gateway;
+#[cfg(feature = "generate")]
+pub mod generate;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+pub mod health;
+pub mod http;
+pub mod jobs;
+pub mod metrics;
+pub mod notifications;
+pub mod pii;
+pub mod ratelimit;
+pub mod redact;
+pub mod reload;
 pub mod repositories;
 pub mod servers;
+pub mod session;
+pub mod shutdown;
+pub mod telemetry;
+pub mod tls;
 pub mod usecases;
+pub mod vault;
+
+pub use app::{run, run_with_shutdown};
 
 #[derive(Debug)]
 pub enum Error {
     NotFound,
+    InvalidArgument(String),
+    PermissionDenied,
+    FailedPrecondition(String),
+    // the server is draining, callers should retry elsewhere
+    Unavailable(String),
     Internal(Box<dyn std::error::Error + Send + Sync>),
 }
 
@@ -17,6 +66,10 @@ impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::NotFound => write!(f, "resource not found"),
+            Error::InvalidArgument(msg) => write!(f, "invalid argument: {}", msg),
+            Error::PermissionDenied => write!(f, "permission denied"),
+            Error::FailedPrecondition(msg) => write!(f, "failed precondition: {}", msg),
+            Error::Unavailable(msg) => write!(f, "unavailable: {}", msg),
             Error::Internal(e) => write!(f, "internal error: {}", e),
         }
     }
@@ -0,0 +1,249 @@
+pub mod requests;
+pub mod runtime;
+pub mod statsd;
+pub mod streams;
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{LazyLock, Mutex},
+};
+
+pub const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0,
+];
+pub const COUNT_BUCKETS: &[f64] = &[1.0, 10.0, 100.0, 1_000.0, 10_000.0, 100_000.0, 1_000_000.0];
+
+static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::default);
+
+pub fn registry() -> &'static Registry {
+    &REGISTRY
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Key {
+    pub name: &'static str,
+    pub labels: Vec<(&'static str, String)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Counter(u64),
+    Gauge(f64),
+    Histogram {
+        buckets: &'static [f64],
+        counts: Vec<u64>,
+        sum: f64,
+        count: u64,
+    },
+}
+
+#[derive(Default)]
+pub struct Registry {
+    metrics: Mutex<BTreeMap<Key, Value>>,
+}
+
+impl Registry {
+    pub fn increment_counter(&self, name: &'static str, labels: &[(&'static str, &str)], by: u64) {
+        self.update(name, labels, Value::Counter(0), |value| {
+            if let Value::Counter(c) = value {
+                *c += by;
+            }
+        });
+    }
+
+    pub fn set_gauge(&self, name: &'static str, labels: &[(&'static str, &str)], to: f64) {
+        self.update(name, labels, Value::Gauge(0.0), |value| {
+            if let Value::Gauge(g) = value {
+                *g = to;
+            }
+        });
+    }
+
+    pub fn add_gauge(&self, name: &'static str, labels: &[(&'static str, &str)], delta: f64) {
+        self.update(name, labels, Value::Gauge(0.0), |value| {
+            if let Value::Gauge(g) = value {
+                *g += delta;
+            }
+        });
+    }
+
+    pub fn observe(
+        &self,
+        name: &'static str,
+        labels: &[(&'static str, &str)],
+        buckets: &'static [f64],
+        observed: f64,
+    ) {
+        let empty = Value::Histogram {
+            buckets,
+            counts: vec![0; buckets.len()],
+            sum: 0.0,
+            count: 0,
+        };
+        self.update(name, labels, empty, |value| {
+            if let Value::Histogram {
+                buckets,
+                counts,
+                sum,
+                count,
+            } = value
+            {
+                for (bound, c) in buckets.iter().zip(counts.iter_mut()) {
+                    if observed <= *bound {
+                        *c += 1;
+                    }
+                }
+                *sum += observed;
+                *count += 1;
+            }
+        });
+    }
+
+    pub fn get(&self, name: &'static str, labels: &[(&'static str, &str)]) -> Option<Value> {
+        self.metrics
+            .lock()
+            .unwrap()
+            .get(&key(name, labels))
+            .cloned()
+    }
+
+    pub fn snapshot(&self) -> Vec<(Key, Value)> {
+        self.metrics
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+        let mut last_name = "";
+
+        for (key, value) in self.snapshot() {
+            if key.name != last_name {
+                let kind = match value {
+                    Value::Counter(_) => "counter",
+                    Value::Gauge(_) => "gauge",
+                    Value::Histogram { .. } => "histogram",
+                };
+                let _ = writeln!(out, "# TYPE {} {}", key.name, kind);
+                last_name = key.name;
+            }
+
+            match value {
+                Value::Counter(c) => {
+                    let _ = writeln!(out, "{}{} {}", key.name, render_labels(&key.labels), c);
+                }
+                Value::Gauge(g) => {
+                    let _ = writeln!(out, "{}{} {}", key.name, render_labels(&key.labels), g);
+                }
+                Value::Histogram {
+                    buckets,
+                    counts,
+                    sum,
+                    count,
+                } => {
+                    for (bound, c) in buckets.iter().zip(counts) {
+                        let mut labels = key.labels.clone();
+                        labels.push(("le", bound.to_string()));
+                        let _ =
+                            writeln!(out, "{}_bucket{} {}", key.name, render_labels(&labels), c);
+                    }
+                    let mut labels = key.labels.clone();
+                    labels.push(("le", "+Inf".to_owned()));
+                    let _ = writeln!(
+                        out,
+                        "{}_bucket{} {}",
+                        key.name,
+                        render_labels(&labels),
+                        count
+                    );
+                    let _ = writeln!(
+                        out,
+                        "{}_sum{} {}",
+                        key.name,
+                        render_labels(&key.labels),
+                        sum
+                    );
+                    let _ = writeln!(
+                        out,
+                        "{}_count{} {}",
+                        key.name,
+                        render_labels(&key.labels),
+                        count
+                    );
+                }
+            }
+        }
+
+        out
+    }
+
+    fn update(
+        &self,
+        name: &'static str,
+        labels: &[(&'static str, &str)],
+        empty: Value,
+        f: impl FnOnce(&mut Value),
+    ) {
+        let mut metrics = self.metrics.lock().unwrap();
+        f(metrics.entry(key(name, labels)).or_insert(empty));
+    }
+}
+
+fn key(name: &'static str, labels: &[(&'static str, &str)]) -> Key {
+    Key {
+        name,
+        labels: labels.iter().map(|(k, v)| (*k, v.to_string())).collect(),
+    }
+}
+
+fn render_labels(labels: &[(&'static str, String)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+
+    let labels = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, v.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    format!("{{{}}}", labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counter_and_gauge() {
+        let registry = Registry::default();
+        registry.increment_counter("requests_total", &[("method", "Get")], 2);
+        registry.increment_counter("requests_total", &[("method", "Get")], 1);
+        registry.add_gauge("open", &[], 1.0);
+        registry.add_gauge("open", &[], -1.0);
+
+        assert_eq!(
+            registry.get("requests_total", &[("method", "Get")]),
+            Some(Value::Counter(3))
+        );
+        assert_eq!(registry.get("open", &[]), Some(Value::Gauge(0.0)));
+    }
+
+    #[test]
+    fn test_render_histogram() {
+        let registry = Registry::default();
+        registry.observe("duration_seconds", &[("method", "Get")], &[0.1, 1.0], 0.5);
+
+        let rendered = registry.render_prometheus();
+
+        assert!(rendered.contains("# TYPE duration_seconds histogram"));
+        assert!(rendered.contains("duration_seconds_bucket{method=\"Get\",le=\"0.1\"} 0"));
+        assert!(rendered.contains("duration_seconds_bucket{method=\"Get\",le=\"1\"} 1"));
+        assert!(rendered.contains("duration_seconds_bucket{method=\"Get\",le=\"+Inf\"} 1"));
+        assert!(rendered.contains("duration_seconds_count{method=\"Get\"} 1"));
+    }
+}
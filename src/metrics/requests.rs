@@ -0,0 +1,164 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use axum::http::{Request, Response, header::CONTENT_TYPE};
+use tonic::Code;
+use tower::{Layer, Service};
+
+use crate::metrics::registry;
+
+pub const REQUESTS_TOTAL: &str = "server_requests_total";
+pub const DB_ERRORS_TOTAL: &str = "db_errors_total";
+// by `package.Service/Method`, failed calls included
+pub const GRPC_HANDLED_TOTAL: &str = "grpc_server_handled_total";
+
+// database failures that reached a caller, counted where Error::Internal is
+// turned into a gRPC status or GraphQL error
+pub fn record_internal_error(e: &(dyn std::error::Error + 'static)) {
+    if e.downcast_ref::<sqlx::Error>().is_some() {
+        registry().increment_counter(DB_ERRORS_TOTAL, &[], 1);
+    }
+}
+
+// counts requests by protocol and whether the server failed them, and gRPC
+// calls by method; a gRPC error shows up in the headers of a trailers-only
+// response, so a stream that fails after its first message counts as ok
+#[derive(Clone, Copy, Default)]
+pub struct RequestMetricsLayer;
+
+impl<S> Layer<S> for RequestMetricsLayer {
+    type Service = RequestMetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestMetricsService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestMetricsService<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RequestMetricsService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let grpc = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("application/grpc"));
+        let method = grpc.then(|| req.uri().path().trim_start_matches('/').to_owned());
+        let res = self.inner.call(req);
+
+        Box::pin(async move {
+            let res = res.await?;
+            let failed = if grpc {
+                let code = res
+                    .headers()
+                    .get("grpc-status")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse().ok())
+                    .map_or(Code::Ok, Code::from_i32);
+                // an unknown path is not a method, nor counted as one
+                if code != Code::Unimplemented {
+                    registry().increment_counter(
+                        GRPC_HANDLED_TOTAL,
+                        &[("method", method.as_deref().unwrap_or_default())],
+                        1,
+                    );
+                }
+                // the caller's mistakes, like NotFound, are not server failures
+                matches!(
+                    code,
+                    Code::Unknown | Code::Internal | Code::Unavailable | Code::DataLoss
+                )
+            } else {
+                res.status().is_server_error()
+            };
+            registry().increment_counter(
+                REQUESTS_TOTAL,
+                &[
+                    ("protocol", if grpc { "grpc" } else { "http" }),
+                    ("result", if failed { "error" } else { "ok" }),
+                ],
+                1,
+            );
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::metrics::Value;
+
+    fn count(protocol: &str, result: &str) -> u64 {
+        match registry().get(
+            REQUESTS_TOTAL,
+            &[("protocol", protocol), ("result", result)],
+        ) {
+            Some(Value::Counter(c)) => c,
+            _ => 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_layer_counts_server_failures() {
+        let service =
+            RequestMetricsLayer.layer(tower::service_fn(|req: Request<&'static str>| async move {
+                let res = match *req.body() {
+                    "internal" => Response::builder().header("grpc-status", "13"),
+                    "not found" => Response::builder().header("grpc-status", "5"),
+                    _ => Response::builder().status(StatusCode::BAD_GATEWAY),
+                };
+                Ok::<_, std::convert::Infallible>(res.body(()).unwrap())
+            }));
+        let grpc = |body| {
+            Request::builder()
+                .uri("/user.v1.UserService/GetUserById")
+                .header(CONTENT_TYPE, "application/grpc")
+                .body(body)
+                .unwrap()
+        };
+        let handled = || match registry().get(
+            GRPC_HANDLED_TOTAL,
+            &[("method", "user.v1.UserService/GetUserById")],
+        ) {
+            Some(Value::Counter(c)) => c,
+            _ => 0,
+        };
+        let before = handled();
+        let (grpc_errors, grpc_ok, http_errors) = (
+            count("grpc", "error"),
+            count("grpc", "ok"),
+            count("http", "error"),
+        );
+
+        service.clone().oneshot(grpc("internal")).await.unwrap();
+        service.clone().oneshot(grpc("not found")).await.unwrap();
+        service.oneshot(Request::new("")).await.unwrap();
+
+        assert!(count("grpc", "error") > grpc_errors);
+        assert!(count("grpc", "ok") > grpc_ok);
+        assert!(count("http", "error") > http_errors);
+        assert!(handled() >= before + 2);
+    }
+}
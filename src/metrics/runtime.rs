@@ -0,0 +1,98 @@
+use std::{collections::HashMap, time::Duration};
+
+use tokio::runtime::{Handle, RuntimeMetrics};
+
+use crate::{metrics::registry, shutdown::Shutdown};
+
+pub fn spawn_sampler(interval: Duration, shutdown: Shutdown) {
+    let metrics = Handle::current().metrics();
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut previous_busy = HashMap::new();
+
+        loop {
+            tokio::select! {
+                _ = shutdown.triggered() => break,
+                _ = ticker.tick() => sample(&metrics, interval, &mut previous_busy),
+            }
+        }
+    });
+}
+
+fn sample(
+    metrics: &RuntimeMetrics,
+    interval: Duration,
+    previous_busy: &mut HashMap<usize, Duration>,
+) {
+    let registry = registry();
+    registry.set_gauge("tokio_workers", &[], metrics.num_workers() as f64);
+    registry.set_gauge("tokio_alive_tasks", &[], metrics.num_alive_tasks() as f64);
+    registry.set_gauge(
+        "tokio_global_queue_depth",
+        &[],
+        metrics.global_queue_depth() as f64,
+    );
+
+    for worker in 0..metrics.num_workers() {
+        let label = worker.to_string();
+        let labels = [("worker", label.as_str())];
+
+        let busy = metrics.worker_total_busy_duration(worker);
+        let busy_delta =
+            busy.saturating_sub(previous_busy.insert(worker, busy).unwrap_or_default());
+        registry.set_gauge(
+            "tokio_worker_busy_ratio",
+            &labels,
+            (busy_delta.as_secs_f64() / interval.as_secs_f64()).min(1.0),
+        );
+        registry.set_gauge(
+            "tokio_worker_park_count",
+            &labels,
+            metrics.worker_park_count(worker) as f64,
+        );
+
+        #[cfg(tokio_unstable)]
+        registry.set_gauge(
+            "tokio_worker_mean_poll_time_seconds",
+            &labels,
+            metrics.worker_mean_poll_time(worker).as_secs_f64(),
+        );
+    }
+
+    #[cfg(tokio_unstable)]
+    {
+        registry.set_gauge(
+            "tokio_blocking_threads",
+            &[],
+            metrics.num_blocking_threads() as f64,
+        );
+        registry.set_gauge(
+            "tokio_idle_blocking_threads",
+            &[],
+            metrics.num_idle_blocking_threads() as f64,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_sample_records_runtime_gauges() {
+        let metrics = Handle::current().metrics();
+
+        sample(&metrics, Duration::from_secs(1), &mut HashMap::new());
+
+        assert_eq!(
+            registry().get("tokio_workers", &[]),
+            Some(crate::metrics::Value::Gauge(2.0))
+        );
+        assert!(
+            registry()
+                .get("tokio_worker_busy_ratio", &[("worker", "1")])
+                .is_some()
+        );
+    }
+}
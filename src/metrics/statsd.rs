@@ -0,0 +1,272 @@
+use std::collections::BTreeMap;
+
+use tokio::net::UdpSocket;
+use tracing::warn;
+
+use crate::{
+    Error,
+    config::{StatsdFlavor, StatsdSettings},
+    metrics::{Key, Value, registry},
+    shutdown::Shutdown,
+};
+
+// keeps a packet under a typical 1500 byte MTU
+const MAX_PACKET: usize = 1432;
+
+// pushes the registry in StatsD line format: counters and histograms as the
+// increase since the last flush, gauges as their current value
+pub async fn spawn_exporter(settings: StatsdSettings, shutdown: Shutdown) -> Result<(), Error> {
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+    socket
+        .connect(&settings.addr)
+        .await
+        .map_err(|e| Error::Internal(format!("invalid STATSD_ADDR: {}", e).into()))?;
+    let interval = settings.flush_interval;
+    let mut exporter = Exporter::new(settings);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            let last = tokio::select! {
+                _ = shutdown.triggered() => true,
+                _ = ticker.tick() => false,
+            };
+            for packet in exporter.packets(registry().snapshot()) {
+                if let Err(e) = socket.send(packet.as_bytes()).await {
+                    warn!("failed to push metrics to statsd: {}", e);
+                    break;
+                }
+            }
+            if last {
+                break;
+            }
+        }
+    });
+
+    Ok(())
+}
+
+struct Exporter {
+    settings: StatsdSettings,
+    previous: BTreeMap<Key, Value>,
+}
+
+impl Exporter {
+    fn new(settings: StatsdSettings) -> Self {
+        Self {
+            settings,
+            previous: BTreeMap::new(),
+        }
+    }
+
+    fn packets(&mut self, snapshot: Vec<(Key, Value)>) -> Vec<String> {
+        let mut packets = Vec::new();
+        let mut packet = String::new();
+
+        for line in self.lines(snapshot) {
+            if !packet.is_empty() && packet.len() + 1 + line.len() > MAX_PACKET {
+                packets.push(std::mem::take(&mut packet));
+            }
+            if !packet.is_empty() {
+                packet.push('\n');
+            }
+            packet.push_str(&line);
+        }
+        if !packet.is_empty() {
+            packets.push(packet);
+        }
+
+        packets
+    }
+
+    fn lines(&mut self, snapshot: Vec<(Key, Value)>) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        for (key, value) in snapshot {
+            let previous = self.previous.insert(key.clone(), value.clone());
+            match (value, previous) {
+                (Value::Counter(c), previous) => {
+                    let before = match previous {
+                        Some(Value::Counter(p)) if p <= c => p,
+                        _ => 0,
+                    };
+                    if c > before {
+                        lines.push(self.line(key.name, "", &key.labels, c - before, "c"));
+                    }
+                }
+                // a leading sign would make StatsD treat the gauge as a delta
+                (Value::Gauge(g), _) => {
+                    if g < 0.0 {
+                        lines.push(self.line(key.name, "", &key.labels, 0, "g"));
+                    }
+                    lines.push(self.line(key.name, "", &key.labels, g, "g"));
+                }
+                (
+                    Value::Histogram {
+                        buckets,
+                        counts,
+                        sum,
+                        count,
+                    },
+                    previous,
+                ) => {
+                    let (before_counts, before_sum, before_count) = match previous {
+                        Some(Value::Histogram {
+                            counts, sum, count, ..
+                        }) => (counts, sum, count),
+                        _ => (vec![0; counts.len()], 0.0, 0),
+                    };
+                    if count == before_count {
+                        continue;
+                    }
+                    for ((bound, c), before) in buckets.iter().zip(&counts).zip(before_counts) {
+                        let mut labels = key.labels.clone();
+                        labels.push(("le", bound.to_string()));
+                        lines.push(self.line(key.name, "_bucket", &labels, c - before, "c"));
+                    }
+                    let mut labels = key.labels.clone();
+                    labels.push(("le", "+Inf".to_owned()));
+                    let increase = count - before_count;
+                    lines.push(self.line(key.name, "_bucket", &labels, increase, "c"));
+                    lines.push(self.line(key.name, "_sum", &key.labels, sum - before_sum, "c"));
+                    lines.push(self.line(key.name, "_count", &key.labels, increase, "c"));
+                }
+            }
+        }
+
+        lines
+    }
+
+    // plain StatsD has no tags, so labels become `.key.value` name segments
+    fn line(
+        &self,
+        name: &str,
+        suffix: &str,
+        labels: &[(&'static str, String)],
+        value: impl std::fmt::Display,
+        kind: &str,
+    ) -> String {
+        let mut line = format!("{}{}{}", self.settings.prefix, name, suffix);
+        match self.settings.flavor {
+            StatsdFlavor::Statsd => {
+                for (k, v) in labels {
+                    line += &format!(".{}.{}", k, sanitize(v, ".:|@"));
+                }
+                line += &format!(":{}|{}", value, kind);
+            }
+            StatsdFlavor::Dogstatsd => {
+                line += &format!(":{}|{}", value, kind);
+                let tags: Vec<_> = labels
+                    .iter()
+                    .map(|(k, v)| format!("{}:{}", k, sanitize(v, ",|#")))
+                    .chain(self.settings.tags.iter().cloned())
+                    .collect();
+                if !tags.is_empty() {
+                    line += &format!("|#{}", tags.join(","));
+                }
+            }
+        }
+
+        line
+    }
+}
+
+fn sanitize(value: &str, reserved: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if reserved.contains(c) || c.is_whitespace() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::metrics::Registry;
+
+    fn settings(flavor: StatsdFlavor) -> StatsdSettings {
+        StatsdSettings {
+            addr: "127.0.0.1:8125".to_string(),
+            flavor,
+            prefix: "gin_tonik.".to_string(),
+            tags: vec!["env:test".to_string()],
+            flush_interval: Duration::from_secs(10),
+        }
+    }
+
+    #[test]
+    fn test_statsd_lines_are_deltas() {
+        let registry = Registry::default();
+        let mut exporter = Exporter::new(settings(StatsdFlavor::Statsd));
+
+        registry.increment_counter("grpc_requests_total", &[("method", "Get:User")], 3);
+        registry.set_gauge("db_pool_connections", &[], 4.0);
+        assert_eq!(
+            exporter.lines(registry.snapshot()),
+            [
+                "gin_tonik.db_pool_connections:4|g",
+                "gin_tonik.grpc_requests_total.method.Get_User:3|c",
+            ]
+        );
+
+        registry.increment_counter("grpc_requests_total", &[("method", "Get:User")], 2);
+        assert_eq!(
+            exporter.lines(registry.snapshot()),
+            [
+                "gin_tonik.db_pool_connections:4|g",
+                "gin_tonik.grpc_requests_total.method.Get_User:2|c",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dogstatsd_histogram() {
+        let registry = Registry::default();
+        let mut exporter = Exporter::new(settings(StatsdFlavor::Dogstatsd));
+
+        registry.observe("duration_seconds", &[("method", "Get")], &[0.1, 1.0], 0.5);
+        let lines = exporter.lines(registry.snapshot());
+
+        assert_eq!(
+            lines,
+            [
+                "gin_tonik.duration_seconds_bucket:0|c|#method:Get,le:0.1,env:test",
+                "gin_tonik.duration_seconds_bucket:1|c|#method:Get,le:1,env:test",
+                "gin_tonik.duration_seconds_bucket:1|c|#method:Get,le:+Inf,env:test",
+                "gin_tonik.duration_seconds_sum:0.5|c|#method:Get,env:test",
+                "gin_tonik.duration_seconds_count:1|c|#method:Get,env:test",
+            ]
+        );
+        // nothing new was observed
+        assert!(exporter.lines(registry.snapshot()).is_empty());
+    }
+
+    #[test]
+    fn test_packets_stay_under_the_mtu() {
+        let registry = Registry::default();
+        let mut exporter = Exporter::new(settings(StatsdFlavor::Statsd));
+        for n in 0..200 {
+            registry.increment_counter("requests_total", &[("n", &n.to_string())], 1);
+        }
+
+        let packets = exporter.packets(registry.snapshot());
+
+        assert!(packets.len() > 1);
+        assert!(packets.iter().all(|p| p.len() <= MAX_PACKET));
+        assert_eq!(
+            packets.iter().map(|p| p.lines().count()).sum::<usize>(),
+            200
+        );
+    }
+}
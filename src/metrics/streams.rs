@@ -0,0 +1,120 @@
+use std::time::Instant;
+
+use crate::metrics::{COUNT_BUCKETS, DURATION_BUCKETS, registry};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Termination {
+    Completed,
+    ClientCancelled,
+    Error,
+    Shutdown,
+}
+
+impl Termination {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Termination::Completed => "completed",
+            Termination::ClientCancelled => "client_cancelled",
+            Termination::Error => "error",
+            Termination::Shutdown => "shutdown",
+        }
+    }
+}
+
+pub struct StreamGuard {
+    method: &'static str,
+    started: Instant,
+    sent: u64,
+    termination: Option<Termination>,
+}
+
+impl StreamGuard {
+    pub fn open(method: &'static str) -> Self {
+        registry().add_gauge("grpc_server_open_streams", &[("method", method)], 1.0);
+
+        Self {
+            method,
+            started: Instant::now(),
+            sent: 0,
+            termination: None,
+        }
+    }
+
+    pub fn message_sent(&mut self) {
+        self.sent += 1;
+        registry().increment_counter(
+            "grpc_server_stream_messages_sent_total",
+            &[("method", self.method)],
+            1,
+        );
+    }
+
+    pub fn finish(mut self, termination: Termination) {
+        self.termination = Some(termination);
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        let method = self.method;
+        let reason = self.termination.unwrap_or(Termination::Error).as_str();
+        let registry = registry();
+
+        registry.add_gauge("grpc_server_open_streams", &[("method", method)], -1.0);
+        registry.increment_counter(
+            "grpc_server_streams_total",
+            &[("method", method), ("reason", reason)],
+            1,
+        );
+        registry.observe(
+            "grpc_server_stream_messages",
+            &[("method", method)],
+            COUNT_BUCKETS,
+            self.sent as f64,
+        );
+        registry.observe(
+            "grpc_server_stream_duration_seconds",
+            &[("method", method), ("reason", reason)],
+            DURATION_BUCKETS,
+            self.started.elapsed().as_secs_f64(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Value;
+
+    #[test]
+    fn test_stream_guard_records_lifetime() {
+        let mut guard = StreamGuard::open("TestStream");
+        assert_eq!(
+            registry().get("grpc_server_open_streams", &[("method", "TestStream")]),
+            Some(Value::Gauge(1.0))
+        );
+
+        guard.message_sent();
+        guard.message_sent();
+        guard.finish(Termination::ClientCancelled);
+
+        assert_eq!(
+            registry().get("grpc_server_open_streams", &[("method", "TestStream")]),
+            Some(Value::Gauge(0.0))
+        );
+        assert_eq!(
+            registry().get(
+                "grpc_server_streams_total",
+                &[("method", "TestStream"), ("reason", "client_cancelled")]
+            ),
+            Some(Value::Counter(1))
+        );
+        assert_eq!(
+            registry().get(
+                "grpc_server_stream_messages_sent_total",
+                &[("method", "TestStream")]
+            ),
+            Some(Value::Counter(2))
+        );
+    }
+}
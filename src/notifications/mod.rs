@@ -0,0 +1,359 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::{info, warn};
+
+pub mod templates;
+
+use crate::{
+    Error,
+    config::{NotificationChannel, NotificationSettings},
+    entities::users::User,
+    metrics::registry,
+    shutdown::Shutdown,
+};
+use templates::Templates;
+
+pub const NOTIFICATIONS_TOTAL: &str = "user_notifications_total";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotificationKind {
+    UserCreated,
+    UserSuspended,
+}
+
+impl NotificationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::UserCreated => "user_created",
+            NotificationKind::UserSuspended => "user_suspended",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    pub user: User,
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send(&self, notification: &Notification) -> Result<(), Error>;
+}
+
+// leaves the names out, the log is not the place for them
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn send(&self, notification: &Notification) -> Result<(), Error> {
+        info!(
+            kind = notification.kind.as_str(),
+            user_id = notification.user.id,
+            "notification"
+        );
+        Ok(())
+    }
+}
+
+// posts to the JSON API of an email provider; the users carry no address,
+// so the configured recipients are the ones told
+pub struct EmailNotifier {
+    client: reqwest::Client,
+    templates: Arc<Templates>,
+    api_url: String,
+    api_token: Option<String>,
+    from: String,
+    to: Vec<String>,
+}
+
+impl EmailNotifier {
+    pub fn new(
+        templates: Arc<Templates>,
+        api_url: String,
+        api_token: Option<String>,
+        from: String,
+        to: Vec<String>,
+    ) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            templates,
+            api_url,
+            api_token,
+            from,
+            to,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct EmailPayload<'a> {
+    from: &'a str,
+    to: &'a [String],
+    subject: String,
+    text: String,
+}
+
+#[async_trait]
+impl Notifier for EmailNotifier {
+    async fn send(&self, notification: &Notification) -> Result<(), Error> {
+        let message = self.templates.render(notification)?;
+        post(
+            &self.client,
+            &self.api_url,
+            self.api_token.as_deref(),
+            &EmailPayload {
+                from: &self.from,
+                to: &self.to,
+                subject: message.subject,
+                text: message.text,
+            },
+        )
+        .await
+    }
+}
+
+// posts one message per recipient to the JSON API of an SMS gateway
+pub struct SmsNotifier {
+    client: reqwest::Client,
+    templates: Arc<Templates>,
+    api_url: String,
+    api_token: Option<String>,
+    to: Vec<String>,
+}
+
+impl SmsNotifier {
+    pub fn new(
+        templates: Arc<Templates>,
+        api_url: String,
+        api_token: Option<String>,
+        to: Vec<String>,
+    ) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            templates,
+            api_url,
+            api_token,
+            to,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SmsPayload<'a> {
+    to: &'a str,
+    text: &'a str,
+}
+
+#[async_trait]
+impl Notifier for SmsNotifier {
+    async fn send(&self, notification: &Notification) -> Result<(), Error> {
+        // texts have no subject line
+        let text = self.templates.render(notification)?.text;
+        for to in &self.to {
+            post(
+                &self.client,
+                &self.api_url,
+                self.api_token.as_deref(),
+                &SmsPayload { to, text: &text },
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+async fn post(
+    client: &reqwest::Client,
+    url: &str,
+    token: Option<&str>,
+    payload: &impl Serialize,
+) -> Result<(), Error> {
+    let request = client
+        .post(url)
+        .timeout(Duration::from_secs(10))
+        .json(payload);
+    let request = match token {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    };
+    request
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+    Ok(())
+}
+
+pub fn notifier(channel: &NotificationChannel, templates: Arc<Templates>) -> Arc<dyn Notifier> {
+    match channel {
+        NotificationChannel::Log => Arc::new(LogNotifier),
+        NotificationChannel::Email {
+            api_url,
+            api_token,
+            from,
+            to,
+        } => Arc::new(EmailNotifier::new(
+            templates,
+            api_url.clone(),
+            api_token.clone(),
+            from.clone(),
+            to.clone(),
+        )),
+        NotificationChannel::Sms {
+            api_url,
+            api_token,
+            to,
+        } => Arc::new(SmsNotifier::new(
+            templates,
+            api_url.clone(),
+            api_token.clone(),
+            to.clone(),
+        )),
+    }
+}
+
+// hands notifications to a background sender, so a slow or failing channel
+// never holds up or fails the call that caused them
+#[derive(Clone)]
+pub struct NotificationQueue {
+    tx: mpsc::Sender<Notification>,
+}
+
+impl NotificationQueue {
+    // what is still queued at shutdown is sent before the sender stops; fails
+    // on a template directory that cannot be read or holds a broken template
+    pub fn spawn(settings: &NotificationSettings, shutdown: &Shutdown) -> Result<Self, Error> {
+        let templates = match &settings.template_dir {
+            Some(dir) => Templates::load(dir, &settings.locale)?,
+            None => Templates::builtin(),
+        };
+        let notifier = notifier(&settings.channel, Arc::new(templates));
+
+        Ok(Self::with_notifier(notifier, settings.queue_size, shutdown))
+    }
+
+    pub fn with_notifier(
+        notifier: Arc<dyn Notifier>,
+        queue_size: usize,
+        shutdown: &Shutdown,
+    ) -> Self {
+        let (tx, mut rx) = mpsc::channel::<Notification>(queue_size.max(1));
+        let stop = shutdown.clone();
+        shutdown.spawn(async move {
+            loop {
+                let notification = tokio::select! {
+                    biased;
+                    notification = rx.recv() => match notification {
+                        Some(notification) => notification,
+                        None => break,
+                    },
+                    _ = stop.triggered() => break,
+                };
+                deliver(notifier.as_ref(), &notification).await;
+            }
+            rx.close();
+            while let Ok(notification) = rx.try_recv() {
+                deliver(notifier.as_ref(), &notification).await;
+            }
+        });
+
+        Self { tx }
+    }
+
+    pub fn push(&self, notification: Notification) {
+        let kind = notification.kind.as_str();
+        match self.tx.try_send(notification) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                warn!(
+                    "notification queue is full, dropping a {} notification",
+                    kind
+                );
+                count(kind, "dropped");
+            }
+            Err(TrySendError::Closed(_)) => count(kind, "dropped"),
+        }
+    }
+}
+
+async fn deliver(notifier: &dyn Notifier, notification: &Notification) {
+    let kind = notification.kind.as_str();
+    match notifier.send(notification).await {
+        Ok(()) => count(kind, "sent"),
+        Err(e) => {
+            warn!("failed to send a {} notification: {}", kind, e);
+            count(kind, "failed");
+        }
+    }
+}
+
+fn count(kind: &str, result: &str) {
+    registry().increment_counter(
+        NOTIFICATIONS_TOTAL,
+        &[("kind", kind), ("result", result)],
+        1,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        sent: Mutex<Vec<Notification>>,
+    }
+
+    #[async_trait]
+    impl Notifier for Recorder {
+        async fn send(&self, notification: &Notification) -> Result<(), Error> {
+            self.sent.lock().unwrap().push(notification.clone());
+            if notification.user.id == 0 {
+                return Err(Error::Internal("unreachable".into()));
+            }
+            Ok(())
+        }
+    }
+
+    fn notification(id: i32) -> Notification {
+        Notification {
+            kind: NotificationKind::UserCreated,
+            user: User {
+                id,
+                name: "Ann".to_string(),
+                surname: "Lee".to_string(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queue_sends_past_failures_and_drains_on_shutdown() {
+        let recorder = Arc::new(Recorder::default());
+        let shutdown = Shutdown::new();
+        let queue = NotificationQueue::with_notifier(recorder.clone(), 8, &shutdown);
+
+        for id in [0, 1, 2] {
+            queue.push(notification(id));
+        }
+        shutdown.trigger();
+        assert!(shutdown.drain(Duration::from_secs(1)).await);
+
+        let ids: Vec<i32> = recorder
+            .sent
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|n| n.user.id)
+            .collect();
+        assert_eq!(ids, [0, 1, 2]);
+        // the sender is gone, pushing is still harmless
+        queue.push(notification(3));
+    }
+}
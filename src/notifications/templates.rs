@@ -0,0 +1,216 @@
+use std::path::Path;
+
+use minijinja::{Environment, UndefinedBehavior, context};
+
+use crate::{
+    Error,
+    notifications::{Notification, NotificationKind},
+};
+
+// used for whatever the template directory leaves out
+const BUILTIN: &str = "builtin";
+const BUILTIN_TEMPLATES: &[(NotificationKind, &str, &str)] = &[
+    (
+        NotificationKind::UserCreated,
+        "User {{ user.id }} created",
+        "{{ user.name }} {{ user.surname }} signed up as user {{ user.id }}",
+    ),
+    (
+        NotificationKind::UserSuspended,
+        "User {{ user.id }} suspended",
+        "{{ user.name }} {{ user.surname }} (user {{ user.id }}) was suspended",
+    ),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    pub subject: String,
+    pub text: String,
+}
+
+// minijinja templates named `<locale>/<kind>.subject.txt` and
+// `<locale>/<kind>.text.txt`, e.g. `de/user_created.text.txt`, with `user`
+// (`id`, `name`, `surname`) and `kind` to render from
+pub struct Templates {
+    env: Environment<'static>,
+    // most specific first: `de-AT`, then `de`, then the builtin ones
+    locales: Vec<String>,
+}
+
+impl Templates {
+    pub fn builtin() -> Self {
+        let mut env = Environment::new();
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+        for (kind, subject, text) in BUILTIN_TEMPLATES {
+            for (part, source) in [("subject", subject), ("text", text)] {
+                env.add_template_owned(name(BUILTIN, *kind, part), source.to_string())
+                    .expect("builtin notification templates parse");
+            }
+        }
+
+        Self {
+            env,
+            locales: vec![BUILTIN.to_string()],
+        }
+    }
+
+    // reads every `<dir>/<locale>/*.txt`; a template that does not parse
+    // fails here rather than when it is first sent
+    pub fn load(dir: &Path, locale: &str) -> Result<Self, Error> {
+        let mut templates = Self::builtin();
+        let read_dir = |path: &Path| {
+            std::fs::read_dir(path).map_err(|e| {
+                Error::Internal(format!("failed to read {}: {}", path.display(), e).into())
+            })
+        };
+
+        for entry in read_dir(dir)? {
+            let path = entry.map_err(|e| Error::Internal(Box::new(e)))?.path();
+            let Some(dir_locale) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if !path.is_dir() || dir_locale == BUILTIN {
+                continue;
+            }
+            let dir_locale = dir_locale.to_string();
+            for file in read_dir(&path)? {
+                let file = file.map_err(|e| Error::Internal(Box::new(e)))?.path();
+                let Some(file_name) = file.file_name().and_then(|n| n.to_str()) else {
+                    continue;
+                };
+                if !file_name.ends_with(".txt") {
+                    continue;
+                }
+                let source = std::fs::read_to_string(&file).map_err(|e| {
+                    Error::Internal(format!("failed to read {}: {}", file.display(), e).into())
+                })?;
+                templates
+                    .env
+                    .add_template_owned(format!("{}/{}", dir_locale, file_name), source)
+                    .map_err(|e| {
+                        Error::Internal(
+                            format!("invalid notification template {}: {}", file.display(), e)
+                                .into(),
+                        )
+                    })?;
+            }
+        }
+
+        templates.locales = fallbacks(locale);
+        Ok(templates)
+    }
+
+    pub fn render(&self, notification: &Notification) -> Result<Message, Error> {
+        Ok(Message {
+            subject: self.render_part(notification, "subject")?,
+            text: self.render_part(notification, "text")?,
+        })
+    }
+
+    fn render_part(&self, notification: &Notification, part: &str) -> Result<String, Error> {
+        let template = self
+            .locales
+            .iter()
+            .find_map(|locale| {
+                self.env
+                    .get_template(&name(locale, notification.kind, part))
+                    .ok()
+            })
+            .ok_or_else(|| {
+                Error::Internal(
+                    format!("no {} template for {}", part, notification.kind.as_str()).into(),
+                )
+            })?;
+        let user = &notification.user;
+
+        template
+            .render(context! {
+                kind => notification.kind.as_str(),
+                user => context! {
+                    id => user.id,
+                    name => user.name.as_str(),
+                    surname => user.surname.as_str(),
+                },
+            })
+            .map_err(|e| Error::Internal(Box::new(e)))
+    }
+}
+
+fn name(locale: &str, kind: NotificationKind, part: &str) -> String {
+    format!("{}/{}.{}.txt", locale, kind.as_str(), part)
+}
+
+// `de-AT` falls back to `de`, every locale to the builtin templates
+fn fallbacks(locale: &str) -> Vec<String> {
+    let mut locales = Vec::new();
+    let mut tag = locale;
+    while !tag.is_empty() {
+        locales.push(tag.to_string());
+        tag = tag.rsplit_once(['-', '_']).map_or("", |(head, _)| head);
+    }
+    locales.push(BUILTIN.to_string());
+    locales
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::users::User;
+
+    fn notification(kind: NotificationKind) -> Notification {
+        Notification {
+            kind,
+            user: User {
+                id: 7,
+                name: "Ann".to_string(),
+                surname: "Lee".to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_builtin_templates() {
+        let message = Templates::builtin()
+            .render(&notification(NotificationKind::UserSuspended))
+            .unwrap();
+
+        assert_eq!(message.subject, "User 7 suspended");
+        assert_eq!(message.text, "Ann Lee (user 7) was suspended");
+    }
+
+    #[test]
+    fn test_locale_variants_fall_back() {
+        let dir = std::env::temp_dir().join(format!("gin_tonik_templates_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("de")).unwrap();
+        std::fs::write(
+            dir.join("de/user_created.subject.txt"),
+            "Neuer Benutzer {{ user.id }}\n",
+        )
+        .unwrap();
+
+        let templates = Templates::load(&dir, "de-AT").unwrap();
+        let message = templates
+            .render(&notification(NotificationKind::UserCreated))
+            .unwrap();
+
+        assert_eq!(message.subject, "Neuer Benutzer 7");
+        assert_eq!(message.text, "Ann Lee signed up as user 7");
+
+        std::fs::write(dir.join("de/user_created.text.txt"), "{{ user.email }}").unwrap();
+        let strict = Templates::load(&dir, "de").unwrap();
+        assert!(
+            strict
+                .render(&notification(NotificationKind::UserCreated))
+                .is_err()
+        );
+        std::fs::write(dir.join("de/user_created.text.txt"), "{% if %}").unwrap();
+        assert!(Templates::load(&dir, "de").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_fallbacks() {
+        assert_eq!(fallbacks("de-AT"), ["de-AT", "de", BUILTIN]);
+        assert_eq!(fallbacks(""), [BUILTIN]);
+    }
+}
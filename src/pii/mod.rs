@@ -0,0 +1,442 @@
+pub mod rotation;
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use base64::{
+    Engine,
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+};
+use hmac::{Hmac, Mac};
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use serde::{Deserialize, de::DeserializeOwned};
+use sha2::Sha256;
+use tracing::info;
+
+use crate::{
+    Error, config::PiiMasterKey, entities::pii::WrappedDataKey, repositories::PiiRepository,
+};
+
+// `pii1:<data key id>:<base64 of the nonce, ciphertext and tag>`; a stored
+// value without the prefix is plaintext from before encryption was turned on
+const PREFIX: &str = "pii1:";
+pub const DATA_KEY_LEN: usize = 32;
+
+// wraps the data keys, so only wrapped ones are ever stored
+#[async_trait]
+pub trait MasterKey: Send + Sync {
+    async fn wrap(&self, data_key: &[u8]) -> Result<Vec<u8>, Error>;
+    async fn unwrap(&self, wrapped: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+pub fn master_key(config: &PiiMasterKey) -> Result<Arc<dyn MasterKey>, Error> {
+    match config {
+        PiiMasterKey::Local(key) => Ok(Arc::new(LocalMasterKey::new(key)?)),
+        PiiMasterKey::Transit { url, key, token } => Ok(Arc::new(TransitMasterKey::new(
+            url.clone(),
+            key.clone(),
+            token.clone(),
+        ))),
+    }
+}
+
+// PII_MASTER_KEY, AES-256-GCM under a key from the environment
+pub struct LocalMasterKey {
+    key: LessSafeKey,
+}
+
+impl LocalMasterKey {
+    pub fn new(key: &[u8]) -> Result<Self, Error> {
+        Ok(Self {
+            key: aead_key(key)?,
+        })
+    }
+}
+
+#[async_trait]
+impl MasterKey for LocalMasterKey {
+    async fn wrap(&self, data_key: &[u8]) -> Result<Vec<u8>, Error> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        Ok(seal(&self.key, nonce, b"pii data key", data_key))
+    }
+
+    async fn unwrap(&self, wrapped: &[u8]) -> Result<Vec<u8>, Error> {
+        open(&self.key, b"pii data key", wrapped)
+            .ok_or_else(|| Error::Internal("failed to unwrap a PII data key".into()))
+    }
+}
+
+// PII_TRANSIT_URL, a Vault transit key that never leaves Vault
+pub struct TransitMasterKey {
+    client: reqwest::Client,
+    url: String,
+    key: String,
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct TransitResponse<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct Encrypted {
+    ciphertext: String,
+}
+
+#[derive(Deserialize)]
+struct Decrypted {
+    plaintext: String,
+}
+
+impl TransitMasterKey {
+    pub fn new(url: String, key: String, token: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+            key,
+            token,
+        }
+    }
+
+    async fn call<T: DeserializeOwned>(
+        &self,
+        operation: &str,
+        body: serde_json::Value,
+    ) -> Result<T, Error> {
+        let url = format!(
+            "{}/{}/{}",
+            self.url.trim_end_matches('/'),
+            operation,
+            self.key
+        );
+        let response: TransitResponse<T> = self
+            .client
+            .post(url)
+            .timeout(Duration::from_secs(10))
+            .header("X-Vault-Token", &self.token)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| Error::Internal(Box::new(e)))?
+            .json()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(response.data)
+    }
+}
+
+#[async_trait]
+impl MasterKey for TransitMasterKey {
+    async fn wrap(&self, data_key: &[u8]) -> Result<Vec<u8>, Error> {
+        let encrypted: Encrypted = self
+            .call(
+                "encrypt",
+                serde_json::json!({ "plaintext": STANDARD.encode(data_key) }),
+            )
+            .await?;
+        Ok(encrypted.ciphertext.into_bytes())
+    }
+
+    async fn unwrap(&self, wrapped: &[u8]) -> Result<Vec<u8>, Error> {
+        let ciphertext = String::from_utf8_lossy(wrapped);
+        let decrypted: Decrypted = self
+            .call("decrypt", serde_json::json!({ "ciphertext": ciphertext }))
+            .await?;
+        STANDARD
+            .decode(decrypted.plaintext)
+            .map_err(|e| Error::Internal(Box::new(e)))
+    }
+}
+
+struct DataKey {
+    seal: LessSafeKey,
+    // derives the nonce from the plaintext
+    nonce: Hmac<Sha256>,
+}
+
+// encrypts names and surnames deterministically: equal plaintexts under one
+// data key give equal ciphertexts, which keeps them findable by equality at
+// the cost of showing which stored values are equal
+pub struct PiiCipher {
+    keys: BTreeMap<i32, DataKey>,
+    active: i32,
+}
+
+impl PiiCipher {
+    // unwraps every data key, creating the first one on a database that has none
+    pub async fn load(master: &dyn MasterKey, repo: &dyn PiiRepository) -> Result<Self, Error> {
+        let mut wrapped = repo.data_keys().await?;
+        if wrapped.is_empty() {
+            let data_key: [u8; DATA_KEY_LEN] = rand::random();
+            let id = repo.add_data_key(master.wrap(&data_key).await?).await?;
+            info!("created PII data key {}", id);
+            // another replica starting at the same time may have added one too
+            wrapped = repo.data_keys().await?;
+        }
+        Self::unwrap_keys(master, wrapped).await
+    }
+
+    // the newest active key encrypts
+    async fn unwrap_keys(
+        master: &dyn MasterKey,
+        wrapped: Vec<WrappedDataKey>,
+    ) -> Result<Self, Error> {
+        let active = wrapped.iter().rev().find(|k| k.active).map(|k| k.id);
+        let mut keys = Vec::with_capacity(wrapped.len());
+        for key in wrapped {
+            keys.push((key.id, master.unwrap(&key.wrapped_key).await?));
+        }
+
+        let mut cipher = Self::from_keys(keys)?;
+        if let Some(active) = active {
+            cipher.active = active;
+        }
+        Ok(cipher)
+    }
+
+    // unwrapped data keys by id; the highest id encrypts
+    pub fn from_keys(keys: Vec<(i32, Vec<u8>)>) -> Result<Self, Error> {
+        let mut data_keys = BTreeMap::new();
+        for (id, key) in keys {
+            let subkey = |label: &[u8]| {
+                let mut mac = Hmac::<Sha256>::new_from_slice(&key).expect("any key length");
+                mac.update(label);
+                mac.finalize().into_bytes()
+            };
+            data_keys.insert(
+                id,
+                DataKey {
+                    seal: aead_key(&subkey(b"pii seal"))?,
+                    nonce: Hmac::new_from_slice(&subkey(b"pii nonce")).expect("any key length"),
+                },
+            );
+        }
+        let Some(active) = data_keys.keys().next_back().copied() else {
+            return Err(Error::Internal("no PII data key".into()));
+        };
+
+        Ok(Self {
+            keys: data_keys,
+            active,
+        })
+    }
+
+    pub fn active_key(&self) -> i32 {
+        self.active
+    }
+
+    // whether `wrapped` holds no key this cipher lacks, and activates none
+    // other than its active one
+    fn covers(&self, wrapped: &[WrappedDataKey]) -> bool {
+        let active = wrapped.iter().rev().find(|k| k.active).map(|k| k.id);
+        wrapped.iter().all(|k| self.keys.contains_key(&k.id))
+            && active.is_none_or(|id| id == self.active)
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        self.encrypt_with(self.active, plaintext)
+    }
+
+    fn encrypt_with(&self, id: i32, plaintext: &str) -> String {
+        let key = &self.keys[&id];
+        let mut mac = key.nonce.clone();
+        mac.update(plaintext.as_bytes());
+        let mut nonce = [0; NONCE_LEN];
+        nonce.copy_from_slice(&mac.finalize().into_bytes()[..NONCE_LEN]);
+
+        let sealed = seal(&key.seal, nonce, aad(id).as_bytes(), plaintext.as_bytes());
+        format!("{}{}:{}", PREFIX, id, URL_SAFE_NO_PAD.encode(sealed))
+    }
+
+    // plaintext stored before encryption was turned on comes back as it is
+    pub fn decrypt(&self, stored: &str) -> Result<String, Error> {
+        let Some(rest) = stored.strip_prefix(PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let invalid = || Error::Internal("failed to decrypt a stored PII value".into());
+        let (id, sealed) = rest.split_once(':').ok_or_else(invalid)?;
+        let id: i32 = id.parse().map_err(|_| invalid())?;
+        let key = self
+            .keys
+            .get(&id)
+            .ok_or_else(|| Error::Internal(format!("PII data key {} is not known", id).into()))?;
+        let sealed = URL_SAFE_NO_PAD.decode(sealed).map_err(|_| invalid())?;
+        let plaintext = open(&key.seal, aad(id).as_bytes(), &sealed).ok_or_else(invalid)?;
+
+        String::from_utf8(plaintext).map_err(|_| invalid())
+    }
+
+    // every form `plaintext` may be stored in: under each data key, and as it
+    // is in rows not encrypted yet
+    pub fn candidates(&self, plaintext: &str) -> Vec<String> {
+        let mut candidates: Vec<String> = self
+            .keys
+            .keys()
+            .map(|id| self.encrypt_with(*id, plaintext))
+            .collect();
+        candidates.push(plaintext.to_string());
+        candidates
+    }
+
+    // what a stored value should become under the active key, None when it
+    // is encrypted with it already
+    pub fn reencrypt(&self, stored: &str) -> Result<Option<String>, Error> {
+        let current = format!("{}{}:", PREFIX, self.active);
+        if stored.starts_with(&current) {
+            return Ok(None);
+        }
+        Ok(Some(self.encrypt(&self.decrypt(stored)?)))
+    }
+}
+
+// the cipher in use, replaced when a rotation adds or activates a data key
+pub struct PiiKeyring {
+    cipher: RwLock<Arc<PiiCipher>>,
+}
+
+impl PiiKeyring {
+    pub fn new(cipher: PiiCipher) -> Self {
+        Self {
+            cipher: RwLock::new(Arc::new(cipher)),
+        }
+    }
+
+    pub fn current(&self) -> Arc<PiiCipher> {
+        self.cipher.read().unwrap().clone()
+    }
+
+    // unwraps the data keys again when one was added or activated; whether
+    // the cipher changed
+    pub async fn reload(
+        &self,
+        master: &dyn MasterKey,
+        repo: &dyn PiiRepository,
+    ) -> Result<bool, Error> {
+        let wrapped = repo.data_keys().await?;
+        if self.current().covers(&wrapped) {
+            return Ok(false);
+        }
+
+        let cipher = PiiCipher::unwrap_keys(master, wrapped).await?;
+        info!(
+            "loaded the PII data keys, encrypting with data key {}",
+            cipher.active_key()
+        );
+        *self.cipher.write().unwrap() = Arc::new(cipher);
+        Ok(true)
+    }
+}
+
+fn aad(id: i32) -> String {
+    format!("{}{}", PREFIX, id)
+}
+
+fn aead_key(key: &[u8]) -> Result<LessSafeKey, Error> {
+    let key = UnboundKey::new(&AES_256_GCM, key)
+        .map_err(|_| Error::Internal(format!("a PII key must be {} bytes", DATA_KEY_LEN).into()))?;
+    Ok(LessSafeKey::new(key))
+}
+
+// the nonce followed by the ciphertext and tag
+fn seal(key: &LessSafeKey, nonce: [u8; NONCE_LEN], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut in_out = plaintext.to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(aad),
+        &mut in_out,
+    )
+    .expect("plaintext fits AES-GCM");
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&in_out);
+    sealed
+}
+
+fn open(key: &LessSafeKey, aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+    let mut in_out = ciphertext.to_vec();
+    let plaintext = key.open_in_place(nonce, Aad::from(aad), &mut in_out).ok()?;
+
+    Some(plaintext.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher(ids: &[i32]) -> PiiCipher {
+        PiiCipher::from_keys(ids.iter().map(|id| (*id, vec![*id as u8; 32])).collect()).unwrap()
+    }
+
+    #[test]
+    fn test_encrypts_deterministically_per_key() {
+        let old = cipher(&[1]);
+        let rotated = cipher(&[1, 2]);
+
+        let stored = old.encrypt("Ann");
+        assert!(stored.starts_with("pii1:1:"));
+        assert_eq!(old.encrypt("Ann"), stored);
+        assert_ne!(old.encrypt("Bob"), stored);
+        assert_eq!(rotated.decrypt(&stored).unwrap(), "Ann");
+        assert_eq!(rotated.decrypt("Ann").unwrap(), "Ann");
+        assert_eq!(
+            rotated.candidates("Ann"),
+            [stored.clone(), rotated.encrypt("Ann"), "Ann".to_string()]
+        );
+
+        assert_eq!(old.reencrypt(&stored).unwrap(), None);
+        let reencrypted = rotated.reencrypt(&stored).unwrap().unwrap();
+        assert!(reencrypted.starts_with("pii1:2:"));
+        assert!(cipher(&[2]).decrypt(&stored).is_err());
+        assert!(old.decrypt(&stored.replace("pii1:1:", "pii1:1:A")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_local_master_key_wraps() {
+        let master = LocalMasterKey::new(&[7; 32]).unwrap();
+
+        let wrapped = master.wrap(b"data key").await.unwrap();
+        assert_ne!(wrapped, b"data key");
+        assert_eq!(master.unwrap(&wrapped).await.unwrap(), b"data key");
+        let other = LocalMasterKey::new(&[8; 32]).unwrap();
+        assert!(other.unwrap(&wrapped).await.is_err());
+        assert!(LocalMasterKey::new(&[7; 16]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pending_key_decrypts_but_does_not_encrypt() {
+        let master = LocalMasterKey::new(&[7; 32]).unwrap();
+        let mut wrapped = Vec::new();
+        for (id, active) in [(1, true), (2, false)] {
+            wrapped.push(WrappedDataKey {
+                id,
+                wrapped_key: master.wrap(&[id as u8; 32]).await.unwrap(),
+                active,
+            });
+        }
+
+        let loaded = PiiCipher::unwrap_keys(&master, wrapped.clone())
+            .await
+            .unwrap();
+        assert_eq!(loaded.active_key(), 1);
+        assert!(loaded.encrypt("Ann").starts_with("pii1:1:"));
+        assert_eq!(
+            loaded.decrypt(&cipher(&[1, 2]).encrypt("Ann")).unwrap(),
+            "Ann"
+        );
+        assert!(loaded.covers(&wrapped));
+        wrapped[1].active = true;
+        assert!(!loaded.covers(&wrapped));
+    }
+}
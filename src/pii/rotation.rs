@@ -0,0 +1,465 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tracing::{info, warn};
+
+use super::{DATA_KEY_LEN, MasterKey, PiiKeyring};
+use crate::{
+    Error,
+    entities::pii::{KeyRotation, RotationState},
+    repositories::PiiRepository,
+    shutdown::Shutdown,
+};
+
+pub const MAX_BATCH_SIZE: u32 = 10_000;
+// the longest a batch may be throttled for, well within the lease
+const MAX_BATCH_PAUSE: Duration = Duration::from_secs(60);
+const MIN_LEASE: Duration = Duration::from_secs(120);
+
+// rotates the data key encrypting names and surnames. A rotation adds a data
+// key that only decrypts, waits a reload interval for every replica to load
+// it, activates it, waits another interval for every replica to encrypt with
+// it, then re-encrypts the users in batches. Any replica can run a rotation:
+// the one holding its lease does, and another takes over once it expires
+pub struct KeyRotator {
+    keyring: Arc<PiiKeyring>,
+    master: Arc<dyn MasterKey>,
+    repo: Arc<dyn PiiRepository>,
+    reload_interval: Duration,
+    owner: String,
+}
+
+impl KeyRotator {
+    pub fn new(
+        keyring: Arc<PiiKeyring>,
+        master: Arc<dyn MasterKey>,
+        repo: Arc<dyn PiiRepository>,
+        reload_interval: Duration,
+    ) -> Self {
+        Self {
+            keyring,
+            master,
+            repo,
+            reload_interval,
+            owner: format!("{:016x}", rand::random::<u64>()),
+        }
+    }
+
+    // renewed after every batch and every wait
+    fn lease(&self) -> Duration {
+        (self.reload_interval * 3).max(MIN_LEASE)
+    }
+
+    pub async fn start(
+        &self,
+        batch_size: u32,
+        max_users_per_second: Option<u32>,
+    ) -> Result<KeyRotation, Error> {
+        if batch_size == 0 || batch_size > MAX_BATCH_SIZE {
+            return Err(Error::InvalidArgument(format!(
+                "batch_size must be 1 to {}",
+                MAX_BATCH_SIZE
+            )));
+        }
+        if let Some(rate) = max_users_per_second {
+            if rate == 0 || rate > i32::MAX as u32 {
+                return Err(Error::InvalidArgument(
+                    "max_users_per_second must be positive".to_string(),
+                ));
+            }
+            if Duration::from_secs_f64(batch_size as f64 / rate as f64) > MAX_BATCH_PAUSE {
+                return Err(Error::InvalidArgument(format!(
+                    "a batch of {} users at {} per second takes over {:?}",
+                    batch_size, rate, MAX_BATCH_PAUSE
+                )));
+            }
+        }
+
+        let data_key: [u8; DATA_KEY_LEN] = rand::random();
+        let rotation = self
+            .repo
+            .start_rotation(
+                self.master.wrap(&data_key).await?,
+                batch_size,
+                max_users_per_second,
+            )
+            .await?;
+        info!(
+            "started PII key rotation {} to data key {}",
+            rotation.id, rotation.data_key_id
+        );
+        Ok(rotation)
+    }
+
+    pub async fn rotation(&self, id: i32) -> Result<Option<KeyRotation>, Error> {
+        self.repo.rotation(id).await
+    }
+
+    pub async fn latest_rotation(&self) -> Result<Option<KeyRotation>, Error> {
+        self.repo.latest_rotation().await
+    }
+
+    // runs `rotation` to the end from where it stopped; None when another
+    // owner holds it, or on shutdown
+    pub async fn run(
+        &self,
+        rotation: KeyRotation,
+        shutdown: &Shutdown,
+    ) -> Result<Option<KeyRotation>, Error> {
+        let id = rotation.id;
+        if !self.claim(id).await? {
+            return Ok(None);
+        }
+        info!(
+            "running PII key rotation {} to data key {}",
+            id, rotation.data_key_id
+        );
+
+        if rotation.state == RotationState::Pending {
+            // each replica finds what the new key encrypts before any uses it
+            if !pause(self.reload_interval, shutdown).await
+                || !self.claim(id).await?
+                || !self.repo.activate_rotation_key(id, &self.owner).await?
+            {
+                return Ok(None);
+            }
+        }
+        self.keyring
+            .reload(self.master.as_ref(), self.repo.as_ref())
+            .await?;
+        let cipher = self.keyring.current();
+        if cipher.active_key() != rotation.data_key_id {
+            return Err(Error::Internal(
+                format!("PII data key {} is not active", rotation.data_key_id).into(),
+            ));
+        }
+        // and no replica writes under the old key behind the batches
+        if !pause(self.reload_interval, shutdown).await {
+            return Ok(None);
+        }
+
+        let batch_size = rotation.batch_size as i32;
+        let mut after_id = rotation.after_user_id;
+        loop {
+            if !self.claim(id).await? {
+                return Ok(None);
+            }
+            let started = Instant::now();
+            let ids = self.repo.user_ids_after(after_id, batch_size).await?;
+            let rewritten = if ids.is_empty() {
+                0
+            } else {
+                self.repo
+                    .recode_users(&ids, &|stored: &str| cipher.reencrypt(stored))
+                    .await?
+            };
+            after_id = ids.last().copied().unwrap_or(after_id);
+            let done = ids.len() < rotation.batch_size as usize;
+            if !self
+                .repo
+                .record_rotation_progress(id, &self.owner, after_id, rewritten, done)
+                .await?
+            {
+                return Ok(None);
+            }
+            if done {
+                break;
+            }
+
+            if let Some(rate) = rotation.max_users_per_second {
+                let budget = Duration::from_secs_f64(ids.len() as f64 / rate as f64);
+                if !pause(budget.saturating_sub(started.elapsed()), shutdown).await {
+                    return Ok(None);
+                }
+            }
+        }
+
+        let rotation = self.repo.rotation(id).await?.ok_or(Error::NotFound)?;
+        info!(
+            "finished PII key rotation {}, rewrote {} values",
+            id, rotation.values_rewritten
+        );
+        Ok(Some(rotation))
+    }
+
+    async fn claim(&self, id: i32) -> Result<bool, Error> {
+        self.repo
+            .claim_rotation(id, &self.owner, self.lease())
+            .await
+    }
+
+    // reloads the data keys every interval, and runs an unfinished rotation
+    // whose owner stopped renewing it
+    pub fn spawn(self: Arc<Self>, shutdown: Shutdown) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.reload_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                tokio::select! {
+                    _ = shutdown.triggered() => break,
+                    _ = ticker.tick() => {}
+                }
+                if let Err(e) = self
+                    .keyring
+                    .reload(self.master.as_ref(), self.repo.as_ref())
+                    .await
+                {
+                    warn!("failed to reload the PII data keys: {}", e);
+                }
+                // no key is added while a rotation runs, so pausing the
+                // reloads for it misses nothing
+                match self.repo.latest_rotation().await {
+                    Ok(Some(rotation)) if rotation.state != RotationState::Done => {
+                        let id = rotation.id;
+                        if let Err(e) = self.run(rotation, &shutdown).await {
+                            warn!("PII key rotation {} failed: {}", id, e);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("failed to check for a PII key rotation: {}", e),
+                }
+            }
+        });
+    }
+}
+
+// false when shut down first
+async fn pause(duration: Duration, shutdown: &Shutdown) -> bool {
+    tokio::select! {
+        _ = shutdown.triggered() => false,
+        _ = tokio::time::sleep(duration) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, sync::Mutex, time::SystemTime};
+
+    use async_trait::async_trait;
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::{
+        entities::pii::WrappedDataKey,
+        pii::{LocalMasterKey, PiiCipher},
+        repositories::{
+            UserRepository as _, encrypted_user_repository::EncryptedUserRepository,
+            pii_repository::PiiRepository as PgPiiRepository, pii_repository_trait::Recode,
+            user_repository::UserRepository,
+        },
+    };
+
+    // the data keys, users and one rotation, recording each batch's progress
+    #[derive(Default)]
+    struct MemoryPii {
+        keys: Vec<WrappedDataKey>,
+        users: Mutex<BTreeMap<i32, (String, String)>>,
+        rotation: Mutex<Option<KeyRotation>>,
+        progress: Mutex<Vec<(i32, u64, bool)>>,
+    }
+
+    #[async_trait]
+    impl PiiRepository for MemoryPii {
+        async fn data_keys(&self) -> Result<Vec<WrappedDataKey>, Error> {
+            Ok(self.keys.clone())
+        }
+
+        async fn add_data_key(&self, _wrapped_key: Vec<u8>) -> Result<i32, Error> {
+            unimplemented!()
+        }
+
+        async fn user_ids_after(&self, after_id: i32, limit: i32) -> Result<Vec<i32>, Error> {
+            let users = self.users.lock().unwrap();
+            Ok(users
+                .range(after_id + 1..)
+                .map(|(id, _)| *id)
+                .take(limit as usize)
+                .collect())
+        }
+
+        async fn recode_users(&self, ids: &[i32], recode: &Recode<'_>) -> Result<u64, Error> {
+            let mut users = self.users.lock().unwrap();
+            let mut changed = 0;
+            for id in ids {
+                let (name, surname) = users.get_mut(id).ok_or(Error::NotFound)?;
+                for value in [name, surname] {
+                    if let Some(recoded) = recode(value)? {
+                        *value = recoded;
+                        changed += 1;
+                    }
+                }
+            }
+            Ok(changed)
+        }
+
+        async fn start_rotation(
+            &self,
+            _wrapped_key: Vec<u8>,
+            _batch_size: u32,
+            _max_users_per_second: Option<u32>,
+        ) -> Result<KeyRotation, Error> {
+            unimplemented!()
+        }
+
+        async fn rotation(&self, id: i32) -> Result<Option<KeyRotation>, Error> {
+            let rotation = self.rotation.lock().unwrap();
+            Ok(rotation.clone().filter(|r| r.id == id))
+        }
+
+        async fn latest_rotation(&self) -> Result<Option<KeyRotation>, Error> {
+            Ok(self.rotation.lock().unwrap().clone())
+        }
+
+        async fn claim_rotation(
+            &self,
+            _id: i32,
+            _owner: &str,
+            _lease: Duration,
+        ) -> Result<bool, Error> {
+            Ok(true)
+        }
+
+        async fn activate_rotation_key(&self, _id: i32, _owner: &str) -> Result<bool, Error> {
+            unimplemented!()
+        }
+
+        async fn record_rotation_progress(
+            &self,
+            _id: i32,
+            _owner: &str,
+            after_user_id: i32,
+            values_rewritten: u64,
+            done: bool,
+        ) -> Result<bool, Error> {
+            self.progress
+                .lock()
+                .unwrap()
+                .push((after_user_id, values_rewritten, done));
+            let mut rotation = self.rotation.lock().unwrap();
+            let rotation = rotation.as_mut().ok_or(Error::NotFound)?;
+            rotation.after_user_id = after_user_id;
+            rotation.values_rewritten += values_rewritten;
+            if done {
+                rotation.state = RotationState::Done;
+            }
+            Ok(true)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotation_rewrites_in_batches() {
+        let master: Arc<dyn MasterKey> = Arc::new(LocalMasterKey::new(&[7; 32]).unwrap());
+        let old = PiiCipher::from_keys(vec![(1, vec![1; 32])]).unwrap();
+        let mut keys = Vec::new();
+        for id in [1, 2] {
+            keys.push(WrappedDataKey {
+                id,
+                wrapped_key: master.wrap(&[id as u8; 32]).await.unwrap(),
+                active: true,
+            });
+        }
+        let users = [(1, "Ann"), (2, "Bob"), (3, "Cy")]
+            .into_iter()
+            .map(|(id, name)| (id, (old.encrypt(name), old.encrypt("Lee"))))
+            .collect();
+        let now = SystemTime::now();
+        let rotation = KeyRotation {
+            id: 1,
+            data_key_id: 2,
+            state: RotationState::Rewriting,
+            batch_size: 2,
+            max_users_per_second: None,
+            after_user_id: 0,
+            values_rewritten: 0,
+            started_at: now,
+            updated_at: now,
+            finished_at: None,
+        };
+        let repo = Arc::new(MemoryPii {
+            keys,
+            users: Mutex::new(users),
+            rotation: Mutex::new(Some(rotation.clone())),
+            ..Default::default()
+        });
+        let keyring = Arc::new(PiiKeyring::new(old));
+
+        let rotator = KeyRotator::new(keyring.clone(), master, repo.clone(), Duration::ZERO);
+        let done = rotator
+            .run(rotation, &Shutdown::new())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(done.state, RotationState::Done);
+        assert_eq!(done.values_rewritten, 6);
+        // a full batch, then the short one that finishes the rotation
+        assert_eq!(
+            *repo.progress.lock().unwrap(),
+            [(2, 4, false), (3, 2, true)]
+        );
+
+        let cipher = keyring.current();
+        assert_eq!(cipher.active_key(), 2);
+        for (name, surname) in repo.users.lock().unwrap().values() {
+            assert!(name.starts_with("pii1:2:") && surname.starts_with("pii1:2:"));
+            assert_eq!(cipher.decrypt(surname).unwrap(), "Lee");
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_rotation_reencrypts_every_user(pool: PgPool) {
+        let master: Arc<dyn MasterKey> = Arc::new(LocalMasterKey::new(&[7; 32]).unwrap());
+        let repo: Arc<dyn PiiRepository> = Arc::new(PgPiiRepository::new(pool.clone()));
+        let cipher = PiiCipher::load(master.as_ref(), repo.as_ref())
+            .await
+            .unwrap();
+        let keyring = Arc::new(PiiKeyring::new(cipher));
+        let users = EncryptedUserRepository::new(UserRepository::new(pool.clone()))
+            .with_keyring(keyring.clone());
+        for name in ["Ann", "Bob", "Cy"] {
+            users
+                .create_user(name.to_string(), "Lee".to_string())
+                .await
+                .unwrap();
+        }
+
+        let rotator = KeyRotator::new(keyring.clone(), master, repo, Duration::ZERO);
+        let rotation = rotator.start(2, Some(1000)).await.unwrap();
+        assert_eq!(rotation.state, RotationState::Pending);
+        assert!(matches!(
+            rotator.start(2, None).await,
+            Err(Error::FailedPrecondition(_))
+        ));
+        // nothing encrypts with the new key until the rotation activates it
+        assert_ne!(keyring.current().active_key(), rotation.data_key_id);
+
+        let done = rotator
+            .run(rotation.clone(), &Shutdown::new())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(done.state, RotationState::Done);
+        // the users and their one version each
+        assert_eq!(done.values_rewritten, 6);
+        assert!(done.finished_at.is_some());
+        assert_eq!(keyring.current().active_key(), rotation.data_key_id);
+
+        let stored: Vec<(String,)> = sqlx::query_as("SELECT name FROM users ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        let prefix = format!("pii1:{}:", rotation.data_key_id);
+        assert!(stored.iter().all(|(name,)| name.starts_with(&prefix)));
+        assert!(
+            users
+                .get_user_by_name("Bob".to_string())
+                .await
+                .unwrap()
+                .is_some()
+        );
+        // a finished rotation is not run again
+        assert_eq!(rotator.run(done, &Shutdown::new()).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,514 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    http::{Request, Response},
+};
+use tonic::{Status, metadata::MetadataValue};
+use tower::{Layer, Service};
+use tracing::warn;
+
+use crate::{
+    Error,
+    entities::api_keys::{ApiKeyLimits, UsagePeriod, UsageWindow},
+    metrics::registry,
+    repositories::ApiKeyRepository,
+};
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+// seconds until the exhausted window resets, and when that is in RFC 3339;
+// the gateway passes both on as HTTP headers
+pub const RETRY_AFTER_HEADER: &str = "retry-after";
+pub const QUOTA_RESET_HEADER: &str = "x-quota-reset";
+pub const RATE_LIMITED_TOTAL: &str = "rate_limited_requests_total";
+
+// unknown keys are cached too, so a caller sending random keys cannot grow
+// the cache without bound
+const MAX_CACHED_KEYS: usize = 10_000;
+
+#[async_trait]
+pub trait UsageCounter: Send + Sync {
+    // counts one request against `window`, returning the count including it
+    async fn increment(&self, api_key: &str, window: &UsageWindow) -> Result<u64, Error>;
+    async fn current(&self, api_key: &str, window: &UsageWindow) -> Result<u64, Error>;
+    async fn reset(&self, api_key: &str, window: &UsageWindow) -> Result<(), Error>;
+}
+
+// counts in `api_key_usage`, next to the limits
+pub struct PostgresUsageCounter {
+    keys: Arc<dyn ApiKeyRepository>,
+}
+
+impl PostgresUsageCounter {
+    pub fn new(keys: Arc<dyn ApiKeyRepository>) -> Self {
+        Self { keys }
+    }
+}
+
+#[async_trait]
+impl UsageCounter for PostgresUsageCounter {
+    async fn increment(&self, api_key: &str, window: &UsageWindow) -> Result<u64, Error> {
+        self.keys.increment_usage(api_key, window).await
+    }
+
+    async fn current(&self, api_key: &str, window: &UsageWindow) -> Result<u64, Error> {
+        self.keys.usage(api_key, window).await
+    }
+
+    async fn reset(&self, api_key: &str, window: &UsageWindow) -> Result<(), Error> {
+        self.keys.reset_usage(api_key, window).await
+    }
+}
+
+pub async fn connect_usage_counter(url: &str) -> Result<Arc<dyn UsageCounter>, Error> {
+    #[cfg(feature = "redis")]
+    return Ok(Arc::new(redis::RedisUsageCounter::connect(url).await?));
+
+    #[cfg(not(feature = "redis"))]
+    Err(Error::Internal(
+        format!(
+            "RATE_LIMIT_REDIS_URL={:?} requires building with the `redis` feature",
+            url
+        )
+        .into(),
+    ))
+}
+
+// what GetUsage reports for a key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyUsage {
+    pub limits: Option<ApiKeyLimits>,
+    pub minute_requests: u64,
+    pub month_requests: u64,
+    pub month_reset: SystemTime,
+}
+
+// applies the per-minute limit and monthly quota of `api_key_limits` to the
+// key a request carries; requests without a key, or with one that has no row,
+// are not limited
+pub struct RateLimiter {
+    keys: Arc<dyn ApiKeyRepository>,
+    usage: Arc<dyn UsageCounter>,
+    // how long a key's limits are used before they are read again
+    ttl: Duration,
+    cache: Mutex<HashMap<String, (Instant, Option<ApiKeyLimits>)>>,
+}
+
+impl RateLimiter {
+    pub fn new(
+        keys: Arc<dyn ApiKeyRepository>,
+        usage: Arc<dyn UsageCounter>,
+        ttl: Duration,
+    ) -> Self {
+        Self {
+            keys,
+            usage,
+            ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn limits(&self, api_key: &str) -> Result<Option<ApiKeyLimits>, Error> {
+        if let Some((read_at, limits)) = self.cache.lock().unwrap().get(api_key)
+            && read_at.elapsed() < self.ttl
+        {
+            return Ok(*limits);
+        }
+
+        let limits = self.keys.limits(api_key).await?;
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED_KEYS {
+            cache.clear();
+        }
+        cache.insert(api_key.to_string(), (Instant::now(), limits));
+        Ok(limits)
+    }
+
+    // this replica applies the new limits right away, the others once their
+    // cached ones expire
+    pub async fn set_limits(&self, api_key: &str, limits: ApiKeyLimits) -> Result<(), Error> {
+        self.keys.set_limits(api_key, limits).await?;
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(api_key.to_string(), (Instant::now(), Some(limits)));
+        Ok(())
+    }
+
+    pub async fn usage(&self, api_key: &str, now: SystemTime) -> Result<KeyUsage, Error> {
+        let minute = UsageWindow::containing(UsagePeriod::Minute, now);
+        let month = UsageWindow::containing(UsagePeriod::Month, now);
+
+        Ok(KeyUsage {
+            limits: self.keys.limits(api_key).await?,
+            minute_requests: self.usage.current(api_key, &minute).await?,
+            month_requests: self.usage.current(api_key, &month).await?,
+            month_reset: month.reset,
+        })
+    }
+
+    pub async fn reset_usage(&self, api_key: &str, now: SystemTime) -> Result<(), Error> {
+        for period in [UsagePeriod::Minute, UsagePeriod::Month] {
+            let window = UsageWindow::containing(period, now);
+            self.usage.reset(api_key, &window).await?;
+        }
+        Ok(())
+    }
+
+    // ResourceExhausted once the key is over a limit; the limiter failing lets
+    // the request through, so an outage of the counters is not one of the API
+    pub async fn check(&self, api_key: &str, now: SystemTime) -> Result<(), Status> {
+        match self.try_check(api_key, now).await {
+            Ok(checked) => checked,
+            Err(e) => {
+                warn!("failed to check the rate limits of an API key: {}", e);
+                Ok(())
+            }
+        }
+    }
+
+    async fn try_check(&self, api_key: &str, now: SystemTime) -> Result<Result<(), Status>, Error> {
+        let Some(limits) = self.limits(api_key).await? else {
+            return Ok(Ok(()));
+        };
+
+        // a request turned away by the minute limit does not use up quota
+        if let Some(limit) = limits.requests_per_minute {
+            let window = UsageWindow::containing(UsagePeriod::Minute, now);
+            if self.usage.increment(api_key, &window).await? > u64::from(limit) {
+                count("rate");
+                return Ok(Err(exhausted(
+                    format!("rate limit of {} requests per minute exceeded", limit),
+                    &window,
+                    now,
+                )));
+            }
+        }
+        if let Some(quota) = limits.monthly_quota {
+            let window = UsageWindow::containing(UsagePeriod::Month, now);
+            if self.usage.increment(api_key, &window).await? > quota {
+                count("quota");
+                return Ok(Err(exhausted(
+                    format!(
+                        "monthly quota of {} requests used up, it resets at {}",
+                        quota,
+                        prost_types::Timestamp::from(window.reset)
+                    ),
+                    &window,
+                    now,
+                )));
+            }
+        }
+        Ok(Ok(()))
+    }
+}
+
+fn exhausted(message: String, window: &UsageWindow, now: SystemTime) -> Status {
+    let mut status = Status::resource_exhausted(message);
+    let retry_after = window
+        .reset
+        .duration_since(now)
+        .unwrap_or_default()
+        .as_secs()
+        .max(1);
+    let metadata = status.metadata_mut();
+    metadata.insert(RETRY_AFTER_HEADER, MetadataValue::from(retry_after));
+    if let Ok(reset) = prost_types::Timestamp::from(window.reset)
+        .to_string()
+        .parse()
+    {
+        metadata.insert(QUOTA_RESET_HEADER, reset);
+    }
+    status
+}
+
+fn count(reason: &'static str) {
+    registry().increment_counter(RATE_LIMITED_TOTAL, &[("reason", reason)], 1);
+}
+
+// on the routes, like fault injection, so REST calls through the gateway are
+// counted once and against the same limits
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimitLayer {
+    pub fn new(limiter: Arc<RateLimiter>) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for RateLimitService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let Some(api_key) = req
+            .headers()
+            .get(API_KEY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+        else {
+            return Box::pin(self.inner.call(req));
+        };
+        // the ready service goes into the future, a clone stays for the next call
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let limiter = self.limiter.clone();
+
+        Box::pin(async move {
+            if let Err(status) = limiter.check(&api_key, SystemTime::now()).await {
+                return Ok(status.into_http());
+            }
+            inner.call(req).await
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+pub mod redis {
+    use async_trait::async_trait;
+    use redis::{AsyncCommands, Client, aio::MultiplexedConnection};
+
+    use super::UsageCounter;
+    use crate::{Error, entities::api_keys::UsageWindow};
+
+    // one `api_key_usage:<period>:<window start>:<key>` counter per window,
+    // expiring when the window does
+    pub struct RedisUsageCounter {
+        connection: MultiplexedConnection,
+    }
+
+    impl RedisUsageCounter {
+        pub async fn connect(url: &str) -> Result<Self, Error> {
+            let client = Client::open(url).map_err(|e| Error::Internal(Box::new(e)))?;
+            let connection = client
+                .get_multiplexed_async_connection()
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))?;
+
+            Ok(Self { connection })
+        }
+    }
+
+    fn secs(t: std::time::SystemTime) -> u64 {
+        t.duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+
+    fn key(api_key: &str, window: &UsageWindow) -> String {
+        format!(
+            "api_key_usage:{}:{}:{}",
+            window.period.as_str(),
+            secs(window.start),
+            api_key
+        )
+    }
+
+    #[async_trait]
+    impl UsageCounter for RedisUsageCounter {
+        async fn increment(&self, api_key: &str, window: &UsageWindow) -> Result<u64, Error> {
+            let key = key(api_key, window);
+            let mut connection = self.connection.clone();
+            let (count,): (u64,) = redis::pipe()
+                .atomic()
+                .incr(&key, 1)
+                .expire_at(&key, secs(window.reset) as i64)
+                .ignore()
+                .query_async(&mut connection)
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))?;
+
+            Ok(count)
+        }
+
+        async fn current(&self, api_key: &str, window: &UsageWindow) -> Result<u64, Error> {
+            let mut connection = self.connection.clone();
+            let count: Option<u64> = connection
+                .get(key(api_key, window))
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))?;
+
+            Ok(count.unwrap_or(0))
+        }
+
+        async fn reset(&self, api_key: &str, window: &UsageWindow) -> Result<(), Error> {
+            let mut connection = self.connection.clone();
+            connection
+                .del::<_, ()>(key(api_key, window))
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Fixed {
+        limits: Mutex<HashMap<String, ApiKeyLimits>>,
+        counts: Mutex<HashMap<(String, &'static str, SystemTime), u64>>,
+    }
+
+    #[async_trait]
+    impl ApiKeyRepository for Fixed {
+        async fn limits(&self, api_key: &str) -> Result<Option<ApiKeyLimits>, Error> {
+            Ok(self.limits.lock().unwrap().get(api_key).copied())
+        }
+
+        async fn set_limits(&self, api_key: &str, limits: ApiKeyLimits) -> Result<(), Error> {
+            self.limits
+                .lock()
+                .unwrap()
+                .insert(api_key.to_string(), limits);
+            Ok(())
+        }
+
+        async fn increment_usage(&self, api_key: &str, window: &UsageWindow) -> Result<u64, Error> {
+            let mut counts = self.counts.lock().unwrap();
+            let count = counts
+                .entry((api_key.to_string(), window.period.as_str(), window.start))
+                .or_default();
+            *count += 1;
+            Ok(*count)
+        }
+
+        async fn usage(&self, api_key: &str, window: &UsageWindow) -> Result<u64, Error> {
+            let counts = self.counts.lock().unwrap();
+            let key = (api_key.to_string(), window.period.as_str(), window.start);
+            Ok(counts.get(&key).copied().unwrap_or(0))
+        }
+
+        async fn reset_usage(&self, api_key: &str, window: &UsageWindow) -> Result<(), Error> {
+            self.counts
+                .lock()
+                .unwrap()
+                .retain(|(key, period, _), _| key != api_key || *period != window.period.as_str());
+            Ok(())
+        }
+    }
+
+    fn limiter(limits: ApiKeyLimits) -> RateLimiter {
+        let keys = Arc::new(Fixed {
+            limits: Mutex::new(HashMap::from([("k".to_string(), limits)])),
+            ..Default::default()
+        });
+        let usage = Arc::new(PostgresUsageCounter::new(keys.clone()));
+        RateLimiter::new(keys, usage, Duration::from_secs(60))
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[tokio::test]
+    async fn test_minute_limit() {
+        let limiter = limiter(ApiKeyLimits {
+            requests_per_minute: Some(2),
+            monthly_quota: None,
+        });
+        // 2026-10-14T12:34:56Z
+        let now = at(1_791_981_296);
+
+        assert!(limiter.check("k", now).await.is_ok());
+        assert!(limiter.check("k", now).await.is_ok());
+        let status = limiter.check("k", now).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(status.metadata().get(RETRY_AFTER_HEADER).unwrap(), "4");
+        // another key, and the next minute, start over
+        assert!(limiter.check("other", now).await.is_ok());
+        assert!(limiter.check("k", at(1_791_981_300)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_monthly_quota() {
+        let limiter = limiter(ApiKeyLimits {
+            requests_per_minute: None,
+            monthly_quota: Some(1),
+        });
+        let now = at(1_791_981_296);
+
+        assert!(limiter.check("k", now).await.is_ok());
+        let status = limiter.check("k", now).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(
+            status.metadata().get(QUOTA_RESET_HEADER).unwrap(),
+            "2026-11-01T00:00:00Z"
+        );
+        assert!(status.message().contains("2026-11-01T00:00:00Z"));
+        // 2026-11-01
+        assert!(limiter.check("k", at(1_793_491_200)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_set_limits_and_reset_usage() {
+        let limiter = limiter(ApiKeyLimits {
+            requests_per_minute: None,
+            monthly_quota: Some(1),
+        });
+        let now = at(1_791_981_296);
+        assert!(limiter.check("k", now).await.is_ok());
+        assert!(limiter.check("k", now).await.is_err());
+
+        let raised = ApiKeyLimits {
+            requests_per_minute: Some(10),
+            monthly_quota: Some(3),
+        };
+        limiter.set_limits("k", raised).await.unwrap();
+        assert!(limiter.check("k", now).await.is_ok());
+        let usage = limiter.usage("k", now).await.unwrap();
+        assert_eq!(
+            usage,
+            KeyUsage {
+                limits: Some(raised),
+                minute_requests: 1,
+                month_requests: 3,
+                month_reset: at(1_793_491_200),
+            }
+        );
+
+        limiter.reset_usage("k", now).await.unwrap();
+        let usage = limiter.usage("k", now).await.unwrap();
+        assert_eq!((usage.minute_requests, usage.month_requests), (0, 0));
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    borrow::Cow,
+    fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+const MASK: &str = "***";
+// a phone number is a `+` followed by this many digits, spacing aside
+const PHONE_DIGITS: std::ops::RangeInclusive<usize> = 7..=15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Name,
+    Surname,
+    Email,
+    Phone,
+}
+
+impl Field {
+    pub const ALL: [Field; 4] = [Field::Name, Field::Surname, Field::Email, Field::Phone];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl std::str::FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(Field::Name),
+            "surname" => Ok(Field::Surname),
+            "email" => Ok(Field::Email),
+            "phone" => Ok(Field::Phone),
+            other => Err(format!("unknown field {:?}", other)),
+        }
+    }
+}
+
+// the fields masked in logs and in status messages sent back to clients
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fields(u8);
+
+// everything is masked until the configuration says otherwise
+static CURRENT: AtomicU8 = AtomicU8::new(u8::MAX);
+
+impl Fields {
+    pub fn new(fields: &[Field]) -> Self {
+        Self(fields.iter().fold(0, |bits, f| bits | f.bit()))
+    }
+
+    // `REDACT_FIELDS`: comma separated fields, empty for all of them and
+    // `none` to log values as they are
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim() {
+            "" => Ok(Self::new(&Field::ALL)),
+            "none" => Ok(Self::new(&[])),
+            list => list
+                .split(',')
+                .map(|f| f.trim().parse())
+                .collect::<Result<Vec<_>, _>>()
+                .map(|fields| Self::new(&fields)),
+        }
+    }
+
+    pub fn contains(self, field: Field) -> bool {
+        self.0 & field.bit() != 0
+    }
+
+    // keeps the first character, `Ann` and `Anastasia` both become `A***`
+    pub fn mask(self, field: Field, value: &str) -> Cow<'_, str> {
+        if self.contains(field) {
+            Cow::Owned(masked(value))
+        } else {
+            Cow::Borrowed(value)
+        }
+    }
+
+    // masks the email addresses and `+` prefixed phone numbers in free text,
+    // error messages that may echo what a client sent
+    pub fn scrub(self, text: &str) -> Cow<'_, str> {
+        let (email, phone) = (self.contains(Field::Email), self.contains(Field::Phone));
+        if !email && !phone {
+            return Cow::Borrowed(text);
+        }
+
+        let mut out = String::with_capacity(text.len());
+        let mut changed = false;
+        let mut rest = text;
+        while !rest.is_empty() {
+            let end = match rest.find(|c: char| !is_word(c)) {
+                Some(0) => rest.chars().next().map_or(1, char::len_utf8),
+                Some(end) => end,
+                None => rest.len(),
+            };
+            let (word, after) = rest.split_at(end);
+            // sentence punctuation is not part of an address
+            let token = word.trim_end_matches('.');
+
+            if (email && is_email(token)) || (phone && is_phone(token)) {
+                out.push_str(&masked(token));
+                out.push_str(&word[token.len()..]);
+                changed = true;
+            } else {
+                out.push_str(word);
+            }
+            rest = after;
+        }
+
+        if changed {
+            Cow::Owned(out)
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+}
+
+fn masked(value: &str) -> String {
+    match value.chars().next() {
+        Some(first) => format!("{}{}", first, MASK),
+        None => String::new(),
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || "._%+-@".contains(c)
+}
+
+fn is_email(token: &str) -> bool {
+    match token.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.contains('.')
+                && !domain.starts_with('.')
+        }
+        None => false,
+    }
+}
+
+fn is_phone(token: &str) -> bool {
+    let Some(number) = token.strip_prefix('+') else {
+        return false;
+    };
+    number
+        .chars()
+        .all(|c| c.is_ascii_digit() || c == '-' || c == '.')
+        && PHONE_DIGITS.contains(&number.chars().filter(char::is_ascii_digit).count())
+}
+
+pub fn set_fields(fields: Fields) {
+    CURRENT.store(fields.0, Ordering::Relaxed);
+}
+
+pub fn fields() -> Fields {
+    Fields(CURRENT.load(Ordering::Relaxed))
+}
+
+// a field value for a log line, `{:?}` quotes it like the string it stands for
+pub struct Masked<'a>(Field, &'a str);
+
+pub fn mask(field: Field, value: &str) -> Masked<'_> {
+    Masked(field, value)
+}
+
+impl fmt::Display for Masked<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&fields().mask(self.0, self.1))
+    }
+}
+
+impl fmt::Debug for Masked<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", fields().mask(self.0, self.1))
+    }
+}
+
+// a filter expression for a log line, with the string literals masked when
+// names or surnames are, since those are what they compare against
+pub fn filter(expression: &str) -> Cow<'_, str> {
+    let fields = fields();
+    if !fields.contains(Field::Name) && !fields.contains(Field::Surname) {
+        return Cow::Borrowed(expression);
+    }
+
+    let mut out = String::with_capacity(expression.len());
+    let mut chars = expression.chars();
+    while let Some(c) = chars.next() {
+        out.push(c);
+        if c != '"' {
+            continue;
+        }
+        let mut literal = String::new();
+        let mut escaped = false;
+        for c in chars.by_ref() {
+            if c == '"' && !escaped {
+                break;
+            }
+            escaped = c == '\\' && !escaped;
+            literal.push(c);
+        }
+        out.push_str(&masked(&literal));
+        out.push('"');
+    }
+
+    Cow::Owned(out)
+}
+
+pub fn scrub(text: &str) -> Cow<'_, str> {
+    fields().scrub(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask() {
+        let fields = Fields::parse("name, email").unwrap();
+
+        assert_eq!(fields.mask(Field::Name, "Ada"), "A***");
+        assert_eq!(fields.mask(Field::Name, "Ærlig"), "Æ***");
+        assert_eq!(fields.mask(Field::Name, ""), "");
+        assert_eq!(fields.mask(Field::Surname, "Lovelace"), "Lovelace");
+        assert_eq!(Fields::parse("").unwrap(), Fields::new(&Field::ALL));
+        assert!(!Fields::parse("none").unwrap().contains(Field::Name));
+        assert!(Fields::parse("name,ssn").is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        set_fields(Fields::new(&Field::ALL));
+
+        assert_eq!(
+            filter(r#"name = "Ada" AND surname != "Love\"lace" AND id > 3"#),
+            r#"name = "A***" AND surname != "L***" AND id > 3"#
+        );
+    }
+
+    #[test]
+    fn test_scrub() {
+        let fields = Fields::new(&Field::ALL);
+
+        assert_eq!(
+            fields.scrub("no user ada@example.com, call +44-20-7946-0958."),
+            "no user a***, call +***."
+        );
+        assert_eq!(
+            fields.scrub("user id=1234567 not found"),
+            "user id=1234567 not found"
+        );
+        assert_eq!(
+            Fields::new(&[Field::Phone]).scrub("ada@example.com +15550100"),
+            "ada@example.com +***"
+        );
+    }
+}
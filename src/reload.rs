@@ -0,0 +1,173 @@
+use std::sync::{Arc, Mutex};
+
+use tracing::{error, info, warn};
+
+use crate::{
+    Error,
+    config::{Config, TlsMode},
+    flags::EnvFeatureFlags,
+    redact,
+    servers::request_log,
+    shutdown::Shutdown,
+    telemetry,
+    tls::ReloadableCertificate,
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Reloaded {
+    pub applied: Vec<&'static str>,
+    // settings outside the reloadable ones differ from those the process
+    // started with
+    pub needs_restart: bool,
+}
+
+// applies LOG_LEVEL, the trace and request log sampling, the redacted fields,
+// the feature flags and the TLS_MODE=files certificate again from a fresh read
+// of the configuration, under the `--set` flags it started with, on SIGHUP or through the admin service; nothing is
+// restarted, so open streams carry on
+pub struct Reloader {
+    started: Config,
+    flags: Arc<EnvFeatureFlags>,
+    certificate: Option<Arc<ReloadableCertificate>>,
+    running: Mutex<()>,
+}
+
+impl Reloader {
+    pub fn new(
+        started: Config,
+        flags: Arc<EnvFeatureFlags>,
+        certificate: Option<Arc<ReloadableCertificate>>,
+    ) -> Self {
+        Self {
+            started,
+            flags,
+            certificate,
+            running: Mutex::new(()),
+        }
+    }
+
+    pub fn reload(&self) -> Result<Reloaded, Error> {
+        self.apply(Config::load(&self.started.cli_overrides)?)
+    }
+
+    // the most likely to fail goes first, a failed reload may still have
+    // applied what came before it
+    fn apply(&self, mut config: Config) -> Result<Reloaded, Error> {
+        let _running = self.running.lock().unwrap();
+        // --dev-tls is a flag, not part of the environment
+        if self.started.tls == TlsMode::SelfSigned {
+            config.tls = TlsMode::SelfSigned;
+        }
+
+        let mut reloaded = Reloaded::default();
+        if let Some(certificate) = &self.certificate {
+            certificate.reload()?;
+            reloaded.applied.push("tls_certificate");
+        }
+        self.flags.set_enabled(&config.feature_flags)?;
+        self.flags.reload()?;
+        reloaded.applied.push("feature_flags");
+        telemetry::configure_log_level(&config.log_level)?;
+        reloaded.applied.push("log_level");
+        telemetry::set_trace_sampling(config.trace_sampling.clone());
+        reloaded.applied.push("trace_sampling");
+        request_log::set_rules(config.request_log.clone());
+        reloaded.applied.push("request_log");
+        redact::set_fields(config.redact_fields);
+        reloaded.applied.push("redact_fields");
+
+        reloaded.needs_restart = restart_only(&config) != restart_only(&self.started);
+        if reloaded.needs_restart {
+            warn!("configuration changed beyond what a reload applies, restart to pick it up");
+        }
+        info!("configuration reloaded: {}", reloaded.applied.join(", "));
+
+        Ok(reloaded)
+    }
+}
+
+// the rest of the configuration, for telling whether it changed
+fn restart_only(config: &Config) -> String {
+    format!(
+        "{:?}",
+        Config {
+            log_level: String::new(),
+            trace_sampling: Default::default(),
+            request_log: Vec::new(),
+            redact_fields: redact::Fields::new(&[]),
+            feature_flags: String::new(),
+            ..config.clone()
+        }
+    )
+}
+
+#[cfg(unix)]
+pub fn reload_on_sighup(reloader: Arc<Reloader>, shutdown: &Shutdown) -> Result<(), Error> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangups = signal(SignalKind::hangup()).map_err(|e| Error::Internal(Box::new(e)))?;
+    let stop = shutdown.clone();
+    shutdown.spawn(async move {
+        loop {
+            tokio::select! {
+                _ = stop.triggered() => return,
+                _ = hangups.recv() => {}
+            }
+            info!("SIGHUP received, reloading configuration");
+            if let Err(e) = reloader.reload() {
+                error!("failed to reload configuration: {}", e);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn reload_on_sighup(_reloader: Arc<Reloader>, _shutdown: &Shutdown) -> Result<(), Error> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flags::{FeatureFlags, Flag};
+
+    #[test]
+    fn test_apply() {
+        let started = Config::from_lookup(|_| None).unwrap();
+        let flags = Arc::new(EnvFeatureFlags::new("", None).unwrap());
+        let reloader = Reloader::new(started.clone(), flags.clone(), None);
+
+        let reloaded = reloader
+            .apply(Config {
+                feature_flags: "soft_delete".to_string(),
+                log_level: "debug".to_string(),
+                ..started.clone()
+            })
+            .unwrap();
+        assert_eq!(
+            reloaded,
+            Reloaded {
+                applied: vec![
+                    "feature_flags",
+                    "log_level",
+                    "trace_sampling",
+                    "request_log",
+                    "redact_fields",
+                ],
+                needs_restart: false,
+            }
+        );
+        assert!(flags.is_enabled(Flag::SoftDelete, None));
+
+        let moved = reloader
+            .apply(Config {
+                cache_ttl: started.cache_ttl + std::time::Duration::from_secs(1),
+                ..started
+            })
+            .unwrap();
+        assert!(moved.needs_restart);
+        assert!(!flags.is_enabled(Flag::SoftDelete, None));
+    }
+}
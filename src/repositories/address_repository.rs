@@ -0,0 +1,99 @@
+use async_trait::async_trait;
+
+use crate::repositories::address_repository_trait::AddressRepository as AddressRepositoryTrait;
+use crate::{Error, entities::addresses::Address};
+
+crate::crud_repository! {
+    pub struct AddressRepository for Address in "addresses" {
+        id: i32,
+        new: NewAddress {
+            user_id: i32,
+            street: String,
+            city: String,
+            postal_code: String,
+            country: String,
+        },
+    }
+}
+
+#[async_trait]
+impl AddressRepositoryTrait for AddressRepository {
+    async fn list_by_user(&self, user_id: i32) -> Result<Vec<Address>, Error> {
+        crate::query_as!(
+            Address,
+            r#"
+                SELECT id, user_id, street, city, postal_code, country
+                FROM addresses
+                WHERE user_id = $1
+                ORDER BY id
+            "#,
+            user_id
+        )
+        .fetch_all(self.pool())
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::{
+        crud::CrudRepository, user_repository::UserRepository,
+        user_repository_trait::UserRepository as UserRepositoryTrait,
+    };
+    use sqlx::PgPool;
+
+    fn new_address(user_id: i32, street: &str) -> NewAddress {
+        NewAddress {
+            user_id,
+            street: street.to_string(),
+            city: "Springfield".to_string(),
+            postal_code: "12345".to_string(),
+            country: "US".to_string(),
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_list_by_user(pool: PgPool) {
+        let users = UserRepository::new(pool.clone());
+        let repo = AddressRepository::new(pool);
+
+        let user = users
+            .create_user("Address".to_string(), "Owner".to_string())
+            .await
+            .unwrap();
+        let home = repo
+            .create(new_address(user.id, "1 Home St"))
+            .await
+            .unwrap();
+        let work = repo
+            .create(new_address(user.id, "2 Work Ave"))
+            .await
+            .unwrap();
+
+        let result = repo.list_by_user(user.id).await;
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), vec![home, work]);
+    }
+
+    #[sqlx::test]
+    async fn test_addresses_removed_with_user(pool: PgPool) {
+        let users = UserRepository::new(pool.clone());
+        let repo = AddressRepository::new(pool);
+
+        let user = users
+            .create_user("Cascade".to_string(), "Owner".to_string())
+            .await
+            .unwrap();
+        let address = repo
+            .create(new_address(user.id, "3 Gone Rd"))
+            .await
+            .unwrap();
+
+        users.delete_user(user.id).await.unwrap();
+
+        assert!(repo.get(address.id).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,11 @@
+use crate::{
+    Error,
+    entities::addresses::Address,
+    repositories::{address_repository::NewAddress, crud::CrudRepository},
+};
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait AddressRepository: CrudRepository<Address, i32, New = NewAddress> {
+    async fn list_by_user(&self, user_id: i32) -> Result<Vec<Address>, Error>;
+}
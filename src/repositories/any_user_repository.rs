@@ -0,0 +1,310 @@
+use std::{str::FromStr, time::SystemTime};
+
+use async_trait::async_trait;
+use sqlx::{
+    PgPool,
+    postgres::{PgConnectOptions, PgPoolOptions},
+};
+
+use crate::repositories::{
+    failover, memory_user_repository::InMemoryUserRepository,
+    sharded_user_repository::ShardedUserRepository, sqlite_user_repository::SqliteUserRepository,
+    temporal_user_repository::TemporalUserRepository, user_repository::UserRepository,
+    user_repository_trait::UserRepository as UserRepositoryTrait,
+};
+use crate::{
+    Error,
+    config::{Config, DatabaseBackend},
+    entities::{
+        audit::AuditEntry,
+        users::{DeletedUser, User, UserStatus},
+    },
+    filter::Filter,
+};
+
+#[derive(Clone)]
+pub enum AnyUserRepository {
+    Postgres(UserRepository),
+    Sqlite(SqliteUserRepository),
+    InMemory(InMemoryUserRepository),
+    Sharded(ShardedUserRepository),
+    Temporal(TemporalUserRepository),
+}
+
+impl AnyUserRepository {
+    pub async fn connect(config: &Config) -> Result<Self, Error> {
+        match config.database_backend {
+            DatabaseBackend::Postgres => {
+                let mut repo = connect_primary(config).await?;
+                if let Some(url) = &config.database_replica_url {
+                    let replica = connect_postgres(config, url).await?;
+                    repo =
+                        repo.with_replica(replica.pool().clone(), config.database_replica_max_wait);
+                }
+
+                Ok(Self::Postgres(repo))
+            }
+            DatabaseBackend::Sqlite => Ok(Self::Sqlite(
+                SqliteUserRepository::connect(&config.database_url, config.db_max_connections)
+                    .await?,
+            )),
+            DatabaseBackend::InMemory => Ok(Self::InMemory(InMemoryUserRepository::new())),
+            DatabaseBackend::Sharded => {
+                let mut shards = Vec::with_capacity(config.database_shards.len());
+                for url in &config.database_shards {
+                    shards.push(connect_postgres(config, url).await?);
+                }
+
+                Ok(Self::Sharded(ShardedUserRepository::new(shards)?))
+            }
+            DatabaseBackend::Temporal => {
+                let pool = connect_postgres(config, &config.database_url).await?;
+
+                Ok(Self::Temporal(
+                    TemporalUserRepository::new(pool.pool().clone())
+                        .with_acquire_warn_threshold(config.db_acquire_warn_threshold),
+                ))
+            }
+        }
+    }
+}
+
+impl AnyUserRepository {
+    // the pool other postgres-only repositories can share, when there is exactly one;
+    // not in temporal mode, where the `users` rows their foreign keys point at are absent
+    pub fn pg_pool(&self) -> Option<PgPool> {
+        match self {
+            Self::Postgres(repo) => Some(repo.pool().clone()),
+            _ => None,
+        }
+    }
+
+    // every postgres pool, by what readiness calls it
+    pub fn health_pools(&self) -> Vec<(String, PgPool)> {
+        match self {
+            Self::Postgres(repo) => {
+                let mut pools = vec![("postgres".to_string(), repo.pool().clone())];
+                if let Some(replica) = repo.pools().get(1) {
+                    pools.push(("postgres_replica".to_string(), replica.clone()));
+                }
+                pools
+            }
+            Self::Sharded(repo) => repo
+                .shards()
+                .iter()
+                .enumerate()
+                .map(|(i, shard)| (format!("postgres_shard_{}", i), shard.pool().clone()))
+                .collect(),
+            Self::Temporal(repo) => vec![("postgres".to_string(), repo.pool().clone())],
+            Self::Sqlite(_) | Self::InMemory(_) => Vec::new(),
+        }
+    }
+
+    // the pools connecting with the credentials of DATABASE_URL and
+    // DATABASE_REPLICA_URL, which Vault may rotate
+    pub fn credential_pools(&self) -> Vec<PgPool> {
+        match self {
+            Self::Postgres(repo) => repo.pools(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn pool_options(config: &Config) -> PgPoolOptions {
+    PgPoolOptions::new()
+        .max_connections(config.db_max_connections)
+        .acquire_timeout(config.db_acquire_timeout)
+}
+
+async fn connect_postgres(config: &Config, url: &str) -> Result<UserRepository, Error> {
+    let pool = pool_options(config)
+        .connect(url)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+    Ok(UserRepository::new(pool)
+        .with_acquire_warn_threshold(config.db_acquire_warn_threshold)
+        .with_bulk_chunk_size(config.db_bulk_insert_chunk_size))
+}
+
+// DATABASE_URL, or the first of DATABASE_FAILOVER_URLS that connects while it
+// does not, with the settings of DATABASE_URL
+async fn connect_primary(config: &Config) -> Result<UserRepository, Error> {
+    let res = connect_postgres(config, &config.database_url).await;
+    let (Err(e), Some(settings)) = (&res, &config.database_failover) else {
+        return res;
+    };
+    tracing::warn!(
+        "failed to connect to DATABASE_URL, trying the failover candidates: {}",
+        e
+    );
+    let base = PgConnectOptions::from_str(&config.database_url)
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+    for candidate in failover::candidates(&config.database_url, settings)?
+        .iter()
+        .skip(1)
+    {
+        match pool_options(config)
+            .connect_with(candidate.options(&base))
+            .await
+        {
+            Ok(pool) => {
+                tracing::warn!("connected to failover database {}", candidate.name());
+                return Ok(UserRepository::new(pool)
+                    .with_acquire_warn_threshold(config.db_acquire_warn_threshold)
+                    .with_bulk_chunk_size(config.db_bulk_insert_chunk_size));
+            }
+            Err(e) => tracing::warn!("failed to connect to {}: {}", candidate.name(), e),
+        }
+    }
+
+    res
+}
+
+macro_rules! dispatch {
+    ($self:ident, $repo:ident => $call:expr) => {
+        match $self {
+            AnyUserRepository::Postgres($repo) => $call,
+            AnyUserRepository::Sqlite($repo) => $call,
+            AnyUserRepository::InMemory($repo) => $call,
+            AnyUserRepository::Sharded($repo) => $call,
+            AnyUserRepository::Temporal($repo) => $call,
+        }
+    };
+}
+
+#[async_trait]
+impl UserRepositoryTrait for AnyUserRepository {
+    async fn create_user(&self, name: String, surname: String) -> Result<User, Error> {
+        dispatch!(self, repo => repo.create_user(name, surname).await)
+    }
+
+    async fn create_users_bulk(&self, users: Vec<(String, String)>) -> Result<Vec<User>, Error> {
+        dispatch!(self, repo => repo.create_users_bulk(users).await)
+    }
+
+    async fn get_users(&self) -> Result<(Vec<User>, i32), Error> {
+        dispatch!(self, repo => repo.get_users().await)
+    }
+
+    async fn count_users(&self) -> Result<i64, Error> {
+        dispatch!(self, repo => repo.count_users().await)
+    }
+
+    async fn get_users_batch(&self, offset: i32, limit: i32) -> Result<Vec<User>, Error> {
+        dispatch!(self, repo => repo.get_users_batch(offset, limit).await)
+    }
+
+    async fn get_users_filtered(
+        &self,
+        filter: Filter,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        dispatch!(self, repo => repo.get_users_filtered(filter, offset, limit).await)
+    }
+
+    async fn get_users_page(
+        &self,
+        filter: Option<Filter>,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<crate::grpc::User>, Error> {
+        dispatch!(self, repo => repo.get_users_page(filter, offset, limit).await)
+    }
+
+    async fn get_user_by_id(&self, id: i32) -> Result<Option<User>, Error> {
+        dispatch!(self, repo => repo.get_user_by_id(id).await)
+    }
+
+    async fn get_user_by_name(&self, name: String) -> Result<Option<User>, Error> {
+        dispatch!(self, repo => repo.get_user_by_name(name).await)
+    }
+
+    async fn get_users_by_names(&self, names: Vec<String>) -> Result<Vec<User>, Error> {
+        dispatch!(self, repo => repo.get_users_by_names(names).await)
+    }
+
+    async fn get_users_by_name_prefix(
+        &self,
+        prefix: String,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        dispatch!(self, repo => repo.get_users_by_name_prefix(prefix, limit).await)
+    }
+
+    async fn user_exists(&self, id: i32) -> Result<bool, Error> {
+        dispatch!(self, repo => repo.user_exists(id).await)
+    }
+
+    async fn update_user(
+        &self,
+        id: i32,
+        name: Option<String>,
+        surname: Option<String>,
+    ) -> Result<Option<User>, Error> {
+        dispatch!(self, repo => repo.update_user(id, name, surname).await)
+    }
+
+    async fn delete_user(&self, id: i32) -> Result<(), Error> {
+        dispatch!(self, repo => repo.delete_user(id).await)
+    }
+
+    async fn soft_delete_user(&self, id: i32) -> Result<(), Error> {
+        dispatch!(self, repo => repo.soft_delete_user(id).await)
+    }
+
+    async fn restore_user(&self, id: i32) -> Result<Option<User>, Error> {
+        dispatch!(self, repo => repo.restore_user(id).await)
+    }
+
+    async fn get_deleted_users(
+        &self,
+        after_id: i32,
+        limit: i32,
+    ) -> Result<Vec<DeletedUser>, Error> {
+        dispatch!(self, repo => repo.get_deleted_users(after_id, limit).await)
+    }
+
+    async fn merge_users(
+        &self,
+        primary_id: i32,
+        duplicate_id: i32,
+        audit: Vec<AuditEntry>,
+    ) -> Result<User, Error> {
+        dispatch!(self, repo => repo.merge_users(primary_id, duplicate_id, audit).await)
+    }
+
+    async fn get_user_status(&self, id: i32) -> Result<Option<UserStatus>, Error> {
+        dispatch!(self, repo => repo.get_user_status(id).await)
+    }
+
+    async fn set_user_status(
+        &self,
+        id: i32,
+        from: UserStatus,
+        to: UserStatus,
+    ) -> Result<bool, Error> {
+        dispatch!(self, repo => repo.set_user_status(id, from, to).await)
+    }
+
+    async fn erase_user(&self, id: i32, name: String, surname: String) -> Result<(), Error> {
+        dispatch!(self, repo => repo.erase_user(id, name, surname).await)
+    }
+
+    async fn get_user_by_id_as_of(
+        &self,
+        id: i32,
+        as_of: SystemTime,
+    ) -> Result<Option<User>, Error> {
+        dispatch!(self, repo => repo.get_user_by_id_as_of(id, as_of).await)
+    }
+
+    async fn get_users_as_of(
+        &self,
+        filter: Option<Filter>,
+        as_of: SystemTime,
+    ) -> Result<Vec<User>, Error> {
+        dispatch!(self, repo => repo.get_users_as_of(filter, as_of).await)
+    }
+}
@@ -0,0 +1,177 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use crate::repositories::api_key_repository_trait::ApiKeyRepository as ApiKeyRepositoryTrait;
+use crate::{
+    Error,
+    entities::api_keys::{ApiKeyLimits, UsageWindow},
+};
+
+#[derive(Clone)]
+pub struct ApiKeyRepository {
+    pool: PgPool,
+}
+
+impl ApiKeyRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepositoryTrait for ApiKeyRepository {
+    async fn limits(&self, api_key: &str) -> Result<Option<ApiKeyLimits>, Error> {
+        let row = crate::query_as!(
+            LimitsRow,
+            r#"
+                SELECT requests_per_minute, monthly_quota
+                FROM api_key_limits
+                WHERE api_key = $1
+            "#,
+            api_key
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(row.map(|row| ApiKeyLimits {
+            requests_per_minute: row.requests_per_minute.map(|n| n as u32),
+            monthly_quota: row.monthly_quota.map(|n| n as u64),
+        }))
+    }
+
+    async fn set_limits(&self, api_key: &str, limits: ApiKeyLimits) -> Result<(), Error> {
+        crate::query!(
+            r#"
+                INSERT INTO api_key_limits (api_key, requests_per_minute, monthly_quota)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (api_key) DO UPDATE SET
+                    requests_per_minute = EXCLUDED.requests_per_minute,
+                    monthly_quota = EXCLUDED.monthly_quota
+            "#,
+            api_key,
+            limits.requests_per_minute.map(|n| n as i32),
+            limits.monthly_quota.map(|n| n as i64)
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(())
+    }
+
+    async fn increment_usage(&self, api_key: &str, window: &UsageWindow) -> Result<u64, Error> {
+        // a replica whose clock is behind counts into the newer window rather
+        // than starting the older one again
+        let requests = crate::query_scalar!(
+            i64,
+            r#"
+                INSERT INTO api_key_usage (api_key, period, window_start, requests)
+                VALUES ($1, $2, $3::text::timestamptz, 1)
+                ON CONFLICT (api_key, period) DO UPDATE SET
+                    requests = CASE
+                        WHEN api_key_usage.window_start >= EXCLUDED.window_start
+                            THEN api_key_usage.requests + 1
+                        ELSE 1
+                    END,
+                    window_start = GREATEST(api_key_usage.window_start, EXCLUDED.window_start)
+                RETURNING requests
+            "#,
+            api_key,
+            window.period.as_str(),
+            prost_types::Timestamp::from(window.start).to_string()
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(requests as u64)
+    }
+
+    async fn usage(&self, api_key: &str, window: &UsageWindow) -> Result<u64, Error> {
+        let requests = crate::query_scalar!(
+            i64,
+            r#"
+                SELECT requests
+                FROM api_key_usage
+                WHERE api_key = $1 AND period = $2 AND window_start = $3::text::timestamptz
+            "#,
+            api_key,
+            window.period.as_str(),
+            prost_types::Timestamp::from(window.start).to_string()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(requests.unwrap_or(0) as u64)
+    }
+
+    // the row goes, whichever window it counted
+    async fn reset_usage(&self, api_key: &str, window: &UsageWindow) -> Result<(), Error> {
+        crate::query!(
+            r#"
+                DELETE FROM api_key_usage
+                WHERE api_key = $1 AND period = $2
+            "#,
+            api_key,
+            window.period.as_str()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(())
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct LimitsRow {
+    requests_per_minute: Option<i32>,
+    monthly_quota: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+    use crate::entities::api_keys::UsagePeriod;
+
+    #[sqlx::test]
+    async fn test_limits_and_usage(pool: PgPool) {
+        let repo = ApiKeyRepository::new(pool);
+        let limits = ApiKeyLimits {
+            requests_per_minute: Some(5),
+            monthly_quota: None,
+        };
+        repo.set_limits("k", limits).await.unwrap();
+
+        assert_eq!(repo.limits("k").await.unwrap(), Some(limits));
+        assert_eq!(repo.limits("other").await.unwrap(), None);
+
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_791_981_296);
+        let window = UsageWindow::containing(UsagePeriod::Minute, now);
+        assert_eq!(repo.increment_usage("k", &window).await.unwrap(), 1);
+        assert_eq!(repo.increment_usage("k", &window).await.unwrap(), 2);
+        let month = UsageWindow::containing(UsagePeriod::Month, now);
+        assert_eq!(repo.increment_usage("k", &month).await.unwrap(), 1);
+
+        let next = UsageWindow::containing(UsagePeriod::Minute, window.reset);
+        assert_eq!(repo.increment_usage("k", &next).await.unwrap(), 1);
+        // late from a replica still in the earlier minute
+        assert_eq!(repo.increment_usage("k", &window).await.unwrap(), 2);
+        assert_eq!(repo.usage("k", &next).await.unwrap(), 2);
+        assert_eq!(repo.usage("k", &window).await.unwrap(), 0);
+
+        repo.reset_usage("k", &next).await.unwrap();
+        assert_eq!(repo.usage("k", &next).await.unwrap(), 0);
+        assert_eq!(repo.usage("k", &month).await.unwrap(), 1);
+        let raised = ApiKeyLimits {
+            requests_per_minute: None,
+            monthly_quota: Some(100),
+        };
+        repo.set_limits("k", raised).await.unwrap();
+        assert_eq!(repo.limits("k").await.unwrap(), Some(raised));
+    }
+}
@@ -0,0 +1,19 @@
+use crate::{
+    Error,
+    entities::api_keys::{ApiKeyLimits, UsageWindow},
+};
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait ApiKeyRepository: Send + Sync {
+    // None for a key without limits
+    async fn limits(&self, api_key: &str) -> Result<Option<ApiKeyLimits>, Error>;
+    // creates the row of the key, or replaces its limits
+    async fn set_limits(&self, api_key: &str, limits: ApiKeyLimits) -> Result<(), Error>;
+    // counts one request of a key with limits against `window`, returning the
+    // count including it
+    async fn increment_usage(&self, api_key: &str, window: &UsageWindow) -> Result<u64, Error>;
+    // the requests counted in `window`, 0 once a newer window has started
+    async fn usage(&self, api_key: &str, window: &UsageWindow) -> Result<u64, Error>;
+    async fn reset_usage(&self, api_key: &str, window: &UsageWindow) -> Result<(), Error>;
+}
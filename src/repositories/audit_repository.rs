@@ -0,0 +1,300 @@
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use sqlx::{PgExecutor, PgPool};
+use tracing::info;
+
+use crate::repositories::audit_repository_trait::AuditRepository as AuditRepositoryTrait;
+use crate::{
+    Error,
+    entities::audit::{AuditEntry, AuditFilter, AuditRecord},
+};
+
+#[derive(Clone)]
+pub struct AuditRepository {
+    pool: PgPool,
+}
+
+impl AuditRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+// shared with writes that record their audit entry in their own transaction
+pub(crate) async fn insert(executor: impl PgExecutor<'_>, entry: &AuditEntry) -> Result<(), Error> {
+    crate::query!(
+        r#"
+            INSERT INTO user_audit_log (action, target_user_id, actor_user_id, effective_user_id)
+            VALUES ($1, $2, $3, $4)
+        "#,
+        entry.action.as_str(),
+        entry.target_user_id,
+        entry.actor_user_id,
+        entry.effective_user_id
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| Error::Internal(Box::new(e)))?;
+
+    Ok(())
+}
+
+#[async_trait]
+impl AuditRepositoryTrait for AuditRepository {
+    async fn record(&self, entry: AuditEntry) -> Result<(), Error> {
+        insert(&self.pool, &entry).await
+    }
+
+    async fn list_by_user(&self, user_id: i32) -> Result<Vec<AuditRecord>, Error> {
+        let rows = crate::query_as!(
+            AuditRow,
+            r#"
+                SELECT
+                    id,
+                    action,
+                    target_user_id,
+                    actor_user_id,
+                    effective_user_id,
+                    (extract(epoch FROM created_at) * 1000000)::bigint AS "created_at_micros!"
+                FROM user_audit_log
+                WHERE target_user_id = $1 OR actor_user_id = $1 OR effective_user_id = $1
+                ORDER BY id
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        rows.into_iter().map(AuditRow::into_record).collect()
+    }
+
+    async fn list(
+        &self,
+        filter: AuditFilter,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<AuditRecord>, Error> {
+        let timestamp = |t: SystemTime| prost_types::Timestamp::from(t).to_string();
+        let rows = crate::query_as!(
+            AuditRow,
+            r#"
+                SELECT
+                    id,
+                    action,
+                    target_user_id,
+                    actor_user_id,
+                    effective_user_id,
+                    (extract(epoch FROM created_at) * 1000000)::bigint AS "created_at_micros!"
+                FROM user_audit_log
+                WHERE id > $1
+                    AND ($2::integer IS NULL OR actor_user_id = $2)
+                    AND ($3::integer IS NULL OR target_user_id = $3)
+                    AND ($4::text IS NULL OR action = $4)
+                    AND ($5::text IS NULL OR created_at >= $5::text::timestamptz)
+                    AND ($6::text IS NULL OR created_at < $6::text::timestamptz)
+                ORDER BY id
+                LIMIT $7
+            "#,
+            after_id,
+            filter.actor_user_id,
+            filter.target_user_id,
+            filter.action.map(|a| a.as_str()),
+            filter.start.map(timestamp),
+            filter.end.map(timestamp),
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        rows.into_iter().map(AuditRow::into_record).collect()
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct AuditRow {
+    id: i64,
+    action: String,
+    target_user_id: i32,
+    actor_user_id: Option<i32>,
+    effective_user_id: Option<i32>,
+    #[sqlx(rename = "created_at_micros!")]
+    created_at_micros: i64,
+}
+
+impl AuditRow {
+    fn into_record(self) -> Result<AuditRecord, Error> {
+        Ok(AuditRecord {
+            id: self.id,
+            entry: AuditEntry {
+                action: self
+                    .action
+                    .parse()
+                    .map_err(|e: String| Error::Internal(e.into()))?,
+                target_user_id: self.target_user_id,
+                actor_user_id: self.actor_user_id,
+                effective_user_id: self.effective_user_id,
+            },
+            created_at: SystemTime::UNIX_EPOCH
+                + Duration::from_micros(self.created_at_micros.max(0) as u64),
+        })
+    }
+}
+
+// used by the backends without a postgres pool
+#[derive(Clone, Default)]
+pub struct LogAuditRepository;
+
+#[async_trait]
+impl AuditRepositoryTrait for LogAuditRepository {
+    async fn record(&self, entry: AuditEntry) -> Result<(), Error> {
+        info!(
+            target: "audit",
+            action = entry.action.as_str(),
+            target_user_id = entry.target_user_id,
+            actor_user_id = ?entry.actor_user_id,
+            effective_user_id = ?entry.effective_user_id,
+            "user mutated"
+        );
+
+        Ok(())
+    }
+
+    // the entries only went to the log
+    async fn list_by_user(&self, _user_id: i32) -> Result<Vec<AuditRecord>, Error> {
+        Ok(Vec::new())
+    }
+
+    async fn list(
+        &self,
+        _filter: AuditFilter,
+        _after_id: i64,
+        _limit: i64,
+    ) -> Result<Vec<AuditRecord>, Error> {
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::audit::AuditAction;
+
+    #[sqlx::test]
+    async fn test_record(pool: PgPool) {
+        let repo = AuditRepository::new(pool.clone());
+        let target = 4242;
+
+        repo.record(AuditEntry {
+            action: AuditAction::DeleteUser,
+            target_user_id: target,
+            actor_user_id: Some(1),
+            effective_user_id: Some(2),
+        })
+        .await
+        .unwrap();
+
+        #[derive(sqlx::FromRow)]
+        struct Row {
+            action: String,
+            actor_user_id: Option<i32>,
+            effective_user_id: Option<i32>,
+        }
+        let row = crate::query_as!(
+            Row,
+            "SELECT action, actor_user_id, effective_user_id FROM user_audit_log WHERE target_user_id = $1",
+            target
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(row.action, "delete_user");
+        assert_eq!(row.actor_user_id, Some(1));
+        assert_eq!(row.effective_user_id, Some(2));
+    }
+
+    #[sqlx::test]
+    async fn test_list_by_user(pool: PgPool) {
+        let repo = AuditRepository::new(pool);
+        for (action, target, actor) in [
+            (AuditAction::CreateUser, 1, None),
+            (AuditAction::UpdateUser, 2, Some(1)),
+            (AuditAction::DeleteUser, 3, Some(3)),
+        ] {
+            repo.record(AuditEntry {
+                action,
+                target_user_id: target,
+                actor_user_id: actor,
+                effective_user_id: actor,
+            })
+            .await
+            .unwrap();
+        }
+
+        let records = repo.list_by_user(1).await.unwrap();
+
+        let actions: Vec<_> = records.iter().map(|r| r.entry.action).collect();
+        assert_eq!(actions, [AuditAction::CreateUser, AuditAction::UpdateUser]);
+        assert!(records[0].created_at <= SystemTime::now());
+    }
+
+    #[sqlx::test]
+    async fn test_list(pool: PgPool) {
+        let repo = AuditRepository::new(pool);
+        for (action, target, actor) in [
+            (AuditAction::CreateUser, 1, None),
+            (AuditAction::UpdateUser, 1, Some(9)),
+            (AuditAction::UpdateUser, 2, Some(9)),
+            (AuditAction::DeleteUser, 1, Some(9)),
+        ] {
+            repo.record(AuditEntry {
+                action,
+                target_user_id: target,
+                actor_user_id: actor,
+                effective_user_id: actor,
+            })
+            .await
+            .unwrap();
+        }
+        let by_actor = AuditFilter {
+            actor_user_id: Some(9),
+            ..AuditFilter::default()
+        };
+
+        let first = repo.list(by_actor.clone(), 0, 2).await.unwrap();
+        let rest = repo.list(by_actor, first[1].id, 2).await.unwrap();
+        let updates = repo
+            .list(
+                AuditFilter {
+                    target_user_id: Some(1),
+                    action: Some(AuditAction::UpdateUser),
+                    ..AuditFilter::default()
+                },
+                0,
+                10,
+            )
+            .await
+            .unwrap();
+        let future = repo
+            .list(
+                AuditFilter {
+                    start: Some(SystemTime::now() + Duration::from_secs(3600)),
+                    ..AuditFilter::default()
+                },
+                0,
+                10,
+            )
+            .await
+            .unwrap();
+
+        let targets: Vec<_> = first.iter().map(|r| r.entry.target_user_id).collect();
+        assert_eq!(targets, [1, 2]);
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].entry.action, AuditAction::DeleteUser);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].entry.actor_user_id, Some(9));
+        assert!(future.is_empty());
+    }
+}
@@ -0,0 +1,20 @@
+use crate::{
+    Error,
+    entities::audit::{AuditEntry, AuditFilter, AuditRecord},
+};
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait AuditRepository: Send + Sync {
+    async fn record(&self, entry: AuditEntry) -> Result<(), Error>;
+    // the entries naming the user as target, actor or effective user, oldest
+    // first
+    async fn list_by_user(&self, user_id: i32) -> Result<Vec<AuditRecord>, Error>;
+    // up to `limit` matching entries with an id above `after_id`, by id
+    async fn list(
+        &self,
+        filter: AuditFilter,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<AuditRecord>, Error>;
+}
@@ -0,0 +1,612 @@
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use sqlx::{Connection, PgConnection, PgPool};
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+
+use crate::repositories::backup_repository_trait::{
+    BackupRepository as BackupRepositoryTrait, RestoreSession,
+};
+use crate::{
+    Error,
+    entities::backups::{Backup, Restore, SnapshotRow},
+};
+
+// rows inserted by one statement of a restore
+const RESTORE_BATCH: usize = 500;
+
+struct Table {
+    name: &'static str,
+    order_by: &'static str,
+    serial: Option<&'static str>,
+    // emptied before a restore. Data keys are only ever added to, the ones
+    // added since the backup stay, as may the names they encrypt elsewhere
+    replaced: bool,
+}
+
+// parents first, which is the order they are restored in as well
+const TABLES: &[Table] = &[
+    Table {
+        name: "pii_data_keys",
+        order_by: "id",
+        serial: Some("id"),
+        replaced: false,
+    },
+    Table {
+        name: "users",
+        order_by: "id",
+        serial: Some("id"),
+        replaced: true,
+    },
+    Table {
+        name: "addresses",
+        order_by: "id",
+        serial: Some("id"),
+        replaced: true,
+    },
+    Table {
+        name: "user_relationships",
+        order_by: "user_id, kind, related_user_id",
+        serial: None,
+        replaced: true,
+    },
+    Table {
+        name: "user_history",
+        order_by: "id",
+        serial: Some("id"),
+        replaced: true,
+    },
+    Table {
+        name: "consents",
+        order_by: "id",
+        serial: Some("id"),
+        replaced: true,
+    },
+    Table {
+        name: "user_audit_log",
+        order_by: "id",
+        serial: Some("id"),
+        replaced: true,
+    },
+];
+
+#[derive(Clone)]
+pub struct BackupRepository {
+    pool: PgPool,
+}
+
+impl BackupRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+async fn schema_version(conn: &mut PgConnection) -> Result<i64, Error> {
+    crate::query_scalar!(
+        i64,
+        r#"SELECT coalesce(max(version), 0) AS "version!" FROM _sqlx_migrations"#
+    )
+    .fetch_one(conn)
+    .await
+    .map_err(|e| Error::Internal(Box::new(e)))
+}
+
+#[async_trait]
+impl BackupRepositoryTrait for BackupRepository {
+    async fn create_backup(&self, object: &str) -> Result<Backup, Error> {
+        let row = crate::query_as!(
+            BackupRow,
+            r#"
+                INSERT INTO backups (object)
+                VALUES ($1)
+                RETURNING
+                    id,
+                    object,
+                    state,
+                    row_count,
+                    byte_count,
+                    error,
+                    (extract(epoch FROM created_at) * 1000000)::bigint AS "created_at_micros!",
+                    (extract(epoch FROM completed_at) * 1000000)::bigint AS "completed_at_micros?",
+                    restore_state,
+                    restored_rows,
+                    restore_error,
+                    (extract(epoch FROM restore_started_at) * 1000000)::bigint AS "restore_started_at_micros?",
+                    (extract(epoch FROM restored_at) * 1000000)::bigint AS "restored_at_micros?"
+            "#,
+            object
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        row.into_backup()
+    }
+
+    async fn finish_backup(
+        &self,
+        id: i64,
+        row_count: u64,
+        byte_count: u64,
+        error: Option<String>,
+    ) -> Result<Backup, Error> {
+        let row = crate::query_as!(
+            BackupRow,
+            r#"
+                UPDATE backups
+                SET
+                    state = CASE WHEN $4::text IS NULL THEN 'completed' ELSE 'failed' END,
+                    row_count = $2,
+                    byte_count = $3,
+                    error = $4,
+                    completed_at = now()
+                WHERE id = $1 AND state = 'running'
+                RETURNING
+                    id,
+                    object,
+                    state,
+                    row_count,
+                    byte_count,
+                    error,
+                    (extract(epoch FROM created_at) * 1000000)::bigint AS "created_at_micros!",
+                    (extract(epoch FROM completed_at) * 1000000)::bigint AS "completed_at_micros?",
+                    restore_state,
+                    restored_rows,
+                    restore_error,
+                    (extract(epoch FROM restore_started_at) * 1000000)::bigint AS "restore_started_at_micros?",
+                    (extract(epoch FROM restored_at) * 1000000)::bigint AS "restored_at_micros?"
+            "#,
+            id,
+            row_count as i64,
+            byte_count as i64,
+            error
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        row.ok_or(Error::NotFound)?.into_backup()
+    }
+
+    async fn backup(&self, id: i64) -> Result<Option<Backup>, Error> {
+        let row = crate::query_as!(
+            BackupRow,
+            r#"
+                SELECT
+                    id,
+                    object,
+                    state,
+                    row_count,
+                    byte_count,
+                    error,
+                    (extract(epoch FROM created_at) * 1000000)::bigint AS "created_at_micros!",
+                    (extract(epoch FROM completed_at) * 1000000)::bigint AS "completed_at_micros?",
+                    restore_state,
+                    restored_rows,
+                    restore_error,
+                    (extract(epoch FROM restore_started_at) * 1000000)::bigint AS "restore_started_at_micros?",
+                    (extract(epoch FROM restored_at) * 1000000)::bigint AS "restored_at_micros?"
+                FROM backups
+                WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        row.map(BackupRow::into_backup).transpose()
+    }
+
+    async fn latest_backup(&self) -> Result<Option<Backup>, Error> {
+        let row = crate::query_as!(
+            BackupRow,
+            r#"
+                SELECT
+                    id,
+                    object,
+                    state,
+                    row_count,
+                    byte_count,
+                    error,
+                    (extract(epoch FROM created_at) * 1000000)::bigint AS "created_at_micros!",
+                    (extract(epoch FROM completed_at) * 1000000)::bigint AS "completed_at_micros?",
+                    restore_state,
+                    restored_rows,
+                    restore_error,
+                    (extract(epoch FROM restore_started_at) * 1000000)::bigint AS "restore_started_at_micros?",
+                    (extract(epoch FROM restored_at) * 1000000)::bigint AS "restored_at_micros?"
+                FROM backups
+                ORDER BY id DESC
+                LIMIT 1
+            "#
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        row.map(BackupRow::into_backup).transpose()
+    }
+
+    async fn snapshot(&self, rows: mpsc::Sender<SnapshotRow>) -> Result<i64, Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        // every table as of the first query, without holding up writers
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        let version = schema_version(&mut tx).await?;
+
+        for table in TABLES {
+            let query = format!(
+                "SELECT row_to_json(t)::text FROM {} t ORDER BY {}",
+                table.name, table.order_by
+            );
+            let mut fetched = sqlx::query_scalar::<_, String>(&query).fetch(&mut *tx);
+            while let Some(row) = fetched.next().await {
+                let row = row.map_err(|e| Error::Internal(Box::new(e)))?;
+                let row = SnapshotRow {
+                    table: table.name.to_string(),
+                    row,
+                };
+                if rows.send(row).await.is_err() {
+                    return Err(Error::Internal("the snapshot was no longer read".into()));
+                }
+            }
+        }
+        tx.commit()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(version)
+    }
+
+    async fn schema_version(&self) -> Result<i64, Error> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        schema_version(&mut conn).await
+    }
+
+    async fn start_restore(&self, id: i64) -> Result<(Backup, Box<dyn RestoreSession>), Error> {
+        // held by the session until the restore is recorded, so a backup still
+        // marked restoring when the lock is taken was left so by a process
+        // that died; the session ends with it, which releases the lock
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?
+            .detach();
+        let acquired: bool = sqlx::query_scalar(
+            "SELECT pg_try_advisory_lock(hashtextextended('backups_restore', 0))",
+        )
+        .fetch_one(&mut conn)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+        if !acquired {
+            let _ = conn.close().await;
+            return Err(Error::FailedPrecondition(
+                "another restore is running".to_string(),
+            ));
+        }
+
+        crate::query!(
+            r#"
+                UPDATE backups
+                SET restore_state = 'failed', restore_error = 'interrupted', restored_at = now()
+                WHERE restore_state = 'restoring'
+            "#
+        )
+        .execute(&mut conn)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+        let row = crate::query_as!(
+            BackupRow,
+            r#"
+                UPDATE backups
+                SET
+                    restore_state = 'restoring',
+                    restored_rows = 0,
+                    restore_error = NULL,
+                    restore_started_at = now(),
+                    restored_at = NULL
+                WHERE id = $1 AND state = 'completed'
+                RETURNING
+                    id,
+                    object,
+                    state,
+                    row_count,
+                    byte_count,
+                    error,
+                    (extract(epoch FROM created_at) * 1000000)::bigint AS "created_at_micros!",
+                    (extract(epoch FROM completed_at) * 1000000)::bigint AS "completed_at_micros?",
+                    restore_state,
+                    restored_rows,
+                    restore_error,
+                    (extract(epoch FROM restore_started_at) * 1000000)::bigint AS "restore_started_at_micros?",
+                    (extract(epoch FROM restored_at) * 1000000)::bigint AS "restored_at_micros?"
+            "#,
+            id
+        )
+        .fetch_optional(&mut conn)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+        let Some(row) = row else {
+            let _ = conn.close().await;
+            return match self.backup(id).await? {
+                Some(backup) => Err(Error::FailedPrecondition(format!(
+                    "backup {} is {}, not completed",
+                    id,
+                    backup.state.as_str()
+                ))),
+                None => Err(Error::NotFound),
+            };
+        };
+
+        Ok((row.into_backup()?, Box::new(PgRestoreSession { conn, id })))
+    }
+}
+
+struct PgRestoreSession {
+    conn: PgConnection,
+    id: i64,
+}
+
+impl PgRestoreSession {
+    async fn load(
+        &mut self,
+        row_count: u64,
+        mut rows: mpsc::Receiver<Result<SnapshotRow, Error>>,
+    ) -> Result<u64, Error> {
+        let mut tx = self
+            .conn
+            .begin()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        // until the transaction ends
+        sqlx::query("SELECT set_config('gin_tonik.skip_user_history', 'on', true)")
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        let replaced: Vec<_> = TABLES
+            .iter()
+            .filter(|t| t.replaced)
+            .map(|t| t.name)
+            .collect();
+        // without CASCADE, so a table referencing these that is not backed up
+        // fails the restore instead of being emptied along
+        sqlx::query(&format!("TRUNCATE {}", replaced.join(", ")))
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        let mut restored = 0;
+        let mut table: Option<&Table> = None;
+        let mut batch = Vec::new();
+        while let Some(row) = rows.recv().await {
+            let row = row?;
+            let Some(of) = TABLES.iter().find(|t| t.name == row.table) else {
+                return Err(Error::InvalidArgument(format!(
+                    "the backup has rows of {}, which is not backed up",
+                    row.table
+                )));
+            };
+            if table.is_some_and(|t| t.name != of.name) || batch.len() == RESTORE_BATCH {
+                restored += insert(&mut tx, table, &mut batch).await?;
+            }
+            table = Some(of);
+            batch.push(row.row);
+        }
+        restored += insert(&mut tx, table, &mut batch).await?;
+        if restored != row_count {
+            return Err(Error::Internal(
+                format!("the backup ended after {} of {} rows", restored, row_count).into(),
+            ));
+        }
+
+        // never moved back, so ids handed out since the backup are not handed
+        // out again
+        for table in TABLES {
+            let Some(column) = table.serial else {
+                continue;
+            };
+            sqlx::query(&format!(
+                "SELECT setval(pg_get_serial_sequence('{0}', '{1}'), \
+                 greatest(coalesce(max({1}), 0), nextval(pg_get_serial_sequence('{0}', '{1}')))) \
+                 FROM {0}",
+                table.name, column
+            ))
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(restored)
+    }
+}
+
+// the rows of `batch`, all of `table`, emptying it; how many there were
+async fn insert(
+    conn: &mut PgConnection,
+    table: Option<&Table>,
+    batch: &mut Vec<String>,
+) -> Result<u64, Error> {
+    let Some(table) = table.filter(|_| !batch.is_empty()) else {
+        return Ok(0);
+    };
+    let conflict = if table.replaced {
+        ""
+    } else {
+        " ON CONFLICT DO NOTHING"
+    };
+    sqlx::query(&format!(
+        "INSERT INTO {0} SELECT * FROM json_populate_recordset(NULL::{0}, $1::json){1}",
+        table.name, conflict
+    ))
+    .bind(format!("[{}]", batch.join(",")))
+    .execute(conn)
+    .await
+    .map_err(|e| Error::Internal(Box::new(e)))?;
+    let count = batch.len() as u64;
+    batch.clear();
+
+    Ok(count)
+}
+
+#[async_trait]
+impl RestoreSession for PgRestoreSession {
+    async fn restore(
+        mut self: Box<Self>,
+        row_count: u64,
+        rows: mpsc::Receiver<Result<SnapshotRow, Error>>,
+    ) -> Result<u64, Error> {
+        let result = self.load(row_count, rows).await;
+        let (state, restored, error) = match &result {
+            Ok(restored) => ("restored", *restored, None),
+            Err(e) => ("failed", 0, Some(e.to_string())),
+        };
+        let recorded = crate::query!(
+            r#"
+                UPDATE backups
+                SET restore_state = $2, restored_rows = $3, restore_error = $4, restored_at = now()
+                WHERE id = $1
+            "#,
+            self.id,
+            state,
+            restored as i64,
+            error
+        )
+        .execute(&mut self.conn)
+        .await;
+        // releases the lock
+        let _ = self.conn.close().await;
+        recorded.map_err(|e| Error::Internal(Box::new(e)))?;
+
+        result
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct BackupRow {
+    id: i64,
+    object: String,
+    state: String,
+    row_count: i64,
+    byte_count: i64,
+    error: Option<String>,
+    #[sqlx(rename = "created_at_micros!")]
+    created_at_micros: i64,
+    #[sqlx(rename = "completed_at_micros?")]
+    completed_at_micros: Option<i64>,
+    restore_state: Option<String>,
+    restored_rows: i64,
+    restore_error: Option<String>,
+    #[sqlx(rename = "restore_started_at_micros?")]
+    restore_started_at_micros: Option<i64>,
+    #[sqlx(rename = "restored_at_micros?")]
+    restored_at_micros: Option<i64>,
+}
+
+impl BackupRow {
+    fn into_backup(self) -> Result<Backup, Error> {
+        let time =
+            |micros: i64| SystemTime::UNIX_EPOCH + Duration::from_micros(micros.max(0) as u64);
+        let restore = match (self.restore_state, self.restore_started_at_micros) {
+            (Some(state), Some(started_at)) => Some(Restore {
+                state: state
+                    .parse()
+                    .map_err(|e: String| Error::Internal(e.into()))?,
+                row_count: self.restored_rows as u64,
+                error: self.restore_error,
+                started_at: time(started_at),
+                finished_at: self.restored_at_micros.map(time),
+            }),
+            _ => None,
+        };
+
+        Ok(Backup {
+            id: self.id,
+            object: self.object,
+            state: self
+                .state
+                .parse()
+                .map_err(|e: String| Error::Internal(e.into()))?,
+            row_count: self.row_count as u64,
+            byte_count: self.byte_count as u64,
+            error: self.error,
+            created_at: time(self.created_at_micros),
+            completed_at: self.completed_at_micros.map(time),
+            restore,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::backups::{BackupState, RestoreState};
+
+    #[sqlx::test]
+    async fn test_one_restore_at_a_time(pool: PgPool) {
+        let repo = BackupRepository::new(pool.clone());
+        let backup = repo.create_backup("backup-1").await.unwrap();
+        assert_eq!(backup.state, BackupState::Running);
+        assert!(matches!(
+            repo.start_restore(backup.id).await,
+            Err(Error::FailedPrecondition(_))
+        ));
+        assert!(matches!(
+            repo.start_restore(backup.id + 1).await,
+            Err(Error::NotFound)
+        ));
+
+        let backup = repo.finish_backup(backup.id, 0, 0, None).await.unwrap();
+        assert_eq!(backup.state, BackupState::Completed);
+        let (restoring, session) = repo.start_restore(backup.id).await.unwrap();
+        assert_eq!(
+            restoring.restore.map(|r| r.state),
+            Some(RestoreState::Restoring)
+        );
+        assert!(matches!(
+            repo.start_restore(backup.id).await,
+            Err(Error::FailedPrecondition(_))
+        ));
+
+        // a session gone before recording its restore left it interrupted,
+        // once Postgres noticed and released the lock
+        drop(session);
+        let mut started = repo.start_restore(backup.id).await;
+        for _ in 0..50 {
+            if started.is_ok() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            started = repo.start_restore(backup.id).await;
+        }
+        let (_, session) = started.unwrap();
+        let (tx, rx) = mpsc::channel(1);
+        drop(tx);
+        assert_eq!(session.restore(0, rx).await.unwrap(), 0);
+        let restore = repo
+            .latest_backup()
+            .await
+            .unwrap()
+            .unwrap()
+            .restore
+            .unwrap();
+        assert_eq!(restore.state, RestoreState::Restored);
+        assert!(restore.finished_at.is_some());
+    }
+}
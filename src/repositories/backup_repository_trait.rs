@@ -0,0 +1,45 @@
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+
+use crate::{
+    Error,
+    entities::backups::{Backup, SnapshotRow},
+};
+
+#[async_trait]
+pub trait BackupRepository: Send + Sync {
+    // a running backup, to be stored under `object`
+    async fn create_backup(&self, object: &str) -> Result<Backup, Error>;
+    // completes a running backup, or fails it with `error`
+    async fn finish_backup(
+        &self,
+        id: i64,
+        row_count: u64,
+        byte_count: u64,
+        error: Option<String>,
+    ) -> Result<Backup, Error>;
+    async fn backup(&self, id: i64) -> Result<Option<Backup>, Error>;
+    async fn latest_backup(&self) -> Result<Option<Backup>, Error>;
+
+    // every row of the backed up tables as of one point in time, parents
+    // before the rows referencing them; the schema version they are of
+    async fn snapshot(&self, rows: mpsc::Sender<SnapshotRow>) -> Result<i64, Error>;
+    // the latest migration applied
+    async fn schema_version(&self) -> Result<i64, Error>;
+    // marks the backup restoring, for the session to restore it; NotFound, or
+    // FailedPrecondition unless it completed or while another restore runs
+    async fn start_restore(&self, id: i64) -> Result<(Backup, Box<dyn RestoreSession>), Error>;
+}
+
+#[async_trait]
+pub trait RestoreSession: Send {
+    // replaces the backed up tables with `rows`, in the order `snapshot` sent
+    // them, in one transaction and without recording history. An Err among
+    // the rows, or fewer than `row_count` of them, rolls it all back. The
+    // outcome is recorded with the backup; the rows restored
+    async fn restore(
+        self: Box<Self>,
+        row_count: u64,
+        rows: mpsc::Receiver<Result<SnapshotRow, Error>>,
+    ) -> Result<u64, Error>;
+}
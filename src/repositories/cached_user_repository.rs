@@ -0,0 +1,473 @@
+use std::{
+    hash::{BuildHasher, RandomState},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use tokio_stream::StreamExt;
+use tracing::warn;
+
+use crate::cache::{
+    UserCache,
+    invalidation::{Invalidation, InvalidationBus, Target},
+};
+use crate::repositories::user_repository_trait::UserRepository as UserRepositoryTrait;
+use crate::{
+    Error,
+    entities::{
+        audit::AuditEntry,
+        users::{DeletedUser, User, UserStatus},
+    },
+    filter::Filter,
+    shutdown::Shutdown,
+};
+
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Clone)]
+pub struct CachedUserRepository<T: UserRepositoryTrait> {
+    inner: T,
+    cache: Arc<UserCache>,
+    bus: Option<Arc<dyn InvalidationBus>>,
+    origin: u64,
+}
+
+impl<T: UserRepositoryTrait> CachedUserRepository<T> {
+    pub fn new(inner: T, cache: Arc<UserCache>) -> Self {
+        Self {
+            inner,
+            cache,
+            bus: None,
+            origin: RandomState::new().hash_one(std::process::id()),
+        }
+    }
+
+    pub fn with_invalidation_bus(mut self, bus: Arc<dyn InvalidationBus>) -> Self {
+        self.bus = Some(bus);
+        self
+    }
+
+    pub fn listen_for_invalidations(&self, shutdown: &Shutdown) {
+        let Some(bus) = self.bus.clone() else {
+            return;
+        };
+        let cache = self.cache.clone();
+        let origin = self.origin;
+        let stop = shutdown.clone();
+
+        shutdown.spawn(async move {
+            while !stop.is_triggered() {
+                let mut invalidations = match bus.subscribe().await {
+                    Ok(invalidations) => invalidations,
+                    Err(e) => {
+                        warn!("failed to subscribe to cache invalidations: {}", e);
+                        tokio::select! {
+                            _ = stop.triggered() => break,
+                            _ = tokio::time::sleep(RESUBSCRIBE_DELAY) => continue,
+                        }
+                    }
+                };
+
+                loop {
+                    tokio::select! {
+                        biased;
+                        _ = stop.triggered() => return,
+                        invalidation = invalidations.next() => match invalidation {
+                            Some(Invalidation::Evict { origin: from, .. }) if from == origin => {}
+                            Some(Invalidation::Evict { target, .. }) => evict(&cache, &target),
+                            Some(Invalidation::All) => cache.clear(),
+                            None => break,
+                        },
+                    }
+                }
+
+                // anything published while resubscribing is lost
+                warn!("cache invalidation subscription ended, resubscribing");
+                cache.clear();
+            }
+        });
+    }
+
+    fn store(&self, user: &User) {
+        self.cache.insert_by_id(user.id, Some(user));
+        self.cache.insert_by_name(&user.name, Some(user));
+    }
+
+    async fn invalidate(&self, target: Target) {
+        evict(&self.cache, &target);
+
+        if let Some(bus) = &self.bus
+            && let Err(e) = bus.publish(self.origin, target.clone()).await
+        {
+            warn!(
+                "failed to publish cache invalidation for {:?}: {}",
+                target, e
+            );
+        }
+    }
+}
+
+fn evict(cache: &UserCache, target: &Target) {
+    match target {
+        Target::User(id) => cache.invalidate(*id),
+        Target::Name(name) => cache.invalidate_name(name),
+    }
+}
+
+#[async_trait]
+impl<T: UserRepositoryTrait> UserRepositoryTrait for CachedUserRepository<T> {
+    async fn create_user(&self, name: String, surname: String) -> Result<User, Error> {
+        let user = self.inner.create_user(name, surname).await?;
+        // drop remembered misses for the new id and name
+        self.invalidate(Target::User(user.id)).await;
+        self.invalidate(Target::Name(user.name.clone())).await;
+        self.store(&user);
+
+        Ok(user)
+    }
+
+    async fn create_users_bulk(&self, users: Vec<(String, String)>) -> Result<Vec<User>, Error> {
+        let users = self.inner.create_users_bulk(users).await?;
+        for user in &users {
+            self.invalidate(Target::User(user.id)).await;
+            self.invalidate(Target::Name(user.name.clone())).await;
+            self.store(user);
+        }
+
+        Ok(users)
+    }
+
+    async fn get_users(&self) -> Result<(Vec<User>, i32), Error> {
+        self.inner.get_users().await
+    }
+
+    async fn count_users(&self) -> Result<i64, Error> {
+        self.inner.count_users().await
+    }
+
+    async fn get_users_batch(&self, offset: i32, limit: i32) -> Result<Vec<User>, Error> {
+        self.inner.get_users_batch(offset, limit).await
+    }
+
+    async fn get_users_filtered(
+        &self,
+        filter: Filter,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        self.inner.get_users_filtered(filter, offset, limit).await
+    }
+
+    async fn get_users_page(
+        &self,
+        filter: Option<Filter>,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<crate::grpc::User>, Error> {
+        self.inner.get_users_page(filter, offset, limit).await
+    }
+
+    async fn get_user_by_id(&self, id: i32) -> Result<Option<User>, Error> {
+        if let Some(cached) = self.cache.get_by_id(id) {
+            return Ok(cached);
+        }
+
+        let user = self.inner.get_user_by_id(id).await?;
+        self.cache.insert_by_id(id, user.as_ref());
+
+        Ok(user)
+    }
+
+    async fn get_user_by_name(&self, name: String) -> Result<Option<User>, Error> {
+        if let Some(cached) = self.cache.get_by_name(&name) {
+            return Ok(cached);
+        }
+
+        let user = self.inner.get_user_by_name(name.clone()).await?;
+        self.cache.insert_by_name(&name, user.as_ref());
+
+        Ok(user)
+    }
+
+    // names are not unique, so the single-user name cache cannot answer this
+    async fn get_users_by_names(&self, names: Vec<String>) -> Result<Vec<User>, Error> {
+        self.inner.get_users_by_names(names).await
+    }
+
+    async fn get_users_by_name_prefix(
+        &self,
+        prefix: String,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        self.inner.get_users_by_name_prefix(prefix, limit).await
+    }
+
+    async fn user_exists(&self, id: i32) -> Result<bool, Error> {
+        if let Some(cached) = self.cache.get_by_id(id) {
+            return Ok(cached.is_some());
+        }
+
+        let exists = self.inner.user_exists(id).await?;
+        if !exists {
+            self.cache.insert_by_id(id, None);
+        }
+
+        Ok(exists)
+    }
+
+    async fn update_user(
+        &self,
+        id: i32,
+        name: Option<String>,
+        surname: Option<String>,
+    ) -> Result<Option<User>, Error> {
+        let user = self.inner.update_user(id, name, surname).await?;
+        self.invalidate(Target::User(id)).await;
+        if let Some(user) = &user {
+            self.invalidate(Target::Name(user.name.clone())).await;
+            self.store(user);
+        }
+
+        Ok(user)
+    }
+
+    async fn delete_user(&self, id: i32) -> Result<(), Error> {
+        self.inner.delete_user(id).await?;
+        self.invalidate(Target::User(id)).await;
+
+        Ok(())
+    }
+
+    async fn soft_delete_user(&self, id: i32) -> Result<(), Error> {
+        self.inner.soft_delete_user(id).await?;
+        self.invalidate(Target::User(id)).await;
+
+        Ok(())
+    }
+
+    async fn restore_user(&self, id: i32) -> Result<Option<User>, Error> {
+        let user = self.inner.restore_user(id).await?;
+        // drop the misses remembered while the user was deleted
+        if let Some(user) = &user {
+            self.invalidate(Target::User(id)).await;
+            self.invalidate(Target::Name(user.name.clone())).await;
+            self.store(user);
+        }
+
+        Ok(user)
+    }
+
+    async fn get_deleted_users(
+        &self,
+        after_id: i32,
+        limit: i32,
+    ) -> Result<Vec<DeletedUser>, Error> {
+        self.inner.get_deleted_users(after_id, limit).await
+    }
+
+    async fn merge_users(
+        &self,
+        primary_id: i32,
+        duplicate_id: i32,
+        audit: Vec<AuditEntry>,
+    ) -> Result<User, Error> {
+        let user = self
+            .inner
+            .merge_users(primary_id, duplicate_id, audit)
+            .await?;
+        self.invalidate(Target::User(duplicate_id)).await;
+
+        Ok(user)
+    }
+
+    // checked before every self-service write, which must not see a stale one
+    async fn get_user_status(&self, id: i32) -> Result<Option<UserStatus>, Error> {
+        self.inner.get_user_status(id).await
+    }
+
+    async fn set_user_status(
+        &self,
+        id: i32,
+        from: UserStatus,
+        to: UserStatus,
+    ) -> Result<bool, Error> {
+        self.inner.set_user_status(id, from, to).await
+    }
+
+    async fn erase_user(&self, id: i32, name: String, surname: String) -> Result<(), Error> {
+        self.inner.erase_user(id, name, surname).await?;
+        self.invalidate(Target::User(id)).await;
+
+        Ok(())
+    }
+
+    // history never changes, but it is read too rarely to be worth caching
+    async fn get_user_by_id_as_of(
+        &self,
+        id: i32,
+        as_of: SystemTime,
+    ) -> Result<Option<User>, Error> {
+        self.inner.get_user_by_id_as_of(id, as_of).await
+    }
+
+    async fn get_users_as_of(
+        &self,
+        filter: Option<Filter>,
+        as_of: SystemTime,
+    ) -> Result<Vec<User>, Error> {
+        self.inner.get_users_as_of(filter, as_of).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cache::invalidation::LocalInvalidationBus,
+        repositories::memory_user_repository::InMemoryUserRepository,
+    };
+
+    fn replica(
+        db: &InMemoryUserRepository,
+        bus: &LocalInvalidationBus,
+    ) -> CachedUserRepository<InMemoryUserRepository> {
+        CachedUserRepository::new(
+            db.clone(),
+            Arc::new(
+                UserCache::new(Duration::from_secs(60)).with_negative_ttl(Duration::from_secs(5)),
+            ),
+        )
+        .with_invalidation_bus(Arc::new(bus.clone()))
+    }
+
+    #[tokio::test]
+    async fn test_serves_cached_user() {
+        let db = InMemoryUserRepository::new();
+        let repo = CachedUserRepository::new(
+            db.clone(),
+            Arc::new(UserCache::new(Duration::from_secs(60))),
+        );
+        let created = db
+            .create_user("Cached".to_string(), "User".to_string())
+            .await
+            .unwrap();
+
+        repo.get_user_by_id(created.id).await.unwrap();
+        db.delete_user(created.id).await.unwrap();
+
+        assert_eq!(
+            repo.get_user_by_id(created.id).await.unwrap(),
+            Some(created)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_through() {
+        let db = InMemoryUserRepository::new();
+        let bus = LocalInvalidationBus::new();
+        let shutdown = Shutdown::new();
+        let repo = replica(&db, &bus);
+        repo.listen_for_invalidations(&shutdown);
+        tokio::task::yield_now().await;
+
+        let created = repo
+            .create_user("Write".to_string(), "Through".to_string())
+            .await
+            .unwrap();
+        let updated = repo
+            .update_user(created.id, None, Some("Again".to_string()))
+            .await
+            .unwrap();
+        tokio::task::yield_now().await;
+        db.delete_user(created.id).await.unwrap();
+
+        assert_eq!(repo.get_user_by_id(created.id).await.unwrap(), updated);
+        assert_eq!(
+            repo.get_user_by_name("Write".to_string()).await.unwrap(),
+            updated
+        );
+        shutdown.trigger();
+    }
+
+    #[tokio::test]
+    async fn test_user_exists_consults_cache() {
+        let db = InMemoryUserRepository::new();
+        let repo = replica(&db, &LocalInvalidationBus::new());
+        let created = repo
+            .create_user("Exists".to_string(), "Cached".to_string())
+            .await
+            .unwrap();
+
+        db.delete_user(created.id).await.unwrap();
+
+        assert!(repo.user_exists(created.id).await.unwrap());
+        assert!(!repo.user_exists(99999).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_negative_cache_cleared_on_create() {
+        let db = InMemoryUserRepository::new();
+        let bus = LocalInvalidationBus::new();
+        let repo = replica(&db, &bus);
+
+        assert_eq!(repo.get_user_by_id(1).await.unwrap(), None);
+        assert_eq!(
+            repo.get_user_by_name("Ghost".to_string()).await.unwrap(),
+            None
+        );
+        db.create_user("Ghost".to_string(), "Behind".to_string())
+            .await
+            .unwrap();
+
+        assert_eq!(repo.get_user_by_id(1).await.unwrap(), None);
+        assert_eq!(
+            repo.get_user_by_name("Ghost".to_string()).await.unwrap(),
+            None
+        );
+
+        let created = repo
+            .create_user("Ghost".to_string(), "Again".to_string())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            repo.get_user_by_name("Ghost".to_string()).await.unwrap(),
+            Some(created)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_evicts_other_replicas() {
+        let db = InMemoryUserRepository::new();
+        let bus = LocalInvalidationBus::new();
+        let shutdown = Shutdown::new();
+        let (a, b) = (replica(&db, &bus), replica(&db, &bus));
+        a.listen_for_invalidations(&shutdown);
+        tokio::task::yield_now().await;
+
+        let created = db
+            .create_user("Stale".to_string(), "User".to_string())
+            .await
+            .unwrap();
+        a.get_user_by_id(created.id).await.unwrap();
+        b.update_user(created.id, Some("Fresh".to_string()), None)
+            .await
+            .unwrap();
+
+        let fresh = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                let user = a.get_user_by_id(created.id).await.unwrap().unwrap();
+                if user.name == "Fresh" {
+                    return user;
+                }
+                tokio::task::yield_now().await;
+            }
+        })
+        .await;
+
+        assert!(fresh.is_ok());
+        shutdown.trigger();
+        assert!(shutdown.drain(Duration::from_secs(1)).await);
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use crate::repositories::consent_repository_trait::ConsentRepository as ConsentRepositoryTrait;
+use crate::{
+    Error,
+    entities::consents::{Consent, NewConsent},
+};
+
+#[derive(Clone)]
+pub struct ConsentRepository {
+    pool: PgPool,
+}
+
+impl ConsentRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ConsentRepositoryTrait for ConsentRepository {
+    async fn record(&self, user_id: i32, consent: NewConsent) -> Result<Consent, Error> {
+        let row = crate::query_as!(
+            ConsentRow,
+            r#"
+                INSERT INTO consents (user_id, consent_type, version, source)
+                VALUES ($1, $2, $3, $4)
+                RETURNING
+                    id,
+                    user_id,
+                    consent_type,
+                    version,
+                    source,
+                    (extract(epoch FROM granted_at) * 1000000)::bigint AS "granted_at_micros!"
+            "#,
+            user_id,
+            consent.consent_type,
+            consent.version,
+            consent.source
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(row.into_consent())
+    }
+
+    async fn list_by_user(&self, user_id: i32) -> Result<Vec<Consent>, Error> {
+        let rows = crate::query_as!(
+            ConsentRow,
+            r#"
+                SELECT
+                    id,
+                    user_id,
+                    consent_type,
+                    version,
+                    source,
+                    (extract(epoch FROM granted_at) * 1000000)::bigint AS "granted_at_micros!"
+                FROM consents
+                WHERE user_id = $1
+                ORDER BY id
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(rows.into_iter().map(ConsentRow::into_consent).collect())
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct ConsentRow {
+    id: i64,
+    user_id: i32,
+    consent_type: String,
+    version: String,
+    source: String,
+    #[sqlx(rename = "granted_at_micros!")]
+    granted_at_micros: i64,
+}
+
+impl ConsentRow {
+    fn into_consent(self) -> Consent {
+        Consent {
+            id: self.id,
+            user_id: self.user_id,
+            consent: NewConsent {
+                consent_type: self.consent_type,
+                version: self.version,
+                source: self.source,
+            },
+            granted_at: SystemTime::UNIX_EPOCH
+                + Duration::from_micros(self.granted_at_micros.max(0) as u64),
+        }
+    }
+}
+
+// used by the backends without a postgres pool, consents last as long as the
+// process like the users they belong to
+#[derive(Clone, Default)]
+pub struct InMemoryConsentRepository {
+    consents: Arc<Mutex<Vec<Consent>>>,
+}
+
+#[async_trait]
+impl ConsentRepositoryTrait for InMemoryConsentRepository {
+    async fn record(&self, user_id: i32, consent: NewConsent) -> Result<Consent, Error> {
+        let mut consents = self.consents.lock().unwrap();
+        let consent = Consent {
+            id: consents.len() as i64 + 1,
+            user_id,
+            consent,
+            granted_at: SystemTime::now(),
+        };
+        consents.push(consent.clone());
+
+        Ok(consent)
+    }
+
+    async fn list_by_user(&self, user_id: i32) -> Result<Vec<Consent>, Error> {
+        let consents = self.consents.lock().unwrap();
+        Ok(consents
+            .iter()
+            .filter(|c| c.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_record_and_list(pool: PgPool) {
+        let repo = ConsentRepository::new(pool);
+        let consent = |consent_type: &str| NewConsent {
+            consent_type: consent_type.to_string(),
+            version: "2026-01".to_string(),
+            source: "signup_form".to_string(),
+        };
+
+        let terms = repo.record(7, consent("terms")).await.unwrap();
+        repo.record(8, consent("terms")).await.unwrap();
+        repo.record(7, consent("marketing")).await.unwrap();
+
+        let consents = repo.list_by_user(7).await.unwrap();
+        let types: Vec<_> = consents
+            .iter()
+            .map(|c| c.consent.consent_type.as_str())
+            .collect();
+        assert_eq!(types, ["terms", "marketing"]);
+        assert_eq!(consents[0], terms);
+        assert!(terms.granted_at <= SystemTime::now());
+    }
+}
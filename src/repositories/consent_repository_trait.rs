@@ -0,0 +1,13 @@
+use crate::{
+    Error,
+    entities::consents::{Consent, NewConsent},
+};
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait ConsentRepository: Send + Sync {
+    async fn record(&self, user_id: i32, consent: NewConsent) -> Result<Consent, Error>;
+    // every consent the user gave, oldest first; a later one of the same type
+    // supersedes the earlier
+    async fn list_by_user(&self, user_id: i32) -> Result<Vec<Consent>, Error>;
+}
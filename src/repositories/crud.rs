@@ -0,0 +1,283 @@
+use async_trait::async_trait;
+
+use crate::Error;
+
+#[async_trait]
+pub trait CrudRepository<E, Id>: Send + Sync + Clone {
+    type New: Send;
+
+    async fn create(&self, new: Self::New) -> Result<E, Error>;
+    async fn get(&self, id: Id) -> Result<Option<E>, Error>;
+    async fn list(&self, offset: i32, limit: i32) -> Result<Vec<E>, Error>;
+    async fn update(&self, id: Id, new: Self::New) -> Result<Option<E>, Error>;
+    async fn delete(&self, id: Id) -> Result<(), Error>;
+}
+
+// crud_repository! {
+//     pub struct AddressRepository for Address in "addresses" {
+//         id: i32,
+//         new: NewAddress { user_id: i32, city: String },
+//     }
+// }
+//
+// generates `NewAddress`, `AddressRepository::new(pool)` and a postgres
+// `CrudRepository<Address, i32>` implementation; the entity must derive `sqlx::FromRow`
+// with its fields named after the table columns
+#[macro_export]
+macro_rules! crud_repository {
+    (
+        $vis:vis struct $repo:ident for $entity:ident in $table:literal {
+            $id:ident: $id_ty:ty,
+            new: $new:ident { $($column:ident: $column_ty:ty),+ $(,)? } $(,)?
+        }
+    ) => {
+        #[derive(Clone, Debug, PartialEq, Eq)]
+        $vis struct $new {
+            $(pub $column: $column_ty),+
+        }
+
+        #[derive(Clone)]
+        $vis struct $repo {
+            pool: ::sqlx::PgPool,
+        }
+
+        impl $repo {
+            $vis fn new(pool: ::sqlx::PgPool) -> Self {
+                Self { pool }
+            }
+
+            #[allow(dead_code)]
+            $vis fn pool(&self) -> &::sqlx::PgPool {
+                &self.pool
+            }
+
+            const TABLE: $crate::repositories::crud::Table = $crate::repositories::crud::Table {
+                name: $table,
+                id: stringify!($id),
+                columns: &[$(stringify!($column)),+],
+            };
+        }
+
+        #[::async_trait::async_trait]
+        impl $crate::repositories::crud::CrudRepository<$entity, $id_ty> for $repo {
+            type New = $new;
+
+            async fn create(&self, new: $new) -> Result<$entity, $crate::Error> {
+                static SQL: ::std::sync::LazyLock<String> =
+                    ::std::sync::LazyLock::new(|| $repo::TABLE.insert());
+
+                ::sqlx::query_as::<_, $entity>(&SQL)
+                    $(.bind(new.$column))+
+                    .fetch_one(&self.pool)
+                    .await
+                    .map_err(|e| $crate::Error::Internal(Box::new(e)))
+            }
+
+            async fn get(&self, id: $id_ty) -> Result<Option<$entity>, $crate::Error> {
+                static SQL: ::std::sync::LazyLock<String> =
+                    ::std::sync::LazyLock::new(|| $repo::TABLE.select_by_id());
+
+                ::sqlx::query_as::<_, $entity>(&SQL)
+                    .bind(id)
+                    .fetch_optional(&self.pool)
+                    .await
+                    .map_err(|e| $crate::Error::Internal(Box::new(e)))
+            }
+
+            async fn list(&self, offset: i32, limit: i32) -> Result<Vec<$entity>, $crate::Error> {
+                static SQL: ::std::sync::LazyLock<String> =
+                    ::std::sync::LazyLock::new(|| $repo::TABLE.select_page());
+
+                ::sqlx::query_as::<_, $entity>(&SQL)
+                    .bind(limit as i64)
+                    .bind(offset as i64)
+                    .fetch_all(&self.pool)
+                    .await
+                    .map_err(|e| $crate::Error::Internal(Box::new(e)))
+            }
+
+            async fn update(
+                &self,
+                id: $id_ty,
+                new: $new,
+            ) -> Result<Option<$entity>, $crate::Error> {
+                static SQL: ::std::sync::LazyLock<String> =
+                    ::std::sync::LazyLock::new(|| $repo::TABLE.update());
+
+                ::sqlx::query_as::<_, $entity>(&SQL)
+                    $(.bind(new.$column))+
+                    .bind(id)
+                    .fetch_optional(&self.pool)
+                    .await
+                    .map_err(|e| $crate::Error::Internal(Box::new(e)))
+            }
+
+            async fn delete(&self, id: $id_ty) -> Result<(), $crate::Error> {
+                static SQL: ::std::sync::LazyLock<String> =
+                    ::std::sync::LazyLock::new(|| $repo::TABLE.delete());
+
+                let result = ::sqlx::query(&SQL)
+                    .bind(id)
+                    .execute(&self.pool)
+                    .await
+                    .map_err(|e| $crate::Error::Internal(Box::new(e)))?;
+
+                if result.rows_affected() == 0 {
+                    return Err($crate::Error::NotFound);
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+pub struct Table {
+    pub name: &'static str,
+    pub id: &'static str,
+    pub columns: &'static [&'static str],
+}
+
+impl Table {
+    fn returning(&self) -> String {
+        format!("{}, {}", self.id, self.columns.join(", "))
+    }
+
+    pub fn insert(&self) -> String {
+        let params: Vec<String> = (1..=self.columns.len())
+            .map(|n| format!("${}", n))
+            .collect();
+
+        format!(
+            "INSERT INTO {} ({}) VALUES ({}) RETURNING {}",
+            self.name,
+            self.columns.join(", "),
+            params.join(", "),
+            self.returning()
+        )
+    }
+
+    pub fn select_by_id(&self) -> String {
+        format!(
+            "SELECT {} FROM {} WHERE {} = $1",
+            self.returning(),
+            self.name,
+            self.id
+        )
+    }
+
+    pub fn select_page(&self) -> String {
+        format!(
+            "SELECT {} FROM {} ORDER BY {} LIMIT $1 OFFSET $2",
+            self.returning(),
+            self.name,
+            self.id
+        )
+    }
+
+    pub fn update(&self) -> String {
+        let set: Vec<String> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(n, column)| format!("{} = ${}", column, n + 1))
+            .collect();
+
+        format!(
+            "UPDATE {} SET {} WHERE {} = ${} RETURNING {}",
+            self.name,
+            set.join(", "),
+            self.id,
+            self.columns.len() + 1,
+            self.returning()
+        )
+    }
+
+    pub fn delete(&self) -> String {
+        format!("DELETE FROM {} WHERE {} = $1", self.name, self.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{FromRow, PgPool};
+
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq, Eq, FromRow)]
+    struct Widget {
+        id: i32,
+        label: String,
+        size: i32,
+    }
+
+    crate::crud_repository! {
+        struct WidgetRepository for Widget in "crud_test_widgets" {
+            id: i32,
+            new: NewWidget { label: String, size: i32 },
+        }
+    }
+
+    async fn setup_repo(pool: PgPool) -> WidgetRepository {
+        sqlx::query(
+            "CREATE TABLE crud_test_widgets (id SERIAL PRIMARY KEY, label TEXT NOT NULL, size INT NOT NULL)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        WidgetRepository::new(pool)
+    }
+
+    #[test]
+    fn test_generated_sql() {
+        let table = Table {
+            name: "widgets",
+            id: "id",
+            columns: &["label", "size"],
+        };
+
+        assert_eq!(
+            table.insert(),
+            "INSERT INTO widgets (label, size) VALUES ($1, $2) RETURNING id, label, size"
+        );
+        assert_eq!(
+            table.update(),
+            "UPDATE widgets SET label = $1, size = $2 WHERE id = $3 RETURNING id, label, size"
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_crud_roundtrip(pool: PgPool) {
+        let repo = setup_repo(pool).await;
+
+        let created = repo
+            .create(NewWidget {
+                label: "gear".to_string(),
+                size: 3,
+            })
+            .await
+            .unwrap();
+        let updated = repo
+            .update(
+                created.id,
+                NewWidget {
+                    label: "cog".to_string(),
+                    size: 4,
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(repo.get(created.id).await.unwrap(), updated);
+        assert!(!repo.list(0, 10).await.unwrap().is_empty());
+
+        repo.delete(created.id).await.unwrap();
+
+        assert_eq!(repo.get(created.id).await.unwrap(), None);
+        assert!(matches!(
+            repo.delete(created.id).await.unwrap_err(),
+            Error::NotFound
+        ));
+    }
+}
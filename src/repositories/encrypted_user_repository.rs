@@ -0,0 +1,332 @@
+use std::{sync::Arc, time::SystemTime};
+
+use async_trait::async_trait;
+
+use crate::repositories::user_repository_trait::UserRepository as UserRepositoryTrait;
+use crate::{
+    Error,
+    entities::{
+        audit::AuditEntry,
+        users::{DeletedUser, User, UserStatus},
+    },
+    filter::Filter,
+    pii::{PiiCipher, PiiKeyring},
+};
+
+// with a keyring, `inner` stores names and surnames encrypted while callers
+// only ever see plaintext. Equality lookups by name still work, ordering and
+// partial matches over the ciphertext would not, so name prefix searches and
+// filters on the two fields are refused
+#[derive(Clone)]
+pub struct EncryptedUserRepository<T: UserRepositoryTrait> {
+    inner: T,
+    keyring: Option<Arc<PiiKeyring>>,
+}
+
+impl<T: UserRepositoryTrait> EncryptedUserRepository<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            keyring: None,
+        }
+    }
+
+    pub fn with_keyring(mut self, keyring: Arc<PiiKeyring>) -> Self {
+        self.keyring = Some(keyring);
+        self
+    }
+
+    // taken once per call, so a rotation never switches keys halfway through
+    fn cipher(&self) -> Option<Arc<PiiCipher>> {
+        self.keyring.as_ref().map(|k| k.current())
+    }
+
+    fn encrypt(&self, value: String) -> String {
+        match self.cipher() {
+            Some(cipher) => cipher.encrypt(&value),
+            None => value,
+        }
+    }
+
+    fn decrypt(&self, user: User) -> Result<User, Error> {
+        decrypt(self.cipher().as_deref(), user)
+    }
+
+    fn decrypt_all(&self, users: Vec<User>) -> Result<Vec<User>, Error> {
+        let cipher = self.cipher();
+        users
+            .into_iter()
+            .map(|u| decrypt(cipher.as_deref(), u))
+            .collect()
+    }
+
+    fn check_filter(&self, filter: Option<&Filter>) -> Result<(), Error> {
+        let encrypted = filter.is_some_and(|f| f.references("name") || f.references("surname"));
+        if self.keyring.is_some() && encrypted {
+            return Err(Error::FailedPrecondition(
+                "names are encrypted, filters cannot use name or surname".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn decrypt(cipher: Option<&PiiCipher>, mut user: User) -> Result<User, Error> {
+    if let Some(cipher) = cipher {
+        user.name = cipher.decrypt(&user.name)?;
+        user.surname = cipher.decrypt(&user.surname)?;
+    }
+    Ok(user)
+}
+
+#[async_trait]
+impl<T: UserRepositoryTrait> UserRepositoryTrait for EncryptedUserRepository<T> {
+    async fn create_user(&self, name: String, surname: String) -> Result<User, Error> {
+        let user = self
+            .inner
+            .create_user(self.encrypt(name), self.encrypt(surname))
+            .await?;
+        self.decrypt(user)
+    }
+
+    async fn create_users_bulk(&self, users: Vec<(String, String)>) -> Result<Vec<User>, Error> {
+        let users = match self.cipher() {
+            Some(cipher) => users
+                .into_iter()
+                .map(|(name, surname)| (cipher.encrypt(&name), cipher.encrypt(&surname)))
+                .collect(),
+            None => users,
+        };
+        let users = self.inner.create_users_bulk(users).await?;
+        self.decrypt_all(users)
+    }
+
+    async fn get_users(&self) -> Result<(Vec<User>, i32), Error> {
+        let (users, count) = self.inner.get_users().await?;
+        Ok((self.decrypt_all(users)?, count))
+    }
+
+    async fn count_users(&self) -> Result<i64, Error> {
+        self.inner.count_users().await
+    }
+
+    async fn get_users_batch(&self, offset: i32, limit: i32) -> Result<Vec<User>, Error> {
+        let users = self.inner.get_users_batch(offset, limit).await?;
+        self.decrypt_all(users)
+    }
+
+    async fn get_users_filtered(
+        &self,
+        filter: Filter,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        self.check_filter(Some(&filter))?;
+        let users = self.inner.get_users_filtered(filter, offset, limit).await?;
+        self.decrypt_all(users)
+    }
+
+    async fn get_user_by_id(&self, id: i32) -> Result<Option<User>, Error> {
+        let user = self.inner.get_user_by_id(id).await?;
+        user.map(|u| self.decrypt(u)).transpose()
+    }
+
+    async fn get_user_by_name(&self, name: String) -> Result<Option<User>, Error> {
+        let Some(cipher) = self.cipher() else {
+            return self.inner.get_user_by_name(name).await;
+        };
+        let users = self
+            .inner
+            .get_users_by_names(cipher.candidates(&name))
+            .await?;
+        users
+            .into_iter()
+            .next()
+            .map(|u| self.decrypt(u))
+            .transpose()
+    }
+
+    async fn get_users_by_names(&self, names: Vec<String>) -> Result<Vec<User>, Error> {
+        let names = match self.cipher() {
+            Some(cipher) => names.iter().flat_map(|n| cipher.candidates(n)).collect(),
+            None => names,
+        };
+        let users = self.inner.get_users_by_names(names).await?;
+        self.decrypt_all(users)
+    }
+
+    async fn get_users_by_name_prefix(
+        &self,
+        prefix: String,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        if self.keyring.is_some() {
+            return Err(Error::FailedPrecondition(
+                "names are encrypted, they cannot be searched by prefix".to_string(),
+            ));
+        }
+        self.inner.get_users_by_name_prefix(prefix, limit).await
+    }
+
+    async fn user_exists(&self, id: i32) -> Result<bool, Error> {
+        self.inner.user_exists(id).await
+    }
+
+    async fn update_user(
+        &self,
+        id: i32,
+        name: Option<String>,
+        surname: Option<String>,
+    ) -> Result<Option<User>, Error> {
+        let user = self
+            .inner
+            .update_user(
+                id,
+                name.map(|n| self.encrypt(n)),
+                surname.map(|s| self.encrypt(s)),
+            )
+            .await?;
+        user.map(|u| self.decrypt(u)).transpose()
+    }
+
+    async fn delete_user(&self, id: i32) -> Result<(), Error> {
+        self.inner.delete_user(id).await
+    }
+
+    async fn soft_delete_user(&self, id: i32) -> Result<(), Error> {
+        self.inner.soft_delete_user(id).await
+    }
+
+    async fn restore_user(&self, id: i32) -> Result<Option<User>, Error> {
+        let user = self.inner.restore_user(id).await?;
+        user.map(|u| self.decrypt(u)).transpose()
+    }
+
+    async fn get_deleted_users(
+        &self,
+        after_id: i32,
+        limit: i32,
+    ) -> Result<Vec<DeletedUser>, Error> {
+        let deleted = self.inner.get_deleted_users(after_id, limit).await?;
+        deleted
+            .into_iter()
+            .map(|d| {
+                Ok(DeletedUser {
+                    user: self.decrypt(d.user)?,
+                    deleted_at: d.deleted_at,
+                })
+            })
+            .collect()
+    }
+
+    async fn merge_users(
+        &self,
+        primary_id: i32,
+        duplicate_id: i32,
+        audit: Vec<AuditEntry>,
+    ) -> Result<User, Error> {
+        let user = self
+            .inner
+            .merge_users(primary_id, duplicate_id, audit)
+            .await?;
+        self.decrypt(user)
+    }
+
+    async fn get_user_status(&self, id: i32) -> Result<Option<UserStatus>, Error> {
+        self.inner.get_user_status(id).await
+    }
+
+    async fn set_user_status(
+        &self,
+        id: i32,
+        from: UserStatus,
+        to: UserStatus,
+    ) -> Result<bool, Error> {
+        self.inner.set_user_status(id, from, to).await
+    }
+
+    async fn erase_user(&self, id: i32, name: String, surname: String) -> Result<(), Error> {
+        self.inner
+            .erase_user(id, self.encrypt(name), self.encrypt(surname))
+            .await
+    }
+
+    async fn get_user_by_id_as_of(
+        &self,
+        id: i32,
+        as_of: SystemTime,
+    ) -> Result<Option<User>, Error> {
+        let user = self.inner.get_user_by_id_as_of(id, as_of).await?;
+        user.map(|u| self.decrypt(u)).transpose()
+    }
+
+    async fn get_users_as_of(
+        &self,
+        filter: Option<Filter>,
+        as_of: SystemTime,
+    ) -> Result<Vec<User>, Error> {
+        self.check_filter(filter.as_ref())?;
+        let users = self.inner.get_users_as_of(filter, as_of).await?;
+        self.decrypt_all(users)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        filter::USER_FIELDS, repositories::memory_user_repository::InMemoryUserRepository,
+    };
+
+    #[tokio::test]
+    async fn test_stores_ciphertext_and_returns_plaintext() {
+        let inner = InMemoryUserRepository::new();
+        let cipher = PiiCipher::from_keys(vec![(1, vec![1; 32])]).unwrap();
+        let repo = EncryptedUserRepository::new(inner.clone())
+            .with_keyring(Arc::new(PiiKeyring::new(cipher)));
+
+        let created = repo
+            .create_user("Ann".to_string(), "Lee".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            (created.name.as_str(), created.surname.as_str()),
+            ("Ann", "Lee")
+        );
+        let stored = inner.get_user_by_id(created.id).await.unwrap().unwrap();
+        assert!(stored.name.starts_with("pii1:1:"));
+
+        // rows from before encryption read and match as they are
+        let plain = inner
+            .create_user("Bob".to_string(), "Ray".to_string())
+            .await
+            .unwrap();
+        assert_eq!(repo.get_user_by_id(plain.id).await.unwrap(), Some(plain));
+        assert_eq!(
+            repo.get_user_by_name("Ann".to_string()).await.unwrap(),
+            Some(created.clone())
+        );
+        let ids: Vec<i32> = repo
+            .get_users_by_names(vec!["Ann".to_string(), "Bob".to_string()])
+            .await
+            .unwrap()
+            .iter()
+            .map(|u| u.id)
+            .collect();
+        assert_eq!(ids.len(), 2);
+
+        assert!(matches!(
+            repo.get_users_by_name_prefix("A".to_string(), 10).await,
+            Err(Error::FailedPrecondition(_))
+        ));
+        let by_name = Filter::parse(r#"name = "Ann""#, USER_FIELDS)
+            .unwrap()
+            .unwrap();
+        assert!(repo.get_users_filtered(by_name, 0, 10).await.is_err());
+        let by_id = Filter::parse("id > 0", USER_FIELDS).unwrap().unwrap();
+        assert_eq!(
+            repo.get_users_filtered(by_id, 0, 10).await.unwrap().len(),
+            2
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use async_trait::async_trait;
+use sqlx::{PgPool, Row};
+use tokio_stream::StreamExt;
+
+use crate::repositories::import_repository_trait::{
+    ImportData, ImportRepository as ImportRepositoryTrait,
+};
+use crate::{Error, metrics::registry};
+
+// what the varchar columns of `users` hold
+const MAX_NAME_LEN: i32 = 255;
+
+// COPYs the rows into a staging table, checks them there and moves them into
+// `users` with one INSERT, all in one transaction: a failed import leaves
+// nothing behind and readers see every row at once. The staging table only
+// lives as long as the transaction, so the queries are not checked ones
+#[derive(Clone)]
+pub struct ImportRepository {
+    pool: PgPool,
+}
+
+impl ImportRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ImportRepositoryTrait for ImportRepository {
+    async fn import_users(&self, mut data: ImportData, strict: bool) -> Result<u64, Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        sqlx::query(
+            r#"
+                CREATE TEMPORARY TABLE users_import (
+                    line bigint GENERATED ALWAYS AS IDENTITY,
+                    name text,
+                    surname text
+                ) ON COMMIT DROP
+            "#,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        let mut copy = tx
+            .copy_in_raw(
+                "COPY users_import (name, surname) FROM STDIN WITH (FORMAT csv, HEADER true)",
+            )
+            .await
+            .map_err(copy_error)?;
+        while let Some(piece) = data.next().await {
+            match piece {
+                Ok(piece) => {
+                    copy.send(piece).await.map_err(copy_error)?;
+                }
+                Err(e) => {
+                    // the rollback drops what was copied either way
+                    let _ = copy.abort("the import data failed").await;
+                    return Err(e);
+                }
+            }
+        }
+        copy.finish().await.map_err(copy_error)?;
+
+        // the first invalid row, counting from the one after the header
+        let invalid = sqlx::query(
+            r#"
+                SELECT line, problem FROM (
+                    SELECT line, CASE
+                        WHEN name IS NULL THEN 'name is missing'
+                        WHEN surname IS NULL THEN 'surname is missing'
+                        WHEN char_length(name) > $1 THEN 'name must be at most ' || $1 || ' characters'
+                        WHEN char_length(surname) > $1 THEN 'surname must be at most ' || $1 || ' characters'
+                        WHEN $2 AND btrim(name) = '' THEN 'name must not be empty'
+                        WHEN $2 AND btrim(surname) = '' THEN 'surname must not be empty'
+                        WHEN $2 AND (name || surname) ~ '[[:cntrl:]]'
+                            THEN 'names must not contain control characters'
+                    END AS problem
+                    FROM users_import
+                ) checked
+                WHERE problem IS NOT NULL
+                ORDER BY line
+                LIMIT 1
+            "#,
+        )
+        .bind(MAX_NAME_LEN)
+        .bind(strict)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+        if let Some(row) = invalid {
+            let line: i64 = row.get("line");
+            let problem: String = row.get("problem");
+            return Err(Error::InvalidArgument(format!(
+                "row {}: {}, nothing was imported",
+                line, problem
+            )));
+        }
+
+        let imported = sqlx::query(
+            r#"
+                INSERT INTO users (name, surname)
+                SELECT name, surname FROM users_import ORDER BY line
+            "#,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?
+        .rows_affected();
+        tx.commit()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        registry().increment_counter("users_imported_total", &[], imported);
+
+        Ok(imported)
+    }
+}
+
+// Postgres rejecting what it was sent is malformed CSV
+fn copy_error(e: sqlx::Error) -> Error {
+    match e {
+        sqlx::Error::Database(e) => Error::InvalidArgument(format!("invalid CSV: {}", e.message())),
+        e => Error::Internal(Box::new(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pieces(pieces: &[&str]) -> ImportData {
+        let pieces: Vec<Result<Vec<u8>, Error>> =
+            pieces.iter().map(|p| Ok(p.as_bytes().to_vec())).collect();
+        Box::pin(tokio_stream::iter(pieces))
+    }
+
+    async fn names(pool: &PgPool) -> Vec<(String, String)> {
+        sqlx::query_as("SELECT name, surname FROM users ORDER BY id")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn test_import_users(pool: PgPool) {
+        let repo = ImportRepository::new(pool.clone());
+
+        // split inside a row and inside a quoted field
+        let data = pieces(&[
+            "name,surname\r\nAda,Love",
+            "lace\r\n\"Smith, ",
+            "Jr\",John\r\n",
+        ]);
+        let imported = repo.import_users(data, true).await.unwrap();
+
+        assert_eq!(imported, 2);
+        assert_eq!(
+            names(&pool).await,
+            [
+                ("Ada".to_string(), "Lovelace".to_string()),
+                ("Smith, Jr".to_string(), "John".to_string())
+            ]
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_import_users_is_all_or_nothing(pool: PgPool) {
+        let repo = ImportRepository::new(pool.clone());
+
+        let long = "x".repeat(256);
+        let data = format!("name,surname\nAda,Lovelace\n{},Hopper\n", long);
+        let err = repo
+            .import_users(pieces(&[&data]), false)
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, Error::InvalidArgument(msg) if msg.starts_with("row 2: name")));
+
+        // empty names are only rejected when strict
+        let data = pieces(&["name,surname\nAda,\"\"\n"]);
+        let err = repo.import_users(data, true).await.unwrap_err();
+        assert!(matches!(&err, Error::InvalidArgument(msg) if msg.starts_with("row 1: surname")));
+
+        let err = repo
+            .import_users(pieces(&["name,surname\nAda\n"]), false)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidArgument(_)));
+
+        assert!(names(&pool).await.is_empty());
+    }
+}
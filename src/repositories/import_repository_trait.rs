@@ -0,0 +1,17 @@
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use tokio_stream::Stream;
+
+use crate::Error;
+
+// consecutive pieces of one CSV file, split anywhere
+pub type ImportData = Pin<Box<dyn Stream<Item = Result<Vec<u8>, Error>> + Send>>;
+
+#[async_trait]
+pub trait ImportRepository: Send + Sync {
+    // creates a user per `name,surname` row after the header and answers how
+    // many, or creates none when a row is invalid or the data fails. `strict`
+    // also rejects empty names and control characters
+    async fn import_users(&self, data: ImportData, strict: bool) -> Result<u64, Error>;
+}
@@ -0,0 +1,574 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+
+use crate::repositories::user_repository_trait::UserRepository as UserRepositoryTrait;
+use crate::{
+    Error,
+    entities::{
+        audit::AuditEntry,
+        users::{DeletedUser, User, UserStatus},
+    },
+    filter::Filter,
+};
+
+#[derive(Default)]
+struct State {
+    last_id: i32,
+    users: BTreeMap<i32, StoredUser>,
+    // every version in write order, the memory counterpart of user_history
+    history: Vec<Version>,
+}
+
+impl State {
+    fn record(&mut self, user: &User, deleted: bool) {
+        self.history.push(Version {
+            changed_at: SystemTime::now(),
+            user: user.clone(),
+            deleted,
+        });
+    }
+
+    // the latest version per user at `as_of`, deleted ones dropped
+    fn as_of(&self, as_of: SystemTime) -> BTreeMap<i32, &User> {
+        let mut users = BTreeMap::new();
+        for version in self.history.iter().filter(|v| v.changed_at <= as_of) {
+            if version.deleted {
+                users.remove(&version.user.id);
+            } else {
+                users.insert(version.user.id, &version.user);
+            }
+        }
+        users
+    }
+}
+
+struct StoredUser {
+    user: User,
+    // set by a soft delete
+    deleted_at: Option<SystemTime>,
+    status: UserStatus,
+}
+
+struct Version {
+    changed_at: SystemTime,
+    user: User,
+    deleted: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct InMemoryUserRepository {
+    state: Arc<RwLock<State>>,
+}
+
+impl InMemoryUserRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn live(state: &State) -> impl Iterator<Item = &User> {
+        state
+            .users
+            .values()
+            .filter(|stored| stored.deleted_at.is_none())
+            .map(|stored| &stored.user)
+    }
+
+    // what the listings show: live users that are not suspended
+    fn listed(state: &State) -> impl Iterator<Item = &User> {
+        state
+            .users
+            .values()
+            .filter(|stored| stored.deleted_at.is_none() && stored.status == UserStatus::Active)
+            .map(|stored| &stored.user)
+    }
+}
+
+#[async_trait]
+impl UserRepositoryTrait for InMemoryUserRepository {
+    async fn create_user(&self, name: String, surname: String) -> Result<User, Error> {
+        let mut state = self.state.write().unwrap();
+        state.last_id += 1;
+
+        let user = User {
+            id: state.last_id,
+            name,
+            surname,
+        };
+        state.users.insert(
+            user.id,
+            StoredUser {
+                user: user.clone(),
+                deleted_at: None,
+                status: UserStatus::Active,
+            },
+        );
+        state.record(&user, false);
+
+        Ok(user)
+    }
+
+    async fn get_users(&self) -> Result<(Vec<User>, i32), Error> {
+        let state = self.state.read().unwrap();
+        let users = Self::listed(&state).cloned().collect::<Vec<User>>();
+        let count = users.len();
+
+        Ok((users, count as i32))
+    }
+
+    async fn count_users(&self) -> Result<i64, Error> {
+        let state = self.state.read().unwrap();
+
+        Ok(Self::listed(&state).count() as i64)
+    }
+
+    async fn get_users_batch(&self, offset: i32, limit: i32) -> Result<Vec<User>, Error> {
+        let state = self.state.read().unwrap();
+
+        Ok(Self::listed(&state)
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_users_filtered(
+        &self,
+        filter: Filter,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        let state = self.state.read().unwrap();
+
+        let mut users = Vec::new();
+        for user in Self::listed(&state) {
+            match filter.matches(user) {
+                Some(true) => users.push(user.clone()),
+                Some(false) => {}
+                None => {
+                    return Err(Error::InvalidArgument(
+                        "filter uses a field the memory backend does not store".to_string(),
+                    ));
+                }
+            }
+        }
+
+        Ok(users
+            .into_iter()
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .collect())
+    }
+
+    async fn get_user_by_id(&self, id: i32) -> Result<Option<User>, Error> {
+        let state = self.state.read().unwrap();
+
+        Ok(Self::live(&state).find(|u| u.id == id).cloned())
+    }
+
+    async fn get_user_by_name(&self, name: String) -> Result<Option<User>, Error> {
+        let state = self.state.read().unwrap();
+
+        Ok(Self::live(&state).find(|u| u.name == name).cloned())
+    }
+
+    async fn get_users_by_names(&self, names: Vec<String>) -> Result<Vec<User>, Error> {
+        let state = self.state.read().unwrap();
+
+        Ok(Self::live(&state)
+            .filter(|u| names.contains(&u.name))
+            .cloned()
+            .collect())
+    }
+
+    async fn get_users_by_name_prefix(
+        &self,
+        prefix: String,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        let state = self.state.read().unwrap();
+
+        let mut users: Vec<User> = Self::live(&state)
+            .filter(|u| u.name.starts_with(&prefix))
+            .cloned()
+            .collect();
+        users.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+        users.truncate(limit.max(0) as usize);
+        Ok(users)
+    }
+
+    async fn user_exists(&self, id: i32) -> Result<bool, Error> {
+        let state = self.state.read().unwrap();
+
+        Ok(Self::live(&state).any(|u| u.id == id))
+    }
+
+    async fn update_user(
+        &self,
+        id: i32,
+        name: Option<String>,
+        surname: Option<String>,
+    ) -> Result<Option<User>, Error> {
+        let mut state = self.state.write().unwrap();
+        let Some(stored) = state.users.get_mut(&id).filter(|s| s.deleted_at.is_none()) else {
+            return Ok(None);
+        };
+
+        if let Some(name) = name {
+            stored.user.name = name;
+        }
+        if let Some(surname) = surname {
+            stored.user.surname = surname;
+        }
+
+        let user = stored.user.clone();
+        state.record(&user, false);
+        Ok(Some(user))
+    }
+
+    async fn delete_user(&self, id: i32) -> Result<(), Error> {
+        let mut state = self.state.write().unwrap();
+
+        match state.users.remove(&id) {
+            Some(stored) => {
+                state.record(&stored.user, true);
+                Ok(())
+            }
+            None => Err(Error::NotFound),
+        }
+    }
+
+    async fn soft_delete_user(&self, id: i32) -> Result<(), Error> {
+        let mut state = self.state.write().unwrap();
+
+        match state.users.get_mut(&id).filter(|s| s.deleted_at.is_none()) {
+            Some(stored) => {
+                stored.deleted_at = Some(SystemTime::now());
+                let user = stored.user.clone();
+                state.record(&user, true);
+                Ok(())
+            }
+            None => Err(Error::NotFound),
+        }
+    }
+
+    async fn restore_user(&self, id: i32) -> Result<Option<User>, Error> {
+        let mut state = self.state.write().unwrap();
+        let Some(stored) = state.users.get_mut(&id).filter(|s| s.deleted_at.is_some()) else {
+            return Ok(None);
+        };
+
+        stored.deleted_at = None;
+        let user = stored.user.clone();
+        state.record(&user, false);
+        Ok(Some(user))
+    }
+
+    async fn get_deleted_users(
+        &self,
+        after_id: i32,
+        limit: i32,
+    ) -> Result<Vec<DeletedUser>, Error> {
+        let state = self.state.read().unwrap();
+
+        Ok(state
+            .users
+            .range(after_id.saturating_add(1)..)
+            .filter_map(|(_, stored)| {
+                stored.deleted_at.map(|deleted_at| DeletedUser {
+                    user: stored.user.clone(),
+                    deleted_at,
+                })
+            })
+            .take(limit.max(0) as usize)
+            .collect())
+    }
+
+    async fn merge_users(
+        &self,
+        _primary_id: i32,
+        _duplicate_id: i32,
+        _audit: Vec<AuditEntry>,
+    ) -> Result<User, Error> {
+        Err(Error::InvalidArgument(
+            "merging users needs DATABASE_BACKEND=postgres".to_string(),
+        ))
+    }
+
+    async fn get_user_status(&self, id: i32) -> Result<Option<UserStatus>, Error> {
+        let state = self.state.read().unwrap();
+
+        Ok(state
+            .users
+            .get(&id)
+            .filter(|stored| stored.deleted_at.is_none())
+            .map(|stored| stored.status))
+    }
+
+    async fn set_user_status(
+        &self,
+        id: i32,
+        from: UserStatus,
+        to: UserStatus,
+    ) -> Result<bool, Error> {
+        let mut state = self.state.write().unwrap();
+
+        match state.users.get_mut(&id) {
+            Some(stored) if stored.deleted_at.is_none() && stored.status == from => {
+                stored.status = to;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn erase_user(&self, id: i32, name: String, surname: String) -> Result<(), Error> {
+        let mut state = self.state.write().unwrap();
+        let mut erased = false;
+        for version in state.history.iter_mut().filter(|v| v.user.id == id) {
+            version.user.name.clone_from(&name);
+            version.user.surname.clone_from(&surname);
+            erased = true;
+        }
+
+        match state.users.get_mut(&id) {
+            Some(stored) => {
+                stored.user.name = name;
+                stored.user.surname = surname;
+                let (user, deleted) = (stored.user.clone(), stored.deleted_at.is_some());
+                state.record(&user, deleted);
+                Ok(())
+            }
+            None if erased => Ok(()),
+            None => Err(Error::NotFound),
+        }
+    }
+
+    async fn get_user_by_id_as_of(
+        &self,
+        id: i32,
+        as_of: SystemTime,
+    ) -> Result<Option<User>, Error> {
+        let state = self.state.read().unwrap();
+
+        Ok(state.as_of(as_of).remove(&id).cloned())
+    }
+
+    async fn get_users_as_of(
+        &self,
+        filter: Option<Filter>,
+        as_of: SystemTime,
+    ) -> Result<Vec<User>, Error> {
+        let state = self.state.read().unwrap();
+
+        let mut users = Vec::new();
+        for user in state.as_of(as_of).into_values() {
+            match filter.as_ref().map_or(Some(true), |f| f.matches(user)) {
+                Some(true) => users.push(user.clone()),
+                Some(false) => {}
+                None => {
+                    return Err(Error::InvalidArgument(
+                        "filter uses a field the memory backend does not store".to_string(),
+                    ));
+                }
+            }
+        }
+
+        Ok(users)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_create_and_get_user() {
+        let repo = InMemoryUserRepository::new();
+
+        let created = repo
+            .create_user("Memory".to_string(), "User".to_string())
+            .await
+            .unwrap();
+        let found = repo.get_user_by_id(created.id).await.unwrap();
+
+        assert_eq!(found, Some(created));
+    }
+
+    #[tokio::test]
+    async fn test_update_user_keeps_unset_fields() {
+        let repo = InMemoryUserRepository::new();
+        let created = repo
+            .create_user("Update".to_string(), "Me".to_string())
+            .await
+            .unwrap();
+
+        let updated = repo
+            .update_user(created.id, None, Some("You".to_string()))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(updated.name, "Update");
+        assert_eq!(updated.surname, "You");
+    }
+
+    #[tokio::test]
+    async fn test_soft_deleted_users_are_hidden() {
+        let repo = InMemoryUserRepository::new();
+        let created = repo
+            .create_user("Soft".to_string(), "Delete".to_string())
+            .await
+            .unwrap();
+
+        repo.soft_delete_user(created.id).await.unwrap();
+
+        assert!(repo.get_user_by_id(created.id).await.unwrap().is_none());
+        assert_eq!(repo.count_users().await.unwrap(), 0);
+        assert!(matches!(
+            repo.delete_user(999).await.unwrap_err(),
+            Error::NotFound
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_suspended_users_are_not_listed() {
+        let repo = InMemoryUserRepository::new();
+        let created = repo
+            .create_user("Suspended".to_string(), "User".to_string())
+            .await
+            .unwrap();
+
+        assert!(
+            repo.set_user_status(created.id, UserStatus::Active, UserStatus::Suspended)
+                .await
+                .unwrap()
+        );
+
+        assert_eq!(repo.get_users().await.unwrap(), (Vec::new(), 0));
+        assert_eq!(
+            repo.get_user_by_id(created.id).await.unwrap(),
+            Some(created.clone())
+        );
+        assert!(
+            !repo
+                .set_user_status(created.id, UserStatus::Active, UserStatus::Suspended)
+                .await
+                .unwrap()
+        );
+        assert!(
+            repo.set_user_status(created.id, UserStatus::Suspended, UserStatus::Active)
+                .await
+                .unwrap()
+        );
+        assert_eq!(repo.count_users().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_restore_user() {
+        let repo = InMemoryUserRepository::new();
+        let created = repo
+            .create_user("Soft".to_string(), "Restore".to_string())
+            .await
+            .unwrap();
+        repo.soft_delete_user(created.id).await.unwrap();
+
+        let deleted = repo.get_deleted_users(0, 10).await.unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].user, created);
+
+        assert_eq!(
+            repo.restore_user(created.id).await.unwrap(),
+            Some(created.clone())
+        );
+        assert_eq!(
+            repo.get_user_by_id(created.id).await.unwrap(),
+            Some(created.clone())
+        );
+        assert_eq!(repo.restore_user(created.id).await.unwrap(), None);
+        repo.delete_user(created.id).await.unwrap();
+        assert_eq!(repo.restore_user(created.id).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_erase_user_rewrites_history() {
+        let repo = InMemoryUserRepository::new();
+        let created = repo
+            .create_user("Erase".to_string(), "Me".to_string())
+            .await
+            .unwrap();
+        let before = SystemTime::now();
+        repo.delete_user(created.id).await.unwrap();
+
+        repo.erase_user(created.id, "x".to_string(), "y".to_string())
+            .await
+            .unwrap();
+
+        let erased = repo
+            .get_user_by_id_as_of(created.id, before)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((erased.name.as_str(), erased.surname.as_str()), ("x", "y"));
+        assert!(matches!(
+            repo.erase_user(999, "x".to_string(), "y".to_string())
+                .await
+                .unwrap_err(),
+            Error::NotFound
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_users_batch() {
+        let repo = InMemoryUserRepository::new();
+        for i in 0..5 {
+            repo.create_user(format!("Batch{}", i), "User".to_string())
+                .await
+                .unwrap();
+        }
+
+        let batch = repo.get_users_batch(2, 2).await.unwrap();
+
+        assert_eq!(
+            batch.iter().map(|u| u.name.as_str()).collect::<Vec<_>>(),
+            vec!["Batch2", "Batch3"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reads_as_of() {
+        let repo = InMemoryUserRepository::new();
+        let before = SystemTime::now();
+        let user = repo
+            .create_user("Past".to_string(), "Self".to_string())
+            .await
+            .unwrap();
+        let created = SystemTime::now();
+        repo.update_user(user.id, None, Some("Changed".to_string()))
+            .await
+            .unwrap();
+        let updated = SystemTime::now();
+        repo.delete_user(user.id).await.unwrap();
+
+        assert_eq!(
+            repo.get_user_by_id_as_of(user.id, before).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            repo.get_user_by_id_as_of(user.id, created).await.unwrap(),
+            Some(user.clone())
+        );
+        let at_update = repo.get_users_as_of(None, updated).await.unwrap();
+        assert_eq!(at_update[0].surname, "Changed");
+        assert!(
+            repo.get_users_as_of(None, SystemTime::now())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
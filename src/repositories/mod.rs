@@ -1,4 +1,50 @@
+pub mod address_repository;
+pub mod address_repository_trait;
+pub mod any_user_repository;
+pub mod api_key_repository;
+pub mod api_key_repository_trait;
+pub mod audit_repository;
+pub mod audit_repository_trait;
+pub mod backup_repository;
+pub mod backup_repository_trait;
+pub mod cached_user_repository;
+pub mod consent_repository;
+pub mod consent_repository_trait;
+pub mod crud;
+pub mod encrypted_user_repository;
+pub mod failover;
+pub mod import_repository;
+pub mod import_repository_trait;
+pub mod memory_user_repository;
+pub mod pii_repository;
+pub mod pii_repository_trait;
+pub mod pool_metrics;
+pub mod queries;
+pub mod relationship_repository;
+pub mod relationship_repository_trait;
+pub mod revision_repository;
+pub mod revision_repository_trait;
+pub mod shadow_user_repository;
+pub mod sharded_user_repository;
+pub mod slow_user_repository;
+pub mod sqlite_user_repository;
+pub mod stats_repository;
+pub mod stats_repository_trait;
+pub mod temporal_user_repository;
+pub mod user_event_repository;
+pub mod user_event_repository_trait;
 pub mod user_repository;
 pub mod user_repository_trait;
 
+pub use address_repository_trait::AddressRepository;
+pub use api_key_repository_trait::ApiKeyRepository;
+pub use audit_repository_trait::AuditRepository;
+pub use backup_repository_trait::BackupRepository;
+pub use consent_repository_trait::ConsentRepository;
+pub use import_repository_trait::ImportRepository;
+pub use pii_repository_trait::PiiRepository;
+pub use relationship_repository_trait::RelationshipRepository;
+pub use revision_repository_trait::RevisionRepository;
+pub use stats_repository_trait::StatsRepository;
+pub use user_event_repository_trait::UserEventRepository;
 pub use user_repository_trait::UserRepository;
@@ -0,0 +1,499 @@
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use crate::repositories::pii_repository_trait::{PiiRepository as PiiRepositoryTrait, Recode};
+use crate::{
+    Error,
+    entities::pii::{KeyRotation, WrappedDataKey},
+};
+
+#[derive(Clone)]
+pub struct PiiRepository {
+    pool: PgPool,
+}
+
+impl PiiRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl PiiRepositoryTrait for PiiRepository {
+    async fn data_keys(&self) -> Result<Vec<WrappedDataKey>, Error> {
+        let rows = crate::query_as!(
+            DataKeyRow,
+            r#"
+                SELECT id, wrapped_key, activated_at IS NOT NULL AS "active!"
+                FROM pii_data_keys
+                ORDER BY id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(rows
+            .into_iter()
+            .map(|r| WrappedDataKey {
+                id: r.id,
+                wrapped_key: r.wrapped_key,
+                active: r.active,
+            })
+            .collect())
+    }
+
+    async fn add_data_key(&self, wrapped_key: Vec<u8>) -> Result<i32, Error> {
+        crate::query_scalar!(
+            i32,
+            r#"
+                INSERT INTO pii_data_keys (wrapped_key)
+                VALUES ($1)
+                RETURNING id
+            "#,
+            wrapped_key
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))
+    }
+
+    async fn user_ids_after(&self, after_id: i32, limit: i32) -> Result<Vec<i32>, Error> {
+        crate::query_scalar!(
+            i32,
+            r#"
+                SELECT id
+                FROM users
+                WHERE id > $1
+                ORDER BY id
+                LIMIT $2
+            "#,
+            after_id,
+            limit as i64
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))
+    }
+
+    async fn recode_users(&self, ids: &[i32], recode: &Recode<'_>) -> Result<u64, Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        // until the transaction ends
+        sqlx::query("SELECT set_config('gin_tonik.skip_user_history', 'on', true)")
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        // locked, so a concurrent update is neither lost nor recoded stale
+        let users = crate::query_as!(
+            StoredRow,
+            r#"
+                SELECT id::bigint AS "id!", name, surname
+                FROM users
+                WHERE id = ANY($1)
+                FOR UPDATE
+            "#,
+            ids
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+        let history = crate::query_as!(
+            StoredRow,
+            r#"
+                SELECT id AS "id!", name, surname
+                FROM user_history
+                WHERE user_id = ANY($1)
+                FOR UPDATE
+            "#,
+            ids
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        let mut changed = 0;
+        for row in users {
+            let Some((name, surname)) = row.recoded(recode)? else {
+                continue;
+            };
+            crate::query!(
+                r#"
+                    UPDATE users
+                    SET name = $1, surname = $2
+                    WHERE id = $3
+                "#,
+                name,
+                surname,
+                row.id as i32
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+            changed += 1;
+        }
+        for row in history {
+            let Some((name, surname)) = row.recoded(recode)? else {
+                continue;
+            };
+            crate::query!(
+                r#"
+                    UPDATE user_history
+                    SET name = $1, surname = $2
+                    WHERE id = $3
+                "#,
+                name,
+                surname,
+                row.id
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+            changed += 1;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(changed)
+    }
+
+    async fn start_rotation(
+        &self,
+        wrapped_key: Vec<u8>,
+        batch_size: u32,
+        max_users_per_second: Option<u32>,
+    ) -> Result<KeyRotation, Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        let data_key_id = crate::query_scalar!(
+            i32,
+            r#"
+                INSERT INTO pii_data_keys (wrapped_key, activated_at)
+                VALUES ($1, NULL)
+                RETURNING id
+            "#,
+            wrapped_key
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+        let row = crate::query_as!(
+            RotationRow,
+            r#"
+                INSERT INTO pii_key_rotations (data_key_id, batch_size, max_users_per_second)
+                VALUES ($1, $2, $3)
+                RETURNING
+                    id,
+                    data_key_id,
+                    state,
+                    batch_size,
+                    max_users_per_second,
+                    after_user_id,
+                    values_rewritten,
+                    (extract(epoch FROM started_at) * 1000000)::bigint AS "started_at_micros!",
+                    (extract(epoch FROM updated_at) * 1000000)::bigint AS "updated_at_micros!",
+                    (extract(epoch FROM finished_at) * 1000000)::bigint AS "finished_at_micros?"
+            "#,
+            data_key_id,
+            batch_size as i32,
+            max_users_per_second.map(|n| n as i32)
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| match e.as_database_error().and_then(|db| db.code()) {
+            // unique_violation of pii_key_rotations_unfinished
+            Some(code) if code == "23505" => {
+                Error::FailedPrecondition("another key rotation is unfinished".to_string())
+            }
+            _ => Error::Internal(Box::new(e)),
+        })?;
+        tx.commit()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        row.into_rotation()
+    }
+
+    async fn rotation(&self, id: i32) -> Result<Option<KeyRotation>, Error> {
+        let row = crate::query_as!(
+            RotationRow,
+            r#"
+                SELECT
+                    id,
+                    data_key_id,
+                    state,
+                    batch_size,
+                    max_users_per_second,
+                    after_user_id,
+                    values_rewritten,
+                    (extract(epoch FROM started_at) * 1000000)::bigint AS "started_at_micros!",
+                    (extract(epoch FROM updated_at) * 1000000)::bigint AS "updated_at_micros!",
+                    (extract(epoch FROM finished_at) * 1000000)::bigint AS "finished_at_micros?"
+                FROM pii_key_rotations
+                WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        row.map(RotationRow::into_rotation).transpose()
+    }
+
+    async fn latest_rotation(&self) -> Result<Option<KeyRotation>, Error> {
+        let row = crate::query_as!(
+            RotationRow,
+            r#"
+                SELECT
+                    id,
+                    data_key_id,
+                    state,
+                    batch_size,
+                    max_users_per_second,
+                    after_user_id,
+                    values_rewritten,
+                    (extract(epoch FROM started_at) * 1000000)::bigint AS "started_at_micros!",
+                    (extract(epoch FROM updated_at) * 1000000)::bigint AS "updated_at_micros!",
+                    (extract(epoch FROM finished_at) * 1000000)::bigint AS "finished_at_micros?"
+                FROM pii_key_rotations
+                ORDER BY id DESC
+                LIMIT 1
+            "#
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        row.map(RotationRow::into_rotation).transpose()
+    }
+
+    async fn claim_rotation(&self, id: i32, owner: &str, lease: Duration) -> Result<bool, Error> {
+        let result = crate::query!(
+            r#"
+                UPDATE pii_key_rotations
+                SET owner = $2, heartbeat_at = now()
+                WHERE id = $1
+                    AND finished_at IS NULL
+                    AND (
+                        owner IS NULL
+                        OR owner = $2
+                        OR heartbeat_at < now() - $3::float8 * interval '1 second'
+                    )
+            "#,
+            id,
+            owner,
+            lease.as_secs_f64()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn activate_rotation_key(&self, id: i32, owner: &str) -> Result<bool, Error> {
+        let result = crate::query!(
+            r#"
+                WITH rotation AS (
+                    UPDATE pii_key_rotations
+                    SET state = 'rewriting', heartbeat_at = now(), updated_at = now()
+                    WHERE id = $1 AND owner = $2 AND state = 'pending'
+                    RETURNING data_key_id
+                )
+                UPDATE pii_data_keys
+                SET activated_at = now()
+                WHERE id IN (SELECT data_key_id FROM rotation)
+            "#,
+            id,
+            owner
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn record_rotation_progress(
+        &self,
+        id: i32,
+        owner: &str,
+        after_user_id: i32,
+        values_rewritten: u64,
+        done: bool,
+    ) -> Result<bool, Error> {
+        let result = crate::query!(
+            r#"
+                UPDATE pii_key_rotations
+                SET
+                    after_user_id = $3,
+                    values_rewritten = values_rewritten + $4,
+                    state = CASE WHEN $5 THEN 'done' ELSE state END,
+                    finished_at = CASE WHEN $5 THEN now() END,
+                    heartbeat_at = now(),
+                    updated_at = now()
+                WHERE id = $1 AND owner = $2 AND finished_at IS NULL
+            "#,
+            id,
+            owner,
+            after_user_id,
+            values_rewritten as i64,
+            done
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct DataKeyRow {
+    id: i32,
+    wrapped_key: Vec<u8>,
+    #[sqlx(rename = "active!")]
+    active: bool,
+}
+
+#[derive(sqlx::FromRow)]
+struct RotationRow {
+    id: i32,
+    data_key_id: i32,
+    state: String,
+    batch_size: i32,
+    max_users_per_second: Option<i32>,
+    after_user_id: i32,
+    values_rewritten: i64,
+    #[sqlx(rename = "started_at_micros!")]
+    started_at_micros: i64,
+    #[sqlx(rename = "updated_at_micros!")]
+    updated_at_micros: i64,
+    #[sqlx(rename = "finished_at_micros?")]
+    finished_at_micros: Option<i64>,
+}
+
+impl RotationRow {
+    fn into_rotation(self) -> Result<KeyRotation, Error> {
+        let time =
+            |micros: i64| SystemTime::UNIX_EPOCH + Duration::from_micros(micros.max(0) as u64);
+        Ok(KeyRotation {
+            id: self.id,
+            data_key_id: self.data_key_id,
+            state: self
+                .state
+                .parse()
+                .map_err(|e: String| Error::Internal(e.into()))?,
+            batch_size: self.batch_size as u32,
+            max_users_per_second: self.max_users_per_second.map(|n| n as u32),
+            after_user_id: self.after_user_id,
+            values_rewritten: self.values_rewritten as u64,
+            started_at: time(self.started_at_micros),
+            updated_at: time(self.updated_at_micros),
+            finished_at: self.finished_at_micros.map(time),
+        })
+    }
+}
+
+// a row of users or user_history
+#[derive(sqlx::FromRow)]
+struct StoredRow {
+    #[sqlx(rename = "id!")]
+    id: i64,
+    name: String,
+    surname: String,
+}
+
+impl StoredRow {
+    fn recoded(&self, recode: &Recode<'_>) -> Result<Option<(String, String)>, Error> {
+        let name = recode(&self.name)?;
+        let surname = recode(&self.surname)?;
+        if name.is_none() && surname.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some((
+            name.unwrap_or_else(|| self.name.clone()),
+            surname.unwrap_or_else(|| self.surname.clone()),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entities::pii::RotationState,
+        repositories::{UserRepository as _, user_repository::UserRepository},
+    };
+
+    #[sqlx::test]
+    async fn test_recode_users_leaves_no_history(pool: PgPool) {
+        let repo = PiiRepository::new(pool.clone());
+        let user = UserRepository::new(pool.clone())
+            .create_user("Ann".to_string(), "Lee".to_string())
+            .await
+            .unwrap();
+
+        let upper = |v: &str| -> Result<Option<String>, Error> {
+            Ok((v != v.to_uppercase()).then(|| v.to_uppercase()))
+        };
+        assert_eq!(repo.user_ids_after(0, 10).await.unwrap(), [user.id]);
+        // the user and its one version
+        assert_eq!(repo.recode_users(&[user.id], &upper).await.unwrap(), 2);
+        assert_eq!(repo.recode_users(&[user.id], &upper).await.unwrap(), 0);
+
+        let history: Vec<(String,)> =
+            sqlx::query_as("SELECT name FROM user_history WHERE user_id = $1")
+                .bind(user.id)
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(history, [("ANN".to_string(),)]);
+    }
+
+    #[sqlx::test]
+    async fn test_rotation_lease(pool: PgPool) {
+        let repo = PiiRepository::new(pool);
+        let rotation = repo.start_rotation(vec![1], 10, None).await.unwrap();
+        assert!(!repo.data_keys().await.unwrap()[0].active);
+
+        let lease = Duration::from_secs(60);
+        assert!(repo.claim_rotation(rotation.id, "a", lease).await.unwrap());
+        assert!(!repo.claim_rotation(rotation.id, "b", lease).await.unwrap());
+        // once `a` stops renewing it
+        assert!(
+            repo.claim_rotation(rotation.id, "b", Duration::ZERO)
+                .await
+                .unwrap()
+        );
+        assert!(!repo.activate_rotation_key(rotation.id, "a").await.unwrap());
+        assert!(repo.activate_rotation_key(rotation.id, "b").await.unwrap());
+        assert!(repo.data_keys().await.unwrap()[0].active);
+
+        assert!(
+            repo.record_rotation_progress(rotation.id, "b", 5, 3, true)
+                .await
+                .unwrap()
+        );
+        let done = repo.latest_rotation().await.unwrap().unwrap();
+        assert_eq!(
+            (done.state, done.after_user_id, done.values_rewritten),
+            (RotationState::Done, 5, 3)
+        );
+        assert!(!repo.claim_rotation(done.id, "b", lease).await.unwrap());
+        assert!(repo.start_rotation(vec![2], 10, None).await.is_ok());
+    }
+}
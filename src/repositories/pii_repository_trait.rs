@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use crate::{
+    Error,
+    entities::pii::{KeyRotation, WrappedDataKey},
+};
+use async_trait::async_trait;
+
+// maps a stored name or surname to what it should be stored as, None to leave
+// it as it is
+pub type Recode<'a> = dyn Fn(&str) -> Result<Option<String>, Error> + Send + Sync + 'a;
+
+#[async_trait]
+pub trait PiiRepository: Send + Sync {
+    // every data key by id, the newest last
+    async fn data_keys(&self) -> Result<Vec<WrappedDataKey>, Error>;
+    // an active data key
+    async fn add_data_key(&self, wrapped_key: Vec<u8>) -> Result<i32, Error>;
+    // up to `limit` user ids above `after_id`, soft deleted or not, by id
+    async fn user_ids_after(&self, after_id: i32, limit: i32) -> Result<Vec<i32>, Error>;
+    // recodes the names and surnames of the users and of their history in one
+    // transaction, without recording new versions; the values changed
+    async fn recode_users(&self, ids: &[i32], recode: &Recode<'_>) -> Result<u64, Error>;
+
+    // adds the wrapped key, not active yet, with a pending rotation to it;
+    // FailedPrecondition while another rotation is unfinished
+    async fn start_rotation(
+        &self,
+        wrapped_key: Vec<u8>,
+        batch_size: u32,
+        max_users_per_second: Option<u32>,
+    ) -> Result<KeyRotation, Error>;
+    async fn rotation(&self, id: i32) -> Result<Option<KeyRotation>, Error>;
+    // the newest rotation, the only one that may be unfinished
+    async fn latest_rotation(&self) -> Result<Option<KeyRotation>, Error>;
+    // takes or renews the lease of an unfinished rotation for `owner`; false
+    // while another owner renewed it within `lease`, or once it is done
+    async fn claim_rotation(&self, id: i32, owner: &str, lease: Duration) -> Result<bool, Error>;
+    // activates the data key of a pending rotation held by `owner`
+    async fn activate_rotation_key(&self, id: i32, owner: &str) -> Result<bool, Error>;
+    // moves the rotation past `after_user_id`, adding the values rewritten by
+    // the batch and finishing it when `done`; false once `owner` no longer
+    // holds it
+    async fn record_rotation_progress(
+        &self,
+        id: i32,
+        owner: &str,
+        after_user_id: i32,
+        values_rewritten: u64,
+        done: bool,
+    ) -> Result<bool, Error>;
+}
@@ -0,0 +1,57 @@
+use std::time::{Duration, Instant};
+
+use sqlx::{PgPool, Postgres, pool::PoolConnection};
+use tracing::warn;
+
+use crate::{
+    Error,
+    metrics::{DURATION_BUCKETS, registry},
+};
+
+pub const DEFAULT_ACQUIRE_WARN_THRESHOLD: Duration = Duration::from_millis(100);
+
+pub async fn acquire(
+    pool: &PgPool,
+    warn_threshold: Duration,
+) -> Result<PoolConnection<Postgres>, Error> {
+    let started = Instant::now();
+    let res = pool.acquire().await;
+    let waited = started.elapsed();
+
+    let registry = registry();
+    registry.observe(
+        "db_pool_acquire_duration_seconds",
+        &[],
+        DURATION_BUCKETS,
+        waited.as_secs_f64(),
+    );
+    record_pool_state(pool);
+
+    if waited >= warn_threshold {
+        warn!(
+            "waited {:?} for a database connection (size={}, idle={}, max={})",
+            waited,
+            pool.size(),
+            pool.num_idle(),
+            pool.options().get_max_connections()
+        );
+    }
+
+    res.map_err(|e| {
+        if matches!(e, sqlx::Error::PoolTimedOut) {
+            registry.increment_counter("db_pool_acquire_timeouts_total", &[], 1);
+        }
+        Error::Internal(Box::new(e))
+    })
+}
+
+pub fn record_pool_state(pool: &PgPool) {
+    let registry = registry();
+    registry.set_gauge("db_pool_connections", &[], pool.size() as f64);
+    registry.set_gauge("db_pool_idle_connections", &[], pool.num_idle() as f64);
+    registry.set_gauge(
+        "db_pool_max_connections",
+        &[],
+        pool.options().get_max_connections() as f64,
+    );
+}
@@ -0,0 +1,56 @@
+// drop-in `sqlx` query macros for the postgres repositories: checked against
+// the database (or `.sqlx/`) at compile time, or with the `runtime-queries`
+// feature the same SQL is sent unchecked, for builds that have neither.
+// Runtime rows come from `sqlx::FromRow` by column name, so a column named
+// with an `AS "col!"` override needs `#[sqlx(rename = "col!")]` on its field
+// (scalars decode by position), and `query!` has no row type at all: it is only
+// for statements returning none.
+
+#[cfg(not(feature = "runtime-queries"))]
+#[macro_export]
+macro_rules! query {
+    ($($args:tt)*) => {
+        ::sqlx::query!($($args)*)
+    };
+}
+
+#[cfg(not(feature = "runtime-queries"))]
+#[macro_export]
+macro_rules! query_as {
+    ($($args:tt)*) => {
+        ::sqlx::query_as!($($args)*)
+    };
+}
+
+// the type is only needed at runtime, the checked macro infers it
+#[cfg(not(feature = "runtime-queries"))]
+#[macro_export]
+macro_rules! query_scalar {
+    ($ty:ty, $($args:tt)*) => {
+        ::sqlx::query_scalar!($($args)*)
+    };
+}
+
+#[cfg(feature = "runtime-queries")]
+#[macro_export]
+macro_rules! query {
+    ($sql:expr $(, $arg:expr)* $(,)?) => {
+        ::sqlx::query($sql)$(.bind($arg))*
+    };
+}
+
+#[cfg(feature = "runtime-queries")]
+#[macro_export]
+macro_rules! query_as {
+    ($row:ty, $sql:expr $(, $arg:expr)* $(,)?) => {
+        ::sqlx::query_as::<_, $row>($sql)$(.bind($arg))*
+    };
+}
+
+#[cfg(feature = "runtime-queries")]
+#[macro_export]
+macro_rules! query_scalar {
+    ($ty:ty, $sql:expr $(, $arg:expr)* $(,)?) => {
+        ::sqlx::query_scalar::<_, $ty>($sql)$(.bind($arg))*
+    };
+}
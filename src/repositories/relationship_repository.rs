@@ -0,0 +1,254 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use crate::repositories::relationship_repository_trait::RelationshipRepository as RelationshipRepositoryTrait;
+use crate::{
+    Error,
+    entities::{
+        relationships::{RelatedUser, Relationship, RelationshipKind},
+        users::User,
+    },
+};
+
+#[derive(Clone)]
+pub struct RelationshipRepository {
+    pool: PgPool,
+}
+
+impl RelationshipRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl RelationshipRepositoryTrait for RelationshipRepository {
+    async fn add_relationship(&self, relationship: Relationship) -> Result<(), Error> {
+        crate::query!(
+            r#"
+                INSERT INTO user_relationships (user_id, related_user_id, kind)
+                VALUES ($1, $2, $3)
+                ON CONFLICT DO NOTHING
+            "#,
+            relationship.user_id,
+            relationship.related_user_id,
+            relationship.kind.as_str()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e.as_database_error().and_then(|db| db.code()) {
+            // foreign_key_violation: one of the users does not exist
+            Some(code) if code == "23503" => Error::NotFound,
+            Some(code) if code == "23514" => {
+                Error::InvalidArgument("a user cannot be related to itself".to_string())
+            }
+            _ => Error::Internal(Box::new(e)),
+        })?;
+
+        Ok(())
+    }
+
+    async fn remove_relationship(&self, relationship: Relationship) -> Result<(), Error> {
+        let result = crate::query!(
+            r#"
+                DELETE FROM user_relationships
+                WHERE user_id = $1 AND related_user_id = $2 AND kind = $3
+            "#,
+            relationship.user_id,
+            relationship.related_user_id,
+            relationship.kind.as_str()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn list_related_users(
+        &self,
+        user_id: i32,
+        kind: RelationshipKind,
+        max_depth: i32,
+        after_id: i32,
+        limit: i32,
+    ) -> Result<Vec<RelatedUser>, Error> {
+        // the path array keeps the walk from revisiting users, so cycles terminate
+        let res = crate::query_as!(
+            RelatedRow,
+            r#"
+                WITH RECURSIVE related (id, depth, path) AS (
+                    SELECT r.related_user_id, 1, ARRAY[r.user_id, r.related_user_id]
+                    FROM user_relationships r
+                    WHERE r.user_id = $1 AND r.kind = $2
+                    UNION ALL
+                    SELECT r.related_user_id, related.depth + 1, related.path || r.related_user_id
+                    FROM related
+                    JOIN user_relationships r ON r.user_id = related.id AND r.kind = $2
+                    WHERE related.depth < $3 AND NOT r.related_user_id = ANY(related.path)
+                )
+                SELECT u.id, u.name, u.surname, MIN(related.depth) AS "depth!"
+                FROM related
+                JOIN users u ON u.id = related.id
+                WHERE u.deleted_at IS NULL AND u.id <> $1 AND u.id > $4
+                GROUP BY u.id, u.name, u.surname
+                ORDER BY u.id
+                LIMIT $5
+            "#,
+            user_id,
+            kind.as_str(),
+            max_depth,
+            after_id,
+            limit as i64
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?
+        .into_iter()
+        .map(|row| RelatedUser {
+            user: User {
+                id: row.id,
+                name: row.name,
+                surname: row.surname,
+            },
+            depth: row.depth,
+        })
+        .collect();
+
+        Ok(res)
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct RelatedRow {
+    id: i32,
+    name: String,
+    surname: String,
+    #[sqlx(rename = "depth!")]
+    depth: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::{
+        user_repository::UserRepository,
+        user_repository_trait::UserRepository as UserRepositoryTrait,
+    };
+
+    async fn create_users(pool: &PgPool, n: usize) -> Vec<User> {
+        let users = UserRepository::new(pool.clone());
+        let mut created = Vec::with_capacity(n);
+        for i in 0..n {
+            created.push(
+                users
+                    .create_user(format!("Related{}", i), "Graph".to_string())
+                    .await
+                    .unwrap(),
+            );
+        }
+        created
+    }
+
+    fn friend(user_id: i32, related_user_id: i32) -> Relationship {
+        Relationship {
+            user_id,
+            related_user_id,
+            kind: RelationshipKind::Friend,
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_list_related_users_with_cycle(pool: PgPool) {
+        let repo = RelationshipRepository::new(pool.clone());
+        let users = create_users(&pool, 3).await;
+        let (a, b, c) = (users[0].id, users[1].id, users[2].id);
+
+        repo.add_relationship(friend(a, b)).await.unwrap();
+        repo.add_relationship(friend(b, c)).await.unwrap();
+        repo.add_relationship(friend(c, a)).await.unwrap();
+
+        let direct = repo
+            .list_related_users(a, RelationshipKind::Friend, 1, 0, 10)
+            .await
+            .unwrap();
+        let transitive = repo
+            .list_related_users(a, RelationshipKind::Friend, 5, 0, 10)
+            .await
+            .unwrap();
+
+        assert_eq!(direct.len(), 1);
+        assert_eq!(direct[0].user.id, b);
+        assert_eq!(
+            transitive
+                .iter()
+                .map(|r| (r.user.id, r.depth))
+                .collect::<Vec<_>>(),
+            vec![(b, 1), (c, 2)]
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_list_related_users_pagination(pool: PgPool) {
+        let repo = RelationshipRepository::new(pool.clone());
+        let users = create_users(&pool, 4).await;
+        for user in &users[1..] {
+            repo.add_relationship(friend(users[0].id, user.id))
+                .await
+                .unwrap();
+        }
+
+        let first = repo
+            .list_related_users(users[0].id, RelationshipKind::Friend, 1, 0, 2)
+            .await
+            .unwrap();
+        let rest = repo
+            .list_related_users(
+                users[0].id,
+                RelationshipKind::Friend,
+                1,
+                first[1].user.id,
+                2,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(first.len(), 2);
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].user.id, users[3].id);
+    }
+
+    #[sqlx::test]
+    async fn test_remove_relationship(pool: PgPool) {
+        let repo = RelationshipRepository::new(pool.clone());
+        let users = create_users(&pool, 2).await;
+
+        repo.add_relationship(friend(users[0].id, users[1].id))
+            .await
+            .unwrap();
+        repo.remove_relationship(friend(users[0].id, users[1].id))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            repo.remove_relationship(friend(users[0].id, users[1].id))
+                .await
+                .unwrap_err(),
+            Error::NotFound
+        ));
+    }
+
+    #[sqlx::test]
+    async fn test_add_relationship_unknown_user(pool: PgPool) {
+        let repo = RelationshipRepository::new(pool.clone());
+        let users = create_users(&pool, 1).await;
+
+        let result = repo.add_relationship(friend(users[0].id, 99999)).await;
+
+        assert!(matches!(result.unwrap_err(), Error::NotFound));
+    }
+}
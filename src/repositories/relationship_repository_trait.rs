@@ -0,0 +1,19 @@
+use crate::{
+    Error,
+    entities::relationships::{RelatedUser, Relationship, RelationshipKind},
+};
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait RelationshipRepository: Send + Sync + Clone {
+    async fn add_relationship(&self, relationship: Relationship) -> Result<(), Error>;
+    async fn remove_relationship(&self, relationship: Relationship) -> Result<(), Error>;
+    async fn list_related_users(
+        &self,
+        user_id: i32,
+        kind: RelationshipKind,
+        max_depth: i32,
+        after_id: i32,
+        limit: i32,
+    ) -> Result<Vec<RelatedUser>, Error>;
+}
@@ -0,0 +1,234 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use crate::repositories::revision_repository_trait::RevisionRepository as RevisionRepositoryTrait;
+use crate::{
+    Error,
+    entities::{
+        revisions::{RevisionAuthor, UserRevision},
+        users::User,
+    },
+    pii::PiiKeyring,
+};
+
+// reads `user_history`; the author of a version is taken from the first
+// audit entry about the user written between it and the next version, so
+// versions from before the audit log have none
+#[derive(Clone)]
+pub struct RevisionRepository {
+    pool: PgPool,
+    keyring: Option<Arc<PiiKeyring>>,
+}
+
+impl RevisionRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            keyring: None,
+        }
+    }
+
+    // past versions are stored encrypted like the users themselves
+    pub fn with_keyring(mut self, keyring: Arc<PiiKeyring>) -> Self {
+        self.keyring = Some(keyring);
+        self
+    }
+
+    async fn revisions(
+        &self,
+        user_id: i32,
+        revision: Option<i64>,
+    ) -> Result<Vec<UserRevision>, Error> {
+        let rows = crate::query_as!(
+            RevisionRow,
+            r#"
+                SELECT
+                    h.revision AS "revision!",
+                    h.user_id AS "user_id!",
+                    h.name AS "name!",
+                    h.surname AS "surname!",
+                    h.deleted AS "deleted!",
+                    (extract(epoch FROM h.changed_at) * 1000000)::bigint AS "changed_at_micros!",
+                    a.action AS "action?",
+                    a.actor_user_id AS "actor_user_id?",
+                    a.effective_user_id AS "effective_user_id?"
+                FROM (
+                    SELECT
+                        user_id,
+                        name,
+                        surname,
+                        deleted,
+                        changed_at,
+                        row_number() OVER (ORDER BY changed_at, id) AS revision,
+                        lead(changed_at) OVER (ORDER BY changed_at, id) AS next_changed_at
+                    FROM user_history
+                    WHERE user_id = $1
+                ) h
+                LEFT JOIN LATERAL (
+                    SELECT action, actor_user_id, effective_user_id
+                    FROM user_audit_log
+                    WHERE target_user_id = h.user_id
+                        AND created_at >= h.changed_at
+                        AND (h.next_changed_at IS NULL OR created_at < h.next_changed_at)
+                    ORDER BY id
+                    LIMIT 1
+                ) a ON true
+                WHERE $2::bigint IS NULL OR h.revision = $2
+                ORDER BY h.revision
+            "#,
+            user_id,
+            revision
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        let cipher = self.keyring.as_ref().map(|k| k.current());
+        rows.into_iter()
+            .map(|row| {
+                let mut revision = row.into_revision()?;
+                if let Some(cipher) = &cipher {
+                    revision.user.name = cipher.decrypt(&revision.user.name)?;
+                    revision.user.surname = cipher.decrypt(&revision.user.surname)?;
+                }
+                Ok(revision)
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl RevisionRepositoryTrait for RevisionRepository {
+    async fn list_revisions(&self, user_id: i32) -> Result<Vec<UserRevision>, Error> {
+        self.revisions(user_id, None).await
+    }
+
+    async fn get_revision(
+        &self,
+        user_id: i32,
+        revision: i64,
+    ) -> Result<Option<UserRevision>, Error> {
+        Ok(self.revisions(user_id, Some(revision)).await?.pop())
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct RevisionRow {
+    #[sqlx(rename = "revision!")]
+    revision: i64,
+    #[sqlx(rename = "user_id!")]
+    user_id: i32,
+    #[sqlx(rename = "name!")]
+    name: String,
+    #[sqlx(rename = "surname!")]
+    surname: String,
+    #[sqlx(rename = "deleted!")]
+    deleted: bool,
+    #[sqlx(rename = "changed_at_micros!")]
+    changed_at_micros: i64,
+    #[sqlx(rename = "action?")]
+    action: Option<String>,
+    #[sqlx(rename = "actor_user_id?")]
+    actor_user_id: Option<i32>,
+    #[sqlx(rename = "effective_user_id?")]
+    effective_user_id: Option<i32>,
+}
+
+impl RevisionRow {
+    fn into_revision(self) -> Result<UserRevision, Error> {
+        let changed_by = match self.action {
+            Some(action) => Some(RevisionAuthor {
+                action: action
+                    .parse()
+                    .map_err(|e: String| Error::Internal(e.into()))?,
+                actor_user_id: self.actor_user_id,
+                effective_user_id: self.effective_user_id,
+            }),
+            None => None,
+        };
+
+        Ok(UserRevision {
+            revision: self.revision,
+            user: User {
+                id: self.user_id,
+                name: self.name,
+                surname: self.surname,
+            },
+            deleted: self.deleted,
+            changed_at: SystemTime::UNIX_EPOCH
+                + Duration::from_micros(self.changed_at_micros.max(0) as u64),
+            changed_by,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entities::audit::{AuditAction, AuditEntry},
+        repositories::{
+            AuditRepository as _, UserRepository as _, audit_repository::AuditRepository,
+            user_repository::UserRepository,
+        },
+    };
+
+    #[sqlx::test]
+    async fn test_list_revisions(pool: PgPool) {
+        let users = UserRepository::new(pool.clone());
+        let audit = AuditRepository::new(pool.clone());
+        let repo = RevisionRepository::new(pool);
+
+        let user = users
+            .create_user("Ann".to_string(), "Lee".to_string())
+            .await
+            .unwrap();
+        audit
+            .record(AuditEntry {
+                action: AuditAction::CreateUser,
+                target_user_id: user.id,
+                actor_user_id: None,
+                effective_user_id: None,
+            })
+            .await
+            .unwrap();
+        users
+            .update_user(user.id, Some("Anna".to_string()), None)
+            .await
+            .unwrap();
+        audit
+            .record(AuditEntry {
+                action: AuditAction::UpdateUser,
+                target_user_id: user.id,
+                actor_user_id: Some(9),
+                effective_user_id: Some(user.id),
+            })
+            .await
+            .unwrap();
+
+        let revisions = repo.list_revisions(user.id).await.unwrap();
+
+        let names: Vec<_> = revisions.iter().map(|r| r.user.name.as_str()).collect();
+        assert_eq!(names, ["Ann", "Anna"]);
+        assert_eq!(revisions[0].revision, 1);
+        assert_eq!(
+            revisions[1].changed_by,
+            Some(RevisionAuthor {
+                action: AuditAction::UpdateUser,
+                actor_user_id: Some(9),
+                effective_user_id: Some(user.id),
+            })
+        );
+        assert_eq!(
+            repo.get_revision(user.id, 2).await.unwrap(),
+            Some(revisions[1].clone())
+        );
+        assert_eq!(repo.get_revision(user.id, 3).await.unwrap(), None);
+        assert!(repo.list_revisions(user.id + 1).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,13 @@
+use crate::{Error, entities::revisions::UserRevision};
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait RevisionRepository: Send + Sync {
+    // oldest first, empty when nothing was ever stored for the id
+    async fn list_revisions(&self, user_id: i32) -> Result<Vec<UserRevision>, Error>;
+    async fn get_revision(
+        &self,
+        user_id: i32,
+        revision: i64,
+    ) -> Result<Option<UserRevision>, Error>;
+}
@@ -0,0 +1,440 @@
+use std::{fmt::Debug, future::Future, sync::Arc, time::SystemTime};
+
+use async_trait::async_trait;
+use tokio::sync::Semaphore;
+use tracing::warn;
+
+use crate::metrics::registry;
+use crate::repositories::user_repository_trait::UserRepository as UserRepositoryTrait;
+use crate::{
+    Error,
+    entities::{
+        audit::AuditEntry,
+        users::{DeletedUser, User, UserStatus},
+    },
+    filter::Filter,
+};
+
+pub const SHADOW_COMPARISONS_TOTAL: &str = "shadow_comparisons_total";
+
+#[derive(Clone)]
+struct Shadow<T> {
+    repo: T,
+    writes: bool,
+    in_flight: Arc<Semaphore>,
+}
+
+// validates a new backend before cutover: every call is answered by `primary`,
+// then replayed against the shadow in the background and the two answers
+// compared. Without mirrored writes the shadow has to be kept in sync some other
+// way, and with them it can still briefly lag, so a mismatch right after a
+// write is not necessarily a bug
+#[derive(Clone)]
+pub struct ShadowUserRepository<T: UserRepositoryTrait> {
+    primary: T,
+    shadow: Option<Shadow<T>>,
+}
+
+impl<T: UserRepositoryTrait + 'static> ShadowUserRepository<T> {
+    pub fn new(primary: T) -> Self {
+        Self {
+            primary,
+            shadow: None,
+        }
+    }
+
+    // at most `max_in_flight` shadow calls run at once, the rest are skipped
+    // rather than queued behind a slow shadow
+    pub fn with_shadow(mut self, repo: T, writes: bool, max_in_flight: usize) -> Self {
+        self.shadow = Some(Shadow {
+            repo,
+            writes,
+            in_flight: Arc::new(Semaphore::new(max_in_flight)),
+        });
+        self
+    }
+
+    fn mirror<R, F, Fut>(&self, operation: &'static str, primary: &Result<R, Error>, call: F)
+    where
+        R: Clone + Debug + PartialEq + Send + 'static,
+        F: FnOnce(T) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<R, Error>> + Send,
+    {
+        let Some(shadow) = &self.shadow else {
+            return;
+        };
+        // nothing to hold the shadow's answer against
+        let Some(expected) = outcome(primary) else {
+            return;
+        };
+        let Ok(permit) = shadow.in_flight.clone().try_acquire_owned() else {
+            record(operation, "skipped");
+            return;
+        };
+        let repo = shadow.repo.clone();
+
+        tokio::spawn(async move {
+            let result = call(repo).await;
+            drop(permit);
+            match outcome(&result) {
+                None => {
+                    warn!(
+                        "shadow {} failed: {}",
+                        operation,
+                        result.err().map(|e| e.to_string()).unwrap_or_default()
+                    );
+                    record(operation, "error");
+                }
+                Some(actual) if actual == expected => record(operation, "match"),
+                Some(actual) => {
+                    warn!(
+                        primary = ?expected,
+                        shadow = ?actual,
+                        "shadow {} disagrees with the primary backend",
+                        operation
+                    );
+                    record(operation, "mismatch");
+                }
+            }
+        });
+    }
+
+    fn mirror_write<R, F, Fut>(&self, operation: &'static str, primary: &Result<R, Error>, call: F)
+    where
+        R: Clone + Debug + PartialEq + Send + 'static,
+        F: FnOnce(T) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<R, Error>> + Send,
+    {
+        if self.shadow.as_ref().is_some_and(|s| s.writes) {
+            self.mirror(operation, primary, call);
+        }
+    }
+}
+
+// the comparable part of a result; domain errors are answers too, but an
+// internal or unavailable error says nothing about the data
+fn outcome<R: Clone>(result: &Result<R, Error>) -> Option<Result<R, &'static str>> {
+    match result {
+        Ok(value) => Some(Ok(value.clone())),
+        Err(Error::NotFound) => Some(Err("not found")),
+        Err(Error::InvalidArgument(_)) => Some(Err("invalid argument")),
+        Err(Error::PermissionDenied) => Some(Err("permission denied")),
+        Err(Error::FailedPrecondition(_)) => Some(Err("failed precondition")),
+        Err(Error::Unavailable(_)) | Err(Error::Internal(_)) => None,
+    }
+}
+
+fn record(operation: &'static str, result: &'static str) {
+    registry().increment_counter(
+        SHADOW_COMPARISONS_TOTAL,
+        &[("operation", operation), ("result", result)],
+        1,
+    );
+}
+
+#[async_trait]
+impl<T: UserRepositoryTrait + 'static> UserRepositoryTrait for ShadowUserRepository<T> {
+    async fn create_user(&self, name: String, surname: String) -> Result<User, Error> {
+        let result = self
+            .primary
+            .create_user(name.clone(), surname.clone())
+            .await;
+        self.mirror_write("create_user", &result, move |shadow| async move {
+            shadow.create_user(name, surname).await
+        });
+        result
+    }
+
+    async fn create_users_bulk(&self, users: Vec<(String, String)>) -> Result<Vec<User>, Error> {
+        let result = self.primary.create_users_bulk(users.clone()).await;
+        self.mirror_write("create_users_bulk", &result, move |shadow| async move {
+            shadow.create_users_bulk(users).await
+        });
+        result
+    }
+
+    async fn get_users(&self) -> Result<(Vec<User>, i32), Error> {
+        let result = self.primary.get_users().await;
+        self.mirror("get_users", &result, move |shadow| async move {
+            shadow.get_users().await
+        });
+        result
+    }
+
+    async fn count_users(&self) -> Result<i64, Error> {
+        let result = self.primary.count_users().await;
+        self.mirror("count_users", &result, move |shadow| async move {
+            shadow.count_users().await
+        });
+        result
+    }
+
+    async fn get_users_batch(&self, offset: i32, limit: i32) -> Result<Vec<User>, Error> {
+        let result = self.primary.get_users_batch(offset, limit).await;
+        self.mirror("get_users_batch", &result, move |shadow| async move {
+            shadow.get_users_batch(offset, limit).await
+        });
+        result
+    }
+
+    async fn get_users_filtered(
+        &self,
+        filter: Filter,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        let result = self
+            .primary
+            .get_users_filtered(filter.clone(), offset, limit)
+            .await;
+        self.mirror("get_users_filtered", &result, move |shadow| async move {
+            shadow.get_users_filtered(filter, offset, limit).await
+        });
+        result
+    }
+
+    async fn get_user_by_id(&self, id: i32) -> Result<Option<User>, Error> {
+        let result = self.primary.get_user_by_id(id).await;
+        self.mirror("get_user_by_id", &result, move |shadow| async move {
+            shadow.get_user_by_id(id).await
+        });
+        result
+    }
+
+    async fn get_user_by_name(&self, name: String) -> Result<Option<User>, Error> {
+        let result = self.primary.get_user_by_name(name.clone()).await;
+        self.mirror("get_user_by_name", &result, move |shadow| async move {
+            shadow.get_user_by_name(name).await
+        });
+        result
+    }
+
+    async fn get_users_by_names(&self, names: Vec<String>) -> Result<Vec<User>, Error> {
+        let result = self.primary.get_users_by_names(names.clone()).await;
+        self.mirror("get_users_by_names", &result, move |shadow| async move {
+            shadow.get_users_by_names(names).await
+        });
+        result
+    }
+
+    async fn get_users_by_name_prefix(
+        &self,
+        prefix: String,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        let result = self
+            .primary
+            .get_users_by_name_prefix(prefix.clone(), limit)
+            .await;
+        self.mirror(
+            "get_users_by_name_prefix",
+            &result,
+            move |shadow| async move { shadow.get_users_by_name_prefix(prefix, limit).await },
+        );
+        result
+    }
+
+    async fn user_exists(&self, id: i32) -> Result<bool, Error> {
+        let result = self.primary.user_exists(id).await;
+        self.mirror("user_exists", &result, move |shadow| async move {
+            shadow.user_exists(id).await
+        });
+        result
+    }
+
+    async fn update_user(
+        &self,
+        id: i32,
+        name: Option<String>,
+        surname: Option<String>,
+    ) -> Result<Option<User>, Error> {
+        let result = self
+            .primary
+            .update_user(id, name.clone(), surname.clone())
+            .await;
+        self.mirror_write("update_user", &result, move |shadow| async move {
+            shadow.update_user(id, name, surname).await
+        });
+        result
+    }
+
+    async fn delete_user(&self, id: i32) -> Result<(), Error> {
+        let result = self.primary.delete_user(id).await;
+        self.mirror_write("delete_user", &result, move |shadow| async move {
+            shadow.delete_user(id).await
+        });
+        result
+    }
+
+    async fn soft_delete_user(&self, id: i32) -> Result<(), Error> {
+        let result = self.primary.soft_delete_user(id).await;
+        self.mirror_write("soft_delete_user", &result, move |shadow| async move {
+            shadow.soft_delete_user(id).await
+        });
+        result
+    }
+
+    async fn restore_user(&self, id: i32) -> Result<Option<User>, Error> {
+        let result = self.primary.restore_user(id).await;
+        self.mirror_write("restore_user", &result, move |shadow| async move {
+            shadow.restore_user(id).await
+        });
+        result
+    }
+
+    // not compared, the backends never agree on the deletion times
+    async fn get_deleted_users(
+        &self,
+        after_id: i32,
+        limit: i32,
+    ) -> Result<Vec<DeletedUser>, Error> {
+        self.primary.get_deleted_users(after_id, limit).await
+    }
+
+    async fn merge_users(
+        &self,
+        primary_id: i32,
+        duplicate_id: i32,
+        audit: Vec<AuditEntry>,
+    ) -> Result<User, Error> {
+        let result = self
+            .primary
+            .merge_users(primary_id, duplicate_id, audit.clone())
+            .await;
+        self.mirror_write("merge_users", &result, move |shadow| async move {
+            shadow.merge_users(primary_id, duplicate_id, audit).await
+        });
+        result
+    }
+
+    async fn get_user_status(&self, id: i32) -> Result<Option<UserStatus>, Error> {
+        let result = self.primary.get_user_status(id).await;
+        self.mirror("get_user_status", &result, move |shadow| async move {
+            shadow.get_user_status(id).await
+        });
+        result
+    }
+
+    async fn set_user_status(
+        &self,
+        id: i32,
+        from: UserStatus,
+        to: UserStatus,
+    ) -> Result<bool, Error> {
+        let result = self.primary.set_user_status(id, from, to).await;
+        self.mirror_write("set_user_status", &result, move |shadow| async move {
+            shadow.set_user_status(id, from, to).await
+        });
+        result
+    }
+
+    async fn erase_user(&self, id: i32, name: String, surname: String) -> Result<(), Error> {
+        let result = self
+            .primary
+            .erase_user(id, name.clone(), surname.clone())
+            .await;
+        self.mirror_write("erase_user", &result, move |shadow| async move {
+            shadow.erase_user(id, name, surname).await
+        });
+        result
+    }
+
+    async fn get_user_by_id_as_of(
+        &self,
+        id: i32,
+        as_of: SystemTime,
+    ) -> Result<Option<User>, Error> {
+        let result = self.primary.get_user_by_id_as_of(id, as_of).await;
+        self.mirror("get_user_by_id_as_of", &result, move |shadow| async move {
+            shadow.get_user_by_id_as_of(id, as_of).await
+        });
+        result
+    }
+
+    async fn get_users_as_of(
+        &self,
+        filter: Option<Filter>,
+        as_of: SystemTime,
+    ) -> Result<Vec<User>, Error> {
+        let result = self.primary.get_users_as_of(filter.clone(), as_of).await;
+        self.mirror("get_users_as_of", &result, move |shadow| async move {
+            shadow.get_users_as_of(filter, as_of).await
+        });
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::repositories::memory_user_repository::InMemoryUserRepository;
+
+    fn comparisons(operation: &'static str, result: &'static str) -> u64 {
+        match registry().get(
+            SHADOW_COMPARISONS_TOTAL,
+            &[("operation", operation), ("result", result)],
+        ) {
+            Some(crate::metrics::Value::Counter(n)) => n,
+            _ => 0,
+        }
+    }
+
+    // the comparison runs in the background
+    async fn settle() {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    #[tokio::test]
+    async fn test_compares_reads_and_mirrors_writes() {
+        let shadow = InMemoryUserRepository::new();
+        let repo = ShadowUserRepository::new(InMemoryUserRepository::new()).with_shadow(
+            shadow.clone(),
+            true,
+            8,
+        );
+
+        let created = repo
+            .create_user("Ada".to_string(), "Lovelace".to_string())
+            .await
+            .unwrap();
+        settle().await;
+        assert_eq!(
+            shadow.get_user_by_id(created.id).await.unwrap(),
+            Some(created.clone())
+        );
+
+        let matched = comparisons("user_exists", "match");
+        repo.user_exists(created.id).await.unwrap();
+        settle().await;
+        assert_eq!(comparisons("user_exists", "match"), matched + 1);
+
+        // the shadow drifts behind the mirror's back
+        shadow
+            .update_user(created.id, Some("Grace".to_string()), None)
+            .await
+            .unwrap();
+        let mismatched = comparisons("get_user_by_id", "mismatch");
+        repo.get_user_by_id(created.id).await.unwrap();
+        settle().await;
+        assert_eq!(comparisons("get_user_by_id", "mismatch"), mismatched + 1);
+    }
+
+    #[tokio::test]
+    async fn test_leaves_the_shadow_alone_without_write_mirroring() {
+        let shadow = InMemoryUserRepository::new();
+        let repo = ShadowUserRepository::new(InMemoryUserRepository::new()).with_shadow(
+            shadow.clone(),
+            false,
+            8,
+        );
+
+        repo.create_user("Ada".to_string(), "Lovelace".to_string())
+            .await
+            .unwrap();
+        settle().await;
+
+        assert_eq!(shadow.count_users().await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+use tokio::task::JoinSet;
+
+use crate::repositories::{
+    user_repository::UserRepository, user_repository_trait::UserRepository as UserRepositoryTrait,
+};
+use crate::{
+    Error,
+    entities::{
+        audit::AuditEntry,
+        users::{DeletedUser, User, UserStatus},
+    },
+    filter::Filter,
+};
+
+// ids are generated so that `id mod shard count` is the shard holding the row,
+// which means the shard map can only grow by re-sharding existing rows
+#[derive(Clone)]
+pub struct ShardedUserRepository {
+    shards: Vec<UserRepository>,
+    next_shard: Arc<AtomicUsize>,
+}
+
+impl ShardedUserRepository {
+    pub fn new(shards: Vec<UserRepository>) -> Result<Self, Error> {
+        if shards.is_empty() {
+            return Err(Error::Internal("sharded repository needs a shard".into()));
+        }
+
+        Ok(Self {
+            shards,
+            next_shard: Arc::default(),
+        })
+    }
+
+    pub fn shards(&self) -> &[UserRepository] {
+        &self.shards
+    }
+
+    fn shard(&self, id: i32) -> &UserRepository {
+        &self.shards[shard_for(id, self.shards.len())]
+    }
+
+    async fn fan_out<R, F>(&self, query: impl Fn(UserRepository) -> F) -> Result<Vec<R>, Error>
+    where
+        F: Future<Output = Result<R, Error>> + Send + 'static,
+        R: Send + 'static,
+    {
+        let mut tasks: JoinSet<_> = self.shards.iter().cloned().map(query).collect();
+        let mut results = Vec::with_capacity(self.shards.len());
+        while let Some(result) = tasks.join_next().await {
+            results.push(result.map_err(|e| Error::Internal(Box::new(e)))??);
+        }
+
+        Ok(results)
+    }
+}
+
+pub fn shard_for(id: i32, shard_count: usize) -> usize {
+    id.rem_euclid(shard_count as i32) as usize
+}
+
+fn merge_batch(shards: Vec<Vec<User>>, offset: i32, limit: i32) -> Vec<User> {
+    let mut users: Vec<User> = shards.into_iter().flatten().collect();
+    users.sort_by_key(|u| u.id);
+
+    users
+        .into_iter()
+        .skip(offset.max(0) as usize)
+        .take(limit.max(0) as usize)
+        .collect()
+}
+
+#[async_trait]
+impl UserRepositoryTrait for ShardedUserRepository {
+    async fn create_user(&self, name: String, surname: String) -> Result<User, Error> {
+        let shard = self.next_shard.fetch_add(1, Ordering::Relaxed) % self.shards.len();
+
+        self.shards[shard]
+            .create_user_in_shard(shard as i32, self.shards.len() as i32, name, surname)
+            .await
+    }
+
+    async fn get_users(&self) -> Result<(Vec<User>, i32), Error> {
+        let mut users: Vec<User> = self
+            .fan_out(|shard| async move { shard.get_users().await.map(|(users, _)| users) })
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+        users.sort_by_key(|u| u.id);
+        let count = users.len();
+
+        Ok((users, count as i32))
+    }
+
+    async fn count_users(&self) -> Result<i64, Error> {
+        let counts = self
+            .fan_out(|shard| async move { shard.count_users().await })
+            .await?;
+
+        Ok(counts.into_iter().sum())
+    }
+
+    async fn get_users_batch(&self, offset: i32, limit: i32) -> Result<Vec<User>, Error> {
+        // any shard may hold every row of the page, so each one is asked for all of it
+        let window = offset.saturating_add(limit);
+        let shards = self
+            .fan_out(|shard| async move { shard.get_users_batch(0, window).await })
+            .await?;
+
+        Ok(merge_batch(shards, offset, limit))
+    }
+
+    async fn get_users_filtered(
+        &self,
+        filter: Filter,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        let window = offset.saturating_add(limit);
+        let shards = self
+            .fan_out(|shard| {
+                let filter = filter.clone();
+                async move { shard.get_users_filtered(filter, 0, window).await }
+            })
+            .await?;
+
+        Ok(merge_batch(shards, offset, limit))
+    }
+
+    async fn get_user_by_id(&self, id: i32) -> Result<Option<User>, Error> {
+        self.shard(id).get_user_by_id(id).await
+    }
+
+    async fn get_user_by_name(&self, name: String) -> Result<Option<User>, Error> {
+        let found = self
+            .fan_out(|shard| {
+                let name = name.clone();
+                async move { shard.get_user_by_name(name).await }
+            })
+            .await?;
+
+        Ok(found.into_iter().flatten().min_by_key(|u| u.id))
+    }
+
+    async fn get_users_by_names(&self, names: Vec<String>) -> Result<Vec<User>, Error> {
+        let shards = self
+            .fan_out(|shard| {
+                let names = names.clone();
+                async move { shard.get_users_by_names(names).await }
+            })
+            .await?;
+
+        let mut users: Vec<User> = shards.into_iter().flatten().collect();
+        users.sort_by_key(|u| u.id);
+        Ok(users)
+    }
+
+    async fn get_users_by_name_prefix(
+        &self,
+        prefix: String,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        let shards = self
+            .fan_out(|shard| {
+                let prefix = prefix.clone();
+                async move { shard.get_users_by_name_prefix(prefix, limit).await }
+            })
+            .await?;
+
+        let mut users: Vec<User> = shards.into_iter().flatten().collect();
+        users.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+        users.truncate(limit.max(0) as usize);
+        Ok(users)
+    }
+
+    async fn user_exists(&self, id: i32) -> Result<bool, Error> {
+        self.shard(id).user_exists(id).await
+    }
+
+    async fn update_user(
+        &self,
+        id: i32,
+        name: Option<String>,
+        surname: Option<String>,
+    ) -> Result<Option<User>, Error> {
+        self.shard(id).update_user(id, name, surname).await
+    }
+
+    async fn delete_user(&self, id: i32) -> Result<(), Error> {
+        self.shard(id).delete_user(id).await
+    }
+
+    async fn soft_delete_user(&self, id: i32) -> Result<(), Error> {
+        self.shard(id).soft_delete_user(id).await
+    }
+
+    async fn restore_user(&self, id: i32) -> Result<Option<User>, Error> {
+        self.shard(id).restore_user(id).await
+    }
+
+    async fn get_deleted_users(
+        &self,
+        after_id: i32,
+        limit: i32,
+    ) -> Result<Vec<DeletedUser>, Error> {
+        let shards = self
+            .fan_out(|shard| async move { shard.get_deleted_users(after_id, limit).await })
+            .await?;
+
+        let mut users: Vec<DeletedUser> = shards.into_iter().flatten().collect();
+        users.sort_by_key(|d| d.user.id);
+        users.truncate(limit.max(0) as usize);
+        Ok(users)
+    }
+
+    // a merge is one transaction, which cannot span shards
+    async fn merge_users(
+        &self,
+        primary_id: i32,
+        duplicate_id: i32,
+        audit: Vec<AuditEntry>,
+    ) -> Result<User, Error> {
+        let shard = shard_for(primary_id, self.shards.len());
+        if shard != shard_for(duplicate_id, self.shards.len()) {
+            return Err(Error::InvalidArgument(
+                "users on different shards cannot be merged".to_string(),
+            ));
+        }
+        self.shards[shard]
+            .merge_users(primary_id, duplicate_id, audit)
+            .await
+    }
+
+    async fn get_user_status(&self, id: i32) -> Result<Option<UserStatus>, Error> {
+        self.shard(id).get_user_status(id).await
+    }
+
+    async fn set_user_status(
+        &self,
+        id: i32,
+        from: UserStatus,
+        to: UserStatus,
+    ) -> Result<bool, Error> {
+        self.shard(id).set_user_status(id, from, to).await
+    }
+
+    async fn erase_user(&self, id: i32, name: String, surname: String) -> Result<(), Error> {
+        self.shard(id).erase_user(id, name, surname).await
+    }
+
+    async fn get_user_by_id_as_of(
+        &self,
+        id: i32,
+        as_of: SystemTime,
+    ) -> Result<Option<User>, Error> {
+        self.shard(id).get_user_by_id_as_of(id, as_of).await
+    }
+
+    async fn get_users_as_of(
+        &self,
+        filter: Option<Filter>,
+        as_of: SystemTime,
+    ) -> Result<Vec<User>, Error> {
+        let shards = self
+            .fan_out(|shard| {
+                let filter = filter.clone();
+                async move { shard.get_users_as_of(filter, as_of).await }
+            })
+            .await?;
+
+        let mut users: Vec<User> = shards.into_iter().flatten().collect();
+        users.sort_by_key(|u| u.id);
+        Ok(users)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: i32) -> User {
+        User {
+            id,
+            name: format!("User{}", id),
+            surname: "Sharded".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_shard_for() {
+        assert_eq!(shard_for(6, 3), 0);
+        assert_eq!(shard_for(7, 3), 1);
+        assert_eq!(shard_for(-1, 3), 2);
+        assert_eq!(shard_for(42, 1), 0);
+    }
+
+    #[test]
+    fn test_merge_batch() {
+        let shards = vec![
+            vec![user(3), user(6), user(9)],
+            vec![user(1), user(4)],
+            vec![user(2), user(5), user(8)],
+        ];
+
+        let page = merge_batch(shards, 2, 3);
+
+        assert_eq!(page, vec![user(3), user(4), user(5)]);
+    }
+}
@@ -0,0 +1,346 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+
+use crate::repositories::user_repository_trait::UserRepository as UserRepositoryTrait;
+use crate::{
+    Error,
+    entities::{
+        audit::AuditEntry,
+        users::{DeletedUser, User, UserStatus},
+    },
+    filter::Filter,
+};
+
+// every operation a delay can be set for, besides `*`
+pub const OPERATIONS: &[&str] = &[
+    "create_user",
+    "create_users_bulk",
+    "get_users",
+    "count_users",
+    "get_users_batch",
+    "get_users_filtered",
+    "get_user_by_id",
+    "get_user_by_name",
+    "get_users_by_names",
+    "get_users_by_name_prefix",
+    "user_exists",
+    "update_user",
+    "delete_user",
+    "soft_delete_user",
+    "restore_user",
+    "get_deleted_users",
+    "merge_users",
+    "get_user_status",
+    "set_user_status",
+    "erase_user",
+    "get_user_by_id_as_of",
+    "get_users_as_of",
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SlowOperation {
+    pub delay: Duration,
+    // after the delay, fail like a pool that never handed out a connection
+    // instead of running the operation
+    pub time_out: bool,
+}
+
+// the delays in effect, shared between the repository and the admin service
+// that changes them at runtime
+#[derive(Clone, Default)]
+pub struct SlowOperations(Arc<RwLock<BTreeMap<String, SlowOperation>>>);
+
+impl SlowOperations {
+    // a zero delay without a timeout removes the operation's entry
+    pub fn set(&self, operation: &str, slow: SlowOperation) -> Result<(), Error> {
+        if operation != "*" && !OPERATIONS.contains(&operation) {
+            return Err(Error::InvalidArgument(format!(
+                "unknown repository operation {:?}",
+                operation
+            )));
+        }
+
+        let mut operations = self.0.write().unwrap();
+        if slow == SlowOperation::default() {
+            operations.remove(operation);
+        } else {
+            operations.insert(operation.to_string(), slow);
+        }
+        Ok(())
+    }
+
+    pub fn clear(&self) {
+        self.0.write().unwrap().clear();
+    }
+
+    pub fn list(&self) -> Vec<(String, SlowOperation)> {
+        let operations = self.0.read().unwrap();
+        operations
+            .iter()
+            .map(|(op, slow)| (op.clone(), *slow))
+            .collect()
+    }
+
+    // the operation's own entry wins over `*`
+    fn get(&self, operation: &str) -> Option<SlowOperation> {
+        let operations = self.0.read().unwrap();
+        operations
+            .get(operation)
+            .or_else(|| operations.get("*"))
+            .copied()
+    }
+}
+
+// a slow database for chaos drills: holds each operation back by the delay set
+// for it, or times it out, before handing it to `inner`
+#[derive(Clone)]
+pub struct SlowUserRepository<T: UserRepositoryTrait> {
+    inner: T,
+    operations: SlowOperations,
+}
+
+impl<T: UserRepositoryTrait> SlowUserRepository<T> {
+    pub fn new(inner: T, operations: SlowOperations) -> Self {
+        Self { inner, operations }
+    }
+
+    async fn hold(&self, operation: &str) -> Result<(), Error> {
+        let Some(slow) = self.operations.get(operation) else {
+            return Ok(());
+        };
+        tokio::time::sleep(slow.delay).await;
+
+        if slow.time_out {
+            return Err(Error::Internal(Box::new(sqlx::Error::PoolTimedOut)));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: UserRepositoryTrait> UserRepositoryTrait for SlowUserRepository<T> {
+    async fn create_user(&self, name: String, surname: String) -> Result<User, Error> {
+        self.hold("create_user").await?;
+        self.inner.create_user(name, surname).await
+    }
+
+    async fn create_users_bulk(&self, users: Vec<(String, String)>) -> Result<Vec<User>, Error> {
+        self.hold("create_users_bulk").await?;
+        self.inner.create_users_bulk(users).await
+    }
+
+    async fn get_users(&self) -> Result<(Vec<User>, i32), Error> {
+        self.hold("get_users").await?;
+        self.inner.get_users().await
+    }
+
+    async fn count_users(&self) -> Result<i64, Error> {
+        self.hold("count_users").await?;
+        self.inner.count_users().await
+    }
+
+    async fn get_users_batch(&self, offset: i32, limit: i32) -> Result<Vec<User>, Error> {
+        self.hold("get_users_batch").await?;
+        self.inner.get_users_batch(offset, limit).await
+    }
+
+    async fn get_users_filtered(
+        &self,
+        filter: Filter,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        self.hold("get_users_filtered").await?;
+        self.inner.get_users_filtered(filter, offset, limit).await
+    }
+
+    async fn get_user_by_id(&self, id: i32) -> Result<Option<User>, Error> {
+        self.hold("get_user_by_id").await?;
+        self.inner.get_user_by_id(id).await
+    }
+
+    async fn get_user_by_name(&self, name: String) -> Result<Option<User>, Error> {
+        self.hold("get_user_by_name").await?;
+        self.inner.get_user_by_name(name).await
+    }
+
+    async fn get_users_by_names(&self, names: Vec<String>) -> Result<Vec<User>, Error> {
+        self.hold("get_users_by_names").await?;
+        self.inner.get_users_by_names(names).await
+    }
+
+    async fn get_users_by_name_prefix(
+        &self,
+        prefix: String,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        self.hold("get_users_by_name_prefix").await?;
+        self.inner.get_users_by_name_prefix(prefix, limit).await
+    }
+
+    async fn user_exists(&self, id: i32) -> Result<bool, Error> {
+        self.hold("user_exists").await?;
+        self.inner.user_exists(id).await
+    }
+
+    async fn update_user(
+        &self,
+        id: i32,
+        name: Option<String>,
+        surname: Option<String>,
+    ) -> Result<Option<User>, Error> {
+        self.hold("update_user").await?;
+        self.inner.update_user(id, name, surname).await
+    }
+
+    async fn delete_user(&self, id: i32) -> Result<(), Error> {
+        self.hold("delete_user").await?;
+        self.inner.delete_user(id).await
+    }
+
+    async fn soft_delete_user(&self, id: i32) -> Result<(), Error> {
+        self.hold("soft_delete_user").await?;
+        self.inner.soft_delete_user(id).await
+    }
+
+    async fn restore_user(&self, id: i32) -> Result<Option<User>, Error> {
+        self.hold("restore_user").await?;
+        self.inner.restore_user(id).await
+    }
+
+    async fn get_deleted_users(
+        &self,
+        after_id: i32,
+        limit: i32,
+    ) -> Result<Vec<DeletedUser>, Error> {
+        self.hold("get_deleted_users").await?;
+        self.inner.get_deleted_users(after_id, limit).await
+    }
+
+    async fn merge_users(
+        &self,
+        primary_id: i32,
+        duplicate_id: i32,
+        audit: Vec<AuditEntry>,
+    ) -> Result<User, Error> {
+        self.hold("merge_users").await?;
+        self.inner
+            .merge_users(primary_id, duplicate_id, audit)
+            .await
+    }
+
+    async fn get_user_status(&self, id: i32) -> Result<Option<UserStatus>, Error> {
+        self.hold("get_user_status").await?;
+        self.inner.get_user_status(id).await
+    }
+
+    async fn set_user_status(
+        &self,
+        id: i32,
+        from: UserStatus,
+        to: UserStatus,
+    ) -> Result<bool, Error> {
+        self.hold("set_user_status").await?;
+        self.inner.set_user_status(id, from, to).await
+    }
+
+    async fn erase_user(&self, id: i32, name: String, surname: String) -> Result<(), Error> {
+        self.hold("erase_user").await?;
+        self.inner.erase_user(id, name, surname).await
+    }
+
+    async fn get_user_by_id_as_of(
+        &self,
+        id: i32,
+        as_of: SystemTime,
+    ) -> Result<Option<User>, Error> {
+        self.hold("get_user_by_id_as_of").await?;
+        self.inner.get_user_by_id_as_of(id, as_of).await
+    }
+
+    async fn get_users_as_of(
+        &self,
+        filter: Option<Filter>,
+        as_of: SystemTime,
+    ) -> Result<Vec<User>, Error> {
+        self.hold("get_users_as_of").await?;
+        self.inner.get_users_as_of(filter, as_of).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::repositories::memory_user_repository::InMemoryUserRepository;
+
+    #[tokio::test]
+    async fn test_delays_and_times_out_operations() {
+        let operations = SlowOperations::default();
+        let repo = SlowUserRepository::new(InMemoryUserRepository::new(), operations.clone());
+        let created = repo
+            .create_user("Slow".to_string(), "Down".to_string())
+            .await
+            .unwrap();
+
+        operations
+            .set(
+                "*",
+                SlowOperation {
+                    delay: Duration::from_millis(30),
+                    time_out: false,
+                },
+            )
+            .unwrap();
+        operations
+            .set(
+                "delete_user",
+                SlowOperation {
+                    delay: Duration::ZERO,
+                    time_out: true,
+                },
+            )
+            .unwrap();
+
+        let started = Instant::now();
+        assert!(repo.user_exists(created.id).await.unwrap());
+        assert!(started.elapsed() >= Duration::from_millis(30));
+        assert!(matches!(
+            repo.delete_user(created.id).await,
+            Err(Error::Internal(_))
+        ));
+
+        operations.clear();
+        repo.delete_user(created.id).await.unwrap();
+        assert!(operations.list().is_empty());
+    }
+
+    #[test]
+    fn test_set() {
+        let operations = SlowOperations::default();
+        let slow = SlowOperation {
+            delay: Duration::from_millis(5),
+            time_out: false,
+        };
+
+        operations.set("get_user_by_id", slow).unwrap();
+        assert_eq!(operations.list(), [("get_user_by_id".to_string(), slow)]);
+        assert_eq!(operations.get("get_user_by_id"), Some(slow));
+        assert_eq!(operations.get("get_users"), None);
+
+        operations
+            .set("get_user_by_id", SlowOperation::default())
+            .unwrap();
+        assert!(operations.list().is_empty());
+        assert!(matches!(
+            operations.set("drop_table", slow),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+}
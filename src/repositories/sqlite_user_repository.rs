@@ -0,0 +1,765 @@
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use sqlx::{
+    QueryBuilder, Sqlite, SqlitePool,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+};
+
+use crate::repositories::user_repository_trait::UserRepository as UserRepositoryTrait;
+use crate::{
+    Error,
+    entities::{
+        audit::AuditEntry,
+        users::{DeletedUser, User, UserStatus},
+    },
+    filter::{Dialect, Filter, Value},
+};
+
+// the postgres migrations, including the user_history triggers; changed_at
+// keeps milliseconds so as-of reads can tell apart changes within a second
+const SCHEMA: &[&str] = &[
+    r#"
+        CREATE TABLE IF NOT EXISTS users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name VARCHAR(255) NOT NULL,
+            surname VARCHAR(255) NOT NULL,
+            deleted_at TEXT,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            status VARCHAR(16) NOT NULL DEFAULT 'active'
+        )
+    "#,
+    r#"
+        CREATE TABLE IF NOT EXISTS user_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            name VARCHAR(255) NOT NULL,
+            surname VARCHAR(255) NOT NULL,
+            created_at TEXT NOT NULL,
+            deleted INTEGER NOT NULL DEFAULT 0,
+            changed_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now'))
+        )
+    "#,
+    r#"
+        CREATE INDEX IF NOT EXISTS user_history_user_changed_idx
+        ON user_history (user_id, changed_at)
+    "#,
+    r#"
+        CREATE TRIGGER IF NOT EXISTS users_history_insert AFTER INSERT ON users BEGIN
+            INSERT INTO user_history (user_id, name, surname, created_at, deleted)
+            VALUES (new.id, new.name, new.surname, new.created_at, new.deleted_at IS NOT NULL);
+        END
+    "#,
+    r#"
+        CREATE TRIGGER IF NOT EXISTS users_history_update AFTER UPDATE ON users BEGIN
+            INSERT INTO user_history (user_id, name, surname, created_at, deleted)
+            VALUES (new.id, new.name, new.surname, new.created_at, new.deleted_at IS NOT NULL);
+        END
+    "#,
+    r#"
+        CREATE TRIGGER IF NOT EXISTS users_history_delete AFTER DELETE ON users BEGIN
+            INSERT INTO user_history (user_id, name, surname, created_at, deleted)
+            VALUES (old.id, old.name, old.surname, old.created_at, 1);
+        END
+    "#,
+    // databases created before the history existed start it at creation
+    r#"
+        INSERT INTO user_history (user_id, name, surname, created_at, deleted, changed_at)
+        SELECT id, name, surname, created_at, deleted_at IS NOT NULL, created_at
+        FROM users
+        WHERE id NOT IN (SELECT user_id FROM user_history)
+    "#,
+];
+
+#[derive(Clone)]
+pub struct SqliteUserRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteUserRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn connect(url: &str, max_connections: u32) -> Result<Self, Error> {
+        let options = SqliteConnectOptions::from_str(url)
+            .map_err(|e| Error::Internal(Box::new(e)))?
+            .create_if_missing(true);
+        // every connection to an in-memory database would see its own empty database
+        let max_connections = if url.contains(":memory:") {
+            1
+        } else {
+            max_connections
+        };
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(options)
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        let repo = Self::new(pool);
+        repo.create_schema().await?;
+
+        Ok(repo)
+    }
+
+    async fn create_schema(&self) -> Result<(), Error> {
+        for statement in SCHEMA {
+            sqlx::query(statement)
+                .execute(&self.pool)
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserRepositoryTrait for SqliteUserRepository {
+    async fn create_user(&self, name: String, surname: String) -> Result<User, Error> {
+        sqlx::query_as::<_, User>(
+            r#"
+                INSERT INTO users (name, surname)
+                VALUES (?, ?)
+                RETURNING id, name, surname
+            "#,
+        )
+        .bind(name)
+        .bind(surname)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))
+    }
+
+    async fn get_users(&self) -> Result<(Vec<User>, i32), Error> {
+        let res = sqlx::query_as::<_, User>(
+            r#"
+                SELECT id, name, surname
+                FROM users
+                WHERE deleted_at IS NULL AND status = 'active'
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+        let count = res.len();
+
+        Ok((res, count as i32))
+    }
+
+    async fn count_users(&self) -> Result<i64, Error> {
+        sqlx::query_scalar::<_, i64>(
+            r#"
+                SELECT COUNT(*)
+                FROM users
+                WHERE deleted_at IS NULL AND status = 'active'
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))
+    }
+
+    async fn get_users_batch(&self, offset: i32, limit: i32) -> Result<Vec<User>, Error> {
+        sqlx::query_as::<_, User>(
+            r#"
+                SELECT id, name, surname
+                FROM users
+                WHERE deleted_at IS NULL AND status = 'active'
+                ORDER BY id
+                LIMIT ? OFFSET ?
+            "#,
+        )
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))
+    }
+
+    async fn get_users_filtered(
+        &self,
+        filter: Filter,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        let (clause, values) = filter.to_sql(Dialect::Sqlite, 1);
+        let sql = format!(
+            "SELECT id, name, surname FROM users WHERE deleted_at IS NULL AND status = 'active' AND {} ORDER BY id LIMIT ? OFFSET ?",
+            clause
+        );
+
+        let mut query = sqlx::query_as::<_, User>(&sql);
+        for value in values {
+            query = match value {
+                Value::Integer(v) => query.bind(v),
+                Value::Text(v) | Value::Timestamp(v) => query.bind(v),
+            };
+        }
+
+        query
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))
+    }
+
+    async fn get_user_by_id(&self, id: i32) -> Result<Option<User>, Error> {
+        sqlx::query_as::<_, User>(
+            r#"
+                SELECT id, name, surname
+                FROM users
+                WHERE id = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))
+    }
+
+    async fn get_user_by_name(&self, name: String) -> Result<Option<User>, Error> {
+        sqlx::query_as::<_, User>(
+            r#"
+                SELECT id, name, surname
+                FROM users
+                WHERE name = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))
+    }
+
+    async fn get_users_by_names(&self, names: Vec<String>) -> Result<Vec<User>, Error> {
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut query = QueryBuilder::<Sqlite>::new(
+            "SELECT id, name, surname FROM users WHERE deleted_at IS NULL AND name IN (",
+        );
+        let mut separated = query.separated(", ");
+        for name in names {
+            separated.push_bind(name);
+        }
+        query.push(") ORDER BY id");
+
+        query
+            .build_query_as::<User>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))
+    }
+
+    async fn get_users_by_name_prefix(
+        &self,
+        prefix: String,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        // sqlite's LIKE ignores ASCII case, compare the leading characters instead
+        sqlx::query_as::<_, User>(
+            r#"
+                SELECT id, name, surname
+                FROM users
+                WHERE substr(name, 1, length(?)) = ? AND deleted_at IS NULL
+                ORDER BY name, id
+                LIMIT ?
+            "#,
+        )
+        .bind(&prefix)
+        .bind(&prefix)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))
+    }
+
+    async fn user_exists(&self, id: i32) -> Result<bool, Error> {
+        sqlx::query_scalar::<_, bool>(
+            r#"
+                SELECT EXISTS(
+                    SELECT 1
+                    FROM users
+                    WHERE id = ? AND deleted_at IS NULL
+                )
+            "#,
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))
+    }
+
+    async fn update_user(
+        &self,
+        id: i32,
+        name: Option<String>,
+        surname: Option<String>,
+    ) -> Result<Option<User>, Error> {
+        sqlx::query_as::<_, User>(
+            r#"
+                UPDATE users
+                SET
+                    name = COALESCE(?, name),
+                    surname = COALESCE(?, surname)
+                WHERE id = ? AND deleted_at IS NULL
+                RETURNING id, name, surname
+            "#,
+        )
+        .bind(name)
+        .bind(surname)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))
+    }
+
+    async fn delete_user(&self, id: i32) -> Result<(), Error> {
+        let result = sqlx::query(
+            r#"
+                DELETE FROM users
+                WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn soft_delete_user(&self, id: i32) -> Result<(), Error> {
+        let result = sqlx::query(
+            r#"
+                UPDATE users
+                SET deleted_at = datetime('now')
+                WHERE id = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn restore_user(&self, id: i32) -> Result<Option<User>, Error> {
+        sqlx::query_as::<_, User>(
+            r#"
+                UPDATE users
+                SET deleted_at = NULL
+                WHERE id = ? AND deleted_at IS NOT NULL
+                RETURNING id, name, surname
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))
+    }
+
+    // deleted_at is stored to the second
+    async fn get_deleted_users(
+        &self,
+        after_id: i32,
+        limit: i32,
+    ) -> Result<Vec<DeletedUser>, Error> {
+        let res = sqlx::query_as::<_, (i32, String, String, i64)>(
+            r#"
+                SELECT id, name, surname, CAST(strftime('%s', deleted_at) AS INTEGER)
+                FROM users
+                WHERE deleted_at IS NOT NULL AND id > ?
+                ORDER BY id
+                LIMIT ?
+            "#,
+        )
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(res
+            .into_iter()
+            .map(|(id, name, surname, deleted_at)| DeletedUser {
+                user: User { id, name, surname },
+                deleted_at: SystemTime::UNIX_EPOCH + Duration::from_secs(deleted_at.max(0) as u64),
+            })
+            .collect())
+    }
+
+    async fn merge_users(
+        &self,
+        _primary_id: i32,
+        _duplicate_id: i32,
+        _audit: Vec<AuditEntry>,
+    ) -> Result<User, Error> {
+        Err(Error::InvalidArgument(
+            "merging users needs DATABASE_BACKEND=postgres".to_string(),
+        ))
+    }
+
+    async fn get_user_status(&self, id: i32) -> Result<Option<UserStatus>, Error> {
+        let res = sqlx::query_scalar::<_, String>(
+            r#"
+                SELECT status
+                FROM users
+                WHERE id = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        res.map(|status| {
+            status
+                .parse()
+                .map_err(|e: String| Error::Internal(e.into()))
+        })
+        .transpose()
+    }
+
+    async fn set_user_status(
+        &self,
+        id: i32,
+        from: UserStatus,
+        to: UserStatus,
+    ) -> Result<bool, Error> {
+        let result = sqlx::query(
+            r#"
+                UPDATE users
+                SET status = ?
+                WHERE id = ? AND status = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(to.as_str())
+        .bind(id)
+        .bind(from.as_str())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn erase_user(&self, id: i32, name: String, surname: String) -> Result<(), Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        let user = sqlx::query("UPDATE users SET name = ?, surname = ? WHERE id = ?")
+            .bind(&name)
+            .bind(&surname)
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        let history =
+            sqlx::query("UPDATE user_history SET name = ?, surname = ? WHERE user_id = ?")
+                .bind(&name)
+                .bind(&surname)
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        if user.rows_affected() == 0 && history.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+        tx.commit().await.map_err(|e| Error::Internal(Box::new(e)))
+    }
+
+    async fn get_user_by_id_as_of(
+        &self,
+        id: i32,
+        as_of: SystemTime,
+    ) -> Result<Option<User>, Error> {
+        let res = sqlx::query_as::<_, (i32, String, String, bool)>(
+            r#"
+                SELECT user_id, name, surname, deleted
+                FROM user_history
+                WHERE user_id = ? AND changed_at <= strftime('%Y-%m-%d %H:%M:%f', ?)
+                ORDER BY changed_at DESC, id DESC
+                LIMIT 1
+            "#,
+        )
+        .bind(id)
+        .bind(prost_types::Timestamp::from(as_of).to_string())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(res
+            .filter(|(_, _, _, deleted)| !deleted)
+            .map(|(id, name, surname, _)| User { id, name, surname }))
+    }
+
+    async fn get_users_as_of(
+        &self,
+        filter: Option<Filter>,
+        as_of: SystemTime,
+    ) -> Result<Vec<User>, Error> {
+        let (clause, values) = filter.map_or_else(
+            || ("1".to_string(), Vec::new()),
+            |f| f.to_sql(Dialect::Sqlite, 1),
+        );
+        let sql = format!(
+            "SELECT id, name, surname FROM (
+                SELECT user_id AS id, name, surname, created_at, deleted,
+                    ROW_NUMBER() OVER (
+                        PARTITION BY user_id
+                        ORDER BY changed_at DESC, user_history.id DESC
+                    ) AS version
+                FROM user_history
+                WHERE changed_at <= strftime('%Y-%m-%d %H:%M:%f', ?)
+            ) WHERE version = 1 AND NOT deleted AND {} ORDER BY id",
+            clause
+        );
+
+        let mut query =
+            sqlx::query_as::<_, User>(&sql).bind(prost_types::Timestamp::from(as_of).to_string());
+        for value in values {
+            query = match value {
+                Value::Integer(v) => query.bind(v),
+                Value::Text(v) | Value::Timestamp(v) => query.bind(v),
+            };
+        }
+
+        query
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn setup_repo() -> SqliteUserRepository {
+        SqliteUserRepository::connect("sqlite::memory:", 1)
+            .await
+            .expect("Failed to open sqlite database")
+    }
+
+    #[tokio::test]
+    async fn test_create_and_get_user() {
+        let repo = setup_repo().await;
+
+        let created = repo
+            .create_user("Sqlite".to_string(), "User".to_string())
+            .await
+            .unwrap();
+        let found = repo.get_user_by_name("Sqlite".to_string()).await.unwrap();
+
+        assert_eq!(found, Some(created.clone()));
+        assert!(repo.user_exists(created.id).await.unwrap());
+        assert!(!repo.user_exists(99999).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_get_users_by_names() {
+        let repo = setup_repo().await;
+        for (name, surname) in [("Ann", "One"), ("Bob", "Two"), ("Ann", "Three")] {
+            repo.create_user(name.to_string(), surname.to_string())
+                .await
+                .unwrap();
+        }
+
+        let found = repo
+            .get_users_by_names(vec!["Ann".to_string(), "Zed".to_string()])
+            .await
+            .unwrap();
+
+        assert_eq!(found.len(), 2);
+        assert!(found.iter().all(|u| u.name == "Ann"));
+        assert!(
+            repo.get_users_by_names(Vec::new())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_users_filtered() {
+        let repo = setup_repo().await;
+        for (name, surname) in [("Ann", "One"), ("Bob", "Two"), ("Ann", "Three")] {
+            repo.create_user(name.to_string(), surname.to_string())
+                .await
+                .unwrap();
+        }
+
+        let filter = Filter::parse(
+            r#"name = "Ann" AND created_at > "2000-01-01" OR id = 2"#,
+            crate::filter::USER_FIELDS,
+        )
+        .unwrap()
+        .unwrap();
+        let found = repo.get_users_filtered(filter, 1, 10).await.unwrap();
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].surname, "Three");
+    }
+
+    #[tokio::test]
+    async fn test_update_user_empty_vs_unchanged() {
+        let repo = setup_repo().await;
+        let created = repo
+            .create_user("Keep".to_string(), "Clear".to_string())
+            .await
+            .unwrap();
+
+        let updated = repo
+            .update_user(created.id, None, Some(String::new()))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(updated.name, "Keep");
+        assert_eq!(updated.surname, "");
+    }
+
+    #[tokio::test]
+    async fn test_update_user_not_found() {
+        let repo = setup_repo().await;
+
+        let result = repo.update_user(99999, Some("No".to_string()), None).await;
+
+        assert!(result.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_soft_delete_user() {
+        let repo = setup_repo().await;
+        let created = repo
+            .create_user("Soft".to_string(), "Delete".to_string())
+            .await
+            .unwrap();
+
+        repo.soft_delete_user(created.id).await.unwrap();
+
+        assert!(repo.get_user_by_id(created.id).await.unwrap().is_none());
+        assert_eq!(repo.count_users().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_restore_user() {
+        let repo = setup_repo().await;
+        let created = repo
+            .create_user("Soft".to_string(), "Restore".to_string())
+            .await
+            .unwrap();
+        repo.soft_delete_user(created.id).await.unwrap();
+
+        let deleted = repo.get_deleted_users(0, 10).await.unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].user, created);
+        assert!(deleted[0].deleted_at > SystemTime::UNIX_EPOCH);
+
+        assert_eq!(
+            repo.restore_user(created.id).await.unwrap(),
+            Some(created.clone())
+        );
+        assert!(repo.user_exists(created.id).await.unwrap());
+        assert_eq!(repo.restore_user(created.id).await.unwrap(), None);
+        assert!(repo.get_deleted_users(0, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_suspended_users_are_not_listed() {
+        let repo = setup_repo().await;
+        let created = repo
+            .create_user("Suspended".to_string(), "User".to_string())
+            .await
+            .unwrap();
+
+        assert!(
+            repo.set_user_status(created.id, UserStatus::Active, UserStatus::Suspended)
+                .await
+                .unwrap()
+        );
+
+        assert_eq!(
+            repo.get_user_status(created.id).await.unwrap(),
+            Some(UserStatus::Suspended)
+        );
+        assert_eq!(repo.count_users().await.unwrap(), 0);
+        assert!(repo.get_users_batch(0, 10).await.unwrap().is_empty());
+        assert!(repo.get_user_by_id(created.id).await.unwrap().is_some());
+        assert!(
+            !repo
+                .set_user_status(created.id, UserStatus::Active, UserStatus::Suspended)
+                .await
+                .unwrap()
+        );
+        assert_eq!(repo.get_user_status(99999).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_delete_user_not_found() {
+        let repo = setup_repo().await;
+
+        let result = repo.delete_user(99999).await;
+
+        assert!(matches!(result.unwrap_err(), Error::NotFound));
+    }
+
+    #[tokio::test]
+    async fn test_reads_as_of() {
+        let repo = setup_repo().await;
+        // changed_at keeps milliseconds
+        let tick = || tokio::time::sleep(std::time::Duration::from_millis(5));
+
+        let created = repo
+            .create_user("AsOf".to_string(), "Before".to_string())
+            .await
+            .unwrap();
+        tick().await;
+        let after_create = SystemTime::now();
+        tick().await;
+        repo.update_user(created.id, None, Some("After".to_string()))
+            .await
+            .unwrap();
+        tick().await;
+        let after_update = SystemTime::now();
+        tick().await;
+        repo.delete_user(created.id).await.unwrap();
+
+        assert_eq!(
+            repo.get_user_by_id_as_of(created.id, after_create)
+                .await
+                .unwrap(),
+            Some(created.clone())
+        );
+        let users = repo.get_users_as_of(None, after_update).await.unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].surname, "After");
+        assert!(
+            repo.get_users_as_of(None, SystemTime::now())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
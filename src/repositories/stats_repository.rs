@@ -0,0 +1,168 @@
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use crate::repositories::stats_repository_trait::StatsRepository as StatsRepositoryTrait;
+use crate::{
+    Error,
+    entities::stats::{StatsPeriod, UserStats},
+};
+
+// aggregates `user_history`, which unlike `users` still has the hard deleted
+#[derive(Clone)]
+pub struct StatsRepository {
+    pool: PgPool,
+}
+
+impl StatsRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl StatsRepositoryTrait for StatsRepository {
+    async fn user_stats(
+        &self,
+        period: StatsPeriod,
+        start: SystemTime,
+        end: SystemTime,
+    ) -> Result<Vec<UserStats>, Error> {
+        // periods are counted in UTC timestamps so months do not depend on
+        // the session time zone
+        let rows = crate::query_as!(
+            StatsRow,
+            r#"
+                WITH periods AS (
+                    SELECT period_start, period_start + ('1 ' || $1)::interval AS period_end
+                    FROM generate_series(
+                        date_trunc($1, $2::text::timestamptz AT TIME ZONE 'UTC'),
+                        date_trunc($1, $3::text::timestamptz AT TIME ZONE 'UTC'),
+                        ('1 ' || $1)::interval
+                    ) AS period_start
+                ),
+                lifetimes AS (
+                    SELECT
+                        min(created_at) AT TIME ZONE 'UTC' AS created_at,
+                        min(changed_at) FILTER (WHERE deleted) AT TIME ZONE 'UTC' AS deleted_at
+                    FROM user_history
+                    GROUP BY user_id
+                )
+                SELECT
+                    extract(epoch FROM p.period_start)::bigint AS "period_start!",
+                    count(l.created_at) FILTER (WHERE l.created_at >= p.period_start) AS "created!",
+                    count(l.deleted_at) FILTER (
+                        WHERE l.deleted_at >= p.period_start AND l.deleted_at < p.period_end
+                    ) AS "deleted!",
+                    count(l.created_at) FILTER (
+                        WHERE l.deleted_at IS NULL OR l.deleted_at >= p.period_end
+                    ) AS "active!"
+                FROM periods p
+                LEFT JOIN lifetimes l ON l.created_at < p.period_end
+                GROUP BY p.period_start
+                ORDER BY p.period_start
+            "#,
+            period.as_str(),
+            prost_types::Timestamp::from(start).to_string(),
+            prost_types::Timestamp::from(end).to_string()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        rows.into_iter()
+            .map(|row| {
+                let period_start = prost_types::Timestamp {
+                    seconds: row.period_start,
+                    nanos: 0,
+                };
+                Ok(UserStats {
+                    period_start: SystemTime::try_from(period_start)
+                        .map_err(|e| Error::Internal(Box::new(e)))?,
+                    created: row.created,
+                    deleted: row.deleted,
+                    active: row.active,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct StatsRow {
+    #[sqlx(rename = "period_start!")]
+    period_start: i64,
+    #[sqlx(rename = "created!")]
+    created: i64,
+    #[sqlx(rename = "deleted!")]
+    deleted: i64,
+    #[sqlx(rename = "active!")]
+    active: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(rfc3339: &str) -> SystemTime {
+        SystemTime::try_from(rfc3339.parse::<prost_types::Timestamp>().unwrap()).unwrap()
+    }
+
+    #[sqlx::test]
+    async fn test_user_stats(pool: PgPool) {
+        let repo = StatsRepository::new(pool.clone());
+        crate::query!(
+            r#"
+                INSERT INTO user_history (user_id, name, surname, created_at, deleted, changed_at)
+                VALUES
+                    (1, 'Ann', 'Lee', '1990-01-10T00:00:00Z', false, '1990-01-10T00:00:00Z'),
+                    (1, 'Ann', 'Lee', '1990-01-10T00:00:00Z', true, '1990-02-03T00:00:00Z'),
+                    (2, 'Bo', 'Chen', '1990-02-01T00:00:00Z', false, '1990-02-01T00:00:00Z')
+            "#
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let stats = repo
+            .user_stats(
+                StatsPeriod::Month,
+                at("1989-12-15T00:00:00Z"),
+                at("1990-02-20T00:00:00Z"),
+            )
+            .await
+            .unwrap();
+
+        let month = |start: &str, created, deleted, active| UserStats {
+            period_start: at(start),
+            created,
+            deleted,
+            active,
+        };
+        assert_eq!(
+            stats,
+            [
+                month("1989-12-01T00:00:00Z", 0, 0, 0),
+                month("1990-01-01T00:00:00Z", 1, 0, 1),
+                month("1990-02-01T00:00:00Z", 1, 1, 1),
+            ]
+        );
+
+        let days = repo
+            .user_stats(
+                StatsPeriod::Day,
+                at("1990-01-10T12:00:00Z"),
+                at("1990-01-11T00:00:00Z"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].created, 1);
+        assert_eq!(
+            days[1].period_start,
+            at("1990-01-10T00:00:00Z") + Duration::from_secs(24 * 60 * 60)
+        );
+    }
+}
@@ -0,0 +1,19 @@
+use std::time::SystemTime;
+
+use crate::{
+    Error,
+    entities::stats::{StatsPeriod, UserStats},
+};
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait StatsRepository: Send + Sync {
+    // one entry per period from the one containing `start` to the one
+    // containing `end`, in UTC and including empty periods
+    async fn user_stats(
+        &self,
+        period: StatsPeriod,
+        start: SystemTime,
+        end: SystemTime,
+    ) -> Result<Vec<UserStats>, Error>;
+}
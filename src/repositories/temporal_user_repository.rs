@@ -0,0 +1,699 @@
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use sqlx::{Connection, PgConnection, PgPool, Postgres, pool::PoolConnection};
+
+use crate::repositories::{
+    pool_metrics::{self, DEFAULT_ACQUIRE_WARN_THRESHOLD},
+    user_repository::{DeletedRow, filter_error},
+    user_repository_trait::UserRepository as UserRepositoryTrait,
+};
+use crate::{
+    Error,
+    entities::{
+        audit::AuditEntry,
+        users::{DeletedUser, User, UserStatus},
+    },
+    filter::{Dialect, Filter, Value},
+};
+
+// users kept in `user_versions`, one row per version valid over
+// [valid_from, valid_to); writes close the current version instead of
+// changing it, so as-of reads need no separate history
+#[derive(Clone)]
+pub struct TemporalUserRepository {
+    pool: PgPool,
+    acquire_warn_threshold: Duration,
+}
+
+impl TemporalUserRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            acquire_warn_threshold: DEFAULT_ACQUIRE_WARN_THRESHOLD,
+        }
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    pub fn with_acquire_warn_threshold(mut self, threshold: Duration) -> Self {
+        self.acquire_warn_threshold = threshold;
+        self
+    }
+
+    async fn acquire(&self) -> Result<PoolConnection<Postgres>, Error> {
+        pool_metrics::acquire(&self.pool, self.acquire_warn_threshold).await
+    }
+}
+
+// serializes writers of one user until the transaction ends; a row lock is
+// not enough, a waiting writer would wake up on the version just closed and
+// miss the one that replaced it
+async fn lock_user(conn: &mut PgConnection, id: i32) -> Result<(), Error> {
+    sqlx::query("SELECT pg_advisory_xact_lock('user_versions'::regclass::oid::int, $1)")
+        .bind(id)
+        .execute(conn)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+    Ok(())
+}
+
+#[async_trait]
+impl UserRepositoryTrait for TemporalUserRepository {
+    async fn create_user(&self, name: String, surname: String) -> Result<User, Error> {
+        let res = crate::query_as!(
+            User,
+            r#"
+                INSERT INTO user_versions (id, name, surname)
+                VALUES (nextval('user_versions_user_id_seq')::int, $1, $2)
+                RETURNING id, name, surname
+            "#,
+            name,
+            surname
+        )
+        .fetch_one(&mut *self.acquire().await?)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(res)
+    }
+
+    async fn get_users(&self) -> Result<(Vec<User>, i32), Error> {
+        let res = crate::query_as!(
+            User,
+            r#"
+                SELECT id, name, surname
+                FROM user_versions
+                WHERE valid_to IS NULL
+            "#
+        )
+        .fetch_all(&mut *self.acquire().await?)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+        let count = res.len();
+
+        Ok((res, count as i32))
+    }
+
+    async fn count_users(&self) -> Result<i64, Error> {
+        let res = crate::query_scalar!(
+            i64,
+            r#"
+                SELECT COUNT(*) AS "count!"
+                FROM user_versions
+                WHERE valid_to IS NULL
+            "#
+        )
+        .fetch_one(&mut *self.acquire().await?)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(res)
+    }
+
+    async fn get_users_batch(&self, offset: i32, limit: i32) -> Result<Vec<User>, Error> {
+        let res = crate::query_as!(
+            User,
+            r#"
+                SELECT id, name, surname
+                FROM user_versions
+                WHERE valid_to IS NULL
+                ORDER BY id
+                LIMIT $1 OFFSET $2
+            "#,
+            limit as i64,
+            offset as i64
+        )
+        .fetch_all(&mut *self.acquire().await?)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(res)
+    }
+
+    async fn get_users_filtered(
+        &self,
+        filter: Filter,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        let (clause, values) = filter.to_sql(Dialect::Postgres, 1);
+        let sql = format!(
+            "SELECT id, name, surname FROM user_versions WHERE valid_to IS NULL AND {} ORDER BY id LIMIT ${} OFFSET ${}",
+            clause,
+            values.len() + 1,
+            values.len() + 2
+        );
+
+        let mut query = sqlx::query_as::<_, User>(&sql);
+        for value in values {
+            query = match value {
+                Value::Integer(v) => query.bind(v),
+                Value::Text(v) | Value::Timestamp(v) => query.bind(v),
+            };
+        }
+
+        query
+            .bind(limit as i64)
+            .bind(offset as i64)
+            .fetch_all(&mut *self.acquire().await?)
+            .await
+            .map_err(filter_error)
+    }
+
+    async fn get_user_by_id(&self, id: i32) -> Result<Option<User>, Error> {
+        let res = crate::query_as!(
+            User,
+            r#"
+                SELECT id, name, surname
+                FROM user_versions
+                WHERE id = $1 AND valid_to IS NULL
+            "#,
+            id
+        )
+        .fetch_optional(&mut *self.acquire().await?)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(res)
+    }
+
+    async fn get_user_by_name(&self, name: String) -> Result<Option<User>, Error> {
+        let res = crate::query_as!(
+            User,
+            r#"
+                SELECT id, name, surname
+                FROM user_versions
+                WHERE name = $1 AND valid_to IS NULL
+                LIMIT 1
+            "#,
+            name
+        )
+        .fetch_optional(&mut *self.acquire().await?)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(res)
+    }
+
+    async fn get_users_by_names(&self, names: Vec<String>) -> Result<Vec<User>, Error> {
+        let res = crate::query_as!(
+            User,
+            r#"
+                SELECT id, name, surname
+                FROM user_versions
+                WHERE name = ANY($1) AND valid_to IS NULL
+                ORDER BY id
+            "#,
+            &names
+        )
+        .fetch_all(&mut *self.acquire().await?)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(res)
+    }
+
+    async fn get_users_by_name_prefix(
+        &self,
+        prefix: String,
+        limit: i32,
+    ) -> Result<Vec<User>, Error> {
+        // LIKE wildcards in the prefix must match literally
+        let pattern = format!(
+            "{}%",
+            prefix
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        let res = crate::query_as!(
+            User,
+            r#"
+                SELECT id, name, surname
+                FROM user_versions
+                WHERE name LIKE $1 AND valid_to IS NULL
+                ORDER BY name, id
+                LIMIT $2
+            "#,
+            pattern,
+            limit as i64
+        )
+        .fetch_all(&mut *self.acquire().await?)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(res)
+    }
+
+    async fn user_exists(&self, id: i32) -> Result<bool, Error> {
+        let res = crate::query_scalar!(
+            bool,
+            r#"
+                SELECT EXISTS(
+                    SELECT 1
+                    FROM user_versions
+                    WHERE id = $1 AND valid_to IS NULL
+                ) AS "exists!"
+            "#,
+            id
+        )
+        .fetch_one(&mut *self.acquire().await?)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(res)
+    }
+
+    // statement_timestamp() is shared by both halves of the statement, so the
+    // new version starts exactly where the old one ends; now() would be the
+    // transaction start, possibly before the previous lock holder's version
+    async fn update_user(
+        &self,
+        id: i32,
+        name: Option<String>,
+        surname: Option<String>,
+    ) -> Result<Option<User>, Error> {
+        let mut conn = self.acquire().await?;
+        let mut tx = conn
+            .begin()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        lock_user(&mut tx, id).await?;
+
+        let res = crate::query_as!(
+            User,
+            r#"
+                WITH closed AS (
+                    UPDATE user_versions
+                    SET valid_to = statement_timestamp()
+                    WHERE id = $3 AND valid_to IS NULL
+                    RETURNING id, name, surname, created_at
+                )
+                INSERT INTO user_versions (id, name, surname, created_at, valid_from)
+                SELECT id, COALESCE($1, name), COALESCE($2, surname), created_at, statement_timestamp()
+                FROM closed
+                RETURNING id, name, surname
+            "#,
+            name,
+            surname,
+            id
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(res)
+    }
+
+    // a hard delete erases the history with the user
+    async fn delete_user(&self, id: i32) -> Result<(), Error> {
+        let mut conn = self.acquire().await?;
+        let mut tx = conn
+            .begin()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        lock_user(&mut tx, id).await?;
+
+        let result = crate::query!(
+            r#"
+                DELETE FROM user_versions
+                WHERE id = $1
+            "#,
+            id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn soft_delete_user(&self, id: i32) -> Result<(), Error> {
+        let mut conn = self.acquire().await?;
+        let mut tx = conn
+            .begin()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        lock_user(&mut tx, id).await?;
+
+        let result = crate::query!(
+            r#"
+                UPDATE user_versions
+                SET valid_to = statement_timestamp()
+                WHERE id = $1 AND valid_to IS NULL
+            "#,
+            id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    // a new current version, copied from the one the soft delete closed
+    async fn restore_user(&self, id: i32) -> Result<Option<User>, Error> {
+        let mut conn = self.acquire().await?;
+        let mut tx = conn
+            .begin()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        lock_user(&mut tx, id).await?;
+
+        let res = crate::query_as!(
+            User,
+            r#"
+                INSERT INTO user_versions (id, name, surname, created_at)
+                SELECT id, name, surname, created_at
+                FROM user_versions
+                WHERE id = $1
+                    AND NOT EXISTS (
+                        SELECT 1
+                        FROM user_versions
+                        WHERE id = $1 AND valid_to IS NULL
+                    )
+                ORDER BY valid_from DESC, version_id DESC
+                LIMIT 1
+                RETURNING id, name, surname
+            "#,
+            id
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(res)
+    }
+
+    async fn get_deleted_users(
+        &self,
+        after_id: i32,
+        limit: i32,
+    ) -> Result<Vec<DeletedUser>, Error> {
+        let res = crate::query_as!(
+            DeletedRow,
+            r#"
+                SELECT DISTINCT ON (v.id)
+                    v.id,
+                    v.name,
+                    v.surname,
+                    (extract(epoch FROM v.valid_to) * 1000000)::bigint AS "deleted_at_micros!"
+                FROM user_versions v
+                WHERE v.id > $1
+                    AND NOT EXISTS (
+                        SELECT 1
+                        FROM user_versions c
+                        WHERE c.id = v.id AND c.valid_to IS NULL
+                    )
+                ORDER BY v.id, v.valid_from DESC, v.version_id DESC
+                LIMIT $2
+            "#,
+            after_id,
+            limit as i64
+        )
+        .fetch_all(&mut *self.acquire().await?)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(res.into_iter().map(DeletedRow::into_deleted_user).collect())
+    }
+
+    // only the postgres backend moves addresses, relationships and consents
+    async fn merge_users(
+        &self,
+        _primary_id: i32,
+        _duplicate_id: i32,
+        _audit: Vec<AuditEntry>,
+    ) -> Result<User, Error> {
+        Err(Error::InvalidArgument(
+            "merging users needs DATABASE_BACKEND=postgres".to_string(),
+        ))
+    }
+
+    // versions carry no status, so every user stays active
+    async fn get_user_status(&self, id: i32) -> Result<Option<UserStatus>, Error> {
+        Ok(self.user_exists(id).await?.then_some(UserStatus::Active))
+    }
+
+    async fn set_user_status(
+        &self,
+        _id: i32,
+        _from: UserStatus,
+        _to: UserStatus,
+    ) -> Result<bool, Error> {
+        Err(Error::InvalidArgument(
+            "suspending users needs DATABASE_BACKEND=postgres, sqlite or memory".to_string(),
+        ))
+    }
+
+    // every version is rewritten in place, erasure is not a new version
+    async fn erase_user(&self, id: i32, name: String, surname: String) -> Result<(), Error> {
+        let mut conn = self.acquire().await?;
+        let mut tx = conn
+            .begin()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        lock_user(&mut tx, id).await?;
+
+        let result = crate::query!(
+            r#"
+                UPDATE user_versions
+                SET name = $1, surname = $2
+                WHERE id = $3
+            "#,
+            name,
+            surname,
+            id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn get_user_by_id_as_of(
+        &self,
+        id: i32,
+        as_of: SystemTime,
+    ) -> Result<Option<User>, Error> {
+        let res = crate::query_as!(
+            User,
+            r#"
+                SELECT id, name, surname
+                FROM user_versions
+                WHERE id = $1
+                    AND valid_from <= $2::text::timestamptz
+                    AND (valid_to IS NULL OR valid_to > $2::text::timestamptz)
+            "#,
+            id,
+            prost_types::Timestamp::from(as_of).to_string()
+        )
+        .fetch_optional(&mut *self.acquire().await?)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(res)
+    }
+
+    async fn get_users_as_of(
+        &self,
+        filter: Option<Filter>,
+        as_of: SystemTime,
+    ) -> Result<Vec<User>, Error> {
+        let (clause, values) = filter.map_or_else(
+            || ("TRUE".to_string(), Vec::new()),
+            |f| f.to_sql(Dialect::Postgres, 2),
+        );
+        let sql = format!(
+            "SELECT id, name, surname FROM user_versions
+            WHERE valid_from <= $1::timestamptz AND (valid_to IS NULL OR valid_to > $1::timestamptz)
+                AND {}
+            ORDER BY id",
+            clause
+        );
+
+        let mut query =
+            sqlx::query_as::<_, User>(&sql).bind(prost_types::Timestamp::from(as_of).to_string());
+        for value in values {
+            query = match value {
+                Value::Integer(v) => query.bind(v),
+                Value::Text(v) | Value::Timestamp(v) => query.bind(v),
+            };
+        }
+
+        query
+            .fetch_all(&mut *self.acquire().await?)
+            .await
+            .map_err(filter_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_update_keeps_versions(pool: PgPool) {
+        let repo = TemporalUserRepository::new(pool);
+
+        let created = repo
+            .create_user("Temporal".to_string(), "Before".to_string())
+            .await
+            .unwrap();
+        let after_create = SystemTime::now();
+        let updated = repo
+            .update_user(created.id, None, Some("After".to_string()))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(updated.surname, "After");
+        assert_eq!(
+            repo.get_user_by_id(created.id).await.unwrap(),
+            Some(updated)
+        );
+        assert_eq!(
+            repo.get_user_by_id_as_of(created.id, after_create)
+                .await
+                .unwrap(),
+            Some(created.clone())
+        );
+
+        let filter =
+            Filter::parse(&format!("id = {}", created.id), crate::filter::USER_FIELDS).unwrap();
+        let users = repo.get_users_as_of(filter, after_create).await.unwrap();
+        assert_eq!(users, vec![created.clone()]);
+
+        repo.delete_user(created.id).await.unwrap();
+        assert!(
+            repo.get_user_by_id_as_of(created.id, after_create)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_soft_delete_closes_the_current_version(pool: PgPool) {
+        let repo = TemporalUserRepository::new(pool);
+
+        let created = repo
+            .create_user("Temporal".to_string(), "SoftDelete".to_string())
+            .await
+            .unwrap();
+        let before_delete = SystemTime::now();
+        repo.soft_delete_user(created.id).await.unwrap();
+
+        assert!(!repo.user_exists(created.id).await.unwrap());
+        assert!(
+            repo.update_user(created.id, Some("Again".to_string()), None)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(matches!(
+            repo.soft_delete_user(created.id).await.unwrap_err(),
+            Error::NotFound
+        ));
+        assert_eq!(
+            repo.get_user_by_id_as_of(created.id, before_delete)
+                .await
+                .unwrap(),
+            Some(created)
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_restore_opens_a_new_version(pool: PgPool) {
+        let repo = TemporalUserRepository::new(pool);
+
+        let created = repo
+            .create_user("Temporal".to_string(), "Restore".to_string())
+            .await
+            .unwrap();
+        repo.soft_delete_user(created.id).await.unwrap();
+        let deleted = repo.get_deleted_users(0, 10).await.unwrap();
+        let after_delete = SystemTime::now();
+
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].user, created);
+        assert_eq!(
+            repo.restore_user(created.id).await.unwrap(),
+            Some(created.clone())
+        );
+        assert_eq!(repo.restore_user(created.id).await.unwrap(), None);
+        assert!(repo.user_exists(created.id).await.unwrap());
+        assert_eq!(
+            repo.get_user_by_id_as_of(created.id, after_delete)
+                .await
+                .unwrap(),
+            None
+        );
+        assert!(repo.get_deleted_users(0, 10).await.unwrap().is_empty());
+    }
+
+    #[sqlx::test]
+    async fn test_concurrent_updates_keep_one_current_version(pool: PgPool) {
+        let repo = TemporalUserRepository::new(pool);
+
+        let created = repo
+            .create_user("Temporal".to_string(), "Concurrent".to_string())
+            .await
+            .unwrap();
+        let mut updates = tokio::task::JoinSet::new();
+        for n in 0..8 {
+            let repo = repo.clone();
+            updates.spawn(async move {
+                repo.update_user(created.id, Some(format!("Name{}", n)), None)
+                    .await
+            });
+        }
+
+        for res in updates.join_all().await {
+            assert!(res.unwrap().is_some());
+        }
+        let current = crate::query_scalar!(
+            i64,
+            r#"SELECT COUNT(*) AS "count!" FROM user_versions WHERE id = $1 AND valid_to IS NULL"#,
+            created.id
+        )
+        .fetch_one(&repo.pool)
+        .await
+        .unwrap();
+        assert_eq!(current, 1);
+
+        repo.delete_user(created.id).await.unwrap();
+    }
+}
@@ -0,0 +1,464 @@
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use crate::repositories::user_event_repository_trait::UserEventRepository as UserEventRepositoryTrait;
+use crate::{
+    Error,
+    entities::users::{DeadLetter, StoredUserEvent, UserEventKind},
+};
+
+#[derive(Clone)]
+pub struct UserEventRepository {
+    pool: PgPool,
+}
+
+impl UserEventRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl UserEventRepositoryTrait for UserEventRepository {
+    async fn append(&self, kind: UserEventKind, user_id: i32) -> Result<u64, Error> {
+        let token = crate::query_scalar!(
+            i64,
+            r#"
+                INSERT INTO user_events (kind, user_id)
+                VALUES ($1, $2)
+                RETURNING token
+            "#,
+            kind.as_str(),
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(token as u64)
+    }
+
+    async fn events_after(&self, token: u64, limit: i64) -> Result<Vec<StoredUserEvent>, Error> {
+        let rows = crate::query_as!(
+            EventRow,
+            r#"
+                SELECT token, kind, user_id
+                FROM user_events
+                WHERE token > $1
+                ORDER BY token
+                LIMIT $2
+            "#,
+            token as i64,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        rows.into_iter().map(EventRow::into_event).collect()
+    }
+
+    async fn settled_events_after(
+        &self,
+        token: u64,
+        limit: i64,
+        settle: Duration,
+    ) -> Result<Vec<StoredUserEvent>, Error> {
+        let rows = crate::query_as!(
+            EventRow,
+            r#"
+                SELECT token, kind, user_id
+                FROM user_events
+                WHERE token > $1 AND created_at < now() - $3::float8 * interval '1 second'
+                ORDER BY token
+                LIMIT $2
+            "#,
+            token as i64,
+            limit,
+            settle.as_secs_f64()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        rows.into_iter().map(EventRow::into_event).collect()
+    }
+
+    async fn token_range(&self) -> Result<Option<(u64, u64)>, Error> {
+        let row = crate::query_as!(
+            RangeRow,
+            r#"
+                SELECT min(token) AS oldest, max(token) AS newest
+                FROM user_events
+            "#
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(row
+            .oldest
+            .zip(row.newest)
+            .map(|(oldest, newest)| (oldest as u64, newest as u64)))
+    }
+
+    async fn delivered_through(&self, destination: &str) -> Result<u64, Error> {
+        let token = crate::query_scalar!(
+            i64,
+            r#"
+                INSERT INTO outbox_cursors (destination)
+                VALUES ($1)
+                ON CONFLICT (destination) DO UPDATE SET destination = EXCLUDED.destination
+                RETURNING delivered_token
+            "#,
+            destination
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(token as u64)
+    }
+
+    async fn confirm_delivery(&self, destination: &str, token: u64) -> Result<(), Error> {
+        crate::query!(
+            r#"
+                UPDATE outbox_cursors
+                SET delivered_token = $2, updated_at = now()
+                WHERE destination = $1 AND delivered_token < $2
+            "#,
+            destination,
+            token as i64
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(())
+    }
+
+    async fn purge(&self, retention: Duration, destinations: &[String]) -> Result<u64, Error> {
+        // a destination without a cursor yet has confirmed nothing
+        let result = crate::query!(
+            r#"
+                DELETE FROM user_events
+                WHERE created_at < now() - $1::float8 * interval '1 second'
+                    AND token <= (
+                        SELECT CASE
+                            WHEN count(*) < cardinality($2::text[]) THEN 0
+                            ELSE coalesce(min(delivered_token), 9223372036854775807)
+                        END
+                        FROM outbox_cursors
+                        WHERE destination = ANY($2)
+                    )
+            "#,
+            retention.as_secs_f64(),
+            destinations
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn dead_letter(
+        &self,
+        destination: &str,
+        event: &StoredUserEvent,
+        attempts: i32,
+        reason: &str,
+    ) -> Result<(), Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| Error::Internal(Box::new(e)))?;
+        // already set aside when the relay failed over before moving on
+        crate::query!(
+            r#"
+                INSERT INTO dead_letters (destination, token, kind, user_id, attempts, reason)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (destination, token) DO NOTHING
+            "#,
+            destination,
+            event.token as i64,
+            event.kind.as_str(),
+            event.user_id,
+            attempts,
+            reason
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+        crate::query!(
+            r#"
+                UPDATE outbox_cursors
+                SET delivered_token = $2, updated_at = now()
+                WHERE destination = $1 AND delivered_token < $2
+            "#,
+            destination,
+            event.token as i64
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        tx.commit().await.map_err(|e| Error::Internal(Box::new(e)))
+    }
+
+    async fn dead_letters(
+        &self,
+        destination: Option<&str>,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<DeadLetter>, Error> {
+        let rows = crate::query_as!(
+            DeadLetterRow,
+            r#"
+                SELECT
+                    id, destination, token, kind, user_id, attempts, reason,
+                    (extract(epoch FROM failed_at) * 1000000)::bigint AS "failed_at_micros!"
+                FROM dead_letters
+                WHERE id > $1 AND ($2::text IS NULL OR destination = $2)
+                ORDER BY id
+                LIMIT $3
+            "#,
+            after_id,
+            destination,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        rows.into_iter()
+            .map(DeadLetterRow::into_dead_letter)
+            .collect()
+    }
+
+    async fn dead_letter_by_id(&self, id: i64) -> Result<Option<DeadLetter>, Error> {
+        let row = crate::query_as!(
+            DeadLetterRow,
+            r#"
+                SELECT
+                    id, destination, token, kind, user_id, attempts, reason,
+                    (extract(epoch FROM failed_at) * 1000000)::bigint AS "failed_at_micros!"
+                FROM dead_letters
+                WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        row.map(DeadLetterRow::into_dead_letter).transpose()
+    }
+
+    async fn redelivery_failed(&self, id: i64, reason: &str) -> Result<(), Error> {
+        crate::query!(
+            r#"
+                UPDATE dead_letters
+                SET attempts = attempts + 1, reason = $2, failed_at = now()
+                WHERE id = $1
+            "#,
+            id,
+            reason
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(())
+    }
+
+    async fn remove_dead_letter(&self, id: i64) -> Result<bool, Error> {
+        let result = crate::query!(
+            r#"
+                DELETE FROM dead_letters
+                WHERE id = $1
+            "#,
+            id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct EventRow {
+    token: i64,
+    kind: String,
+    user_id: i32,
+}
+
+impl EventRow {
+    fn into_event(self) -> Result<StoredUserEvent, Error> {
+        Ok(StoredUserEvent {
+            token: self.token as u64,
+            kind: self
+                .kind
+                .parse()
+                .map_err(|e: String| Error::Internal(e.into()))?,
+            user_id: self.user_id,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct DeadLetterRow {
+    id: i64,
+    destination: String,
+    token: i64,
+    kind: String,
+    user_id: i32,
+    attempts: i32,
+    reason: String,
+    #[sqlx(rename = "failed_at_micros!")]
+    failed_at_micros: i64,
+}
+
+impl DeadLetterRow {
+    fn into_dead_letter(self) -> Result<DeadLetter, Error> {
+        let event = EventRow {
+            token: self.token,
+            kind: self.kind,
+            user_id: self.user_id,
+        }
+        .into_event()?;
+
+        Ok(DeadLetter {
+            id: self.id,
+            destination: self.destination,
+            event,
+            attempts: self.attempts,
+            reason: self.reason,
+            failed_at: SystemTime::UNIX_EPOCH
+                + Duration::from_micros(self.failed_at_micros.max(0) as u64),
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct RangeRow {
+    oldest: Option<i64>,
+    newest: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_events_in_token_order(pool: PgPool) {
+        let repo = UserEventRepository::new(pool);
+        assert_eq!(repo.token_range().await.unwrap(), None);
+
+        let created = repo.append(UserEventKind::Created, 7).await.unwrap();
+        let deleted = repo.append(UserEventKind::Deleted, 7).await.unwrap();
+        assert!(deleted > created);
+        assert_eq!(repo.token_range().await.unwrap(), Some((created, deleted)));
+        assert_eq!(
+            repo.events_after(created, 10).await.unwrap(),
+            [StoredUserEvent {
+                token: deleted,
+                kind: UserEventKind::Deleted,
+                user_id: 7,
+            }]
+        );
+        assert_eq!(repo.events_after(0, 1).await.unwrap()[0].token, created);
+
+        assert!(
+            repo.settled_events_after(0, 10, Duration::from_secs(3600))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            repo.settled_events_after(0, 10, Duration::ZERO)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        assert_eq!(repo.purge(Duration::from_secs(3600), &[]).await.unwrap(), 0);
+        assert_eq!(repo.purge(Duration::ZERO, &[]).await.unwrap(), 2);
+        assert_eq!(repo.token_range().await.unwrap(), None);
+    }
+
+    #[sqlx::test]
+    async fn test_purge_keeps_unconfirmed_events(pool: PgPool) {
+        let repo = UserEventRepository::new(pool);
+        let first = repo.append(UserEventKind::Created, 1).await.unwrap();
+        let second = repo.append(UserEventKind::Created, 2).await.unwrap();
+        let destinations = ["audit".to_string(), "search".to_string()];
+
+        assert_eq!(repo.delivered_through("audit").await.unwrap(), 0);
+        repo.confirm_delivery("audit", second).await.unwrap();
+        repo.confirm_delivery("audit", first).await.unwrap();
+        assert_eq!(repo.delivered_through("audit").await.unwrap(), second);
+        // "search" has no cursor yet
+        assert_eq!(repo.purge(Duration::ZERO, &destinations).await.unwrap(), 0);
+
+        repo.delivered_through("search").await.unwrap();
+        repo.confirm_delivery("search", first).await.unwrap();
+        assert_eq!(repo.purge(Duration::ZERO, &destinations).await.unwrap(), 1);
+        assert_eq!(repo.token_range().await.unwrap(), Some((second, second)));
+    }
+
+    #[sqlx::test]
+    async fn test_dead_letters(pool: PgPool) {
+        let repo = UserEventRepository::new(pool);
+        let token = repo.append(UserEventKind::Deleted, 3).await.unwrap();
+        let event = repo.events_after(0, 1).await.unwrap()[0];
+        repo.delivered_through("search").await.unwrap();
+
+        repo.dead_letter("search", &event, 8, "503 Service Unavailable")
+            .await
+            .unwrap();
+        // set aside again after a failover, kept once
+        repo.dead_letter("search", &event, 8, "timed out")
+            .await
+            .unwrap();
+        assert_eq!(repo.delivered_through("search").await.unwrap(), token);
+
+        let letters = repo.dead_letters(None, 0, 10).await.unwrap();
+        assert_eq!(letters.len(), 1);
+        assert_eq!(
+            (
+                letters[0].event,
+                letters[0].attempts,
+                letters[0].reason.as_str()
+            ),
+            (event, 8, "503 Service Unavailable")
+        );
+        assert!(
+            repo.dead_letters(Some("audit"), 0, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            repo.dead_letters(None, letters[0].id, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let id = letters[0].id;
+        repo.redelivery_failed(id, "timed out").await.unwrap();
+        let letter = repo.dead_letter_by_id(id).await.unwrap().unwrap();
+        assert_eq!((letter.attempts, letter.reason.as_str()), (9, "timed out"));
+
+        assert!(repo.remove_dead_letter(id).await.unwrap());
+        assert!(!repo.remove_dead_letter(id).await.unwrap());
+        assert_eq!(repo.dead_letter_by_id(id).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use crate::{
+    Error,
+    entities::users::{DeadLetter, StoredUserEvent, UserEventKind},
+};
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait UserEventRepository: Send + Sync {
+    // the token of the appended event, above any appended before
+    async fn append(&self, kind: UserEventKind, user_id: i32) -> Result<u64, Error>;
+    // up to `limit` events after `token`, oldest first
+    async fn events_after(&self, token: u64, limit: i64) -> Result<Vec<StoredUserEvent>, Error>;
+    // like `events_after`, leaving out the events appended within `settle`:
+    // tokens are taken before commit, so a newer one may show first
+    async fn settled_events_after(
+        &self,
+        token: u64,
+        limit: i64,
+        settle: Duration,
+    ) -> Result<Vec<StoredUserEvent>, Error>;
+    // the oldest and newest tokens kept, None while there are none
+    async fn token_range(&self) -> Result<Option<(u64, u64)>, Error>;
+    // the last token `destination` confirmed, 0 for one new
+    async fn delivered_through(&self, destination: &str) -> Result<u64, Error>;
+    // moves `destination` forward to `token`, never back
+    async fn confirm_delivery(&self, destination: &str, token: u64) -> Result<(), Error>;
+    // drops the events older than `retention` that every one of
+    // `destinations` confirmed; how many went
+    async fn purge(&self, retention: Duration, destinations: &[String]) -> Result<u64, Error>;
+    // sets `event` aside for `destination` after its last failed attempt and
+    // moves the destination past it, both or neither
+    async fn dead_letter(
+        &self,
+        destination: &str,
+        event: &StoredUserEvent,
+        attempts: i32,
+        reason: &str,
+    ) -> Result<(), Error>;
+    // up to `limit` dead letters after the id `after_id`, of one destination
+    // or of all, oldest first
+    async fn dead_letters(
+        &self,
+        destination: Option<&str>,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<DeadLetter>, Error>;
+    async fn dead_letter_by_id(&self, id: i64) -> Result<Option<DeadLetter>, Error>;
+    // one more failed attempt, `reason` replacing the last
+    async fn redelivery_failed(&self, id: i64, reason: &str) -> Result<(), Error>;
+    // false when there is no such dead letter
+    async fn remove_dead_letter(&self, id: i64) -> Result<bool, Error>;
+}
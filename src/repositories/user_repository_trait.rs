@@ -1,13 +1,63 @@
-use crate::{Error, entities::users::User};
+use std::time::SystemTime;
+
+use crate::{
+    Error,
+    entities::{
+        audit::AuditEntry,
+        users::{DeletedUser, User, UserStatus},
+    },
+    filter::Filter,
+};
 use async_trait::async_trait;
 
 #[async_trait]
 pub trait UserRepository: Send + Sync + Clone {
     async fn create_user(&self, name: String, surname: String) -> Result<User, Error>;
+    // (name, surname) pairs, created in their order; backends that can do
+    // better than one insert per user override it. On an error the users
+    // created before it stay
+    async fn create_users_bulk(&self, users: Vec<(String, String)>) -> Result<Vec<User>, Error> {
+        let mut created = Vec::with_capacity(users.len());
+        for (name, surname) in users {
+            created.push(self.create_user(name, surname).await?);
+        }
+
+        Ok(created)
+    }
+    // the listings below leave out suspended users
     async fn get_users(&self) -> Result<(Vec<User>, i32), Error>;
+    async fn count_users(&self) -> Result<i64, Error>;
     async fn get_users_batch(&self, offset: i32, limit: i32) -> Result<Vec<User>, Error>;
+    async fn get_users_filtered(
+        &self,
+        filter: Filter,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<User>, Error>;
+    // a GetUsers page as it is sent; backends reading their rows one at a
+    // time override it to convert each as it arrives
+    async fn get_users_page(
+        &self,
+        filter: Option<Filter>,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<crate::grpc::User>, Error> {
+        let users = match filter {
+            Some(filter) => self.get_users_filtered(filter, offset, limit).await?,
+            None => self.get_users_batch(offset, limit).await?,
+        };
+
+        Ok(users.into_iter().map(User::into_grpc).collect())
+    }
     async fn get_user_by_id(&self, id: i32) -> Result<Option<User>, Error>;
     async fn get_user_by_name(&self, name: String) -> Result<Option<User>, Error>;
+    async fn get_users_by_names(&self, names: Vec<String>) -> Result<Vec<User>, Error>;
+    async fn get_users_by_name_prefix(
+        &self,
+        prefix: String,
+        limit: i32,
+    ) -> Result<Vec<User>, Error>;
+    async fn user_exists(&self, id: i32) -> Result<bool, Error>;
     async fn update_user(
         &self,
         id: i32,
@@ -15,4 +65,43 @@ pub trait UserRepository: Send + Sync + Clone {
         surname: Option<String>,
     ) -> Result<Option<User>, Error>;
     async fn delete_user(&self, id: i32) -> Result<(), Error>;
+    async fn soft_delete_user(&self, id: i32) -> Result<(), Error>;
+    // undoes soft_delete_user; None when the user is not soft deleted, be it
+    // live, hard deleted or never stored
+    async fn restore_user(&self, id: i32) -> Result<Option<User>, Error>;
+    // up to `limit` soft deleted users with an id above `after_id`, by id
+    async fn get_deleted_users(&self, after_id: i32, limit: i32)
+    -> Result<Vec<DeletedUser>, Error>;
+    // moves the addresses and relationships of the duplicate over to the
+    // primary, soft deletes the duplicate and records `audit`, all or nothing;
+    // NotFound unless both users are live
+    async fn merge_users(
+        &self,
+        primary_id: i32,
+        duplicate_id: i32,
+        audit: Vec<AuditEntry>,
+    ) -> Result<User, Error>;
+    // None unless the user is live
+    async fn get_user_status(&self, id: i32) -> Result<Option<UserStatus>, Error>;
+    // moves a live user from `from` to `to`; false when it is not live or no
+    // longer in `from`
+    async fn set_user_status(
+        &self,
+        id: i32,
+        from: UserStatus,
+        to: UserStatus,
+    ) -> Result<bool, Error>;
+    // overwrites the name and surname of the user, soft deleted or not, and of
+    // every earlier version kept of it, so the old values survive nowhere;
+    // NotFound when neither the user nor any version of it is stored
+    async fn erase_user(&self, id: i32, name: String, surname: String) -> Result<(), Error>;
+    // the state at `as_of`, rebuilt from the change history; None when the
+    // user did not exist yet or was already deleted
+    async fn get_user_by_id_as_of(&self, id: i32, as_of: SystemTime)
+    -> Result<Option<User>, Error>;
+    async fn get_users_as_of(
+        &self,
+        filter: Option<Filter>,
+        as_of: SystemTime,
+    ) -> Result<Vec<User>, Error>;
 }
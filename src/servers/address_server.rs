@@ -0,0 +1,121 @@
+use tonic::Status;
+use tracing::error;
+
+use crate::{
+    auth::Principal,
+    grpc::{
+        AddUserAddressRequest, AddUserAddressResponse, DeleteAddressRequest, DeleteAddressResponse,
+        ListUserAddressesRequest, ListUserAddressesResponse,
+        address_service_server::AddressService,
+    },
+    redact,
+    repositories::address_repository::NewAddress,
+    servers::{self, into_status, request_log::log_request},
+    usecases::AddressUsecaseTrait,
+};
+
+const SERVICE: &str = "user.v1.AddressService";
+
+pub struct AddressServer<T: AddressUsecaseTrait> {
+    span: tracing::Span,
+    usecase: T,
+}
+
+impl<T: AddressUsecaseTrait> AddressServer<T> {
+    pub fn new(span: tracing::Span, usecase: T) -> Self {
+        Self { span, usecase }
+    }
+}
+
+#[tonic::async_trait]
+impl<T: AddressUsecaseTrait + 'static> AddressService for AddressServer<T> {
+    async fn add_user_address(
+        &self,
+        input: tonic::Request<AddUserAddressRequest>,
+    ) -> Result<tonic::Response<AddUserAddressResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "AddUserAddress",
+            caller = ?caller.map(|p| p.user_id),
+            "adding address for user id={:?}",
+            body.user_id
+        );
+        let res = self
+            .usecase
+            .add_user_address(
+                caller,
+                NewAddress {
+                    user_id: body.user_id,
+                    street: body.street,
+                    city: body.city,
+                    postal_code: body.postal_code,
+                    country: body.country,
+                },
+            )
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to add address: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn list_user_addresses(
+        &self,
+        input: tonic::Request<ListUserAddressesRequest>,
+    ) -> Result<tonic::Response<ListUserAddressesResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "ListUserAddresses",
+            caller = ?caller.map(|p| p.user_id),
+            "listing addresses for user id={:?}",
+            body.user_id
+        );
+        let res = self
+            .usecase
+            .list_user_addresses(caller, body.user_id)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to list addresses: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn delete_address(
+        &self,
+        input: tonic::Request<DeleteAddressRequest>,
+    ) -> Result<tonic::Response<DeleteAddressResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "DeleteAddress",
+            caller = ?caller.map(|p| p.user_id),
+            "deleting address id={:?}",
+            body.id
+        );
+        let res = self
+            .usecase
+            .delete_address(caller, body.id)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to delete address: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+}
@@ -0,0 +1,1400 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use sqlx::PgPool;
+use tonic::Status;
+use tracing::{info, warn};
+
+use crate::{
+    auth::Principal,
+    backup::Backups,
+    build_info,
+    entities::{
+        api_keys::ApiKeyLimits,
+        audit::{AuditFilter, AuditRecord},
+        backups::Backup,
+        pii::KeyRotation,
+        users::DeadLetter,
+    },
+    grpc::{
+        self, ApiKeyQuota, CacheStats, ClearRepositoryDelaysRequest, ClearRepositoryDelaysResponse,
+        CreateBackupRequest, CreateBackupResponse, DbPoolStats, EnterLameDuckRequest,
+        EnterLameDuckResponse, GetBackupRequest, GetBackupResponse, GetDependencyHealthRequest,
+        GetDependencyHealthResponse, GetLogLevelRequest, GetLogLevelResponse,
+        GetPiiKeyRotationRequest, GetPiiKeyRotationResponse, GetServerInfoRequest,
+        GetServerInfoResponse, GetServerStatsRequest, GetServerStatsResponse,
+        GetTraceSamplingRequest, GetTraceSamplingResponse, GetUsageRequest, GetUsageResponse,
+        ListAuditEntriesRequest, ListAuditEntriesResponse, ListDeadLettersRequest,
+        ListDeadLettersResponse, ListDeletedUsersRequest, ListDeletedUsersResponse,
+        ListRepositoryDelaysRequest, ListRepositoryDelaysResponse, MethodSampling, MethodStats,
+        PiiKeyRotation, RedeliverDeadLetterRequest, RedeliverDeadLetterResponse,
+        ReloadConfigRequest, ReloadConfigResponse, RepositoryDelay, ResetUsageRequest,
+        ResetUsageResponse, RestoreBackupRequest, RestoreBackupResponse, RotatePiiKeyRequest,
+        RotatePiiKeyResponse, SetLogLevelRequest, SetLogLevelResponse, SetQuotaRequest,
+        SetQuotaResponse, SetRepositoryDelayRequest, SetRepositoryDelayResponse,
+        SetTraceSamplingRequest, SetTraceSamplingResponse, StreamStats,
+        admin_service_server::AdminService,
+    },
+    health::{DependencyHealth, DependencyStatus},
+    jobs::outbox_relay::OutboxRelay,
+    metrics::{Key, Value, registry, requests::GRPC_HANDLED_TOTAL},
+    pii::rotation::KeyRotator,
+    ratelimit::RateLimiter,
+    reload::Reloader,
+    repositories::{
+        AuditRepository,
+        audit_repository::LogAuditRepository,
+        slow_user_repository::{SlowOperation, SlowOperations},
+    },
+    servers::{self, into_status},
+    shutdown::Shutdown,
+    telemetry::{self, TraceSampling},
+    usecases::{UserUsecaseTrait, paging},
+};
+
+// the api_key column of api_key_limits
+const MAX_API_KEY_LEN: usize = 128;
+const DEFAULT_ROTATION_BATCH_SIZE: u32 = 500;
+
+pub struct AdminServer {
+    span: tracing::Span,
+    audit: Arc<dyn AuditRepository>,
+    users: Option<Arc<dyn UserUsecaseTrait>>,
+    slow_operations: SlowOperations,
+    slow_db_simulation: bool,
+    reloader: Option<Arc<Reloader>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    key_rotator: Option<Arc<KeyRotator>>,
+    outbox_relay: Option<Arc<OutboxRelay>>,
+    backups: Option<Arc<Backups>>,
+    health: DependencyHealth,
+    shutdown: Option<Shutdown>,
+    db_pool: Option<PgPool>,
+    // when the server was set up, at startup
+    started: (SystemTime, Instant),
+    // bumped by every SetLogLevel, so only the latest one reverts
+    log_level_changes: Arc<AtomicU64>,
+}
+
+impl AdminServer {
+    pub fn new(span: tracing::Span, slow_operations: SlowOperations) -> Self {
+        Self {
+            span,
+            audit: Arc::new(LogAuditRepository),
+            users: None,
+            slow_operations,
+            slow_db_simulation: false,
+            reloader: None,
+            rate_limiter: None,
+            key_rotator: None,
+            outbox_relay: None,
+            backups: None,
+            health: DependencyHealth::default(),
+            shutdown: None,
+            db_pool: None,
+            started: (SystemTime::now(), Instant::now()),
+            log_level_changes: Arc::default(),
+        }
+    }
+
+    pub fn with_audit_log(mut self, audit: Arc<dyn AuditRepository>) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    pub fn with_users(mut self, users: Arc<dyn UserUsecaseTrait>) -> Self {
+        self.users = Some(users);
+        self
+    }
+
+    pub fn with_reloader(mut self, reloader: Arc<Reloader>) -> Self {
+        self.reloader = Some(reloader);
+        self
+    }
+
+    // RATE_LIMITS, without it there are no quotas to manage
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    // PII_MASTER_KEY or PII_TRANSIT_URL, without it there is no key to rotate
+    pub fn with_key_rotator(mut self, key_rotator: Arc<KeyRotator>) -> Self {
+        self.key_rotator = Some(key_rotator);
+        self
+    }
+
+    // OUTBOX_DESTINATIONS, without it nothing is dead lettered
+    pub fn with_outbox_relay(mut self, outbox_relay: Arc<OutboxRelay>) -> Self {
+        self.outbox_relay = Some(outbox_relay);
+        self
+    }
+
+    // BACKUP_STORAGE_URL, without it there is nowhere to keep backups
+    pub fn with_backups(mut self, backups: Arc<Backups>) -> Self {
+        self.backups = Some(backups);
+        self
+    }
+
+    // what readiness checks, shown by GetDependencyHealth
+    pub fn with_dependency_health(mut self, health: DependencyHealth) -> Self {
+        self.health = health;
+        self
+    }
+
+    // the server's, put in lame duck by EnterLameDuck
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    // the postgres backend's, whose state GetServerStats shows
+    pub fn with_db_pool(mut self, db_pool: PgPool) -> Self {
+        self.db_pool = Some(db_pool);
+        self
+    }
+
+    // SLOW_DB_SIMULATION, without it no delay can be set
+    pub fn with_slow_db_simulation(mut self, enabled: bool) -> Self {
+        self.slow_db_simulation = enabled;
+        self
+    }
+
+    fn rate_limiter(&self, api_key: &str) -> Result<&RateLimiter, Status> {
+        let Some(rate_limiter) = &self.rate_limiter else {
+            return Err(Status::failed_precondition("quotas need RATE_LIMITS=true"));
+        };
+        if api_key.is_empty() || api_key.len() > MAX_API_KEY_LEN {
+            return Err(Status::invalid_argument(format!(
+                "api_key must be 1 to {} characters",
+                MAX_API_KEY_LEN
+            )));
+        }
+        Ok(rate_limiter)
+    }
+
+    fn key_rotator(&self) -> Result<&KeyRotator, Status> {
+        self.key_rotator.as_deref().ok_or_else(|| {
+            Status::failed_precondition("key rotation needs PII_MASTER_KEY or PII_TRANSIT_URL")
+        })
+    }
+
+    fn outbox_relay(&self) -> Result<&OutboxRelay, Status> {
+        self.outbox_relay
+            .as_deref()
+            .ok_or_else(|| Status::failed_precondition("dead letters need OUTBOX_DESTINATIONS"))
+    }
+
+    fn backups(&self) -> Result<&Backups, Status> {
+        self.backups
+            .as_deref()
+            .ok_or_else(|| Status::failed_precondition("backups need BACKUP_STORAGE_URL"))
+    }
+
+    fn delays(&self) -> Vec<RepositoryDelay> {
+        self.slow_operations
+            .list()
+            .into_iter()
+            .map(|(operation, slow)| RepositoryDelay {
+                operation,
+                delay_ms: slow.delay.as_millis().try_into().unwrap_or(u32::MAX),
+                time_out: slow.time_out,
+            })
+            .collect()
+    }
+}
+
+// without authentication there is no caller and nothing to enforce
+fn authorize(extensions: &tonic::Extensions) -> Result<Option<i32>, Status> {
+    match Principal::from_extensions(extensions) {
+        Some(caller) if !caller.is_admin() => Err(Status::permission_denied(
+            "the admin service needs the admin role",
+        )),
+        caller => Ok(caller.map(|p| p.real_user_id())),
+    }
+}
+
+fn audit_filter(request: &ListAuditEntriesRequest) -> Result<AuditFilter, Status> {
+    let time = |field: &str, time: Option<prost_types::Timestamp>| {
+        time.map(SystemTime::try_from)
+            .transpose()
+            .map_err(|e| Status::invalid_argument(format!("invalid {}: {}", field, e)))
+    };
+
+    Ok(AuditFilter {
+        actor_user_id: request.actor_user_id,
+        target_user_id: request.user_id,
+        action: match request.action.as_str() {
+            "" => None,
+            action => Some(action.parse().map_err(Status::invalid_argument)?),
+        },
+        start: time("start", request.start)?,
+        end: time("end", request.end)?,
+    })
+}
+
+fn audit_entry_message(record: AuditRecord) -> grpc::AuditEntry {
+    grpc::AuditEntry {
+        id: record.id,
+        action: record.entry.action.as_str().to_string(),
+        target_user_id: record.entry.target_user_id,
+        actor_user_id: record.entry.actor_user_id,
+        effective_user_id: record.entry.effective_user_id,
+        created_at: Some(record.created_at.into()),
+    }
+}
+
+fn dead_letter_message(letter: DeadLetter) -> grpc::DeadLetter {
+    grpc::DeadLetter {
+        id: letter.id,
+        destination: letter.destination,
+        token: letter.event.token,
+        kind: letter.event.kind.as_str().to_string(),
+        user_id: letter.event.user_id,
+        attempts: letter.attempts,
+        reason: letter.reason,
+        failed_at: Some(letter.failed_at.into()),
+    }
+}
+
+// the parts of GetServerStats the metrics registry keeps
+fn registry_stats(snapshot: Vec<(Key, Value)>) -> (GetServerStatsResponse, u64) {
+    let mut stats = GetServerStatsResponse::default();
+    let (mut user_cache, mut response_cache) = (CacheStats::default(), CacheStats::default());
+    let mut acquire_timeouts = 0;
+    for (key, value) in snapshot {
+        let label = |name: &str| {
+            key.labels
+                .iter()
+                .find(|(l, _)| *l == name)
+                .map(|(_, v)| v.clone())
+                .unwrap_or_default()
+        };
+        match (key.name, value) {
+            (GRPC_HANDLED_TOTAL, Value::Counter(requests)) => stats.methods.push(MethodStats {
+                method: label("method"),
+                requests,
+            }),
+            ("grpc_server_open_streams", Value::Gauge(open)) if open >= 1.0 => {
+                stats.open_streams.push(StreamStats {
+                    method: label("method"),
+                    open: open as u32,
+                })
+            }
+            ("user_cache_requests_total", Value::Counter(n)) => {
+                count_lookups(&mut user_cache, &label("result"), n)
+            }
+            ("response_cache_requests_total", Value::Counter(n)) => {
+                count_lookups(&mut response_cache, &label("result"), n)
+            }
+            ("db_pool_acquire_timeouts_total", Value::Counter(n)) => acquire_timeouts = n,
+            _ => {}
+        }
+    }
+    for cache in [&mut user_cache, &mut response_cache] {
+        let lookups = cache.hits + cache.misses;
+        if lookups > 0 {
+            cache.hit_rate = cache.hits as f64 / lookups as f64;
+        }
+    }
+    stats.user_cache = Some(user_cache);
+    stats.response_cache = Some(response_cache);
+
+    (stats, acquire_timeouts)
+}
+
+// "hit" and the user cache's "negative_hit" are hits
+fn count_lookups(cache: &mut CacheStats, result: &str, n: u64) {
+    if result == "miss" {
+        cache.misses += n;
+    } else {
+        cache.hits += n;
+    }
+}
+
+fn quota_message(api_key: String, limits: ApiKeyLimits) -> ApiKeyQuota {
+    ApiKeyQuota {
+        api_key,
+        requests_per_minute: limits.requests_per_minute,
+        monthly_quota: limits.monthly_quota,
+    }
+}
+
+fn rotation_message(rotation: KeyRotation) -> PiiKeyRotation {
+    PiiKeyRotation {
+        id: rotation.id,
+        data_key_id: rotation.data_key_id,
+        state: rotation.state.as_str().to_string(),
+        batch_size: rotation.batch_size,
+        max_users_per_second: rotation.max_users_per_second,
+        after_user_id: rotation.after_user_id,
+        values_rewritten: rotation.values_rewritten,
+        started_at: Some(rotation.started_at.into()),
+        updated_at: Some(rotation.updated_at.into()),
+        finished_at: rotation.finished_at.map(Into::into),
+    }
+}
+
+fn backup_message(backup: Backup) -> grpc::Backup {
+    let restore = backup.restore;
+    grpc::Backup {
+        id: backup.id,
+        object: backup.object,
+        state: backup.state.as_str().to_string(),
+        row_count: backup.row_count,
+        byte_count: backup.byte_count,
+        error: backup.error.unwrap_or_default(),
+        created_at: Some(backup.created_at.into()),
+        completed_at: backup.completed_at.map(Into::into),
+        restore_state: restore
+            .as_ref()
+            .map(|r| r.state.as_str().to_string())
+            .unwrap_or_default(),
+        restored_rows: restore.as_ref().map(|r| r.row_count).unwrap_or_default(),
+        restore_error: restore
+            .as_ref()
+            .and_then(|r| r.error.clone())
+            .unwrap_or_default(),
+        restore_started_at: restore.as_ref().map(|r| r.started_at.into()),
+        restored_at: restore.and_then(|r| r.finished_at).map(Into::into),
+    }
+}
+
+fn dependency_message(status: DependencyStatus) -> grpc::DependencyHealth {
+    grpc::DependencyHealth {
+        name: status.name,
+        kind: status.kind.to_string(),
+        critical: status.critical,
+        healthy: status.healthy,
+        error: status.error.unwrap_or_default(),
+        latency_ms: status.latency.as_millis().min(u32::MAX as u128) as u32,
+        checked_at: Some(status.checked_at.into()),
+        changed_at: Some(status.changed_at.into()),
+    }
+}
+
+fn sampling_message(sampling: TraceSampling) -> grpc::TraceSampling {
+    grpc::TraceSampling {
+        rate: sampling.rate,
+        methods: sampling
+            .methods
+            .into_iter()
+            .map(|(method, rate)| MethodSampling { method, rate })
+            .collect(),
+    }
+}
+
+fn sampling_from(message: grpc::TraceSampling) -> Result<TraceSampling, Status> {
+    let rates = std::iter::once(message.rate).chain(message.methods.iter().map(|m| m.rate));
+    if let Some(rate) = rates.into_iter().find(|r| !(0.0..=1.0).contains(r)) {
+        return Err(Status::invalid_argument(format!(
+            "sampling rate {} is not between 0 and 1",
+            rate
+        )));
+    }
+    if message.methods.iter().any(|m| m.method.is_empty()) {
+        return Err(Status::invalid_argument("method must be set"));
+    }
+
+    Ok(TraceSampling {
+        rate: message.rate,
+        methods: message
+            .methods
+            .into_iter()
+            .map(|m| (m.method, m.rate))
+            .collect(),
+    })
+}
+
+#[tonic::async_trait]
+impl AdminService for AdminServer {
+    async fn set_repository_delay(
+        &self,
+        input: tonic::Request<SetRepositoryDelayRequest>,
+    ) -> Result<tonic::Response<SetRepositoryDelayResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = authorize(&extensions)?;
+        if !self.slow_db_simulation {
+            return Err(Status::failed_precondition(
+                "repository delays need SLOW_DB_SIMULATION=true",
+            ));
+        }
+
+        let slow = SlowOperation {
+            delay: Duration::from_millis(body.delay_ms.into()),
+            time_out: body.time_out,
+        };
+        self.slow_operations
+            .set(&body.operation, slow)
+            .map_err(|e| into_status(&e, format!("failed to set repository delay: {:?}", e)))?;
+        warn!(
+            caller = ?caller,
+            "repository operation {:?} now delayed by {:?}, time_out={}",
+            body.operation, slow.delay, slow.time_out
+        );
+
+        Ok(tonic::Response::new(SetRepositoryDelayResponse {
+            delays: self.delays(),
+        }))
+    }
+
+    async fn list_repository_delays(
+        &self,
+        input: tonic::Request<ListRepositoryDelaysRequest>,
+    ) -> Result<tonic::Response<ListRepositoryDelaysResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        authorize(input.extensions())?;
+
+        Ok(tonic::Response::new(ListRepositoryDelaysResponse {
+            delays: self.delays(),
+        }))
+    }
+
+    async fn clear_repository_delays(
+        &self,
+        input: tonic::Request<ClearRepositoryDelaysRequest>,
+    ) -> Result<tonic::Response<ClearRepositoryDelaysResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let caller = authorize(input.extensions())?;
+
+        self.slow_operations.clear();
+        info!(caller = ?caller, "repository delays cleared");
+
+        Ok(tonic::Response::new(ClearRepositoryDelaysResponse {}))
+    }
+
+    async fn set_log_level(
+        &self,
+        input: tonic::Request<SetLogLevelRequest>,
+    ) -> Result<tonic::Response<SetLogLevelResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = authorize(&extensions)?;
+
+        let previous = telemetry::override_log_level(&body.filter)
+            .map_err(|e| into_status(&e, format!("failed to set log level: {}", e)))?;
+        let filter = telemetry::log_level().unwrap_or_default();
+        let change = self.log_level_changes.fetch_add(1, Ordering::SeqCst) + 1;
+        warn!(caller = ?caller, "log level set to {:?}, was {:?}", filter, previous);
+
+        if body.revert_after_secs > 0 {
+            let changes = self.log_level_changes.clone();
+            let revert_to = previous.clone();
+            let after = Duration::from_secs(body.revert_after_secs.into());
+            tokio::spawn(async move {
+                tokio::time::sleep(after).await;
+                if changes.load(Ordering::SeqCst) != change {
+                    return;
+                }
+                match telemetry::override_log_level(&revert_to) {
+                    Ok(_) => info!("log level reverted to {:?}", revert_to),
+                    Err(e) => warn!("failed to revert the log level: {}", e),
+                }
+            });
+        }
+
+        Ok(tonic::Response::new(SetLogLevelResponse {
+            filter,
+            previous,
+        }))
+    }
+
+    async fn get_log_level(
+        &self,
+        input: tonic::Request<GetLogLevelRequest>,
+    ) -> Result<tonic::Response<GetLogLevelResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        authorize(input.extensions())?;
+
+        Ok(tonic::Response::new(GetLogLevelResponse {
+            filter: telemetry::log_level().unwrap_or_default(),
+        }))
+    }
+
+    async fn set_trace_sampling(
+        &self,
+        input: tonic::Request<SetTraceSamplingRequest>,
+    ) -> Result<tonic::Response<SetTraceSamplingResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = authorize(&extensions)?;
+
+        let sampling = sampling_from(body.sampling.unwrap_or_default())?;
+        warn!(caller = ?caller, "trace sampling set to {:?}", sampling);
+        telemetry::set_trace_sampling(sampling.clone());
+
+        Ok(tonic::Response::new(SetTraceSamplingResponse {
+            sampling: Some(sampling_message(sampling)),
+        }))
+    }
+
+    async fn get_trace_sampling(
+        &self,
+        input: tonic::Request<GetTraceSamplingRequest>,
+    ) -> Result<tonic::Response<GetTraceSamplingResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        authorize(input.extensions())?;
+
+        Ok(tonic::Response::new(GetTraceSamplingResponse {
+            sampling: Some(sampling_message(telemetry::trace_sampling())),
+        }))
+    }
+
+    async fn get_dependency_health(
+        &self,
+        input: tonic::Request<GetDependencyHealthRequest>,
+    ) -> Result<tonic::Response<GetDependencyHealthResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        authorize(input.extensions())?;
+
+        let statuses = self.health.statuses();
+        Ok(tonic::Response::new(GetDependencyHealthResponse {
+            state: self.health.state().as_str().to_string(),
+            dependencies: statuses.into_iter().map(dependency_message).collect(),
+        }))
+    }
+
+    async fn get_server_info(
+        &self,
+        input: tonic::Request<GetServerInfoRequest>,
+    ) -> Result<tonic::Response<GetServerInfoResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        authorize(input.extensions())?;
+
+        Ok(tonic::Response::new(GetServerInfoResponse {
+            version: build_info::VERSION.to_string(),
+            git_sha: build_info::GIT_SHA.to_string(),
+            built_at: Some(build_info::built_at().into()),
+            rustc_version: build_info::RUSTC_VERSION.to_string(),
+        }))
+    }
+
+    async fn get_server_stats(
+        &self,
+        input: tonic::Request<GetServerStatsRequest>,
+    ) -> Result<tonic::Response<GetServerStatsResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        authorize(input.extensions())?;
+
+        let (mut stats, acquire_timeouts) = registry_stats(registry().snapshot());
+        stats.started_at = Some(self.started.0.into());
+        stats.uptime_seconds = self.started.1.elapsed().as_secs();
+        stats.version = build_info::VERSION.to_string();
+        stats.db_pool = self.db_pool.as_ref().map(|pool| DbPoolStats {
+            connections: pool.size(),
+            idle_connections: pool.num_idle() as u32,
+            max_connections: pool.options().get_max_connections(),
+            acquire_timeouts,
+        });
+
+        Ok(tonic::Response::new(stats))
+    }
+
+    async fn reload_config(
+        &self,
+        input: tonic::Request<ReloadConfigRequest>,
+    ) -> Result<tonic::Response<ReloadConfigResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let caller = authorize(input.extensions())?;
+        let Some(reloader) = &self.reloader else {
+            return Err(Status::failed_precondition(
+                "configuration reload is not set up",
+            ));
+        };
+
+        info!(caller = ?caller, "configuration reload requested");
+        let reloaded = reloader
+            .reload()
+            .map_err(|e| into_status(&e, format!("failed to reload configuration: {}", e)))?;
+
+        Ok(tonic::Response::new(ReloadConfigResponse {
+            reloaded: reloaded.applied.iter().map(|s| s.to_string()).collect(),
+            needs_restart: reloaded.needs_restart,
+        }))
+    }
+
+    async fn enter_lame_duck(
+        &self,
+        input: tonic::Request<EnterLameDuckRequest>,
+    ) -> Result<tonic::Response<EnterLameDuckResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let caller = authorize(input.extensions())?;
+        let Some(shutdown) = &self.shutdown else {
+            return Err(Status::failed_precondition("lame duck is not set up"));
+        };
+
+        let entered = shutdown.enter_lame_duck();
+        if entered {
+            warn!(caller = ?caller, "entered lame duck, refusing new streams");
+        }
+        Ok(tonic::Response::new(EnterLameDuckResponse { entered }))
+    }
+
+    async fn list_audit_entries(
+        &self,
+        input: tonic::Request<ListAuditEntriesRequest>,
+    ) -> Result<tonic::Response<ListAuditEntriesResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = authorize(&extensions)?;
+        let filter = audit_filter(&body)?;
+        let page_size = paging::page_size(body.page_size)
+            .map_err(|e| into_status(&e, format!("failed to list audit entries: {:?}", e)))?;
+        // the token is the id of the last entry on the previous page
+        let after_id = match body.page_token.as_str() {
+            "" => 0,
+            token => token
+                .parse()
+                .map_err(|_| Status::invalid_argument("invalid page_token"))?,
+        };
+
+        info!(caller = ?caller, "listing audit entries {:?} after id={}", filter, after_id);
+        // one extra entry tells whether another page exists
+        let mut records = self
+            .audit
+            .list(filter, after_id, i64::from(page_size) + 1)
+            .await
+            .map_err(|e| into_status(&e, format!("failed to list audit entries: {:?}", e)))?;
+        let next_page_token = if records.len() > page_size as usize {
+            records.truncate(page_size as usize);
+            records.last().map(|r| r.id.to_string()).unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        Ok(tonic::Response::new(ListAuditEntriesResponse {
+            entries: records.into_iter().map(audit_entry_message).collect(),
+            next_page_token,
+        }))
+    }
+
+    async fn list_deleted_users(
+        &self,
+        input: tonic::Request<ListDeletedUsersRequest>,
+    ) -> Result<tonic::Response<ListDeletedUsersResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = authorize(&extensions)?;
+        let Some(users) = &self.users else {
+            return Err(Status::failed_precondition("deleted users are not set up"));
+        };
+
+        info!(caller = ?caller, "listing deleted users after {:?}", body.page_token);
+        let res = users
+            .list_deleted_users(body.page_size, body.page_token)
+            .await
+            .map_err(|e| into_status(&e, format!("failed to list deleted users: {:?}", e)))?;
+
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn list_dead_letters(
+        &self,
+        input: tonic::Request<ListDeadLettersRequest>,
+    ) -> Result<tonic::Response<ListDeadLettersResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        authorize(&extensions)?;
+        let outbox_relay = self.outbox_relay()?;
+        let page_size = paging::page_size(body.page_size)
+            .map_err(|e| into_status(&e, format!("failed to list dead letters: {:?}", e)))?;
+        // the token is the id of the last letter on the previous page
+        let after_id = match body.page_token.as_str() {
+            "" => 0,
+            token => token
+                .parse()
+                .map_err(|_| Status::invalid_argument("invalid page_token"))?,
+        };
+        let destination = Some(body.destination.as_str()).filter(|d| !d.is_empty());
+
+        let mut letters = outbox_relay
+            .dead_letters(destination, after_id, i64::from(page_size) + 1)
+            .await
+            .map_err(|e| into_status(&e, format!("failed to list dead letters: {:?}", e)))?;
+        let next_page_token = if letters.len() > page_size as usize {
+            letters.truncate(page_size as usize);
+            letters.last().map(|l| l.id.to_string()).unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        Ok(tonic::Response::new(ListDeadLettersResponse {
+            dead_letters: letters.into_iter().map(dead_letter_message).collect(),
+            next_page_token,
+        }))
+    }
+
+    async fn redeliver_dead_letter(
+        &self,
+        input: tonic::Request<RedeliverDeadLetterRequest>,
+    ) -> Result<tonic::Response<RedeliverDeadLetterResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = authorize(&extensions)?;
+        let outbox_relay = self.outbox_relay()?;
+
+        let letter = outbox_relay
+            .redeliver(body.id)
+            .await
+            .map_err(|e| into_status(&e, format!("failed to redeliver dead letter: {}", e)))?;
+        warn!(
+            caller = ?caller,
+            "dead letter {} redelivered to {}", letter.id, letter.destination
+        );
+
+        Ok(tonic::Response::new(RedeliverDeadLetterResponse {
+            dead_letter: Some(dead_letter_message(letter)),
+        }))
+    }
+
+    async fn set_quota(
+        &self,
+        input: tonic::Request<SetQuotaRequest>,
+    ) -> Result<tonic::Response<SetQuotaResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = authorize(&extensions)?;
+        let rate_limiter = self.rate_limiter(&body.api_key)?;
+        if body.requests_per_minute == Some(0)
+            || body
+                .requests_per_minute
+                .is_some_and(|n| n > i32::MAX as u32)
+        {
+            return Err(Status::invalid_argument(
+                "requests_per_minute must be positive",
+            ));
+        }
+        if body.monthly_quota == Some(0) || body.monthly_quota.is_some_and(|n| n > i64::MAX as u64)
+        {
+            return Err(Status::invalid_argument("monthly_quota must be positive"));
+        }
+
+        let limits = ApiKeyLimits {
+            requests_per_minute: body.requests_per_minute,
+            monthly_quota: body.monthly_quota,
+        };
+        rate_limiter
+            .set_limits(&body.api_key, limits)
+            .await
+            .map_err(|e| into_status(&e, format!("failed to set quota: {:?}", e)))?;
+        warn!(caller = ?caller, "quota of an API key set to {:?}", limits);
+
+        Ok(tonic::Response::new(SetQuotaResponse {
+            quota: Some(quota_message(body.api_key, limits)),
+        }))
+    }
+
+    async fn get_usage(
+        &self,
+        input: tonic::Request<GetUsageRequest>,
+    ) -> Result<tonic::Response<GetUsageResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        authorize(&extensions)?;
+        let rate_limiter = self.rate_limiter(&body.api_key)?;
+
+        let usage = rate_limiter
+            .usage(&body.api_key, SystemTime::now())
+            .await
+            .map_err(|e| into_status(&e, format!("failed to get usage: {:?}", e)))?;
+
+        Ok(tonic::Response::new(GetUsageResponse {
+            quota: usage
+                .limits
+                .map(|limits| quota_message(body.api_key, limits)),
+            minute_requests: usage.minute_requests,
+            month_requests: usage.month_requests,
+            month_reset: Some(usage.month_reset.into()),
+        }))
+    }
+
+    async fn reset_usage(
+        &self,
+        input: tonic::Request<ResetUsageRequest>,
+    ) -> Result<tonic::Response<ResetUsageResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = authorize(&extensions)?;
+        let rate_limiter = self.rate_limiter(&body.api_key)?;
+
+        rate_limiter
+            .reset_usage(&body.api_key, SystemTime::now())
+            .await
+            .map_err(|e| into_status(&e, format!("failed to reset usage: {:?}", e)))?;
+        warn!(caller = ?caller, "usage of an API key reset");
+
+        Ok(tonic::Response::new(ResetUsageResponse {}))
+    }
+
+    async fn rotate_pii_key(
+        &self,
+        input: tonic::Request<RotatePiiKeyRequest>,
+    ) -> Result<tonic::Response<RotatePiiKeyResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = authorize(&extensions)?;
+        let key_rotator = self.key_rotator()?;
+        let batch_size = match body.batch_size {
+            0 => DEFAULT_ROTATION_BATCH_SIZE,
+            n => n,
+        };
+
+        let rotation = key_rotator
+            .start(batch_size, body.max_users_per_second)
+            .await
+            .map_err(|e| into_status(&e, format!("failed to start a key rotation: {:?}", e)))?;
+        warn!(caller = ?caller, "PII key rotation {} started", rotation.id);
+
+        Ok(tonic::Response::new(RotatePiiKeyResponse {
+            rotation: Some(rotation_message(rotation)),
+        }))
+    }
+
+    async fn get_pii_key_rotation(
+        &self,
+        input: tonic::Request<GetPiiKeyRotationRequest>,
+    ) -> Result<tonic::Response<GetPiiKeyRotationResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        authorize(&extensions)?;
+        let key_rotator = self.key_rotator()?;
+
+        let rotation = match body.id {
+            0 => key_rotator.latest_rotation().await,
+            id => key_rotator.rotation(id).await,
+        }
+        .map_err(|e| into_status(&e, format!("failed to get a key rotation: {:?}", e)))?
+        .ok_or_else(|| Status::not_found("no such key rotation"))?;
+
+        Ok(tonic::Response::new(GetPiiKeyRotationResponse {
+            rotation: Some(rotation_message(rotation)),
+        }))
+    }
+
+    async fn create_backup(
+        &self,
+        input: tonic::Request<CreateBackupRequest>,
+    ) -> Result<tonic::Response<CreateBackupResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let caller = authorize(input.extensions())?;
+        let backups = self.backups()?;
+
+        let backup = backups
+            .create()
+            .await
+            .map_err(|e| into_status(&e, format!("failed to back up: {:?}", e)))?;
+        info!(caller = ?caller, "backup {} created", backup.id);
+
+        Ok(tonic::Response::new(CreateBackupResponse {
+            backup: Some(backup_message(backup)),
+        }))
+    }
+
+    async fn restore_backup(
+        &self,
+        input: tonic::Request<RestoreBackupRequest>,
+    ) -> Result<tonic::Response<RestoreBackupResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = authorize(&extensions)?;
+        let backups = self.backups()?;
+
+        let backup = backups
+            .restore(body.id)
+            .await
+            .map_err(|e| into_status(&e, format!("failed to start a restore: {:?}", e)))?;
+        warn!(caller = ?caller, "restore of backup {} started", backup.id);
+
+        Ok(tonic::Response::new(RestoreBackupResponse {
+            backup: Some(backup_message(backup)),
+        }))
+    }
+
+    async fn get_backup(
+        &self,
+        input: tonic::Request<GetBackupRequest>,
+    ) -> Result<tonic::Response<GetBackupResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        authorize(&extensions)?;
+        let backups = self.backups()?;
+
+        let backup = match body.id {
+            0 => backups.latest_backup().await,
+            id => backups.backup(id).await,
+        }
+        .map_err(|e| into_status(&e, format!("failed to get a backup: {:?}", e)))?
+        .ok_or_else(|| Status::not_found("no such backup"))?;
+
+        Ok(tonic::Response::new(GetBackupResponse {
+            backup: Some(backup_message(backup)),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request<T>(body: T, roles: &[&str]) -> tonic::Request<T> {
+        let mut request = tonic::Request::new(body);
+        request.extensions_mut().insert(Principal {
+            user_id: 1,
+            roles: roles.iter().map(|r| r.to_string()).collect(),
+            tenant: None,
+            impersonator: None,
+        });
+        request
+    }
+
+    fn set(operation: &str, delay_ms: u32) -> SetRepositoryDelayRequest {
+        SetRepositoryDelayRequest {
+            operation: operation.to_string(),
+            delay_ms,
+            time_out: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_repository_delay() {
+        let server = AdminServer::new(tracing::Span::none(), SlowOperations::default())
+            .with_slow_db_simulation(true);
+
+        let delays = server
+            .set_repository_delay(request(set("get_user_by_id", 250), &["admin"]))
+            .await
+            .unwrap()
+            .into_inner()
+            .delays;
+        assert_eq!(
+            delays,
+            [RepositoryDelay {
+                operation: "get_user_by_id".to_string(),
+                delay_ms: 250,
+                time_out: false,
+            }]
+        );
+
+        let denied = server
+            .set_repository_delay(request(set("*", 1), &[]))
+            .await
+            .unwrap_err();
+        assert_eq!(denied.code(), tonic::Code::PermissionDenied);
+        let unknown = server
+            .set_repository_delay(request(set("truncate", 1), &["admin"]))
+            .await
+            .unwrap_err();
+        assert_eq!(unknown.code(), tonic::Code::InvalidArgument);
+
+        server
+            .clear_repository_delays(request(ClearRepositoryDelaysRequest {}, &["admin"]))
+            .await
+            .unwrap();
+        let listed = server
+            .list_repository_delays(request(ListRepositoryDelaysRequest {}, &["admin"]))
+            .await
+            .unwrap();
+        assert!(listed.into_inner().delays.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_set_log_level() {
+        let server = AdminServer::new(tracing::Span::none(), SlowOperations::default());
+        let set = |filter: &str, roles: &[&str]| {
+            request(
+                SetLogLevelRequest {
+                    filter: filter.to_string(),
+                    revert_after_secs: 0,
+                },
+                roles,
+            )
+        };
+
+        server
+            .set_log_level(set("info,gin_tonik::repositories::*=debug", &["admin"]))
+            .await
+            .unwrap();
+        let invalid = server
+            .set_log_level(set("gin_tonik=chatty", &["admin"]))
+            .await
+            .unwrap_err();
+        assert_eq!(invalid.code(), tonic::Code::InvalidArgument);
+        let denied = server.set_log_level(set("debug", &[])).await.unwrap_err();
+        assert_eq!(denied.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[test]
+    fn test_sampling_from() {
+        let sampling = sampling_from(grpc::TraceSampling {
+            rate: 0.1,
+            methods: vec![MethodSampling {
+                method: "DeleteUser".to_string(),
+                rate: 1.0,
+            }],
+        })
+        .unwrap();
+        assert_eq!(sampling.methods, [("DeleteUser".to_string(), 1.0)]);
+
+        let invalid = sampling_from(grpc::TraceSampling {
+            rate: f64::NAN,
+            methods: vec![],
+        });
+        assert_eq!(invalid.unwrap_err().code(), tonic::Code::InvalidArgument);
+    }
+
+    #[sqlx::test]
+    async fn test_list_audit_entries(pool: sqlx::PgPool) {
+        use crate::{
+            entities::audit::{AuditAction, AuditEntry},
+            repositories::audit_repository::AuditRepository as PgAuditRepository,
+        };
+
+        let audit = PgAuditRepository::new(pool);
+        for target in 1..=3 {
+            audit
+                .record(AuditEntry::new(AuditAction::CreateUser, target, None))
+                .await
+                .unwrap();
+        }
+        let server = AdminServer::new(tracing::Span::none(), SlowOperations::default())
+            .with_audit_log(Arc::new(audit));
+        let list = |page_token: String, action: &str, roles: &[&str]| {
+            request(
+                ListAuditEntriesRequest {
+                    action: action.to_string(),
+                    page_size: 2,
+                    page_token,
+                    ..ListAuditEntriesRequest::default()
+                },
+                roles,
+            )
+        };
+
+        let first = server
+            .list_audit_entries(list(String::new(), "create_user", &["admin"]))
+            .await
+            .unwrap()
+            .into_inner();
+        let second = server
+            .list_audit_entries(list(first.next_page_token.clone(), "", &["admin"]))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let targets: Vec<_> = first
+            .entries
+            .iter()
+            .chain(&second.entries)
+            .map(|e| e.target_user_id)
+            .collect();
+        assert_eq!(targets, [1, 2, 3]);
+        assert!(second.next_page_token.is_empty());
+        let unknown = server
+            .list_audit_entries(list(String::new(), "drop_table", &["admin"]))
+            .await
+            .unwrap_err();
+        assert_eq!(unknown.code(), tonic::Code::InvalidArgument);
+        let denied = server
+            .list_audit_entries(list(String::new(), "", &[]))
+            .await
+            .unwrap_err();
+        assert_eq!(denied.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_list_deleted_users() {
+        use crate::{
+            repositories::{UserRepository as _, memory_user_repository::InMemoryUserRepository},
+            usecases::user_usecase::UserUsecase,
+        };
+
+        let repo = InMemoryUserRepository::new();
+        let user = repo
+            .create_user("Deleted".to_string(), "User".to_string())
+            .await
+            .unwrap();
+        repo.soft_delete_user(user.id).await.unwrap();
+        let server = AdminServer::new(tracing::Span::none(), SlowOperations::default())
+            .with_users(Arc::new(UserUsecase::new(repo)));
+
+        let listed = server
+            .list_deleted_users(request(ListDeletedUsersRequest::default(), &["admin"]))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(listed.users.len(), 1);
+        assert_eq!(listed.users[0].user.as_ref().unwrap().id, user.id);
+        let denied = server
+            .list_deleted_users(request(ListDeletedUsersRequest::default(), &[]))
+            .await
+            .unwrap_err();
+        assert_eq!(denied.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_set_repository_delay_needs_the_simulation() {
+        let server = AdminServer::new(tracing::Span::none(), SlowOperations::default());
+
+        let status = server
+            .set_repository_delay(tonic::Request::new(set("*", 100)))
+            .await
+            .unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+    }
+
+    #[tokio::test]
+    async fn test_quotas_need_rate_limits() {
+        let server = AdminServer::new(tracing::Span::none(), SlowOperations::default());
+
+        let status = server
+            .set_quota(request(
+                SetQuotaRequest {
+                    api_key: "k".to_string(),
+                    requests_per_minute: Some(10),
+                    monthly_quota: None,
+                },
+                &["admin"],
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        let denied = server
+            .reset_usage(request(
+                ResetUsageRequest {
+                    api_key: "k".to_string(),
+                },
+                &[],
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(denied.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_backups_need_storage() {
+        let server = AdminServer::new(tracing::Span::none(), SlowOperations::default());
+
+        let status = server
+            .create_backup(request(CreateBackupRequest {}, &["admin"]))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        let status = server
+            .restore_backup(request(RestoreBackupRequest { id: 1 }, &["admin"]))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        let denied = server
+            .get_backup(request(GetBackupRequest::default(), &[]))
+            .await
+            .unwrap_err();
+        assert_eq!(denied.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_key_rotation_needs_pii_encryption() {
+        let server = AdminServer::new(tracing::Span::none(), SlowOperations::default());
+
+        let status = server
+            .rotate_pii_key(request(RotatePiiKeyRequest::default(), &["admin"]))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        let denied = server
+            .get_pii_key_rotation(request(GetPiiKeyRotationRequest::default(), &[]))
+            .await
+            .unwrap_err();
+        assert_eq!(denied.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_dead_letters_need_outbox_destinations() {
+        let server = AdminServer::new(tracing::Span::none(), SlowOperations::default());
+
+        let status = server
+            .list_dead_letters(request(ListDeadLettersRequest::default(), &["admin"]))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        let denied = server
+            .redeliver_dead_letter(request(RedeliverDeadLetterRequest { id: 1 }, &[]))
+            .await
+            .unwrap_err();
+        assert_eq!(denied.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[test]
+    fn test_registry_stats() {
+        let key = |name, labels: &[(&'static str, &str)]| Key {
+            name,
+            labels: labels.iter().map(|(l, v)| (*l, v.to_string())).collect(),
+        };
+        let (stats, acquire_timeouts) = registry_stats(vec![
+            (
+                key(
+                    GRPC_HANDLED_TOTAL,
+                    &[("method", "user.v1.UserService/GetUserById")],
+                ),
+                Value::Counter(7),
+            ),
+            (
+                key("grpc_server_open_streams", &[("method", "StreamUsers")]),
+                Value::Gauge(0.0),
+            ),
+            (
+                key("grpc_server_open_streams", &[("method", "WatchUsers")]),
+                Value::Gauge(2.0),
+            ),
+            (
+                key("user_cache_requests_total", &[("result", "hit")]),
+                Value::Counter(2),
+            ),
+            (
+                key("user_cache_requests_total", &[("result", "negative_hit")]),
+                Value::Counter(1),
+            ),
+            (
+                key("user_cache_requests_total", &[("result", "miss")]),
+                Value::Counter(1),
+            ),
+            (
+                key("db_pool_acquire_timeouts_total", &[]),
+                Value::Counter(3),
+            ),
+        ]);
+
+        assert_eq!(
+            stats.methods,
+            [MethodStats {
+                method: "user.v1.UserService/GetUserById".to_string(),
+                requests: 7
+            }]
+        );
+        assert_eq!(
+            stats.open_streams,
+            [StreamStats {
+                method: "WatchUsers".to_string(),
+                open: 2
+            }]
+        );
+        let user_cache = stats.user_cache.unwrap();
+        assert_eq!((user_cache.hits, user_cache.misses), (3, 1));
+        assert_eq!(user_cache.hit_rate, 0.75);
+        assert_eq!(stats.response_cache.unwrap().hit_rate, 0.0);
+        assert_eq!(acquire_timeouts, 3);
+    }
+
+    struct Down;
+
+    #[async_trait::async_trait]
+    impl crate::health::Probe for Down {
+        async fn check(&self) -> Result<(), crate::Error> {
+            Err(crate::Error::Unavailable("connection refused".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_dependency_health() {
+        let health = DependencyHealth::new(vec![crate::health::Dependency {
+            name: "redis_change_feed".to_string(),
+            kind: "redis",
+            critical: false,
+            probe: Arc::new(Down),
+        }]);
+        health.check().await;
+        let server = AdminServer::new(tracing::Span::none(), SlowOperations::default())
+            .with_dependency_health(health);
+
+        let res = server
+            .get_dependency_health(request(GetDependencyHealthRequest {}, &["admin"]))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(res.state, "degraded");
+        assert_eq!(res.dependencies.len(), 1);
+        assert!(!res.dependencies[0].healthy);
+        assert_eq!(res.dependencies[0].error, "unavailable: connection refused");
+    }
+
+    #[tokio::test]
+    async fn test_get_server_stats() {
+        let server = AdminServer::new(tracing::Span::none(), SlowOperations::default());
+
+        let stats = server
+            .get_server_stats(request(GetServerStatsRequest {}, &["admin"]))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(stats.version, build_info::VERSION);
+        assert!(stats.started_at.is_some());
+        assert_eq!(stats.db_pool, None);
+        let info = server
+            .get_server_info(request(GetServerInfoRequest {}, &["admin"]))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(info.git_sha, build_info::GIT_SHA);
+        assert!(info.built_at.is_some());
+        let denied = server
+            .get_server_stats(request(GetServerStatsRequest {}, &[]))
+            .await
+            .unwrap_err();
+        assert_eq!(denied.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_enter_lame_duck() {
+        let shutdown = Shutdown::new();
+        let server = AdminServer::new(tracing::Span::none(), SlowOperations::default())
+            .with_shutdown(shutdown.clone());
+
+        let denied = server
+            .enter_lame_duck(request(EnterLameDuckRequest {}, &[]))
+            .await
+            .unwrap_err();
+        assert_eq!(denied.code(), tonic::Code::PermissionDenied);
+        assert!(!shutdown.is_lame_duck());
+
+        for entered in [true, false] {
+            let response = server
+                .enter_lame_duck(request(EnterLameDuckRequest {}, &["admin"]))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(response.entered, entered);
+        }
+        assert!(shutdown.is_lame_duck());
+        assert!(!shutdown.is_triggered());
+    }
+}
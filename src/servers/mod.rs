@@ -1,3 +1,40 @@
+pub mod address_server;
+pub mod admin_server;
+pub mod relationship_server;
+pub mod request_log;
 pub mod user_server;
+pub mod v2;
 
+pub use address_server::AddressServer;
+pub use admin_server::AdminServer;
+pub use relationship_server::RelationshipServer;
 pub use user_server::UserServer;
+
+use tonic::Status;
+
+// the service's span per call, under the request span so handler logs carry
+// the trace ids recorded by telemetry::TraceContextLayer
+pub(crate) fn call_span(service: &tracing::Span) -> tracing::Span {
+    let name = service.metadata().map_or("", |m| m.name());
+    let span = tracing::info_span!("call", service = name);
+    span.follows_from(service);
+    span
+}
+
+// status messages go back to clients, with whatever PII they echo masked
+pub(crate) fn into_status(e: &crate::Error, msg: String) -> Status {
+    let msg = crate::redact::scrub(&msg).into_owned();
+    match e {
+        crate::Error::NotFound => Status::not_found(msg),
+        crate::Error::InvalidArgument(_) => Status::invalid_argument(msg),
+        crate::Error::PermissionDenied => Status::permission_denied(msg),
+        crate::Error::FailedPrecondition(_) => Status::failed_precondition(msg),
+        crate::Error::Unavailable(_) => Status::unavailable(msg),
+        crate::Error::Internal(source) => {
+            crate::metrics::requests::record_internal_error(source.as_ref());
+            #[cfg(feature = "sentry")]
+            crate::error_reporting::report(e);
+            Status::internal(msg)
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use tonic::Status;
+use tracing::error;
+
+use crate::{
+    auth::Principal,
+    entities::relationships::{Relationship, RelationshipKind},
+    grpc::{
+        AddRelationshipRequest, AddRelationshipResponse, ListRelatedUsersRequest,
+        ListRelatedUsersResponse, RelationshipType, RemoveRelationshipRequest,
+        RemoveRelationshipResponse, relationship_service_server::RelationshipService,
+    },
+    redact,
+    servers::{self, into_status, request_log::log_request},
+    usecases::RelationshipUsecaseTrait,
+};
+
+const SERVICE: &str = "user.v1.RelationshipService";
+
+pub struct RelationshipServer<T: RelationshipUsecaseTrait> {
+    span: tracing::Span,
+    usecase: T,
+}
+
+impl<T: RelationshipUsecaseTrait> RelationshipServer<T> {
+    pub fn new(span: tracing::Span, usecase: T) -> Self {
+        Self { span, usecase }
+    }
+}
+
+fn into_kind(relationship_type: i32) -> Result<RelationshipKind, Status> {
+    match RelationshipType::try_from(relationship_type) {
+        Ok(RelationshipType::Friend) => Ok(RelationshipKind::Friend),
+        Ok(RelationshipType::Follows) => Ok(RelationshipKind::Follows),
+        Ok(RelationshipType::Blocks) => Ok(RelationshipKind::Blocks),
+        _ => Err(Status::invalid_argument("relationship_type must be set")),
+    }
+}
+
+#[tonic::async_trait]
+impl<T: RelationshipUsecaseTrait + 'static> RelationshipService for RelationshipServer<T> {
+    async fn add_relationship(
+        &self,
+        input: tonic::Request<AddRelationshipRequest>,
+    ) -> Result<tonic::Response<AddRelationshipResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "AddRelationship",
+            caller = ?caller.map(|p| p.user_id),
+            "adding relationship user_id={:?} related_user_id={:?}",
+            body.user_id,
+            body.related_user_id
+        );
+        let relationship = Relationship {
+            user_id: body.user_id,
+            related_user_id: body.related_user_id,
+            kind: into_kind(body.relationship_type)?,
+        };
+        let res = self
+            .usecase
+            .add_relationship(caller, relationship)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to add relationship: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn remove_relationship(
+        &self,
+        input: tonic::Request<RemoveRelationshipRequest>,
+    ) -> Result<tonic::Response<RemoveRelationshipResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "RemoveRelationship",
+            caller = ?caller.map(|p| p.user_id),
+            "removing relationship user_id={:?} related_user_id={:?}",
+            body.user_id,
+            body.related_user_id
+        );
+        let relationship = Relationship {
+            user_id: body.user_id,
+            related_user_id: body.related_user_id,
+            kind: into_kind(body.relationship_type)?,
+        };
+        let res = self
+            .usecase
+            .remove_relationship(caller, relationship)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to remove relationship: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn list_related_users(
+        &self,
+        input: tonic::Request<ListRelatedUsersRequest>,
+    ) -> Result<tonic::Response<ListRelatedUsersResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "ListRelatedUsers",
+            caller = ?caller.map(|p| p.user_id),
+            "listing related users for user_id={:?} depth={:?}",
+            body.user_id,
+            body.depth
+        );
+        let res = self
+            .usecase
+            .list_related_users(
+                caller,
+                body.user_id,
+                into_kind(body.relationship_type)?,
+                body.depth,
+                body.page_size,
+                body.page_token,
+            )
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to list related users: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+}
@@ -0,0 +1,126 @@
+use std::sync::RwLock;
+
+use tracing::Level;
+
+use crate::faults::matches_service_method;
+
+// one `REQUEST_LOG` rule, e.g. `GetUserById:rate=0.01` or
+// `UserService/GetUsers:level=debug,rate=0.1`
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestLogRule {
+    // `Method`, `Service/Method` (with or without the package) or `*`
+    pub method: String,
+    // share of calls whose request line is logged
+    pub rate: f64,
+    pub level: Level,
+}
+
+static RULES: RwLock<Vec<RequestLogRule>> = RwLock::new(Vec::new());
+
+// rules separated by `;`, the first one matching a method applies and
+// methods matching none log every call at info
+pub fn parse(spec: &str) -> Result<Vec<RequestLogRule>, String> {
+    spec.split(';')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(parse_rule)
+        .collect()
+}
+
+fn parse_rule(rule: &str) -> Result<RequestLogRule, String> {
+    let (method, settings) = rule
+        .split_once(':')
+        .ok_or_else(|| format!("{:?} has no `method:` prefix", rule))?;
+    let mut parsed = RequestLogRule {
+        method: method.trim().to_string(),
+        rate: 1.0,
+        level: Level::INFO,
+    };
+
+    for setting in settings.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (key, value) = setting
+            .split_once('=')
+            .ok_or_else(|| format!("{:?} is not `key=value`", setting))?;
+        let (key, value) = (key.trim(), value.trim());
+        let bad = || format!("bad {} {:?}", key, value);
+
+        match key {
+            "rate" => {
+                parsed.rate = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|r| (0.0..=1.0).contains(r))
+                    .ok_or_else(bad)?
+            }
+            "level" => parsed.level = value.parse().map_err(|_| bad())?,
+            _ => return Err(format!("unknown setting {:?}", key)),
+        }
+    }
+
+    Ok(parsed)
+}
+
+pub fn set_rules(rules: Vec<RequestLogRule>) {
+    *RULES.write().unwrap() = rules;
+}
+
+// the level to log this call's request line at, None to leave it out
+pub fn level(service: &str, method: &str) -> Option<Level> {
+    let rules = RULES.read().unwrap();
+    let Some(rule) = rules
+        .iter()
+        .find(|rule| matches_service_method(&rule.method, service, method))
+    else {
+        return Some(Level::INFO);
+    };
+
+    (rule.rate >= 1.0 || rand::random::<f64>() < rule.rate).then_some(rule.level)
+}
+
+// the line a handler logs for each call, sampled and leveled by REQUEST_LOG;
+// failures are logged regardless
+macro_rules! log_request {
+    ($service:expr, $method:expr, $($arg:tt)+) => {
+        match $crate::servers::request_log::level($service, $method) {
+            Some(tracing::Level::ERROR) => tracing::error!($($arg)+),
+            Some(tracing::Level::WARN) => tracing::warn!($($arg)+),
+            Some(tracing::Level::INFO) => tracing::info!($($arg)+),
+            Some(tracing::Level::DEBUG) => tracing::debug!($($arg)+),
+            Some(_) => tracing::trace!($($arg)+),
+            None => {}
+        }
+    };
+}
+
+pub(crate) use log_request;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let rules =
+            parse("GetUserById:rate=0.01; UserService/GetUsers:level=debug,rate=0.5").unwrap();
+
+        assert_eq!(
+            rules,
+            [
+                RequestLogRule {
+                    method: "GetUserById".to_string(),
+                    rate: 0.01,
+                    level: Level::INFO,
+                },
+                RequestLogRule {
+                    method: "UserService/GetUsers".to_string(),
+                    rate: 0.5,
+                    level: Level::DEBUG,
+                },
+            ]
+        );
+        assert!(parse("GetUserById").is_err());
+        assert!(parse("GetUserById:rate=2").is_err());
+        assert!(parse("GetUserById:level=loud").is_err());
+        assert!(parse("GetUserById:sample=1").is_err());
+    }
+}
@@ -1,27 +1,98 @@
-use std::pin::Pin;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use tokio_stream::{Stream, wrappers::ReceiverStream};
 use tonic::Status;
-use tracing::{error, info};
+use tracing::error;
 
 use crate::{
+    auth::Principal,
+    cache::responses::ResponseCache,
+    entities::{consents::NewConsent, stats::StatsPeriod},
+    export::ExportFormat,
     grpc::{
-        CreateUserRequest, CreateUserResponse, DeleteUserRequest, DeleteUserResponse,
-        GetUserByIdRequest, GetUserByIdResponse, GetUserByNameRequest, GetUserByNameResponse,
-        GetUsersRequest, GetUsersResponse, StreamUsersRequest, StreamUsersResponse,
-        UpdateUserRequest, UpdateUserResponse, user_service_server::UserService,
+        ActivateUserRequest, ActivateUserResponse, AutocompleteUsersRequest,
+        AutocompleteUsersResponse, BatchGetUsersByNameRequest, BatchGetUsersByNameResponse,
+        ConsentGrant, CountUsersRequest, CountUsersResponse, CreateUserRequest, CreateUserResponse,
+        DeleteUserRequest, DeleteUserResponse, EraseUserRequest, EraseUserResponse,
+        ExportUserDataRequest, ExportUserDataResponse, ExportUsersRequest, ExportUsersResponse,
+        GetConsentsRequest, GetConsentsResponse, GetUserByIdRequest, GetUserByIdResponse,
+        GetUserByNameRequest, GetUserByNameResponse, GetUserRevisionRequest,
+        GetUserRevisionResponse, GetUserStatsRequest, GetUserStatsResponse, GetUsersRequest,
+        GetUsersResponse, ImportUsersRequest, ImportUsersResponse, ListUserRevisionsRequest,
+        ListUserRevisionsResponse, ListUsersByNamePrefixRequest, ListUsersByNamePrefixResponse,
+        MergeUsersRequest, MergeUsersResponse, RecordConsentRequest, RecordConsentResponse,
+        RestoreUserRequest, RestoreUserResponse, StreamUsersRequest, StreamUsersResponse,
+        SuspendUserRequest, SuspendUserResponse, UpdateUserRequest, UpdateUserResponse, UserEvent,
+        UserExistsRequest, UserExistsResponse, WatchUsersRequest, user_service_server::UserService,
     },
+    redact::{self, Field},
+    servers::{self, into_status, request_log::log_request},
     usecases::UserUsecaseTrait,
 };
 
+const SERVICE: &str = "user.v1.UserService";
+// ExportUserData is cut into messages of at most this many bytes
+const USER_DATA_PIECE_LEN: usize = 64 * 1024;
+
 pub struct UserServer<T: UserUsecaseTrait> {
     span: tracing::Span,
     usecase: T,
+    responses: Option<Arc<ResponseCache>>,
 }
 
 impl<T: UserUsecaseTrait> UserServer<T> {
     pub fn new(span: tracing::Span, usecase: T) -> Self {
-        Self { span, usecase }
+        Self {
+            span,
+            usecase,
+            responses: None,
+        }
+    }
+
+    // caches GetUsers and CountUsers, for dashboards polling the same listing
+    pub fn with_response_cache(mut self, responses: Arc<ResponseCache>) -> Self {
+        self.responses = Some(responses);
+        self
+    }
+
+    async fn cached<Req, Res, Fut>(
+        &self,
+        method: &'static str,
+        request: &Req,
+        f: impl FnOnce() -> Fut,
+    ) -> Result<Res, Status>
+    where
+        Req: prost::Message,
+        Res: prost::Message + Default,
+        Fut: Future<Output = Result<Res, Status>>,
+    {
+        match &self.responses {
+            Some(responses) => responses.get_or_insert_with(method, request, f).await,
+            None => f().await,
+        }
+    }
+}
+
+fn into_time(field: &str, time: prost_types::Timestamp) -> Result<SystemTime, Status> {
+    SystemTime::try_from(time)
+        .map_err(|e| Status::invalid_argument(format!("invalid {}: {}", field, e)))
+}
+
+// heartbeat_secs of a stream request, 0 for none
+fn heartbeat(secs: u32) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs.into()))
+}
+
+fn into_new_consent(grant: ConsentGrant) -> NewConsent {
+    NewConsent {
+        consent_type: grant.consent_type,
+        version: grant.version,
+        source: grant.source,
     }
 }
 
@@ -29,25 +100,43 @@ impl<T: UserUsecaseTrait> UserServer<T> {
 impl<T: UserUsecaseTrait + 'static> UserService for UserServer<T> {
     type StreamUsersStream =
         Pin<Box<dyn Stream<Item = Result<StreamUsersResponse, Status>> + Send>>;
+    type AutocompleteUsersStream =
+        Pin<Box<dyn Stream<Item = Result<AutocompleteUsersResponse, Status>> + Send>>;
+    type ExportUsersStream =
+        Pin<Box<dyn Stream<Item = Result<ExportUsersResponse, Status>> + Send>>;
+    type ExportUserDataStream =
+        Pin<Box<dyn Stream<Item = Result<ExportUserDataResponse, Status>> + Send>>;
+    type WatchUsersStream = Pin<Box<dyn Stream<Item = Result<UserEvent, Status>> + Send>>;
 
     async fn create_user(
         &self,
         input: tonic::Request<CreateUserRequest>,
     ) -> Result<tonic::Response<CreateUserResponse>, Status> {
-        let _guard = self.span.enter();
-        let (_meta_data, _extentions, body) = input.into_parts();
-        info!(
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "CreateUser",
+            caller = ?caller.map(|p| p.user_id),
             "creating user with name={:?} and surname={:?}",
-            body.name, body.surname
+            redact::mask(Field::Name, &body.name),
+            redact::mask(Field::Surname, &body.surname)
         );
         let res = self
             .usecase
-            .create_user(body.name, body.surname)
+            .create_user_with_consents(
+                caller,
+                body.name,
+                body.surname,
+                body.consents.into_iter().map(into_new_consent).collect(),
+            )
             .await
             .map_err(|e| {
                 let msg = format!("failed to create user: {:?}", e);
-                error!(msg);
-                Status::internal(msg)
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
             })?;
         Ok(tonic::Response::new(res))
     }
@@ -56,16 +145,30 @@ impl<T: UserUsecaseTrait + 'static> UserService for UserServer<T> {
         &self,
         input: tonic::Request<GetUserByIdRequest>,
     ) -> Result<tonic::Response<GetUserByIdResponse>, tonic::Status> {
-        let _guard = self.span.enter();
-        let (_meta_data, _extentions, body) = input.into_parts();
-        info!("getting user by id={:?}", body.id);
-        let res = self.usecase.get_user_by_id(body.id).await.map_err(|e| {
-            let msg = format!("failed to retrieve user: {:?}", e);
-            error!(msg);
-            match e {
-                crate::Error::NotFound => Status::not_found(msg),
-                _ => Status::internal(msg),
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "GetUserById",
+            caller = ?caller.map(|p| p.user_id),
+            "getting user by id={:?} as_of={:?}",
+            body.id,
+            body.as_of
+        );
+        let res = match body.as_of {
+            Some(as_of) => {
+                self.usecase
+                    .get_user_by_id_as_of(caller, body.id, into_time("as_of", as_of)?)
+                    .await
             }
+            None => self.usecase.get_user_by_id(caller, body.id).await,
+        }
+        .map_err(|e| {
+            let msg = format!("failed to retrieve user: {:?}", e);
+            error!("{}", redact::scrub(&msg));
+            into_status(&e, msg)
         })?;
         Ok(tonic::Response::new(res))
     }
@@ -74,20 +177,106 @@ impl<T: UserUsecaseTrait + 'static> UserService for UserServer<T> {
         &self,
         input: tonic::Request<GetUserByNameRequest>,
     ) -> Result<tonic::Response<GetUserByNameResponse>, tonic::Status> {
-        let _guard = self.span.enter();
-        let (_meta_data, _extentions, body) = input.into_parts();
-        info!("getting user by name={:?}", body.name);
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "GetUserByName",
+            caller = ?caller.map(|p| p.user_id),
+            "getting user by name={:?}",
+            redact::mask(Field::Name, &body.name)
+        );
         let res = self
             .usecase
-            .get_user_by_name(body.name)
+            .get_user_by_name(caller, body.name)
             .await
             .map_err(|e| {
                 let msg = format!("failed to retrieve user: {:?}", e);
-                error!(msg);
-                match e {
-                    crate::Error::NotFound => Status::not_found(msg),
-                    _ => Status::internal(msg),
-                }
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn batch_get_users_by_name(
+        &self,
+        input: tonic::Request<BatchGetUsersByNameRequest>,
+    ) -> Result<tonic::Response<BatchGetUsersByNameResponse>, tonic::Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "BatchGetUsersByName",
+            caller = ?caller.map(|p| p.user_id),
+            "batch getting {} users by name",
+            body.names.len()
+        );
+        let res = self
+            .usecase
+            .batch_get_users_by_name(caller, body.names)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to batch get users by name: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn list_users_by_name_prefix(
+        &self,
+        input: tonic::Request<ListUsersByNamePrefixRequest>,
+    ) -> Result<tonic::Response<ListUsersByNamePrefixResponse>, tonic::Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "ListUsersByNamePrefix",
+            caller = ?caller.map(|p| p.user_id),
+            "listing users by name prefix={:?}",
+            redact::mask(Field::Name, &body.prefix)
+        );
+        let res = self
+            .usecase
+            .list_users_by_name_prefix(caller, body.prefix, body.limit)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to list users by name prefix: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn user_exists(
+        &self,
+        input: tonic::Request<UserExistsRequest>,
+    ) -> Result<tonic::Response<UserExistsResponse>, tonic::Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "UserExists",
+            caller = ?caller.map(|p| p.user_id),
+            "checking whether user id={:?} exists",
+            body.id
+        );
+        let res = self
+            .usecase
+            .user_exists(caller, body.id)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to check user existence: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
             })?;
         Ok(tonic::Response::new(res))
     }
@@ -96,35 +285,142 @@ impl<T: UserUsecaseTrait + 'static> UserService for UserServer<T> {
         &self,
         input: tonic::Request<UpdateUserRequest>,
     ) -> Result<tonic::Response<UpdateUserResponse>, tonic::Status> {
-        let _guard = self.span.enter();
-        let (_meta_data, _extentions, body) = input.into_parts();
-        info!(
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "UpdateUser",
+            caller = ?caller.map(|p| p.user_id),
             "updating user with id={:?}, setting name={:?} and surname={:?}",
-            body.id, body.name, body.surname
+            body.id,
+            body.name.as_deref().map(|n| redact::mask(Field::Name, n)),
+            body.surname.as_deref().map(|s| redact::mask(Field::Surname, s))
         );
         let res = self
             .usecase
-            .update_user(body.id, body.name, body.surname)
+            .update_user(caller, body.id, body.name, body.surname)
             .await
             .map_err(|e| {
                 let msg = format!("failed to update user: {:?}", e);
-                error!(msg);
-                Status::internal(msg)
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
             })?;
         Ok(tonic::Response::new(res))
     }
 
     async fn get_users(
         &self,
-        _input: tonic::Request<GetUsersRequest>,
+        input: tonic::Request<GetUsersRequest>,
     ) -> Result<tonic::Response<GetUsersResponse>, tonic::Status> {
-        let _guard = self.span.enter();
-        info!("getting all users");
-        let res = self.usecase.get_users().await.map_err(|e| {
-            let msg = format!("failed to retrieve users: {:?}", e);
-            error!(msg);
-            Status::internal(msg)
-        })?;
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "GetUsers",
+            caller = ?caller.map(|p| p.user_id),
+            "getting users with filter={:?} as_of={:?} page_size={:?} page_token={:?}",
+            redact::filter(&body.filter),
+            body.as_of,
+            body.page_size,
+            body.page_token
+        );
+        let fetch = || async {
+            match body.as_of {
+                Some(as_of) => {
+                    self.usecase
+                        .get_users_as_of(
+                            caller,
+                            body.filter.clone(),
+                            into_time("as_of", as_of)?,
+                            body.page_size,
+                            body.page_token.clone(),
+                        )
+                        .await
+                }
+                None => {
+                    self.usecase
+                        .get_users(
+                            caller,
+                            body.filter.clone(),
+                            body.page_size,
+                            body.page_token.clone(),
+                        )
+                        .await
+                }
+            }
+            .map_err(|e| {
+                let msg = format!("failed to retrieve users: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })
+        };
+        // cached pages hold every user, a non-admin is refused rather than
+        // served one
+        let res = if caller.is_some_and(|p| !p.is_admin()) {
+            fetch().await?
+        } else {
+            self.cached("GetUsers", &body, fetch).await?
+        };
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn count_users(
+        &self,
+        input: tonic::Request<CountUsersRequest>,
+    ) -> Result<tonic::Response<CountUsersResponse>, tonic::Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let exact = input.get_ref().exact.unwrap_or(true);
+        log_request!(SERVICE, "CountUsers", "counting users exact={:?}", exact);
+        let res = self
+            .cached("CountUsers", input.get_ref(), || async {
+                self.usecase.count_users(exact).await.map_err(|e| {
+                    let msg = format!("failed to count users: {:?}", e);
+                    error!("{}", redact::scrub(&msg));
+                    into_status(&e, msg)
+                })
+            })
+            .await?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn get_user_stats(
+        &self,
+        input: tonic::Request<GetUserStatsRequest>,
+    ) -> Result<tonic::Response<GetUserStatsResponse>, tonic::Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let body = input.into_inner();
+        log_request!(
+            SERVICE,
+            "GetUserStats",
+            "getting user stats per {:?} from {:?} to {:?}",
+            body.period(),
+            body.start,
+            body.end
+        );
+        let period = match body.period() {
+            crate::grpc::StatsPeriod::Unspecified | crate::grpc::StatsPeriod::Day => {
+                StatsPeriod::Day
+            }
+            crate::grpc::StatsPeriod::Week => StatsPeriod::Week,
+            crate::grpc::StatsPeriod::Month => StatsPeriod::Month,
+        };
+        let start = body.start.map(|t| into_time("start", t)).transpose()?;
+        let end = body.end.map(|t| into_time("end", t)).transpose()?;
+        let res = self
+            .usecase
+            .get_user_stats(period, start, end)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to get user stats: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
         Ok(tonic::Response::new(res))
     }
 
@@ -132,32 +428,477 @@ impl<T: UserUsecaseTrait + 'static> UserService for UserServer<T> {
         &self,
         input: tonic::Request<DeleteUserRequest>,
     ) -> Result<tonic::Response<DeleteUserResponse>, tonic::Status> {
-        let _guard = self.span.enter();
-        let (_meta_data, _extentions, body) = input.into_parts();
-        info!("deleting user with id={:?}", body.id);
-        let res = self.usecase.delete_user(body.id).await.map_err(|e| {
-            let msg = format!("failed to delete user: {:?}", e);
-            error!(msg);
-            Status::internal(msg)
-        })?;
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "DeleteUser",
+            caller = ?caller.map(|p| p.user_id),
+            "deleting user with id={:?}",
+            body.id
+        );
+        let res = self
+            .usecase
+            .delete_user(caller, body.id)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to delete user: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn erase_user(
+        &self,
+        input: tonic::Request<EraseUserRequest>,
+    ) -> Result<tonic::Response<EraseUserResponse>, tonic::Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "EraseUser",
+            caller = ?caller.map(|p| p.user_id),
+            "erasing user with id={:?}",
+            body.id
+        );
+        let res = self
+            .usecase
+            .erase_user(caller, body.id)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to erase user: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn restore_user(
+        &self,
+        input: tonic::Request<RestoreUserRequest>,
+    ) -> Result<tonic::Response<RestoreUserResponse>, tonic::Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "RestoreUser",
+            caller = ?caller.map(|p| p.user_id),
+            "restoring user with id={:?}",
+            body.id
+        );
+        let res = self
+            .usecase
+            .restore_user(caller, body.id)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to restore user: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn suspend_user(
+        &self,
+        input: tonic::Request<SuspendUserRequest>,
+    ) -> Result<tonic::Response<SuspendUserResponse>, tonic::Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "SuspendUser",
+            caller = ?caller.map(|p| p.user_id),
+            "suspending user with id={:?}",
+            body.id
+        );
+        let res = self
+            .usecase
+            .suspend_user(caller, body.id)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to suspend user: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn activate_user(
+        &self,
+        input: tonic::Request<ActivateUserRequest>,
+    ) -> Result<tonic::Response<ActivateUserResponse>, tonic::Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "ActivateUser",
+            caller = ?caller.map(|p| p.user_id),
+            "activating user with id={:?}",
+            body.id
+        );
+        let res = self
+            .usecase
+            .activate_user(caller, body.id)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to activate user: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn merge_users(
+        &self,
+        input: tonic::Request<MergeUsersRequest>,
+    ) -> Result<tonic::Response<MergeUsersResponse>, tonic::Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "MergeUsers",
+            caller = ?caller.map(|p| p.user_id),
+            "merging user with id={:?} into id={:?}",
+            body.duplicate_id,
+            body.primary_id
+        );
+        let res = self
+            .usecase
+            .merge_users(caller, body.primary_id, body.duplicate_id)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to merge users: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
         Ok(tonic::Response::new(res))
     }
 
     async fn stream_users(
         &self,
-        _input: tonic::Request<StreamUsersRequest>,
+        input: tonic::Request<StreamUsersRequest>,
     ) -> Result<tonic::Response<Self::StreamUsersStream>, Status> {
-        let _guard = self.span.enter();
-        info!("streaming all users");
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "StreamUsers",
+            caller = ?caller.map(|p| p.user_id),
+            "streaming all users with chunk_size={} heartbeat_secs={}",
+            body.chunk_size,
+            body.heartbeat_secs
+        );
         let (tx, rx) = tokio::sync::mpsc::channel(128);
-        self.usecase.send_users(tx).await.map_err(|e| {
-            let msg = format!("failed to start streaming users: {:?}", e);
-            error!(msg);
-            Status::internal(msg)
-        })?;
+        self.usecase
+            .send_users(caller, body.chunk_size, heartbeat(body.heartbeat_secs), tx)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to start streaming users: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
 
         Ok(tonic::Response::new(
             Box::pin(ReceiverStream::new(rx)) as Self::StreamUsersStream
         ))
     }
+
+    async fn export_users(
+        &self,
+        input: tonic::Request<ExportUsersRequest>,
+    ) -> Result<tonic::Response<Self::ExportUsersStream>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        let format = match body.format() {
+            crate::grpc::ExportFormat::Unspecified | crate::grpc::ExportFormat::Csv => {
+                ExportFormat::Csv
+            }
+            crate::grpc::ExportFormat::Parquet => ExportFormat::Parquet,
+        };
+        log_request!(
+            SERVICE,
+            "ExportUsers",
+            caller = ?caller.map(|p| p.user_id),
+            "exporting all users as {:?}",
+            format
+        );
+        let (tx, rx) = tokio::sync::mpsc::channel(4);
+        self.usecase
+            .send_export(caller, format, tx)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to start exporting users: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+
+        Ok(tonic::Response::new(
+            Box::pin(ReceiverStream::new(rx)) as Self::ExportUsersStream
+        ))
+    }
+
+    async fn list_user_revisions(
+        &self,
+        input: tonic::Request<ListUserRevisionsRequest>,
+    ) -> Result<tonic::Response<ListUserRevisionsResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "ListUserRevisions",
+            caller = ?caller.map(|p| p.user_id),
+            "listing revisions of user id={:?}",
+            body.user_id
+        );
+        let res = self
+            .usecase
+            .list_user_revisions(caller, body.user_id)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to list user revisions: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn get_user_revision(
+        &self,
+        input: tonic::Request<GetUserRevisionRequest>,
+    ) -> Result<tonic::Response<GetUserRevisionResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "GetUserRevision",
+            caller = ?caller.map(|p| p.user_id),
+            "getting revision {:?} of user id={:?}",
+            body.revision,
+            body.user_id
+        );
+        let res = self
+            .usecase
+            .get_user_revision(caller, body.user_id, body.revision)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to get user revision: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn record_consent(
+        &self,
+        input: tonic::Request<RecordConsentRequest>,
+    ) -> Result<tonic::Response<RecordConsentResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        let consent = into_new_consent(body.consent.unwrap_or_default());
+        log_request!(
+            SERVICE,
+            "RecordConsent",
+            caller = ?caller.map(|p| p.user_id),
+            "recording consent {:?} version {:?} of user id={:?}",
+            consent.consent_type,
+            consent.version,
+            body.user_id
+        );
+        let res = self
+            .usecase
+            .record_consent(caller, body.user_id, consent)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to record consent: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn get_consents(
+        &self,
+        input: tonic::Request<GetConsentsRequest>,
+    ) -> Result<tonic::Response<GetConsentsResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "GetConsents",
+            caller = ?caller.map(|p| p.user_id),
+            "getting consents of user id={:?}",
+            body.user_id
+        );
+        let res = self
+            .usecase
+            .get_consents(caller, body.user_id)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to get consents: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn export_user_data(
+        &self,
+        input: tonic::Request<ExportUserDataRequest>,
+    ) -> Result<tonic::Response<Self::ExportUserDataStream>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "ExportUserData",
+            caller = ?caller.map(|p| p.user_id),
+            "exporting data of user id={:?}",
+            body.id
+        );
+        let document = self
+            .usecase
+            .export_user_data(caller, body.id)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to export user data: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+
+        let pieces: Vec<_> = document
+            .chunks(USER_DATA_PIECE_LEN)
+            .map(|data| {
+                Ok(ExportUserDataResponse {
+                    data: data.to_vec(),
+                })
+            })
+            .collect();
+        Ok(tonic::Response::new(
+            Box::pin(tokio_stream::iter(pieces)) as Self::ExportUserDataStream
+        ))
+    }
+
+    async fn autocomplete_users(
+        &self,
+        input: tonic::Request<tonic::Streaming<AutocompleteUsersRequest>>,
+    ) -> Result<tonic::Response<Self::AutocompleteUsersStream>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "AutocompleteUsers",
+            caller = ?caller.map(|p| p.user_id),
+            "autocompleting users"
+        );
+        let (tx, rx) = tokio::sync::mpsc::channel(16);
+        self.usecase
+            .send_autocomplete(caller, Box::pin(body), tx)
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to start autocompleting users: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+
+        Ok(tonic::Response::new(
+            Box::pin(ReceiverStream::new(rx)) as Self::AutocompleteUsersStream
+        ))
+    }
+
+    async fn import_users(
+        &self,
+        input: tonic::Request<tonic::Streaming<ImportUsersRequest>>,
+    ) -> Result<tonic::Response<ImportUsersResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "ImportUsers",
+            caller = ?caller.map(|p| p.user_id),
+            "importing users"
+        );
+        let res = self
+            .usecase
+            .import_users(caller, Box::pin(body))
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to import users: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+        Ok(tonic::Response::new(res))
+    }
+
+    async fn watch_users(
+        &self,
+        input: tonic::Request<WatchUsersRequest>,
+    ) -> Result<tonic::Response<Self::WatchUsersStream>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        let WatchUsersRequest {
+            after_sequence,
+            resume_token,
+            heartbeat_secs,
+        } = body;
+        log_request!(
+            SERVICE,
+            "WatchUsers",
+            caller = ?caller.map(|p| p.user_id),
+            "watching users after sequence {} or resume token {} heartbeat_secs={}",
+            after_sequence,
+            resume_token,
+            heartbeat_secs
+        );
+        let (tx, rx) = tokio::sync::mpsc::channel(128);
+        self.usecase
+            .send_user_events(
+                caller,
+                after_sequence,
+                resume_token,
+                heartbeat(heartbeat_secs),
+                tx,
+            )
+            .await
+            .map_err(|e| {
+                let msg = format!("failed to start watching users: {:?}", e);
+                error!("{}", redact::scrub(&msg));
+                into_status(&e, msg)
+            })?;
+
+        Ok(tonic::Response::new(
+            Box::pin(ReceiverStream::new(rx)) as Self::WatchUsersStream
+        ))
+    }
 }
@@ -0,0 +1,74 @@
+use tonic::Status;
+use tracing::error;
+
+use crate::{
+    auth::Principal,
+    grpc::v2::{
+        Address, ListAddressesRequest, ListAddressesResponse,
+        address_service_server::AddressService,
+    },
+    redact,
+    servers::{
+        self, into_status,
+        request_log::log_request,
+        v2::{address_name, parse_user_name},
+    },
+    usecases::AddressUsecaseTrait,
+};
+
+const SERVICE: &str = "user.v2.AddressService";
+
+pub struct AddressServer<T: AddressUsecaseTrait> {
+    span: tracing::Span,
+    usecase: T,
+}
+
+impl<T: AddressUsecaseTrait> AddressServer<T> {
+    pub fn new(span: tracing::Span, usecase: T) -> Self {
+        Self { span, usecase }
+    }
+}
+
+#[tonic::async_trait]
+impl<T: AddressUsecaseTrait + 'static> AddressService for AddressServer<T> {
+    async fn list_addresses(
+        &self,
+        input: tonic::Request<ListAddressesRequest>,
+    ) -> Result<tonic::Response<ListAddressesResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "ListAddresses",
+            caller = ?caller.map(|p| p.user_id),
+            "listing addresses of {:?}",
+            body.parent
+        );
+        let res = async {
+            let user_id = parse_user_name(&body.parent)?;
+            self.usecase.list_user_addresses(caller, user_id).await
+        }
+        .await
+        .map_err(|e| {
+            let msg = format!("failed to list addresses: {:?}", e);
+            error!("{}", redact::scrub(&msg));
+            into_status(&e, msg)
+        })?;
+
+        Ok(tonic::Response::new(ListAddressesResponse {
+            addresses: res
+                .addresses
+                .into_iter()
+                .map(|a| Address {
+                    name: address_name(a.user_id, a.id),
+                    street: a.street,
+                    city: a.city,
+                    postal_code: a.postal_code,
+                    country: a.country,
+                })
+                .collect(),
+        }))
+    }
+}
@@ -0,0 +1,62 @@
+pub mod address_server;
+pub mod user_server;
+
+pub use address_server::AddressServer;
+pub use user_server::UserServer;
+
+use crate::Error;
+
+// resource names (https://google.aip.dev/122) wrap the internal integer ids
+
+pub fn user_name(id: i32) -> String {
+    format!("users/{}", id)
+}
+
+pub fn address_name(user_id: i32, id: i32) -> String {
+    format!("users/{}/addresses/{}", user_id, id)
+}
+
+pub fn parse_user_name(name: &str) -> Result<i32, Error> {
+    match name.split('/').collect::<Vec<_>>().as_slice() {
+        ["users", id] => parse_id(name, id),
+        _ => Err(invalid_name(name, "users/{user}")),
+    }
+}
+
+pub fn parse_address_name(name: &str) -> Result<(i32, i32), Error> {
+    match name.split('/').collect::<Vec<_>>().as_slice() {
+        ["users", user_id, "addresses", id] => Ok((parse_id(name, user_id)?, parse_id(name, id)?)),
+        _ => Err(invalid_name(name, "users/{user}/addresses/{address}")),
+    }
+}
+
+fn parse_id(name: &str, id: &str) -> Result<i32, Error> {
+    id.parse()
+        .map_err(|_| Error::InvalidArgument(format!("invalid id in resource name {:?}", name)))
+}
+
+fn invalid_name(name: &str, pattern: &str) -> Error {
+    Error::InvalidArgument(format!(
+        "resource name {:?} does not match {}",
+        name, pattern
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resource_names_roundtrip() {
+        assert_eq!(parse_user_name(&user_name(42)).unwrap(), 42);
+        assert_eq!(parse_address_name(&address_name(4, 2)).unwrap(), (4, 2));
+    }
+
+    #[test]
+    fn test_invalid_resource_names() {
+        assert!(parse_user_name("42").is_err());
+        assert!(parse_user_name("users/abc").is_err());
+        assert!(parse_user_name("users/1/addresses/2").is_err());
+        assert!(parse_address_name("users/1").is_err());
+    }
+}
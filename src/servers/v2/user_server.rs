@@ -0,0 +1,276 @@
+use prost_types::FieldMask;
+use tonic::Status;
+use tracing::error;
+
+use crate::{
+    auth::Principal,
+    grpc::{
+        self,
+        v2::{
+            CreateUserRequest, DeleteUserRequest, GetUserRequest, ListUsersRequest,
+            ListUsersResponse, UpdateUserRequest, User, user_service_server::UserService,
+        },
+    },
+    redact::{self, Field},
+    servers::{
+        self, into_status,
+        request_log::log_request,
+        v2::{parse_user_name, user_name},
+    },
+    usecases::UserUsecaseTrait,
+};
+
+const DEFAULT_PAGE_SIZE: i32 = 50;
+const MAX_PAGE_SIZE: i32 = 1000;
+
+const SERVICE: &str = "user.v2.UserService";
+
+pub struct UserServer<T: UserUsecaseTrait> {
+    span: tracing::Span,
+    usecase: T,
+}
+
+impl<T: UserUsecaseTrait> UserServer<T> {
+    pub fn new(span: tracing::Span, usecase: T) -> Self {
+        Self { span, usecase }
+    }
+}
+
+fn into_v2(user: grpc::User) -> User {
+    User {
+        name: user_name(user.id),
+        given_name: user.name,
+        family_name: user.surname,
+    }
+}
+
+// AIP-134: without a mask only populated fields change, a mask (or `*`) sets the
+// listed fields exactly, so an empty string in a masked field clears it
+fn apply_update_mask(
+    user: &User,
+    mask: Option<FieldMask>,
+) -> Result<(Option<String>, Option<String>), Status> {
+    let Some(mask) = mask.filter(|m| !m.paths.is_empty()) else {
+        let populated = |v: &String| Some(v.clone()).filter(|v| !v.is_empty());
+        return Ok((populated(&user.given_name), populated(&user.family_name)));
+    };
+
+    let (mut given_name, mut family_name) = (None, None);
+    for path in mask.paths {
+        match path.as_str() {
+            "given_name" => given_name = Some(user.given_name.clone()),
+            "family_name" => family_name = Some(user.family_name.clone()),
+            "*" => {
+                given_name = Some(user.given_name.clone());
+                family_name = Some(user.family_name.clone());
+            }
+            other => {
+                return Err(Status::invalid_argument(format!(
+                    "unknown update_mask path {:?}",
+                    other
+                )));
+            }
+        }
+    }
+
+    Ok((given_name, family_name))
+}
+
+fn failed(action: &str, e: crate::Error) -> Status {
+    let msg = format!("failed to {}: {:?}", action, e);
+    error!("{}", redact::scrub(&msg));
+    into_status(&e, msg)
+}
+
+#[tonic::async_trait]
+impl<T: UserUsecaseTrait + 'static> UserService for UserServer<T> {
+    async fn get_user(
+        &self,
+        input: tonic::Request<GetUserRequest>,
+    ) -> Result<tonic::Response<User>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "GetUser",
+            caller = ?caller.map(|p| p.user_id),
+            "getting user {:?}",
+            body.name
+        );
+        let id = parse_user_name(&body.name).map_err(|e| failed("retrieve user", e))?;
+        let res = self
+            .usecase
+            .get_user_by_id(caller, id)
+            .await
+            .map_err(|e| failed("retrieve user", e))?;
+
+        match res.user {
+            Some(user) => Ok(tonic::Response::new(into_v2(user))),
+            None => Err(Status::not_found(format!("{} not found", body.name))),
+        }
+    }
+
+    async fn list_users(
+        &self,
+        input: tonic::Request<ListUsersRequest>,
+    ) -> Result<tonic::Response<ListUsersResponse>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "ListUsers",
+            caller = ?caller.map(|p| p.user_id),
+            "listing users page_size={:?} page_token={:?} filter={:?}",
+            body.page_size,
+            body.page_token,
+            redact::filter(&body.filter)
+        );
+        let page_size = match body.page_size {
+            0 => DEFAULT_PAGE_SIZE,
+            size => size.clamp(1, MAX_PAGE_SIZE),
+        };
+        let res = self
+            .usecase
+            .get_users(caller, body.filter, page_size, body.page_token)
+            .await
+            .map_err(|e| failed("list users", e))?;
+
+        Ok(tonic::Response::new(ListUsersResponse {
+            users: res.users.into_iter().map(into_v2).collect(),
+            next_page_token: res.next_page_token,
+        }))
+    }
+
+    async fn create_user(
+        &self,
+        input: tonic::Request<CreateUserRequest>,
+    ) -> Result<tonic::Response<User>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        let user = body
+            .user
+            .ok_or_else(|| Status::invalid_argument("user must be set"))?;
+        log_request!(
+            SERVICE,
+            "CreateUser",
+            caller = ?caller.map(|p| p.user_id),
+            "creating user with given_name={:?} and family_name={:?}",
+            redact::mask(Field::Name, &user.given_name),
+            redact::mask(Field::Surname, &user.family_name)
+        );
+        let res = self
+            .usecase
+            .create_user(caller, user.given_name, user.family_name)
+            .await
+            .map_err(|e| failed("create user", e))?;
+
+        res.user
+            .map(|user| tonic::Response::new(into_v2(user)))
+            .ok_or_else(|| Status::internal("created user missing from response"))
+    }
+
+    async fn update_user(
+        &self,
+        input: tonic::Request<UpdateUserRequest>,
+    ) -> Result<tonic::Response<User>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        let user = body
+            .user
+            .ok_or_else(|| Status::invalid_argument("user must be set"))?;
+        log_request!(
+            SERVICE,
+            "UpdateUser",
+            caller = ?caller.map(|p| p.user_id),
+            "updating user {:?}",
+            user.name
+        );
+        let id = parse_user_name(&user.name).map_err(|e| failed("update user", e))?;
+
+        let (given_name, family_name) = apply_update_mask(&user, body.update_mask)?;
+
+        let res = self
+            .usecase
+            .update_user(caller, id, given_name, family_name)
+            .await
+            .map_err(|e| failed("update user", e))?;
+
+        res.user
+            .map(|user| tonic::Response::new(into_v2(user)))
+            .ok_or_else(|| Status::not_found(format!("{} not found", user.name)))
+    }
+
+    async fn delete_user(
+        &self,
+        input: tonic::Request<DeleteUserRequest>,
+    ) -> Result<tonic::Response<()>, Status> {
+        let span = servers::call_span(&self.span);
+        let _guard = span.enter();
+        let (_meta_data, extensions, body) = input.into_parts();
+        let caller = Principal::from_extensions(&extensions);
+        log_request!(
+            SERVICE,
+            "DeleteUser",
+            caller = ?caller.map(|p| p.user_id),
+            "deleting user {:?}",
+            body.name
+        );
+        let id = parse_user_name(&body.name).map_err(|e| failed("delete user", e))?;
+        self.usecase
+            .delete_user(caller, id)
+            .await
+            .map_err(|e| failed("delete user", e))?;
+
+        Ok(tonic::Response::new(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(given_name: &str, family_name: &str) -> User {
+        User {
+            name: user_name(1),
+            given_name: given_name.to_string(),
+            family_name: family_name.to_string(),
+        }
+    }
+
+    fn mask(paths: &[&str]) -> Option<FieldMask> {
+        Some(FieldMask {
+            paths: paths.iter().map(|p| p.to_string()).collect(),
+        })
+    }
+
+    #[test]
+    fn test_update_mask_distinguishes_empty_from_unchanged() {
+        assert_eq!(
+            apply_update_mask(&user("Ann", ""), None).unwrap(),
+            (Some("Ann".to_string()), None)
+        );
+        assert_eq!(
+            apply_update_mask(&user("Ann", ""), mask(&["family_name"])).unwrap(),
+            (None, Some(String::new()))
+        );
+        assert_eq!(
+            apply_update_mask(&user("", ""), mask(&["*"])).unwrap(),
+            (Some(String::new()), Some(String::new()))
+        );
+    }
+
+    #[test]
+    fn test_update_mask_rejects_unknown_paths() {
+        let status = apply_update_mask(&user("Ann", "Lee"), mask(&["email"])).unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+}
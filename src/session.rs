@@ -0,0 +1,177 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use axum::http::{HeaderValue, Request, Response};
+use tower::{Layer, Service};
+use tracing::debug;
+
+// returned after writes and sent back on later reads, so a read routed to a
+// replica waits until that replica has replayed the caller's own writes
+pub const SESSION_TOKEN_HEADER: &str = "x-session-token";
+
+tokio::task_local! {
+    static SESSION: Session;
+}
+
+// a PostgreSQL WAL position, written like pg_lsn as `16/B374D848`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Lsn(pub u64);
+
+impl FromStr for Lsn {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || crate::Error::InvalidArgument(format!("invalid session token {:?}", s));
+        let (high, low) = s.split_once('/').ok_or_else(invalid)?;
+        let high = u32::from_str_radix(high, 16).map_err(|_| invalid())?;
+        let low = u32::from_str_radix(low, 16).map_err(|_| invalid())?;
+
+        Ok(Self((high as u64) << 32 | low as u64))
+    }
+}
+
+impl fmt::Display for Lsn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:X}/{:X}", self.0 >> 32, self.0 as u32)
+    }
+}
+
+#[derive(Clone, Default)]
+struct Session {
+    read_after: Option<Lsn>,
+    written: Arc<Mutex<Option<Lsn>>>,
+}
+
+// runs `f` as one session, returning the furthest position it wrote
+pub async fn scope<F: Future>(read_after: Option<Lsn>, f: F) -> (F::Output, Option<Lsn>) {
+    let session = Session {
+        read_after,
+        ..Default::default()
+    };
+    let written = session.written.clone();
+    let out = SESSION.scope(session, f).await;
+
+    let written = *written.lock().unwrap();
+    (out, written)
+}
+
+// the position reads in the current request must have caught up to
+pub fn read_after() -> Option<Lsn> {
+    SESSION.try_with(|s| s.read_after).ok().flatten()
+}
+
+// keeps the furthest write of the request for the response token
+pub fn record_write(lsn: Lsn) {
+    let _ = SESSION.try_with(|s| {
+        let mut written = s.written.lock().unwrap();
+        *written = (*written).max(Some(lsn));
+    });
+}
+
+// scopes the session over each request, for both the gRPC server and the
+// HTTP router, since the gateway calls the gRPC routes without this layer
+#[derive(Clone, Copy, Default)]
+pub struct SessionLayer;
+
+impl<S> Layer<S> for SessionLayer {
+    type Service = SessionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SessionService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct SessionService<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SessionService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        // an unreadable token cannot be shown to be replayed, so its reads
+        // end up on the primary
+        let read_after = req.headers().get(SESSION_TOKEN_HEADER).map(|value| {
+            value
+                .to_str()
+                .ok()
+                .and_then(|token| token.parse().ok())
+                .unwrap_or_else(|| {
+                    debug!("ignoring malformed {} {:?}", SESSION_TOKEN_HEADER, value);
+                    Lsn(u64::MAX)
+                })
+        });
+        let res = self.inner.call(req);
+
+        Box::pin(async move {
+            let (res, written) = scope(read_after, res).await;
+            let mut res = res?;
+            if let Some(lsn) = written
+                && let Ok(value) = HeaderValue::from_str(&lsn.to_string())
+            {
+                res.headers_mut().insert(SESSION_TOKEN_HEADER, value);
+            }
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_lsn() {
+        let lsn: Lsn = "16/B374D848".parse().unwrap();
+
+        assert_eq!(lsn, Lsn(0x16_B374_D848));
+        assert_eq!(lsn.to_string(), "16/B374D848");
+        assert!("0/1".parse::<Lsn>().unwrap() < lsn);
+        assert!("16".parse::<Lsn>().is_err());
+        assert!("16/xyz".parse::<Lsn>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_layer_threads_the_session() {
+        let service = SessionLayer.layer(tower::service_fn(|_: Request<()>| async {
+            let read_after = read_after();
+            record_write(Lsn(5));
+            record_write(Lsn(3));
+            Ok::<_, std::convert::Infallible>(Response::new(read_after))
+        }));
+
+        let req = Request::builder()
+            .header(SESSION_TOKEN_HEADER, "0/2")
+            .body(())
+            .unwrap();
+        let res = service.clone().oneshot(req).await.unwrap();
+        assert_eq!(*res.body(), Some(Lsn(2)));
+        assert_eq!(res.headers()[SESSION_TOKEN_HEADER], "0/5");
+
+        let req = Request::builder()
+            .header(SESSION_TOKEN_HEADER, "garbage")
+            .body(())
+            .unwrap();
+        let res = service.oneshot(req).await.unwrap();
+        assert_eq!(*res.body(), Some(Lsn(u64::MAX)));
+        assert_eq!(read_after(), None);
+    }
+}
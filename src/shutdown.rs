@@ -0,0 +1,156 @@
+use std::{
+    future::Future,
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
+};
+
+use tokio::task::JoinHandle;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::info;
+
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    token: CancellationToken,
+    tasks: TaskTracker,
+    // lame duck: health reports NOT_SERVING and new streams are refused,
+    // while calls and streams already running carry on
+    lame_duck: CancellationToken,
+    lame_duck_since: Arc<OnceLock<Instant>>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn<F>(&self, task: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.tasks.spawn(task)
+    }
+
+    pub fn trigger(&self) {
+        self.token.cancel();
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    pub async fn triggered(&self) {
+        self.token.cancelled().await
+    }
+
+    // false when already in lame duck
+    pub fn enter_lame_duck(&self) -> bool {
+        let entered = self.lame_duck_since.set(Instant::now()).is_ok();
+        self.lame_duck.cancel();
+        entered
+    }
+
+    pub fn is_lame_duck(&self) -> bool {
+        self.lame_duck.is_cancelled()
+    }
+
+    pub async fn lame_duck_entered(&self) {
+        self.lame_duck.cancelled().await
+    }
+
+    // on SIGINT/SIGTERM, spends what is left of `lame_duck` in lame duck
+    // before shutting down, so load balancers stop sending work first; a
+    // second signal cuts it short
+    pub async fn wait_for_signal(self, lame_duck: Duration) {
+        tokio::select! {
+            _ = signal() => {},
+            _ = self.triggered() => {},
+        }
+
+        let in_lame_duck = self
+            .lame_duck_since
+            .get()
+            .map_or(Duration::ZERO, Instant::elapsed);
+        let remaining = lame_duck.saturating_sub(in_lame_duck);
+        if !self.is_triggered() && !remaining.is_zero() {
+            info!("shutdown requested, in lame duck for {:?}", remaining);
+            self.enter_lame_duck();
+            tokio::select! {
+                _ = tokio::time::sleep(remaining) => {},
+                _ = signal() => {},
+                _ = self.triggered() => {},
+            }
+        }
+
+        info!("shutdown requested, draining open streams");
+        self.trigger();
+    }
+
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        self.tasks.close();
+        tokio::time::timeout(timeout, self.tasks.wait())
+            .await
+            .is_ok()
+    }
+}
+
+async fn signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drain_waits_for_tasks() {
+        let shutdown = Shutdown::new();
+        let task_shutdown = shutdown.clone();
+        shutdown.spawn(async move { task_shutdown.triggered().await });
+
+        shutdown.trigger();
+
+        assert!(shutdown.drain(Duration::from_secs(1)).await);
+    }
+
+    #[tokio::test]
+    async fn test_lame_duck() {
+        let shutdown = Shutdown::new();
+        let clone = shutdown.clone();
+        assert!(!clone.is_lame_duck());
+
+        assert!(shutdown.enter_lame_duck());
+        assert!(!shutdown.enter_lame_duck());
+        clone.lame_duck_entered().await;
+        assert!(clone.is_lame_duck());
+        // in-flight work is not told to stop
+        assert!(!clone.is_triggered());
+    }
+
+    #[tokio::test]
+    async fn test_drain_times_out() {
+        let shutdown = Shutdown::new();
+        shutdown.spawn(std::future::pending::<()>());
+
+        assert!(!shutdown.drain(Duration::from_millis(10)).await);
+    }
+}
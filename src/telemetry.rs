@@ -0,0 +1,497 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Mutex, OnceLock, RwLock},
+    task::{Context, Poll},
+};
+
+use axum::http::{HeaderMap, Request, Response};
+use tonic::metadata::MetadataMap;
+use tower::{Layer, Service};
+use tracing::{Instrument, debug};
+use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
+    prelude::*,
+    reload,
+};
+
+use crate::Error;
+
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+pub const TRACESTATE_HEADER: &str = "tracestate";
+
+// the filter swapped in by reloading LOG_LEVEL or from the admin service
+struct LogLevel {
+    reload: Box<dyn Fn(Targets) -> Result<(), reload::Error> + Send + Sync>,
+    configured: String,
+    current: String,
+}
+
+static LOG_LEVEL: OnceLock<Mutex<LogLevel>> = OnceLock::new();
+
+// `log_level` is a LOG_LEVEL value, already validated by the config
+pub fn init(log_level: &str) {
+    let log_level = match log_level.trim() {
+        "" => "info",
+        log_level => log_level,
+    };
+    let (filter, handle) = reload::Layer::new(
+        parse_log_level(log_level)
+            .unwrap_or_else(|_| Targets::new().with_default(LevelFilter::INFO)),
+    );
+    let _ = LOG_LEVEL.set(Mutex::new(LogLevel {
+        reload: Box::new(move |targets| handle.reload(targets)),
+        configured: log_level.to_owned(),
+        current: log_level.to_owned(),
+    }));
+    let fmt = tracing_subscriber::fmt::layer()
+        .pretty()
+        .with_filter(filter);
+
+    #[cfg(feature = "console")]
+    tracing_subscriber::registry()
+        .with(console_subscriber::spawn())
+        .with(fmt)
+        .init();
+
+    #[cfg(not(feature = "console"))]
+    tracing_subscriber::registry().with(fmt).init();
+}
+
+// `info` or `info,gin_tonik::repositories=debug`, as LOG_LEVEL takes it; a
+// target may end in `::*`, which means the same as without it
+pub fn parse_log_level(value: &str) -> Result<Targets, Error> {
+    let directives: Vec<_> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(|d| match d.split_once('=') {
+            Some((target, level)) => format!("{}={}", target.trim_end_matches("::*"), level),
+            None => d.trim_end_matches("::*").to_owned(),
+        })
+        .collect();
+    if directives.is_empty() {
+        return Ok(Targets::new().with_default(LevelFilter::INFO));
+    }
+
+    directives
+        .join(",")
+        .parse()
+        .map_err(|e| Error::InvalidArgument(format!("invalid log level {:?}: {}", value, e)))
+}
+
+// LOG_LEVEL changed on reload, replacing any override as well
+pub fn configure_log_level(value: &str) -> Result<(), Error> {
+    swap_log_level(value, true).map(|_| ())
+}
+
+// until the next reload or override; empty goes back to LOG_LEVEL. Returns the
+// filter it replaced
+pub fn override_log_level(value: &str) -> Result<String, Error> {
+    swap_log_level(value, false)
+}
+
+// None without the subscriber `init` installs
+pub fn log_level() -> Option<String> {
+    LOG_LEVEL
+        .get()
+        .map(|level| level.lock().unwrap().current.clone())
+}
+
+fn swap_log_level(value: &str, configure: bool) -> Result<String, Error> {
+    let Some(level) = LOG_LEVEL.get() else {
+        parse_log_level(value)?;
+        return Ok(String::new());
+    };
+    let mut level = level.lock().unwrap();
+    let value = match value.trim() {
+        "" if !configure => level.configured.clone(),
+        value => value.to_owned(),
+    };
+
+    (level.reload)(parse_log_level(&value)?).map_err(|e| Error::Internal(Box::new(e)))?;
+    if configure {
+        level.configured = value.clone();
+    }
+    Ok(std::mem::replace(&mut level.current, value))
+}
+
+// how many new traces are recorded: `rate` of them, or the rate of the first
+// method override matching the request, which also overrides a caller's
+// decision (`DeleteUser=1` samples every call)
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceSampling {
+    pub rate: f64,
+    // `Method`, `Service/Method` or `*`, as in FAULT_INJECTION
+    pub methods: Vec<(String, f64)>,
+}
+
+impl Default for TraceSampling {
+    fn default() -> Self {
+        Self {
+            rate: 1.0,
+            methods: Vec::new(),
+        }
+    }
+}
+
+impl TraceSampling {
+    // `DeleteUser=1,StreamUsers=0.01`, as TRACE_SAMPLE_METHODS takes it
+    pub fn parse_methods(value: &str) -> Result<Vec<(String, f64)>, String> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|m| !m.is_empty())
+            .map(|m| {
+                let (method, rate) = m
+                    .split_once('=')
+                    .ok_or_else(|| format!("{:?} is not `Method=rate`", m))?;
+                let rate = rate
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|r| (0.0..=1.0).contains(r))
+                    .ok_or_else(|| format!("bad rate {:?} for {}", rate.trim(), method.trim()))?;
+                Ok((method.trim().to_owned(), rate))
+            })
+            .collect()
+    }
+
+    // `parent` is the caller's sampled flag, when there is a caller
+    fn sampled(&self, path: &str, parent: Option<bool>) -> bool {
+        let rate = self
+            .methods
+            .iter()
+            .find(|(method, _)| crate::faults::matches_method(method, path))
+            .map(|(_, rate)| *rate);
+        match (rate, parent) {
+            (Some(rate), _) => rate >= 1.0 || rand::random::<f64>() < rate,
+            (None, Some(sampled)) => sampled,
+            (None, None) => self.rate >= 1.0 || rand::random::<f64>() < self.rate,
+        }
+    }
+}
+
+static SAMPLING: RwLock<Option<TraceSampling>> = RwLock::new(None);
+
+pub fn set_trace_sampling(sampling: TraceSampling) {
+    *SAMPLING.write().unwrap() = Some(sampling);
+}
+
+pub fn trace_sampling() -> TraceSampling {
+    SAMPLING.read().unwrap().clone().unwrap_or_default()
+}
+
+tokio::task_local! {
+    static CURRENT: Current;
+}
+
+struct Current {
+    context: TraceContext,
+    path: String,
+}
+
+// W3C trace context of the request being served; `span_id` is this hop and
+// becomes the parent id of every outbound call
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub parent_id: Option<u64>,
+    pub flags: u8,
+    pub state: Option<String>,
+}
+
+impl TraceContext {
+    // continues the caller's trace, or starts a sampled one when there is
+    // none or it is malformed
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let parent = headers
+            .get(TRACEPARENT_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| {
+                let parsed = parse_traceparent(v);
+                if parsed.is_none() {
+                    debug!("ignoring malformed {} {:?}", TRACEPARENT_HEADER, v);
+                }
+                parsed
+            });
+
+        match parent {
+            Some((trace_id, parent_id, flags)) => {
+                // repeated tracestate headers are one comma separated list
+                let state = headers
+                    .get_all(TRACESTATE_HEADER)
+                    .iter()
+                    .filter_map(|v| v.to_str().ok())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                Self {
+                    trace_id,
+                    span_id: new_id(),
+                    parent_id: Some(parent_id),
+                    flags,
+                    state: Some(state).filter(|s| !s.is_empty()),
+                }
+            }
+            None => Self {
+                trace_id: loop {
+                    let id = rand::random::<u128>();
+                    if id != 0 {
+                        break id;
+                    }
+                },
+                span_id: new_id(),
+                parent_id: None,
+                flags: 0x01,
+                state: None,
+            },
+        }
+    }
+
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id, self.span_id, self.flags
+        )
+    }
+}
+
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.trace_id)
+    }
+}
+
+// `<version>-<trace-id>-<parent-id>-<flags>`, later versions may append fields
+fn parse_traceparent(value: &str) -> Option<(u128, u64, u8)> {
+    let mut parts = value.trim().split('-');
+    let version = hex_field(parts.next()?, 2)?;
+    let trace_id = hex_field(parts.next()?, 32)?;
+    let parent_id = hex_field(parts.next()?, 16)?;
+    let flags = hex_field(parts.next()?, 2)?;
+    if version == 0xff || (version == 0 && parts.next().is_some()) {
+        return None;
+    }
+    if trace_id == 0 || parent_id == 0 {
+        return None;
+    }
+
+    Some((trace_id, parent_id as u64, flags as u8))
+}
+
+fn hex_field(value: &str, len: usize) -> Option<u128> {
+    let lowercase = value
+        .bytes()
+        .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+    if value.len() != len || !lowercase {
+        return None;
+    }
+    u128::from_str_radix(value, 16).ok()
+}
+
+fn new_id() -> u64 {
+    loop {
+        let id = rand::random::<u64>();
+        if id != 0 {
+            return id;
+        }
+    }
+}
+
+pub fn current() -> Option<TraceContext> {
+    CURRENT.try_with(|c| c.context.clone()).ok()
+}
+
+// the URI path of the request being served, `/<service>/<method>` for gRPC
+pub fn current_path() -> Option<String> {
+    CURRENT.try_with(|c| c.path.clone()).ok()
+}
+
+// propagates the current request's trace to an outbound gRPC call
+pub fn inject(metadata: &mut MetadataMap) {
+    let Some(context) = current() else {
+        return;
+    };
+    if let Ok(value) = context.traceparent().parse() {
+        metadata.insert(TRACEPARENT_HEADER, value);
+    }
+    if let Some(Ok(value)) = context.state.as_deref().map(str::parse) {
+        metadata.insert(TRACESTATE_HEADER, value);
+    }
+}
+
+// wraps each request in a span carrying its trace ids, for the gRPC server
+// and the HTTP router alike
+#[derive(Clone, Copy, Default)]
+pub struct TraceContextLayer;
+
+impl<S> Layer<S> for TraceContextLayer {
+    type Service = TraceContextService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TraceContextService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct TraceContextService<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for TraceContextService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let mut context = TraceContext::from_headers(req.headers());
+        let path = req.uri().path().to_owned();
+        let parent = context.parent_id.map(|_| context.flags & 0x01 != 0);
+        // an unsampled request gets no span, and its calls further down
+        // inherit the decision through traceparent
+        let span = if trace_sampling().sampled(&path, parent) {
+            context.flags |= 0x01;
+            tracing::info_span!(
+                "request",
+                path = %path,
+                trace_id = %context,
+                span_id = %format_args!("{:016x}", context.span_id),
+                parent_id = %format_args!("{:016x}", context.parent_id.unwrap_or_default()),
+            )
+        } else {
+            context.flags &= !0x01;
+            tracing::Span::none()
+        };
+        let res = span.in_scope(|| self.inner.call(req));
+
+        Box::pin(
+            CURRENT
+                .scope(Current { context, path }, res)
+                .instrument(span),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::ServiceExt;
+    use tracing::Level;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_parse_log_level() {
+        let targets = parse_log_level("warn,gin_tonik::repositories=debug").unwrap();
+
+        assert!(targets.would_enable("gin_tonik::repositories::user_repository", &Level::DEBUG));
+        assert!(!targets.would_enable("gin_tonik::servers", &Level::INFO));
+        assert!(
+            parse_log_level("")
+                .unwrap()
+                .would_enable("h2", &Level::INFO)
+        );
+        assert!(parse_log_level("gin_tonik=loud").is_err());
+
+        let wildcard = parse_log_level("info, gin_tonik::repositories::*=debug").unwrap();
+        assert!(wildcard.would_enable("gin_tonik::repositories::cached", &Level::DEBUG));
+        assert!(!wildcard.would_enable("gin_tonik::servers", &Level::DEBUG));
+    }
+
+    #[test]
+    fn test_parse_traceparent() {
+        assert_eq!(
+            parse_traceparent(TRACEPARENT),
+            Some((0x4bf92f3577b34da6a3ce929d0e0e4736, 0x00f067aa0ba902b7, 0x01))
+        );
+        // future versions may add fields, version 00 may not
+        assert!(parse_traceparent(&format!("01{}-extra", &TRACEPARENT[2..])).is_some());
+        assert!(parse_traceparent(&format!("{}-extra", TRACEPARENT)).is_none());
+        assert!(parse_traceparent(&format!("ff{}", &TRACEPARENT[2..])).is_none());
+        assert!(parse_traceparent(&TRACEPARENT.to_uppercase()).is_none());
+        assert!(
+            parse_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01").is_none()
+        );
+        assert!(parse_traceparent("00-4bf92f3577b34da6-00f067aa0ba902b7-01").is_none());
+    }
+
+    #[test]
+    fn test_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(TRACEPARENT_HEADER, TRACEPARENT.parse().unwrap());
+        headers.append(TRACESTATE_HEADER, "congo=t61rcWkgMzE".parse().unwrap());
+        headers.append(TRACESTATE_HEADER, "rojo=00f067aa0ba902b7".parse().unwrap());
+
+        let context = TraceContext::from_headers(&headers);
+        assert_eq!(context.trace_id, 0x4bf92f3577b34da6a3ce929d0e0e4736);
+        assert_eq!(context.parent_id, Some(0x00f067aa0ba902b7));
+        assert_ne!(context.span_id, 0x00f067aa0ba902b7);
+        assert_eq!(
+            context.state.as_deref(),
+            Some("congo=t61rcWkgMzE,rojo=00f067aa0ba902b7")
+        );
+        assert!(
+            context
+                .traceparent()
+                .starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-")
+        );
+
+        let fresh = TraceContext::from_headers(&HeaderMap::new());
+        assert_eq!(fresh.parent_id, None);
+        assert_ne!(fresh.trace_id, 0);
+    }
+
+    #[test]
+    fn test_trace_sampling() {
+        let sampling = TraceSampling {
+            rate: 0.0,
+            methods: TraceSampling::parse_methods("DeleteUser=1, UserService/StreamUsers=0")
+                .unwrap(),
+        };
+        let path = |method: &str| format!("/user.v1.UserService/{}", method);
+
+        assert!(sampling.sampled(&path("DeleteUser"), None));
+        assert!(sampling.sampled(&path("DeleteUser"), Some(false)));
+        assert!(!sampling.sampled(&path("StreamUsers"), Some(true)));
+        assert!(!sampling.sampled(&path("GetUserById"), None));
+        assert!(sampling.sampled(&path("GetUserById"), Some(true)));
+
+        assert!(TraceSampling::parse_methods("DeleteUser").is_err());
+        assert!(TraceSampling::parse_methods("DeleteUser=2").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_layer_scopes_the_context() {
+        let service = TraceContextLayer.layer(tower::service_fn(|_: Request<()>| async {
+            let mut metadata = MetadataMap::new();
+            inject(&mut metadata);
+            Ok::<_, std::convert::Infallible>(Response::new(metadata))
+        }));
+
+        let req = Request::builder()
+            .header(TRACEPARENT_HEADER, TRACEPARENT)
+            .header(TRACESTATE_HEADER, "congo=t61rcWkgMzE")
+            .body(())
+            .unwrap();
+        let metadata = service.oneshot(req).await.unwrap().into_body();
+
+        let outbound = metadata.get(TRACEPARENT_HEADER).unwrap().to_str().unwrap();
+        assert!(outbound.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert!(!outbound.contains("00f067aa0ba902b7"));
+        assert_eq!(
+            metadata.get(TRACESTATE_HEADER).unwrap(),
+            "congo=t61rcWkgMzE"
+        );
+        assert_eq!(current(), None);
+    }
+}
@@ -0,0 +1,85 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use rustls_acme::{AcmeConfig, caches::DirCache, is_tls_alpn_challenge};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_rustls::{LazyConfigAcceptor, server::TlsStream};
+use tokio_stream::{Stream, StreamExt, wrappers::ReceiverStream};
+use tracing::{error, info};
+
+use crate::{Error, config::AcmeSettings};
+
+pub async fn incoming(
+    addr: SocketAddr,
+    settings: &AcmeSettings,
+) -> Result<impl Stream<Item = Result<TlsStream<TcpStream>, std::io::Error>>, Error> {
+    let mut state = AcmeConfig::new(&settings.domains)
+        .contact(settings.contact.iter().map(|e| format!("mailto:{}", e)))
+        .cache(DirCache::new(settings.cache_dir.clone()))
+        .directory_lets_encrypt(settings.production)
+        .state();
+
+    let challenge_config = state.challenge_rustls_config();
+    let mut default_config = (*state.default_rustls_config()).clone();
+    default_config.alpn_protocols = vec![b"h2".to_vec()];
+    let default_config = Arc::new(default_config);
+
+    tokio::spawn(async move {
+        while let Some(event) = state.next().await {
+            match event {
+                Ok(ok) => info!("acme event: {:?}", ok),
+                Err(e) => error!("acme error: {:?}", e),
+            }
+        }
+    });
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+    let (tx, rx) = mpsc::channel(128);
+
+    tokio::spawn(async move {
+        loop {
+            let tcp = match listener.accept().await {
+                Ok((tcp, _)) => tcp,
+                Err(e) => {
+                    error!("failed to accept connection: {:?}", e);
+                    continue;
+                }
+            };
+            let challenge_config = challenge_config.clone();
+            let default_config = default_config.clone();
+            let tx = tx.clone();
+
+            tokio::spawn(async move {
+                let handshake = match LazyConfigAcceptor::new(Default::default(), tcp).await {
+                    Ok(handshake) => handshake,
+                    Err(e) => {
+                        error!("tls handshake failed: {:?}", e);
+                        return;
+                    }
+                };
+
+                if is_tls_alpn_challenge(&handshake.client_hello()) {
+                    info!("received TLS-ALPN-01 validation request");
+                    if let Ok(mut tls) = handshake.into_stream(challenge_config).await {
+                        let _ = tls.shutdown().await;
+                    }
+                    return;
+                }
+
+                match handshake.into_stream(default_config).await {
+                    Ok(tls) => {
+                        let _ = tx.send(Ok(tls)).await;
+                    }
+                    Err(e) => error!("tls handshake failed: {:?}", e),
+                }
+            });
+        }
+    });
+
+    Ok(ReceiverStream::new(rx))
+}
@@ -0,0 +1,263 @@
+#[cfg(feature = "acme")]
+pub mod acme;
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use sha2::{Digest, Sha256};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        ServerConfig,
+        crypto::ring,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+        server::{ClientHello, ResolvesServerCert},
+        sign::CertifiedKey,
+    },
+    server::TlsStream,
+};
+use tokio_stream::{Stream, wrappers::ReceiverStream};
+use tonic::transport::{Identity, ServerTlsConfig};
+use tracing::{debug, error};
+
+use crate::Error;
+
+const DEV_SUBJECT_ALT_NAMES: [&str; 3] = ["localhost", "127.0.0.1", "::1"];
+
+// TLS_MODE=files: the certificate and key are read again on every reload, new
+// handshakes get the new pair while established connections keep theirs.
+// TLS_MODE=vault has no files, its pair is replaced by the Vault refresh
+#[derive(Debug)]
+pub struct ReloadableCertificate {
+    files: Option<(PathBuf, PathBuf)>,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ReloadableCertificate {
+    pub fn load(cert: &Path, key: &Path) -> Result<Self, Error> {
+        Ok(Self {
+            files: Some((cert.to_owned(), key.to_owned())),
+            current: RwLock::new(certified_key(cert, key)?),
+        })
+    }
+
+    pub fn from_pem(cert: &[u8], key: &[u8]) -> Result<Self, Error> {
+        Ok(Self {
+            files: None,
+            current: RwLock::new(pem_certified_key(cert, key)?),
+        })
+    }
+
+    // the old pair stays in use when the new one does not load
+    pub fn reload(&self) -> Result<(), Error> {
+        let Some((cert, key)) = &self.files else {
+            return Ok(());
+        };
+        *self.current.write().unwrap() = certified_key(cert, key)?;
+        Ok(())
+    }
+
+    pub fn replace(&self, cert: &[u8], key: &[u8]) -> Result<(), Error> {
+        *self.current.write().unwrap() = pem_certified_key(cert, key)?;
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for ReloadableCertificate {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+fn certified_key(cert: &Path, key: &Path) -> Result<Arc<CertifiedKey>, Error> {
+    let chain = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| Error::Internal(format!("invalid {}: {}", cert.display(), e).into()))?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| Error::Internal(format!("invalid {}: {}", key.display(), e).into()))?;
+
+    signed(chain, key)
+}
+
+fn pem_certified_key(cert: &[u8], key: &[u8]) -> Result<Arc<CertifiedKey>, Error> {
+    let chain = CertificateDer::pem_slice_iter(cert)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::Internal(format!("invalid certificate: {}", e).into()))?;
+    let key = PrivateKeyDer::from_pem_slice(key)
+        .map_err(|e| Error::Internal(format!("invalid private key: {}", e).into()))?;
+
+    signed(chain, key)
+}
+
+fn signed(
+    chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+) -> Result<Arc<CertifiedKey>, Error> {
+    if chain.is_empty() {
+        return Err(Error::Internal("no certificate in the PEM".into()));
+    }
+    let signing_key =
+        ring::sign::any_supported_type(&key).map_err(|e| Error::Internal(Box::new(e)))?;
+
+    Ok(Arc::new(CertifiedKey::new(chain, signing_key)))
+}
+
+// the connections tonic serves with TLS_MODE=files; ends once the server stops
+// taking them
+pub async fn incoming(
+    addr: SocketAddr,
+    certificate: Arc<ReloadableCertificate>,
+) -> Result<impl Stream<Item = Result<TlsStream<TcpStream>, std::io::Error>>, Error> {
+    let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| Error::Internal(Box::new(e)))?
+        .with_no_client_auth()
+        .with_cert_resolver(certificate);
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?;
+    let (tx, rx) = mpsc::channel(128);
+
+    tokio::spawn(async move {
+        loop {
+            let tcp = tokio::select! {
+                _ = tx.closed() => return,
+                accepted = listener.accept() => match accepted {
+                    Ok((tcp, _)) => tcp,
+                    Err(e) => {
+                        error!("failed to accept connection: {:?}", e);
+                        continue;
+                    }
+                },
+            };
+            let acceptor = acceptor.clone();
+            let tx = tx.clone();
+
+            tokio::spawn(async move {
+                match acceptor.accept(tcp).await {
+                    Ok(tls) => {
+                        let _ = tx.send(Ok(tls)).await;
+                    }
+                    Err(e) => debug!("tls handshake failed: {:?}", e),
+                }
+            });
+        }
+    });
+
+    Ok(ReceiverStream::new(rx))
+}
+
+pub struct SelfSigned {
+    pub config: ServerTlsConfig,
+    pub cert_pem: String,
+    pub fingerprint: String,
+}
+
+pub fn self_signed_tls_config() -> Result<SelfSigned, Error> {
+    let alt_names = DEV_SUBJECT_ALT_NAMES.map(str::to_owned).to_vec();
+    let certified =
+        rcgen::generate_simple_self_signed(alt_names).map_err(|e| Error::Internal(Box::new(e)))?;
+
+    let cert_pem = certified.cert.pem();
+    let fingerprint = fingerprint(certified.cert.der());
+    let identity = Identity::from_pem(&cert_pem, certified.signing_key.serialize_pem());
+
+    Ok(SelfSigned {
+        config: ServerTlsConfig::new().identity(identity),
+        cert_pem,
+        fingerprint,
+    })
+}
+
+fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reloadable_certificate() {
+        let dir = std::env::temp_dir().join(format!("gin_tonik_tls_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (cert, key) = (dir.join("cert.pem"), dir.join("key.pem"));
+        let write = |alt_name: &str| {
+            let certified = rcgen::generate_simple_self_signed(vec![alt_name.to_string()]).unwrap();
+            std::fs::write(&cert, certified.cert.pem()).unwrap();
+            std::fs::write(&key, certified.signing_key.serialize_pem()).unwrap();
+            certified.cert.der().to_vec()
+        };
+        let served = |certificate: &ReloadableCertificate| {
+            certificate.current.read().unwrap().cert[0].to_vec()
+        };
+
+        let first = write("localhost");
+        let certificate = ReloadableCertificate::load(&cert, &key).unwrap();
+        assert_eq!(served(&certificate), first);
+
+        let second = write("example.test");
+        certificate.reload().unwrap();
+        assert_eq!(served(&certificate), second);
+
+        std::fs::write(&key, "not a key").unwrap();
+        assert!(certificate.reload().is_err());
+        assert_eq!(served(&certificate), second);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_replaced_certificate() {
+        let pem = |alt_name: &str| {
+            let certified = rcgen::generate_simple_self_signed(vec![alt_name.to_string()]).unwrap();
+            (
+                certified.cert.pem(),
+                certified.signing_key.serialize_pem(),
+                certified.cert.der().to_vec(),
+            )
+        };
+        let served = |certificate: &ReloadableCertificate| {
+            certificate.current.read().unwrap().cert[0].to_vec()
+        };
+
+        let (cert, key, first) = pem("localhost");
+        let certificate = ReloadableCertificate::from_pem(cert.as_bytes(), key.as_bytes()).unwrap();
+        certificate.reload().unwrap();
+        assert_eq!(served(&certificate), first);
+
+        let (cert, key, second) = pem("example.test");
+        certificate
+            .replace(cert.as_bytes(), key.as_bytes())
+            .unwrap();
+        assert_eq!(served(&certificate), second);
+        assert!(certificate.replace(b"", key.as_bytes()).is_err());
+        assert_eq!(served(&certificate), second);
+    }
+
+    #[test]
+    fn test_self_signed_fingerprint() {
+        let self_signed = self_signed_tls_config().unwrap();
+
+        assert!(
+            self_signed
+                .cert_pem
+                .starts_with("-----BEGIN CERTIFICATE-----")
+        );
+        assert_eq!(self_signed.fingerprint.len(), 32 * 3 - 1);
+        assert_eq!(self_signed.fingerprint.split(':').count(), 32);
+    }
+}
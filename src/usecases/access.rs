@@ -0,0 +1,23 @@
+use crate::{Error, auth::Principal};
+
+// a non-admin caller may only touch their own record; without
+// authentication there is no caller and nothing to enforce
+pub fn authorize(caller: Option<&Principal>, id: i32) -> Result<(), Error> {
+    match caller {
+        Some(p) if !p.is_admin() && p.user_id != id => Err(Error::PermissionDenied),
+        _ => Ok(()),
+    }
+}
+
+// for what spans every user
+pub fn authorize_admin(caller: Option<&Principal>) -> Result<(), Error> {
+    match caller {
+        Some(p) if !p.is_admin() => Err(Error::PermissionDenied),
+        _ => Ok(()),
+    }
+}
+
+// the one user a non-admin caller may see, None when it may see any
+pub fn own_record(caller: Option<&Principal>) -> Option<i32> {
+    caller.filter(|p| !p.is_admin()).map(|p| p.user_id)
+}
@@ -0,0 +1,292 @@
+use crate::{
+    Error,
+    auth::Principal,
+    entities::addresses::Address,
+    grpc::{AddUserAddressResponse, DeleteAddressResponse, ListUserAddressesResponse},
+    repositories::{AddressRepository, UserRepository, address_repository::NewAddress},
+    usecases::{
+        AddressUsecaseTrait,
+        access::{authorize, own_record},
+        write_protection::WriteProtection,
+    },
+};
+use async_trait::async_trait;
+
+pub struct AddressUsecase<A: AddressRepository, U: UserRepository> {
+    addresses: A,
+    users: U,
+    write_protection: WriteProtection,
+}
+
+impl<A: AddressRepository, U: UserRepository> AddressUsecase<A, U> {
+    pub fn new(addresses: A, users: U) -> Self {
+        Self {
+            addresses,
+            users,
+            write_protection: WriteProtection::default(),
+        }
+    }
+
+    pub fn with_write_protection(mut self, write_protection: WriteProtection) -> Self {
+        self.write_protection = write_protection;
+        self
+    }
+
+    async fn ensure_user(&self, user_id: i32) -> Result<(), Error> {
+        if self.users.user_exists(user_id).await? {
+            Ok(())
+        } else {
+            Err(Error::NotFound)
+        }
+    }
+}
+
+fn into_proto(address: Address) -> crate::grpc::Address {
+    crate::grpc::Address {
+        id: address.id,
+        user_id: address.user_id,
+        street: address.street,
+        city: address.city,
+        postal_code: address.postal_code,
+        country: address.country,
+    }
+}
+
+#[async_trait]
+impl<A: AddressRepository + 'static, U: UserRepository + 'static> AddressUsecaseTrait
+    for AddressUsecase<A, U>
+{
+    async fn add_user_address(
+        &self,
+        caller: Option<&Principal>,
+        address: NewAddress,
+    ) -> Result<AddUserAddressResponse, Error> {
+        self.write_protection.check()?;
+        authorize(caller, address.user_id)?;
+        for (field, value) in [("street", &address.street), ("city", &address.city)] {
+            if value.trim().is_empty() {
+                return Err(Error::InvalidArgument(format!(
+                    "{} must not be empty",
+                    field
+                )));
+            }
+        }
+        self.ensure_user(address.user_id).await?;
+
+        let address = self.addresses.create(address).await?;
+
+        Ok(AddUserAddressResponse {
+            address: Some(into_proto(address)),
+        })
+    }
+
+    async fn list_user_addresses(
+        &self,
+        caller: Option<&Principal>,
+        user_id: i32,
+    ) -> Result<ListUserAddressesResponse, Error> {
+        authorize(caller, user_id)?;
+        self.ensure_user(user_id).await?;
+
+        let addresses = self.addresses.list_by_user(user_id).await?;
+
+        Ok(ListUserAddressesResponse {
+            addresses: addresses.into_iter().map(into_proto).collect(),
+        })
+    }
+
+    async fn delete_address(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+    ) -> Result<DeleteAddressResponse, Error> {
+        self.write_protection.check()?;
+        // only a non-admin needs the owner looked up
+        if let Some(own) = own_record(caller) {
+            let address = self.addresses.get(id).await?.ok_or(Error::NotFound)?;
+            if address.user_id != own {
+                return Err(Error::PermissionDenied);
+            }
+        }
+        self.addresses.delete(id).await?;
+
+        Ok(DeleteAddressResponse {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::{
+        crud::CrudRepository, memory_user_repository::InMemoryUserRepository,
+        user_repository_trait::UserRepository as UserRepositoryTrait,
+    };
+    use mockall::predicate::*;
+
+    mockall::mock! {
+        Repo {}
+
+        #[async_trait::async_trait]
+        impl CrudRepository<Address, i32> for Repo {
+            type New = NewAddress;
+
+            async fn create(&self, new: NewAddress) -> Result<Address, crate::Error>;
+            async fn get(&self, id: i32) -> Result<Option<Address>, crate::Error>;
+            async fn list(&self, offset: i32, limit: i32) -> Result<Vec<Address>, crate::Error>;
+            async fn update(&self, id: i32, new: NewAddress) -> Result<Option<Address>, crate::Error>;
+            async fn delete(&self, id: i32) -> Result<(), crate::Error>;
+        }
+
+        #[async_trait::async_trait]
+        impl crate::repositories::address_repository_trait::AddressRepository for Repo {
+            async fn list_by_user(&self, user_id: i32) -> Result<Vec<Address>, crate::Error>;
+        }
+    }
+
+    impl Clone for MockRepo {
+        fn clone(&self) -> Self {
+            Self::default()
+        }
+    }
+
+    fn new_address(user_id: i32, street: &str) -> NewAddress {
+        NewAddress {
+            user_id,
+            street: street.to_string(),
+            city: "Springfield".to_string(),
+            postal_code: "12345".to_string(),
+            country: "US".to_string(),
+        }
+    }
+
+    async fn users_with_one() -> InMemoryUserRepository {
+        let users = InMemoryUserRepository::new();
+        users
+            .create_user("Address".to_string(), "Owner".to_string())
+            .await
+            .unwrap();
+        users
+    }
+
+    #[tokio::test]
+    async fn test_add_user_address() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo
+            .expect_create()
+            .with(eq(new_address(1, "1 Main St")))
+            .times(1)
+            .returning(|new| {
+                Ok(Address {
+                    id: 10,
+                    user_id: new.user_id,
+                    street: new.street,
+                    city: new.city,
+                    postal_code: new.postal_code,
+                    country: new.country,
+                })
+            });
+
+        let usecase = AddressUsecase::new(mock_repo, users_with_one().await);
+        let result = usecase
+            .add_user_address(None, new_address(1, "1 Main St"))
+            .await;
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().address.unwrap().id, 10);
+    }
+
+    #[tokio::test]
+    async fn test_add_address_unknown_user() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo.expect_create().times(0);
+
+        let usecase = AddressUsecase::new(mock_repo, InMemoryUserRepository::new());
+        let result = usecase
+            .add_user_address(None, new_address(1, "1 Main St"))
+            .await;
+
+        assert!(matches!(result.unwrap_err(), Error::NotFound));
+    }
+
+    #[tokio::test]
+    async fn test_add_address_requires_street() {
+        let usecase = AddressUsecase::new(MockRepo::new(), users_with_one().await);
+        let result = usecase.add_user_address(None, new_address(1, "  ")).await;
+
+        assert!(matches!(result.unwrap_err(), Error::InvalidArgument(_)));
+    }
+
+    #[tokio::test]
+    async fn test_list_user_addresses() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo
+            .expect_list_by_user()
+            .with(eq(1))
+            .times(1)
+            .returning(|_| Ok(vec![Address::default(), Address::default()]));
+
+        let usecase = AddressUsecase::new(mock_repo, users_with_one().await);
+        let result = usecase.list_user_addresses(None, 1).await;
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().addresses.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_delete_address_not_found() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo
+            .expect_delete()
+            .with(eq(5))
+            .times(1)
+            .returning(|_| Err(Error::NotFound));
+
+        let usecase = AddressUsecase::new(mock_repo, InMemoryUserRepository::new());
+        let result = usecase.delete_address(None, 5).await;
+
+        assert!(matches!(result.unwrap_err(), Error::NotFound));
+    }
+
+    #[tokio::test]
+    async fn test_non_admin_limited_to_own_addresses() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo.expect_create().times(0);
+        mock_repo.expect_list_by_user().times(0);
+        mock_repo.expect_get().returning(|id| {
+            Ok(Some(Address {
+                id,
+                user_id: id / 10,
+                ..Default::default()
+            }))
+        });
+        mock_repo
+            .expect_delete()
+            .with(eq(10))
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let usecase = AddressUsecase::new(mock_repo, users_with_one().await);
+        let caller = Principal {
+            user_id: 1,
+            roles: Vec::new(),
+            tenant: None,
+            impersonator: None,
+        };
+
+        assert!(matches!(
+            usecase
+                .add_user_address(Some(&caller), new_address(2, "1 Main St"))
+                .await,
+            Err(Error::PermissionDenied)
+        ));
+        assert!(matches!(
+            usecase.list_user_addresses(Some(&caller), 2).await,
+            Err(Error::PermissionDenied)
+        ));
+        assert!(matches!(
+            usecase.delete_address(Some(&caller), 20).await,
+            Err(Error::PermissionDenied)
+        ));
+        assert!(usecase.delete_address(Some(&caller), 10).await.is_ok());
+    }
+}
@@ -0,0 +1,27 @@
+use crate::{
+    Error,
+    auth::Principal,
+    grpc::{AddUserAddressResponse, DeleteAddressResponse, ListUserAddressesResponse},
+    repositories::address_repository::NewAddress,
+};
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait AddressUsecase: Send + Sync {
+    // a non-admin caller only reaches their own addresses
+    async fn add_user_address(
+        &self,
+        caller: Option<&Principal>,
+        address: NewAddress,
+    ) -> Result<AddUserAddressResponse, Error>;
+    async fn list_user_addresses(
+        &self,
+        caller: Option<&Principal>,
+        user_id: i32,
+    ) -> Result<ListUserAddressesResponse, Error>;
+    async fn delete_address(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+    ) -> Result<DeleteAddressResponse, Error>;
+}
@@ -0,0 +1,114 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tracing::warn;
+
+use crate::Error;
+
+// the last exact count, handed out while a refresh runs in the background,
+// so at most one COUNT per `max_age` reaches the database
+#[derive(Clone)]
+pub struct CountEstimate {
+    max_age: Duration,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    count: Option<(i64, Instant)>,
+    refreshing: bool,
+}
+
+impl CountEstimate {
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            max_age,
+            state: Arc::default(),
+        }
+    }
+
+    // only the first call waits for `count`; a failed refresh keeps the old
+    // value and is retried by the next call
+    pub async fn get<F, Fut>(&self, count: F) -> Result<i64, Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<i64, Error>> + Send + 'static,
+    {
+        let cached = {
+            let mut state = self.state.lock().unwrap();
+            match state.count {
+                Some((cached, at)) if at.elapsed() < self.max_age || state.refreshing => {
+                    return Ok(cached);
+                }
+                Some((cached, _)) => {
+                    state.refreshing = true;
+                    Some(cached)
+                }
+                None => None,
+            }
+        };
+        let Some(cached) = cached else {
+            let fresh = count().await?;
+            self.state.lock().unwrap().count = Some((fresh, Instant::now()));
+            return Ok(fresh);
+        };
+
+        let refresh = count();
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            let fresh = refresh.await;
+            let mut state = state.lock().unwrap();
+            state.refreshing = false;
+            match fresh {
+                Ok(fresh) => state.count = Some((fresh, Instant::now())),
+                Err(e) => warn!("failed to refresh the user count: {:?}", e),
+            }
+        });
+
+        Ok(cached)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_serves_the_last_count_while_refreshing() {
+        let estimate = CountEstimate::new(Duration::from_millis(20));
+        let calls = Arc::new(AtomicI64::new(0));
+        let count = || {
+            let calls = calls.clone();
+            async move { Ok(calls.fetch_add(1, Ordering::SeqCst) + 1) }
+        };
+
+        assert_eq!(estimate.get(count).await.unwrap(), 1);
+        assert_eq!(estimate.get(count).await.unwrap(), 1);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        // stale, answered from the old count while the refresh runs
+        assert_eq!(estimate.get(count).await.unwrap(), 1);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(estimate.get(count).await.unwrap(), 2);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_keeps_the_count_when_a_refresh_fails() {
+        let estimate = CountEstimate::new(Duration::ZERO);
+
+        assert_eq!(estimate.get(|| async { Ok(7) }).await.unwrap(), 7);
+        let failed = estimate
+            .get(|| async { Err(Error::Internal("database is down".into())) })
+            .await;
+        assert_eq!(failed.unwrap(), 7);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!estimate.state.lock().unwrap().refreshing);
+    }
+}
@@ -0,0 +1,269 @@
+use std::{pin::Pin, sync::Arc};
+
+use async_trait::async_trait;
+#[cfg(feature = "redis")]
+use serde_json::{Value, json};
+use tokio::sync::broadcast;
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+
+use crate::{
+    Error,
+    entities::users::{User, UserEventKind},
+};
+
+// what one replica's change feed tells the others; sequences are assigned by
+// each receiving feed, so they are not part of it, event store tokens are
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FeedMessage {
+    Event {
+        kind: UserEventKind,
+        user: User,
+        token: u64,
+    },
+    Erase {
+        user: User,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Relayed {
+    // origin identifies the publishing replica so it can skip its own messages
+    Message { origin: u64, message: FeedMessage },
+    // the subscriber may have missed messages
+    Gap,
+}
+
+pub type RelayedMessages = Pin<Box<dyn Stream<Item = Relayed> + Send>>;
+
+#[async_trait]
+pub trait FeedBus: Send + Sync {
+    async fn publish(&self, origin: u64, message: &FeedMessage) -> Result<(), Error>;
+    async fn subscribe(&self) -> Result<RelayedMessages, Error>;
+}
+
+pub async fn connect(url: &str) -> Result<Arc<dyn FeedBus>, Error> {
+    #[cfg(feature = "redis")]
+    return Ok(Arc::new(redis::RedisFeedBus::connect(url).await?));
+
+    #[cfg(not(feature = "redis"))]
+    Err(Error::Internal(
+        format!(
+            "CHANGE_FEED_URL={:?} requires building with the `redis` feature",
+            url
+        )
+        .into(),
+    ))
+}
+
+#[derive(Clone)]
+pub struct LocalFeedBus {
+    sender: broadcast::Sender<(u64, FeedMessage)>,
+}
+
+impl LocalFeedBus {
+    pub fn new() -> Self {
+        Self {
+            sender: broadcast::channel(1024).0,
+        }
+    }
+}
+
+impl Default for LocalFeedBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl FeedBus for LocalFeedBus {
+    async fn publish(&self, origin: u64, message: &FeedMessage) -> Result<(), Error> {
+        // no subscribers is not an error
+        let _ = self.sender.send((origin, message.clone()));
+        Ok(())
+    }
+
+    async fn subscribe(&self) -> Result<RelayedMessages, Error> {
+        let stream = BroadcastStream::new(self.sender.subscribe()).map(|msg| match msg {
+            Ok((origin, message)) => Relayed::Message { origin, message },
+            Err(_) => Relayed::Gap,
+        });
+
+        Ok(Box::pin(stream))
+    }
+}
+
+// names travel as they do to watchers, in the clear
+#[cfg(feature = "redis")]
+fn encode(origin: u64, message: &FeedMessage) -> String {
+    let (kind, user, token) = match message {
+        FeedMessage::Event { kind, user, token } => (kind.as_str(), user, *token),
+        FeedMessage::Erase { user } => ("erased", user, 0),
+    };
+    json!({
+        "origin": origin,
+        "kind": kind,
+        "token": token,
+        "user": { "id": user.id, "name": user.name, "surname": user.surname },
+    })
+    .to_string()
+}
+
+#[cfg(feature = "redis")]
+fn decode(payload: &str) -> Option<(u64, FeedMessage)> {
+    let value: Value = serde_json::from_str(payload).ok()?;
+    let user = &value["user"];
+    let user = User {
+        id: user["id"].as_i64()?.try_into().ok()?,
+        name: user["name"].as_str()?.to_owned(),
+        surname: user["surname"].as_str()?.to_owned(),
+    };
+    let origin = value["origin"].as_u64()?;
+    let kind = match value["kind"].as_str()? {
+        "erased" => return Some((origin, FeedMessage::Erase { user })),
+        kind => kind.parse().ok()?,
+    };
+    let token = value["token"].as_u64()?;
+
+    Some((origin, FeedMessage::Event { kind, user, token }))
+}
+
+#[cfg(feature = "redis")]
+pub mod redis {
+    use async_trait::async_trait;
+    use redis::{AsyncCommands, Client, aio::MultiplexedConnection};
+    use tokio_stream::StreamExt;
+    use tracing::warn;
+
+    use super::{FeedBus, FeedMessage, Relayed, RelayedMessages, decode, encode};
+    use crate::Error;
+
+    const CHANNEL: &str = "user_change_feed";
+
+    pub struct RedisFeedBus {
+        client: Client,
+        connection: MultiplexedConnection,
+    }
+
+    impl RedisFeedBus {
+        pub async fn connect(url: &str) -> Result<Self, Error> {
+            let client = Client::open(url).map_err(|e| Error::Internal(Box::new(e)))?;
+            let connection = client
+                .get_multiplexed_async_connection()
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))?;
+
+            Ok(Self { client, connection })
+        }
+    }
+
+    #[async_trait]
+    impl FeedBus for RedisFeedBus {
+        async fn publish(&self, origin: u64, message: &FeedMessage) -> Result<(), Error> {
+            let mut connection = self.connection.clone();
+            connection
+                .publish::<_, _, ()>(CHANNEL, encode(origin, message))
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))
+        }
+
+        async fn subscribe(&self) -> Result<RelayedMessages, Error> {
+            let mut pubsub = self
+                .client
+                .get_async_pubsub()
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))?;
+            pubsub
+                .subscribe(CHANNEL)
+                .await
+                .map_err(|e| Error::Internal(Box::new(e)))?;
+
+            let stream = pubsub.into_on_message().map(|msg| {
+                let decoded = msg.get_payload::<String>().ok().and_then(|p| decode(&p));
+                match decoded {
+                    Some((origin, message)) => Relayed::Message { origin, message },
+                    None => {
+                        warn!("malformed change feed message on {}", CHANNEL);
+                        Relayed::Gap
+                    }
+                }
+            });
+
+            Ok(Box::pin(stream))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{shutdown::Shutdown, usecases::user_feed::UserFeed};
+
+    #[cfg(feature = "redis")]
+    #[test]
+    fn test_messages_round_trip() {
+        let user = User {
+            id: 7,
+            name: "Ann \"A\"".to_string(),
+            surname: "Lee".to_string(),
+        };
+        for message in [
+            FeedMessage::Event {
+                kind: UserEventKind::Deleted,
+                user: user.clone(),
+                token: 9,
+            },
+            FeedMessage::Erase { user },
+        ] {
+            assert_eq!(decode(&encode(42, &message)), Some((42, message)));
+        }
+        assert_eq!(decode(r#"{"origin":1,"kind":"moved","user":{}}"#), None);
+    }
+
+    #[tokio::test]
+    async fn test_events_reach_every_replica() {
+        let shutdown = Shutdown::new();
+        let bus = LocalFeedBus::new();
+        let a = UserFeed::new().with_bus(Arc::new(bus.clone()), &shutdown);
+        let b = UserFeed::new().with_bus(Arc::new(bus.clone()), &shutdown);
+        while bus.sender.receiver_count() < 2 {
+            tokio::task::yield_now().await;
+        }
+        let (mut on_a, mut on_b) = (a.subscribe(), b.subscribe());
+
+        let user = User {
+            id: 1,
+            name: "Ann".to_string(),
+            surname: "Lee".to_string(),
+        };
+        a.publish(UserEventKind::Created, user.clone());
+
+        assert_eq!(on_a.recv().await.unwrap().user, user);
+        let relayed = tokio::time::timeout(Duration::from_secs(1), on_b.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (relayed.sequence, relayed.kind),
+            (1, UserEventKind::Created)
+        );
+        // a replica does not take its own messages back
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(on_a.try_recv().is_err());
+
+        let erased = User {
+            id: 1,
+            ..User::default()
+        };
+        a.erase(&erased);
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while b.retained(1)[0].user != erased {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+        shutdown.trigger();
+    }
+}
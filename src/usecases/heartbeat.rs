@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+
+// when a stream is due a heartbeat: `tick` completes once nothing was sent
+// for the interval, and never without one. Sending is left to the stream
+pub struct Heartbeat {
+    interval: Option<Interval>,
+}
+
+impl Heartbeat {
+    pub fn new(every: Option<Duration>) -> Self {
+        let interval = every.map(|every| {
+            let mut interval = tokio::time::interval_at(Instant::now() + every, every);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            interval
+        });
+
+        Self { interval }
+    }
+
+    pub async fn tick(&mut self) {
+        match &mut self.interval {
+            Some(interval) => {
+                interval.tick().await;
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    // after every message, so a busy stream sends no heartbeats
+    pub fn reset(&mut self) {
+        if let Some(interval) = &mut self.interval {
+            interval.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ticks_only_when_quiet() {
+        let mut heartbeat = Heartbeat::new(Some(Duration::from_millis(40)));
+        let start = Instant::now();
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        heartbeat.reset();
+        heartbeat.tick().await;
+        assert!(start.elapsed() >= Duration::from_millis(70));
+        heartbeat.tick().await;
+        assert!(start.elapsed() >= Duration::from_millis(110));
+
+        let mut none = Heartbeat::new(None);
+        let ticked = tokio::time::timeout(Duration::from_millis(50), none.tick()).await;
+        assert!(ticked.is_err());
+    }
+}
@@ -1,4 +1,18 @@
+pub mod access;
+pub mod address_usecase;
+pub mod address_usecase_trait;
+pub mod count_estimate;
+pub mod feed_bus;
+pub mod heartbeat;
+pub mod paging;
+pub mod relationship_usecase;
+pub mod relationship_usecase_trait;
+pub mod single_flight;
+pub mod user_feed;
 pub mod user_usecase;
 pub mod user_usecase_trait;
+pub mod write_protection;
 
+pub use address_usecase_trait::AddressUsecase as AddressUsecaseTrait;
+pub use relationship_usecase_trait::RelationshipUsecase as RelationshipUsecaseTrait;
 pub use user_usecase_trait::UserUsecase as UserUsecaseTrait;
@@ -0,0 +1,17 @@
+use crate::Error;
+
+pub const DEFAULT_PAGE_SIZE: i32 = 50;
+pub const MAX_PAGE_SIZE: i32 = 1000;
+
+// anything past the maximum is refused instead of cut short, the caller
+// would take the shorter page for what it asked
+pub fn page_size(requested: i32) -> Result<i32, Error> {
+    match requested {
+        0 => Ok(DEFAULT_PAGE_SIZE),
+        1..=MAX_PAGE_SIZE => Ok(requested),
+        _ => Err(Error::InvalidArgument(format!(
+            "page_size must be between 1 and {}",
+            MAX_PAGE_SIZE
+        ))),
+    }
+}
@@ -0,0 +1,303 @@
+use crate::{
+    Error,
+    auth::Principal,
+    entities::relationships::{Relationship, RelationshipKind},
+    grpc::{
+        AddRelationshipResponse, ListRelatedUsersResponse, RelatedUser, RemoveRelationshipResponse,
+    },
+    repositories::{RelationshipRepository, UserRepository},
+    usecases::{RelationshipUsecaseTrait, access::authorize, write_protection::WriteProtection},
+};
+use async_trait::async_trait;
+
+const MAX_DEPTH: i32 = 3;
+const DEFAULT_PAGE_SIZE: i32 = 50;
+const MAX_PAGE_SIZE: i32 = 500;
+
+pub struct RelationshipUsecase<R: RelationshipRepository, U: UserRepository> {
+    relationships: R,
+    users: U,
+    write_protection: WriteProtection,
+}
+
+impl<R: RelationshipRepository, U: UserRepository> RelationshipUsecase<R, U> {
+    pub fn new(relationships: R, users: U) -> Self {
+        Self {
+            relationships,
+            users,
+            write_protection: WriteProtection::default(),
+        }
+    }
+
+    pub fn with_write_protection(mut self, write_protection: WriteProtection) -> Self {
+        self.write_protection = write_protection;
+        self
+    }
+
+    async fn ensure_user(&self, user_id: i32) -> Result<(), Error> {
+        if self.users.user_exists(user_id).await? {
+            Ok(())
+        } else {
+            Err(Error::NotFound)
+        }
+    }
+}
+
+#[async_trait]
+impl<R: RelationshipRepository + 'static, U: UserRepository + 'static> RelationshipUsecaseTrait
+    for RelationshipUsecase<R, U>
+{
+    async fn add_relationship(
+        &self,
+        caller: Option<&Principal>,
+        relationship: Relationship,
+    ) -> Result<AddRelationshipResponse, Error> {
+        self.write_protection.check()?;
+        authorize(caller, relationship.user_id)?;
+        if relationship.user_id == relationship.related_user_id {
+            return Err(Error::InvalidArgument(
+                "a user cannot be related to itself".to_string(),
+            ));
+        }
+        self.ensure_user(relationship.user_id).await?;
+        self.ensure_user(relationship.related_user_id).await?;
+
+        self.relationships.add_relationship(relationship).await?;
+
+        Ok(AddRelationshipResponse {})
+    }
+
+    async fn remove_relationship(
+        &self,
+        caller: Option<&Principal>,
+        relationship: Relationship,
+    ) -> Result<RemoveRelationshipResponse, Error> {
+        self.write_protection.check()?;
+        authorize(caller, relationship.user_id)?;
+        self.relationships.remove_relationship(relationship).await?;
+
+        Ok(RemoveRelationshipResponse {})
+    }
+
+    async fn list_related_users(
+        &self,
+        caller: Option<&Principal>,
+        user_id: i32,
+        kind: RelationshipKind,
+        depth: i32,
+        page_size: i32,
+        page_token: String,
+    ) -> Result<ListRelatedUsersResponse, Error> {
+        authorize(caller, user_id)?;
+        let depth = match depth {
+            0 => 1,
+            1..=MAX_DEPTH => depth,
+            _ => {
+                return Err(Error::InvalidArgument(format!(
+                    "depth must be between 1 and {}",
+                    MAX_DEPTH
+                )));
+            }
+        };
+        let page_size = match page_size {
+            0 => DEFAULT_PAGE_SIZE,
+            1..=MAX_PAGE_SIZE => page_size,
+            _ => {
+                return Err(Error::InvalidArgument(format!(
+                    "page_size must be between 1 and {}",
+                    MAX_PAGE_SIZE
+                )));
+            }
+        };
+        // the token is the id of the last user on the previous page
+        let after_id = match page_token.as_str() {
+            "" => 0,
+            token => token
+                .parse()
+                .map_err(|_| Error::InvalidArgument("invalid page_token".to_string()))?,
+        };
+        self.ensure_user(user_id).await?;
+
+        // one extra row tells whether another page exists
+        let mut related = self
+            .relationships
+            .list_related_users(user_id, kind, depth, after_id, page_size + 1)
+            .await?;
+        let next_page_token = if related.len() > page_size as usize {
+            related.truncate(page_size as usize);
+            related
+                .last()
+                .map(|r| r.user.id.to_string())
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        Ok(ListRelatedUsersResponse {
+            users: related
+                .into_iter()
+                .map(|r| RelatedUser {
+                    user: Some(crate::grpc::User {
+                        id: r.user.id,
+                        name: r.user.name,
+                        surname: r.user.surname,
+                    }),
+                    depth: r.depth,
+                })
+                .collect(),
+            next_page_token,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entities::{relationships::RelatedUser as RelatedUserEntity, users::User},
+        repositories::{
+            memory_user_repository::InMemoryUserRepository,
+            user_repository_trait::UserRepository as UserRepositoryTrait,
+        },
+    };
+    use mockall::predicate::*;
+
+    mockall::mock! {
+        Repo {}
+
+        #[async_trait::async_trait]
+        impl crate::repositories::relationship_repository_trait::RelationshipRepository for Repo {
+            async fn add_relationship(&self, relationship: Relationship) -> Result<(), crate::Error>;
+            async fn remove_relationship(&self, relationship: Relationship) -> Result<(), crate::Error>;
+            async fn list_related_users(&self, user_id: i32, kind: RelationshipKind, max_depth: i32, after_id: i32, limit: i32) -> Result<Vec<RelatedUserEntity>, crate::Error>;
+        }
+    }
+
+    impl Clone for MockRepo {
+        fn clone(&self) -> Self {
+            Self::default()
+        }
+    }
+
+    async fn users(n: usize) -> InMemoryUserRepository {
+        let users = InMemoryUserRepository::new();
+        for i in 0..n {
+            users
+                .create_user(format!("User{}", i), "Graph".to_string())
+                .await
+                .unwrap();
+        }
+        users
+    }
+
+    fn related(id: i32) -> RelatedUserEntity {
+        RelatedUserEntity {
+            user: User {
+                id,
+                name: format!("User{}", id),
+                surname: "Graph".to_string(),
+            },
+            depth: 1,
+        }
+    }
+
+    fn friend(user_id: i32, related_user_id: i32) -> Relationship {
+        Relationship {
+            user_id,
+            related_user_id,
+            kind: RelationshipKind::Friend,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_relationship() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo
+            .expect_add_relationship()
+            .with(eq(friend(1, 2)))
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let usecase = RelationshipUsecase::new(mock_repo, users(2).await);
+        let result = usecase.add_relationship(None, friend(1, 2)).await;
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_add_relationship_to_self() {
+        let usecase = RelationshipUsecase::new(MockRepo::new(), users(1).await);
+        let result = usecase.add_relationship(None, friend(1, 1)).await;
+
+        assert!(matches!(result.unwrap_err(), Error::InvalidArgument(_)));
+    }
+
+    #[tokio::test]
+    async fn test_list_related_users_pages() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo
+            .expect_list_related_users()
+            .with(eq(1), eq(RelationshipKind::Friend), eq(1), eq(0), eq(3))
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(vec![related(2), related(3), related(4)]));
+
+        let usecase = RelationshipUsecase::new(mock_repo, users(1).await);
+        let result = usecase
+            .list_related_users(None, 1, RelationshipKind::Friend, 0, 2, String::new())
+            .await
+            .unwrap();
+
+        assert_eq!(result.users.len(), 2);
+        assert_eq!(result.next_page_token, "3");
+    }
+
+    #[tokio::test]
+    async fn test_list_related_users_invalid_depth() {
+        let usecase = RelationshipUsecase::new(MockRepo::new(), users(1).await);
+        let result = usecase
+            .list_related_users(None, 1, RelationshipKind::Friend, 10, 0, String::new())
+            .await;
+
+        assert!(matches!(result.unwrap_err(), Error::InvalidArgument(_)));
+    }
+
+    #[tokio::test]
+    async fn test_non_admin_limited_to_own_relationships() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo.expect_add_relationship().times(0);
+        mock_repo.expect_remove_relationship().times(0);
+        mock_repo.expect_list_related_users().times(0);
+
+        let usecase = RelationshipUsecase::new(mock_repo, users(2).await);
+        let caller = Principal {
+            user_id: 1,
+            roles: Vec::new(),
+            tenant: None,
+            impersonator: None,
+        };
+
+        assert!(matches!(
+            usecase.add_relationship(Some(&caller), friend(2, 1)).await,
+            Err(Error::PermissionDenied)
+        ));
+        assert!(matches!(
+            usecase
+                .remove_relationship(Some(&caller), friend(2, 1))
+                .await,
+            Err(Error::PermissionDenied)
+        ));
+        assert!(matches!(
+            usecase
+                .list_related_users(
+                    Some(&caller),
+                    2,
+                    RelationshipKind::Friend,
+                    0,
+                    0,
+                    String::new()
+                )
+                .await,
+            Err(Error::PermissionDenied)
+        ));
+    }
+}
@@ -0,0 +1,31 @@
+use crate::{
+    Error,
+    auth::Principal,
+    entities::relationships::{Relationship, RelationshipKind},
+    grpc::{AddRelationshipResponse, ListRelatedUsersResponse, RemoveRelationshipResponse},
+};
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait RelationshipUsecase: Send + Sync {
+    // a non-admin caller only reaches relationships from themselves
+    async fn add_relationship(
+        &self,
+        caller: Option<&Principal>,
+        relationship: Relationship,
+    ) -> Result<AddRelationshipResponse, Error>;
+    async fn remove_relationship(
+        &self,
+        caller: Option<&Principal>,
+        relationship: Relationship,
+    ) -> Result<RemoveRelationshipResponse, Error>;
+    async fn list_related_users(
+        &self,
+        caller: Option<&Principal>,
+        user_id: i32,
+        kind: RelationshipKind,
+        depth: i32,
+        page_size: i32,
+        page_token: String,
+    ) -> Result<ListRelatedUsersResponse, Error>;
+}
@@ -0,0 +1,131 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::OnceCell;
+
+use crate::Error;
+
+// concurrent calls with the same key share the first caller's result; once it
+// is in, the key is forgotten, so this never serves anything stale
+pub struct SingleFlight<K, V> {
+    calls: Mutex<HashMap<K, Arc<OnceCell<V>>>>,
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self {
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> SingleFlight<K, V> {
+    // an error is not shared, the next waiter runs `f` itself, and so does
+    // everyone if the leading call is cancelled
+    pub async fn run<F, Fut>(&self, key: K, f: F) -> Result<(V, bool), Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, Error>>,
+    {
+        let cell = self
+            .calls
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let _forget = Forget {
+            calls: &self.calls,
+            key,
+            cell: cell.clone(),
+        };
+
+        let mut led = false;
+        let value = cell
+            .get_or_try_init(|| {
+                led = true;
+                f()
+            })
+            .await?
+            .clone();
+
+        Ok((value, !led))
+    }
+}
+
+struct Forget<'a, K: Eq + Hash, V> {
+    calls: &'a Mutex<HashMap<K, Arc<OnceCell<V>>>>,
+    key: K,
+    cell: Arc<OnceCell<V>>,
+}
+
+impl<K: Eq + Hash, V> Drop for Forget<'_, K, V> {
+    fn drop(&mut self) {
+        let mut calls = self.calls.lock().unwrap();
+        if calls
+            .get(&self.key)
+            .is_some_and(|cell| Arc::ptr_eq(cell, &self.cell))
+        {
+            calls.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use tokio::task::JoinSet;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_concurrent_calls_share_one_result() {
+        let flight = Arc::new(SingleFlight::<i32, i32>::default());
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let mut tasks = JoinSet::new();
+        for _ in 0..10 {
+            let (flight, runs) = (flight.clone(), runs.clone());
+            tasks.spawn(async move {
+                flight
+                    .run(1, || async {
+                        runs.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        Ok(42)
+                    })
+                    .await
+                    .unwrap()
+            });
+        }
+        let results = tasks.join_all().await;
+
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert!(results.iter().all(|(value, _)| *value == 42));
+        assert_eq!(results.iter().filter(|(_, shared)| !shared).count(), 1);
+
+        // finished calls are not cached
+        let (value, shared) = flight.run(1, || async { Ok(7) }).await.unwrap();
+        assert_eq!((value, shared), (7, false));
+        assert!(flight.calls.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_not_shared() {
+        let flight = SingleFlight::<i32, i32>::default();
+
+        let res = flight
+            .run(1, || async { Err(Error::Internal("down".into())) })
+            .await;
+
+        assert!(res.is_err());
+        assert_eq!(flight.run(1, || async { Ok(1) }).await.unwrap(), (1, false));
+    }
+}
@@ -0,0 +1,364 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::StreamExt;
+use tracing::warn;
+
+use super::feed_bus::{FeedBus, FeedMessage, Relayed};
+use crate::{
+    Error,
+    entities::users::{StoredUserEvent, User, UserEvent, UserEventKind},
+    repositories::UserEventRepository,
+    shutdown::Shutdown,
+};
+
+const FEED_CAPACITY: usize = 1024;
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+// in-process change feed shared by every UserUsecase of a server; watchers
+// that fall more than FEED_CAPACITY events behind are dropped, and the last
+// FEED_CAPACITY events are kept so reconnecting watchers can resume. With a
+// bus, the events of every replica reach the watchers of every other, each
+// feed numbering them in the order it received them. With an event store,
+// each event is appended to it before going out, and its token lets watchers
+// resume from the store on any replica
+#[derive(Clone)]
+pub struct UserFeed {
+    sender: broadcast::Sender<UserEvent>,
+    state: Arc<Mutex<FeedState>>,
+    // shared, so clones taken before `with_bus` relay as well
+    relay: Arc<OnceLock<mpsc::Sender<FeedMessage>>>,
+    store: Option<Arc<dyn UserEventRepository>>,
+    appends: Option<mpsc::Sender<FeedMessage>>,
+}
+
+#[derive(Default)]
+struct FeedState {
+    sequence: u64,
+    recent: VecDeque<UserEvent>,
+}
+
+impl UserFeed {
+    pub fn new() -> Self {
+        Self {
+            sender: broadcast::channel(FEED_CAPACITY).0,
+            state: Arc::default(),
+            relay: Arc::default(),
+            store: None,
+            appends: None,
+        }
+    }
+
+    // publishes on `bus` in the order published here, and feeds in what the
+    // other replicas publish on it
+    pub fn with_bus(self, bus: Arc<dyn FeedBus>, shutdown: &Shutdown) -> Self {
+        let origin = rand::random::<u64>();
+        let (relay, mut outgoing) = mpsc::channel::<FeedMessage>(FEED_CAPACITY);
+
+        let (out, stop) = (bus.clone(), shutdown.clone());
+        shutdown.spawn(async move {
+            loop {
+                let message = tokio::select! {
+                    _ = stop.triggered() => break,
+                    message = outgoing.recv() => match message {
+                        Some(message) => message,
+                        None => break,
+                    },
+                };
+                if let Err(e) = out.publish(origin, &message).await {
+                    warn!("failed to relay a change feed event: {}", e);
+                }
+            }
+        });
+
+        let (feed, stop) = (self.clone(), shutdown.clone());
+        shutdown.spawn(async move {
+            while !stop.is_triggered() {
+                let mut messages = match bus.subscribe().await {
+                    Ok(messages) => messages,
+                    Err(e) => {
+                        warn!("failed to subscribe to the change feed: {}", e);
+                        tokio::select! {
+                            _ = stop.triggered() => break,
+                            _ = tokio::time::sleep(RESUBSCRIBE_DELAY) => continue,
+                        }
+                    }
+                };
+
+                loop {
+                    tokio::select! {
+                        biased;
+                        _ = stop.triggered() => return,
+                        message = messages.next() => match message {
+                            Some(relayed) => feed.receive(origin, relayed),
+                            None => break,
+                        },
+                    }
+                }
+
+                // anything published while resubscribing is lost
+                warn!("change feed subscription ended, resubscribing");
+            }
+        });
+
+        let _ = self.relay.set(relay);
+        self
+    }
+
+    // events and erasures go out in the order published, each event once
+    // appended; one the store fails to take goes out without a token
+    pub fn with_event_store(
+        mut self,
+        store: Arc<dyn UserEventRepository>,
+        shutdown: &Shutdown,
+    ) -> Self {
+        let (appends, mut pending) = mpsc::channel::<FeedMessage>(FEED_CAPACITY);
+
+        let (feed, events, stop) = (self.clone(), store.clone(), shutdown.clone());
+        shutdown.spawn(async move {
+            loop {
+                let mut message = tokio::select! {
+                    _ = stop.triggered() => break,
+                    message = pending.recv() => match message {
+                        Some(message) => message,
+                        None => break,
+                    },
+                };
+                if let FeedMessage::Event { kind, user, token } = &mut message {
+                    match events.append(*kind, user.id).await {
+                        Ok(appended) => *token = appended,
+                        Err(e) => warn!("failed to append a change feed event: {}", e),
+                    }
+                }
+                feed.commit(message);
+            }
+        });
+
+        self.store = Some(store);
+        self.appends = Some(appends);
+        self
+    }
+
+    fn receive(&self, own: u64, relayed: Relayed) {
+        match relayed {
+            Relayed::Message { origin, .. } if origin == own => {}
+            Relayed::Message { message, .. } => self.apply(message),
+            Relayed::Gap => warn!("missed change feed events from other replicas"),
+        }
+    }
+
+    pub fn publish(&self, kind: UserEventKind, user: User) {
+        self.submit(FeedMessage::Event {
+            kind,
+            user,
+            token: 0,
+        });
+    }
+
+    fn submit(&self, message: FeedMessage) {
+        let Some(appends) = &self.appends else {
+            return self.commit(message);
+        };
+        if let Err(e) = appends.try_send(message) {
+            warn!("the event store is behind, an event goes out without a token");
+            self.commit(e.into_inner());
+        }
+    }
+
+    fn commit(&self, message: FeedMessage) {
+        self.apply(message.clone());
+        self.send(message);
+    }
+
+    fn apply(&self, message: FeedMessage) {
+        match message {
+            FeedMessage::Event { kind, user, token } => self.record(kind, user, token),
+            FeedMessage::Erase { user } => self.erase_retained(&user),
+        }
+    }
+
+    fn send(&self, message: FeedMessage) {
+        let Some(relay) = self.relay.get() else {
+            return;
+        };
+        if relay.try_send(message).is_err() {
+            warn!("change feed relay is full, an event stays on this replica");
+        }
+    }
+
+    fn record(&self, kind: UserEventKind, user: User, token: u64) {
+        // held across the send so sequence numbers reach watchers in order
+        let mut state = self.state.lock().unwrap();
+        state.sequence += 1;
+        let event = UserEvent {
+            sequence: state.sequence,
+            token,
+            kind,
+            user,
+        };
+        if state.recent.len() == FEED_CAPACITY {
+            state.recent.pop_front();
+        }
+        state.recent.push_back(event.clone());
+        // no watchers is not an error
+        let _ = self.sender.send(event);
+    }
+
+    // rewrites the retained events about `user` to its erased values, so
+    // resuming watchers no longer receive the old ones; sequences stay as they are
+    pub fn erase(&self, user: &User) {
+        self.submit(FeedMessage::Erase { user: user.clone() });
+    }
+
+    fn erase_retained(&self, user: &User) {
+        let mut state = self.state.lock().unwrap();
+        for event in state.recent.iter_mut().filter(|e| e.user.id == user.id) {
+            if event.kind != UserEventKind::Deleted {
+                event.user = user.clone();
+            }
+        }
+    }
+
+    // the retained events about one user, oldest first
+    pub fn retained(&self, user_id: i32) -> Vec<UserEvent> {
+        let state = self.state.lock().unwrap();
+        state
+            .recent
+            .iter()
+            .filter(|e| e.user.id == user_id)
+            .cloned()
+            .collect()
+    }
+
+    // the sequence of the latest event, 0 before the first
+    pub fn sequence(&self) -> u64 {
+        self.state.lock().unwrap().sequence
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<UserEvent> {
+        self.sender.subscribe()
+    }
+
+    // the retained events after `after` plus a receiver for the ones that
+    // follow them, without gaps or duplicates in between
+    pub fn resume(
+        &self,
+        after: u64,
+    ) -> Result<(Vec<UserEvent>, broadcast::Receiver<UserEvent>), Error> {
+        let state = self.state.lock().unwrap();
+        let oldest = state
+            .recent
+            .front()
+            .map_or(state.sequence + 1, |e| e.sequence);
+        // a sequence from the future means the server restarted since
+        if after > state.sequence || after + 1 < oldest {
+            return Err(Error::InvalidArgument(format!(
+                "events after sequence {} are no longer retained",
+                after
+            )));
+        }
+
+        let backlog = state
+            .recent
+            .iter()
+            .filter(|e| e.sequence > after)
+            .cloned()
+            .collect();
+
+        Ok((backlog, self.sender.subscribe()))
+    }
+
+    // up to `limit` stored events after `token` plus a receiver subscribed
+    // before reading them, so none falls in between; it may repeat some of
+    // them, their tokens tell which
+    pub async fn replay(
+        &self,
+        token: u64,
+        limit: i64,
+    ) -> Result<(Vec<StoredUserEvent>, broadcast::Receiver<UserEvent>), Error> {
+        let Some(store) = &self.store else {
+            return Err(Error::FailedPrecondition(
+                "resume tokens need the event store, see EVENT_STORE_RETENTION_HOURS".to_string(),
+            ));
+        };
+        let live = self.sender.subscribe();
+        // a token from the future is from another event store
+        let retained = store
+            .token_range()
+            .await?
+            .is_some_and(|(oldest, newest)| token + 1 >= oldest && token <= newest);
+        if !retained {
+            return Err(Error::InvalidArgument(format!(
+                "events after resume token {} are no longer retained",
+                token
+            )));
+        }
+
+        let events = store.events_after(token, limit + 1).await?;
+        if events.len() as i64 > limit {
+            return Err(Error::FailedPrecondition(format!(
+                "over {} events to replay, resync instead",
+                limit
+            )));
+        }
+
+        Ok((events, live))
+    }
+}
+
+impl Default for UserFeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_events_are_sequenced() {
+        let feed = UserFeed::new();
+        let mut events = feed.subscribe();
+
+        feed.publish(UserEventKind::Created, User::default());
+        feed.publish(UserEventKind::Deleted, User::default());
+
+        assert_eq!(events.recv().await.unwrap().sequence, 1);
+        let deleted = events.recv().await.unwrap();
+        assert_eq!(deleted.sequence, 2);
+        assert_eq!(deleted.kind, UserEventKind::Deleted);
+    }
+
+    #[tokio::test]
+    async fn test_resume_replays_retained_events() {
+        let feed = UserFeed::new();
+        for _ in 0..3 {
+            feed.publish(UserEventKind::Updated, User::default());
+        }
+
+        let (backlog, mut events) = feed.resume(1).unwrap();
+        feed.publish(UserEventKind::Deleted, User::default());
+
+        let sequences: Vec<_> = backlog.iter().map(|e| e.sequence).collect();
+        assert_eq!(sequences, vec![2, 3]);
+        assert_eq!(events.recv().await.unwrap().sequence, 4);
+        assert!(feed.resume(4).unwrap().0.is_empty());
+        assert!(feed.resume(9).is_err());
+    }
+
+    #[test]
+    fn test_resume_past_retention_fails() {
+        let feed = UserFeed::new();
+        for _ in 0..FEED_CAPACITY + 2 {
+            feed.publish(UserEventKind::Updated, User::default());
+        }
+
+        assert!(feed.resume(1).is_err());
+        assert_eq!(feed.resume(2).unwrap().0.len(), FEED_CAPACITY);
+    }
+}
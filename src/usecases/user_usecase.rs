@@ -1,25 +1,484 @@
-use tokio::sync::mpsc::Sender;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use tokio::sync::{
+    broadcast::error::RecvError,
+    mpsc::{Sender, error::TrySendError},
+};
+use tokio_stream::StreamExt;
 use tonic::Status;
 use tracing::error;
 use tracing::info;
 
 use crate::{
+    Error,
+    auth::Principal,
+    config::DEFAULT_GET_USERS_MAX_ROWS,
+    entities::{
+        audit::{AuditAction, AuditEntry},
+        consents::{Consent, NewConsent, RequiredConsent},
+        revisions::UserRevision,
+        stats::StatsPeriod,
+        users::{StoredUserEvent, User, UserEvent, UserEventKind, UserStatus},
+    },
+    export::{self, ExportFormat, user_data::UserData},
+    filter::{Filter, USER_FIELDS},
+    flags::{EnvFeatureFlags, FeatureFlags, Flag},
     grpc::{
-        CreateUserResponse, DeleteUserResponse, GetUserByIdResponse, GetUserByNameResponse,
-        GetUsersResponse, StreamUsersResponse, UpdateUserResponse,
+        ActivateUserResponse, AutocompleteUsersResponse, BatchGetUsersByNameResponse,
+        CountUsersResponse, CreateUserResponse, DeleteUserResponse, EraseUserResponse,
+        ExportUsersResponse, GetConsentsResponse, GetUserByIdResponse, GetUserByNameResponse,
+        GetUserRevisionResponse, GetUserStatsResponse, GetUsersResponse, ImportUsersResponse,
+        ListDeletedUsersResponse, ListUserRevisionsResponse, ListUsersByNamePrefixResponse,
+        MergeUsersResponse, RecordConsentResponse, RestoreUserResponse, StreamUsersResponse,
+        SuspendUserResponse, UpdateUserResponse, UserExistsResponse, UserList,
+    },
+    metrics::{
+        registry,
+        streams::{StreamGuard, Termination},
+    },
+    notifications::{Notification, NotificationKind, NotificationQueue},
+    repositories::{
+        AddressRepository, AuditRepository, ConsentRepository, ImportRepository,
+        RevisionRepository, StatsRepository, UserRepository, address_repository,
+        audit_repository::LogAuditRepository, consent_repository::InMemoryConsentRepository,
+    },
+    session::{self, Lsn},
+    shutdown::Shutdown,
+    usecases::{
+        UserUsecaseTrait,
+        access::{authorize, authorize_admin, own_record},
+        count_estimate::CountEstimate,
+        heartbeat::Heartbeat,
+        paging::page_size,
+        single_flight::SingleFlight,
+        user_feed::UserFeed,
+        user_usecase_trait::{AutocompleteRequests, ImportRequests},
+        write_protection::WriteProtection,
     },
-    repositories::UserRepository,
-    usecases::UserUsecaseTrait,
 };
 use async_trait::async_trait;
+use prost::Message;
+
+const MAX_NAME_LEN: usize = 255;
+const MAX_BATCH_NAMES: usize = 1000;
+const DEFAULT_PREFIX_LIMIT: i32 = 10;
+const MAX_PREFIX_LIMIT: i32 = 50;
+const MAX_STREAM_CHUNK: i32 = 10_000;
+// a watcher further behind resyncs instead
+const MAX_REPLAYED_EVENTS: i64 = 10_000;
+// well under the 4 MiB a tonic client decodes by default
+const MAX_STREAM_CHUNK_BYTES: usize = 1024 * 1024;
+const DEFAULT_COUNT_ESTIMATE_MAX_AGE: Duration = Duration::from_secs(60);
+const DEFAULT_STATS_PERIODS: u32 = 30;
+const MAX_STATS_PERIODS: u32 = 1000;
+// what EraseUser leaves in place of the name and surname
+pub const ERASED_NAME: &str = "[erased]";
+pub const ERASED_SURNAME: &str = "[erased]";
+// the widths of the consents columns
+const MAX_CONSENT_TYPE_LEN: usize = 64;
+const MAX_CONSENT_VERSION_LEN: usize = 32;
+const MAX_CONSENT_SOURCE_LEN: usize = 64;
+
+fn into_grpc_event(event: UserEvent) -> crate::grpc::UserEvent {
+    let kind = match event.kind {
+        UserEventKind::Created => crate::grpc::UserEventKind::Created,
+        UserEventKind::Updated => crate::grpc::UserEventKind::Updated,
+        UserEventKind::Deleted => crate::grpc::UserEventKind::Deleted,
+    };
+
+    crate::grpc::UserEvent {
+        sequence: event.sequence,
+        kind: kind as i32,
+        user: Some(crate::grpc::User {
+            id: event.user.id,
+            name: event.user.name,
+            surname: event.user.surname,
+        }),
+        resume_token: event.token,
+        heartbeat: false,
+    }
+}
+
+fn into_grpc_consent(consent: Consent) -> crate::grpc::Consent {
+    crate::grpc::Consent {
+        id: consent.id,
+        user_id: consent.user_id,
+        grant: Some(crate::grpc::ConsentGrant {
+            consent_type: consent.consent.consent_type,
+            version: consent.consent.version,
+            source: consent.consent.source,
+        }),
+        granted_at: Some(consent.granted_at.into()),
+    }
+}
+
+fn into_grpc_revision(revision: UserRevision) -> crate::grpc::UserRevision {
+    let changed_by = revision.changed_by;
+    crate::grpc::UserRevision {
+        revision: revision.revision,
+        user: Some(crate::grpc::User {
+            id: revision.user.id,
+            name: revision.user.name,
+            surname: revision.user.surname,
+        }),
+        deleted: revision.deleted,
+        changed_at: Some(revision.changed_at.into()),
+        action: changed_by
+            .as_ref()
+            .map(|a| a.action.as_str().to_string())
+            .unwrap_or_default(),
+        actor_user_id: changed_by.as_ref().and_then(|a| a.actor_user_id),
+        effective_user_id: changed_by.as_ref().and_then(|a| a.effective_user_id),
+    }
+}
+
+fn validate_consent(consent: &NewConsent) -> Result<(), Error> {
+    for (field, value, max_len) in [
+        ("consent_type", &consent.consent_type, MAX_CONSENT_TYPE_LEN),
+        ("version", &consent.version, MAX_CONSENT_VERSION_LEN),
+        ("source", &consent.source, MAX_CONSENT_SOURCE_LEN),
+    ] {
+        if value.trim().is_empty() {
+            return Err(Error::InvalidArgument(format!(
+                "consent {} must not be empty",
+                field
+            )));
+        }
+        if value.chars().count() > max_len {
+            return Err(Error::InvalidArgument(format!(
+                "consent {} must be at most {} characters",
+                field, max_len
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+// the token of a GetUsers page is its offset
+fn page_offset(token: &str) -> Result<i32, Error> {
+    match token {
+        "" => Ok(0),
+        token => token
+            .parse()
+            .ok()
+            .filter(|offset: &i32| *offset >= 0)
+            .ok_or_else(|| Error::InvalidArgument("invalid page_token".to_string())),
+    }
+}
+
+// from users fetched one past the page size, the extra one telling whether
+// another page exists
+fn users_page(mut users: Vec<crate::grpc::User>, offset: i32, page_size: i32) -> GetUsersResponse {
+    let next_page_token = if users.len() > page_size as usize {
+        users.truncate(page_size as usize);
+        offset.saturating_add(page_size).to_string()
+    } else {
+        String::new()
+    };
+
+    GetUsersResponse {
+        count: users.len() as i32,
+        users,
+        next_page_token,
+    }
+}
+
+fn prefix_limit(limit: i32) -> i32 {
+    match limit {
+        l if l <= 0 => DEFAULT_PREFIX_LIMIT,
+        l => l.min(MAX_PREFIX_LIMIT),
+    }
+}
 
 pub struct UserUsecase<T: UserRepository + Clone> {
     repo: T,
+    shutdown: Shutdown,
+    flags: Arc<dyn FeatureFlags>,
+    audit: Arc<dyn AuditRepository>,
+    // only the postgres backend keeps the history they are counted from
+    stats: Option<Arc<dyn StatsRepository>>,
+    // COPY is postgres only, and it writes names as sent, so not with PII
+    // encryption either
+    imports: Option<Arc<dyn ImportRepository>>,
+    revisions: Option<Arc<dyn RevisionRepository>>,
+    // addresses are only stored by the postgres backend as well
+    addresses: Option<address_repository::AddressRepository>,
+    consents: Arc<dyn ConsentRepository>,
+    required_consents: Vec<RequiredConsent>,
+    feed: UserFeed,
+    notifications: Option<NotificationQueue>,
+    // keyed by the session token too, a caller must not share a read that
+    // was allowed to run before its own writes were replayed
+    reads: SingleFlight<(i32, Option<Lsn>), Option<User>>,
+    count_estimate: CountEstimate,
+    write_protection: WriteProtection,
+    max_rows: usize,
 }
 
 impl<T: UserRepository + Clone> UserUsecase<T> {
     pub fn new(repo: T) -> Self {
-        Self { repo }
+        Self {
+            repo,
+            shutdown: Shutdown::new(),
+            flags: Arc::new(EnvFeatureFlags::default()),
+            audit: Arc::new(LogAuditRepository),
+            stats: None,
+            imports: None,
+            revisions: None,
+            addresses: None,
+            consents: Arc::new(InMemoryConsentRepository::default()),
+            required_consents: Vec::new(),
+            feed: UserFeed::new(),
+            notifications: None,
+            reads: SingleFlight::default(),
+            count_estimate: CountEstimate::new(DEFAULT_COUNT_ESTIMATE_MAX_AGE),
+            write_protection: WriteProtection::default(),
+            max_rows: DEFAULT_GET_USERS_MAX_ROWS,
+        }
+    }
+
+    // the most users one GetUsers call may hold, see GET_USERS_MAX_ROWS
+    pub fn with_get_users_max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = max_rows;
+        self
+    }
+
+    pub fn with_change_feed(mut self, feed: UserFeed) -> Self {
+        self.feed = feed;
+        self
+    }
+
+    pub fn with_audit_log(mut self, audit: Arc<dyn AuditRepository>) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    // shared by the usecases of every API surface
+    pub fn with_count_estimate(mut self, count_estimate: CountEstimate) -> Self {
+        self.count_estimate = count_estimate;
+        self
+    }
+
+    pub fn with_stats(mut self, stats: Arc<dyn StatsRepository>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    pub fn with_imports(mut self, imports: Arc<dyn ImportRepository>) -> Self {
+        self.imports = Some(imports);
+        self
+    }
+
+    pub fn with_notifications(mut self, notifications: NotificationQueue) -> Self {
+        self.notifications = Some(notifications);
+        self
+    }
+
+    pub fn with_revisions(mut self, revisions: Arc<dyn RevisionRepository>) -> Self {
+        self.revisions = Some(revisions);
+        self
+    }
+
+    pub fn with_addresses(mut self, addresses: address_repository::AddressRepository) -> Self {
+        self.addresses = Some(addresses);
+        self
+    }
+
+    pub fn with_consents(mut self, consents: Arc<dyn ConsentRepository>) -> Self {
+        self.consents = consents;
+        self
+    }
+
+    // the consents CreateUser must be given, see REQUIRED_CONSENTS
+    pub fn with_required_consents(mut self, required: Vec<RequiredConsent>) -> Self {
+        self.required_consents = required;
+        self
+    }
+
+    pub fn with_feature_flags(mut self, flags: Arc<dyn FeatureFlags>) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    // DATABASE_READ_ONLY, or whether the database is a standby
+    pub fn with_write_protection(mut self, write_protection: WriteProtection) -> Self {
+        self.write_protection = write_protection;
+        self
+    }
+
+    // the users as they are now: one deleted since is left to its deletion,
+    // which comes later
+    async fn replayed_events(&self, stored: Vec<StoredUserEvent>) -> Result<Vec<UserEvent>, Error> {
+        let mut users: HashMap<i32, Option<User>> = HashMap::new();
+        let mut events = Vec::with_capacity(stored.len());
+        for event in stored {
+            let user = match event.kind {
+                UserEventKind::Deleted => Some(User {
+                    id: event.user_id,
+                    ..User::default()
+                }),
+                _ => match users.get(&event.user_id) {
+                    Some(user) => user.clone(),
+                    None => {
+                        let user = self.repo.get_user_by_id(event.user_id).await?;
+                        users.insert(event.user_id, user.clone());
+                        user
+                    }
+                },
+            };
+            if let Some(user) = user {
+                events.push(UserEvent {
+                    sequence: 0,
+                    token: event.token,
+                    kind: event.kind,
+                    user,
+                });
+            }
+        }
+
+        Ok(events)
+    }
+
+    // streams can outlast the lame duck period, so none start in it
+    fn accept_stream(&self) -> Result<(), Error> {
+        if self.shutdown.is_lame_duck() {
+            return Err(Error::Unavailable(
+                "server is draining, retry on another instance".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // a page holds at most min(MAX_PAGE_SIZE, max_rows) users and is checked
+    // before its query, an as_of rebuild, which needs every user, before its
+    // page is built and sent
+    fn check_size(&self, rows: usize, reason: &'static str) -> Result<(), Error> {
+        if rows <= self.max_rows {
+            return Ok(());
+        }
+        registry().increment_counter("get_users_oversized_total", &[("reason", reason)], 1);
+        Err(Error::FailedPrecondition(format!(
+            "GetUsers answers at most {} users at once, page through them with a smaller \
+             page_size or use StreamUsers",
+            self.max_rows
+        )))
+    }
+
+    fn validate_name(&self, field: &str, value: &str) -> Result<(), Error> {
+        if !self.flags.is_enabled(Flag::StrictValidation, None) {
+            return Ok(());
+        }
+
+        if value.trim().is_empty() {
+            return Err(Error::InvalidArgument(format!(
+                "{} must not be empty",
+                field
+            )));
+        }
+        if value.chars().count() > MAX_NAME_LEN {
+            return Err(Error::InvalidArgument(format!(
+                "{} must be at most {} characters",
+                field, MAX_NAME_LEN
+            )));
+        }
+        if value.chars().any(char::is_control) {
+            return Err(Error::InvalidArgument(format!(
+                "{} must not contain control characters",
+                field
+            )));
+        }
+
+        Ok(())
+    }
+
+    // the mutation already happened, a lost audit entry must not fail it
+    async fn audit(&self, action: AuditAction, target: i32, caller: Option<&Principal>) {
+        if let Err(e) = self
+            .audit
+            .record(AuditEntry::new(action, target, caller))
+            .await
+        {
+            error!("failed to record audit entry: {:?}", e);
+        }
+    }
+
+    // queued, so the call never waits for nor fails with the channel
+    fn notify(&self, kind: NotificationKind, user: &User) {
+        if let Some(notifications) = &self.notifications {
+            notifications.push(Notification {
+                kind,
+                user: user.clone(),
+            });
+        }
+    }
+
+    fn revisions(&self) -> Result<&dyn RevisionRepository, Error> {
+        self.revisions.as_deref().ok_or_else(|| {
+            Error::InvalidArgument("user revisions need DATABASE_BACKEND=postgres".to_string())
+        })
+    }
+
+    // authorize, and keep suspended users from changing their own record
+    async fn authorize_write(&self, caller: Option<&Principal>, id: i32) -> Result<(), Error> {
+        authorize(caller, id)?;
+        match caller {
+            Some(p) if !p.is_admin() => match self.repo.get_user_status(id).await? {
+                Some(UserStatus::Suspended) => Err(Error::PermissionDenied),
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    async fn transition(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+        to: UserStatus,
+    ) -> Result<(), Error> {
+        self.write_protection.check()?;
+        authorize_admin(caller)?;
+        let from = self
+            .repo
+            .get_user_status(id)
+            .await?
+            .ok_or(Error::NotFound)?;
+        if from == to {
+            return Err(Error::FailedPrecondition(format!(
+                "user is already {}",
+                to.as_str()
+            )));
+        }
+        // false when another transition got there first
+        if !self.repo.set_user_status(id, from, to).await? {
+            return Err(Error::FailedPrecondition(
+                "user status changed concurrently".to_string(),
+            ));
+        }
+
+        let action = match to {
+            UserStatus::Active => AuditAction::ActivateUser,
+            UserStatus::Suspended => AuditAction::SuspendUser,
+        };
+        self.audit(action, id, caller).await;
+        // the listings change, and the response cache with them
+        if let Some(user) = self.repo.get_user_by_id(id).await? {
+            if to == UserStatus::Suspended {
+                self.notify(NotificationKind::UserSuspended, &user);
+            }
+            self.feed.publish(UserEventKind::Updated, user);
+        }
+        Ok(())
     }
 }
 
@@ -27,10 +486,48 @@ impl<T: UserRepository + Clone> UserUsecase<T> {
 impl<T: UserRepository + Clone + 'static> UserUsecaseTrait for UserUsecase<T> {
     async fn create_user(
         &self,
+        caller: Option<&Principal>,
         name: String,
         surname: String,
     ) -> Result<CreateUserResponse, crate::Error> {
+        self.create_user_with_consents(caller, name, surname, Vec::new())
+            .await
+    }
+
+    async fn create_user_with_consents(
+        &self,
+        caller: Option<&Principal>,
+        name: String,
+        surname: String,
+        consents: Vec<NewConsent>,
+    ) -> Result<CreateUserResponse, crate::Error> {
+        self.write_protection.check()?;
+        self.validate_name("name", &name)?;
+        self.validate_name("surname", &surname)?;
+        consents.iter().try_for_each(validate_consent)?;
+        if let Some(missing) = self
+            .required_consents
+            .iter()
+            .find(|required| !consents.iter().any(|c| required.is_met_by(c)))
+        {
+            return Err(Error::InvalidArgument(match &missing.version {
+                Some(version) => format!(
+                    "consent {:?} version {:?} is required",
+                    missing.consent_type, version
+                ),
+                None => format!("consent {:?} is required", missing.consent_type),
+            }));
+        }
+
         let res = self.repo.create_user(name, surname).await?;
+        self.audit(AuditAction::CreateUser, res.id, caller).await;
+        self.feed.publish(UserEventKind::Created, res.clone());
+        self.notify(NotificationKind::UserCreated, &res);
+        // the user exists by now, a failure here is reported so the consents
+        // can be recorded again through RecordConsent
+        for consent in consents {
+            self.consents.record(res.id, consent).await?;
+        }
         Ok(CreateUserResponse {
             user: Some(crate::grpc::User {
                 id: res.id,
@@ -40,31 +537,110 @@ impl<T: UserRepository + Clone + 'static> UserUsecaseTrait for UserUsecase<T> {
         })
     }
 
-    async fn get_users(&self) -> Result<GetUsersResponse, crate::Error> {
-        let (res, count) = self.repo.get_users().await?;
+    async fn get_users(
+        &self,
+        caller: Option<&Principal>,
+        filter: String,
+        page_size: i32,
+        page_token: String,
+    ) -> Result<GetUsersResponse, crate::Error> {
+        self.get_users_page(caller, filter, page_offset(&page_token)?, page_size)
+            .await
+    }
 
-        Ok(GetUsersResponse {
-            users: res
-                .iter()
-                .map(|u| crate::grpc::User {
-                    id: u.id,
-                    name: u.name.clone(),
-                    surname: u.surname.clone(),
+    async fn get_users_page(
+        &self,
+        caller: Option<&Principal>,
+        filter: String,
+        offset: i32,
+        limit: i32,
+    ) -> Result<GetUsersResponse, crate::Error> {
+        authorize_admin(caller)?;
+        let limit = page_size(limit)?;
+        self.check_size(limit as usize, "page_size")?;
+        if offset < 0 {
+            return Err(Error::InvalidArgument("offset must be >= 0".to_string()));
+        }
+        let filter = Filter::parse(&filter, USER_FIELDS)?;
+        let res = self.repo.get_users_page(filter, offset, limit + 1).await?;
+
+        Ok(users_page(res, offset, limit))
+    }
+
+    async fn count_users(&self, exact: bool) -> Result<CountUsersResponse, crate::Error> {
+        let count = if exact {
+            self.repo.count_users().await?
+        } else {
+            let repo = self.repo.clone();
+            self.count_estimate
+                .get(|| async move { repo.count_users().await })
+                .await?
+        };
+
+        Ok(CountUsersResponse { count })
+    }
+
+    async fn get_user_stats(
+        &self,
+        period: StatsPeriod,
+        start: Option<SystemTime>,
+        end: Option<SystemTime>,
+    ) -> Result<GetUserStatsResponse, crate::Error> {
+        let Some(stats) = &self.stats else {
+            return Err(Error::InvalidArgument(
+                "user stats need DATABASE_BACKEND=postgres".to_string(),
+            ));
+        };
+        let end = end.unwrap_or_else(SystemTime::now);
+        let start = start.unwrap_or_else(|| {
+            end.checked_sub(period.max_len() * (DEFAULT_STATS_PERIODS - 1))
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        });
+        let span = end
+            .duration_since(start)
+            .map_err(|_| Error::InvalidArgument("start must not be after end".to_string()))?;
+        if span > period.max_len() * MAX_STATS_PERIODS {
+            return Err(Error::InvalidArgument(format!(
+                "at most {} periods can be requested",
+                MAX_STATS_PERIODS
+            )));
+        }
+
+        let stats = stats.user_stats(period, start, end).await?;
+
+        Ok(GetUserStatsResponse {
+            stats: stats
+                .into_iter()
+                .map(|s| crate::grpc::UserStats {
+                    period_start: Some(s.period_start.into()),
+                    created: s.created,
+                    deleted: s.deleted,
+                    active: s.active,
                 })
                 .collect(),
-            count,
         })
     }
 
-    async fn get_user_by_id(&self, id: i32) -> Result<GetUserByIdResponse, crate::Error> {
-        let res = self.repo.get_user_by_id(id).await?;
+    async fn get_user_by_id(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+    ) -> Result<GetUserByIdResponse, crate::Error> {
+        authorize(caller, id)?;
+        let (res, shared) = self
+            .reads
+            .run((id, session::read_after()), || self.repo.get_user_by_id(id))
+            .await?;
+        if shared {
+            registry().increment_counter("user_reads_deduplicated_total", &[], 1);
+        }
 
         if let Some(user) = res {
             Ok(GetUserByIdResponse {
                 user: Some(crate::grpc::User {
                     id: user.id,
-                    name: user.name.clone(),
-                    surname: user.surname.clone(),
+                    name: user.name,
+                    surname: user.surname,
                 }),
             })
         } else {
@@ -72,15 +648,67 @@ impl<T: UserRepository + Clone + 'static> UserUsecaseTrait for UserUsecase<T> {
         }
     }
 
-    async fn get_user_by_name(&self, name: String) -> Result<GetUserByNameResponse, crate::Error> {
+    async fn get_user_by_id_as_of(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+        as_of: SystemTime,
+    ) -> Result<GetUserByIdResponse, crate::Error> {
+        authorize(caller, id)?;
+        let user = self
+            .repo
+            .get_user_by_id_as_of(id, as_of)
+            .await?
+            .ok_or(crate::Error::NotFound)?;
+
+        Ok(GetUserByIdResponse {
+            user: Some(crate::grpc::User {
+                id: user.id,
+                name: user.name,
+                surname: user.surname,
+            }),
+        })
+    }
+
+    async fn get_users_as_of(
+        &self,
+        caller: Option<&Principal>,
+        filter: String,
+        as_of: SystemTime,
+        page_size: i32,
+        page_token: String,
+    ) -> Result<GetUsersResponse, crate::Error> {
+        authorize_admin(caller)?;
+        let (limit, offset) = (self::page_size(page_size)?, page_offset(&page_token)?);
+        self.check_size(limit as usize, "page_size")?;
+        let filter = Filter::parse(&filter, USER_FIELDS)?;
+        // rebuilt in memory as a whole, only the page is sent on
+        let users = self.repo.get_users_as_of(filter, as_of).await?;
+        self.check_size(users.len(), "as_of")?;
+        let users = users
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize + 1)
+            .map(User::into_grpc)
+            .collect();
+
+        Ok(users_page(users, offset, limit))
+    }
+
+    async fn get_user_by_name(
+        &self,
+        caller: Option<&Principal>,
+        name: String,
+    ) -> Result<GetUserByNameResponse, crate::Error> {
         let res = self.repo.get_user_by_name(name).await?;
 
         if let Some(user) = res {
+            authorize(caller, user.id)?;
             Ok(GetUserByNameResponse {
                 user: Some(crate::grpc::User {
                     id: user.id,
-                    name: user.name.clone(),
-                    surname: user.surname.clone(),
+                    name: user.name,
+                    surname: user.surname,
                 }),
             })
         } else {
@@ -88,15 +716,117 @@ impl<T: UserRepository + Clone + 'static> UserUsecaseTrait for UserUsecase<T> {
         }
     }
 
+    async fn batch_get_users_by_name(
+        &self,
+        caller: Option<&Principal>,
+        names: Vec<String>,
+    ) -> Result<BatchGetUsersByNameResponse, crate::Error> {
+        let mut unique = Vec::with_capacity(names.len());
+        for name in names {
+            if !unique.contains(&name) {
+                unique.push(name);
+            }
+        }
+        if unique.len() > MAX_BATCH_NAMES {
+            return Err(Error::InvalidArgument(format!(
+                "at most {} names can be looked up at once",
+                MAX_BATCH_NAMES
+            )));
+        }
+        if unique.is_empty() {
+            return Ok(BatchGetUsersByNameResponse::default());
+        }
+
+        let own = own_record(caller);
+        let mut users: HashMap<String, UserList> = HashMap::new();
+        for user in self.repo.get_users_by_names(unique.clone()).await? {
+            if own.is_some_and(|id| id != user.id) {
+                continue;
+            }
+            users
+                .entry(user.name.clone())
+                .or_default()
+                .users
+                .push(crate::grpc::User {
+                    id: user.id,
+                    name: user.name,
+                    surname: user.surname,
+                });
+        }
+        let unmatched_names = unique
+            .into_iter()
+            .filter(|name| !users.contains_key(name))
+            .collect();
+
+        Ok(BatchGetUsersByNameResponse {
+            users,
+            unmatched_names,
+        })
+    }
+
+    async fn list_users_by_name_prefix(
+        &self,
+        caller: Option<&Principal>,
+        prefix: String,
+        limit: i32,
+    ) -> Result<ListUsersByNamePrefixResponse, crate::Error> {
+        if prefix.is_empty() {
+            return Err(Error::InvalidArgument(
+                "prefix must not be empty".to_string(),
+            ));
+        }
+
+        let res = self
+            .repo
+            .get_users_by_name_prefix(prefix, prefix_limit(limit))
+            .await?;
+        let own = own_record(caller);
+
+        Ok(ListUsersByNamePrefixResponse {
+            users: res
+                .into_iter()
+                .filter(|u| own.is_none_or(|id| id == u.id))
+                .map(|u| crate::grpc::User {
+                    id: u.id,
+                    name: u.name,
+                    surname: u.surname,
+                })
+                .collect(),
+        })
+    }
+
+    async fn user_exists(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+    ) -> Result<UserExistsResponse, crate::Error> {
+        authorize(caller, id)?;
+        let exists = self.repo.user_exists(id).await?;
+
+        Ok(UserExistsResponse { exists })
+    }
+
     async fn update_user(
         &self,
+        caller: Option<&Principal>,
         id: i32,
         name: Option<String>,
         surname: Option<String>,
     ) -> Result<UpdateUserResponse, crate::Error> {
+        self.write_protection.check()?;
+        self.authorize_write(caller, id).await?;
+        if let Some(name) = &name {
+            self.validate_name("name", name)?;
+        }
+        if let Some(surname) = &surname {
+            self.validate_name("surname", surname)?;
+        }
+
         let res = self.repo.update_user(id, name, surname).await?;
 
         if let Some(u) = res {
+            self.audit(AuditAction::UpdateUser, id, caller).await;
+            self.feed.publish(UserEventKind::Updated, u.clone());
             Ok(UpdateUserResponse {
                 user: Some(crate::grpc::User {
                     id: u.id,
@@ -109,59 +839,708 @@ impl<T: UserRepository + Clone + 'static> UserUsecaseTrait for UserUsecase<T> {
         }
     }
 
-    async fn delete_user(&self, id: i32) -> Result<DeleteUserResponse, crate::Error> {
-        self.repo.delete_user(id).await?;
+    async fn delete_user(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+    ) -> Result<DeleteUserResponse, crate::Error> {
+        self.write_protection.check()?;
+        self.authorize_write(caller, id).await?;
+        if self.flags.is_enabled(Flag::SoftDelete, None) {
+            self.repo.soft_delete_user(id).await?;
+        } else {
+            self.repo.delete_user(id).await?;
+        }
+        self.audit(AuditAction::DeleteUser, id, caller).await;
+        self.feed.publish(
+            UserEventKind::Deleted,
+            User {
+                id,
+                ..User::default()
+            },
+        );
 
         Ok(DeleteUserResponse {})
     }
 
+    async fn erase_user(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+    ) -> Result<EraseUserResponse, crate::Error> {
+        self.write_protection.check()?;
+        authorize(caller, id)?;
+        self.repo
+            .erase_user(id, ERASED_NAME.to_string(), ERASED_SURNAME.to_string())
+            .await?;
+        self.audit(AuditAction::EraseUser, id, caller).await;
+
+        let erased = User {
+            id,
+            name: ERASED_NAME.to_string(),
+            surname: ERASED_SURNAME.to_string(),
+        };
+        self.feed.erase(&erased);
+        // deleted users are erased all the same, but have nothing to announce
+        if self.repo.user_exists(id).await? {
+            self.feed.publish(UserEventKind::Updated, erased);
+        }
+
+        Ok(EraseUserResponse {})
+    }
+
+    async fn restore_user(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+    ) -> Result<RestoreUserResponse, crate::Error> {
+        self.write_protection.check()?;
+        authorize(caller, id)?;
+        // the page starting right below the id holds the user if soft deleted
+        let deleted = self
+            .repo
+            .get_deleted_users(id.saturating_sub(1), 1)
+            .await?
+            .into_iter()
+            .find(|d| d.user.id == id);
+        let Some(deleted) = deleted else {
+            return Err(Error::FailedPrecondition(
+                if self.repo.user_exists(id).await? {
+                    "user is not deleted".to_string()
+                } else {
+                    "only soft deleted users can be restored".to_string()
+                },
+            ));
+        };
+        if deleted.user.name == ERASED_NAME && deleted.user.surname == ERASED_SURNAME {
+            return Err(Error::FailedPrecondition(
+                "erased users cannot be restored".to_string(),
+            ));
+        }
+
+        // None when restored concurrently
+        let user = self
+            .repo
+            .restore_user(id)
+            .await?
+            .ok_or_else(|| Error::FailedPrecondition("user is not deleted".to_string()))?;
+        self.audit(AuditAction::RestoreUser, id, caller).await;
+        self.feed.publish(UserEventKind::Created, user.clone());
+
+        Ok(RestoreUserResponse {
+            user: Some(crate::grpc::User {
+                id: user.id,
+                name: user.name,
+                surname: user.surname,
+            }),
+        })
+    }
+
+    async fn merge_users(
+        &self,
+        caller: Option<&Principal>,
+        primary_id: i32,
+        duplicate_id: i32,
+    ) -> Result<MergeUsersResponse, crate::Error> {
+        self.write_protection.check()?;
+        authorize_admin(caller)?;
+        if primary_id == duplicate_id {
+            return Err(Error::InvalidArgument(
+                "a user cannot be merged into itself".to_string(),
+            ));
+        }
+
+        // written with the merge, so there is no merge without its entries
+        let audit = [primary_id, duplicate_id]
+            .into_iter()
+            .map(|target| AuditEntry::new(AuditAction::MergeUsers, target, caller))
+            .collect();
+        let user = self
+            .repo
+            .merge_users(primary_id, duplicate_id, audit)
+            .await?;
+        self.feed.publish(
+            UserEventKind::Deleted,
+            User {
+                id: duplicate_id,
+                ..User::default()
+            },
+        );
+
+        Ok(MergeUsersResponse {
+            user: Some(crate::grpc::User {
+                id: user.id,
+                name: user.name,
+                surname: user.surname,
+            }),
+        })
+    }
+
+    async fn suspend_user(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+    ) -> Result<SuspendUserResponse, crate::Error> {
+        self.transition(caller, id, UserStatus::Suspended).await?;
+
+        Ok(SuspendUserResponse {
+            status: crate::grpc::UserStatus::Suspended as i32,
+        })
+    }
+
+    async fn activate_user(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+    ) -> Result<ActivateUserResponse, crate::Error> {
+        self.transition(caller, id, UserStatus::Active).await?;
+
+        Ok(ActivateUserResponse {
+            status: crate::grpc::UserStatus::Active as i32,
+        })
+    }
+
+    async fn list_deleted_users(
+        &self,
+        page_size: i32,
+        page_token: String,
+    ) -> Result<ListDeletedUsersResponse, crate::Error> {
+        let page_size = self::page_size(page_size)?;
+        // the token is the id of the last user on the previous page
+        let after_id = match page_token.as_str() {
+            "" => 0,
+            token => token
+                .parse()
+                .map_err(|_| Error::InvalidArgument("invalid page_token".to_string()))?,
+        };
+
+        // one extra user tells whether another page exists
+        let mut users = self.repo.get_deleted_users(after_id, page_size + 1).await?;
+        let next_page_token = if users.len() > page_size as usize {
+            users.truncate(page_size as usize);
+            users
+                .last()
+                .map(|d| d.user.id.to_string())
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        Ok(ListDeletedUsersResponse {
+            users: users
+                .into_iter()
+                .map(|d| crate::grpc::DeletedUser {
+                    user: Some(crate::grpc::User {
+                        id: d.user.id,
+                        name: d.user.name,
+                        surname: d.user.surname,
+                    }),
+                    deleted_at: Some(d.deleted_at.into()),
+                })
+                .collect(),
+            next_page_token,
+        })
+    }
+
+    async fn list_user_revisions(
+        &self,
+        caller: Option<&Principal>,
+        user_id: i32,
+    ) -> Result<ListUserRevisionsResponse, crate::Error> {
+        authorize(caller, user_id)?;
+        let revisions = self.revisions()?.list_revisions(user_id).await?;
+        if revisions.is_empty() {
+            return Err(Error::NotFound);
+        }
+
+        Ok(ListUserRevisionsResponse {
+            revisions: revisions.into_iter().map(into_grpc_revision).collect(),
+        })
+    }
+
+    async fn get_user_revision(
+        &self,
+        caller: Option<&Principal>,
+        user_id: i32,
+        revision: i64,
+    ) -> Result<GetUserRevisionResponse, crate::Error> {
+        authorize(caller, user_id)?;
+        let revision = self
+            .revisions()?
+            .get_revision(user_id, revision)
+            .await?
+            .ok_or(Error::NotFound)?;
+
+        Ok(GetUserRevisionResponse {
+            revision: Some(into_grpc_revision(revision)),
+        })
+    }
+
+    async fn record_consent(
+        &self,
+        caller: Option<&Principal>,
+        user_id: i32,
+        consent: NewConsent,
+    ) -> Result<RecordConsentResponse, crate::Error> {
+        self.write_protection.check()?;
+        self.authorize_write(caller, user_id).await?;
+        validate_consent(&consent)?;
+        if !self.repo.user_exists(user_id).await? {
+            return Err(Error::NotFound);
+        }
+
+        let consent = self.consents.record(user_id, consent).await?;
+        self.audit(AuditAction::RecordConsent, user_id, caller)
+            .await;
+        Ok(RecordConsentResponse {
+            consent: Some(into_grpc_consent(consent)),
+        })
+    }
+
+    // the consents of deleted users are still listed, they are the record
+    // of what those users agreed to
+    async fn get_consents(
+        &self,
+        caller: Option<&Principal>,
+        user_id: i32,
+    ) -> Result<GetConsentsResponse, crate::Error> {
+        authorize(caller, user_id)?;
+        let consents = self.consents.list_by_user(user_id).await?;
+
+        Ok(GetConsentsResponse {
+            consents: consents.into_iter().map(into_grpc_consent).collect(),
+        })
+    }
+
+    async fn export_user_data(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+    ) -> Result<Vec<u8>, crate::Error> {
+        authorize(caller, id)?;
+        let user = self.repo.get_user_by_id(id).await?.ok_or(Error::NotFound)?;
+        let addresses = match &self.addresses {
+            Some(addresses) => addresses.list_by_user(id).await?,
+            None => Vec::new(),
+        };
+
+        UserData {
+            user,
+            addresses,
+            consents: self.consents.list_by_user(id).await?,
+            audit_entries: self.audit.list_by_user(id).await?,
+            events: self.feed.retained(id),
+        }
+        .to_json(SystemTime::now())
+    }
+
     async fn send_users(
         &self,
+        caller: Option<&Principal>,
+        chunk_size: i32,
+        heartbeat: Option<Duration>,
         tx: Sender<Result<StreamUsersResponse, Status>>,
     ) -> Result<(), crate::Error> {
         const BATCH_SIZE: i32 = 100;
+        authorize_admin(caller)?;
+        self.accept_stream()?;
+        if chunk_size < 0 {
+            return Err(crate::Error::InvalidArgument(
+                "chunk_size must not be negative".to_string(),
+            ));
+        }
+        let chunk_size = chunk_size.min(MAX_STREAM_CHUNK);
+        // big chunks are read a chunk at a time
+        let page = chunk_size.max(BATCH_SIZE);
         let repo = self.repo.clone();
+        let shutdown = self.shutdown.clone();
 
-        tokio::spawn(async move {
-            let span = tracing::info_span!("streaming users");
+        let parent = tracing::Span::current();
+        self.shutdown.spawn(async move {
+            let span = tracing::info_span!(parent: &parent, "streaming users");
             let _guard = span.enter();
 
+            let mut stream = StreamGuard::open("StreamUsers");
+            let mut heartbeat = Heartbeat::new(heartbeat);
             let mut offset = 0;
+            let mut chunk = Vec::new();
+            let mut chunk_bytes = 0;
 
-            loop {
-                let batch = repo.get_users_batch(offset, BATCH_SIZE).await;
-
-                match batch {
-                    Ok(users) if users.is_empty() => break,
-                    Ok(users) => {
-                        for user in users {
-                            let res = StreamUsersResponse {
-                                user: Some(crate::grpc::User {
-                                    id: user.id,
-                                    name: user.name,
-                                    surname: user.surname,
-                                }),
+            let termination = 'stream: loop {
+                let batch = repo.get_users_batch(offset, page);
+                tokio::pin!(batch);
+                // a slow batch is the only quiet a stream of the table has
+                let batch = loop {
+                    tokio::select! {
+                        biased;
+                        _ = shutdown.triggered() => break 'stream Termination::Shutdown,
+                        batch = &mut batch => break batch,
+                        _ = heartbeat.tick() => {
+                            let beat = StreamUsersResponse {
+                                heartbeat: true,
+                                ..Default::default()
                             };
-
-                            if (tx.send(Ok(res))).await.is_err() {
-                                info!("client disconnected");
-                                break;
+                            // a full channel has the client busy enough
+                            if let Err(TrySendError::Closed(_)) = tx.try_send(Ok(beat)) {
+                                break 'stream Termination::ClientCancelled;
                             }
                         }
-                        offset += BATCH_SIZE;
                     }
+                };
+
+                let users = match batch {
+                    Ok(users) => users,
                     Err(e) => {
                         error!("error fetching users batch: {:?}", e);
-                        break;
+                        let _ = tx.try_send(Err(Status::internal("failed to fetch users")));
+                        break Termination::Error;
+                    }
+                };
+                let last = users.is_empty();
+
+                let mut messages = Vec::new();
+                for user in users {
+                    let user = crate::grpc::User {
+                        id: user.id,
+                        name: user.name,
+                        surname: user.surname,
+                    };
+                    if chunk_size == 0 {
+                        messages.push(StreamUsersResponse {
+                            user: Some(user),
+                            ..Default::default()
+                        });
+                        continue;
+                    }
+                    chunk_bytes += user.encoded_len();
+                    chunk.push(user);
+                    if chunk.len() >= chunk_size as usize || chunk_bytes >= MAX_STREAM_CHUNK_BYTES {
+                        chunk_bytes = 0;
+                        messages.push(StreamUsersResponse {
+                            users: std::mem::take(&mut chunk),
+                            ..Default::default()
+                        });
+                    }
+                }
+                if last && !chunk.is_empty() {
+                    messages.push(StreamUsersResponse {
+                        users: std::mem::take(&mut chunk),
+                        ..Default::default()
+                    });
+                }
+
+                for res in messages {
+                    let sent = tokio::select! {
+                        biased;
+                        _ = shutdown.triggered() => break 'stream Termination::Shutdown,
+                        sent = tx.send(Ok(res)) => sent,
+                    };
+                    if sent.is_err() {
+                        info!("client disconnected");
+                        break 'stream Termination::ClientCancelled;
+                    }
+                    stream.message_sent();
+                    heartbeat.reset();
+                }
+                if last {
+                    break Termination::Completed;
+                }
+                offset += page;
+            };
+
+            match termination {
+                Termination::Shutdown => {
+                    info!("server shutting down, closing stream");
+                    let _ = tx.try_send(Err(Status::unavailable("server is shutting down")));
+                }
+                Termination::Completed => info!("streaming complete"),
+                _ => {}
+            }
+            stream.finish(termination);
+        });
+
+        Ok(())
+    }
+
+    async fn send_export(
+        &self,
+        caller: Option<&Principal>,
+        format: ExportFormat,
+        tx: Sender<Result<ExportUsersResponse, Status>>,
+    ) -> Result<(), crate::Error> {
+        // one parquet row group per page
+        const PAGE_SIZE: i32 = 10_000;
+        authorize_admin(caller)?;
+        self.accept_stream()?;
+        let mut encoder = export::encoder(format)?;
+        let repo = self.repo.clone();
+        let shutdown = self.shutdown.clone();
+
+        let parent = tracing::Span::current();
+        self.shutdown.spawn(async move {
+            let span = tracing::info_span!(parent: &parent, "exporting users", ?format);
+            let _guard = span.enter();
+
+            let mut stream = StreamGuard::open("ExportUsers");
+            let mut offset = 0;
+
+            let termination = 'stream: loop {
+                let batch = tokio::select! {
+                    biased;
+                    _ = shutdown.triggered() => break 'stream Termination::Shutdown,
+                    batch = repo.get_users_batch(offset, PAGE_SIZE) => batch,
+                };
+                let last = matches!(&batch, Ok(users) if users.is_empty());
+                let data = match batch {
+                    Ok(_) if last => encoder.finish(),
+                    Ok(users) => encoder.page(&users),
+                    Err(e) => Err(e),
+                };
+                let data = match data {
+                    Ok(data) => data,
+                    Err(e) => {
+                        error!("error exporting users: {:?}", e);
+                        let _ = tx.try_send(Err(Status::internal("failed to export users")));
+                        break Termination::Error;
+                    }
+                };
+
+                let sent = tokio::select! {
+                    biased;
+                    _ = shutdown.triggered() => break 'stream Termination::Shutdown,
+                    sent = tx.send(Ok(ExportUsersResponse { data })) => sent,
+                };
+                if sent.is_err() {
+                    info!("client disconnected");
+                    break Termination::ClientCancelled;
+                }
+                stream.message_sent();
+                if last {
+                    break Termination::Completed;
+                }
+                offset += PAGE_SIZE;
+            };
+
+            match termination {
+                Termination::Shutdown => {
+                    info!("server shutting down, closing export");
+                    let _ = tx.try_send(Err(Status::unavailable("server is shutting down")));
+                }
+                Termination::Completed => info!("export complete"),
+                _ => {}
+            }
+            stream.finish(termination);
+        });
+
+        Ok(())
+    }
+
+    async fn send_user_events(
+        &self,
+        caller: Option<&Principal>,
+        after_sequence: u64,
+        resume_token: u64,
+        heartbeat: Option<Duration>,
+        tx: Sender<Result<crate::grpc::UserEvent, Status>>,
+    ) -> Result<(), crate::Error> {
+        authorize_admin(caller)?;
+        self.accept_stream()?;
+        let mut replayed = HashSet::new();
+        let (backlog, mut events) = match (after_sequence, resume_token) {
+            (0, 0) => (Vec::new(), self.feed.subscribe()),
+            (after, 0) => self.feed.resume(after)?,
+            (0, token) => {
+                let (stored, events) = self.feed.replay(token, MAX_REPLAYED_EVENTS).await?;
+                replayed.extend(stored.iter().map(|e| e.token));
+                (self.replayed_events(stored).await?, events)
+            }
+            _ => {
+                return Err(crate::Error::InvalidArgument(
+                    "after_sequence and resume_token cannot both be set".to_string(),
+                ));
+            }
+        };
+        let shutdown = self.shutdown.clone();
+
+        let parent = tracing::Span::current();
+        self.shutdown.spawn(async move {
+            let span = tracing::info_span!(parent: &parent, "watching users");
+            let _guard = span.enter();
+
+            let mut stream = StreamGuard::open("WatchUsers");
+            let mut heartbeat = Heartbeat::new(heartbeat);
+            let mut backlog = backlog.into_iter();
+            let mut last_token = 0;
+
+            let termination = loop {
+                let event = match backlog.next() {
+                    Some(event) => Ok(event),
+                    None => tokio::select! {
+                        biased;
+                        _ = shutdown.triggered() => break Termination::Shutdown,
+                        _ = tx.closed() => break Termination::ClientCancelled,
+                        event = events.recv() => match event {
+                            Ok(event) if replayed.contains(&event.token) => continue,
+                            event => event,
+                        },
+                        _ = heartbeat.tick() => {
+                            let beat = crate::grpc::UserEvent {
+                                resume_token: last_token,
+                                heartbeat: true,
+                                ..Default::default()
+                            };
+                            // a full channel has the client busy enough
+                            if let Err(TrySendError::Closed(_)) = tx.try_send(Ok(beat)) {
+                                break Termination::ClientCancelled;
+                            }
+                            continue;
+                        }
+                    },
+                };
+                let event = match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(missed)) => {
+                        error!("watcher fell {} events behind, closing stream", missed);
+                        let _ = tx.try_send(Err(Status::aborted(format!(
+                            "fell behind the change feed, {} events were dropped",
+                            missed
+                        ))));
+                        break Termination::Error;
                     }
+                    Err(RecvError::Closed) => break Termination::Completed,
+                };
+                if event.token > 0 {
+                    last_token = event.token;
+                }
+
+                let sent = tokio::select! {
+                    biased;
+                    _ = shutdown.triggered() => break Termination::Shutdown,
+                    sent = tx.send(Ok(into_grpc_event(event))) => sent,
+                };
+                if sent.is_err() {
+                    info!("client disconnected");
+                    break Termination::ClientCancelled;
                 }
+                stream.message_sent();
+                heartbeat.reset();
+            };
+
+            if let Termination::Shutdown = termination {
+                info!("server shutting down, closing stream");
+                let _ = tx.try_send(Err(Status::unavailable("server is shutting down")));
             }
+            stream.finish(termination);
+        });
+
+        Ok(())
+    }
+
+    async fn send_autocomplete(
+        &self,
+        caller: Option<&Principal>,
+        mut requests: AutocompleteRequests,
+        tx: Sender<Result<AutocompleteUsersResponse, Status>>,
+    ) -> Result<(), crate::Error> {
+        self.accept_stream()?;
+        let own = own_record(caller);
+        let repo = self.repo.clone();
+        let shutdown = self.shutdown.clone();
+
+        let parent = tracing::Span::current();
+        self.shutdown.spawn(async move {
+            let span = tracing::info_span!(parent: &parent, "autocompleting users");
+            let _guard = span.enter();
+
+            let mut stream = StreamGuard::open("AutocompleteUsers");
+
+            let termination = loop {
+                let next = tokio::select! {
+                    biased;
+                    _ = shutdown.triggered() => break Termination::Shutdown,
+                    next = requests.next() => next,
+                };
+                let req = match next {
+                    Some(Ok(req)) => req,
+                    Some(Err(status)) => {
+                        info!("client stream failed: {}", status);
+                        break Termination::ClientCancelled;
+                    }
+                    None => break Termination::Completed,
+                };
+
+                // an emptied input box clears the suggestions
+                let users = if req.prefix.is_empty() {
+                    Ok(Vec::new())
+                } else {
+                    repo.get_users_by_name_prefix(req.prefix.clone(), prefix_limit(req.limit))
+                        .await
+                };
+                let users = match users {
+                    Ok(users) => users,
+                    Err(e) => {
+                        error!("error fetching users by prefix: {:?}", e);
+                        let _ = tx.try_send(Err(Status::internal("failed to fetch users")));
+                        break Termination::Error;
+                    }
+                };
+
+                let res = AutocompleteUsersResponse {
+                    prefix: req.prefix,
+                    users: users
+                        .into_iter()
+                        .filter(|u| own.is_none_or(|id| id == u.id))
+                        .map(|u| crate::grpc::User {
+                            id: u.id,
+                            name: u.name,
+                            surname: u.surname,
+                        })
+                        .collect(),
+                };
+                if tx.send(Ok(res)).await.is_err() {
+                    info!("client disconnected");
+                    break Termination::ClientCancelled;
+                }
+                stream.message_sent();
+            };
 
-            info!("streaming complete");
+            if termination == Termination::Shutdown {
+                info!("server shutting down, closing stream");
+                let _ = tx.try_send(Err(Status::unavailable("server is shutting down")));
+            }
+            stream.finish(termination);
         });
 
         Ok(())
     }
+
+    async fn import_users(
+        &self,
+        caller: Option<&Principal>,
+        requests: ImportRequests,
+    ) -> Result<ImportUsersResponse, crate::Error> {
+        self.write_protection.check()?;
+        authorize_admin(caller)?;
+        self.accept_stream()?;
+        let Some(imports) = &self.imports else {
+            return Err(Error::InvalidArgument(
+                "imports need DATABASE_BACKEND=postgres without PII_MASTER_KEY".to_string(),
+            ));
+        };
+
+        let data = Box::pin(requests.map(|req| {
+            req.map(|req| req.data).map_err(|status| {
+                Error::InvalidArgument(format!("the import stream failed: {}", status.message()))
+            })
+        }));
+        let strict = self.flags.is_enabled(Flag::StrictValidation, None);
+        let imported = imports.import_users(data, strict).await?;
+        info!("imported {} users", imported);
+
+        Ok(ImportUsersResponse {
+            imported: imported as i64,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -177,74 +1556,458 @@ mod tests {
         impl crate::repositories::user_repository_trait::UserRepository for Repo {
             async fn create_user(&self, name: String, surname: String) -> Result<User, crate::Error>;
             async fn get_users(&self) -> Result<(Vec<User>, i32), crate::Error>;
+            async fn count_users(&self) -> Result<i64, crate::Error>;
             async fn get_users_batch(&self, offset: i32, limit: i32) -> Result<Vec<User>, crate::Error>;
+            async fn get_users_filtered(&self, filter: crate::filter::Filter, offset: i32, limit: i32) -> Result<Vec<User>, crate::Error>;
             async fn get_user_by_id(&self, id: i32) -> Result<Option<User>, crate::Error>;
             async fn get_user_by_name(&self, name: String) -> Result<Option<User>, crate::Error>;
+            async fn get_users_by_names(&self, names: Vec<String>) -> Result<Vec<User>, crate::Error>;
+            async fn get_users_by_name_prefix(&self, prefix: String, limit: i32) -> Result<Vec<User>, crate::Error>;
+            async fn user_exists(&self, id: i32) -> Result<bool, crate::Error>;
             async fn update_user(&self, id: i32, name: Option<String>, surname: Option<String>) -> Result<Option<User>, crate::Error>;
             async fn delete_user(&self, id: i32) -> Result<(), crate::Error>;
+            async fn soft_delete_user(&self, id: i32) -> Result<(), crate::Error>;
+            async fn restore_user(&self, id: i32) -> Result<Option<User>, crate::Error>;
+            async fn get_deleted_users(&self, after_id: i32, limit: i32) -> Result<Vec<crate::entities::users::DeletedUser>, crate::Error>;
+            async fn merge_users(&self, primary_id: i32, duplicate_id: i32, audit: Vec<crate::entities::audit::AuditEntry>) -> Result<User, crate::Error>;
+            async fn get_user_status(&self, id: i32) -> Result<Option<UserStatus>, crate::Error>;
+            async fn set_user_status(&self, id: i32, from: UserStatus, to: UserStatus) -> Result<bool, crate::Error>;
+            async fn erase_user(&self, id: i32, name: String, surname: String) -> Result<(), crate::Error>;
+            async fn get_user_by_id_as_of(&self, id: i32, as_of: SystemTime) -> Result<Option<User>, crate::Error>;
+            async fn get_users_as_of(&self, filter: Option<crate::filter::Filter>, as_of: SystemTime) -> Result<Vec<User>, crate::Error>;
+        }
+    }
+
+    mockall::mock! {
+        Audit {}
+
+        #[async_trait::async_trait]
+        impl crate::repositories::audit_repository_trait::AuditRepository for Audit {
+            async fn record(&self, entry: AuditEntry) -> Result<(), crate::Error>;
+            async fn list_by_user(&self, user_id: i32) -> Result<Vec<crate::entities::audit::AuditRecord>, crate::Error>;
+            async fn list(&self, filter: crate::entities::audit::AuditFilter, after_id: i64, limit: i64) -> Result<Vec<crate::entities::audit::AuditRecord>, crate::Error>;
+        }
+    }
+
+    mockall::mock! {
+        Stats {}
+
+        #[async_trait::async_trait]
+        impl crate::repositories::stats_repository_trait::StatsRepository for Stats {
+            async fn user_stats(&self, period: StatsPeriod, start: SystemTime, end: SystemTime) -> Result<Vec<crate::entities::stats::UserStats>, crate::Error>;
+        }
+    }
+
+    impl Clone for MockRepo {
+        fn clone(&self) -> Self {
+            Self::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_user() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo
+            .expect_create_user()
+            .with(eq("John".to_string()), eq("Doe".to_string()))
+            .times(1)
+            .returning(|name, surname| {
+                Ok(User {
+                    id: 1,
+                    name,
+                    surname,
+                })
+            });
+
+        let usecase = UserUsecase::new(mock_repo);
+        let result = usecase
+            .create_user(None, "John".to_string(), "Doe".to_string())
+            .await;
+
+        assert!(result.is_ok());
+        let response = result.unwrap();
+        assert!(response.user.is_some());
+        assert_eq!(response.user.unwrap().id, 1);
+    }
+
+    #[tokio::test]
+    async fn test_mutations_reach_watchers() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo.expect_create_user().returning(|name, surname| {
+            Ok(User {
+                id: 1,
+                name,
+                surname,
+            })
+        });
+        mock_repo.expect_delete_user().returning(|_| Ok(()));
+
+        let usecase = UserUsecase::new(mock_repo);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(4);
+        usecase
+            .send_user_events(None, 0, 0, None, tx)
+            .await
+            .unwrap();
+        usecase
+            .create_user(None, "John".to_string(), "Doe".to_string())
+            .await
+            .unwrap();
+        usecase.delete_user(None, 1).await.unwrap();
+
+        let created = rx.recv().await.unwrap().unwrap();
+        let deleted = rx.recv().await.unwrap().unwrap();
+        assert_eq!(created.kind(), crate::grpc::UserEventKind::Created);
+        assert_eq!(created.user.unwrap().name, "John");
+        assert_eq!(deleted.kind(), crate::grpc::UserEventKind::Deleted);
+        assert_eq!(deleted.sequence, created.sequence + 1);
+    }
+
+    #[tokio::test]
+    async fn test_write_protection_rejects_mutations() {
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        let protection = WriteProtection::default();
+        let usecase = UserUsecase::new(repo).with_write_protection(protection.clone());
+        let ann = usecase
+            .create_user(None, "Ann".to_string(), "Lee".to_string())
+            .await
+            .unwrap()
+            .user
+            .unwrap();
+
+        protection.set(true);
+        assert!(matches!(
+            usecase
+                .create_user(None, "Bob".to_string(), "Lee".to_string())
+                .await,
+            Err(Error::FailedPrecondition(_))
+        ));
+        assert!(matches!(
+            usecase.delete_user(None, ann.id).await,
+            Err(Error::FailedPrecondition(_))
+        ));
+        assert!(matches!(
+            usecase.suspend_user(None, ann.id).await,
+            Err(Error::FailedPrecondition(_))
+        ));
+        // reads go on
+        assert!(
+            usecase
+                .get_user_by_id(None, ann.id)
+                .await
+                .unwrap()
+                .user
+                .is_some()
+        );
+
+        protection.set(false);
+        assert!(usecase.delete_user(None, ann.id).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_quiet_watchers_get_heartbeats() {
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        let usecase = UserUsecase::new(repo);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(4);
+        let every = Duration::from_millis(20);
+        usecase
+            .send_user_events(None, 0, 0, Some(every), tx)
+            .await
+            .unwrap();
+
+        let beat = rx.recv().await.unwrap().unwrap();
+        assert!(beat.heartbeat && beat.user.is_none());
+        usecase
+            .create_user(None, "Ann".to_string(), "Lee".to_string())
+            .await
+            .unwrap();
+        let created = rx.recv().await.unwrap().unwrap();
+        assert!(!created.heartbeat);
+        assert!(rx.recv().await.unwrap().unwrap().heartbeat);
+    }
+
+    #[tokio::test]
+    async fn test_get_users() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo.expect_get_users().times(0);
+        mock_repo
+            .expect_get_users_batch()
+            .with(eq(0), eq(51))
+            .times(1)
+            .returning(|_, _| {
+                Ok(vec![
+                    User {
+                        id: 1,
+                        name: "John".to_string(),
+                        surname: "Doe".to_string(),
+                    },
+                    User {
+                        id: 2,
+                        name: "Jane".to_string(),
+                        surname: "Smith".to_string(),
+                    },
+                ])
+            });
+
+        let usecase = UserUsecase::new(mock_repo);
+        let result = usecase
+            .get_users(None, String::new(), 0, String::new())
+            .await;
+
+        assert!(result.is_ok());
+        let response = result.unwrap();
+        assert_eq!(response.users.len(), 2);
+        assert_eq!(response.count, 2);
+        assert!(response.next_page_token.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_users_pages() {
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        let usecase = UserUsecase::new(repo);
+        for n in 0..3 {
+            usecase
+                .create_user(None, format!("User{}", n), "Paged".to_string())
+                .await
+                .unwrap();
+        }
+
+        let first = usecase
+            .get_users(None, String::new(), 2, String::new())
+            .await
+            .unwrap();
+        assert_eq!(first.count, 2);
+        let rest = usecase
+            .get_users(None, String::new(), 2, first.next_page_token)
+            .await
+            .unwrap();
+        assert_eq!(rest.users[0].name, "User2");
+        assert!(rest.next_page_token.is_empty());
+
+        for (page_size, page_token) in [(1001, ""), (-1, ""), (0, "-2"), (0, "next")] {
+            assert!(matches!(
+                usecase
+                    .get_users(None, String::new(), page_size, page_token.to_string())
+                    .await,
+                Err(Error::InvalidArgument(_))
+            ));
         }
     }
 
-    impl Clone for MockRepo {
-        fn clone(&self) -> Self {
-            Self::default()
+    #[tokio::test]
+    async fn test_get_users_max_rows() {
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        let usecase = UserUsecase::new(repo).with_get_users_max_rows(2);
+        for n in 0..3 {
+            usecase
+                .create_user(None, format!("User{}", n), "Big".to_string())
+                .await
+                .unwrap();
+        }
+
+        // below MAX_PAGE_SIZE the configured limit caps the page size
+        let page = usecase
+            .get_users(None, String::new(), 2, String::new())
+            .await
+            .unwrap();
+        assert_eq!(page.count, 2);
+        let oversized = usecase
+            .get_users(None, String::new(), 3, String::new())
+            .await;
+        assert!(
+            matches!(oversized, Err(Error::FailedPrecondition(msg)) if msg.contains("StreamUsers"))
+        );
+        let oversized = usecase
+            .get_users_as_of(None, String::new(), SystemTime::now(), 3, String::new())
+            .await;
+        assert!(matches!(oversized, Err(Error::FailedPrecondition(_))));
+
+        // the whole table is rebuilt for an as_of page, however small
+        let as_of = usecase
+            .get_users_as_of(None, String::new(), SystemTime::now(), 1, String::new())
+            .await;
+        assert!(matches!(as_of, Err(Error::FailedPrecondition(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_users_filtered() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo.expect_get_users().times(0);
+        mock_repo
+            .expect_get_users_filtered()
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(vec![User {
+                    id: 1,
+                    name: "Ann".to_string(),
+                    surname: "Lee".to_string(),
+                }])
+            });
+
+        let usecase = UserUsecase::new(mock_repo);
+        let result = usecase
+            .get_users(None, r#"name = "Ann""#.to_string(), 0, String::new())
+            .await;
+        let invalid = usecase
+            .get_users(None, "password = 1".to_string(), 0, String::new())
+            .await;
+
+        assert_eq!(result.unwrap().count, 1);
+        assert!(matches!(invalid, Err(crate::Error::InvalidArgument(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_users_page() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo
+            .expect_get_users_batch()
+            .with(eq(20), eq(11))
+            .times(1)
+            .returning(|_, _| {
+                Ok(vec![User {
+                    id: 21,
+                    name: "Paged".to_string(),
+                    surname: "User".to_string(),
+                }])
+            });
+
+        let usecase = UserUsecase::new(mock_repo);
+        let result = usecase.get_users_page(None, String::new(), 20, 10).await;
+
+        assert!(result.is_ok());
+        let response = result.unwrap();
+        assert_eq!(response.count, 1);
+        assert_eq!(response.users[0].id, 21);
+    }
+
+    #[tokio::test]
+    async fn test_count_users() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo.expect_count_users().times(1).returning(|| Ok(42));
+
+        let usecase = UserUsecase::new(mock_repo);
+        let result = usecase.count_users(true).await;
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().count, 42);
+    }
+
+    #[tokio::test]
+    async fn test_get_user_stats() {
+        let end = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+        let mut mock_stats = MockStats::new();
+        mock_stats
+            .expect_user_stats()
+            .withf(move |period, start, e| {
+                *period == StatsPeriod::Week
+                    && *e == end
+                    && end.duration_since(*start).unwrap() == StatsPeriod::Week.max_len() * 29
+            })
+            .times(1)
+            .returning(move |_, start, _| {
+                Ok(vec![crate::entities::stats::UserStats {
+                    period_start: start,
+                    created: 3,
+                    deleted: 1,
+                    active: 2,
+                }])
+            });
+
+        let usecase = UserUsecase::new(MockRepo::new()).with_stats(Arc::new(mock_stats));
+        let res = usecase
+            .get_user_stats(StatsPeriod::Week, None, Some(end))
+            .await
+            .unwrap();
+        assert_eq!(res.stats.len(), 1);
+        assert_eq!((res.stats[0].created, res.stats[0].active), (3, 2));
+
+        let reversed = usecase
+            .get_user_stats(StatsPeriod::Day, Some(end), Some(SystemTime::UNIX_EPOCH))
+            .await;
+        assert!(matches!(reversed, Err(Error::InvalidArgument(_))));
+        let too_long = usecase
+            .get_user_stats(StatsPeriod::Day, Some(SystemTime::UNIX_EPOCH), Some(end))
+            .await;
+        assert!(matches!(too_long, Err(Error::InvalidArgument(_))));
+
+        let without_stats = UserUsecase::new(MockRepo::new())
+            .get_user_stats(StatsPeriod::Day, None, None)
+            .await;
+        assert!(matches!(without_stats, Err(Error::InvalidArgument(_))));
+    }
+
+    // counts the bytes it is sent
+    struct CountingImports;
+
+    #[async_trait::async_trait]
+    impl ImportRepository for CountingImports {
+        async fn import_users(
+            &self,
+            mut data: crate::repositories::import_repository_trait::ImportData,
+            _strict: bool,
+        ) -> Result<u64, crate::Error> {
+            let mut len = 0;
+            while let Some(piece) = data.next().await {
+                len += piece?.len() as u64;
+            }
+            Ok(len)
         }
     }
 
     #[tokio::test]
-    async fn test_create_user() {
-        let mut mock_repo = MockRepo::new();
-        mock_repo
-            .expect_create_user()
-            .with(eq("John".to_string()), eq("Doe".to_string()))
-            .times(1)
-            .returning(|name, surname| {
-                Ok(User {
-                    id: 1,
-                    name,
-                    surname,
+    async fn test_import_users() {
+        let requests = |pieces: Vec<Result<&str, Status>>| -> ImportRequests {
+            let requests: Vec<_> = pieces
+                .into_iter()
+                .map(|p| {
+                    p.map(|data| crate::grpc::ImportUsersRequest {
+                        data: data.as_bytes().to_vec(),
+                    })
                 })
-            });
+                .collect();
+            Box::pin(tokio_stream::iter(requests))
+        };
+        let usecase = UserUsecase::new(MockRepo::new()).with_imports(Arc::new(CountingImports));
 
-        let usecase = UserUsecase::new(mock_repo);
-        let result = usecase
-            .create_user("John".to_string(), "Doe".to_string())
+        let res = usecase
+            .import_users(None, requests(vec![Ok("name,"), Ok("surname\n")]))
+            .await
+            .unwrap();
+        assert_eq!(res.imported, 13);
+
+        let failed = usecase
+            .import_users(
+                None,
+                requests(vec![Ok("name"), Err(Status::cancelled("gone"))]),
+            )
             .await;
+        assert!(matches!(failed, Err(Error::InvalidArgument(_))));
 
-        assert!(result.is_ok());
-        let response = result.unwrap();
-        assert!(response.user.is_some());
-        assert_eq!(response.user.unwrap().id, 1);
+        let user = principal(9, &[]);
+        let denied = usecase
+            .import_users(Some(&user), requests(Vec::new()))
+            .await;
+        assert!(matches!(denied, Err(Error::PermissionDenied)));
+
+        let without_imports = UserUsecase::new(MockRepo::new())
+            .import_users(None, requests(Vec::new()))
+            .await;
+        assert!(matches!(without_imports, Err(Error::InvalidArgument(_))));
     }
 
     #[tokio::test]
-    async fn test_get_users() {
+    async fn test_user_exists() {
         let mut mock_repo = MockRepo::new();
-        mock_repo.expect_get_users().times(1).returning(|| {
-            Ok((
-                vec![
-                    User {
-                        id: 1,
-                        name: "John".to_string(),
-                        surname: "Doe".to_string(),
-                    },
-                    User {
-                        id: 2,
-                        name: "Jane".to_string(),
-                        surname: "Smith".to_string(),
-                    },
-                ],
-                2,
-            ))
-        });
+        mock_repo
+            .expect_user_exists()
+            .with(eq(1))
+            .times(1)
+            .returning(|_| Ok(true));
 
         let usecase = UserUsecase::new(mock_repo);
-        let result = usecase.get_users().await;
+        let result = usecase.user_exists(None, 1).await;
 
         assert!(result.is_ok());
-        let response = result.unwrap();
-        assert_eq!(response.users.len(), 2);
-        assert_eq!(response.count, 2);
+        assert!(result.unwrap().exists);
     }
 
     #[tokio::test]
@@ -263,7 +2026,7 @@ mod tests {
             });
 
         let usecase = UserUsecase::new(mock_repo);
-        let result = usecase.get_user_by_id(1).await;
+        let result = usecase.get_user_by_id(None, 1).await;
 
         assert!(result.is_ok());
         let response = result.unwrap();
@@ -281,7 +2044,7 @@ mod tests {
             .returning(|_| Ok(None));
 
         let usecase = UserUsecase::new(mock_repo);
-        let result = usecase.get_user_by_id(999).await;
+        let result = usecase.get_user_by_id(None, 999).await;
 
         assert!(result.is_err());
         assert!(matches!(result.unwrap_err(), crate::Error::NotFound));
@@ -303,7 +2066,7 @@ mod tests {
             });
 
         let usecase = UserUsecase::new(mock_repo);
-        let result = usecase.get_user_by_name("John".to_string()).await;
+        let result = usecase.get_user_by_name(None, "John".to_string()).await;
 
         assert!(result.is_ok());
         let response = result.unwrap();
@@ -321,7 +2084,7 @@ mod tests {
             .returning(|_| Ok(None));
 
         let usecase = UserUsecase::new(mock_repo);
-        let result = usecase.get_user_by_name("Unknown".to_string()).await;
+        let result = usecase.get_user_by_name(None, "Unknown".to_string()).await;
 
         assert!(result.is_err());
         assert!(matches!(result.unwrap_err(), crate::Error::NotFound));
@@ -344,7 +2107,7 @@ mod tests {
 
         let usecase = UserUsecase::new(mock_repo);
         let result = usecase
-            .update_user(1, Some("Updated".to_string()), None)
+            .update_user(None, 1, Some("Updated".to_string()), None)
             .await;
 
         assert!(result.is_ok());
@@ -353,6 +2116,26 @@ mod tests {
         assert_eq!(response.user.unwrap().name, "Updated");
     }
 
+    #[tokio::test]
+    async fn test_update_user_clears_field_with_empty_string() {
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        let created = repo
+            .create_user("John".to_string(), "Doe".to_string())
+            .await
+            .unwrap();
+        let usecase = UserUsecase::new(repo);
+
+        let cleared = usecase
+            .update_user(None, created.id, None, Some(String::new()))
+            .await
+            .unwrap()
+            .user
+            .unwrap();
+
+        assert_eq!(cleared.name, "John");
+        assert_eq!(cleared.surname, "");
+    }
+
     #[tokio::test]
     async fn test_update_user_not_found() {
         let mut mock_repo = MockRepo::new();
@@ -363,7 +2146,9 @@ mod tests {
             .returning(|_, _, _| Ok(None));
 
         let usecase = UserUsecase::new(mock_repo);
-        let result = usecase.update_user(999, Some("No".to_string()), None).await;
+        let result = usecase
+            .update_user(None, 999, Some("No".to_string()), None)
+            .await;
 
         assert!(result.is_err());
         assert!(matches!(result.unwrap_err(), crate::Error::NotFound));
@@ -379,8 +2164,861 @@ mod tests {
             .returning(|_| Ok(()));
 
         let usecase = UserUsecase::new(mock_repo);
-        let result = usecase.delete_user(1).await;
+        let result = usecase.delete_user(None, 1).await;
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_delete_user_soft_delete_flag() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo.expect_delete_user().times(0);
+        mock_repo
+            .expect_soft_delete_user()
+            .with(eq(1))
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let flags = EnvFeatureFlags::new("soft_delete", None).unwrap();
+        let usecase = UserUsecase::new(mock_repo).with_feature_flags(Arc::new(flags));
+        let result = usecase.delete_user(None, 1).await;
 
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_create_user_strict_validation() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo.expect_create_user().times(0);
+
+        let flags = EnvFeatureFlags::new("strict_validation", None).unwrap();
+        let usecase = UserUsecase::new(mock_repo).with_feature_flags(Arc::new(flags));
+        let result = usecase
+            .create_user(None, "   ".to_string(), "Doe".to_string())
+            .await;
+
+        assert!(matches!(
+            result.unwrap_err(),
+            crate::Error::InvalidArgument(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_send_users_stops_on_shutdown() {
+        let shutdown = Shutdown::new();
+        shutdown.trigger();
+
+        let usecase = UserUsecase::new(MockRepo::new()).with_shutdown(shutdown.clone());
+        let (tx, mut rx) = tokio::sync::mpsc::channel(4);
+        usecase.send_users(None, 0, None, tx).await.unwrap();
+
+        let terminal = rx.recv().await.unwrap();
+        assert_eq!(terminal.unwrap_err().code(), tonic::Code::Unavailable);
+        assert!(rx.recv().await.is_none());
+        assert!(shutdown.drain(std::time::Duration::from_secs(1)).await);
+    }
+
+    #[tokio::test]
+    async fn test_streams_refused_in_lame_duck() {
+        let shutdown = Shutdown::new();
+        let usecase = UserUsecase::new(MockRepo::new()).with_shutdown(shutdown.clone());
+        shutdown.enter_lame_duck();
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(4);
+        assert!(matches!(
+            usecase.send_users(None, 0, None, tx).await,
+            Err(crate::Error::Unavailable(_))
+        ));
+        let (tx, _rx) = tokio::sync::mpsc::channel(4);
+        assert!(matches!(
+            usecase.send_user_events(None, 0, 0, None, tx).await,
+            Err(crate::Error::Unavailable(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_send_users_in_chunks() {
+        // the mock repo's clones lose their expectations, and the stream
+        // runs on a clone
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        for n in 0..250 {
+            repo.create_user(format!("User{}", n), "Chunked".to_string())
+                .await
+                .unwrap();
+        }
+
+        let usecase = UserUsecase::new(repo);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        usecase.send_users(None, 40, None, tx).await.unwrap();
+
+        let mut sizes = Vec::new();
+        while let Some(res) = rx.recv().await {
+            let res = res.unwrap();
+            assert_eq!(res.user, None);
+            sizes.push(res.users.len());
+        }
+        // the last chunk is flushed once the users run out
+        assert_eq!(sizes, [40, 40, 40, 40, 40, 40, 10]);
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        assert!(usecase.send_users(None, -1, None, tx).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_erase_user_rewrites_retained_events() {
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        let feed = UserFeed::new();
+        let usecase = UserUsecase::new(repo).with_change_feed(feed.clone());
+        let user = usecase
+            .create_user(None, "Ann".to_string(), "Lee".to_string())
+            .await
+            .unwrap()
+            .user
+            .unwrap();
+
+        assert!(matches!(
+            usecase
+                .erase_user(Some(&principal(user.id + 1, &[])), user.id)
+                .await,
+            Err(Error::PermissionDenied)
+        ));
+        usecase
+            .erase_user(Some(&principal(user.id, &[])), user.id)
+            .await
+            .unwrap();
+
+        let (events, _) = feed.resume(0).unwrap();
+        let kinds: Vec<_> = events.iter().map(|e| e.kind).collect();
+        assert_eq!(kinds, [UserEventKind::Created, UserEventKind::Updated]);
+        assert!(events.iter().all(|e| e.user.name == ERASED_NAME));
+        let found = usecase.get_user_by_id(None, user.id).await.unwrap().user;
+        assert_eq!(found.unwrap().surname, ERASED_SURNAME);
+    }
+
+    #[sqlx::test]
+    async fn test_watchers_resume_from_the_event_store(pool: sqlx::PgPool) {
+        use crate::repositories::user_event_repository::UserEventRepository;
+
+        let shutdown = Shutdown::new();
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        let feed =
+            UserFeed::new().with_event_store(Arc::new(UserEventRepository::new(pool)), &shutdown);
+        let usecase = UserUsecase::new(repo).with_change_feed(feed);
+        let (tx, mut live) = tokio::sync::mpsc::channel(8);
+        usecase
+            .send_user_events(None, 0, 0, None, tx)
+            .await
+            .unwrap();
+
+        let mut ids = Vec::new();
+        for name in ["Ann", "Bob"] {
+            let user = usecase
+                .create_user(None, name.to_string(), "Lee".to_string())
+                .await
+                .unwrap()
+                .user
+                .unwrap();
+            ids.push(user.id);
+        }
+        usecase
+            .update_user(None, ids[0], Some("Anna".to_string()), None)
+            .await
+            .unwrap();
+        usecase.delete_user(None, ids[1]).await.unwrap();
+        let mut tokens = Vec::new();
+        for _ in 0..4 {
+            tokens.push(live.recv().await.unwrap().unwrap().resume_token);
+        }
+        assert!(tokens[0] > 0 && tokens.is_sorted());
+
+        // Bob's creation is left to his deletion, Ann is as she is now
+        let (tx, mut resumed) = tokio::sync::mpsc::channel(8);
+        usecase
+            .send_user_events(None, 0, tokens[0], None, tx)
+            .await
+            .unwrap();
+        usecase
+            .create_user(None, "Cy".to_string(), "Lee".to_string())
+            .await
+            .unwrap();
+        let updated = resumed.recv().await.unwrap().unwrap();
+        assert_eq!((updated.sequence, updated.resume_token), (0, tokens[2]));
+        assert_eq!(updated.user.unwrap().name, "Anna");
+        let deleted = resumed.recv().await.unwrap().unwrap();
+        assert_eq!(deleted.kind(), crate::grpc::UserEventKind::Deleted);
+        let created = resumed.recv().await.unwrap().unwrap();
+        assert!(created.sequence > 0 && created.resume_token > tokens[3]);
+
+        assert!(matches!(
+            usecase
+                .send_user_events(None, 1, tokens[0], None, tokio::sync::mpsc::channel(1).0)
+                .await,
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            usecase
+                .send_user_events(
+                    None,
+                    0,
+                    tokens[3] + 100,
+                    None,
+                    tokio::sync::mpsc::channel(1).0
+                )
+                .await,
+            Err(Error::InvalidArgument(_))
+        ));
+        let without_store = UserUsecase::new(
+            crate::repositories::memory_user_repository::InMemoryUserRepository::new(),
+        );
+        assert!(matches!(
+            without_store
+                .send_user_events(None, 0, tokens[0], None, tokio::sync::mpsc::channel(1).0)
+                .await,
+            Err(Error::FailedPrecondition(_))
+        ));
+        shutdown.trigger();
+    }
+
+    #[tokio::test]
+    async fn test_restore_user() {
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        let flags = EnvFeatureFlags::new("soft_delete", None).unwrap();
+        let usecase = UserUsecase::new(repo.clone()).with_feature_flags(Arc::new(flags));
+        let mut ids = Vec::new();
+        for name in ["Kept", "Gone", "Erased", "Live"] {
+            let user = usecase
+                .create_user(None, name.to_string(), "Lee".to_string())
+                .await
+                .unwrap()
+                .user
+                .unwrap();
+            ids.push(user.id);
+        }
+        let (kept, gone, erased, live) = (ids[0], ids[1], ids[2], ids[3]);
+        for id in [kept, gone, erased] {
+            usecase.delete_user(None, id).await.unwrap();
+        }
+        repo.delete_user(gone).await.unwrap();
+        usecase.erase_user(None, erased).await.unwrap();
+
+        let listed = usecase.list_deleted_users(1, String::new()).await.unwrap();
+        assert_eq!(listed.users.len(), 1);
+        let rest = usecase
+            .list_deleted_users(0, listed.next_page_token)
+            .await
+            .unwrap();
+        assert!(rest.next_page_token.is_empty());
+        assert_eq!(
+            rest.users
+                .iter()
+                .map(|d| d.user.as_ref().unwrap().id)
+                .collect::<Vec<_>>(),
+            [erased]
+        );
+
+        let restored = usecase.restore_user(None, kept).await.unwrap().user;
+        assert_eq!(restored.unwrap().name, "Kept");
+        assert!(usecase.get_user_by_id(None, kept).await.is_ok());
+        for id in [kept, gone, erased, live] {
+            assert!(matches!(
+                usecase.restore_user(None, id).await,
+                Err(Error::FailedPrecondition(_))
+            ));
+        }
+        assert!(matches!(
+            usecase.list_deleted_users(1001, String::new()).await,
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_suspend_and_activate_user() {
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        let usecase = UserUsecase::new(repo);
+        let id = usecase
+            .create_user(None, "Ann".to_string(), "Lee".to_string())
+            .await
+            .unwrap()
+            .user
+            .unwrap()
+            .id;
+        let (admin, own) = (principal(9, &["admin"]), principal(id, &[]));
+
+        assert!(matches!(
+            usecase.suspend_user(Some(&own), id).await,
+            Err(Error::PermissionDenied)
+        ));
+        usecase.suspend_user(Some(&admin), id).await.unwrap();
+
+        assert_eq!(
+            usecase
+                .get_users(None, String::new(), 0, String::new())
+                .await
+                .unwrap()
+                .count,
+            0
+        );
+        assert!(usecase.get_user_by_id(Some(&own), id).await.is_ok());
+        assert!(matches!(
+            usecase
+                .update_user(Some(&own), id, Some("Bob".to_string()), None)
+                .await,
+            Err(Error::PermissionDenied)
+        ));
+        assert!(matches!(
+            usecase.suspend_user(Some(&admin), id).await,
+            Err(Error::FailedPrecondition(_))
+        ));
+
+        usecase.activate_user(Some(&admin), id).await.unwrap();
+        assert_eq!(
+            usecase
+                .get_users(None, String::new(), 0, String::new())
+                .await
+                .unwrap()
+                .count,
+            1
+        );
+        assert!(
+            usecase
+                .update_user(Some(&own), id, Some("Bob".to_string()), None)
+                .await
+                .is_ok()
+        );
+        assert!(matches!(
+            usecase.activate_user(Some(&admin), 999).await,
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_notifies_created_and_suspended_users() {
+        struct Forward(tokio::sync::mpsc::UnboundedSender<Notification>);
+
+        #[async_trait]
+        impl crate::notifications::Notifier for Forward {
+            async fn send(&self, notification: &Notification) -> Result<(), crate::Error> {
+                let _ = self.0.send(notification.clone());
+                Ok(())
+            }
+        }
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let queue = NotificationQueue::with_notifier(Arc::new(Forward(tx)), 8, &Shutdown::new());
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        let usecase = UserUsecase::new(repo).with_notifications(queue);
+
+        let id = usecase
+            .create_user(None, "Ann".to_string(), "Lee".to_string())
+            .await
+            .unwrap()
+            .user
+            .unwrap()
+            .id;
+        usecase.suspend_user(None, id).await.unwrap();
+        usecase.activate_user(None, id).await.unwrap();
+
+        assert_eq!(rx.recv().await.unwrap().kind, NotificationKind::UserCreated);
+        assert_eq!(
+            rx.recv().await.unwrap().kind,
+            NotificationKind::UserSuspended
+        );
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_merge_users() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo
+            .expect_merge_users()
+            .withf(|primary_id, duplicate_id, audit| {
+                *primary_id == 1
+                    && *duplicate_id == 2
+                    && audit
+                        .iter()
+                        .map(|e| (e.action, e.target_user_id, e.actor_user_id))
+                        .eq([
+                            (AuditAction::MergeUsers, 1, Some(9)),
+                            (AuditAction::MergeUsers, 2, Some(9)),
+                        ])
+            })
+            .times(1)
+            .returning(|primary_id, _, _| {
+                Ok(User {
+                    id: primary_id,
+                    name: "Ann".to_string(),
+                    surname: "Lee".to_string(),
+                })
+            });
+        let usecase = UserUsecase::new(mock_repo);
+        let admin = principal(9, &["admin"]);
+
+        let merged = usecase.merge_users(Some(&admin), 1, 2).await.unwrap();
+
+        assert_eq!(merged.user.unwrap().id, 1);
+        assert!(matches!(
+            usecase.merge_users(Some(&principal(1, &[])), 1, 2).await,
+            Err(Error::PermissionDenied)
+        ));
+        assert!(matches!(
+            usecase.merge_users(Some(&admin), 1, 1).await,
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_required_consents() {
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        let usecase = UserUsecase::new(repo)
+            .with_required_consents(RequiredConsent::parse("terms:2026-01").unwrap());
+        let terms = |version: &str| NewConsent {
+            consent_type: "terms".to_string(),
+            version: version.to_string(),
+            source: "signup_form".to_string(),
+        };
+
+        assert!(matches!(
+            usecase
+                .create_user(None, "Ann".to_string(), "Lee".to_string())
+                .await,
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            usecase
+                .create_user_with_consents(
+                    None,
+                    "Ann".to_string(),
+                    "Lee".to_string(),
+                    vec![terms("2025-06")]
+                )
+                .await,
+            Err(Error::InvalidArgument(_))
+        ));
+        let user = usecase
+            .create_user_with_consents(
+                None,
+                "Ann".to_string(),
+                "Lee".to_string(),
+                vec![terms("2026-01")],
+            )
+            .await
+            .unwrap()
+            .user
+            .unwrap();
+
+        let consents = usecase
+            .get_consents(Some(&principal(user.id, &[])), user.id)
+            .await
+            .unwrap()
+            .consents;
+        assert_eq!(consents.len(), 1);
+        assert_eq!(consents[0].grant.as_ref().unwrap().version, "2026-01");
+    }
+
+    #[tokio::test]
+    async fn test_record_consent() {
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        let usecase = UserUsecase::new(repo);
+        let user = usecase
+            .create_user(None, "Ann".to_string(), "Lee".to_string())
+            .await
+            .unwrap()
+            .user
+            .unwrap();
+        let marketing = NewConsent {
+            consent_type: "marketing".to_string(),
+            version: "1".to_string(),
+            source: "settings_page".to_string(),
+        };
+
+        assert!(matches!(
+            usecase
+                .record_consent(
+                    Some(&principal(user.id + 1, &[])),
+                    user.id,
+                    marketing.clone()
+                )
+                .await,
+            Err(Error::PermissionDenied)
+        ));
+        assert!(matches!(
+            usecase
+                .record_consent(None, user.id + 1, marketing.clone())
+                .await,
+            Err(Error::NotFound)
+        ));
+        assert!(matches!(
+            usecase
+                .record_consent(
+                    None,
+                    user.id,
+                    NewConsent {
+                        source: String::new(),
+                        ..marketing.clone()
+                    }
+                )
+                .await,
+            Err(Error::InvalidArgument(_))
+        ));
+        let consent = usecase
+            .record_consent(Some(&principal(user.id, &[])), user.id, marketing)
+            .await
+            .unwrap()
+            .consent
+            .unwrap();
+
+        assert_eq!(consent.user_id, user.id);
+        assert_eq!(consent.grant.unwrap().source, "settings_page");
+        assert!(consent.granted_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_user_revisions_need_postgres() {
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        let usecase = UserUsecase::new(repo);
+
+        assert!(matches!(
+            usecase.list_user_revisions(None, 1).await,
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            usecase
+                .list_user_revisions(Some(&principal(2, &[])), 1)
+                .await,
+            Err(Error::PermissionDenied)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_export_user_data() {
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        let usecase = UserUsecase::new(repo).with_change_feed(UserFeed::new());
+        let user = usecase
+            .create_user(None, "Ann".to_string(), "Lee".to_string())
+            .await
+            .unwrap()
+            .user
+            .unwrap();
+
+        assert!(matches!(
+            usecase
+                .export_user_data(Some(&principal(user.id + 1, &[])), user.id)
+                .await,
+            Err(Error::PermissionDenied)
+        ));
+        let document = usecase
+            .export_user_data(Some(&principal(user.id, &[])), user.id)
+            .await
+            .unwrap();
+
+        let document: serde_json::Value = serde_json::from_slice(&document).unwrap();
+        assert_eq!(document["user"]["name"], "Ann");
+        assert_eq!(document["addresses"], serde_json::json!([]));
+        assert_eq!(document["events"][0]["kind"], "created");
+    }
+
+    #[tokio::test]
+    async fn test_send_export() {
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        repo.create_user("Ann".to_string(), "Lee".to_string())
+            .await
+            .unwrap();
+
+        let usecase = UserUsecase::new(repo);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(4);
+        usecase
+            .send_export(None, ExportFormat::Csv, tx)
+            .await
+            .unwrap();
+
+        let mut file = Vec::new();
+        while let Some(res) = rx.recv().await {
+            file.extend(res.unwrap().data);
+        }
+        assert_eq!(file, b"id,name,surname\r\n1,Ann,Lee\r\n");
+    }
+
+    fn principal(user_id: i32, roles: &[&str]) -> Principal {
+        Principal {
+            user_id,
+            roles: roles.iter().map(|r| r.to_string()).collect(),
+            tenant: None,
+            impersonator: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_non_admin_limited_to_own_record() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo.expect_update_user().times(0);
+        mock_repo.expect_delete_user().times(0);
+        mock_repo.expect_get_user_by_id().returning(|id| {
+            Ok(Some(User {
+                id,
+                name: "John".to_string(),
+                surname: "Doe".to_string(),
+            }))
+        });
+        mock_repo.expect_get_user_by_name().returning(|name| {
+            Ok(Some(User {
+                id: 2,
+                name,
+                surname: "Doe".to_string(),
+            }))
+        });
+
+        let usecase = UserUsecase::new(mock_repo);
+        let caller = principal(1, &[]);
+
+        assert!(usecase.get_user_by_id(Some(&caller), 1).await.is_ok());
+        assert!(matches!(
+            usecase.get_user_by_id(Some(&caller), 2).await,
+            Err(crate::Error::PermissionDenied)
+        ));
+        assert!(matches!(
+            usecase
+                .get_user_by_name(Some(&caller), "Jane".to_string())
+                .await,
+            Err(crate::Error::PermissionDenied)
+        ));
+        assert!(matches!(
+            usecase
+                .update_user(Some(&caller), 2, Some("X".to_string()), None)
+                .await,
+            Err(crate::Error::PermissionDenied)
+        ));
+        assert!(matches!(
+            usecase.delete_user(Some(&caller), 2).await,
+            Err(crate::Error::PermissionDenied)
+        ));
+        assert!(matches!(
+            usecase.user_exists(Some(&caller), 2).await,
+            Err(crate::Error::PermissionDenied)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_non_admin_cannot_list_users() {
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        for name in ["Ann", "Anna", "Bob"] {
+            repo.create_user(name.to_string(), "Doe".to_string())
+                .await
+                .unwrap();
+        }
+        let usecase = UserUsecase::new(repo);
+        let caller = principal(2, &[]);
+        let denied = |res: Result<_, Error>| matches!(res, Err(Error::PermissionDenied));
+
+        assert!(denied(
+            usecase
+                .get_users(Some(&caller), String::new(), 0, String::new())
+                .await
+                .map(|_| ())
+        ));
+        assert!(denied(
+            usecase
+                .get_users_page(Some(&caller), String::new(), 0, 10)
+                .await
+                .map(|_| ())
+        ));
+        assert!(denied(
+            usecase
+                .get_users_as_of(
+                    Some(&caller),
+                    String::new(),
+                    SystemTime::now(),
+                    0,
+                    String::new()
+                )
+                .await
+                .map(|_| ())
+        ));
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        assert!(denied(usecase.send_users(Some(&caller), 0, None, tx).await));
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        assert!(denied(
+            usecase
+                .send_export(Some(&caller), ExportFormat::Csv, tx)
+                .await
+        ));
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        assert!(denied(
+            usecase
+                .send_user_events(Some(&caller), 0, 0, None, tx)
+                .await
+        ));
+
+        let admin = principal(1, &["admin"]);
+        let all = usecase
+            .get_users(Some(&admin), String::new(), 0, String::new())
+            .await
+            .unwrap();
+        assert_eq!(all.users.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_non_admin_finds_only_own_record() {
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        for name in ["Ann", "Anna", "Bob"] {
+            repo.create_user(name.to_string(), "Doe".to_string())
+                .await
+                .unwrap();
+        }
+        let usecase = UserUsecase::new(repo);
+        let caller = principal(2, &[]);
+
+        let names = ["Ann", "Anna"].map(String::from).to_vec();
+        let res = usecase
+            .batch_get_users_by_name(Some(&caller), names)
+            .await
+            .unwrap();
+        assert_eq!(res.users.keys().collect::<Vec<_>>(), ["Anna"]);
+        assert_eq!(res.unmatched_names, vec!["Ann".to_string()]);
+
+        let res = usecase
+            .list_users_by_name_prefix(Some(&caller), "An".to_string(), 0)
+            .await
+            .unwrap();
+        assert_eq!(res.users.iter().map(|u| u.id).collect::<Vec<_>>(), [2]);
+
+        let requests = ["A", "B"].map(|prefix| {
+            Ok(crate::grpc::AutocompleteUsersRequest {
+                prefix: prefix.to_string(),
+                limit: 0,
+            })
+        });
+        let (tx, mut rx) = tokio::sync::mpsc::channel(2);
+        usecase
+            .send_autocomplete(Some(&caller), Box::pin(tokio_stream::iter(requests)), tx)
+            .await
+            .unwrap();
+        let mut counts = Vec::new();
+        while let Some(res) = rx.recv().await {
+            counts.push(res.unwrap().users.len());
+        }
+        assert_eq!(counts, vec![1, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_admin_accesses_any_record() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo
+            .expect_delete_user()
+            .with(eq(2))
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let usecase = UserUsecase::new(mock_repo);
+        let admin = principal(1, &["admin"]);
+
+        assert!(usecase.delete_user(Some(&admin), 2).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_audit_records_real_and_effective_caller() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo.expect_delete_user().returning(|_| Ok(()));
+        mock_repo
+            .expect_get_user_status()
+            .returning(|_| Ok(Some(UserStatus::Active)));
+        let mut mock_audit = MockAudit::new();
+        mock_audit
+            .expect_record()
+            .with(eq(AuditEntry {
+                action: AuditAction::DeleteUser,
+                target_user_id: 42,
+                actor_user_id: Some(7),
+                effective_user_id: Some(42),
+            }))
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let usecase = UserUsecase::new(mock_repo).with_audit_log(Arc::new(mock_audit));
+        let impersonated = Principal {
+            impersonator: Some(7),
+            ..principal(42, &[])
+        };
+
+        assert!(usecase.delete_user(Some(&impersonated), 42).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_batch_get_users_by_name() {
+        let mut mock_repo = MockRepo::new();
+        mock_repo
+            .expect_get_users_by_names()
+            .with(eq(vec![
+                "Ann".to_string(),
+                "Bob".to_string(),
+                "Zed".to_string(),
+            ]))
+            .times(1)
+            .returning(|_| {
+                Ok(vec![
+                    User {
+                        id: 1,
+                        name: "Ann".to_string(),
+                        surname: "One".to_string(),
+                    },
+                    User {
+                        id: 2,
+                        name: "Bob".to_string(),
+                        surname: "Two".to_string(),
+                    },
+                    User {
+                        id: 3,
+                        name: "Ann".to_string(),
+                        surname: "Three".to_string(),
+                    },
+                ])
+            });
+
+        let usecase = UserUsecase::new(mock_repo);
+        let names = ["Ann", "Bob", "Ann", "Zed"].map(String::from).to_vec();
+        let res = usecase.batch_get_users_by_name(None, names).await.unwrap();
+
+        assert_eq!(res.users["Ann"].users.len(), 2);
+        assert_eq!(res.users["Bob"].users.len(), 1);
+        assert_eq!(res.unmatched_names, vec!["Zed".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_send_autocomplete() {
+        let repo = crate::repositories::memory_user_repository::InMemoryUserRepository::new();
+        for name in ["Ann", "Anna", "Bob"] {
+            repo.create_user(name.to_string(), "Doe".to_string())
+                .await
+                .unwrap();
+        }
+        let usecase = UserUsecase::new(repo);
+        let requests = ["A", "Ann", "", "Bo"].map(|prefix| {
+            Ok(crate::grpc::AutocompleteUsersRequest {
+                prefix: prefix.to_string(),
+                limit: 0,
+            })
+        });
+        let (tx, mut rx) = tokio::sync::mpsc::channel(4);
+
+        usecase
+            .send_autocomplete(None, Box::pin(tokio_stream::iter(requests)), tx)
+            .await
+            .unwrap();
+        let mut counts = Vec::new();
+        while let Some(res) = rx.recv().await {
+            counts.push(res.unwrap().users.len());
+        }
+
+        assert_eq!(counts, vec![2, 2, 0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_by_name_prefix_requires_prefix() {
+        let usecase = UserUsecase::new(MockRepo::new());
+
+        let result = usecase
+            .list_users_by_name_prefix(None, String::new(), 0)
+            .await;
+
+        assert!(matches!(result, Err(crate::Error::InvalidArgument(_))));
+    }
 }
@@ -1,30 +1,224 @@
 use crate::{
     Error,
+    auth::Principal,
+    entities::{consents::NewConsent, stats::StatsPeriod},
+    export::ExportFormat,
     grpc::{
-        CreateUserResponse, DeleteUserResponse, GetUserByIdResponse, GetUserByNameResponse,
-        GetUsersResponse, StreamUsersResponse, UpdateUserResponse,
+        ActivateUserResponse, AutocompleteUsersRequest, AutocompleteUsersResponse,
+        BatchGetUsersByNameResponse, CountUsersResponse, CreateUserResponse, DeleteUserResponse,
+        EraseUserResponse, ExportUsersResponse, GetConsentsResponse, GetUserByIdResponse,
+        GetUserByNameResponse, GetUserRevisionResponse, GetUserStatsResponse, GetUsersResponse,
+        ImportUsersRequest, ImportUsersResponse, ListDeletedUsersResponse,
+        ListUserRevisionsResponse, ListUsersByNamePrefixResponse, MergeUsersResponse,
+        RecordConsentResponse, RestoreUserResponse, StreamUsersResponse, SuspendUserResponse,
+        UpdateUserResponse, UserEvent, UserExistsResponse,
     },
 };
 use async_trait::async_trait;
+use std::{
+    pin::Pin,
+    time::{Duration, SystemTime},
+};
 use tokio::sync::mpsc::Sender;
+use tokio_stream::Stream;
 use tonic::Status;
 
+pub type AutocompleteRequests =
+    Pin<Box<dyn Stream<Item = Result<AutocompleteUsersRequest, Status>> + Send>>;
+pub type ImportRequests = Pin<Box<dyn Stream<Item = Result<ImportUsersRequest, Status>> + Send>>;
+
 #[async_trait]
 pub trait UserUsecase: Send + Sync {
-    async fn create_user(&self, name: String, surname: String)
-    -> Result<CreateUserResponse, Error>;
-    async fn get_users(&self) -> Result<GetUsersResponse, Error>;
-    async fn get_user_by_id(&self, id: i32) -> Result<GetUserByIdResponse, Error>;
-    async fn get_user_by_name(&self, name: String) -> Result<GetUserByNameResponse, Error>;
+    async fn create_user(
+        &self,
+        caller: Option<&Principal>,
+        name: String,
+        surname: String,
+    ) -> Result<CreateUserResponse, Error>;
+    // creates the user and records the consents given with it, refused when
+    // one of the required consents is missing
+    async fn create_user_with_consents(
+        &self,
+        caller: Option<&Principal>,
+        name: String,
+        surname: String,
+        consents: Vec<NewConsent>,
+    ) -> Result<CreateUserResponse, Error>;
+    // lists come in pages of at most 1000 users, 50 when the size is 0; a
+    // larger size is INVALID_ARGUMENT. The page token is an offset. Listing
+    // and streaming every user is for admins only
+    async fn get_users(
+        &self,
+        caller: Option<&Principal>,
+        filter: String,
+        page_size: i32,
+        page_token: String,
+    ) -> Result<GetUsersResponse, Error>;
+    async fn get_users_page(
+        &self,
+        caller: Option<&Principal>,
+        filter: String,
+        offset: i32,
+        limit: i32,
+    ) -> Result<GetUsersResponse, Error>;
+    // `exact: false` may answer with an older count, see CountEstimate
+    async fn count_users(&self, exact: bool) -> Result<CountUsersResponse, Error>;
+    async fn get_user_stats(
+        &self,
+        period: StatsPeriod,
+        start: Option<SystemTime>,
+        end: Option<SystemTime>,
+    ) -> Result<GetUserStatsResponse, Error>;
+    async fn get_user_by_id(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+    ) -> Result<GetUserByIdResponse, Error>;
+    async fn get_user_by_id_as_of(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+        as_of: SystemTime,
+    ) -> Result<GetUserByIdResponse, Error>;
+    async fn get_users_as_of(
+        &self,
+        caller: Option<&Principal>,
+        filter: String,
+        as_of: SystemTime,
+        page_size: i32,
+        page_token: String,
+    ) -> Result<GetUsersResponse, Error>;
+    async fn get_user_by_name(
+        &self,
+        caller: Option<&Principal>,
+        name: String,
+    ) -> Result<GetUserByNameResponse, Error>;
+    // a non-admin caller only finds their own record, in these and in
+    // send_autocomplete
+    async fn batch_get_users_by_name(
+        &self,
+        caller: Option<&Principal>,
+        names: Vec<String>,
+    ) -> Result<BatchGetUsersByNameResponse, Error>;
+    async fn list_users_by_name_prefix(
+        &self,
+        caller: Option<&Principal>,
+        prefix: String,
+        limit: i32,
+    ) -> Result<ListUsersByNamePrefixResponse, Error>;
+    // non-admins may only ask about themselves
+    async fn user_exists(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+    ) -> Result<UserExistsResponse, Error>;
     async fn update_user(
         &self,
+        caller: Option<&Principal>,
         id: i32,
         name: Option<String>,
         surname: Option<String>,
     ) -> Result<UpdateUserResponse, Error>;
-    async fn delete_user(&self, id: i32) -> Result<DeleteUserResponse, Error>;
+    async fn delete_user(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+    ) -> Result<DeleteUserResponse, Error>;
+    async fn erase_user(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+    ) -> Result<EraseUserResponse, Error>;
+    // brings back a soft deleted user, FailedPrecondition for any other and
+    // for erased ones
+    async fn restore_user(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+    ) -> Result<RestoreUserResponse, Error>;
+    // admins only; folds the duplicate into the primary and soft deletes it
+    async fn merge_users(
+        &self,
+        caller: Option<&Principal>,
+        primary_id: i32,
+        duplicate_id: i32,
+    ) -> Result<MergeUsersResponse, Error>;
+    // admins only; FailedPrecondition when the user already has the status
+    async fn suspend_user(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+    ) -> Result<SuspendUserResponse, Error>;
+    async fn activate_user(
+        &self,
+        caller: Option<&Principal>,
+        id: i32,
+    ) -> Result<ActivateUserResponse, Error>;
+    // for the admin service, which checks the caller itself
+    async fn list_deleted_users(
+        &self,
+        page_size: i32,
+        page_token: String,
+    ) -> Result<ListDeletedUsersResponse, Error>;
+    // every stored version of the user, postgres backend only
+    async fn list_user_revisions(
+        &self,
+        caller: Option<&Principal>,
+        user_id: i32,
+    ) -> Result<ListUserRevisionsResponse, Error>;
+    async fn get_user_revision(
+        &self,
+        caller: Option<&Principal>,
+        user_id: i32,
+        revision: i64,
+    ) -> Result<GetUserRevisionResponse, Error>;
+    async fn record_consent(
+        &self,
+        caller: Option<&Principal>,
+        user_id: i32,
+        consent: NewConsent,
+    ) -> Result<RecordConsentResponse, Error>;
+    async fn get_consents(
+        &self,
+        caller: Option<&Principal>,
+        user_id: i32,
+    ) -> Result<GetConsentsResponse, Error>;
+    // the ExportUserData document, see export::user_data
+    async fn export_user_data(&self, caller: Option<&Principal>, id: i32)
+    -> Result<Vec<u8>, Error>;
+    // `heartbeat` is how long a stream may go quiet before a heartbeat is
+    // sent, None for never
     async fn send_users(
         &self,
+        caller: Option<&Principal>,
+        chunk_size: i32,
+        heartbeat: Option<Duration>,
         tx: Sender<Result<StreamUsersResponse, Status>>,
     ) -> Result<(), Error>;
+    async fn send_export(
+        &self,
+        caller: Option<&Principal>,
+        format: ExportFormat,
+        tx: Sender<Result<ExportUsersResponse, Status>>,
+    ) -> Result<(), Error>;
+    async fn send_user_events(
+        &self,
+        caller: Option<&Principal>,
+        after_sequence: u64,
+        resume_token: u64,
+        heartbeat: Option<Duration>,
+        tx: Sender<Result<UserEvent, Status>>,
+    ) -> Result<(), Error>;
+    async fn send_autocomplete(
+        &self,
+        caller: Option<&Principal>,
+        requests: AutocompleteRequests,
+        tx: Sender<Result<AutocompleteUsersResponse, Status>>,
+    ) -> Result<(), Error>;
+    // answers once every request was read and the users are stored
+    async fn import_users(
+        &self,
+        caller: Option<&Principal>,
+        requests: ImportRequests,
+    ) -> Result<ImportUsersResponse, Error>;
 }
@@ -0,0 +1,114 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use sqlx::PgPool;
+use tracing::{info, warn};
+
+use crate::{Error, metrics::registry, shutdown::Shutdown};
+
+// whether the database takes writes. A hot standby does not: while it is
+// one, every mutation is FailedPrecondition before it reaches the database,
+// instead of the read_only_sql_transaction Postgres would answer halfway
+// through. Reads go on as usual
+#[derive(Clone, Default)]
+pub struct WriteProtection {
+    on: Arc<AtomicBool>,
+}
+
+impl WriteProtection {
+    pub fn new(on: bool) -> Self {
+        let protection = Self::default();
+        protection.set(on);
+        protection
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.on.load(Ordering::Relaxed)
+    }
+
+    // whether it was on before
+    pub fn set(&self, on: bool) -> bool {
+        registry().set_gauge("db_write_protected", &[], if on { 1.0 } else { 0.0 });
+        self.on.swap(on, Ordering::Relaxed)
+    }
+
+    // first thing in every mutation
+    pub fn check(&self) -> Result<(), Error> {
+        if self.is_on() {
+            return Err(Error::FailedPrecondition(
+                "the database is read-only, writes are rejected until it takes them again"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // follows pg_is_in_recovery() every `interval` until shutdown, so a
+    // standby promoted to primary takes writes without a restart. A failed
+    // check leaves it as it was
+    pub fn follow(&self, pool: PgPool, interval: Duration, shutdown: &Shutdown) {
+        let protection = self.clone();
+        let stop = shutdown.clone();
+        shutdown.spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = stop.triggered() => return,
+                    _ = ticker.tick() => {}
+                }
+                match in_recovery(&pool).await {
+                    Ok(standby) => protection.apply(standby),
+                    Err(e) => warn!("failed to check whether the database is a standby: {}", e),
+                }
+            }
+        });
+    }
+
+    fn apply(&self, standby: bool) {
+        match (self.set(standby), standby) {
+            (false, true) => warn!("the database is a read-only standby, rejecting writes"),
+            (true, false) => info!("the database takes writes again"),
+            _ => {}
+        }
+    }
+}
+
+pub async fn in_recovery(pool: &PgPool) -> Result<bool, Error> {
+    sqlx::query_scalar("SELECT pg_is_in_recovery()")
+        .fetch_one(pool)
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_follows_the_database(pool: PgPool) {
+        let protection = WriteProtection::new(true);
+        assert!(matches!(
+            protection.check(),
+            Err(Error::FailedPrecondition(_))
+        ));
+
+        // the test database is a primary
+        assert!(!in_recovery(&pool).await.unwrap());
+        let shutdown = Shutdown::new();
+        protection.follow(pool, Duration::from_millis(10), &shutdown);
+        for _ in 0..50 {
+            if !protection.is_on() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(protection.check().is_ok());
+        shutdown.trigger();
+    }
+}